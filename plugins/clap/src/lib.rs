@@ -0,0 +1,680 @@
+mod ffi;
+
+use std::{
+    ffi::{CStr, c_char, c_void},
+    ptr,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+};
+
+use ffi::*;
+use miniroog_model_r::{
+    instrument::{
+        GLIDE_STEP_FRAMES, HostParam, HostSwitch, Instrument, KNOB_COUNT, SWITCH_COUNT,
+        format_knob, host_param, host_params, load_macros, load_state, parse_knob, parse_switch,
+        state_preset,
+    },
+    macros::{MACRO_COUNT, MacroAssignment},
+    params::KnobId,
+    preset::Preset,
+    synthparams::ParamSpec,
+};
+
+struct SyncWrapper<T>(T);
+
+unsafe impl<T> Sync for SyncWrapper<T> {}
+
+static FEATURES: SyncWrapper<[*const c_char; 4]> = SyncWrapper([
+    c"instrument".as_ptr(),
+    c"synthesizer".as_ptr(),
+    c"mono".as_ptr(),
+    ptr::null(),
+]);
+
+static DESCRIPTOR: SyncWrapper<ClapPluginDescriptor> = SyncWrapper(ClapPluginDescriptor {
+    clap_version: CLAP_VERSION,
+    id: c"com.hadlock.miniroog-model-r".as_ptr(),
+    name: c"MiniRoog Model R".as_ptr(),
+    vendor: c"Hadlock".as_ptr(),
+    url: c"".as_ptr(),
+    manual_url: c"".as_ptr(),
+    support_url: c"".as_ptr(),
+    version: c"0.1.0".as_ptr(),
+    description: c"Three-oscillator monophonic synthesizer".as_ptr(),
+    features: &FEATURES.0 as *const [*const c_char; 4] as *const *const c_char,
+});
+
+#[unsafe(no_mangle)]
+pub static clap_entry: ClapPluginEntry = ClapPluginEntry {
+    clap_version: CLAP_VERSION,
+    init: entry_init,
+    deinit: entry_deinit,
+    get_factory: entry_get_factory,
+};
+
+static FACTORY: ClapPluginFactory = ClapPluginFactory {
+    get_plugin_count: factory_plugin_count,
+    get_plugin_descriptor: factory_descriptor,
+    create_plugin: factory_create,
+};
+
+static PARAMS: ClapPluginParams = ClapPluginParams {
+    count: params_count,
+    get_info: params_info,
+    get_value: params_value,
+    value_to_text: params_value_to_text,
+    text_to_value: params_text_to_value,
+    flush: params_flush,
+};
+
+static AUDIO_PORTS: ClapPluginAudioPorts = ClapPluginAudioPorts {
+    count: audio_ports_count,
+    get: audio_ports_get,
+};
+
+static NOTE_PORTS: ClapPluginNotePorts = ClapPluginNotePorts {
+    count: note_ports_count,
+    get: note_ports_get,
+};
+
+static STATE: ClapPluginState = ClapPluginState {
+    save: state_save,
+    load: state_load,
+};
+
+// The host reads and writes parameters from the main thread while the audio
+// thread owns the engine, so values are shared as f32 bits and switches as
+// their positions. `state` is the preset last restored, which saving writes
+// the current parameters over, and `macros` its macro assignments.
+struct Instance {
+    clap: ClapPlugin,
+    values: [AtomicU32; KNOB_COUNT],
+    switches: [AtomicU32; SWITCH_COUNT],
+    state: Mutex<Preset>,
+    macros: Mutex<[MacroAssignment; MACRO_COUNT]>,
+    reload: AtomicBool,
+    synth: Mutex<Option<Instrument>>,
+}
+
+impl Instance {
+    fn value(&self, knob: KnobId) -> f32 {
+        f32::from_bits(self.values[knob as usize].load(Ordering::Relaxed))
+    }
+
+    fn store(&self, knob: KnobId, value: f32) {
+        self.values[knob as usize].store(value.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> [f32; KNOB_COUNT] {
+        std::array::from_fn(|index| self.value(KnobId::ALL[index]))
+    }
+
+    fn switch(&self, switch: HostSwitch) -> usize {
+        self.switches[switch as usize].load(Ordering::Relaxed) as usize
+    }
+
+    fn store_switch(&self, switch: HostSwitch, position: usize) {
+        let position = position.min(switch.positions() - 1);
+        self.switches[switch as usize].store(position as u32, Ordering::Relaxed);
+    }
+
+    fn switch_snapshot(&self) -> [usize; SWITCH_COUNT] {
+        HostSwitch::ALL.map(|switch| self.switch(switch))
+    }
+
+    // Knobs travel 0 to 1; switches are stepped, valued by position.
+    fn param_value(&self, param: HostParam) -> f64 {
+        match param {
+            HostParam::Knob(knob) => self.value(knob) as f64,
+            HostParam::Switch(switch) => self.switch(switch) as f64,
+        }
+    }
+
+    fn store_param(&self, param: HostParam, value: f64) {
+        match param {
+            HostParam::Knob(knob) => self.store(knob, value as f32),
+            HostParam::Switch(switch) => self.store_switch(switch, value.max(0.0).round() as usize),
+        }
+    }
+
+    fn apply_param(&self, param: HostParam, synth: &mut Instrument) {
+        match param {
+            HostParam::Knob(knob) => {
+                synth.set_knob(knob, self.value(knob));
+                for target in synth.macro_targets(knob) {
+                    self.store(target.knob, synth.value(target.knob));
+                }
+            }
+            HostParam::Switch(switch) => synth.set_switch(switch, self.switch(switch)),
+        }
+    }
+
+    unsafe fn param_events(&self, events: *const ClapInputEvents, synth: Option<&mut Instrument>) {
+        let mut synth = synth;
+        if events.is_null() {
+            return;
+        }
+        let count = unsafe { ((*events).size)(events) };
+        for index in 0..count {
+            let header = unsafe { ((*events).get)(events, index) };
+            if header.is_null() {
+                continue;
+            }
+            let header = unsafe { &*header };
+            if header.space_id != CLAP_CORE_EVENT_SPACE_ID || header.type_ != CLAP_EVENT_PARAM_VALUE
+            {
+                continue;
+            }
+            let event =
+                unsafe { &*(header as *const ClapEventHeader as *const ClapEventParamValue) };
+            if let Some(param) = host_param(event.param_id) {
+                self.store_param(param, event.value);
+                match synth.as_deref_mut() {
+                    Some(synth) => self.apply_param(param, synth),
+                    None => self.reload.store(true, Ordering::Relaxed),
+                }
+            }
+        }
+    }
+}
+
+unsafe fn instance<'a>(plugin: *const ClapPlugin) -> &'a Instance {
+    unsafe { &*((*plugin).plugin_data as *const Instance) }
+}
+
+unsafe extern "C" fn entry_init(_plugin_path: *const c_char) -> bool {
+    true
+}
+
+unsafe extern "C" fn entry_deinit() {}
+
+unsafe extern "C" fn entry_get_factory(factory_id: *const c_char) -> *const c_void {
+    if !factory_id.is_null() && unsafe { CStr::from_ptr(factory_id) } == CLAP_PLUGIN_FACTORY_ID {
+        &FACTORY as *const ClapPluginFactory as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn factory_plugin_count(_factory: *const ClapPluginFactory) -> u32 {
+    1
+}
+
+unsafe extern "C" fn factory_descriptor(
+    _factory: *const ClapPluginFactory,
+    index: u32,
+) -> *const ClapPluginDescriptor {
+    if index == 0 {
+        &DESCRIPTOR.0
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn factory_create(
+    _factory: *const ClapPluginFactory,
+    _host: *const c_void,
+    plugin_id: *const c_char,
+) -> *const ClapPlugin {
+    if plugin_id.is_null()
+        || unsafe { CStr::from_ptr(plugin_id) != CStr::from_ptr(DESCRIPTOR.0.id) }
+    {
+        return ptr::null();
+    }
+    let instance = Box::into_raw(Box::new(Instance {
+        clap: ClapPlugin {
+            desc: &DESCRIPTOR.0,
+            plugin_data: ptr::null_mut(),
+            init: plugin_init,
+            destroy: plugin_destroy,
+            activate: plugin_activate,
+            deactivate: plugin_deactivate,
+            start_processing: plugin_start_processing,
+            stop_processing: plugin_stop_processing,
+            reset: plugin_reset,
+            process: plugin_process,
+            get_extension: plugin_get_extension,
+            on_main_thread: plugin_on_main_thread,
+        },
+        values: std::array::from_fn(|index| {
+            AtomicU32::new(KnobId::ALL[index].default_value().to_bits())
+        }),
+        switches: std::array::from_fn(|index| {
+            AtomicU32::new(HostSwitch::ALL[index].default_position() as u32)
+        }),
+        state: Mutex::new(Preset::new("Plugin state")),
+        macros: Mutex::new(Default::default()),
+        reload: AtomicBool::new(false),
+        synth: Mutex::new(None),
+    }));
+    unsafe {
+        (*instance).clap.plugin_data = instance as *mut c_void;
+        &(*instance).clap
+    }
+}
+
+unsafe extern "C" fn plugin_init(_plugin: *const ClapPlugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_destroy(plugin: *const ClapPlugin) {
+    drop(unsafe { Box::from_raw((*plugin).plugin_data as *mut Instance) });
+}
+
+unsafe extern "C" fn plugin_activate(
+    plugin: *const ClapPlugin,
+    sample_rate: f64,
+    _min_frames_count: u32,
+    _max_frames_count: u32,
+) -> bool {
+    let instance = unsafe { instance(plugin) };
+    let Ok(macros) = instance.macros.lock() else {
+        return false;
+    };
+    let synth = Instrument::new(
+        sample_rate as f32,
+        instance.snapshot(),
+        instance.switch_snapshot(),
+        *macros,
+    );
+    drop(macros);
+    instance.reload.store(false, Ordering::Relaxed);
+    match instance.synth.lock() {
+        Ok(mut guard) => {
+            *guard = Some(synth);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+unsafe extern "C" fn plugin_deactivate(plugin: *const ClapPlugin) {
+    if let Ok(mut guard) = unsafe { instance(plugin) }.synth.lock() {
+        *guard = None;
+    }
+}
+
+unsafe extern "C" fn plugin_start_processing(_plugin: *const ClapPlugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_stop_processing(_plugin: *const ClapPlugin) {}
+
+unsafe extern "C" fn plugin_reset(plugin: *const ClapPlugin) {
+    let instance = unsafe { instance(plugin) };
+    if let Ok(mut guard) = instance.synth.lock()
+        && let Some(synth) = guard.as_mut()
+        && let Ok(macros) = instance.macros.lock()
+    {
+        *synth = Instrument::new(
+            synth.sample_rate(),
+            instance.snapshot(),
+            instance.switch_snapshot(),
+            *macros,
+        );
+    }
+}
+
+unsafe extern "C" fn plugin_process(plugin: *const ClapPlugin, process: *const ClapProcess) -> i32 {
+    let instance = unsafe { instance(plugin) };
+    let process = unsafe { &*process };
+    if process.audio_outputs_count == 0 || process.audio_outputs.is_null() {
+        return CLAP_PROCESS_ERROR;
+    }
+    let output = unsafe { &*process.audio_outputs };
+    if output.data32.is_null() || output.channel_count == 0 {
+        return CLAP_PROCESS_ERROR;
+    }
+    let frames = process.frames_count as usize;
+    let left = unsafe { std::slice::from_raw_parts_mut(*output.data32, frames) };
+    let right = if output.channel_count > 1 {
+        unsafe { std::slice::from_raw_parts_mut(*output.data32.add(1), frames) }
+    } else {
+        // Mono hosts get the left channel only.
+        &mut []
+    };
+    // Only the main thread contends for this lock, and only while the host
+    // has processing stopped.
+    let Ok(mut guard) = instance.synth.try_lock() else {
+        left.fill(0.0);
+        right.fill(0.0);
+        return CLAP_PROCESS_CONTINUE;
+    };
+    let Some(synth) = guard.as_mut() else {
+        return CLAP_PROCESS_ERROR;
+    };
+    // A state load holds the macros only briefly; if it still does, the
+    // reload waits for the next block.
+    if instance.reload.swap(false, Ordering::Relaxed) {
+        match instance.macros.try_lock() {
+            Ok(macros) => {
+                synth.load_values(instance.snapshot(), instance.switch_snapshot(), *macros)
+            }
+            Err(_) => instance.reload.store(true, Ordering::Relaxed),
+        }
+    }
+
+    let mut mono_scratch = [0.0f32; GLIDE_STEP_FRAMES];
+    let mut render = |synth: &mut Instrument, start: usize, end: usize| {
+        if right.is_empty() {
+            for chunk_start in (start..end).step_by(GLIDE_STEP_FRAMES) {
+                let chunk_end = (chunk_start + GLIDE_STEP_FRAMES).min(end);
+                let len = chunk_end - chunk_start;
+                synth.render(&mut left[chunk_start..chunk_end], &mut mono_scratch[..len]);
+            }
+        } else {
+            synth.render(&mut left[start..end], &mut right[start..end]);
+        }
+    };
+
+    let events = process.in_events;
+    let count = if events.is_null() {
+        0
+    } else {
+        unsafe { ((*events).size)(events) }
+    };
+    let mut cursor = 0;
+    for index in 0..count {
+        let header = unsafe { ((*events).get)(events, index) };
+        if header.is_null() {
+            continue;
+        }
+        let header = unsafe { &*header };
+        if header.space_id != CLAP_CORE_EVENT_SPACE_ID {
+            continue;
+        }
+        let time = (header.time as usize).clamp(cursor, frames);
+        if time > cursor {
+            render(synth, cursor, time);
+            cursor = time;
+        }
+        let event = header as *const ClapEventHeader;
+        match header.type_ {
+            CLAP_EVENT_NOTE_ON => {
+                let note = unsafe { &*(event as *const ClapEventNote) };
+                if (0..128).contains(&note.key) {
+                    synth.note_on(note.key as u8, note.velocity as f32);
+                }
+            }
+            CLAP_EVENT_NOTE_OFF | CLAP_EVENT_NOTE_CHOKE => {
+                let note = unsafe { &*(event as *const ClapEventNote) };
+                // Key -1 is the wildcard: every note ends.
+                if note.key == -1 {
+                    synth.all_notes_off();
+                } else if (0..128).contains(&note.key) {
+                    synth.note_off(note.key as u8);
+                }
+            }
+            CLAP_EVENT_PARAM_VALUE => {
+                let param = unsafe { &*(event as *const ClapEventParamValue) };
+                if let Some(target) = host_param(param.param_id) {
+                    instance.store_param(target, param.value);
+                    instance.apply_param(target, synth);
+                }
+            }
+            CLAP_EVENT_MIDI => {
+                let midi = unsafe { &*(event as *const ClapEventMidi) };
+                synth.handle_midi(midi.data);
+            }
+            _ => {}
+        }
+    }
+    if cursor < frames {
+        render(synth, cursor, frames);
+    }
+    for channel in 2..output.channel_count as usize {
+        let extra = unsafe { std::slice::from_raw_parts_mut(*output.data32.add(channel), frames) };
+        extra.fill(0.0);
+    }
+    CLAP_PROCESS_CONTINUE
+}
+
+unsafe extern "C" fn plugin_get_extension(
+    _plugin: *const ClapPlugin,
+    id: *const c_char,
+) -> *const c_void {
+    if id.is_null() {
+        return ptr::null();
+    }
+    let id = unsafe { CStr::from_ptr(id) };
+    if id == CLAP_EXT_PARAMS {
+        &PARAMS as *const ClapPluginParams as *const c_void
+    } else if id == CLAP_EXT_AUDIO_PORTS {
+        &AUDIO_PORTS as *const ClapPluginAudioPorts as *const c_void
+    } else if id == CLAP_EXT_NOTE_PORTS {
+        &NOTE_PORTS as *const ClapPluginNotePorts as *const c_void
+    } else if id == CLAP_EXT_STATE {
+        &STATE as *const ClapPluginState as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn plugin_on_main_thread(_plugin: *const ClapPlugin) {}
+
+unsafe extern "C" fn params_count(_plugin: *const ClapPlugin) -> u32 {
+    host_params().count() as u32
+}
+
+unsafe extern "C" fn params_info(
+    _plugin: *const ClapPlugin,
+    param_index: u32,
+    param_info: *mut ClapParamInfo,
+) -> bool {
+    let Some((id, param)) = host_params().nth(param_index as usize) else {
+        return false;
+    };
+    let info = unsafe { &mut *param_info };
+    info.id = id;
+    info.cookie = ptr::null_mut();
+    info.min_value = 0.0;
+    match param {
+        HostParam::Knob(knob) => {
+            let spec = ParamSpec::of(knob);
+            info.flags = CLAP_PARAM_IS_AUTOMATABLE;
+            write_c_string(&mut info.name, spec.name);
+            write_c_string(&mut info.module, spec.key.split('.').next().unwrap_or(""));
+            info.max_value = 1.0;
+            info.default_value = spec.default as f64;
+        }
+        HostParam::Switch(switch) => {
+            info.flags = CLAP_PARAM_IS_AUTOMATABLE | CLAP_PARAM_IS_STEPPED;
+            write_c_string(&mut info.name, switch.label());
+            write_c_string(
+                &mut info.module,
+                switch.preset_key().split('.').next().unwrap_or(""),
+            );
+            info.max_value = (switch.positions() - 1) as f64;
+            info.default_value = switch.default_position() as f64;
+        }
+    }
+    true
+}
+
+unsafe extern "C" fn params_value(
+    plugin: *const ClapPlugin,
+    param_id: u32,
+    out_value: *mut f64,
+) -> bool {
+    let Some(param) = host_param(param_id) else {
+        return false;
+    };
+    unsafe { *out_value = instance(plugin).param_value(param) };
+    true
+}
+
+unsafe extern "C" fn params_value_to_text(
+    _plugin: *const ClapPlugin,
+    param_id: u32,
+    value: f64,
+    out_buffer: *mut c_char,
+    out_buffer_capacity: u32,
+) -> bool {
+    let Some(param) = host_param(param_id) else {
+        return false;
+    };
+    if out_buffer.is_null() || out_buffer_capacity == 0 {
+        return false;
+    }
+    let text = match param {
+        HostParam::Knob(knob) => format_knob(knob, value as f32),
+        HostParam::Switch(switch) => switch
+            .position_label(value.max(0.0).round() as usize)
+            .to_string(),
+    };
+    let buffer =
+        unsafe { std::slice::from_raw_parts_mut(out_buffer, out_buffer_capacity as usize) };
+    write_c_string(buffer, &text);
+    true
+}
+
+unsafe extern "C" fn params_text_to_value(
+    _plugin: *const ClapPlugin,
+    param_id: u32,
+    param_value_text: *const c_char,
+    out_value: *mut f64,
+) -> bool {
+    let Some(param) = host_param(param_id) else {
+        return false;
+    };
+    if param_value_text.is_null() {
+        return false;
+    }
+    let text = unsafe { CStr::from_ptr(param_value_text) }.to_string_lossy();
+    let parsed = match param {
+        HostParam::Knob(_) => parse_knob(&text).map(f64::from),
+        HostParam::Switch(switch) => parse_switch(switch, &text).map(|at| at as f64),
+    };
+    match parsed {
+        Some(value) => {
+            unsafe { *out_value = value };
+            true
+        }
+        None => false,
+    }
+}
+
+unsafe extern "C" fn params_flush(
+    plugin: *const ClapPlugin,
+    in_events: *const ClapInputEvents,
+    _out_events: *const ClapOutputEvents,
+) {
+    let instance = unsafe { instance(plugin) };
+    match instance.synth.try_lock() {
+        Ok(mut guard) => unsafe { instance.param_events(in_events, guard.as_mut()) },
+        Err(_) => unsafe { instance.param_events(in_events, None) },
+    }
+}
+
+unsafe extern "C" fn audio_ports_count(_plugin: *const ClapPlugin, is_input: bool) -> u32 {
+    if is_input { 0 } else { 1 }
+}
+
+unsafe extern "C" fn audio_ports_get(
+    _plugin: *const ClapPlugin,
+    index: u32,
+    is_input: bool,
+    info: *mut ClapAudioPortInfo,
+) -> bool {
+    if is_input || index != 0 {
+        return false;
+    }
+    let info = unsafe { &mut *info };
+    info.id = 0;
+    write_c_string(&mut info.name, "Output");
+    info.flags = CLAP_AUDIO_PORT_IS_MAIN;
+    info.channel_count = 2;
+    info.port_type = CLAP_PORT_STEREO.as_ptr();
+    info.in_place_pair = CLAP_INVALID_ID;
+    true
+}
+
+unsafe extern "C" fn note_ports_count(_plugin: *const ClapPlugin, is_input: bool) -> u32 {
+    if is_input { 1 } else { 0 }
+}
+
+unsafe extern "C" fn note_ports_get(
+    _plugin: *const ClapPlugin,
+    index: u32,
+    is_input: bool,
+    info: *mut ClapNotePortInfo,
+) -> bool {
+    if !is_input || index != 0 {
+        return false;
+    }
+    let info = unsafe { &mut *info };
+    info.id = 0;
+    info.supported_dialects = CLAP_NOTE_DIALECT_CLAP | CLAP_NOTE_DIALECT_MIDI;
+    info.preferred_dialect = CLAP_NOTE_DIALECT_CLAP;
+    write_c_string(&mut info.name, "Notes");
+    true
+}
+
+// Plugin state uses the same `key = value` text as `.mrp` preset files.
+unsafe extern "C" fn state_save(plugin: *const ClapPlugin, stream: *const ClapOstream) -> bool {
+    let instance = unsafe { instance(plugin) };
+    let Ok(base) = instance.state.lock() else {
+        return false;
+    };
+    let text = state_preset(&base, &instance.snapshot(), &instance.switch_snapshot()).serialize();
+    drop(base);
+    let mut remaining = text.as_bytes();
+    while !remaining.is_empty() {
+        let written = unsafe {
+            ((*stream).write)(
+                stream,
+                remaining.as_ptr() as *const c_void,
+                remaining.len() as u64,
+            )
+        };
+        if written <= 0 {
+            return false;
+        }
+        remaining = &remaining[written as usize..];
+    }
+    true
+}
+
+unsafe extern "C" fn state_load(plugin: *const ClapPlugin, stream: *const ClapIstream) -> bool {
+    let instance = unsafe { instance(plugin) };
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let read = unsafe {
+            ((*stream).read)(
+                stream,
+                chunk.as_mut_ptr() as *mut c_void,
+                chunk.len() as u64,
+            )
+        };
+        if read < 0 {
+            return false;
+        }
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read as usize]);
+    }
+    let Ok(preset) = Preset::parse(&String::from_utf8_lossy(&bytes)) else {
+        return false;
+    };
+    let mut values = instance.snapshot();
+    let mut switches = instance.switch_snapshot();
+    load_state(&preset, &mut values, &mut switches);
+    for knob in KnobId::ALL {
+        instance.store(knob, values[knob as usize]);
+    }
+    for switch in HostSwitch::ALL {
+        instance.store_switch(switch, switches[switch as usize]);
+    }
+    if let Ok(mut macros) = instance.macros.lock() {
+        *macros = load_macros(&preset);
+    }
+    if let Ok(mut state) = instance.state.lock() {
+        *state = preset;
+    }
+    instance.reload.store(true, Ordering::Relaxed);
+    true
+}
@@ -0,0 +1,458 @@
+// Hand-written subset of the VST3 C++ ABI (pluginterfaces/base and
+// pluginterfaces/vst); only what a single-component instrument needs. Every
+// interface is a pointer to a vtable whose first three slots are FUnknown.
+
+use std::ffi::{c_char, c_void};
+
+pub type TResult = i32;
+pub type Tuid = [u8; 16];
+pub type ParamId = u32;
+pub type ParamValue = f64;
+pub type String128 = [u16; 128];
+
+#[cfg(windows)]
+mod results {
+    pub const K_NO_INTERFACE: i32 = 0x8000_4002_u32 as i32;
+    pub const K_RESULT_OK: i32 = 0;
+    pub const K_RESULT_FALSE: i32 = 1;
+    pub const K_INVALID_ARGUMENT: i32 = 0x8007_0057_u32 as i32;
+    pub const K_NOT_IMPLEMENTED: i32 = 0x8000_4001_u32 as i32;
+    pub const K_NOT_INITIALIZED: i32 = 0x8000_FFFF_u32 as i32;
+}
+
+#[cfg(not(windows))]
+mod results {
+    pub const K_NO_INTERFACE: i32 = -1;
+    pub const K_RESULT_OK: i32 = 0;
+    pub const K_RESULT_FALSE: i32 = 1;
+    pub const K_INVALID_ARGUMENT: i32 = 2;
+    pub const K_NOT_IMPLEMENTED: i32 = 3;
+    pub const K_NOT_INITIALIZED: i32 = 5;
+}
+
+pub use results::*;
+pub const K_RESULT_TRUE: i32 = K_RESULT_OK;
+
+// INLINE_UID: Windows hosts compare interface ids as COM GUIDs, everyone else
+// as four big-endian words.
+#[cfg(windows)]
+pub const fn uid(l1: u32, l2: u32, l3: u32, l4: u32) -> Tuid {
+    let [a, b, c, d] = l1.to_le_bytes();
+    let [e, f, g, h] = l2.to_be_bytes();
+    let [i, j, k, l] = l3.to_be_bytes();
+    let [m, n, o, p] = l4.to_be_bytes();
+    [a, b, c, d, f, e, h, g, i, j, k, l, m, n, o, p]
+}
+
+#[cfg(not(windows))]
+pub const fn uid(l1: u32, l2: u32, l3: u32, l4: u32) -> Tuid {
+    let [a, b, c, d] = l1.to_be_bytes();
+    let [e, f, g, h] = l2.to_be_bytes();
+    let [i, j, k, l] = l3.to_be_bytes();
+    let [m, n, o, p] = l4.to_be_bytes();
+    [a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p]
+}
+
+pub const FUNKNOWN_IID: Tuid = uid(0x0000_0000, 0x0000_0000, 0xC000_0000, 0x0000_0046);
+pub const IPLUGIN_BASE_IID: Tuid = uid(0x2288_8DDB, 0x156E_45AE, 0x8358_B348, 0x0819_0625);
+pub const IPLUGIN_FACTORY_IID: Tuid = uid(0x7A4D_811C, 0x5211_4A1F, 0xAED9_D2EE, 0x0B43_BF9F);
+pub const IPLUGIN_FACTORY2_IID: Tuid = uid(0x0007_B650, 0xF24B_4C0B, 0xA464_EDB9, 0xF00B_2ABB);
+pub const ICOMPONENT_IID: Tuid = uid(0xE831_FF31, 0xF2D5_4301, 0x928E_BBEE, 0x2569_7802);
+pub const IAUDIO_PROCESSOR_IID: Tuid = uid(0x4204_3F99, 0xB7DA_453C, 0xA569_E79D, 0x9AAE_C33D);
+pub const IEDIT_CONTROLLER_IID: Tuid = uid(0xDCD7_BBE3, 0x7742_448D, 0xA874_AACC, 0x979C_759E);
+
+pub const K_MANY_INSTANCES: i32 = 0x7FFF_FFFF;
+pub const K_UNICODE: i32 = 1 << 4;
+pub const K_VST_AUDIO_EFFECT_CLASS: &[u8] = b"Audio Module Class";
+pub const K_VST_VERSION_STRING: &[u8] = b"VST 3.7.9";
+
+pub const K_AUDIO: i32 = 0;
+pub const K_EVENT: i32 = 1;
+pub const K_INPUT: i32 = 0;
+pub const K_OUTPUT: i32 = 1;
+pub const K_MAIN: i32 = 0;
+pub const K_DEFAULT_ACTIVE: u32 = 1 << 0;
+pub const K_STEREO: u64 = 0x3;
+pub const K_SAMPLE32: i32 = 0;
+pub const K_CAN_AUTOMATE: i32 = 1 << 0;
+pub const K_IS_LIST: i32 = 1 << 3;
+
+pub const K_NOTE_ON_EVENT: u16 = 0;
+pub const K_NOTE_OFF_EVENT: u16 = 1;
+
+#[repr(C)]
+pub struct PFactoryInfo {
+    pub vendor: [c_char; 64],
+    pub url: [c_char; 256],
+    pub email: [c_char; 128],
+    pub flags: i32,
+}
+
+#[repr(C)]
+pub struct PClassInfo {
+    pub cid: Tuid,
+    pub cardinality: i32,
+    pub category: [c_char; 32],
+    pub name: [c_char; 64],
+}
+
+#[repr(C)]
+pub struct PClassInfo2 {
+    pub cid: Tuid,
+    pub cardinality: i32,
+    pub category: [c_char; 32],
+    pub name: [c_char; 64],
+    pub class_flags: u32,
+    pub sub_categories: [c_char; 128],
+    pub vendor: [c_char; 64],
+    pub version: [c_char; 64],
+    pub sdk_version: [c_char; 64],
+}
+
+#[repr(C)]
+pub struct BusInfo {
+    pub media_type: i32,
+    pub direction: i32,
+    pub channel_count: i32,
+    pub name: String128,
+    pub bus_type: i32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+pub struct ParameterInfo {
+    pub id: ParamId,
+    pub title: String128,
+    pub short_title: String128,
+    pub units: String128,
+    pub step_count: i32,
+    pub default_normalized_value: ParamValue,
+    pub unit_id: i32,
+    pub flags: i32,
+}
+
+#[repr(C)]
+pub struct ProcessSetup {
+    pub process_mode: i32,
+    pub symbolic_sample_size: i32,
+    pub max_samples_per_block: i32,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct AudioBusBuffers {
+    pub num_channels: i32,
+    pub silence_flags: u64,
+    pub channel_buffers32: *mut *mut f32,
+}
+
+#[repr(C)]
+pub struct ProcessData {
+    pub process_mode: i32,
+    pub symbolic_sample_size: i32,
+    pub num_samples: i32,
+    pub num_inputs: i32,
+    pub num_outputs: i32,
+    pub inputs: *mut AudioBusBuffers,
+    pub outputs: *mut AudioBusBuffers,
+    pub input_parameter_changes: *mut IParameterChanges,
+    pub output_parameter_changes: *mut c_void,
+    pub input_events: *mut IEventList,
+    pub output_events: *mut c_void,
+    pub process_context: *mut c_void,
+}
+
+#[repr(C)]
+pub struct NoteOnEvent {
+    pub channel: i16,
+    pub pitch: i16,
+    pub tuning: f32,
+    pub velocity: f32,
+    pub length: i32,
+    pub note_id: i32,
+}
+
+#[repr(C)]
+pub struct NoteOffEvent {
+    pub channel: i16,
+    pub pitch: i16,
+    pub velocity: f32,
+    pub note_id: i32,
+    pub tuning: f32,
+}
+
+// The payload union is only read for note events; it is sized to cover the
+// largest member (NoteExpressionTextEvent) so hosts can copy any event in.
+#[repr(C)]
+pub union EventPayload {
+    pub note_on: std::mem::ManuallyDrop<NoteOnEvent>,
+    pub note_off: std::mem::ManuallyDrop<NoteOffEvent>,
+    pub raw: [u64; 4],
+}
+
+#[repr(C)]
+pub struct Event {
+    pub bus_index: i32,
+    pub sample_offset: i32,
+    pub ppq_position: f64,
+    pub flags: u16,
+    pub type_: u16,
+    pub payload: EventPayload,
+}
+
+#[repr(C)]
+pub struct FUnknownVtbl {
+    pub query_interface: unsafe extern "system" fn(
+        this: *mut c_void,
+        iid: *const Tuid,
+        obj: *mut *mut c_void,
+    ) -> TResult,
+    pub add_ref: unsafe extern "system" fn(this: *mut c_void) -> u32,
+    pub release: unsafe extern "system" fn(this: *mut c_void) -> u32,
+}
+
+#[repr(C)]
+pub struct IPluginFactory2Vtbl {
+    pub unknown: FUnknownVtbl,
+    pub get_factory_info:
+        unsafe extern "system" fn(this: *mut c_void, info: *mut PFactoryInfo) -> TResult,
+    pub count_classes: unsafe extern "system" fn(this: *mut c_void) -> i32,
+    pub get_class_info:
+        unsafe extern "system" fn(this: *mut c_void, index: i32, info: *mut PClassInfo) -> TResult,
+    pub create_instance: unsafe extern "system" fn(
+        this: *mut c_void,
+        cid: *const c_char,
+        iid: *const c_char,
+        obj: *mut *mut c_void,
+    ) -> TResult,
+    pub get_class_info2:
+        unsafe extern "system" fn(this: *mut c_void, index: i32, info: *mut PClassInfo2) -> TResult,
+}
+
+#[repr(C)]
+pub struct IComponentVtbl {
+    pub unknown: FUnknownVtbl,
+    pub initialize: unsafe extern "system" fn(this: *mut c_void, context: *mut c_void) -> TResult,
+    pub terminate: unsafe extern "system" fn(this: *mut c_void) -> TResult,
+    pub get_controller_class_id:
+        unsafe extern "system" fn(this: *mut c_void, class_id: *mut Tuid) -> TResult,
+    pub set_io_mode: unsafe extern "system" fn(this: *mut c_void, mode: i32) -> TResult,
+    pub get_bus_count: unsafe extern "system" fn(this: *mut c_void, media: i32, dir: i32) -> i32,
+    pub get_bus_info: unsafe extern "system" fn(
+        this: *mut c_void,
+        media: i32,
+        dir: i32,
+        index: i32,
+        bus: *mut BusInfo,
+    ) -> TResult,
+    pub get_routing_info: unsafe extern "system" fn(
+        this: *mut c_void,
+        in_info: *mut c_void,
+        out_info: *mut c_void,
+    ) -> TResult,
+    pub activate_bus: unsafe extern "system" fn(
+        this: *mut c_void,
+        media: i32,
+        dir: i32,
+        index: i32,
+        state: u8,
+    ) -> TResult,
+    pub set_active: unsafe extern "system" fn(this: *mut c_void, state: u8) -> TResult,
+    pub set_state: unsafe extern "system" fn(this: *mut c_void, state: *mut IBStream) -> TResult,
+    pub get_state: unsafe extern "system" fn(this: *mut c_void, state: *mut IBStream) -> TResult,
+}
+
+#[repr(C)]
+pub struct IAudioProcessorVtbl {
+    pub unknown: FUnknownVtbl,
+    pub set_bus_arrangements: unsafe extern "system" fn(
+        this: *mut c_void,
+        inputs: *mut u64,
+        num_ins: i32,
+        outputs: *mut u64,
+        num_outs: i32,
+    ) -> TResult,
+    pub get_bus_arrangement: unsafe extern "system" fn(
+        this: *mut c_void,
+        dir: i32,
+        index: i32,
+        arrangement: *mut u64,
+    ) -> TResult,
+    pub can_process_sample_size:
+        unsafe extern "system" fn(this: *mut c_void, symbolic_sample_size: i32) -> TResult,
+    pub get_latency_samples: unsafe extern "system" fn(this: *mut c_void) -> u32,
+    pub setup_processing:
+        unsafe extern "system" fn(this: *mut c_void, setup: *mut ProcessSetup) -> TResult,
+    pub set_processing: unsafe extern "system" fn(this: *mut c_void, state: u8) -> TResult,
+    pub process: unsafe extern "system" fn(this: *mut c_void, data: *mut ProcessData) -> TResult,
+    pub get_tail_samples: unsafe extern "system" fn(this: *mut c_void) -> u32,
+}
+
+#[repr(C)]
+pub struct IEditControllerVtbl {
+    pub unknown: FUnknownVtbl,
+    pub initialize: unsafe extern "system" fn(this: *mut c_void, context: *mut c_void) -> TResult,
+    pub terminate: unsafe extern "system" fn(this: *mut c_void) -> TResult,
+    pub set_component_state:
+        unsafe extern "system" fn(this: *mut c_void, state: *mut IBStream) -> TResult,
+    pub set_state: unsafe extern "system" fn(this: *mut c_void, state: *mut IBStream) -> TResult,
+    pub get_state: unsafe extern "system" fn(this: *mut c_void, state: *mut IBStream) -> TResult,
+    pub get_parameter_count: unsafe extern "system" fn(this: *mut c_void) -> i32,
+    pub get_parameter_info: unsafe extern "system" fn(
+        this: *mut c_void,
+        index: i32,
+        info: *mut ParameterInfo,
+    ) -> TResult,
+    pub get_param_string_by_value: unsafe extern "system" fn(
+        this: *mut c_void,
+        id: ParamId,
+        value: ParamValue,
+        string: *mut u16,
+    ) -> TResult,
+    pub get_param_value_by_string: unsafe extern "system" fn(
+        this: *mut c_void,
+        id: ParamId,
+        string: *const u16,
+        value: *mut ParamValue,
+    ) -> TResult,
+    pub normalized_param_to_plain:
+        unsafe extern "system" fn(this: *mut c_void, id: ParamId, value: ParamValue) -> ParamValue,
+    pub plain_param_to_normalized:
+        unsafe extern "system" fn(this: *mut c_void, id: ParamId, value: ParamValue) -> ParamValue,
+    pub get_param_normalized:
+        unsafe extern "system" fn(this: *mut c_void, id: ParamId) -> ParamValue,
+    pub set_param_normalized:
+        unsafe extern "system" fn(this: *mut c_void, id: ParamId, value: ParamValue) -> TResult,
+    pub set_component_handler:
+        unsafe extern "system" fn(this: *mut c_void, handler: *mut c_void) -> TResult,
+    pub create_view:
+        unsafe extern "system" fn(this: *mut c_void, name: *const c_char) -> *mut c_void,
+}
+
+// Host-owned interfaces: only the vtables are declared, the plugin calls
+// through them.
+
+#[repr(C)]
+pub struct IBStream {
+    pub vtbl: *const IBStreamVtbl,
+}
+
+#[repr(C)]
+pub struct IBStreamVtbl {
+    pub unknown: FUnknownVtbl,
+    pub read: unsafe extern "system" fn(
+        this: *mut IBStream,
+        buffer: *mut c_void,
+        num_bytes: i32,
+        num_bytes_read: *mut i32,
+    ) -> TResult,
+    pub write: unsafe extern "system" fn(
+        this: *mut IBStream,
+        buffer: *const c_void,
+        num_bytes: i32,
+        num_bytes_written: *mut i32,
+    ) -> TResult,
+    pub seek: unsafe extern "system" fn(
+        this: *mut IBStream,
+        pos: i64,
+        mode: i32,
+        result: *mut i64,
+    ) -> TResult,
+    pub tell: unsafe extern "system" fn(this: *mut IBStream, pos: *mut i64) -> TResult,
+}
+
+#[repr(C)]
+pub struct IParameterChanges {
+    pub vtbl: *const IParameterChangesVtbl,
+}
+
+#[repr(C)]
+pub struct IParameterChangesVtbl {
+    pub unknown: FUnknownVtbl,
+    pub get_parameter_count: unsafe extern "system" fn(this: *mut IParameterChanges) -> i32,
+    pub get_parameter_data: unsafe extern "system" fn(
+        this: *mut IParameterChanges,
+        index: i32,
+    ) -> *mut IParamValueQueue,
+    pub add_parameter_data: unsafe extern "system" fn(
+        this: *mut IParameterChanges,
+        id: *const ParamId,
+        index: *mut i32,
+    ) -> *mut IParamValueQueue,
+}
+
+#[repr(C)]
+pub struct IParamValueQueue {
+    pub vtbl: *const IParamValueQueueVtbl,
+}
+
+#[repr(C)]
+pub struct IParamValueQueueVtbl {
+    pub unknown: FUnknownVtbl,
+    pub get_parameter_id: unsafe extern "system" fn(this: *mut IParamValueQueue) -> ParamId,
+    pub get_point_count: unsafe extern "system" fn(this: *mut IParamValueQueue) -> i32,
+    pub get_point: unsafe extern "system" fn(
+        this: *mut IParamValueQueue,
+        index: i32,
+        sample_offset: *mut i32,
+        value: *mut ParamValue,
+    ) -> TResult,
+    pub add_point: unsafe extern "system" fn(
+        this: *mut IParamValueQueue,
+        sample_offset: i32,
+        value: ParamValue,
+        index: *mut i32,
+    ) -> TResult,
+}
+
+#[repr(C)]
+pub struct IEventList {
+    pub vtbl: *const IEventListVtbl,
+}
+
+#[repr(C)]
+pub struct IEventListVtbl {
+    pub unknown: FUnknownVtbl,
+    pub get_event_count: unsafe extern "system" fn(this: *mut IEventList) -> i32,
+    pub get_event:
+        unsafe extern "system" fn(this: *mut IEventList, index: i32, event: *mut Event) -> TResult,
+    pub add_event: unsafe extern "system" fn(this: *mut IEventList, event: *mut Event) -> TResult,
+}
+
+pub fn write_c_string(dest: &mut [c_char], text: &[u8]) {
+    let len = text.len().min(dest.len().saturating_sub(1));
+    for (slot, byte) in dest.iter_mut().zip(&text[..len]) {
+        *slot = *byte as c_char;
+    }
+    if let Some(end) = dest.get_mut(len) {
+        *end = 0;
+    }
+}
+
+pub fn write_string128(dest: &mut [u16], text: &str) {
+    let capacity = dest.len().saturating_sub(1);
+    let mut len = 0;
+    for (slot, unit) in dest.iter_mut().take(capacity).zip(text.encode_utf16()) {
+        *slot = unit;
+        len += 1;
+    }
+    if let Some(end) = dest.get_mut(len) {
+        *end = 0;
+    }
+}
+
+/// Reads a NUL-terminated UTF-16 string the host handed us.
+///
+/// # Safety
+/// `text` must be null or point to a NUL-terminated UTF-16 buffer.
+pub unsafe fn read_utf16(text: *const u16) -> Option<String> {
+    if text.is_null() {
+        return None;
+    }
+    let mut len = 0;
+    while unsafe { *text.add(len) } != 0 {
+        len += 1;
+    }
+    let units = unsafe { std::slice::from_raw_parts(text, len) };
+    Some(String::from_utf16_lossy(units))
+}
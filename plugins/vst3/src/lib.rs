@@ -0,0 +1,1084 @@
+mod ffi;
+
+use std::{
+    ffi::{c_char, c_void},
+    mem::offset_of,
+    ptr,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+};
+
+use ffi::*;
+use miniroog_model_r::{
+    instrument::{
+        GLIDE_STEP_FRAMES, HostParam, HostSwitch, Instrument, KNOB_COUNT, SWITCH_COUNT,
+        format_knob, host_param, host_params, load_macros, load_state, parse_knob, parse_switch,
+        state_preset,
+    },
+    macros::{MACRO_COUNT, MacroAssignment},
+    params::KnobId,
+    preset::Preset,
+    synthparams::ParamSpec,
+};
+
+const PLUGIN_CID: Tuid = *b"MiniRoogModelR\x00\x03";
+const PLUGIN_NAME: &[u8] = b"MiniRoog Model R";
+const VENDOR: &[u8] = b"Hadlock";
+const VERSION: &[u8] = b"0.1.0";
+// Generous upper bound on events plus automation points per block; the
+// scratch list is allocated once on activation.
+const CHANGE_CAPACITY: usize = 1024;
+
+static FACTORY_VTBL: IPluginFactory2Vtbl = IPluginFactory2Vtbl {
+    unknown: FUnknownVtbl {
+        query_interface: factory_query_interface,
+        add_ref: factory_add_ref,
+        release: factory_release,
+    },
+    get_factory_info: factory_info,
+    count_classes: factory_count_classes,
+    get_class_info: factory_class_info,
+    create_instance: factory_create_instance,
+    get_class_info2: factory_class_info2,
+};
+
+#[repr(C)]
+struct Factory {
+    vtbl: &'static IPluginFactory2Vtbl,
+}
+
+static FACTORY: Factory = Factory {
+    vtbl: &FACTORY_VTBL,
+};
+
+static COMPONENT_VTBL: IComponentVtbl = IComponentVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: component_query_interface,
+        add_ref: component_add_ref,
+        release: component_release,
+    },
+    initialize: component_initialize,
+    terminate: component_terminate,
+    get_controller_class_id: component_controller_class_id,
+    set_io_mode: component_set_io_mode,
+    get_bus_count: component_bus_count,
+    get_bus_info: component_bus_info,
+    get_routing_info: component_routing_info,
+    activate_bus: component_activate_bus,
+    set_active: component_set_active,
+    set_state: component_set_state,
+    get_state: component_get_state,
+};
+
+static PROCESSOR_VTBL: IAudioProcessorVtbl = IAudioProcessorVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: processor_query_interface,
+        add_ref: processor_add_ref,
+        release: processor_release,
+    },
+    set_bus_arrangements: processor_set_bus_arrangements,
+    get_bus_arrangement: processor_bus_arrangement,
+    can_process_sample_size: processor_can_process_sample_size,
+    get_latency_samples: processor_latency_samples,
+    setup_processing: processor_setup_processing,
+    set_processing: processor_set_processing,
+    process: processor_process,
+    get_tail_samples: processor_tail_samples,
+};
+
+static CONTROLLER_VTBL: IEditControllerVtbl = IEditControllerVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: controller_query_interface,
+        add_ref: controller_add_ref,
+        release: controller_release,
+    },
+    initialize: controller_initialize,
+    terminate: controller_terminate,
+    set_component_state: controller_set_component_state,
+    set_state: controller_set_state,
+    get_state: controller_get_state,
+    get_parameter_count: controller_parameter_count,
+    get_parameter_info: controller_parameter_info,
+    get_param_string_by_value: controller_param_string,
+    get_param_value_by_string: controller_param_value,
+    normalized_param_to_plain: controller_normalized_to_plain,
+    plain_param_to_normalized: controller_plain_to_normalized,
+    get_param_normalized: controller_get_param,
+    set_param_normalized: controller_set_param,
+    set_component_handler: controller_set_component_handler,
+    create_view: controller_create_view,
+};
+
+#[derive(Clone, Copy)]
+enum ChangeKind {
+    Param(KnobId, f32),
+    Switch(HostSwitch, usize),
+    NoteOn(u8, f32),
+    NoteOff(u8),
+}
+
+struct Change {
+    offset: usize,
+    order: usize,
+    kind: ChangeKind,
+}
+
+struct Active {
+    synth: Instrument,
+    changes: Vec<Change>,
+}
+
+impl Active {
+    // Queues a change for its frame. Once the list is full, the change
+    // applies at the start of the block instead of growing the list on the
+    // audio thread.
+    fn queue(&mut self, plugin: &Plugin, offset: usize, kind: ChangeKind) {
+        if self.changes.len() < self.changes.capacity() {
+            let order = self.changes.len();
+            self.changes.push(Change {
+                offset,
+                order,
+                kind,
+            });
+        } else {
+            apply_change(plugin, &mut self.synth, kind);
+        }
+    }
+}
+
+fn apply_change(plugin: &Plugin, synth: &mut Instrument, kind: ChangeKind) {
+    match kind {
+        ChangeKind::Param(knob, value) => {
+            synth.set_knob(knob, value);
+            for target in synth.macro_targets(knob) {
+                plugin.store(target.knob, synth.value(target.knob));
+            }
+        }
+        ChangeKind::Switch(switch, position) => synth.set_switch(switch, position),
+        ChangeKind::NoteOn(key, velocity) => synth.note_on(key, velocity),
+        ChangeKind::NoteOff(key) => synth.note_off(key),
+    }
+}
+
+// One object serves as component, processor and edit controller (the SDK's
+// "single component" layout), so parameters live in one place. Each
+// interface pointer is the address of its vtable field. Switches are held as
+// their positions; `state` is the preset last restored, which saving writes
+// the current parameters over, and `macros` its macro assignments.
+#[repr(C)]
+struct Plugin {
+    component: &'static IComponentVtbl,
+    processor: &'static IAudioProcessorVtbl,
+    controller: &'static IEditControllerVtbl,
+    refs: AtomicU32,
+    values: [AtomicU32; KNOB_COUNT],
+    switches: [AtomicU32; SWITCH_COUNT],
+    state: Mutex<Preset>,
+    macros: Mutex<[MacroAssignment; MACRO_COUNT]>,
+    reload: AtomicBool,
+    sample_rate: AtomicU32,
+    active: Mutex<Option<Active>>,
+}
+
+impl Plugin {
+    fn new() -> Self {
+        Self {
+            component: &COMPONENT_VTBL,
+            processor: &PROCESSOR_VTBL,
+            controller: &CONTROLLER_VTBL,
+            refs: AtomicU32::new(1),
+            values: std::array::from_fn(|index| {
+                AtomicU32::new(KnobId::ALL[index].default_value().to_bits())
+            }),
+            switches: std::array::from_fn(|index| {
+                AtomicU32::new(HostSwitch::ALL[index].default_position() as u32)
+            }),
+            state: Mutex::new(Preset::new("Plugin state")),
+            macros: Mutex::new(Default::default()),
+            reload: AtomicBool::new(false),
+            sample_rate: AtomicU32::new(44_100.0f32.to_bits()),
+            active: Mutex::new(None),
+        }
+    }
+
+    fn value(&self, knob: KnobId) -> f32 {
+        f32::from_bits(self.values[knob as usize].load(Ordering::Relaxed))
+    }
+
+    fn store(&self, knob: KnobId, value: f32) {
+        self.values[knob as usize].store(value.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> [f32; KNOB_COUNT] {
+        std::array::from_fn(|index| self.value(KnobId::ALL[index]))
+    }
+
+    fn switch(&self, switch: HostSwitch) -> usize {
+        self.switches[switch as usize].load(Ordering::Relaxed) as usize
+    }
+
+    fn store_switch(&self, switch: HostSwitch, position: usize) {
+        let position = position.min(switch.positions() - 1);
+        self.switches[switch as usize].store(position as u32, Ordering::Relaxed);
+    }
+
+    fn switch_snapshot(&self) -> [usize; SWITCH_COUNT] {
+        HostSwitch::ALL.map(|switch| self.switch(switch))
+    }
+
+    fn normalized(&self, param: HostParam) -> f32 {
+        match param {
+            HostParam::Knob(knob) => self.value(knob),
+            HostParam::Switch(switch) => switch.normalized(self.switch(switch)),
+        }
+    }
+
+    fn store_normalized(&self, param: HostParam, value: f32) {
+        match param {
+            HostParam::Knob(knob) => self.store(knob, value),
+            HostParam::Switch(switch) => self.store_switch(switch, switch.position(value)),
+        }
+    }
+
+    unsafe fn query_interface(&self, iid: *const Tuid, obj: *mut *mut c_void) -> TResult {
+        if iid.is_null() || obj.is_null() {
+            return K_INVALID_ARGUMENT;
+        }
+        let iid = unsafe { &*iid };
+        let interface =
+            if *iid == FUNKNOWN_IID || *iid == IPLUGIN_BASE_IID || *iid == ICOMPONENT_IID {
+                &self.component as *const _ as *mut c_void
+            } else if *iid == IAUDIO_PROCESSOR_IID {
+                &self.processor as *const _ as *mut c_void
+            } else if *iid == IEDIT_CONTROLLER_IID {
+                &self.controller as *const _ as *mut c_void
+            } else {
+                unsafe { *obj = ptr::null_mut() };
+                return K_NO_INTERFACE;
+            };
+        self.refs.fetch_add(1, Ordering::Relaxed);
+        unsafe { *obj = interface };
+        K_RESULT_OK
+    }
+
+    fn add_ref(&self) -> u32 {
+        self.refs.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    unsafe fn release(this: *const Plugin) -> u32 {
+        let remaining = unsafe { (*this).refs.fetch_sub(1, Ordering::AcqRel) } - 1;
+        if remaining == 0 {
+            drop(unsafe { Box::from_raw(this as *mut Plugin) });
+        }
+        remaining
+    }
+
+    unsafe fn load(&self, stream: *mut IBStream) -> TResult {
+        if stream.is_null() {
+            return K_INVALID_ARGUMENT;
+        }
+        let mut bytes = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let mut read = 0;
+            let result = unsafe {
+                ((*(*stream).vtbl).read)(
+                    stream,
+                    chunk.as_mut_ptr() as *mut c_void,
+                    chunk.len() as i32,
+                    &mut read,
+                )
+            };
+            if result != K_RESULT_OK {
+                return result;
+            }
+            if read <= 0 {
+                break;
+            }
+            bytes.extend_from_slice(&chunk[..read as usize]);
+        }
+        let Ok(preset) = Preset::parse(&String::from_utf8_lossy(&bytes)) else {
+            return K_RESULT_FALSE;
+        };
+        let mut values = self.snapshot();
+        let mut switches = self.switch_snapshot();
+        load_state(&preset, &mut values, &mut switches);
+        for knob in KnobId::ALL {
+            self.store(knob, values[knob as usize]);
+        }
+        for switch in HostSwitch::ALL {
+            self.store_switch(switch, switches[switch as usize]);
+        }
+        if let Ok(mut macros) = self.macros.lock() {
+            *macros = load_macros(&preset);
+        }
+        if let Ok(mut state) = self.state.lock() {
+            *state = preset;
+        }
+        self.reload.store(true, Ordering::Relaxed);
+        K_RESULT_OK
+    }
+
+    unsafe fn save(&self, stream: *mut IBStream) -> TResult {
+        if stream.is_null() {
+            return K_INVALID_ARGUMENT;
+        }
+        let Ok(base) = self.state.lock() else {
+            return K_RESULT_FALSE;
+        };
+        let text = state_preset(&base, &self.snapshot(), &self.switch_snapshot()).serialize();
+        drop(base);
+        let mut remaining = text.as_bytes();
+        while !remaining.is_empty() {
+            let mut written = 0;
+            let result = unsafe {
+                ((*(*stream).vtbl).write)(
+                    stream,
+                    remaining.as_ptr() as *const c_void,
+                    remaining.len() as i32,
+                    &mut written,
+                )
+            };
+            if result != K_RESULT_OK {
+                return result;
+            }
+            if written <= 0 {
+                return K_RESULT_FALSE;
+            }
+            remaining = &remaining[written as usize..];
+        }
+        K_RESULT_OK
+    }
+}
+
+unsafe fn from_field<'a>(this: *mut c_void, offset: usize) -> &'a Plugin {
+    unsafe { &*((this as *const u8).sub(offset) as *const Plugin) }
+}
+
+unsafe fn component<'a>(this: *mut c_void) -> &'a Plugin {
+    unsafe { from_field(this, offset_of!(Plugin, component)) }
+}
+
+unsafe fn processor<'a>(this: *mut c_void) -> &'a Plugin {
+    unsafe { from_field(this, offset_of!(Plugin, processor)) }
+}
+
+unsafe fn controller<'a>(this: *mut c_void) -> &'a Plugin {
+    unsafe { from_field(this, offset_of!(Plugin, controller)) }
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn GetPluginFactory() -> *mut c_void {
+    &FACTORY as *const Factory as *mut c_void
+}
+
+#[cfg(target_os = "linux")]
+#[unsafe(no_mangle)]
+pub extern "C" fn ModuleEntry(_library_handle: *mut c_void) -> bool {
+    true
+}
+
+#[cfg(target_os = "linux")]
+#[unsafe(no_mangle)]
+pub extern "C" fn ModuleExit() -> bool {
+    true
+}
+
+#[cfg(target_os = "macos")]
+#[unsafe(no_mangle)]
+pub extern "C" fn bundleEntry(_bundle: *mut c_void) -> bool {
+    true
+}
+
+#[cfg(target_os = "macos")]
+#[unsafe(no_mangle)]
+pub extern "C" fn bundleExit() -> bool {
+    true
+}
+
+#[cfg(windows)]
+#[unsafe(no_mangle)]
+pub extern "system" fn InitDll() -> bool {
+    true
+}
+
+#[cfg(windows)]
+#[unsafe(no_mangle)]
+pub extern "system" fn ExitDll() -> bool {
+    true
+}
+
+unsafe extern "system" fn factory_query_interface(
+    this: *mut c_void,
+    iid: *const Tuid,
+    obj: *mut *mut c_void,
+) -> TResult {
+    if iid.is_null() || obj.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let iid = unsafe { &*iid };
+    if *iid == FUNKNOWN_IID || *iid == IPLUGIN_FACTORY_IID || *iid == IPLUGIN_FACTORY2_IID {
+        unsafe { *obj = this };
+        K_RESULT_OK
+    } else {
+        unsafe { *obj = ptr::null_mut() };
+        K_NO_INTERFACE
+    }
+}
+
+// The factory is a static, so reference counting is a no-op.
+unsafe extern "system" fn factory_add_ref(_this: *mut c_void) -> u32 {
+    1
+}
+
+unsafe extern "system" fn factory_release(_this: *mut c_void) -> u32 {
+    1
+}
+
+unsafe extern "system" fn factory_info(_this: *mut c_void, info: *mut PFactoryInfo) -> TResult {
+    if info.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let info = unsafe { &mut *info };
+    write_c_string(&mut info.vendor, VENDOR);
+    write_c_string(&mut info.url, b"");
+    write_c_string(&mut info.email, b"");
+    info.flags = K_UNICODE;
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn factory_count_classes(_this: *mut c_void) -> i32 {
+    1
+}
+
+unsafe extern "system" fn factory_class_info(
+    _this: *mut c_void,
+    index: i32,
+    info: *mut PClassInfo,
+) -> TResult {
+    if index != 0 || info.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let info = unsafe { &mut *info };
+    info.cid = PLUGIN_CID;
+    info.cardinality = K_MANY_INSTANCES;
+    write_c_string(&mut info.category, K_VST_AUDIO_EFFECT_CLASS);
+    write_c_string(&mut info.name, PLUGIN_NAME);
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn factory_class_info2(
+    _this: *mut c_void,
+    index: i32,
+    info: *mut PClassInfo2,
+) -> TResult {
+    if index != 0 || info.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let info = unsafe { &mut *info };
+    info.cid = PLUGIN_CID;
+    info.cardinality = K_MANY_INSTANCES;
+    write_c_string(&mut info.category, K_VST_AUDIO_EFFECT_CLASS);
+    write_c_string(&mut info.name, PLUGIN_NAME);
+    info.class_flags = 0;
+    write_c_string(&mut info.sub_categories, b"Instrument|Synth");
+    write_c_string(&mut info.vendor, VENDOR);
+    write_c_string(&mut info.version, VERSION);
+    write_c_string(&mut info.sdk_version, K_VST_VERSION_STRING);
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn factory_create_instance(
+    _this: *mut c_void,
+    cid: *const c_char,
+    iid: *const c_char,
+    obj: *mut *mut c_void,
+) -> TResult {
+    if cid.is_null() || iid.is_null() || obj.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    if unsafe { *(cid as *const Tuid) } != PLUGIN_CID {
+        unsafe { *obj = ptr::null_mut() };
+        return K_NO_INTERFACE;
+    }
+    let plugin = Box::into_raw(Box::new(Plugin::new()));
+    let result = unsafe { (*plugin).query_interface(iid as *const Tuid, obj) };
+    // Drops the construction reference; a failed query frees the object.
+    unsafe { Plugin::release(plugin) };
+    result
+}
+
+unsafe extern "system" fn component_query_interface(
+    this: *mut c_void,
+    iid: *const Tuid,
+    obj: *mut *mut c_void,
+) -> TResult {
+    unsafe { component(this).query_interface(iid, obj) }
+}
+
+unsafe extern "system" fn component_add_ref(this: *mut c_void) -> u32 {
+    unsafe { component(this) }.add_ref()
+}
+
+unsafe extern "system" fn component_release(this: *mut c_void) -> u32 {
+    unsafe { Plugin::release(component(this)) }
+}
+
+unsafe extern "system" fn component_initialize(
+    _this: *mut c_void,
+    _context: *mut c_void,
+) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn component_terminate(_this: *mut c_void) -> TResult {
+    K_RESULT_OK
+}
+
+// No separate controller class: hosts fall back to querying the component
+// for IEditController.
+unsafe extern "system" fn component_controller_class_id(
+    _this: *mut c_void,
+    _class_id: *mut Tuid,
+) -> TResult {
+    K_NOT_IMPLEMENTED
+}
+
+unsafe extern "system" fn component_set_io_mode(_this: *mut c_void, _mode: i32) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn component_bus_count(_this: *mut c_void, media: i32, dir: i32) -> i32 {
+    match (media, dir) {
+        (K_AUDIO, K_OUTPUT) | (K_EVENT, K_INPUT) => 1,
+        _ => 0,
+    }
+}
+
+unsafe extern "system" fn component_bus_info(
+    _this: *mut c_void,
+    media: i32,
+    dir: i32,
+    index: i32,
+    bus: *mut BusInfo,
+) -> TResult {
+    if index != 0 || bus.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let (channel_count, name) = match (media, dir) {
+        (K_AUDIO, K_OUTPUT) => (2, "Output"),
+        (K_EVENT, K_INPUT) => (16, "Notes"),
+        _ => return K_INVALID_ARGUMENT,
+    };
+    let bus = unsafe { &mut *bus };
+    bus.media_type = media;
+    bus.direction = dir;
+    bus.channel_count = channel_count;
+    write_string128(&mut bus.name, name);
+    bus.bus_type = K_MAIN;
+    bus.flags = K_DEFAULT_ACTIVE;
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn component_routing_info(
+    _this: *mut c_void,
+    _in_info: *mut c_void,
+    _out_info: *mut c_void,
+) -> TResult {
+    K_NOT_IMPLEMENTED
+}
+
+unsafe extern "system" fn component_activate_bus(
+    _this: *mut c_void,
+    _media: i32,
+    _dir: i32,
+    _index: i32,
+    _state: u8,
+) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn component_set_active(this: *mut c_void, state: u8) -> TResult {
+    let plugin = unsafe { component(this) };
+    let Ok(mut guard) = plugin.active.lock() else {
+        return K_RESULT_FALSE;
+    };
+    *guard = if state != 0 {
+        let sample_rate = f32::from_bits(plugin.sample_rate.load(Ordering::Relaxed));
+        let Ok(macros) = plugin.macros.lock() else {
+            return K_RESULT_FALSE;
+        };
+        plugin.reload.store(false, Ordering::Relaxed);
+        Some(Active {
+            synth: Instrument::new(
+                sample_rate,
+                plugin.snapshot(),
+                plugin.switch_snapshot(),
+                *macros,
+            ),
+            changes: Vec::with_capacity(CHANGE_CAPACITY),
+        })
+    } else {
+        None
+    };
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn component_set_state(this: *mut c_void, state: *mut IBStream) -> TResult {
+    unsafe { component(this).load(state) }
+}
+
+unsafe extern "system" fn component_get_state(this: *mut c_void, state: *mut IBStream) -> TResult {
+    unsafe { component(this).save(state) }
+}
+
+unsafe extern "system" fn processor_query_interface(
+    this: *mut c_void,
+    iid: *const Tuid,
+    obj: *mut *mut c_void,
+) -> TResult {
+    unsafe { processor(this).query_interface(iid, obj) }
+}
+
+unsafe extern "system" fn processor_add_ref(this: *mut c_void) -> u32 {
+    unsafe { processor(this) }.add_ref()
+}
+
+unsafe extern "system" fn processor_release(this: *mut c_void) -> u32 {
+    unsafe { Plugin::release(processor(this)) }
+}
+
+unsafe extern "system" fn processor_set_bus_arrangements(
+    _this: *mut c_void,
+    _inputs: *mut u64,
+    num_ins: i32,
+    outputs: *mut u64,
+    num_outs: i32,
+) -> TResult {
+    if num_ins == 0 && num_outs == 1 && !outputs.is_null() && unsafe { *outputs } == K_STEREO {
+        K_RESULT_TRUE
+    } else {
+        K_RESULT_FALSE
+    }
+}
+
+unsafe extern "system" fn processor_bus_arrangement(
+    _this: *mut c_void,
+    dir: i32,
+    index: i32,
+    arrangement: *mut u64,
+) -> TResult {
+    if dir != K_OUTPUT || index != 0 || arrangement.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    unsafe { *arrangement = K_STEREO };
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn processor_can_process_sample_size(
+    _this: *mut c_void,
+    symbolic_sample_size: i32,
+) -> TResult {
+    if symbolic_sample_size == K_SAMPLE32 {
+        K_RESULT_TRUE
+    } else {
+        K_RESULT_FALSE
+    }
+}
+
+unsafe extern "system" fn processor_latency_samples(_this: *mut c_void) -> u32 {
+    0
+}
+
+unsafe extern "system" fn processor_setup_processing(
+    this: *mut c_void,
+    setup: *mut ProcessSetup,
+) -> TResult {
+    if setup.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let setup = unsafe { &*setup };
+    if setup.symbolic_sample_size != K_SAMPLE32 {
+        return K_RESULT_FALSE;
+    }
+    let plugin = unsafe { processor(this) };
+    plugin
+        .sample_rate
+        .store((setup.sample_rate as f32).to_bits(), Ordering::Relaxed);
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn processor_set_processing(_this: *mut c_void, _state: u8) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe fn collect_param_changes(
+    plugin: &Plugin,
+    changes: *mut IParameterChanges,
+    active: &mut Active,
+) {
+    if changes.is_null() {
+        return;
+    }
+    let count = unsafe { ((*(*changes).vtbl).get_parameter_count)(changes) };
+    for index in 0..count {
+        let queue = unsafe { ((*(*changes).vtbl).get_parameter_data)(changes, index) };
+        if queue.is_null() {
+            continue;
+        }
+        let Some(param) = host_param(unsafe { ((*(*queue).vtbl).get_parameter_id)(queue) }) else {
+            continue;
+        };
+        let points = unsafe { ((*(*queue).vtbl).get_point_count)(queue) };
+        for point in 0..points {
+            let mut offset = 0;
+            let mut value = 0.0;
+            let result =
+                unsafe { ((*(*queue).vtbl).get_point)(queue, point, &mut offset, &mut value) };
+            if result != K_RESULT_OK {
+                continue;
+            }
+            // The controller side reports the latest value straight away.
+            plugin.store_normalized(param, value as f32);
+            let kind = match param {
+                HostParam::Knob(knob) => ChangeKind::Param(knob, (value as f32).clamp(0.0, 1.0)),
+                HostParam::Switch(switch) => {
+                    ChangeKind::Switch(switch, switch.position(value as f32))
+                }
+            };
+            active.queue(plugin, offset.max(0) as usize, kind);
+        }
+    }
+}
+
+unsafe fn collect_note_events(plugin: &Plugin, events: *mut IEventList, active: &mut Active) {
+    if events.is_null() {
+        return;
+    }
+    let count = unsafe { ((*(*events).vtbl).get_event_count)(events) };
+    for index in 0..count {
+        let mut event: Event = unsafe { std::mem::zeroed() };
+        if unsafe { ((*(*events).vtbl).get_event)(events, index, &mut event) } != K_RESULT_OK {
+            continue;
+        }
+        let kind = match event.type_ {
+            K_NOTE_ON_EVENT => {
+                let note = unsafe { &event.payload.note_on };
+                if !(0..128).contains(&note.pitch) {
+                    continue;
+                }
+                // Velocity-zero note-ons are note-offs, as in MIDI.
+                if note.velocity > 0.0 {
+                    ChangeKind::NoteOn(note.pitch as u8, note.velocity)
+                } else {
+                    ChangeKind::NoteOff(note.pitch as u8)
+                }
+            }
+            K_NOTE_OFF_EVENT => {
+                let note = unsafe { &event.payload.note_off };
+                if !(0..128).contains(&note.pitch) {
+                    continue;
+                }
+                ChangeKind::NoteOff(note.pitch as u8)
+            }
+            _ => continue,
+        };
+        active.queue(plugin, event.sample_offset.max(0) as usize, kind);
+    }
+}
+
+unsafe extern "system" fn processor_process(this: *mut c_void, data: *mut ProcessData) -> TResult {
+    if data.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let plugin = unsafe { processor(this) };
+    let data = unsafe { &*data };
+    let frames = data.num_samples.max(0) as usize;
+    let mut channels: [&mut [f32]; 2] = [&mut [], &mut []];
+    if data.num_outputs > 0 && !data.outputs.is_null() {
+        let output = unsafe { &mut *data.outputs };
+        output.silence_flags = 0;
+        if !output.channel_buffers32.is_null() {
+            for channel in 0..output.num_channels.max(0) as usize {
+                let buffer = unsafe { *output.channel_buffers32.add(channel) };
+                if buffer.is_null() {
+                    continue;
+                }
+                let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, frames) };
+                match channels.get_mut(channel) {
+                    Some(slot) => *slot = buffer,
+                    None => buffer.fill(0.0),
+                }
+            }
+        }
+    }
+    let [left, right] = channels;
+
+    // Only the main thread contends for this lock, and only while the host
+    // has processing stopped.
+    let Ok(mut guard) = plugin.active.try_lock() else {
+        left.fill(0.0);
+        right.fill(0.0);
+        return K_RESULT_OK;
+    };
+    let Some(active) = guard.as_mut() else {
+        left.fill(0.0);
+        right.fill(0.0);
+        return K_NOT_INITIALIZED;
+    };
+    // A state load holds the macros only briefly; if it still does, the
+    // reload waits for the next block.
+    if plugin.reload.swap(false, Ordering::Relaxed) {
+        match plugin.macros.try_lock() {
+            Ok(macros) => {
+                active
+                    .synth
+                    .load_values(plugin.snapshot(), plugin.switch_snapshot(), *macros)
+            }
+            Err(_) => plugin.reload.store(true, Ordering::Relaxed),
+        }
+    }
+    active.changes.clear();
+    unsafe {
+        collect_param_changes(plugin, data.input_parameter_changes, active);
+        collect_note_events(plugin, data.input_events, active);
+    }
+    active
+        .changes
+        .sort_unstable_by_key(|change| (change.offset, change.order));
+
+    let synth = &mut active.synth;
+    let mut mono_scratch = [0.0f32; GLIDE_STEP_FRAMES];
+    let mut render = |synth: &mut Instrument, start: usize, end: usize| {
+        if left.is_empty() {
+            return;
+        }
+        if right.is_empty() {
+            for chunk_start in (start..end).step_by(GLIDE_STEP_FRAMES) {
+                let chunk_end = (chunk_start + GLIDE_STEP_FRAMES).min(end);
+                let len = chunk_end - chunk_start;
+                synth.render(&mut left[chunk_start..chunk_end], &mut mono_scratch[..len]);
+            }
+        } else {
+            synth.render(&mut left[start..end], &mut right[start..end]);
+        }
+    };
+    let mut cursor = 0;
+    for change in &active.changes {
+        let time = change.offset.clamp(cursor, frames);
+        if time > cursor {
+            render(synth, cursor, time);
+            cursor = time;
+        }
+        apply_change(plugin, synth, change.kind);
+    }
+    if cursor < frames {
+        render(synth, cursor, frames);
+    }
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn processor_tail_samples(_this: *mut c_void) -> u32 {
+    0
+}
+
+unsafe extern "system" fn controller_query_interface(
+    this: *mut c_void,
+    iid: *const Tuid,
+    obj: *mut *mut c_void,
+) -> TResult {
+    unsafe { controller(this).query_interface(iid, obj) }
+}
+
+unsafe extern "system" fn controller_add_ref(this: *mut c_void) -> u32 {
+    unsafe { controller(this) }.add_ref()
+}
+
+unsafe extern "system" fn controller_release(this: *mut c_void) -> u32 {
+    unsafe { Plugin::release(controller(this)) }
+}
+
+unsafe extern "system" fn controller_initialize(
+    _this: *mut c_void,
+    _context: *mut c_void,
+) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn controller_terminate(_this: *mut c_void) -> TResult {
+    K_RESULT_OK
+}
+
+// The controller shares the component's parameter storage, so the state the
+// host forwards from the component is already applied.
+unsafe extern "system" fn controller_set_component_state(
+    _this: *mut c_void,
+    _state: *mut IBStream,
+) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn controller_set_state(
+    _this: *mut c_void,
+    _state: *mut IBStream,
+) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn controller_get_state(
+    _this: *mut c_void,
+    _state: *mut IBStream,
+) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn controller_parameter_count(_this: *mut c_void) -> i32 {
+    host_params().count() as i32
+}
+
+unsafe extern "system" fn controller_parameter_info(
+    _this: *mut c_void,
+    index: i32,
+    info: *mut ParameterInfo,
+) -> TResult {
+    if index < 0 || info.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let Some((id, param)) = host_params().nth(index as usize) else {
+        return K_INVALID_ARGUMENT;
+    };
+    let info = unsafe { &mut *info };
+    info.id = id;
+    info.unit_id = 0;
+    match param {
+        HostParam::Knob(knob) => {
+            let spec = ParamSpec::of(knob);
+            write_string128(&mut info.title, spec.name);
+            write_string128(&mut info.short_title, spec.name);
+            write_string128(&mut info.units, spec.unit.label());
+            info.step_count = 0;
+            info.default_normalized_value = spec.default as f64;
+            info.flags = K_CAN_AUTOMATE;
+        }
+        HostParam::Switch(switch) => {
+            write_string128(&mut info.title, switch.label());
+            write_string128(&mut info.short_title, switch.label());
+            write_string128(&mut info.units, "");
+            info.step_count = switch.positions() as i32 - 1;
+            info.default_normalized_value = switch.normalized(switch.default_position()) as f64;
+            info.flags = K_CAN_AUTOMATE | K_IS_LIST;
+        }
+    }
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn controller_param_string(
+    _this: *mut c_void,
+    id: ParamId,
+    value: ParamValue,
+    string: *mut u16,
+) -> TResult {
+    let Some(param) = host_param(id) else {
+        return K_INVALID_ARGUMENT;
+    };
+    if string.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let text = match param {
+        HostParam::Knob(knob) => format_knob(knob, value as f32),
+        HostParam::Switch(switch) => switch
+            .position_label(switch.position(value as f32))
+            .to_string(),
+    };
+    let buffer = unsafe { std::slice::from_raw_parts_mut(string, 128) };
+    write_string128(buffer, &text);
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn controller_param_value(
+    _this: *mut c_void,
+    id: ParamId,
+    string: *const u16,
+    value: *mut ParamValue,
+) -> TResult {
+    let Some(param) = host_param(id) else {
+        return K_INVALID_ARGUMENT;
+    };
+    if value.is_null() {
+        return K_INVALID_ARGUMENT;
+    }
+    let parse = |text: String| match param {
+        HostParam::Knob(_) => parse_knob(&text),
+        HostParam::Switch(switch) => parse_switch(switch, &text).map(|at| switch.normalized(at)),
+    };
+    match unsafe { read_utf16(string) }.and_then(parse) {
+        Some(parsed) => {
+            unsafe { *value = parsed as f64 };
+            K_RESULT_OK
+        }
+        None => K_RESULT_FALSE,
+    }
+}
+
+// Knobs have no plain range beyond their normalized travel; a switch's plain
+// value is its position, as the SDK has it for stepped parameters.
+unsafe extern "system" fn controller_normalized_to_plain(
+    _this: *mut c_void,
+    id: ParamId,
+    value: ParamValue,
+) -> ParamValue {
+    match host_param(id) {
+        Some(HostParam::Switch(switch)) => switch.position(value as f32) as f64,
+        _ => value,
+    }
+}
+
+unsafe extern "system" fn controller_plain_to_normalized(
+    _this: *mut c_void,
+    id: ParamId,
+    value: ParamValue,
+) -> ParamValue {
+    match host_param(id) {
+        Some(HostParam::Switch(switch)) => {
+            switch.normalized(value.max(0.0).round() as usize) as f64
+        }
+        _ => value,
+    }
+}
+
+unsafe extern "system" fn controller_get_param(this: *mut c_void, id: ParamId) -> ParamValue {
+    match host_param(id) {
+        Some(param) => unsafe { controller(this) }.normalized(param) as f64,
+        None => 0.0,
+    }
+}
+
+// The host mirrors edits to the processor as parameter changes, so this only
+// updates what the controller reports back.
+unsafe extern "system" fn controller_set_param(
+    this: *mut c_void,
+    id: ParamId,
+    value: ParamValue,
+) -> TResult {
+    let Some(param) = host_param(id) else {
+        return K_INVALID_ARGUMENT;
+    };
+    unsafe { controller(this) }.store_normalized(param, value as f32);
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn controller_set_component_handler(
+    _this: *mut c_void,
+    _handler: *mut c_void,
+) -> TResult {
+    K_RESULT_OK
+}
+
+unsafe extern "system" fn controller_create_view(
+    _this: *mut c_void,
+    _name: *const c_char,
+) -> *mut c_void {
+    ptr::null_mut()
+}
@@ -0,0 +1,234 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    command::EngineCommand,
+    filter::{FilterSlope, FilterType},
+    mixer::Mixer,
+    modifiers::{EnvelopeParams, Modifiers},
+    oscillatorbank::OscillatorBank,
+    output::SynthPipeline,
+    oversample::Oversampling,
+    vco::{OscEngine, VcoCommand, Waveform, midi_to_voltage},
+};
+
+pub const BENCH_SAMPLE_RATE: u32 = 48_000;
+// Same as a mid-sized device buffer, so per-call overhead is counted too.
+const BENCH_BLOCK_FRAMES: usize = 512;
+const BENCH_SEED: u64 = 0x6d69_6e69_726f_6f67;
+const BENCH_VOICES: usize = 3;
+// The patch plays this arpeggio at one note per NOTE_SECONDS, so the
+// envelopes keep cycling through every stage.
+const BENCH_NOTES: [i32; 4] = [36, 43, 48, 55];
+const NOTE_SECONDS: f32 = 0.25;
+
+/// The parts of `SynthPipeline::process` timed separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BenchStage {
+    Oscillators,
+    Mixer,
+    Modifiers,
+    Output,
+}
+
+impl BenchStage {
+    pub const ALL: [BenchStage; 4] = [
+        BenchStage::Oscillators,
+        BenchStage::Mixer,
+        BenchStage::Modifiers,
+        BenchStage::Output,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BenchStage::Oscillators => "oscillators",
+            BenchStage::Mixer => "noise+mixer",
+            BenchStage::Modifiers => "filter+vca",
+            BenchStage::Output => "output",
+        }
+    }
+}
+
+/// Time spent in each stage while profiling is on.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageTimings {
+    totals: [Duration; BenchStage::ALL.len()],
+}
+
+impl StageTimings {
+    pub fn get(&self, stage: BenchStage) -> Duration {
+        self.totals[stage as usize]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.totals.iter().sum()
+    }
+
+    /// Charges the time since `*since` to `stage` and restarts the clock.
+    pub(crate) fn lap(&mut self, stage: BenchStage, since: &mut Instant) {
+        let now = Instant::now();
+        self.totals[stage as usize] += now - *since;
+        *since = now;
+    }
+}
+
+/// One oversampling mode's run of the bench patch.
+pub struct BenchRun {
+    pub oversampling: Oversampling,
+    pub wall: Duration,
+    pub stages: StageTimings,
+}
+
+pub struct BenchReport {
+    pub seconds: f32,
+    pub sample_rate: u32,
+    pub runs: Vec<BenchRun>,
+}
+
+impl BenchReport {
+    fn frames(&self) -> f64 {
+        (self.seconds * self.sample_rate as f32) as f64
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Bench: {:.1} s of the bench patch at {} Hz, {BENCH_BLOCK_FRAMES}-frame blocks",
+            self.seconds, self.sample_rate
+        )?;
+        write!(f, "{:<6}{:>10}{:>12}", "os", "realtime", "frames/s")?;
+        for stage in BenchStage::ALL {
+            write!(f, "{:>13}", stage.label())?;
+        }
+        writeln!(f)?;
+        for run in &self.runs {
+            let wall = run.wall.as_secs_f64().max(f64::EPSILON);
+            write!(
+                f,
+                "{:<6}{:>9.1}x{:>11.2}M",
+                run.oversampling.label(),
+                self.seconds as f64 / wall,
+                self.frames() / wall / 1e6
+            )?;
+            let timed = run.stages.total().as_secs_f64().max(f64::EPSILON);
+            for stage in BenchStage::ALL {
+                let seconds = run.stages.get(stage).as_secs_f64();
+                write!(
+                    f,
+                    "{:>6.0} ms{:>3.0}%",
+                    seconds * 1e3,
+                    seconds / timed * 100.0
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders `seconds` of a fixed, seeded patch offline once per
+/// oversampling mode, timing the whole render and each stage.
+pub fn run(seconds: f32, sample_rate: u32) -> BenchReport {
+    let runs = Oversampling::VALUES
+        .iter()
+        .map(|mode| run_mode(*mode, seconds, sample_rate))
+        .collect();
+    BenchReport {
+        seconds,
+        sample_rate,
+        runs,
+    }
+}
+
+fn run_mode(oversampling: Oversampling, seconds: f32, sample_rate: u32) -> BenchRun {
+    let mut pipeline = SynthPipeline::with_seed(
+        OscillatorBank::new(BENCH_VOICES),
+        Mixer::new(),
+        Modifiers::new(),
+        BENCH_SEED,
+    );
+    pipeline.set_sample_rate(sample_rate as f32);
+    load_bench_patch(&mut pipeline);
+    pipeline.apply(EngineCommand::Oversampling(oversampling));
+    pipeline.set_profiling(true);
+    let frames = (seconds.max(0.0) * sample_rate as f32) as usize;
+    let note_frames = ((NOTE_SECONDS * sample_rate as f32) as usize).max(1);
+    let mut buffer = vec![0.0; BENCH_BLOCK_FRAMES * 2];
+    let started = Instant::now();
+    let mut done = 0;
+    while done < frames {
+        let step = done / note_frames;
+        if done % note_frames < BENCH_BLOCK_FRAMES {
+            play_note(&mut pipeline, BENCH_NOTES[step % BENCH_NOTES.len()]);
+        }
+        let block = (frames - done).min(BENCH_BLOCK_FRAMES);
+        pipeline.process(&mut buffer[..block * 2]);
+        done += block;
+    }
+    BenchRun {
+        oversampling,
+        wall: started.elapsed(),
+        stages: pipeline.take_profile().unwrap_or_default(),
+    }
+}
+
+fn play_note(pipeline: &mut SynthPipeline, note: i32) {
+    for index in 0..BENCH_VOICES {
+        pipeline.apply(EngineCommand::Vco(
+            index,
+            VcoCommand::SetVoltage(midi_to_voltage(note)),
+        ));
+    }
+    pipeline.apply(EngineCommand::Gate(false));
+    pipeline.apply(EngineCommand::Gate(true));
+}
+
+// A busy lead: both oscillator engines, hard sync, FM, noise, the ladder at
+// high emphasis with the contour open, and the output stage's limiter on.
+fn load_bench_patch(pipeline: &mut SynthPipeline) {
+    let voices = [
+        (Waveform::Saw, OscEngine::Classic, 0.0),
+        (Waveform::PulseWide, OscEngine::Wavetable, 0.003),
+        (Waveform::Triangle, OscEngine::Classic, -1.0),
+    ];
+    for (index, (waveform, engine, detune)) in voices.into_iter().enumerate() {
+        for command in [
+            VcoCommand::SetWaveform(waveform),
+            VcoCommand::SetEngine(engine),
+            VcoCommand::SetDetune(detune),
+            VcoCommand::SetSync(index == 1),
+        ] {
+            pipeline.apply(EngineCommand::Vco(index, command));
+        }
+        pipeline.apply(EngineCommand::OscEnabled(index, true));
+        pipeline.apply(EngineCommand::MixLevel(index, 0.7));
+    }
+    let envelope = EnvelopeParams {
+        delay: 0.0,
+        attack: 0.01,
+        hold: 0.0,
+        decay: 0.15,
+        sustain: 0.5,
+        release: 0.1,
+    };
+    for command in [
+        EngineCommand::FmDepth(0.2),
+        EngineCommand::NoiseEnabled(true),
+        EngineCommand::NoiseLevel(0.2),
+        EngineCommand::FilterType(FilterType::LowPass),
+        EngineCommand::FilterSlope(FilterSlope::Db24),
+        EngineCommand::Cutoff(1_200.0),
+        EngineCommand::FilterEmphasis(0.7),
+        EngineCommand::FilterContour(0.6),
+        EngineCommand::FilterEnvelope(envelope),
+        EngineCommand::LoudnessEnvelope(envelope),
+        EngineCommand::MasterLevel(0.8),
+        EngineCommand::Limiter(true),
+    ] {
+        pipeline.apply(command);
+    }
+}
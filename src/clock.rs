@@ -0,0 +1,254 @@
+use std::collections::VecDeque;
+
+pub const DEFAULT_BPM: f32 = 120.0;
+pub const MIN_BPM: f32 = 30.0;
+pub const MAX_BPM: f32 = 300.0;
+pub const BEATS_PER_BAR: u32 = 4;
+
+const MIDI_CLOCK_PPQ: f64 = 24.0;
+// Taps further apart than this start a new count.
+const TAP_TIMEOUT: f64 = 2.0;
+const TAP_HISTORY: usize = 4;
+// MIDI clock arrives in per-frame batches, so its rate is measured over a
+// window long enough to hide the UI frame jitter.
+const CLOCK_WINDOW: f64 = 2.0;
+const CLOCK_MIN_SPAN: f64 = 0.5;
+const CLOCK_TIMEOUT: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClockSource {
+    #[default]
+    Internal,
+    Midi,
+}
+
+impl ClockSource {
+    pub fn next(self) -> Self {
+        match self {
+            ClockSource::Internal => ClockSource::Midi,
+            ClockSource::Midi => ClockSource::Internal,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ClockSource::Internal => "INT",
+            ClockSource::Midi => "MIDI",
+        }
+    }
+}
+
+/// Note length a tempo-synced modulator repeats at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncDivision {
+    TwoBars,
+    Bar,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+impl SyncDivision {
+    pub const VALUES: [SyncDivision; 7] = [
+        SyncDivision::TwoBars,
+        SyncDivision::Bar,
+        SyncDivision::Half,
+        SyncDivision::Quarter,
+        SyncDivision::Eighth,
+        SyncDivision::Sixteenth,
+        SyncDivision::ThirtySecond,
+    ];
+
+    /// Picks a division from a 0–1 rate knob, slowest at the bottom.
+    pub fn from_knob(value: f32) -> Self {
+        let last = Self::VALUES.len() - 1;
+        Self::VALUES[(value.clamp(0.0, 1.0) * last as f32).round() as usize]
+    }
+
+    pub fn beats(self) -> f64 {
+        match self {
+            SyncDivision::TwoBars => 8.0,
+            SyncDivision::Bar => 4.0,
+            SyncDivision::Half => 2.0,
+            SyncDivision::Quarter => 1.0,
+            SyncDivision::Eighth => 0.5,
+            SyncDivision::Sixteenth => 0.25,
+            SyncDivision::ThirtySecond => 0.125,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SyncDivision::TwoBars => "2 BARS",
+            SyncDivision::Bar => "1/1",
+            SyncDivision::Half => "1/2",
+            SyncDivision::Quarter => "1/4",
+            SyncDivision::Eighth => "1/8",
+            SyncDivision::Sixteenth => "1/16",
+            SyncDivision::ThirtySecond => "1/32",
+        }
+    }
+}
+
+/// Global beat position for everything that syncs to tempo. Runs from its
+/// own BPM (set directly or tapped) or follows incoming MIDI clock, in
+/// which case the beat advances by pulses and the BPM is measured.
+#[derive(Clone)]
+pub struct TempoClock {
+    source: ClockSource,
+    bpm: f32,
+    beat: f64,
+    elapsed: f64,
+    taps: VecDeque<f64>,
+    pulses: u64,
+    pulse_history: VecDeque<(f64, u64)>,
+    last_pulse: f64,
+    running: bool,
+    restarted: bool,
+}
+
+impl TempoClock {
+    pub fn new() -> Self {
+        Self {
+            source: ClockSource::Internal,
+            bpm: DEFAULT_BPM,
+            beat: 0.0,
+            elapsed: 0.0,
+            taps: VecDeque::new(),
+            pulses: 0,
+            pulse_history: VecDeque::new(),
+            last_pulse: f64::NEG_INFINITY,
+            running: true,
+            restarted: false,
+        }
+    }
+
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    pub fn set_source(&mut self, source: ClockSource) {
+        self.source = source;
+        self.pulse_history.clear();
+        self.running = true;
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+    }
+
+    /// Beats since the last restart, fractional.
+    pub fn beat(&self) -> f64 {
+        self.beat
+    }
+
+    /// True while MIDI clock is selected and pulses are arriving.
+    pub fn is_locked(&self) -> bool {
+        self.source == ClockSource::Midi && self.elapsed - self.last_pulse < CLOCK_TIMEOUT
+    }
+
+    /// Set when a tap or MIDI Start moved the downbeat; cleared on read.
+    pub fn take_restart(&mut self) -> bool {
+        std::mem::take(&mut self.restarted)
+    }
+
+    /// Averages the last few tap intervals into the BPM; every tap also
+    /// lands on a beat. Ignored while following MIDI clock.
+    pub fn tap(&mut self) {
+        if self.source == ClockSource::Midi {
+            return;
+        }
+        if self
+            .taps
+            .back()
+            .is_some_and(|last| self.elapsed - last > TAP_TIMEOUT)
+        {
+            self.taps.clear();
+        }
+        self.taps.push_back(self.elapsed);
+        if self.taps.len() > TAP_HISTORY + 1 {
+            self.taps.pop_front();
+        }
+        if let (Some(first), Some(last)) = (self.taps.front(), self.taps.back())
+            && self.taps.len() > 1
+        {
+            let interval = (last - first) / (self.taps.len() - 1) as f64;
+            self.set_bpm((60.0 / interval) as f32);
+        }
+        self.beat = self.beat.round();
+        self.restarted = true;
+    }
+
+    pub fn midi_pulse(&mut self) {
+        if self.source != ClockSource::Midi || !self.running {
+            return;
+        }
+        self.pulses += 1;
+        self.last_pulse = self.elapsed;
+        self.beat = self.pulses as f64 / MIDI_CLOCK_PPQ;
+    }
+
+    pub fn midi_start(&mut self) {
+        if self.source != ClockSource::Midi {
+            return;
+        }
+        self.pulses = 0;
+        self.pulse_history.clear();
+        self.beat = 0.0;
+        self.running = true;
+        self.restarted = true;
+    }
+
+    pub fn midi_continue(&mut self) {
+        self.running = true;
+    }
+
+    pub fn midi_stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        let dt = dt as f64;
+        self.elapsed += dt;
+        match self.source {
+            ClockSource::Internal => self.beat += dt * self.bpm as f64 / 60.0,
+            ClockSource::Midi => self.follow_midi(dt),
+        }
+    }
+
+    fn follow_midi(&mut self, dt: f64) {
+        if !self.is_locked() {
+            return;
+        }
+        self.pulse_history.push_back((self.elapsed, self.pulses));
+        while self
+            .pulse_history
+            .front()
+            .is_some_and(|(time, _)| self.elapsed - time > CLOCK_WINDOW)
+        {
+            self.pulse_history.pop_front();
+        }
+        if let Some(&(time, pulses)) = self.pulse_history.front() {
+            let span = self.elapsed - time;
+            if span >= CLOCK_MIN_SPAN {
+                let beats = (self.pulses - pulses) as f64 / MIDI_CLOCK_PPQ;
+                self.set_bpm((beats * 60.0 / span) as f32);
+            }
+        }
+        // Glide between pulses, never past the next one.
+        let next_pulse = (self.pulses + 1) as f64 / MIDI_CLOCK_PPQ;
+        self.beat = (self.beat + dt * self.bpm as f64 / 60.0).min(next_pulse);
+    }
+}
+
+impl Default for TempoClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
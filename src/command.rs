@@ -0,0 +1,298 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+use std::{
+    collections::HashMap,
+    mem::{Discriminant, discriminant},
+    sync::{
+        Arc,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+};
+
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use crate::{
+    clock::SyncDivision,
+    filter::{FilterSlope, FilterType},
+    mixer::ExternalInput,
+    modifiers::{EnvelopeCurve, EnvelopeParams, VelocityRoute},
+    modulation::ModulationParams,
+    oscillatorbank::PhaseMode,
+    oversample::Oversampling,
+    probe::{ProbePoint, ProbeTap},
+    recorder::RecordTap,
+    testsignal::TestSignal,
+    vco::VcoCommand,
+};
+
+const QUEUE_CAPACITY: usize = 1024;
+// Replaced taps waiting for the UI side to free them. Each job retires
+// exactly one, and the sender empties this ring before every push, so what
+// waits here never outnumbers the jobs one full command queue can carry: at
+// this size `retire` cannot fail and nothing is dropped on the audio thread.
+const RETIRED_CAPACITY: usize = QUEUE_CAPACITY;
+
+struct ScheduledCommand {
+    frame: u64,
+    message: EngineMessage,
+}
+
+/// Everything the UI hands the audio thread travels on the one queue:
+/// parameter commands, and jobs that move something into the pipeline.
+pub enum EngineMessage {
+    Command(EngineCommand),
+    Job(PipelineJob),
+}
+
+/// Taps into the signal chain. Unlike parameters these are moved rather
+/// than copied, so they cannot be deduplicated or resent.
+pub enum PipelineJob {
+    RecordTap(Option<RecordTap>),
+    /// Feeds the tuner.
+    ProbeTap(Option<(ProbePoint, ProbeTap)>),
+    /// Feeds the debug scope when it looks somewhere other than the output.
+    ScopeTap(Option<(ProbePoint, ProbeTap)>),
+    /// Audio captured from the input device for EXT INPUT.
+    ExternalInput(Option<ExternalInput>),
+}
+
+type ParamSlot = (
+    Discriminant<EngineCommand>,
+    usize,
+    Option<Discriminant<VcoCommand>>,
+);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EngineCommand {
+    Vco(usize, VcoCommand),
+    Gate(bool),
+    /// Keyboard pitch in volts for the CV output, 1 V/oct like the VCOs.
+    PitchCv(f32),
+    Retrigger,
+    TriggerEnvelopes,
+    PhaseMode(PhaseMode),
+    Oversampling(Oversampling),
+    MixLevel(usize, f32),
+    OscEnabled(usize, bool),
+    Pan(usize, f32),
+    NoisePan(f32),
+    NoiseLevel(f32),
+    NoiseEnabled(bool),
+    /// Position on the violet-to-brown noise color sweep, 0 to 1.
+    NoiseMorph(f32),
+    FmDepth(f32),
+    Modulation(ModulationParams),
+    ExternalLevel(f32),
+    ExternalEnabled(bool),
+    FeedbackPatch(bool),
+    /// Release time in seconds of the external input's envelope follower.
+    FollowerRelease(f32),
+    /// Octaves the follower raises the cutoff by at full input.
+    FollowerCutoff(f32),
+    /// How far the VCA closes while the external input is silent, 0 to 1.
+    FollowerVca(f32),
+    RingLevel(f32),
+    RingEnabled(bool),
+    MasterLevel(f32),
+    Limiter(bool),
+    RumbleFilter(bool),
+    ChorusEnabled(bool),
+    /// Chorus LFO rate in Hz.
+    ChorusRate(f32),
+    /// Chorus sweep depth and wet mix, 0 to 1.
+    ChorusDepth(f32),
+    ChorusMix(f32),
+    DelayEnabled(bool),
+    /// Note length the delay follows at the clock tempo, or `None` to run
+    /// free on `DelayTime`.
+    DelaySync(Option<SyncDivision>),
+    /// Echo time in seconds while the delay runs free.
+    DelayTime(f32),
+    /// Delay feedback and wet mix, 0 to 1.
+    DelayFeedback(f32),
+    /// Corner of the low-pass in the delay loop, in Hz.
+    DelayDamping(f32),
+    DelayMix(f32),
+    ReverbEnabled(bool),
+    /// Reverb tail length in seconds to fall 60 dB.
+    ReverbDecay(f32),
+    /// Corner of the low-pass in the reverb's lines, in Hz.
+    ReverbDamping(f32),
+    ReverbMix(f32),
+    Cutoff(f32),
+    FilterEmphasis(f32),
+    FilterContour(f32),
+    FilterType(FilterType),
+    FilterSlope(FilterSlope),
+    FilterEnvelope(EnvelopeParams),
+    FilterEnvelopeCurve(EnvelopeCurve),
+    FilterEnvelopeLoop(bool),
+    Velocity(f32),
+    VelocityRoute(VelocityRoute),
+    LoudnessEnvelope(EnvelopeParams),
+    LoudnessEnvelopeCurve(EnvelopeCurve),
+    TestSignal(TestSignal),
+    Tempo(f32),
+    ClickLevel(f32),
+    /// Realigns the click to a beat, given as its position in the bar.
+    ClickRestart(u32),
+}
+
+impl EngineCommand {
+    fn slot(&self) -> ParamSlot {
+        match self {
+            EngineCommand::Vco(index, command) => {
+                (discriminant(self), *index, Some(discriminant(command)))
+            }
+            EngineCommand::MixLevel(index, _)
+            | EngineCommand::OscEnabled(index, _)
+            | EngineCommand::Pan(index, _) => (discriminant(self), *index, None),
+            _ => (discriminant(self), 0, None),
+        }
+    }
+}
+
+// Published by the audio callback at the start of every block so the UI can
+// stamp commands with the output frame they should land on.
+struct EngineClock {
+    #[cfg(not(target_arch = "wasm32"))]
+    origin: Instant,
+    block_start_nanos: AtomicU64,
+    block_frame: AtomicU64,
+    block_len: AtomicU64,
+    sample_rate: AtomicU32,
+}
+
+impl EngineClock {
+    fn new() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            origin: Instant::now(),
+            block_start_nanos: AtomicU64::new(0),
+            block_frame: AtomicU64::new(0),
+            block_len: AtomicU64::new(0),
+            sample_rate: AtomicU32::new(44_100f32.to_bits()),
+        }
+    }
+
+    fn publish(&self, frame: u64, len: usize, sample_rate: f32) {
+        let nanos = self.now_nanos();
+        self.block_start_nanos.store(nanos, Ordering::Relaxed);
+        self.block_len.store(len as u64, Ordering::Relaxed);
+        self.sample_rate
+            .store(sample_rate.to_bits(), Ordering::Relaxed);
+        self.block_frame.store(frame, Ordering::Release);
+    }
+
+    // Events are delayed by one block: a command sent partway through the
+    // current block's wall-clock period lands at the same offset in the next.
+    fn schedule_frame(&self) -> u64 {
+        let frame = self.block_frame.load(Ordering::Acquire);
+        let len = self.block_len.load(Ordering::Relaxed);
+        let rate = f32::from_bits(self.sample_rate.load(Ordering::Relaxed));
+        let started = self.block_start_nanos.load(Ordering::Relaxed);
+        let elapsed = self.now_nanos().saturating_sub(started);
+        let offset = ((elapsed as f64 * 1e-9 * rate as f64) as u64).min(len);
+        frame + len + offset
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now_nanos(&self) -> u64 {
+        self.origin.elapsed().as_nanos() as u64
+    }
+
+    // Browsers render on the UI thread between frames, so there is no
+    // wall-clock offset to recover; commands land at the next block start.
+    #[cfg(target_arch = "wasm32")]
+    fn now_nanos(&self) -> u64 {
+        0
+    }
+}
+
+pub struct CommandQueue {
+    consumer: Consumer<ScheduledCommand>,
+    retired: Producer<PipelineJob>,
+    clock: Arc<EngineClock>,
+}
+
+impl CommandQueue {
+    pub fn publish_block(&self, frame: u64, len: usize, sample_rate: f32) {
+        self.clock.publish(frame, len, sample_rate);
+    }
+
+    pub fn pop_due(&mut self, frame: u64) -> Option<EngineMessage> {
+        let scheduled = self.consumer.peek().ok()?;
+        if scheduled.frame > frame {
+            return None;
+        }
+        self.consumer.pop().ok().map(|scheduled| scheduled.message)
+    }
+
+    pub fn next_due(&self) -> Option<u64> {
+        self.consumer.peek().ok().map(|scheduled| scheduled.frame)
+    }
+
+    /// Sends whatever a job replaced back to the UI side to be freed there.
+    pub fn retire(&mut self, job: PipelineJob) {
+        let pushed = self.retired.push(job).is_ok();
+        debug_assert!(pushed, "retire ring smaller than the command queue");
+    }
+}
+
+pub struct CommandSender {
+    producer: Producer<ScheduledCommand>,
+    retired: Consumer<PipelineJob>,
+    clock: Arc<EngineClock>,
+    sent: HashMap<ParamSlot, EngineCommand>,
+}
+
+impl CommandSender {
+    /// Sends a parameter only when it differs from the last value delivered
+    /// for the same slot.
+    pub fn send_changed(&mut self, command: EngineCommand) {
+        let slot = command.slot();
+        if self.sent.get(&slot) == Some(&command) {
+            return;
+        }
+        if self.push(EngineMessage::Command(command)) {
+            self.sent.insert(slot, command);
+        }
+    }
+
+    pub fn send(&mut self, command: EngineCommand) {
+        // A full queue means the audio thread has stalled; blocking the UI
+        // would not help it catch up.
+        self.push(EngineMessage::Command(command));
+    }
+
+    pub fn send_job(&mut self, job: PipelineJob) {
+        self.push(EngineMessage::Job(job));
+    }
+
+    fn push(&mut self, message: EngineMessage) -> bool {
+        while self.retired.pop().is_ok() {}
+        let frame = self.clock.schedule_frame();
+        self.producer
+            .push(ScheduledCommand { frame, message })
+            .is_ok()
+    }
+}
+
+pub fn command_channel() -> (CommandSender, CommandQueue) {
+    let (producer, consumer) = RingBuffer::new(QUEUE_CAPACITY);
+    let (retire_producer, retire_consumer) = RingBuffer::new(RETIRED_CAPACITY);
+    let clock = Arc::new(EngineClock::new());
+    (
+        CommandSender {
+            producer,
+            retired: retire_consumer,
+            clock: clock.clone(),
+            sent: HashMap::new(),
+        },
+        CommandQueue {
+            consumer,
+            retired: retire_producer,
+            clock,
+        },
+    )
+}
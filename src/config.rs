@@ -0,0 +1,437 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use macroquad::prelude::KeyCode;
+use miniroog_model_r::{
+    cvgate::CvGateOutput,
+    output::{BufferRequest, DeviceRequest},
+};
+use tracing::{info, level_filters::LevelFilter, warn};
+
+use crate::controllers::{KeyLayout, key_from_name, key_name};
+
+pub const CONFIG_FILE: &str = "miniroog.toml";
+
+const MIN_WINDOW_SIZE: i32 = 320;
+// Engine rates the resampler is asked to bridge; past these it costs more
+// than it buys.
+const ENGINE_RATE_RANGE: std::ops::RangeInclusive<u32> = 22_050..=192_000;
+
+/// Launch settings from `miniroog.toml`; every field falls back to the
+/// built-in default when missing.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub window_width: i32,
+    pub window_height: i32,
+    pub fullscreen: bool,
+    pub device: DeviceRequest,
+    pub buffer: BufferRequest,
+    pub midi_port: Option<String>,
+    pub midi_output: Option<String>,
+    pub cv_gate: Option<CvGateOutput>,
+    pub gamepad: bool,
+    pub gamepad_device: Option<String>,
+    pub default_preset: Option<PathBuf>,
+    pub key_layout: KeyLayout,
+    pub white_keys: Vec<KeyCode>,
+    pub black_keys: Vec<KeyCode>,
+    pub tuning_scale: Option<PathBuf>,
+    pub tuning_map: Option<PathBuf>,
+    pub log_level: LevelFilter,
+    pub log_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            window_width: crate::SCREEN_WIDTH as i32,
+            window_height: crate::SCREEN_HEIGHT as i32,
+            fullscreen: false,
+            device: DeviceRequest::default(),
+            buffer: BufferRequest::Auto,
+            midi_port: None,
+            midi_output: None,
+            cv_gate: None,
+            gamepad: false,
+            gamepad_device: None,
+            default_preset: None,
+            key_layout: KeyLayout::Qwerty,
+            white_keys: KeyLayout::Qwerty.white_keys().to_vec(),
+            black_keys: KeyLayout::Qwerty.black_keys().to_vec(),
+            tuning_scale: None,
+            tuning_map: None,
+            log_level: LevelFilter::INFO,
+            log_dir: None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config, writing the defaults first if the file is missing.
+    /// A broken file is reported and ignored rather than overwritten.
+    pub fn load_or_create(path: &Path) -> Self {
+        if !path.exists() {
+            match fs::write(path, default_text()) {
+                Ok(()) => info!("Wrote default settings to {}", path.display()),
+                Err(err) => warn!("Could not write {}: {err}", path.display()),
+            }
+            return Self::default();
+        }
+        match Self::load(path) {
+            Ok(config) => config,
+            Err(err) => {
+                warn!("Ignoring {}: {err:#}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading config {}", path.display()))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let values = parse_toml(text)?;
+        let mut config = Self::default();
+        // Explicit key rows win over the layout, whichever comes first.
+        let mut white_keys = None;
+        let mut black_keys = None;
+        let mut cv_enabled = false;
+        let mut cv_gate = CvGateOutput::default();
+        for (key, value) in &values {
+            match key.as_str() {
+                "window.width" => config.window_width = window_size(key, value)?,
+                "window.height" => config.window_height = window_size(key, value)?,
+                "window.fullscreen" => config.fullscreen = value.as_bool(key)?,
+                "audio.device" => config.device.name = non_empty(value.as_str(key)?),
+                "audio.sample_rate" => {
+                    let rate = u32::try_from(value.as_int(key)?)
+                        .map_err(|_| anyhow!("{key}: must be 0 or a rate in Hz"))?;
+                    config.device.sample_rate = (rate > 0).then_some(rate);
+                }
+                "audio.engine_rate" => config.device.engine_rate = engine_rate(key, value)?,
+                "audio.buffer_frames" => {
+                    config.buffer = u32::try_from(value.as_int(key)?)
+                        .ok()
+                        .and_then(BufferRequest::from_frames)
+                        .ok_or_else(|| {
+                            anyhow!("{key}: use 0 (device default), 64, 128, 256, 512 or 1024")
+                        })?;
+                }
+                "midi.port" => config.midi_port = non_empty(value.as_str(key)?),
+                "midi.output" => config.midi_output = non_empty(value.as_str(key)?),
+                "cv.enabled" => cv_enabled = value.as_bool(key)?,
+                "cv.pitch_channel" => cv_gate.pitch_channel = cv_channel(key, value)?,
+                "cv.gate_channel" => cv_gate.gate_channel = cv_channel(key, value)?,
+                "cv.full_scale_volts" => {
+                    cv_gate.full_scale_volts = value.as_float(key)?;
+                    if cv_gate.full_scale_volts <= 0.0 {
+                        bail!("{key}: must be above 0");
+                    }
+                }
+                "cv.gate_volts" => cv_gate.gate_volts = value.as_float(key)?,
+                "gamepad.enabled" => config.gamepad = value.as_bool(key)?,
+                "gamepad.device" => config.gamepad_device = non_empty(value.as_str(key)?),
+                "preset.default" => {
+                    config.default_preset = non_empty(value.as_str(key)?).map(PathBuf::from)
+                }
+                "keys.layout" => {
+                    config.key_layout = KeyLayout::from_name(value.as_str(key)?)
+                        .ok_or_else(|| anyhow!("{key}: use qwerty, azerty or qwertz"))?
+                }
+                "keys.white" => white_keys = Some(parse_keys(key, value.as_str(key)?)?),
+                "keys.black" => black_keys = Some(parse_keys(key, value.as_str(key)?)?),
+                "tuning.scl" => {
+                    config.tuning_scale = non_empty(value.as_str(key)?).map(PathBuf::from)
+                }
+                "tuning.kbm" => {
+                    config.tuning_map = non_empty(value.as_str(key)?).map(PathBuf::from)
+                }
+                "log.level" => config.log_level = parse_log_level(key, value.as_str(key)?)?,
+                "log.dir" => config.log_dir = non_empty(value.as_str(key)?).map(PathBuf::from),
+                _ => warn!("Unknown setting {key} in config"),
+            }
+        }
+        if cv_enabled {
+            if cv_gate.pitch_channel == cv_gate.gate_channel {
+                bail!("cv: pitch_channel and gate_channel must differ");
+            }
+            config.cv_gate = Some(cv_gate);
+        }
+        config.white_keys = white_keys.unwrap_or_else(|| config.key_layout.white_keys().to_vec());
+        config.black_keys = black_keys.unwrap_or_else(|| config.key_layout.black_keys().to_vec());
+        Ok(config)
+    }
+}
+
+fn window_size(key: &str, value: &TomlValue) -> Result<i32> {
+    let size = value.as_int(key)?;
+    if size < MIN_WINDOW_SIZE as i64 || size > i32::MAX as i64 {
+        bail!("{key}: must be at least {MIN_WINDOW_SIZE}");
+    }
+    Ok(size as i32)
+}
+
+fn engine_rate(key: &str, value: &TomlValue) -> Result<Option<u32>> {
+    let rate = value.as_int(key)?;
+    if rate == 0 {
+        return Ok(None);
+    }
+    u32::try_from(rate)
+        .ok()
+        .filter(|rate| ENGINE_RATE_RANGE.contains(rate))
+        .map(Some)
+        .ok_or_else(|| {
+            anyhow!(
+                "{key}: use 0 (the device rate) or {} to {} Hz",
+                ENGINE_RATE_RANGE.start(),
+                ENGINE_RATE_RANGE.end()
+            )
+        })
+}
+
+fn cv_channel(key: &str, value: &TomlValue) -> Result<usize> {
+    usize::try_from(value.as_int(key)?)
+        .ok()
+        .filter(|channel| *channel >= 3)
+        .ok_or_else(|| anyhow!("{key}: use an output channel from 3 up; 1 and 2 carry the audio"))
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+pub fn parse_log_level(key: &str, text: &str) -> Result<LevelFilter> {
+    text.trim()
+        .parse()
+        .map_err(|_| anyhow!("{key}: use off, error, warn, info, debug or trace"))
+}
+
+fn parse_keys(key: &str, text: &str) -> Result<Vec<KeyCode>> {
+    text.split_whitespace()
+        .map(|name| key_from_name(name).ok_or_else(|| anyhow!("{key}: unknown key {name}")))
+        .collect()
+}
+
+fn key_list(keys: &[KeyCode]) -> String {
+    keys.iter()
+        .map(|keycode| key_name(*keycode))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn default_text() -> String {
+    let defaults = Config::default();
+    let cv = CvGateOutput::default();
+    format!(
+        "# MiniRoog Model R settings. Delete this file to restore the defaults.\n\
+         \n\
+         [window]\n\
+         width = {}\n\
+         height = {}\n\
+         fullscreen = false\n\
+         \n\
+         [audio]\n\
+         # Output device name, or part of it; empty uses the system default.\n\
+         device = \"\"\n\
+         # Sample rate in Hz; 0 keeps the device default.\n\
+         sample_rate = 0\n\
+         # Rate the engine runs at, resampled to the device's; 0 runs it at\n\
+         # the device rate. 96000 keeps the filter and aliasing the same on\n\
+         # any device.\n\
+         engine_rate = 0\n\
+         # Frames per buffer: 0 (device default), 64, 128, 256, 512 or 1024.\n\
+         buffer_frames = 0\n\
+         \n\
+         [midi]\n\
+         # Input port name, or part of it; empty opens the first port.\n\
+         port = \"\"\n\
+         # Output port name, or part of it, for the notes played on the window\n\
+         # and knob moves as CCs; empty sends nothing.\n\
+         output = \"\"\n\
+         \n\
+         [cv]\n\
+         # Pitch CV (1 V/oct, 0 V at middle C) and gate on spare channels of a\n\
+         # DC-coupled multichannel interface, for driving modular hardware.\n\
+         enabled = false\n\
+         pitch_channel = {}\n\
+         gate_channel = {}\n\
+         # Volts the interface puts out at full scale, and the gate height.\n\
+         full_scale_volts = {:.1}\n\
+         gate_volts = {:.1}\n\
+         \n\
+         [gamepad]\n\
+         # Play with a game controller: buttons are notes, the sticks bend and\n\
+         # modulate, the triggers sweep the filter. Linux only.\n\
+         enabled = false\n\
+         # Joystick device such as /dev/input/js0; empty opens the first one.\n\
+         device = \"\"\n\
+         \n\
+         [preset]\n\
+         # Patch loaded at startup when neither --preset nor a saved session applies.\n\
+         default = \"\"\n\
+         \n\
+         [keys]\n\
+         # qwerty, azerty or qwertz.\n\
+         layout = \"qwerty\"\n\
+         # Computer keys for the white and black notes, lowest first; these\n\
+         # override the layout when set.\n\
+         # white = \"{}\"\n\
+         # black = \"{}\"\n\
+         \n\
+         [tuning]\n\
+         # Scala scale file; empty plays 12-tone equal temperament.\n\
+         scl = \"\"\n\
+         # Scala keyboard mapping for the scale; empty maps keys in order from middle C.\n\
+         kbm = \"\"\n\
+         \n\
+         [log]\n\
+         # off, error, warn, info, debug or trace.\n\
+         level = \"info\"\n\
+         # Directory for daily-rotated log files; empty logs to the console only.\n\
+         dir = \"\"\n",
+        defaults.window_width,
+        defaults.window_height,
+        cv.pitch_channel,
+        cv.gate_channel,
+        cv.full_scale_volts,
+        cv.gate_volts,
+        key_list(&defaults.white_keys),
+        key_list(&defaults.black_keys),
+    )
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TomlValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl TomlValue {
+    fn as_str(&self, key: &str) -> Result<&str> {
+        match self {
+            TomlValue::String(text) => Ok(text),
+            _ => bail!("{key}: expected a quoted string"),
+        }
+    }
+
+    fn as_int(&self, key: &str) -> Result<i64> {
+        match self {
+            TomlValue::Int(value) => Ok(*value),
+            _ => bail!("{key}: expected a whole number"),
+        }
+    }
+
+    fn as_float(&self, key: &str) -> Result<f32> {
+        match self {
+            TomlValue::Int(value) => Ok(*value as f32),
+            TomlValue::Float(value) => Ok(*value as f32),
+            _ => bail!("{key}: expected a number"),
+        }
+    }
+
+    fn as_bool(&self, key: &str) -> Result<bool> {
+        match self {
+            TomlValue::Bool(value) => Ok(*value),
+            _ => bail!("{key}: expected true or false"),
+        }
+    }
+}
+
+// The small slice of TOML the config uses: `[section]` headers and
+// `key = value` lines with strings, numbers and booleans.
+fn parse_toml(text: &str) -> Result<HashMap<String, TomlValue>> {
+    let mut values = HashMap::new();
+    let mut section = String::new();
+    for (line_no, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let context = || format!("line {}", line_no + 1);
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("unterminated section header"))
+                .with_context(context)?;
+            section = name.trim().to_string();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `key = value`"))
+            .with_context(context)?;
+        let key = if section.is_empty() {
+            key.trim().to_string()
+        } else {
+            format!("{section}.{}", key.trim())
+        };
+        let value = parse_value(value.trim()).with_context(context)?;
+        values.insert(key, value);
+    }
+    Ok(values)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, ch) in line.char_indices() {
+        match ch {
+            '\\' if in_string && !escaped => {
+                escaped = true;
+                continue;
+            }
+            '"' if !escaped => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<TomlValue> {
+    if let Some(body) = text.strip_prefix('"') {
+        let body = body
+            .strip_suffix('"')
+            .ok_or_else(|| anyhow!("unterminated string"))?;
+        let mut value = String::new();
+        let mut chars = body.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '\\' {
+                value.push(ch);
+                continue;
+            }
+            match chars.next() {
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                _ => bail!("unsupported escape in string"),
+            }
+        }
+        return Ok(TomlValue::String(value));
+    }
+    match text {
+        "true" => return Ok(TomlValue::Bool(true)),
+        "false" => return Ok(TomlValue::Bool(false)),
+        _ => {}
+    }
+    let digits = text.replace('_', "");
+    if let Ok(value) = digits.parse::<i64>() {
+        return Ok(TomlValue::Int(value));
+    }
+    digits
+        .parse::<f64>()
+        .map(TomlValue::Float)
+        .map_err(|_| anyhow!("cannot read value `{text}`"))
+}
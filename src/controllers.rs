@@ -1,9 +1,250 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use macroquad::prelude::*;
+use miniroog_model_r::{tuning::Tuning, vco::midi_to_voltage};
 
-const MIDI_MIN: i32 = 21;
-const MIDI_MAX: i32 = 108;
+pub const MIDI_MIN: i32 = 21;
+pub const MIDI_MAX: i32 = 108;
+// Computer keys and touches have no strike strength.
+const FULL_VELOCITY: f32 = 1.0;
+
+pub const DEFAULT_WHITE_KEYS: [KeyCode; 10] = [
+    KeyCode::Z,
+    KeyCode::X,
+    KeyCode::C,
+    KeyCode::V,
+    KeyCode::B,
+    KeyCode::N,
+    KeyCode::M,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+];
+pub const DEFAULT_BLACK_KEYS: [KeyCode; 10] = [
+    KeyCode::S,
+    KeyCode::D,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::J,
+    KeyCode::L,
+    KeyCode::Semicolon,
+    KeyCode::Apostrophe,
+    KeyCode::RightBracket,
+    KeyCode::Backslash,
+];
+
+// French and German boards in the same physical positions, as far as
+// macroquad has key codes for them; keys it cannot name are left out.
+const AZERTY_WHITE_KEYS: [KeyCode; 8] = [
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::C,
+    KeyCode::V,
+    KeyCode::B,
+    KeyCode::N,
+    KeyCode::Comma,
+    KeyCode::Semicolon,
+];
+const AZERTY_BLACK_KEYS: [KeyCode; 7] = [
+    KeyCode::S,
+    KeyCode::D,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::J,
+    KeyCode::L,
+    KeyCode::M,
+];
+const QWERTZ_WHITE_KEYS: [KeyCode; 9] = [
+    KeyCode::Y,
+    KeyCode::X,
+    KeyCode::C,
+    KeyCode::V,
+    KeyCode::B,
+    KeyCode::N,
+    KeyCode::M,
+    KeyCode::Comma,
+    KeyCode::Period,
+];
+const QWERTZ_BLACK_KEYS: [KeyCode; 6] = [
+    KeyCode::S,
+    KeyCode::D,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::J,
+    KeyCode::L,
+];
+
+/// Built-in computer-key layouts for the on-screen keyboard.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyLayout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+}
+
+impl KeyLayout {
+    pub const VALUES: [KeyLayout; 3] = [KeyLayout::Qwerty, KeyLayout::Azerty, KeyLayout::Qwertz];
+
+    pub fn index(self) -> usize {
+        Self::VALUES
+            .iter()
+            .position(|layout| *layout == self)
+            .unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::VALUES[(self.index() + 1) % Self::VALUES.len()]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            KeyLayout::Qwerty => "QWERTY",
+            KeyLayout::Azerty => "AZERTY",
+            KeyLayout::Qwertz => "QWERTZ",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::VALUES
+            .into_iter()
+            .find(|layout| layout.label().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn white_keys(self) -> &'static [KeyCode] {
+        match self {
+            KeyLayout::Qwerty => &DEFAULT_WHITE_KEYS,
+            KeyLayout::Azerty => &AZERTY_WHITE_KEYS,
+            KeyLayout::Qwertz => &QWERTZ_WHITE_KEYS,
+        }
+    }
+
+    pub fn black_keys(self) -> &'static [KeyCode] {
+        match self {
+            KeyLayout::Qwerty => &DEFAULT_BLACK_KEYS,
+            KeyLayout::Azerty => &AZERTY_BLACK_KEYS,
+            KeyLayout::Qwertz => &QWERTZ_BLACK_KEYS,
+        }
+    }
+}
+
+// (MIDI note, position hint) for each on-screen key, lowest first.
+pub const WHITE_KEY_SLOTS: usize = WHITE_NOTES.len();
+const WHITE_NOTES: [(i32, f32); 10] = [
+    (48, 0.0),
+    (50, 1.0),
+    (52, 2.0),
+    (53, 3.0),
+    (55, 4.0),
+    (57, 5.0),
+    (59, 6.0),
+    (60, 7.0),
+    (62, 8.0),
+    (64, 9.0),
+];
+const BLACK_NOTES: [(i32, f32); 10] = [
+    (49, 0.105),
+    (51, 0.205),
+    (54, 0.3888889),
+    (56, 0.5),
+    (58, 0.6111111),
+    (61, 0.79),
+    (63, 0.9),
+    (66, 1.05),
+    (68, 1.2),
+    (70, 1.35),
+];
+
+// Config name (the macroquad `KeyCode` variant) and key-cap label.
+const KEY_NAMES: [(KeyCode, &str, &str); 45] = [
+    (KeyCode::A, "A", "A"),
+    (KeyCode::B, "B", "B"),
+    (KeyCode::C, "C", "C"),
+    (KeyCode::D, "D", "D"),
+    (KeyCode::E, "E", "E"),
+    (KeyCode::F, "F", "F"),
+    (KeyCode::G, "G", "G"),
+    (KeyCode::H, "H", "H"),
+    (KeyCode::I, "I", "I"),
+    (KeyCode::J, "J", "J"),
+    (KeyCode::K, "K", "K"),
+    (KeyCode::L, "L", "L"),
+    (KeyCode::M, "M", "M"),
+    (KeyCode::N, "N", "N"),
+    (KeyCode::O, "O", "O"),
+    (KeyCode::P, "P", "P"),
+    (KeyCode::Q, "Q", "Q"),
+    (KeyCode::R, "R", "R"),
+    (KeyCode::S, "S", "S"),
+    (KeyCode::T, "T", "T"),
+    (KeyCode::U, "U", "U"),
+    (KeyCode::V, "V", "V"),
+    (KeyCode::W, "W", "W"),
+    (KeyCode::X, "X", "X"),
+    (KeyCode::Y, "Y", "Y"),
+    (KeyCode::Z, "Z", "Z"),
+    (KeyCode::Key0, "Key0", "0"),
+    (KeyCode::Key1, "Key1", "1"),
+    (KeyCode::Key2, "Key2", "2"),
+    (KeyCode::Key3, "Key3", "3"),
+    (KeyCode::Key4, "Key4", "4"),
+    (KeyCode::Key5, "Key5", "5"),
+    (KeyCode::Key6, "Key6", "6"),
+    (KeyCode::Key7, "Key7", "7"),
+    (KeyCode::Key8, "Key8", "8"),
+    (KeyCode::Key9, "Key9", "9"),
+    (KeyCode::Comma, "Comma", ","),
+    (KeyCode::Period, "Period", "."),
+    (KeyCode::Slash, "Slash", "/"),
+    (KeyCode::Semicolon, "Semicolon", ";"),
+    (KeyCode::Apostrophe, "Apostrophe", "'"),
+    (KeyCode::LeftBracket, "LeftBracket", "["),
+    (KeyCode::RightBracket, "RightBracket", "]"),
+    (KeyCode::Backslash, "Backslash", "\\"),
+    (KeyCode::GraveAccent, "GraveAccent", "`"),
+];
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEY_NAMES
+        .iter()
+        .find(|(_, key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|(keycode, _, _)| *keycode)
+}
+
+pub fn key_name(keycode: KeyCode) -> &'static str {
+    KEY_NAMES
+        .iter()
+        .find(|(code, _, _)| *code == keycode)
+        .map_or("?", |(_, name, _)| name)
+}
+
+/// Stable number for a bindable key, for formats that only hold numbers.
+pub fn key_id(keycode: KeyCode) -> Option<usize> {
+    KEY_NAMES.iter().position(|(code, _, _)| *code == keycode)
+}
+
+pub fn key_from_id(id: usize) -> Option<KeyCode> {
+    KEY_NAMES.get(id).map(|(keycode, _, _)| *keycode)
+}
+
+fn key_label(keycode: KeyCode) -> &'static str {
+    KEY_NAMES
+        .iter()
+        .find(|(code, _, _)| *code == keycode)
+        .map_or("?", |(_, _, label)| label)
+}
+
+fn bind_keys(keys: &[KeyCode], notes: &[(i32, f32)]) -> Vec<KeyBinding> {
+    keys.iter()
+        .zip(notes)
+        .map(|(keycode, (midi, position_hint))| KeyBinding {
+            label: key_label(*keycode),
+            keycode: *keycode,
+            midi: *midi,
+            position_hint: *position_hint,
+        })
+        .collect()
+}
 
 #[derive(Clone)]
 pub struct KeyBinding {
@@ -13,151 +254,218 @@ pub struct KeyBinding {
     pub position_hint: f32,
 }
 
+/// Scale that played notes snap to, relative to a root pitch class.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    Minor,
+    HarmonicMinor,
+    Dorian,
+    Mixolydian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+}
+
+impl Scale {
+    pub const VALUES: [Scale; 9] = [
+        Scale::Chromatic,
+        Scale::Major,
+        Scale::Minor,
+        Scale::HarmonicMinor,
+        Scale::Dorian,
+        Scale::Mixolydian,
+        Scale::MajorPentatonic,
+        Scale::MinorPentatonic,
+        Scale::Blues,
+    ];
+
+    pub fn index(self) -> usize {
+        Self::VALUES
+            .iter()
+            .position(|scale| *scale == self)
+            .unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::VALUES[(self.index() + 1) % Self::VALUES.len()]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Scale::Chromatic => "CHROMATIC",
+            Scale::Major => "MAJOR",
+            Scale::Minor => "MINOR",
+            Scale::HarmonicMinor => "HARM MIN",
+            Scale::Dorian => "DORIAN",
+            Scale::Mixolydian => "MIXOLYD",
+            Scale::MajorPentatonic => "PENTA MAJ",
+            Scale::MinorPentatonic => "PENTA MIN",
+            Scale::Blues => "BLUES",
+        }
+    }
+
+    /// Semitones above the root that belong to the scale.
+    fn degrees(self) -> &'static [i32] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+        }
+    }
+
+    pub fn contains(self, root: i32, note: i32) -> bool {
+        self.degrees().contains(&(note - root).rem_euclid(12))
+    }
+
+    /// The nearest note in the scale; a note halfway between two resolves
+    /// downward.
+    pub fn quantize(self, root: i32, note: i32) -> i32 {
+        (0..12)
+            .flat_map(|distance| [note - distance, note + distance])
+            .find(|candidate| self.contains(root, *candidate))
+            .unwrap_or(note)
+    }
+}
+
+/// What the keys from the split point up do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpperZone {
+    /// Semitones added to every upper-zone note; 0 plays them as they are.
+    Transpose(i32),
+    Mute,
+}
+
+impl UpperZone {
+    pub const MAX_TRANSPOSE: i32 = 24;
+
+    /// Octave steps up to +2, then from -2 back up through MUTE to 0.
+    pub fn next(self) -> Self {
+        match self {
+            UpperZone::Mute => UpperZone::Transpose(0),
+            UpperZone::Transpose(semitones) => {
+                let next = (semitones.div_euclid(12) + 1) * 12;
+                if next > Self::MAX_TRANSPOSE {
+                    UpperZone::Transpose(-Self::MAX_TRANSPOSE)
+                } else if next == 0 {
+                    UpperZone::Mute
+                } else {
+                    UpperZone::Transpose(next)
+                }
+            }
+        }
+    }
+
+    /// One semitone up or down; a muted zone starts again from 0.
+    pub fn nudge(self, steps: i32) -> Self {
+        match self {
+            UpperZone::Mute => UpperZone::Transpose(0),
+            UpperZone::Transpose(semitones) => UpperZone::Transpose(
+                (semitones + steps).clamp(-Self::MAX_TRANSPOSE, Self::MAX_TRANSPOSE),
+            ),
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            UpperZone::Mute => "MUTE".to_string(),
+            UpperZone::Transpose(0) => "AS PLAYED".to_string(),
+            UpperZone::Transpose(semitones) => format!("{semitones:+} ST"),
+        }
+    }
+}
+
+impl Default for UpperZone {
+    fn default() -> Self {
+        UpperZone::Transpose(0)
+    }
+}
+
+/// Divides the keyboard into a lower zone that plays normally and an upper
+/// zone that is transposed or muted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeySplit {
+    /// Lowest note of the upper zone, after the octave shift; `None` keeps
+    /// the keyboard whole.
+    pub point: Option<i32>,
+    pub upper: UpperZone,
+}
+
+impl KeySplit {
+    /// The note `note` plays through the split, or `None` if its zone is
+    /// muted.
+    pub fn route(self, note: i32) -> Option<i32> {
+        match (self.point, self.upper) {
+            (Some(point), UpperZone::Mute) if note >= point => None,
+            (Some(point), UpperZone::Transpose(semitones)) if note >= point => {
+                Some(note + semitones)
+            }
+            _ => Some(note),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum HeldNote {
+    Key(KeyCode),
+    Touch(KeyCode),
+    Midi(i32),
+}
+
 pub struct ControllerMessage {
     pub gate: bool,
     pub voltage: f32,
     pub midi_note: i32,
+    pub sustain: bool,
+    /// 0–1 strike strength of the most recent note.
+    pub velocity: f32,
+    /// The note was played on the window (computer keys, mouse or touch)
+    /// rather than arriving as MIDI.
+    pub local: bool,
 }
 
 pub struct KeyboardController {
     white_keys: Vec<KeyBinding>,
     black_keys: Vec<KeyBinding>,
-    pressed: Vec<KeyCode>,
+    pressed: Vec<HeldNote>,
     lookup: HashMap<KeyCode, KeyBinding>,
     last_voltage: f32,
     octave_shift: i32,
     min_shift: i32,
     max_shift: i32,
     mouse_active: Option<KeyCode>,
+    sustain_pedal: bool,
+    sustain_key: bool,
+    held_note: Option<i32>,
+    held_local: bool,
+    layout: KeyLayout,
+    learning: Option<KeyCode>,
+    velocity: f32,
+    scale: Scale,
+    scale_root: i32,
+    split: KeySplit,
+    split_learning: bool,
+    learned_split: Option<i32>,
+    tuning: Arc<Tuning>,
 }
 
 impl KeyboardController {
     pub fn new() -> Self {
-        let white_keys = vec![
-            KeyBinding {
-                label: "Z",
-                keycode: KeyCode::Z,
-                midi: 48,
-                position_hint: 0.0,
-            },
-            KeyBinding {
-                label: "X",
-                keycode: KeyCode::X,
-                midi: 50,
-                position_hint: 1.0,
-            },
-            KeyBinding {
-                label: "C",
-                keycode: KeyCode::C,
-                midi: 52,
-                position_hint: 2.0,
-            },
-            KeyBinding {
-                label: "V",
-                keycode: KeyCode::V,
-                midi: 53,
-                position_hint: 3.0,
-            },
-            KeyBinding {
-                label: "B",
-                keycode: KeyCode::B,
-                midi: 55,
-                position_hint: 4.0,
-            },
-            KeyBinding {
-                label: "N",
-                keycode: KeyCode::N,
-                midi: 57,
-                position_hint: 5.0,
-            },
-            KeyBinding {
-                label: "M",
-                keycode: KeyCode::M,
-                midi: 59,
-                position_hint: 6.0,
-            },
-            KeyBinding {
-                label: ",",
-                keycode: KeyCode::Comma,
-                midi: 60,
-                position_hint: 7.0,
-            },
-            KeyBinding {
-                label: ".",
-                keycode: KeyCode::Period,
-                midi: 62,
-                position_hint: 8.0,
-            },
-            KeyBinding {
-                label: "/",
-                keycode: KeyCode::Slash,
-                midi: 64,
-                position_hint: 9.0,
-            },
-        ];
-
-        let black_keys = vec![
-            KeyBinding {
-                label: "S",
-                keycode: KeyCode::S,
-                midi: 49,
-                position_hint: 0.105,
-            },
-            KeyBinding {
-                label: "D",
-                keycode: KeyCode::D,
-                midi: 51,
-                position_hint: 0.205,
-            },
-            KeyBinding {
-                label: "G",
-                keycode: KeyCode::G,
-                midi: 54,
-                position_hint: 0.3888889,
-            },
-            KeyBinding {
-                label: "H",
-                keycode: KeyCode::H,
-                midi: 56,
-                position_hint: 0.5,
-            },
-            KeyBinding {
-                label: "J",
-                keycode: KeyCode::J,
-                midi: 58,
-                position_hint: 0.6111111,
-            },
-            KeyBinding {
-                label: "L",
-                keycode: KeyCode::L,
-                midi: 61,
-                position_hint: 0.79,
-            },
-            KeyBinding {
-                label: ";",
-                keycode: KeyCode::Semicolon,
-                midi: 63,
-                position_hint: 0.9,
-            },
-            KeyBinding {
-                label: "'",
-                keycode: KeyCode::Apostrophe,
-                midi: 66,
-                position_hint: 1.05,
-            },
-            KeyBinding {
-                label: "]",
-                keycode: KeyCode::RightBracket,
-                midi: 68,
-                position_hint: 1.2,
-            },
-            KeyBinding {
-                label: "\\",
-                keycode: KeyCode::Backslash,
-                midi: 70,
-                position_hint: 1.35,
-            },
-        ];
+        Self::with_keys(&DEFAULT_WHITE_KEYS, &DEFAULT_BLACK_KEYS)
+    }
+
+    /// Binds the given keys to the white and black notes from the lowest up;
+    /// extra keys are ignored and missing ones leave their notes unbound.
+    pub fn with_keys(white: &[KeyCode], black: &[KeyCode]) -> Self {
+        let white_keys = bind_keys(white, &WHITE_NOTES);
+        let black_keys = bind_keys(black, &BLACK_NOTES);
 
         let mut lookup = HashMap::new();
         for binding in white_keys.iter().chain(black_keys.iter()) {
@@ -190,11 +498,38 @@ impl KeyboardController {
             min_shift,
             max_shift,
             mouse_active: None,
+            sustain_pedal: false,
+            sustain_key: false,
+            held_note: None,
+            held_local: false,
+            layout: KeyLayout::Qwerty,
+            learning: None,
+            velocity: FULL_VELOCITY,
+            scale: Scale::Chromatic,
+            scale_root: 0,
+            split: KeySplit::default(),
+            split_learning: false,
+            learned_split: None,
+            tuning: Arc::new(Tuning::default()),
         }
     }
 
-    pub fn poll(&mut self, external_change: bool) -> Option<ControllerMessage> {
+    pub fn poll(&mut self, external_change: bool, keys_enabled: bool) -> Option<ControllerMessage> {
         let mut changed = external_change;
+        if !keys_enabled {
+            return changed.then(|| self.current_message());
+        }
+        // While learning, the next key press is a binding, not a note.
+        if let Some(target) = self.learning {
+            if is_key_pressed(KeyCode::Escape) {
+                self.learning = None;
+            } else if let Some(key) = get_last_key_pressed().filter(|key| key_id(*key).is_some()) {
+                self.learning = None;
+                self.remap(target, key);
+                changed = true;
+            }
+            return changed.then(|| self.current_message());
+        }
 
         if is_key_pressed(KeyCode::Minus) {
             self.adjust_octave(-1);
@@ -204,11 +539,16 @@ impl KeyboardController {
             self.adjust_octave(1);
             changed = true;
         }
+        let space = is_key_down(KeyCode::Space);
+        if space != self.sustain_key {
+            self.sustain_key = space;
+            changed = true;
+        }
 
         let keycodes: Vec<KeyCode> = self.lookup.keys().copied().collect();
         for keycode in keycodes {
             if is_key_pressed(keycode) {
-                changed |= self.press_key(keycode);
+                changed |= self.press_key(keycode, FULL_VELOCITY);
             }
             if is_key_released(keycode) {
                 changed |= self.release_key(keycode);
@@ -221,78 +561,316 @@ impl KeyboardController {
         }
     }
 
+    /// `velocity` is taken from where the press landed; keys reached by
+    /// sliding across keep it.
     pub fn handle_mouse_keys(
         &mut self,
         hovered: Option<KeyCode>,
+        velocity: f32,
         mouse_pressed: bool,
         mouse_down: bool,
         mouse_released: bool,
     ) -> bool {
         let mut changed = false;
-        if mouse_pressed {
-            if let Some(code) = hovered {
-                self.mouse_active = Some(code);
-                changed |= self.press_key(code);
-            }
+        if mouse_pressed && let Some(code) = hovered {
+            self.mouse_active = Some(code);
+            changed |= self.press_key(code, velocity);
         }
-        if mouse_down {
-            if let Some(active) = self.mouse_active {
-                if let Some(hit) = hovered {
-                    if hit != active {
-                        changed |= self.release_key(active);
-                        self.mouse_active = Some(hit);
-                        changed |= self.press_key(hit);
-                    }
-                } else {
+        if mouse_down && let Some(active) = self.mouse_active {
+            if let Some(hit) = hovered {
+                if hit != active {
                     changed |= self.release_key(active);
-                    self.mouse_active = None;
+                    self.mouse_active = Some(hit);
+                    changed |= self.press_key(hit, self.velocity);
                 }
+            } else {
+                changed |= self.release_key(active);
+                self.mouse_active = None;
             }
         }
-        if mouse_released {
-            if let Some(active) = self.mouse_active.take() {
-                changed |= self.release_key(active);
+        if mouse_released && let Some(active) = self.mouse_active.take() {
+            changed |= self.release_key(active);
+        }
+        changed
+    }
+
+    /// Rebinds the white and black notes, releasing any held keys.
+    pub fn set_keys(&mut self, white: &[KeyCode], black: &[KeyCode]) {
+        let rebound = Self::with_keys(white, black);
+        self.white_keys = rebound.white_keys;
+        self.black_keys = rebound.black_keys;
+        self.lookup = rebound.lookup;
+        self.min_shift = rebound.min_shift;
+        self.max_shift = rebound.max_shift;
+        self.pressed
+            .retain(|held| matches!(held, HeldNote::Midi(_)));
+        self.mouse_active = None;
+        self.learning = None;
+        self.set_octave_shift(self.octave_shift);
+    }
+
+    /// Switches to a built-in layout; returns whether it was a different
+    /// one, dropping any custom bindings.
+    pub fn set_layout(&mut self, layout: KeyLayout) -> bool {
+        if self.layout == layout {
+            return false;
+        }
+        self.layout = layout;
+        self.set_keys(layout.white_keys(), layout.black_keys());
+        true
+    }
+
+    /// Marks the layout the current bindings started from, without
+    /// rebinding.
+    pub fn set_base_layout(&mut self, layout: KeyLayout) {
+        self.layout = layout;
+    }
+
+    /// Waits for the next computer key press to play the note `keycode`
+    /// plays now. Escape cancels.
+    pub fn learn_key(&mut self, keycode: KeyCode) {
+        self.learning = self.lookup.contains_key(&keycode).then_some(keycode);
+    }
+
+    pub fn learning_key(&self) -> Option<KeyCode> {
+        self.learning
+    }
+
+    // A key already bound elsewhere swaps places with the target.
+    fn remap(&mut self, target: KeyCode, key: KeyCode) {
+        let swap = |binding: &KeyBinding| {
+            if binding.keycode == key {
+                target
+            } else if binding.keycode == target {
+                key
+            } else {
+                binding.keycode
             }
+        };
+        let white: Vec<KeyCode> = self.white_keys.iter().map(swap).collect();
+        let black: Vec<KeyCode> = self.black_keys.iter().map(swap).collect();
+        self.set_keys(&white, &black);
+    }
+
+    /// The key held down with the mouse, if any.
+    pub fn mouse_key(&self) -> Option<KeyCode> {
+        self.mouse_active
+    }
+
+    pub fn handle_touch_keys(&mut self, touched: &[KeyCode]) -> bool {
+        let mut changed = false;
+        let lifted: Vec<HeldNote> = self
+            .pressed
+            .iter()
+            .filter(|held| matches!(held, HeldNote::Touch(code) if !touched.contains(code)))
+            .copied()
+            .collect();
+        for held in lifted {
+            changed |= self.release(held);
+        }
+        for code in touched {
+            changed |= self.press(HeldNote::Touch(*code), FULL_VELOCITY);
         }
         changed
     }
 
+    /// Forgets every held key, touch and MIDI note and lifts the sustain
+    /// pedal, so the next message closes the gate whatever got stuck.
+    pub fn panic(&mut self) {
+        self.pressed.clear();
+        self.mouse_active = None;
+        self.sustain_pedal = false;
+        self.held_note = None;
+        self.learning = None;
+        self.split_learning = false;
+    }
+
+    pub fn set_sustain_pedal(&mut self, down: bool) -> bool {
+        let changed = self.sustain_pedal != down;
+        self.sustain_pedal = down;
+        changed
+    }
+
+    /// Every note, keyboard or MIDI, is snapped to `scale` on `root` (a
+    /// pitch class, 0 = C) before it becomes a voltage. Returns whether
+    /// anything changed, so held notes can be re-sent.
+    pub fn set_scale(&mut self, scale: Scale, root: i32) -> bool {
+        let root = root.rem_euclid(12);
+        let changed = self.scale != scale || self.scale_root != root;
+        self.scale = scale;
+        self.scale_root = root;
+        changed
+    }
+
+    /// Every note, keyboard or MIDI, is routed through `split` ahead of the
+    /// scale. Returns whether it changed, so held notes can be re-sent.
+    pub fn set_split(&mut self, split: KeySplit) -> bool {
+        let changed = self.split != split;
+        self.split = split;
+        changed
+    }
+
+    /// While set, the next note pressed (computer key, mouse, touch or
+    /// MIDI) is taken as the split point instead of being played.
+    pub fn learn_split(&mut self, learning: bool) {
+        self.split_learning = learning;
+    }
+
+    /// The split point picked up since the last call, if any.
+    pub fn take_learned_split(&mut self) -> Option<i32> {
+        self.learned_split.take()
+    }
+
+    /// Whether the key sits in a muted upper zone.
+    pub fn muted(&self, keycode: KeyCode) -> bool {
+        self.lookup.get(&keycode).is_some_and(|binding| {
+            self.split
+                .route(binding.midi + self.octave_shift * 12)
+                .is_none()
+        })
+    }
+
+    /// Whether the key is the lowest of the upper zone.
+    pub fn is_split_key(&self, keycode: KeyCode) -> bool {
+        self.lookup
+            .get(&keycode)
+            .is_some_and(|binding| self.split.point == Some(binding.midi + self.octave_shift * 12))
+    }
+
+    /// Notes become voltages through `tuning` rather than 12-TET. Returns
+    /// whether it is a different table, so held notes can be re-sent.
+    pub fn set_tuning(&mut self, tuning: &Arc<Tuning>) -> bool {
+        let changed = !Arc::ptr_eq(&self.tuning, tuning);
+        self.tuning = Arc::clone(tuning);
+        changed
+    }
+
+    /// Whether the key plays its own note rather than a snapped neighbour.
+    pub fn in_scale(&self, keycode: KeyCode) -> bool {
+        self.lookup.get(&keycode).is_none_or(|binding| {
+            self.scale
+                .contains(self.scale_root, binding.midi + self.octave_shift * 12)
+        })
+    }
+
+    // The note a held key stands for, after the octave shift.
+    fn played_note(&self, held: HeldNote) -> Option<i32> {
+        match held {
+            HeldNote::Key(keycode) | HeldNote::Touch(keycode) => self
+                .lookup
+                .get(&keycode)
+                .map(|binding| binding.midi + self.octave_shift * 12),
+            HeldNote::Midi(note) => Some(note),
+        }
+    }
+
+    fn sustained(&self) -> bool {
+        self.sustain_pedal || self.sustain_key
+    }
+
     fn current_message(&mut self) -> ControllerMessage {
-        if let Some(last) = self.pressed.last() {
-            if let Some(binding) = self.lookup.get(last) {
-                let midi = (binding.midi + self.octave_shift * 12).clamp(MIDI_MIN, MIDI_MAX);
-                let voltage = midi_to_voltage(midi);
-                self.last_voltage = voltage;
-                return ControllerMessage {
-                    gate: true,
-                    voltage,
-                    midi_note: midi,
-                };
-            }
+        let sustain = self.sustained();
+        // Keys the keyboard map leaves unmapped stay silent, so the most
+        // recent note that has a pitch wins.
+        let last = self.pressed.iter().rev().find_map(|held| {
+            let midi = self.split.route(self.played_note(*held)?)?;
+            let midi = self
+                .scale
+                .quantize(self.scale_root, midi)
+                .clamp(MIDI_MIN, MIDI_MAX);
+            let local = !matches!(held, HeldNote::Midi(_));
+            self.tuning
+                .voltage(midi)
+                .map(|voltage| (midi, voltage, local))
+        });
+        if let Some((midi, voltage, local)) = last {
+            self.last_voltage = voltage;
+            self.held_note = Some(midi);
+            self.held_local = local;
+            return ControllerMessage {
+                gate: true,
+                voltage,
+                midi_note: midi,
+                sustain,
+                velocity: self.velocity,
+                local,
+            };
+        }
+        if !sustain {
+            self.held_note = None;
         }
-        ControllerMessage {
-            gate: false,
-            voltage: self.last_voltage,
-            midi_note: -1,
+        match self.held_note {
+            Some(midi) => ControllerMessage {
+                gate: true,
+                voltage: self.last_voltage,
+                midi_note: midi,
+                sustain,
+                velocity: self.velocity,
+                local: self.held_local,
+            },
+            None => ControllerMessage {
+                gate: false,
+                voltage: self.last_voltage,
+                midi_note: -1,
+                sustain,
+                velocity: self.velocity,
+                local: false,
+            },
         }
     }
 
     fn adjust_octave(&mut self, delta: i32) {
-        let new_shift = (self.octave_shift + delta).clamp(self.min_shift, self.max_shift);
-        self.octave_shift = new_shift;
+        self.set_octave_shift(self.octave_shift + delta);
+    }
+
+    pub fn octave_shift(&self) -> i32 {
+        self.octave_shift
+    }
+
+    /// Clamped so the bound keys stay inside the MIDI note range.
+    pub fn set_octave_shift(&mut self, shift: i32) {
+        self.octave_shift = shift.clamp(self.min_shift, self.max_shift);
+    }
+
+    pub fn message(&mut self) -> ControllerMessage {
+        self.current_message()
+    }
+
+    pub fn note_on(&mut self, note: u8, velocity: u8) -> bool {
+        self.press(HeldNote::Midi(note as i32), velocity as f32 / 127.0)
     }
 
-    fn press_key(&mut self, keycode: KeyCode) -> bool {
-        if self.pressed.contains(&keycode) {
+    pub fn note_off(&mut self, note: u8) -> bool {
+        self.release(HeldNote::Midi(note as i32))
+    }
+
+    fn press_key(&mut self, keycode: KeyCode, velocity: f32) -> bool {
+        self.press(HeldNote::Key(keycode), velocity)
+    }
+
+    fn release_key(&mut self, keycode: KeyCode) -> bool {
+        self.release(HeldNote::Key(keycode))
+    }
+
+    fn press(&mut self, note: HeldNote, velocity: f32) -> bool {
+        if self.split_learning
+            && let Some(point) = self.played_note(note)
+        {
+            self.split_learning = false;
+            self.learned_split = Some(point);
+            return false;
+        }
+        if self.pressed.contains(&note) {
             false
         } else {
-            self.pressed.push(keycode);
+            self.pressed.push(note);
+            self.velocity = velocity.clamp(0.0, 1.0);
             true
         }
     }
 
-    fn release_key(&mut self, keycode: KeyCode) -> bool {
-        if let Some(index) = self.pressed.iter().position(|code| *code == keycode) {
+    fn release(&mut self, note: HeldNote) -> bool {
+        if let Some(index) = self.pressed.iter().position(|held| *held == note) {
             self.pressed.remove(index);
             true
         } else {
@@ -301,7 +879,8 @@ impl KeyboardController {
     }
 
     pub fn is_pressed(&self, keycode: KeyCode) -> bool {
-        self.pressed.contains(&keycode)
+        self.pressed.contains(&HeldNote::Key(keycode))
+            || self.pressed.contains(&HeldNote::Touch(keycode))
     }
 
     pub fn white_keys(&self) -> &[KeyBinding] {
@@ -312,7 +891,3 @@ impl KeyboardController {
         &self.black_keys
     }
 }
-
-pub fn midi_to_voltage(midi_note: i32) -> f32 {
-    (midi_note as f32 - 33.0) / 12.0
-}
@@ -0,0 +1,66 @@
+use crate::vco::midi_to_voltage;
+
+/// Note sent as 0 V on the pitch output: middle C, so the keyboard's range
+/// stays inside the ±5 V many DC-coupled interfaces top out at.
+pub const ZERO_VOLT_NOTE: i32 = 60;
+pub const DEFAULT_PITCH_CHANNEL: usize = 3;
+pub const DEFAULT_GATE_CHANNEL: usize = 4;
+pub const DEFAULT_FULL_SCALE_VOLTS: f32 = 10.0;
+pub const DEFAULT_GATE_VOLTS: f32 = 5.0;
+
+/// Pitch CV and gate written to spare channels of a multichannel, DC-coupled
+/// interface so the keyboard can drive modular hardware. Channels count from
+/// 1 like the labels on the interface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CvGateOutput {
+    pub pitch_channel: usize,
+    pub gate_channel: usize,
+    /// Volts the interface puts out for a full-scale sample of 1.0.
+    pub full_scale_volts: f32,
+    pub gate_volts: f32,
+}
+
+impl Default for CvGateOutput {
+    fn default() -> Self {
+        Self {
+            pitch_channel: DEFAULT_PITCH_CHANNEL,
+            gate_channel: DEFAULT_GATE_CHANNEL,
+            full_scale_volts: DEFAULT_FULL_SCALE_VOLTS,
+            gate_volts: DEFAULT_GATE_VOLTS,
+        }
+    }
+}
+
+impl CvGateOutput {
+    /// Channels the device must offer for both outputs to land.
+    pub fn channels_needed(&self) -> usize {
+        self.pitch_channel.max(self.gate_channel)
+    }
+
+    /// Sample levels for the pitch and gate channels, given the engine's
+    /// 1 V/oct pitch voltage.
+    pub fn levels(&self, pitch_voltage: f32, gate: bool) -> [f32; 2] {
+        let scale = self.full_scale_volts.max(f32::EPSILON);
+        let volts = pitch_voltage - midi_to_voltage(ZERO_VOLT_NOTE);
+        let gate_volts = if gate { self.gate_volts } else { 0.0 };
+        [
+            (volts / scale).clamp(-1.0, 1.0),
+            (gate_volts / scale).clamp(-1.0, 1.0),
+        ]
+    }
+
+    /// Overwrites the CV channels of one interleaved device frame.
+    pub fn write<T>(&self, frame: &mut [T], levels: [f32; 2], convert: fn(f32) -> T) {
+        for (channel, level) in [self.pitch_channel, self.gate_channel]
+            .into_iter()
+            .zip(levels)
+        {
+            if let Some(sample) = channel
+                .checked_sub(1)
+                .and_then(|index| frame.get_mut(index))
+            {
+                *sample = convert(level);
+            }
+        }
+    }
+}
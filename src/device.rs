@@ -0,0 +1,445 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use cpal::{
+    BufferSize, InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, SizedSample,
+    Stream, StreamConfig, StreamError, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use rtrb::{Producer, RingBuffer};
+
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    command::{CommandSender, PipelineJob, command_channel},
+    mixer::{ExternalInput, StereoFrame},
+    output::{
+        BufferRequest, DebugData, DeviceRequest, SharedPipeline, StreamContext, retune_pipeline,
+    },
+};
+
+const NULL_SINK_FRAMES: usize = 512;
+// Room for a few input callbacks' worth of frames ahead of the pipeline.
+const CAPTURE_FRAMES: usize = 16_384;
+
+enum Backend {
+    Device {
+        _stream: Stream,
+        _capture: Option<Stream>,
+    },
+    Null {
+        _sink: NullSink,
+    },
+}
+
+pub struct AudioEngine {
+    backend: Backend,
+    device_name: String,
+    follows_default: bool,
+    failed: Arc<AtomicBool>,
+}
+
+impl AudioEngine {
+    pub fn start(
+        pipeline: SharedPipeline,
+        debug: &mut DebugData,
+        buffer: BufferRequest,
+        request: &DeviceRequest,
+    ) -> Result<(Self, CommandSender)> {
+        let host = cpal::default_host();
+        let named = request.name.as_deref().and_then(|wanted| {
+            let wanted = wanted.to_lowercase();
+            let found = host.output_devices().ok()?.find(|device| {
+                device
+                    .name()
+                    .is_ok_and(|name| name.to_lowercase().contains(&wanted))
+            });
+            if found.is_none() {
+                warn!("Audio device matching {wanted:?} not found; using the default");
+            }
+            found
+        });
+        let follows_default = named.is_none();
+        let device = match named {
+            Some(device) => device,
+            None => host
+                .default_output_device()
+                .ok_or_else(|| anyhow!("No default audio output"))?,
+        };
+        let device_name = device.name().unwrap_or_else(|_| "audio output".into());
+        let default_config = device.default_output_config()?;
+        let supported = match request.sample_rate {
+            Some(rate) => config_at_rate(&device, &default_config, rate).unwrap_or_else(|| {
+                warn!("{device_name} cannot run at {rate} Hz; using its default rate");
+                default_config
+            }),
+            None => default_config,
+        };
+        let failed = Arc::new(AtomicBool::new(false));
+        let format = supported.sample_format();
+        let config = supported.config();
+        let sample_rate = config.sample_rate.0;
+        let engine_rate = request.engine_rate(sample_rate);
+        pipeline.announce_sample_rate(engine_rate as f32);
+        retune_pipeline(&pipeline, engine_rate);
+        if let Some(cv_gate) = pipeline.cv_gate()
+            && cv_gate.channels_needed() > config.channels as usize
+        {
+            warn!(
+                "{device_name} has {} output channels; CV/gate needs {}, so it stays off",
+                config.channels,
+                cv_gate.channels_needed()
+            );
+        }
+        let mut open = |config: &StreamConfig| {
+            let (commands, queue) = command_channel();
+            let mut context = StreamContext::new(
+                config.channels as usize,
+                pipeline.clone(),
+                debug.connect(),
+                queue,
+                failed.clone(),
+            );
+            context.set_rates(sample_rate, engine_rate);
+            build_stream(&device, config, format, context).map(|stream| (stream, commands))
+        };
+        let (stream, mut commands) = match buffer.frames() {
+            Some(frames) => {
+                let frames = match supported.buffer_size() {
+                    SupportedBufferSize::Range { min, max } => frames.clamp(*min, *max),
+                    SupportedBufferSize::Unknown => frames,
+                };
+                let fixed = StreamConfig {
+                    buffer_size: BufferSize::Fixed(frames),
+                    ..config.clone()
+                };
+                match open(&fixed) {
+                    Ok(opened) => opened,
+                    Err(err) => {
+                        warn!("Buffer size {frames} rejected ({err}); using device default");
+                        open(&config)?
+                    }
+                }
+            }
+            None => open(&config)?,
+        };
+        stream.play()?;
+        let capture = match start_capture(engine_rate) {
+            Ok((capture, input)) => {
+                commands.send_job(PipelineJob::ExternalInput(Some(input)));
+                Some(capture)
+            }
+            Err(err) => {
+                warn!("No audio capture ({err}); EXT INPUT only carries the feedback patch");
+                commands.send_job(PipelineJob::ExternalInput(None));
+                None
+            }
+        };
+        info!(
+            device = %device_name,
+            sample_rate,
+            engine_rate,
+            channels = config.channels,
+            buffer = ?buffer.frames(),
+            "Audio stream started"
+        );
+        Ok((
+            Self {
+                backend: Backend::Device {
+                    _stream: stream,
+                    _capture: capture,
+                },
+                device_name,
+                follows_default,
+                failed,
+            },
+            commands,
+        ))
+    }
+
+    /// Keeps the pipeline running in real time with no device attached, so
+    /// the scope and meters stay live on machines without audio output.
+    pub fn null(pipeline: SharedPipeline, debug: &mut DebugData) -> (Self, CommandSender) {
+        let (mut commands, queue) = command_channel();
+        commands.send_job(PipelineJob::ExternalInput(None));
+        let failed = Arc::new(AtomicBool::new(false));
+        let context = StreamContext::new(2, pipeline, debug.connect(), queue, failed.clone());
+        let engine = Self {
+            backend: Backend::Null {
+                _sink: NullSink::start(context),
+            },
+            device_name: "null sink".into(),
+            follows_default: true,
+            failed,
+        };
+        debug!("Audio null sink started");
+        (engine, commands)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self.backend, Backend::Null { .. })
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+
+    pub fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    // Enumerating devices can be slow on some hosts; callers should poll this
+    // at a relaxed rate rather than every frame.
+    pub fn default_device_changed(&self) -> bool {
+        // A device picked by name stays put when the system default moves.
+        if !self.follows_default {
+            return false;
+        }
+        cpal::default_host()
+            .default_output_device()
+            .and_then(|device| device.name().ok())
+            .is_some_and(|name| name != self.device_name)
+    }
+}
+
+// Opens the default input at the engine rate, so each captured frame lines
+// up with one the pipeline renders.
+fn start_capture(engine_rate: u32) -> Result<(Stream, ExternalInput)> {
+    let device = cpal::default_host()
+        .default_input_device()
+        .ok_or_else(|| anyhow!("no default audio input"))?;
+    let device_name = device.name().unwrap_or_else(|_| "audio input".into());
+    let default_config = device.default_input_config()?;
+    let supported = matching_config(
+        device.supported_input_configs()?,
+        &default_config,
+        engine_rate,
+    )
+    .ok_or_else(|| anyhow!("{device_name} cannot capture at {engine_rate} Hz"))?;
+    let (producer, input) = RingBuffer::new(CAPTURE_FRAMES);
+    let config = supported.config();
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_capture_stream(&device, &config, producer, |sample| sample),
+        SampleFormat::F64 => build_capture_stream(&device, &config, producer, from_f64),
+        SampleFormat::I8 => build_capture_stream(&device, &config, producer, from_i8),
+        SampleFormat::I16 => build_capture_stream(&device, &config, producer, from_i16),
+        SampleFormat::I32 => build_capture_stream(&device, &config, producer, from_i32),
+        SampleFormat::U8 => build_capture_stream(&device, &config, producer, from_u8),
+        SampleFormat::U16 => build_capture_stream(&device, &config, producer, from_u16),
+        other => Err(anyhow!("unsupported sample format {other}")),
+    }?;
+    stream.play()?;
+    info!(
+        device = %device_name,
+        channels = config.channels,
+        "Audio capture started"
+    );
+    Ok((stream, input))
+}
+
+// Mono inputs feed both sides; past two channels the rest are ignored. A
+// pipeline that falls behind loses the newest frames rather than blocking.
+fn build_capture_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut producer: Producer<StereoFrame>,
+    convert: fn(T) -> f32,
+) -> Result<Stream>
+where
+    T: SizedSample + 'static,
+{
+    let channels = config.channels.max(1) as usize;
+    let stream = device.build_input_stream(
+        config,
+        move |input: &[T], _: &InputCallbackInfo| {
+            for frame in input.chunks_exact(channels) {
+                let left = convert(frame[0]);
+                let right = frame.get(1).map_or(left, |&sample| convert(sample));
+                if producer.push([left, right]).is_err() {
+                    break;
+                }
+            }
+        },
+        |err| warn!("Audio capture error: {err}"),
+        None,
+    )?;
+    Ok(stream)
+}
+
+// Prefers the default channel count and sample format, then anything that
+// supports the rate at all.
+fn config_at_rate(
+    device: &cpal::Device,
+    default_config: &SupportedStreamConfig,
+    rate: u32,
+) -> Option<SupportedStreamConfig> {
+    matching_config(
+        device.supported_output_configs().ok()?,
+        default_config,
+        rate,
+    )
+}
+
+fn matching_config(
+    ranges: impl Iterator<Item = SupportedStreamConfigRange>,
+    default_config: &SupportedStreamConfig,
+    rate: u32,
+) -> Option<SupportedStreamConfig> {
+    let rate = SampleRate(rate);
+    let ranges: Vec<_> = ranges
+        .filter(|range| range.min_sample_rate() <= rate && rate <= range.max_sample_rate())
+        .collect();
+    ranges
+        .iter()
+        .find(|range| {
+            range.channels() == default_config.channels()
+                && range.sample_format() == default_config.sample_format()
+        })
+        .or_else(|| ranges.first())
+        .map(|range| range.with_sample_rate(rate))
+}
+
+fn build_stream(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    format: SampleFormat,
+    context: StreamContext,
+) -> Result<Stream> {
+    match format {
+        SampleFormat::F32 => build_typed_stream(device, config, context, |sample| sample),
+        SampleFormat::F64 => build_typed_stream(device, config, context, to_f64),
+        SampleFormat::I8 => build_typed_stream(device, config, context, to_i8),
+        SampleFormat::I16 => build_typed_stream(device, config, context, to_i16),
+        SampleFormat::I32 => build_typed_stream(device, config, context, to_i32),
+        SampleFormat::U8 => build_typed_stream(device, config, context, to_u8),
+        SampleFormat::U16 => build_typed_stream(device, config, context, to_u16),
+        other => Err(anyhow!("Unsupported sample format {other}")),
+    }
+}
+
+fn build_typed_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut context: StreamContext,
+    convert: fn(f32) -> T,
+) -> Result<Stream>
+where
+    T: SizedSample + 'static,
+{
+    let failed = context.failed.clone();
+    let stream_errors = context.debug.stream_errors();
+    let stream = device.build_output_stream(
+        config,
+        move |output: &mut [T], info: &OutputCallbackInfo| {
+            let timestamp = info.timestamp();
+            let device_delay = timestamp
+                .playback
+                .duration_since(&timestamp.callback)
+                .map_or(0.0, |delay| delay.as_secs_f32());
+            context.fill(output, device_delay, convert);
+        },
+        move |err| {
+            error!("Audio stream error: {err}");
+            stream_errors.fetch_add(1, Ordering::Relaxed);
+            if matches!(err, StreamError::DeviceNotAvailable) {
+                failed.store(true, Ordering::Relaxed);
+            }
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+fn to_f64(sample: f32) -> f64 {
+    sample as f64
+}
+
+fn to_i8(sample: f32) -> i8 {
+    (sample.clamp(-1.0, 1.0) * i8::MAX as f32) as i8
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+fn to_i32(sample: f32) -> i32 {
+    (sample.clamp(-1.0, 1.0) as f64 * i32::MAX as f64) as i32
+}
+
+fn to_u8(sample: f32) -> u8 {
+    let scaled = (sample * 0.5 + 0.5).clamp(0.0, 1.0);
+    (scaled * u8::MAX as f32) as u8
+}
+
+fn to_u16(sample: f32) -> u16 {
+    let scaled = (sample * 0.5 + 0.5).clamp(0.0, 1.0);
+    (scaled * u16::MAX as f32) as u16
+}
+
+fn from_f64(sample: f64) -> f32 {
+    sample as f32
+}
+
+fn from_i8(sample: i8) -> f32 {
+    sample as f32 / i8::MAX as f32
+}
+
+fn from_i16(sample: i16) -> f32 {
+    sample as f32 / i16::MAX as f32
+}
+
+fn from_i32(sample: i32) -> f32 {
+    (sample as f64 / i32::MAX as f64) as f32
+}
+
+fn from_u8(sample: u8) -> f32 {
+    sample as f32 / u8::MAX as f32 * 2.0 - 1.0
+}
+
+fn from_u16(sample: u16) -> f32 {
+    sample as f32 / u16::MAX as f32 * 2.0 - 1.0
+}
+
+struct NullSink {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NullSink {
+    fn start(mut context: StreamContext) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            let sample_rate = context.pipeline.sample_rate();
+            let period = Duration::from_secs_f32(NULL_SINK_FRAMES as f32 / sample_rate);
+            let mut buffer = vec![0.0f32; NULL_SINK_FRAMES * 2];
+            let mut deadline = Instant::now();
+            while !thread_stop.load(Ordering::Relaxed) {
+                context.fill(&mut buffer, 0.0, |sample| sample);
+                deadline += period;
+                thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            }
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for NullSink {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
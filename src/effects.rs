@@ -0,0 +1,101 @@
+use std::f32::consts::PI;
+
+mod chorus;
+mod delay;
+mod reverb;
+
+pub use chorus::{CHORUS_RATE_MAX, CHORUS_RATE_MIN, Chorus};
+pub use delay::{
+    DELAY_DAMPING_CLOSED_HZ, DELAY_DAMPING_OPEN_HZ, DELAY_TIME_MAX, DELAY_TIME_MIN, Delay,
+};
+pub use reverb::{
+    REVERB_DAMPING_CLOSED_HZ, REVERB_DAMPING_OPEN_HZ, REVERB_DECAY_MAX, REVERB_DECAY_MIN, Reverb,
+};
+
+use crate::mixer::StereoFrame;
+
+// Bypass and MIX changes ramp over this long instead of clicking.
+const FADE_SECONDS: f32 = 0.02;
+
+/// The insert effects between the modifiers and the master gain, in signal
+/// order.
+pub struct Effects {
+    pub chorus: Chorus,
+    pub delay: Delay,
+    pub reverb: Reverb,
+}
+
+impl Effects {
+    pub fn new() -> Self {
+        Self {
+            chorus: Chorus::new(),
+            delay: Delay::new(),
+            reverb: Reverb::new(),
+        }
+    }
+
+    /// Resizes every effect's lines; allocates.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.chorus.set_sample_rate(sample_rate);
+        self.delay.set_sample_rate(sample_rate);
+        self.reverb.set_sample_rate(sample_rate);
+    }
+
+    pub fn process(&mut self, input: StereoFrame) -> StereoFrame {
+        let chorused = self.chorus.process(input);
+        let delayed = self.delay.process(chorused);
+        self.reverb.process(delayed)
+    }
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wet amount an effect actually applies: chases its MIX while on and falls
+/// to zero when bypassed, a fixed step per frame, so neither the switch nor
+/// the knob clicks.
+struct WetFade {
+    level: f32,
+    step: f32,
+}
+
+impl WetFade {
+    fn new(sample_rate: f32) -> Self {
+        let mut fade = Self {
+            level: 0.0,
+            step: 0.0,
+        };
+        fade.set_sample_rate(sample_rate);
+        fade
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.step = 1.0 / (FADE_SECONDS * sample_rate.max(1.0));
+    }
+
+    /// Moves one frame toward `mix`, or toward silence when disabled.
+    fn advance(&mut self, enabled: bool, mix: f32) {
+        let target = if enabled { mix } else { 0.0 };
+        self.level += (target - self.level).clamp(-self.step, self.step);
+    }
+
+    /// True once the wet path is fully faded out and can be skipped.
+    fn silent(&self) -> bool {
+        self.level <= 0.0
+    }
+
+    fn blend(&self, dry: StereoFrame, wet: StereoFrame) -> StereoFrame {
+        [
+            dry[0] * (1.0 - self.level) + wet[0] * self.level,
+            dry[1] * (1.0 - self.level) + wet[1] * self.level,
+        ]
+    }
+}
+
+// Coefficient of a one-pole smoother with its corner at `hz`.
+fn one_pole(hz: f32, sample_rate: f32) -> f32 {
+    1.0 - (-2.0 * PI * hz / sample_rate).exp()
+}
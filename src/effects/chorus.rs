@@ -0,0 +1,117 @@
+use super::{WetFade, one_pole};
+use crate::{mixer::StereoFrame, outputstage::soft_clip};
+
+pub const CHORUS_RATE_MIN: f32 = 0.1;
+pub const CHORUS_RATE_MAX: f32 = 6.0;
+// Centre delay of both lines, and how far full depth swings them either way.
+const BASE_DELAY_SEC: f32 = 0.007;
+const MAX_SWING_SEC: f32 = 0.005;
+// The wet path's corner, standing in for a bucket-brigade chip's clock
+// filters, and a little drive so loud notes thicken as they would there.
+const WET_TONE_HZ: f32 = 7_000.0;
+const WET_DRIVE: f32 = 1.4;
+
+/// Stereo chorus after the modifiers: two delay lines swept by one
+/// triangle LFO in opposite phase, so the left and right copies drift
+/// apart and back. Off by default.
+pub struct Chorus {
+    enabled: bool,
+    rate: f32,
+    depth: f32,
+    mix: f32,
+    wet: WetFade,
+    phase: f32,
+    sample_rate: f32,
+    lines: [Vec<f32>; 2],
+    write: usize,
+    tone: [f32; 2],
+}
+
+impl Chorus {
+    pub fn new() -> Self {
+        let mut chorus = Self {
+            enabled: false,
+            rate: 0.5,
+            depth: 0.5,
+            mix: 0.5,
+            wet: WetFade::new(44_100.0),
+            phase: 0.0,
+            sample_rate: 44_100.0,
+            lines: [Vec::new(), Vec::new()],
+            write: 0,
+            tone: [0.0; 2],
+        };
+        chorus.set_sample_rate(chorus.sample_rate);
+        chorus
+    }
+
+    /// Sizes the delay lines for the longest sweep at this rate; allocates,
+    /// so call it where the pipeline's other tables are rebuilt.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate.max(1.0);
+        self.wet.set_sample_rate(self.sample_rate);
+        let frames = ((BASE_DELAY_SEC + MAX_SWING_SEC) * self.sample_rate).ceil() as usize + 2;
+        for line in &mut self.lines {
+            line.clear();
+            line.resize(frames, 0.0);
+        }
+        self.write = 0;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn set_rate(&mut self, hz: f32) {
+        self.rate = hz.clamp(CHORUS_RATE_MIN, CHORUS_RATE_MAX);
+    }
+
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    pub fn process(&mut self, input: StereoFrame) -> StereoFrame {
+        let dt = 1.0 / self.sample_rate;
+        self.wet.advance(self.enabled, self.mix);
+        let len = self.lines[0].len();
+        // Bypassed lines keep filling, so switching on never replays a
+        // stale tail.
+        for (line, sample) in self.lines.iter_mut().zip(input) {
+            line[self.write] = sample;
+        }
+        let output = if self.wet.silent() {
+            input
+        } else {
+            self.phase = (self.phase + self.rate * dt).fract();
+            let triangle = 1.0 - 4.0 * (self.phase - 0.5).abs();
+            let swing = MAX_SWING_SEC * self.depth;
+            let tone = one_pole(WET_TONE_HZ, self.sample_rate);
+            let mut wet = [0.0; 2];
+            for (channel, polarity) in [1.0, -1.0].into_iter().enumerate() {
+                let delay = (BASE_DELAY_SEC + swing * triangle * polarity) * self.sample_rate;
+                let read = (self.write + len) as f32 - delay;
+                let index = read.floor();
+                let fraction = read - index;
+                let line = &self.lines[channel];
+                let first = line[index as usize % len];
+                let second = line[(index as usize + 1) % len];
+                let delayed = first + (second - first) * fraction;
+                self.tone[channel] += (delayed - self.tone[channel]) * tone;
+                wet[channel] = soft_clip(self.tone[channel] * WET_DRIVE) / WET_DRIVE;
+            }
+            self.wet.blend(input, wet)
+        };
+        self.write = (self.write + 1) % len;
+        output
+    }
+}
+
+impl Default for Chorus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
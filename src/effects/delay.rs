@@ -0,0 +1,171 @@
+use super::{WetFade, one_pole};
+use crate::{
+    clock::{DEFAULT_BPM, SyncDivision},
+    mixer::StereoFrame,
+    outputstage::soft_clip,
+};
+
+pub const DELAY_TIME_MIN: f32 = 0.02;
+pub const DELAY_TIME_MAX: f32 = 2.0;
+// Loop low-pass corner with DAMPING at zero and fully up.
+pub const DELAY_DAMPING_OPEN_HZ: f32 = 12_000.0;
+pub const DELAY_DAMPING_CLOSED_HZ: f32 = 800.0;
+const DELAY_FEEDBACK_MAX: f32 = 0.95;
+// Longest echo the lines hold; slower synced divisions are clamped to it.
+const DELAY_LINE_SEC: f32 = 4.0;
+// Time changes glide over about this long, bending the repeats' pitch the
+// way a bucket-brigade or tape delay does instead of clicking.
+const TIME_GLIDE_SEC: f32 = 0.12;
+// Keeps low end from piling up in the loop at high feedback.
+const LOOP_HIGH_PASS_HZ: f32 = 60.0;
+
+/// Stereo echo in the analog manner: each repeat passes a damping low-pass
+/// and a gentle saturation on its way round the loop, so the tail darkens
+/// and thickens as it fades. Off by default.
+pub struct Delay {
+    enabled: bool,
+    division: Option<SyncDivision>,
+    time: f32,
+    bpm: f32,
+    feedback: f32,
+    damping_hz: f32,
+    mix: f32,
+    wet: WetFade,
+    // Current read distance in frames, gliding toward the set time.
+    distance: f32,
+    sample_rate: f32,
+    damping: f32,
+    high_pass: f32,
+    glide: f32,
+    lines: [Vec<f32>; 2],
+    write: usize,
+    low: [f32; 2],
+    rumble: [f32; 2],
+}
+
+impl Delay {
+    pub fn new() -> Self {
+        let mut delay = Self {
+            enabled: false,
+            division: None,
+            time: 0.2,
+            bpm: DEFAULT_BPM,
+            feedback: 0.35,
+            damping_hz: 4_000.0,
+            mix: 0.35,
+            wet: WetFade::new(44_100.0),
+            distance: 0.0,
+            sample_rate: 44_100.0,
+            damping: 0.0,
+            high_pass: 0.0,
+            glide: 0.0,
+            lines: [Vec::new(), Vec::new()],
+            write: 0,
+            low: [0.0; 2],
+            rumble: [0.0; 2],
+        };
+        delay.set_sample_rate(delay.sample_rate);
+        delay
+    }
+
+    /// Sizes the lines for the longest echo at this rate; allocates, so call
+    /// it where the pipeline's other tables are rebuilt.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate.max(1.0);
+        self.wet.set_sample_rate(self.sample_rate);
+        let frames = (DELAY_LINE_SEC * self.sample_rate).ceil() as usize + 2;
+        for line in &mut self.lines {
+            line.clear();
+            line.resize(frames, 0.0);
+        }
+        self.write = 0;
+        self.low = [0.0; 2];
+        self.rumble = [0.0; 2];
+        self.high_pass = one_pole(LOOP_HIGH_PASS_HZ, self.sample_rate);
+        self.glide = 1.0 - (-1.0 / (TIME_GLIDE_SEC * self.sample_rate)).exp();
+        self.set_damping(self.damping_hz);
+        self.distance = self.target_distance();
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Locks the echo to a note length at the clock tempo; `None` lets the
+    /// TIME knob set it.
+    pub fn set_division(&mut self, division: Option<SyncDivision>) {
+        self.division = division;
+    }
+
+    /// Echo time in seconds while unsynced.
+    pub fn set_time(&mut self, seconds: f32) {
+        self.time = seconds.clamp(DELAY_TIME_MIN, DELAY_TIME_MAX);
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = bpm.max(1.0);
+    }
+
+    /// 0 to 1; the top of the range stops just short of self-oscillation.
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(0.0, 1.0) * DELAY_FEEDBACK_MAX;
+    }
+
+    /// Corner of the low-pass in the loop, in Hz.
+    pub fn set_damping(&mut self, hz: f32) {
+        self.damping_hz = hz.clamp(DELAY_DAMPING_CLOSED_HZ, DELAY_DAMPING_OPEN_HZ);
+        self.damping = one_pole(self.damping_hz, self.sample_rate);
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    fn target_distance(&self) -> f32 {
+        let seconds = match self.division {
+            Some(division) => division.beats() as f32 * 60.0 / self.bpm,
+            None => self.time,
+        };
+        (seconds * self.sample_rate).clamp(1.0, (self.lines[0].len() - 2) as f32)
+    }
+
+    pub fn process(&mut self, input: StereoFrame) -> StereoFrame {
+        self.wet.advance(self.enabled, self.mix);
+        let len = self.lines[0].len();
+        if self.wet.silent() {
+            // Bypassed: the lines keep the recent input but nothing
+            // recirculates, so switching on starts from a clean loop.
+            for (line, sample) in self.lines.iter_mut().zip(input) {
+                line[self.write] = sample;
+            }
+            self.distance = self.target_distance();
+            self.write = (self.write + 1) % len;
+            return input;
+        }
+        self.distance += (self.target_distance() - self.distance) * self.glide;
+        let feedback = if self.enabled { self.feedback } else { 0.0 };
+        let read = (self.write + len) as f32 - self.distance;
+        let index = read.floor();
+        let fraction = read - index;
+        let mut echoes = [0.0; 2];
+        for channel in 0..2 {
+            let line = &mut self.lines[channel];
+            let first = line[index as usize % len];
+            let second = line[(index as usize + 1) % len];
+            let delayed = first + (second - first) * fraction;
+            self.low[channel] += (delayed - self.low[channel]) * self.damping;
+            self.rumble[channel] += (self.low[channel] - self.rumble[channel]) * self.high_pass;
+            let echo = self.low[channel] - self.rumble[channel];
+            line[self.write] = input[channel] + soft_clip(echo * feedback);
+            echoes[channel] = echo;
+        }
+        self.write = (self.write + 1) % len;
+        self.wet.blend(input, echoes)
+    }
+}
+
+impl Default for Delay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,208 @@
+use super::{WetFade, one_pole};
+use crate::mixer::StereoFrame;
+
+pub const REVERB_DECAY_MIN: f32 = 0.3;
+pub const REVERB_DECAY_MAX: f32 = 10.0;
+// Corner of the in-loop low-pass with DAMPING at zero and fully up.
+pub const REVERB_DAMPING_OPEN_HZ: f32 = 16_000.0;
+pub const REVERB_DAMPING_CLOSED_HZ: f32 = 1_500.0;
+// Feedback-line lengths in frames at 44.1 kHz (Freeverb's comb lengths),
+// spread so their echoes never pile up on the same frame.
+const LINE_FRAMES: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+// Allpasses ahead of the network smear each attack into a wash.
+const DIFFUSER_FRAMES: [usize; 4] = [556, 441, 341, 225];
+const DIFFUSER_GAIN: f32 = 0.5;
+const REFERENCE_RATE: f32 = 44_100.0;
+// Brings the summed lines back to roughly the dry level.
+const WET_GAIN: f32 = 0.3;
+
+struct DelayLine {
+    buffer: Vec<f32>,
+    cursor: usize,
+}
+
+impl DelayLine {
+    fn new(frames: usize) -> Self {
+        Self {
+            buffer: vec![0.0; frames.max(1)],
+            cursor: 0,
+        }
+    }
+
+    // The frame written `buffer.len()` calls ago.
+    fn read(&self) -> f32 {
+        self.buffer[self.cursor]
+    }
+
+    fn write(&mut self, sample: f32) {
+        self.buffer[self.cursor] = sample;
+        self.cursor = (self.cursor + 1) % self.buffer.len();
+    }
+
+    fn clear(&mut self) {
+        self.buffer.fill(0.0);
+    }
+}
+
+// Schroeder allpass: flat in level, smeared in time.
+struct Diffuser {
+    line: DelayLine,
+}
+
+impl Diffuser {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.line.read();
+        let fed = input + delayed * DIFFUSER_GAIN;
+        self.line.write(fed);
+        delayed - fed * DIFFUSER_GAIN
+    }
+}
+
+/// Plate-style reverb: four diffusers into an eight-line feedback delay
+/// network mixed by a Hadamard matrix, with a damping low-pass in every
+/// line. SIZE sets the decay time. Off by default; cheap enough to leave on
+/// while auditioning patches.
+pub struct Reverb {
+    enabled: bool,
+    decay: f32,
+    damping_hz: f32,
+    mix: f32,
+    wet: WetFade,
+    // Whether the lines may still hold a tail from before a bypass.
+    ringing: bool,
+    sample_rate: f32,
+    damping: f32,
+    gains: [f32; 8],
+    diffusers: Vec<Diffuser>,
+    lines: Vec<DelayLine>,
+    low: [f32; 8],
+}
+
+impl Reverb {
+    pub fn new() -> Self {
+        let mut reverb = Self {
+            enabled: false,
+            decay: 2.0,
+            damping_hz: 5_000.0,
+            mix: 0.25,
+            wet: WetFade::new(REFERENCE_RATE),
+            ringing: false,
+            sample_rate: REFERENCE_RATE,
+            damping: 0.0,
+            gains: [0.0; 8],
+            diffusers: Vec::new(),
+            lines: Vec::new(),
+            low: [0.0; 8],
+        };
+        reverb.set_sample_rate(reverb.sample_rate);
+        reverb
+    }
+
+    /// Rebuilds the lines for this rate; allocates, so call it where the
+    /// pipeline's other tables are rebuilt.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate.max(1.0);
+        self.wet.set_sample_rate(self.sample_rate);
+        let scale = self.sample_rate / REFERENCE_RATE;
+        let frames = |length: usize| (length as f32 * scale).round() as usize;
+        self.diffusers = DIFFUSER_FRAMES
+            .iter()
+            .map(|length| Diffuser {
+                line: DelayLine::new(frames(*length)),
+            })
+            .collect();
+        self.lines = LINE_FRAMES
+            .iter()
+            .map(|length| DelayLine::new(frames(*length)))
+            .collect();
+        self.low = [0.0; 8];
+        self.ringing = false;
+        self.set_decay(self.decay);
+        self.set_damping(self.damping_hz);
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Seconds the tail takes to fall by 60 dB.
+    pub fn set_decay(&mut self, seconds: f32) {
+        self.decay = seconds.clamp(REVERB_DECAY_MIN, REVERB_DECAY_MAX);
+        for (gain, line) in self.gains.iter_mut().zip(&self.lines) {
+            let seconds = line.buffer.len() as f32 / self.sample_rate;
+            *gain = 10f32.powf(-3.0 * seconds / self.decay);
+        }
+    }
+
+    /// Corner of the low-pass in every line, in Hz.
+    pub fn set_damping(&mut self, hz: f32) {
+        self.damping_hz = hz.clamp(REVERB_DAMPING_CLOSED_HZ, REVERB_DAMPING_OPEN_HZ);
+        self.damping = one_pole(self.damping_hz, self.sample_rate);
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    pub fn process(&mut self, input: StereoFrame) -> StereoFrame {
+        self.wet.advance(self.enabled, self.mix);
+        if self.wet.silent() {
+            // Bypassed: the network idles, emptied once so switching on
+            // never replays an old tail.
+            if self.ringing {
+                self.lines.iter_mut().for_each(DelayLine::clear);
+                self.diffusers
+                    .iter_mut()
+                    .for_each(|diffuser| diffuser.line.clear());
+                self.low = [0.0; 8];
+                self.ringing = false;
+            }
+            return input;
+        }
+        self.ringing = true;
+        let mut diffused = (input[0] + input[1]) * 0.5;
+        for diffuser in &mut self.diffusers {
+            diffused = diffuser.process(diffused);
+        }
+        let mut taps = [0.0; 8];
+        for (index, line) in self.lines.iter().enumerate() {
+            self.low[index] += (line.read() - self.low[index]) * self.damping;
+            taps[index] = self.low[index] * self.gains[index];
+        }
+        let mut feedback = taps;
+        hadamard(&mut feedback);
+        for (index, (line, fed)) in self.lines.iter_mut().zip(feedback).enumerate() {
+            // Alternating signs keep the input off the matrix's all-ones row.
+            let sign = if index % 2 == 0 { 1.0 } else { -1.0 };
+            line.write(fed + diffused * sign);
+        }
+        let left = taps[0] + taps[2] + taps[4] + taps[6];
+        let right = taps[1] + taps[3] + taps[5] + taps[7];
+        self.wet.blend(input, [left * WET_GAIN, right * WET_GAIN])
+    }
+}
+
+impl Default for Reverb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Orthonormal 8×8 Hadamard mix, done as a fast Walsh-Hadamard transform.
+fn hadamard(values: &mut [f32; 8]) {
+    let mut span = 1;
+    while span < values.len() {
+        for start in (0..values.len()).step_by(span * 2) {
+            for index in start..start + span {
+                let (a, b) = (values[index], values[index + span]);
+                values[index] = a + b;
+                values[index + span] = a - b;
+            }
+        }
+        span *= 2;
+    }
+    let norm = (values.len() as f32).sqrt().recip();
+    for value in values.iter_mut() {
+        *value *= norm;
+    }
+}
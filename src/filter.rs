@@ -0,0 +1,255 @@
+use std::f32::consts::PI;
+
+use rustfft::num_complex::Complex32;
+
+const LADDER_MAX_RESONANCE: f32 = 4.2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+impl FilterType {
+    pub const VALUES: [FilterType; 4] = [
+        FilterType::LowPass,
+        FilterType::HighPass,
+        FilterType::BandPass,
+        FilterType::Notch,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::VALUES
+            .iter()
+            .position(|kind| *kind == self)
+            .unwrap_or(0);
+        Self::VALUES[(index + 1) % Self::VALUES.len()]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterType::LowPass => "LP",
+            FilterType::HighPass => "HP",
+            FilterType::BandPass => "BP",
+            FilterType::Notch => "NOTCH",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterSlope {
+    Db6,
+    Db12,
+    Db18,
+    Db24,
+}
+
+impl FilterSlope {
+    pub const VALUES: [FilterSlope; 4] = [
+        FilterSlope::Db6,
+        FilterSlope::Db12,
+        FilterSlope::Db18,
+        FilterSlope::Db24,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::VALUES
+            .iter()
+            .position(|slope| *slope == self)
+            .unwrap_or(0);
+        Self::VALUES[(index + 1) % Self::VALUES.len()]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterSlope::Db6 => "6 dB",
+            FilterSlope::Db12 => "12 dB",
+            FilterSlope::Db18 => "18 dB",
+            FilterSlope::Db24 => "24 dB",
+        }
+    }
+
+    fn poles(self) -> usize {
+        match self {
+            FilterSlope::Db6 => 1,
+            FilterSlope::Db12 => 2,
+            FilterSlope::Db18 => 3,
+            FilterSlope::Db24 => 4,
+        }
+    }
+}
+
+pub struct Filter {
+    kind: FilterType,
+    slope: FilterSlope,
+    ladder: LadderFilter,
+    svf: StateVariableFilter,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self {
+            kind: FilterType::LowPass,
+            slope: FilterSlope::Db24,
+            ladder: LadderFilter::new(),
+            svf: StateVariableFilter::new(),
+        }
+    }
+
+    pub fn set_type(&mut self, kind: FilterType) {
+        if kind != self.kind {
+            self.ladder = LadderFilter::new();
+            self.svf = StateVariableFilter::new();
+        }
+        self.kind = kind;
+    }
+
+    pub fn set_slope(&mut self, slope: FilterSlope) {
+        self.slope = slope;
+    }
+
+    pub fn process(&mut self, input: f32, cutoff: f32, emphasis: f32, dt: f32) -> f32 {
+        match self.kind {
+            FilterType::LowPass => {
+                self.ladder.process(input, cutoff, emphasis, dt);
+                self.ladder.tap(self.slope.poles())
+            }
+            _ => {
+                let outputs = self.svf.process(input, cutoff, emphasis, dt);
+                match self.kind {
+                    FilterType::HighPass => outputs.high,
+                    FilterType::BandPass => outputs.band,
+                    _ => outputs.low + outputs.high,
+                }
+            }
+        }
+    }
+}
+
+/// Small-signal gain of the filter at `freq`, leaving out the ladder's input
+/// saturation. Both filters are trapezoidal integrators, so the analog
+/// prototype evaluated on the prewarped axis is their exact response.
+pub fn magnitude_response(
+    kind: FilterType,
+    slope: FilterSlope,
+    cutoff: f32,
+    emphasis: f32,
+    freq: f32,
+    sample_rate: f32,
+) -> f32 {
+    let warp = |hz: f32| (PI * (hz / sample_rate).clamp(0.0, 0.49)).tan();
+    let s = Complex32::new(0.0, warp(freq) / warp(cutoff).max(f32::EPSILON));
+    let one = Complex32::new(1.0, 0.0);
+    let response = match kind {
+        FilterType::LowPass => {
+            let stage = one / (one + s);
+            let resonance = emphasis.clamp(0.0, 1.0) * LADDER_MAX_RESONANCE;
+            stage.powu(slope.poles() as u32) / (one + stage.powu(4) * resonance)
+        }
+        _ => {
+            let k = 2.0 - 1.95 * emphasis.clamp(0.0, 1.0);
+            let denominator = s * s + s * k + one;
+            let numerator = match kind {
+                FilterType::HighPass => s * s,
+                FilterType::BandPass => s,
+                _ => s * s + one,
+            };
+            numerator / denominator
+        }
+    };
+    response.norm()
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Zero-delay-feedback ladder: four trapezoidal one-poles with the global
+// feedback loop solved per sample, saturating at the loop input.
+struct LadderFilter {
+    state: [f32; 4],
+    output: [f32; 4],
+}
+
+impl LadderFilter {
+    fn new() -> Self {
+        Self {
+            state: [0.0; 4],
+            output: [0.0; 4],
+        }
+    }
+
+    fn tap(&self, poles: usize) -> f32 {
+        self.output[poles.clamp(1, 4) - 1]
+    }
+
+    fn process(&mut self, input: f32, cutoff: f32, emphasis: f32, dt: f32) {
+        let normalized = (cutoff * dt).clamp(0.0, 0.49);
+        let g = (PI * normalized).tan();
+        let gain = g / (1.0 + g);
+        let carry = 1.0 / (1.0 + g);
+        let resonance = emphasis.clamp(0.0, 1.0) * LADDER_MAX_RESONANCE;
+
+        let mut sigma = 0.0;
+        for state in self.state {
+            sigma = sigma * gain + state * carry;
+        }
+        let gain4 = gain.powi(4);
+        let predicted = (gain4 * input + sigma) / (1.0 + resonance * gain4);
+
+        let mut signal = (input - resonance * predicted).tanh();
+        for (state, output) in self.state.iter_mut().zip(self.output.iter_mut()) {
+            let v = (signal - *state) * gain;
+            let y = v + *state;
+            *state = y + v;
+            *output = y;
+            signal = y;
+        }
+    }
+}
+
+struct SvfOutputs {
+    low: f32,
+    band: f32,
+    high: f32,
+}
+
+// Trapezoidal (zero-delay feedback) state-variable filter.
+struct StateVariableFilter {
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl StateVariableFilter {
+    fn new() -> Self {
+        Self {
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32, cutoff: f32, emphasis: f32, dt: f32) -> SvfOutputs {
+        let normalized = (cutoff * dt).clamp(0.0, 0.49);
+        let g = (PI * normalized).tan();
+        let k = 2.0 - 1.95 * emphasis.clamp(0.0, 1.0);
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        let v3 = input - self.ic2eq;
+        let v1 = a1 * self.ic1eq + a2 * v3;
+        let v2 = self.ic2eq + a2 * self.ic1eq + a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        SvfOutputs {
+            low: v2,
+            band: v1,
+            high: input - k * v1 - v2,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::mixer::StereoFrame;
+
+/// Rise time for the follower: quick enough to catch a drum hit's front
+/// edge without tracking individual cycles of the waveform.
+pub const FOLLOWER_ATTACK_SECONDS: f32 = 0.003;
+pub const FOLLOWER_RELEASE_MIN: f32 = 0.01;
+pub const FOLLOWER_RELEASE_MAX: f32 = 1.5;
+
+/// Peak envelope of the external input, 0 to 1 for signals up to full
+/// scale, rising in `FOLLOWER_ATTACK_SECONDS` and falling over the release.
+pub struct EnvelopeFollower {
+    level: f32,
+    release: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new() -> Self {
+        Self {
+            level: 0.0,
+            release: 0.2,
+        }
+    }
+
+    pub fn set_release(&mut self, seconds: f32) {
+        self.release = seconds.clamp(FOLLOWER_RELEASE_MIN, FOLLOWER_RELEASE_MAX);
+    }
+
+    pub fn process(&mut self, frame: StereoFrame, dt: f32) -> f32 {
+        let input = frame[0].abs().max(frame[1].abs()).min(1.0);
+        let time = if input > self.level {
+            FOLLOWER_ATTACK_SECONDS
+        } else {
+            self.release
+        };
+        self.level += (input - self.level) * (1.0 - (-dt / time).exp());
+        self.level
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+}
+
+impl Default for EnvelopeFollower {
+    fn default() -> Self {
+        Self::new()
+    }
+}
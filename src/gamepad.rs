@@ -0,0 +1,230 @@
+#[cfg(target_os = "linux")]
+use std::{fs, io::Read, path::PathBuf, sync::mpsc, thread};
+
+use anyhow::Result;
+#[cfg(target_os = "linux")]
+use anyhow::{Context, anyhow};
+
+// Face buttons and shoulders play a major pentatonic run from middle C, in
+// the Linux joystick numbering of Xbox-style pads: A B X Y LB RB.
+const NOTE_BUTTONS: [(u8, i32); 6] = [(0, 0), (1, 2), (2, 4), (3, 7), (4, 9), (5, 12)];
+const BASE_NOTE: i32 = 60;
+const MIN_OCTAVE: i32 = -3;
+const MAX_OCTAVE: i32 = 3;
+const AXIS_LEFT_Y: u8 = 1;
+const AXIS_LEFT_TRIGGER: u8 = 2;
+const AXIS_RIGHT_Y: u8 = 4;
+const AXIS_RIGHT_TRIGGER: u8 = 5;
+const AXIS_DPAD_Y: u8 = 7;
+const STICK_DEADZONE: f32 = 0.12;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Button {
+        index: u8,
+        pressed: bool,
+    },
+    /// -1 to 1; triggers rest at -1.
+    Axis {
+        index: u8,
+        value: f32,
+    },
+}
+
+/// What a pad gesture does to the synth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GamepadAction {
+    NoteOn(u8),
+    NoteOff(u8),
+    /// -1 to 1, full bend at the ends.
+    Bend(f32),
+    /// 0 to 1.
+    ModWheel(f32),
+    /// -1 (left trigger) to 1 (right trigger), closing or opening the
+    /// filter from the panel cutoff.
+    Cutoff(f32),
+}
+
+/// Turns raw pad events into performance gestures: buttons play notes, the
+/// D-pad shifts their octave, the left stick bends, the right stick is a
+/// spring-loaded mod wheel and the triggers sweep the cutoff.
+pub struct GamepadMapper {
+    octave: i32,
+    sounding: [Option<u8>; NOTE_BUTTONS.len()],
+    triggers: [f32; 2],
+}
+
+impl GamepadMapper {
+    pub fn new() -> Self {
+        Self {
+            octave: 0,
+            sounding: [None; NOTE_BUTTONS.len()],
+            // Released triggers until the pad reports otherwise.
+            triggers: [0.0; 2],
+        }
+    }
+
+    pub fn octave(&self) -> i32 {
+        self.octave
+    }
+
+    pub fn map(&mut self, event: GamepadEvent) -> Option<GamepadAction> {
+        match event {
+            GamepadEvent::Button { index, pressed } => {
+                let slot = NOTE_BUTTONS
+                    .iter()
+                    .position(|(button, _)| *button == index)?;
+                if pressed {
+                    let note = (BASE_NOTE + self.octave * 12 + NOTE_BUTTONS[slot].1) as u8;
+                    self.sounding[slot] = Some(note);
+                    Some(GamepadAction::NoteOn(note))
+                } else {
+                    // Releases the note the button started, even if the
+                    // octave moved while it was held.
+                    self.sounding[slot].take().map(GamepadAction::NoteOff)
+                }
+            }
+            GamepadEvent::Axis {
+                index: AXIS_LEFT_Y,
+                value,
+            } => Some(GamepadAction::Bend(-deadzone(value))),
+            GamepadEvent::Axis {
+                index: AXIS_RIGHT_Y,
+                value,
+            } => Some(GamepadAction::ModWheel((-deadzone(value)).max(0.0))),
+            GamepadEvent::Axis { index, value }
+                if index == AXIS_LEFT_TRIGGER || index == AXIS_RIGHT_TRIGGER =>
+            {
+                let side = usize::from(index == AXIS_RIGHT_TRIGGER);
+                self.triggers[side] = (value + 1.0) * 0.5;
+                Some(GamepadAction::Cutoff(self.triggers[1] - self.triggers[0]))
+            }
+            GamepadEvent::Axis {
+                index: AXIS_DPAD_Y,
+                value,
+            } => {
+                if value < -0.5 {
+                    self.octave = (self.octave + 1).min(MAX_OCTAVE);
+                } else if value > 0.5 {
+                    self.octave = (self.octave - 1).max(MIN_OCTAVE);
+                }
+                None
+            }
+            GamepadEvent::Axis { .. } => None,
+        }
+    }
+}
+
+impl Default for GamepadMapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn deadzone(value: f32) -> f32 {
+    if value.abs() < STICK_DEADZONE {
+        0.0
+    } else {
+        (value - STICK_DEADZONE * value.signum()) / (1.0 - STICK_DEADZONE)
+    }
+}
+
+/// A pad read through the Linux joystick interface (`/dev/input/js*`) on a
+/// background thread.
+#[cfg(target_os = "linux")]
+pub struct GamepadInput {
+    receiver: mpsc::Receiver<GamepadEvent>,
+    name: String,
+    mapper: GamepadMapper,
+}
+
+#[cfg(target_os = "linux")]
+impl GamepadInput {
+    /// Opens `device`, or the first joystick when none is given.
+    pub fn open(device: Option<&str>) -> Result<Self> {
+        let path = match device {
+            Some(device) => PathBuf::from(device),
+            None => first_joystick().ok_or_else(|| anyhow!("No gamepad found"))?,
+        };
+        let mut file =
+            fs::File::open(&path).with_context(|| format!("opening {}", path.display()))?;
+        let (tx, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("gamepad".into())
+            .spawn(move || {
+                // struct js_event: u32 time, i16 value, u8 type, u8 number.
+                let mut event = [0u8; 8];
+                while file.read_exact(&mut event).is_ok() {
+                    let value = i16::from_ne_bytes([event[4], event[5]]);
+                    let index = event[7];
+                    // The 0x80 flag marks the initial state burst.
+                    let parsed = match event[6] & !0x80 {
+                        0x01 => GamepadEvent::Button {
+                            index,
+                            pressed: value != 0,
+                        },
+                        0x02 => GamepadEvent::Axis {
+                            index,
+                            value: value as f32 / i16::MAX as f32,
+                        },
+                        _ => continue,
+                    };
+                    if tx.send(parsed).is_err() {
+                        break;
+                    }
+                }
+            })
+            .context("starting the gamepad thread")?;
+        Ok(Self {
+            receiver: rx,
+            name: path.display().to_string(),
+            mapper: GamepadMapper::new(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gestures since the last call, in the order they were made.
+    pub fn drain(&mut self) -> Vec<GamepadAction> {
+        self.receiver
+            .try_iter()
+            .filter_map(|event| self.mapper.map(event))
+            .collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn first_joystick() -> Option<PathBuf> {
+    let mut devices: Vec<PathBuf> = fs::read_dir("/dev/input")
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("js"))
+        })
+        .collect();
+    devices.sort();
+    devices.into_iter().next()
+}
+
+// Other platforms would need their own HID bindings.
+#[cfg(not(target_os = "linux"))]
+pub struct GamepadInput;
+
+#[cfg(not(target_os = "linux"))]
+impl GamepadInput {
+    pub fn open(_device: Option<&str>) -> Result<Self> {
+        Err(anyhow::anyhow!("Gamepad input is only supported on Linux"))
+    }
+
+    pub fn name(&self) -> &str {
+        ""
+    }
+
+    pub fn drain(&mut self) -> Vec<GamepadAction> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use miniroog_model_r::{
+    command::EngineCommand,
+    midi::MidiInput,
+    smf::{MidiFile, MidiPlayer},
+};
+use tracing::{info, warn};
+
+use crate::{
+    AudioLink, LaunchOptions, PanelState, build_pipeline, config::Config,
+    controllers::KeyboardController, handle_api_call, handle_gamepad_action, handle_midi_message,
+    handle_osc_control, load_config_tuning, load_startup_preset, log_mode, open_api_server,
+    open_gamepad, open_osc_server, sync_audio_from_panel, sync_click,
+};
+
+const TICK: Duration = Duration::from_millis(5);
+
+pub fn run(options: &LaunchOptions, config: &Config) -> Result<()> {
+    let (pipeline, mut debug_data) = build_pipeline(config);
+    let mut panel_state = PanelState::new();
+    panel_state.buffer_request = config.buffer;
+    panel_state.scala_tuning = load_config_tuning(config);
+    panel_state.retune();
+    if let Some(path) = options
+        .preset_path
+        .as_ref()
+        .or(config.default_preset.as_ref())
+    {
+        load_startup_preset(&mut panel_state, path)?;
+    }
+    let mut controller = KeyboardController::new();
+    let (mut audio, mut commands) = AudioLink::start(
+        &pipeline,
+        &mut debug_data,
+        &panel_state,
+        config.device.clone(),
+    );
+    // A file given on the command line starts straight away and plays once.
+    let mut midi_player = match &options.midi_file {
+        Some(path) => {
+            let mut player = MidiPlayer::new(MidiFile::load(path)?);
+            log_mode("MIDI file", &path.display().to_string());
+            player.play();
+            Some(player)
+        }
+        None => None,
+    };
+    let mut gamepad = open_gamepad(config);
+    // Without a window, a file or a pad there is nothing else to play the
+    // synth with.
+    let midi_input = match MidiInput::open(config.midi_port.as_deref()) {
+        Ok(input) => {
+            log_mode("MIDI input", input.port_name());
+            Some(input)
+        }
+        Err(err) if midi_player.is_some() || gamepad.is_some() => {
+            warn!("MIDI input unavailable: {err}");
+            None
+        }
+        Err(err) => return Err(err),
+    };
+    let osc_server = open_osc_server(options.osc_port);
+    let api_server = open_api_server(options.http_port, options.http_origin.as_deref());
+    sync_audio_from_panel(&mut panel_state, &mut commands);
+    panel_state.refresh_pitch_target();
+    panel_state.apply_pitch(0.0);
+    info!("Running headless, press Ctrl+C to quit");
+
+    let mut last_tick = Instant::now();
+    loop {
+        thread::sleep(TICK);
+        let now = Instant::now();
+        let dt = now.duration_since(last_tick).as_secs_f32();
+        last_tick = now;
+
+        let mut messages = midi_input
+            .as_ref()
+            .map(|input| input.drain())
+            .unwrap_or_default();
+        if let Some(player) = &mut midi_player {
+            messages.extend(player.advance(dt as f64));
+        }
+        let mut notes_changed = controller.set_scale(panel_state.scale, panel_state.scale_root);
+        notes_changed |= controller.set_split(panel_state.split);
+        notes_changed |= controller.set_tuning(&panel_state.tuning);
+        for message in messages {
+            notes_changed |= handle_midi_message(&mut panel_state, &mut controller, message);
+        }
+        if let Some(pad) = &mut gamepad {
+            for action in pad.drain() {
+                notes_changed |= handle_gamepad_action(&mut panel_state, &mut controller, action);
+            }
+        }
+        if let Some(server) = &osc_server {
+            for control in server.drain() {
+                handle_osc_control(&mut panel_state, control);
+            }
+        }
+        if let Some(server) = &api_server {
+            for call in server.drain() {
+                handle_api_call(&mut panel_state, call);
+            }
+        }
+        panel_state.apply_macros();
+        if notes_changed {
+            let message = controller.message();
+            let retrigger = panel_state.note_event(&message);
+            commands.send(EngineCommand::Velocity(message.velocity));
+            commands.send(EngineCommand::Gate(message.gate));
+            if retrigger {
+                commands.send(EngineCommand::Retrigger);
+            }
+        }
+        if panel_state.take_s_trigger() {
+            commands.send(EngineCommand::TriggerEnvelopes);
+        }
+        debug_data.drain();
+        panel_state.refresh_pitch_target();
+        panel_state.clock.advance(dt);
+        sync_click(&mut panel_state, &mut commands);
+        panel_state.apply_pitch(dt);
+
+        audio.update(dt, &pipeline, &mut debug_data, &panel_state, &mut commands);
+        sync_audio_from_panel(&mut panel_state, &mut commands);
+    }
+}
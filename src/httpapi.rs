@@ -0,0 +1,484 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+#[cfg(target_arch = "wasm32")]
+use anyhow::anyhow;
+use anyhow::{Result, bail};
+#[cfg(not(target_arch = "wasm32"))]
+use tracing::warn;
+
+use crate::preset::Preset;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::preset::{PRESET_DIR, list_presets};
+
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_BODY_BYTES: usize = 64 * 1024;
+#[cfg(not(target_arch = "wasm32"))]
+const IO_TIMEOUT: Duration = Duration::from_secs(2);
+// The UI answers once per frame and headless once per tick.
+#[cfg(not(target_arch = "wasm32"))]
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Work the HTTP thread hands to whoever owns the panel state. Parameters
+/// use the preset keys, so a partial preset doubles as a parameter update.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiRequest {
+    GetParams,
+    SetParams(Preset),
+    LoadPreset(String),
+    SavePreset(String),
+}
+
+/// The panel state after the request, or what went wrong.
+pub type ApiReply = std::result::Result<Preset, String>;
+
+pub struct ApiCall {
+    pub request: ApiRequest,
+    #[cfg(not(target_arch = "wasm32"))]
+    reply: mpsc::Sender<ApiReply>,
+}
+
+impl ApiCall {
+    pub fn respond(self, reply: ApiReply) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = self.reply.send(reply);
+        #[cfg(target_arch = "wasm32")]
+        let _ = reply;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Number(f64),
+    Bool(bool),
+    String(String),
+}
+
+/// Parses a single JSON object whose values are numbers, booleans or
+/// strings; that is all the API accepts.
+pub fn parse_flat_object(text: &str) -> Result<Vec<(String, JsonValue)>> {
+    let mut parser = JsonParser {
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    let mut fields = Vec::new();
+    parser.expect(b'{')?;
+    if !parser.eat(b'}') {
+        loop {
+            let key = parser.string()?;
+            parser.expect(b':')?;
+            fields.push((key, parser.value()?));
+            if parser.eat(b'}') {
+                break;
+            }
+            parser.expect(b',')?;
+        }
+    }
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        bail!("trailing characters after JSON object");
+    }
+    Ok(fields)
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|byte| byte.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        if !self.eat(byte) {
+            bail!("expected `{}` at byte {}", byte as char, self.pos);
+        }
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect(b'"')?;
+        let mut text = String::new();
+        loop {
+            let Some(&byte) = self.bytes.get(self.pos) else {
+                bail!("unterminated string");
+            };
+            self.pos += 1;
+            match byte {
+                b'"' => return Ok(text),
+                b'\\' => {
+                    let Some(&escaped) = self.bytes.get(self.pos) else {
+                        bail!("unterminated string");
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'"' | b'\\' | b'/' => text.push(escaped as char),
+                        b'n' => text.push('\n'),
+                        b't' => text.push('\t'),
+                        b'r' => text.push('\r'),
+                        b'b' => text.push('\u{8}'),
+                        b'f' => text.push('\u{c}'),
+                        b'u' => {
+                            let hex = self
+                                .bytes
+                                .get(self.pos..self.pos + 4)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+                            let Some(code) = hex else {
+                                bail!("invalid \\u escape");
+                            };
+                            self.pos += 4;
+                            text.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        _ => bail!("invalid escape in string"),
+                    }
+                }
+                _ => {
+                    // Copy the whole UTF-8 sequence starting at this byte.
+                    let start = self.pos - 1;
+                    let mut end = self.pos;
+                    while self
+                        .bytes
+                        .get(end)
+                        .is_some_and(|byte| (byte & 0xC0) == 0x80)
+                    {
+                        end += 1;
+                    }
+                    text.push_str(std::str::from_utf8(&self.bytes[start..end])?);
+                    self.pos = end;
+                }
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue> {
+        self.skip_whitespace();
+        let rest = &self.bytes[self.pos..];
+        if rest.starts_with(b"\"") {
+            return Ok(JsonValue::String(self.string()?));
+        }
+        for (word, flag) in [(&b"true"[..], true), (&b"false"[..], false)] {
+            if rest.starts_with(word) {
+                self.pos += word.len();
+                return Ok(JsonValue::Bool(flag));
+            }
+        }
+        let len = rest
+            .iter()
+            .position(|byte| !matches!(byte, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'))
+            .unwrap_or(rest.len());
+        let number = std::str::from_utf8(&rest[..len])?;
+        match number.parse::<f64>() {
+            Ok(value) if len > 0 && value.is_finite() => {
+                self.pos += len;
+                Ok(JsonValue::Number(value))
+            }
+            _ => bail!("expected a number, boolean or string at byte {}", self.pos),
+        }
+    }
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn preset_to_json(preset: &Preset) -> String {
+    let params: Vec<String> = preset
+        .values()
+        .map(|(key, value)| format!("{}: {value}", json_string(key)))
+        .collect();
+    format!(
+        "{{\"name\": {}, \"params\": {{{}}}}}",
+        json_string(&preset.name),
+        params.join(", ")
+    )
+}
+
+/// Builds a partial preset from `{"filter.cutoff": 0.7, "output.limiter": true}`.
+pub fn params_from_json(text: &str) -> Result<Preset> {
+    let mut preset = Preset::default();
+    for (key, value) in parse_flat_object(text)? {
+        match value {
+            JsonValue::Number(number) => preset.set(&key, number as f32),
+            JsonValue::Bool(flag) => preset.set_flag(&key, flag),
+            JsonValue::String(_) => bail!("{key}: expected a number or boolean"),
+        }
+    }
+    Ok(preset)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn name_from_json(text: &str) -> Result<String> {
+    for (key, value) in parse_flat_object(text)? {
+        if key == "name"
+            && let JsonValue::String(name) = value
+        {
+            // A line break would let the name write extra preset lines.
+            if name.chars().any(char::is_control) {
+                bail!("preset name contains control characters");
+            }
+            return Ok(name);
+        }
+    }
+    bail!("expected {{\"name\": \"...\"}}")
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ApiServer {
+    receiver: mpsc::Receiver<ApiCall>,
+    address: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ApiServer {
+    /// Listens on localhost only; the API can overwrite preset files, so
+    /// browsers are turned away unless the page comes from `allowed_origin`
+    /// (for example `http://localhost:8000`).
+    pub fn bind(port: u16, allowed_origin: Option<String>) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let address = listener.local_addr()?.to_string();
+        let (tx, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("http-api".into())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if let Err(err) = serve(stream, allowed_origin.as_deref(), &tx) {
+                        warn!("HTTP API request failed: {err:#}");
+                    }
+                }
+            })?;
+        Ok(Self {
+            receiver: rx,
+            address,
+        })
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn drain(&self) -> Vec<ApiCall> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct Response {
+    status: &'static str,
+    body: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Response {
+    fn ok(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            body,
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            body: format!("{{\"error\": {}}}", json_string(message)),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn serve(
+    stream: TcpStream,
+    allowed_origin: Option<&str>,
+    calls: &mpsc::Sender<ApiCall>,
+) -> Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    // The request line and headers share the body's limit, so a client
+    // cannot make the thread buffer an endless line.
+    let mut reader = BufReader::new((&stream).take(MAX_BODY_BYTES as u64));
+    let mut request_line = String::new();
+    read_head_line(&mut reader, &mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let target = parts.next().unwrap_or("").to_string();
+    let mut content_length = 0;
+    let mut content_type = None;
+    let mut origin = None;
+    loop {
+        let mut line = String::new();
+        if read_head_line(&mut reader, &mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.parse().unwrap_or(0),
+            "content-type" => content_type = Some(value.to_string()),
+            "origin" => origin = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    // Whatever the headers left in the buffer is already counted, so the
+    // rest of the body needs at most this much more.
+    reader.get_mut().set_limit(content_length as u64);
+    // Programs other than browsers send no `Origin`; pages must come from
+    // the one origin allowed, if any.
+    let origin_allowed = origin
+        .as_deref()
+        .is_none_or(|origin| allowed_origin == Some(origin));
+    // Anything that changes state must be JSON: browsers will not send that
+    // cross-origin without a preflight, which the origin check then refuses.
+    let is_json = content_type.as_deref().is_some_and(|content_type| {
+        let media_type = content_type.split(';').next().unwrap_or("");
+        media_type.trim().eq_ignore_ascii_case("application/json")
+    });
+    let response = if !origin_allowed {
+        Response::error("403 Forbidden", "origin not allowed")
+    } else if content_length > MAX_BODY_BYTES {
+        Response::error("413 Payload Too Large", "request body too large")
+    } else if matches!(method.as_str(), "PUT" | "POST") && !is_json {
+        Response::error(
+            "415 Unsupported Media Type",
+            "expected Content-Type: application/json",
+        )
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        route(&method, &target, &String::from_utf8_lossy(&body), calls)
+    };
+    let cors = match origin.filter(|_| origin_allowed) {
+        Some(origin) => format!(
+            "Access-Control-Allow-Origin: {origin}\r\nVary: Origin\r\n\
+             Access-Control-Allow-Methods: GET, PUT, POST, OPTIONS\r\n\
+             Access-Control-Allow-Headers: Content-Type\r\n"
+        ),
+        None => String::new(),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         {cors}Connection: close\r\n\r\n{}",
+        response.status,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+// One line of the request head; running out of the size limit partway
+// through a line is an error rather than a short header.
+#[cfg(not(target_arch = "wasm32"))]
+fn read_head_line(reader: &mut impl BufRead, line: &mut String) -> Result<usize> {
+    let len = reader.read_line(line)?;
+    if len > 0 && !line.ends_with('\n') {
+        bail!("request head too large or cut short");
+    }
+    Ok(len)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn route(method: &str, target: &str, body: &str, calls: &mpsc::Sender<ApiCall>) -> Response {
+    let path = target.split('?').next().unwrap_or("");
+    let request = match (method, path) {
+        ("OPTIONS", _) => return Response::ok(String::new()),
+        ("GET", "/params") => ApiRequest::GetParams,
+        ("PUT" | "POST", "/params") => match params_from_json(body) {
+            Ok(preset) => ApiRequest::SetParams(preset),
+            Err(err) => return Response::error("400 Bad Request", &format!("{err:#}")),
+        },
+        ("GET", "/presets") => {
+            let names: Vec<String> = list_presets(Path::new(PRESET_DIR))
+                .iter()
+                .filter_map(|path| path.file_stem())
+                .map(|stem| json_string(&stem.to_string_lossy()))
+                .collect();
+            return Response::ok(format!("{{\"presets\": [{}]}}", names.join(", ")));
+        }
+        ("POST", "/presets/load") | ("POST", "/presets/save") => match name_from_json(body) {
+            Ok(name) if path.ends_with("load") => ApiRequest::LoadPreset(name),
+            Ok(name) => ApiRequest::SavePreset(name),
+            Err(err) => return Response::error("400 Bad Request", &format!("{err:#}")),
+        },
+        (_, "/params" | "/presets" | "/presets/load" | "/presets/save") => {
+            return Response::error("405 Method Not Allowed", "method not allowed");
+        }
+        _ => return Response::error("404 Not Found", "no such endpoint"),
+    };
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let call = ApiCall {
+        request,
+        reply: reply_tx,
+    };
+    if calls.send(call).is_err() {
+        return Response::error("503 Service Unavailable", "synth is shutting down");
+    }
+    match reply_rx.recv_timeout(REPLY_TIMEOUT) {
+        Ok(Ok(preset)) => Response::ok(preset_to_json(&preset)),
+        Ok(Err(message)) => Response::error("400 Bad Request", &message),
+        Err(_) => Response::error("503 Service Unavailable", "synth did not answer"),
+    }
+}
+
+// Browsers cannot listen for connections.
+#[cfg(target_arch = "wasm32")]
+pub struct ApiServer;
+
+#[cfg(target_arch = "wasm32")]
+impl ApiServer {
+    pub fn bind(_port: u16, _allowed_origin: Option<String>) -> Result<Self> {
+        Err(anyhow!(
+            "the HTTP API is not supported in the browser build"
+        ))
+    }
+
+    pub fn address(&self) -> &str {
+        ""
+    }
+
+    pub fn drain(&self) -> Vec<ApiCall> {
+        Vec::new()
+    }
+}
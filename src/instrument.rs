@@ -0,0 +1,801 @@
+use crate::{
+    command::EngineCommand,
+    filter::{FilterSlope, FilterType},
+    macros::{MACRO_COUNT, MACRO_KNOBS, MacroAssignment, MacroTarget},
+    midi::{CC_ALL_NOTES_OFF, CC_MOD_WHEEL, CC_SUSTAIN, MidiMessage, bend_amount},
+    mixer::Mixer,
+    modifiers::{EnvelopeCurve, Modifiers, VelocityRoute},
+    modulation::{ModRouting, ModulationParams},
+    oscillatorbank::{OscillatorBank, PhaseMode},
+    output::SynthPipeline,
+    oversample::Oversampling,
+    params::{
+        BEND_RANGES, DEFAULT_BEND_RANGE, KnobId, OSC3_LFO_OFFSET, knob_to_glide, knob_to_tune,
+        range_setting_from_value,
+    },
+    preset::Preset,
+    synthparams::{PARAM_COUNT, ParamSpec, SynthParams},
+    vco::{OscEngine, VcoCommand, midi_to_voltage},
+};
+
+const VCO_COUNT: usize = 3;
+// Pitch glide is stepped at this rate, matching the engine's block size.
+pub const GLIDE_STEP_FRAMES: usize = 64;
+pub const KNOB_COUNT: usize = PARAM_COUNT;
+pub const SWITCH_COUNT: usize = HostSwitch::ALL.len();
+// Switch parameter IDs start here, clear of the knob IDs as knobs are added.
+pub const SWITCH_PARAM_BASE: u32 = 1000;
+const SWITCH_LABELS: [&str; 2] = ["OFF", "ON"];
+const GLIDE_LABELS: [&str; 3] = ["OFF", "ON", "LEGATO"];
+const GLIDE_LEGATO: usize = 2;
+const OSC3_MODE_LABELS: [&str; 2] = ["AUDIO", "LFO"];
+const BEND_RANGE_LABELS: [&str; BEND_RANGES.len()] = ["±1 ST", "±2 ST", "±5 ST", "±7 ST", "±12 ST"];
+const MODULATION_KNOBS: [KnobId; 6] = [
+    KnobId::ControllersModMix,
+    KnobId::ControllersModRate,
+    KnobId::ControllersModAmount,
+    KnobId::ModFade,
+    KnobId::ModPitchDepth,
+    KnobId::ModFilterDepth,
+];
+
+// Knobs that need an input port the plugin lacks, or drive the desktop's
+// metronome.
+const UNSUPPORTED: [KnobId; 5] = [
+    KnobId::MixerExternal,
+    KnobId::FollowerRelease,
+    KnobId::FollowerCutoff,
+    KnobId::FollowerVca,
+    KnobId::OutputPhones,
+];
+
+pub fn host_knobs() -> impl Iterator<Item = (u32, KnobId)> {
+    KnobId::ALL
+        .iter()
+        .enumerate()
+        .filter(|(_, knob)| !UNSUPPORTED.contains(knob))
+        .map(|(index, knob)| (index as u32, *knob))
+}
+
+pub fn host_knob(param_id: u32) -> Option<KnobId> {
+    KnobId::ALL
+        .get(param_id as usize)
+        .copied()
+        .filter(|knob| !UNSUPPORTED.contains(knob))
+}
+
+/// Panel switches the plugins expose as stepped parameters. Each is saved as
+/// its position under the preset key the desktop panel uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostSwitch {
+    Osc1On,
+    Osc2On,
+    Osc3On,
+    Osc2Sync,
+    Osc3Sync,
+    Osc1Wavetable,
+    Osc2Wavetable,
+    Osc3Wavetable,
+    PhaseMode,
+    NoiseOn,
+    RingOn,
+    FeedbackPatch,
+    FilterType,
+    FilterSlope,
+    FilterEnvCurve,
+    FilterEnvLoop,
+    LoudnessEnvCurve,
+    Glide,
+    Oversampling,
+    RumbleFilter,
+    Limiter,
+    Chorus,
+    Delay,
+    Reverb,
+    OscModulation,
+    FilterModulation,
+    ModSourceNoise,
+    Osc3Lfo,
+    Osc3Control,
+    LfoKeySync,
+    VelocityRoute,
+    BendRange,
+}
+
+impl HostSwitch {
+    pub const ALL: [HostSwitch; 32] = [
+        HostSwitch::Osc1On,
+        HostSwitch::Osc2On,
+        HostSwitch::Osc3On,
+        HostSwitch::Osc2Sync,
+        HostSwitch::Osc3Sync,
+        HostSwitch::Osc1Wavetable,
+        HostSwitch::Osc2Wavetable,
+        HostSwitch::Osc3Wavetable,
+        HostSwitch::PhaseMode,
+        HostSwitch::NoiseOn,
+        HostSwitch::RingOn,
+        HostSwitch::FeedbackPatch,
+        HostSwitch::FilterType,
+        HostSwitch::FilterSlope,
+        HostSwitch::FilterEnvCurve,
+        HostSwitch::FilterEnvLoop,
+        HostSwitch::LoudnessEnvCurve,
+        HostSwitch::Glide,
+        HostSwitch::Oversampling,
+        HostSwitch::RumbleFilter,
+        HostSwitch::Limiter,
+        HostSwitch::Chorus,
+        HostSwitch::Delay,
+        HostSwitch::Reverb,
+        HostSwitch::OscModulation,
+        HostSwitch::FilterModulation,
+        HostSwitch::ModSourceNoise,
+        HostSwitch::Osc3Lfo,
+        HostSwitch::Osc3Control,
+        HostSwitch::LfoKeySync,
+        HostSwitch::VelocityRoute,
+        HostSwitch::BendRange,
+    ];
+
+    pub fn preset_key(self) -> &'static str {
+        match self {
+            HostSwitch::Osc1On => "mixer.osc1.on",
+            HostSwitch::Osc2On => "mixer.osc2.on",
+            HostSwitch::Osc3On => "mixer.osc3.on",
+            HostSwitch::Osc2Sync => "osc2.sync",
+            HostSwitch::Osc3Sync => "osc3.sync",
+            HostSwitch::Osc1Wavetable => "osc1.wavetable",
+            HostSwitch::Osc2Wavetable => "osc2.wavetable",
+            HostSwitch::Osc3Wavetable => "osc3.wavetable",
+            HostSwitch::PhaseMode => "osc.phase_mode",
+            HostSwitch::NoiseOn => "mixer.noise.on",
+            HostSwitch::RingOn => "mixer.ring.on",
+            HostSwitch::FeedbackPatch => "mixer.feedback_patch",
+            HostSwitch::FilterType => "filter.type",
+            HostSwitch::FilterSlope => "filter.slope",
+            HostSwitch::FilterEnvCurve => "filter_env.curve",
+            HostSwitch::FilterEnvLoop => "filter_env.loop",
+            HostSwitch::LoudnessEnvCurve => "loudness_env.curve",
+            HostSwitch::Glide => "switch.glide",
+            HostSwitch::Oversampling => "engine.oversampling",
+            HostSwitch::RumbleFilter => "output.rumble_filter",
+            HostSwitch::Limiter => "output.limiter",
+            HostSwitch::Chorus => "output.chorus",
+            HostSwitch::Delay => "output.delay",
+            HostSwitch::Reverb => "output.reverb",
+            HostSwitch::OscModulation => "switch.osc_modulation",
+            HostSwitch::FilterModulation => "switch.mod_target_filter",
+            HostSwitch::ModSourceNoise => "switch.mod_source_noise",
+            HostSwitch::Osc3Lfo => "switch.osc3_lfo",
+            HostSwitch::Osc3Control => "switch.osc3_control",
+            HostSwitch::LfoKeySync => "switch.lfo_key_sync",
+            HostSwitch::VelocityRoute => "keyboard.velocity_route",
+            HostSwitch::BendRange => "keyboard.bend_range",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            HostSwitch::Osc1On => "Osc 1 On",
+            HostSwitch::Osc2On => "Osc 2 On",
+            HostSwitch::Osc3On => "Osc 3 On",
+            HostSwitch::Osc2Sync => "Osc 2 Sync",
+            HostSwitch::Osc3Sync => "Osc 3 Sync",
+            HostSwitch::Osc1Wavetable => "Osc 1 Wavetable",
+            HostSwitch::Osc2Wavetable => "Osc 2 Wavetable",
+            HostSwitch::Osc3Wavetable => "Osc 3 Wavetable",
+            HostSwitch::PhaseMode => "Osc Phase",
+            HostSwitch::NoiseOn => "Noise On",
+            HostSwitch::RingOn => "Ring Mod On",
+            HostSwitch::FeedbackPatch => "Feedback Patch",
+            HostSwitch::FilterType => "Filter Type",
+            HostSwitch::FilterSlope => "Filter Slope",
+            HostSwitch::FilterEnvCurve => "Filter Env Curve",
+            HostSwitch::FilterEnvLoop => "Filter Env Loop",
+            HostSwitch::LoudnessEnvCurve => "Loudness Env Curve",
+            HostSwitch::Glide => "Glide Mode",
+            HostSwitch::Oversampling => "Oversampling",
+            HostSwitch::RumbleFilter => "Rumble Filter",
+            HostSwitch::Limiter => "Limiter",
+            HostSwitch::Chorus => "Chorus",
+            HostSwitch::Delay => "Delay",
+            HostSwitch::Reverb => "Reverb",
+            HostSwitch::OscModulation => "Osc Modulation",
+            HostSwitch::FilterModulation => "Filter Modulation",
+            HostSwitch::ModSourceNoise => "Mod Source Noise",
+            HostSwitch::Osc3Lfo => "Osc 3 Mode",
+            HostSwitch::Osc3Control => "Osc 3 Control",
+            HostSwitch::LfoKeySync => "LFO Key Sync",
+            HostSwitch::VelocityRoute => "Velocity",
+            HostSwitch::BendRange => "Bend Range",
+        }
+    }
+
+    /// How many positions the switch has; hosts see one step fewer.
+    pub fn positions(self) -> usize {
+        match self {
+            HostSwitch::PhaseMode => PhaseMode::VALUES.len(),
+            HostSwitch::FilterType => FilterType::VALUES.len(),
+            HostSwitch::FilterSlope => FilterSlope::VALUES.len(),
+            HostSwitch::FilterEnvCurve | HostSwitch::LoudnessEnvCurve => {
+                EnvelopeCurve::VALUES.len()
+            }
+            HostSwitch::Glide => GLIDE_LABELS.len(),
+            HostSwitch::Oversampling => Oversampling::VALUES.len(),
+            HostSwitch::VelocityRoute => VelocityRoute::VALUES.len(),
+            HostSwitch::BendRange => BEND_RANGES.len(),
+            _ => SWITCH_LABELS.len(),
+        }
+    }
+
+    /// Where the desktop panel starts.
+    pub fn default_position(self) -> usize {
+        match self {
+            HostSwitch::Osc1On
+            | HostSwitch::Osc2On
+            | HostSwitch::Osc3On
+            | HostSwitch::NoiseOn
+            | HostSwitch::Glide
+            | HostSwitch::FilterModulation
+            | HostSwitch::ModSourceNoise
+            | HostSwitch::Osc3Control => 1,
+            HostSwitch::FilterSlope => FilterSlope::VALUES
+                .iter()
+                .position(|slope| *slope == FilterSlope::Db24)
+                .unwrap_or(0),
+            HostSwitch::BendRange => bend_range_position(DEFAULT_BEND_RANGE as f32),
+            _ => 0,
+        }
+    }
+
+    pub fn position_label(self, position: usize) -> &'static str {
+        let position = position.min(self.positions() - 1);
+        match self {
+            HostSwitch::PhaseMode => PhaseMode::VALUES[position].label(),
+            HostSwitch::FilterType => FilterType::VALUES[position].label(),
+            HostSwitch::FilterSlope => FilterSlope::VALUES[position].label(),
+            HostSwitch::FilterEnvCurve | HostSwitch::LoudnessEnvCurve => {
+                EnvelopeCurve::VALUES[position].label()
+            }
+            HostSwitch::Glide => GLIDE_LABELS[position],
+            HostSwitch::Osc3Lfo => OSC3_MODE_LABELS[position],
+            HostSwitch::Oversampling => Oversampling::VALUES[position].label(),
+            HostSwitch::VelocityRoute => VelocityRoute::VALUES[position].label(),
+            HostSwitch::BendRange => BEND_RANGE_LABELS[position],
+            _ => SWITCH_LABELS[position],
+        }
+    }
+
+    /// The nearest position to a normalized host value.
+    pub fn position(self, value: f32) -> usize {
+        let last = self.positions() - 1;
+        (value.clamp(0.0, 1.0) * last as f32).round() as usize
+    }
+
+    pub fn normalized(self, position: usize) -> f32 {
+        let last = self.positions() - 1;
+        position.min(last) as f32 / last as f32
+    }
+
+    /// What a preset stores for a position: the position itself, except
+    /// the bend range, which the desktop saves in semitones.
+    fn preset_value(self, position: usize) -> f32 {
+        match self {
+            HostSwitch::BendRange => BEND_RANGES[position.min(BEND_RANGES.len() - 1)] as f32,
+            _ => position as f32,
+        }
+    }
+
+    fn preset_position(self, value: f32) -> usize {
+        match self {
+            HostSwitch::BendRange => bend_range_position(value),
+            _ => (value.max(0.0).round() as usize).min(self.positions() - 1),
+        }
+    }
+
+    /// The command that carries this position to the engine, or `None` for
+    /// the glide mode and the modulation switches, which the instrument
+    /// handles itself.
+    fn engine_command(self, position: usize) -> Option<EngineCommand> {
+        let on = position > 0;
+        let engine = if on {
+            OscEngine::Wavetable
+        } else {
+            OscEngine::Classic
+        };
+        let position = position.min(self.positions() - 1);
+        let command = match self {
+            HostSwitch::Osc1On => EngineCommand::OscEnabled(0, on),
+            HostSwitch::Osc2On => EngineCommand::OscEnabled(1, on),
+            HostSwitch::Osc3On => EngineCommand::OscEnabled(2, on),
+            HostSwitch::Osc2Sync => EngineCommand::Vco(1, VcoCommand::SetSync(on)),
+            HostSwitch::Osc3Sync => EngineCommand::Vco(2, VcoCommand::SetSync(on)),
+            HostSwitch::Osc1Wavetable => EngineCommand::Vco(0, VcoCommand::SetEngine(engine)),
+            HostSwitch::Osc2Wavetable => EngineCommand::Vco(1, VcoCommand::SetEngine(engine)),
+            HostSwitch::Osc3Wavetable => EngineCommand::Vco(2, VcoCommand::SetEngine(engine)),
+            HostSwitch::PhaseMode => EngineCommand::PhaseMode(PhaseMode::VALUES[position]),
+            HostSwitch::NoiseOn => EngineCommand::NoiseEnabled(on),
+            HostSwitch::RingOn => EngineCommand::RingEnabled(on),
+            HostSwitch::FeedbackPatch => EngineCommand::FeedbackPatch(on),
+            HostSwitch::FilterType => EngineCommand::FilterType(FilterType::VALUES[position]),
+            HostSwitch::FilterSlope => EngineCommand::FilterSlope(FilterSlope::VALUES[position]),
+            HostSwitch::FilterEnvCurve => {
+                EngineCommand::FilterEnvelopeCurve(EnvelopeCurve::VALUES[position])
+            }
+            HostSwitch::FilterEnvLoop => EngineCommand::FilterEnvelopeLoop(on),
+            HostSwitch::LoudnessEnvCurve => {
+                EngineCommand::LoudnessEnvelopeCurve(EnvelopeCurve::VALUES[position])
+            }
+            HostSwitch::Glide
+            | HostSwitch::OscModulation
+            | HostSwitch::FilterModulation
+            | HostSwitch::ModSourceNoise
+            | HostSwitch::Osc3Lfo
+            | HostSwitch::Osc3Control
+            | HostSwitch::LfoKeySync
+            | HostSwitch::BendRange => return None,
+            HostSwitch::Oversampling => EngineCommand::Oversampling(Oversampling::VALUES[position]),
+            HostSwitch::VelocityRoute => {
+                EngineCommand::VelocityRoute(VelocityRoute::VALUES[position])
+            }
+            HostSwitch::RumbleFilter => EngineCommand::RumbleFilter(on),
+            HostSwitch::Limiter => EngineCommand::Limiter(on),
+            HostSwitch::Chorus => EngineCommand::ChorusEnabled(on),
+            HostSwitch::Delay => EngineCommand::DelayEnabled(on),
+            HostSwitch::Reverb => EngineCommand::ReverbEnabled(on),
+        };
+        Some(command)
+    }
+}
+
+// The listed bend range nearest to `semitones`.
+fn bend_range_position(semitones: f32) -> usize {
+    (0..BEND_RANGES.len())
+        .min_by(|a, b| {
+            let distance = |index: &usize| (BEND_RANGES[*index] as f32 - semitones).abs();
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap_or(0)
+}
+
+/// What a plugin parameter ID stands for: a knob, or a panel switch stepped
+/// through its positions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostParam {
+    Knob(KnobId),
+    Switch(HostSwitch),
+}
+
+/// Every plugin parameter with its host ID, knobs first.
+pub fn host_params() -> impl Iterator<Item = (u32, HostParam)> {
+    let switches = HostSwitch::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, switch)| (SWITCH_PARAM_BASE + index as u32, HostParam::Switch(*switch)));
+    host_knobs()
+        .map(|(id, knob)| (id, HostParam::Knob(knob)))
+        .chain(switches)
+}
+
+pub fn host_param(param_id: u32) -> Option<HostParam> {
+    if let Some(knob) = host_knob(param_id) {
+        return Some(HostParam::Knob(knob));
+    }
+    let index = param_id.checked_sub(SWITCH_PARAM_BASE)?;
+    HostSwitch::ALL
+        .get(index as usize)
+        .map(|switch| HostParam::Switch(*switch))
+}
+
+pub fn format_knob(knob: KnobId, value: f32) -> String {
+    ParamSpec::of(knob).format(value)
+}
+
+/// Accepts a position's label ("LP", "on") or its number.
+pub fn parse_switch(switch: HostSwitch, text: &str) -> Option<usize> {
+    let text = text.trim();
+    (0..switch.positions())
+        .find(|position| switch.position_label(*position).eq_ignore_ascii_case(text))
+        .or_else(|| {
+            text.parse::<usize>()
+                .ok()
+                .filter(|position| *position < switch.positions())
+        })
+}
+
+/// Accepts either a normalized value or a percentage ("42%").
+pub fn parse_knob(text: &str) -> Option<f32> {
+    let text = text.trim();
+    let (number, scale) = match text.strip_suffix('%') {
+        Some(number) => (number, 0.01),
+        None => (text, 1.0),
+    };
+    let value = number.trim().parse::<f32>().ok()?;
+    Some((value * scale).clamp(0.0, 1.0))
+}
+
+pub struct Instrument {
+    pipeline: SynthPipeline,
+    params: SynthParams,
+    switches: [usize; SWITCH_COUNT],
+    macros: [MacroAssignment; MACRO_COUNT],
+    // Where each macro knob was when its targets were last turned.
+    macro_applied: [f32; MACRO_COUNT],
+    scratch: Vec<f32>,
+    held: Vec<u8>,
+    pitch_current: f32,
+    pitch_target: f32,
+    gate: bool,
+    sustain: bool,
+    // -1 to 1, scaled by the BEND RANGE switch.
+    pitch_bend: f32,
+    mod_wheel: f32,
+    sample_rate: f32,
+}
+
+impl Instrument {
+    pub fn new(
+        sample_rate: f32,
+        values: [f32; KNOB_COUNT],
+        switches: [usize; SWITCH_COUNT],
+        macros: [MacroAssignment; MACRO_COUNT],
+    ) -> Self {
+        let mut pipeline = SynthPipeline::new(
+            OscillatorBank::new(VCO_COUNT),
+            Mixer::new(),
+            Modifiers::new(),
+        );
+        pipeline.set_sample_rate(sample_rate);
+        let tune = values[KnobId::ControllersTune as usize];
+        let pitch = midi_to_voltage(48) + knob_to_tune(tune);
+        let mut instrument = Self {
+            pipeline,
+            params: SynthParams::from_values(values),
+            switches,
+            macros,
+            macro_applied: MACRO_KNOBS.map(|knob| values[knob as usize]),
+            scratch: vec![0.0; GLIDE_STEP_FRAMES * 2],
+            held: Vec::with_capacity(128),
+            pitch_current: pitch,
+            pitch_target: pitch,
+            gate: false,
+            sustain: false,
+            pitch_bend: 0.0,
+            mod_wheel: 1.0,
+            sample_rate,
+        };
+        instrument.apply_all();
+        instrument
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn value(&self, knob: KnobId) -> f32 {
+        self.params.get(knob)
+    }
+
+    pub fn set_knob(&mut self, knob: KnobId, value: f32) {
+        self.params.set(knob, value);
+        self.apply_knob(knob);
+    }
+
+    pub fn switch(&self, switch: HostSwitch) -> usize {
+        self.switches[switch as usize]
+    }
+
+    pub fn set_switch(&mut self, switch: HostSwitch, position: usize) {
+        self.switches[switch as usize] = position.min(switch.positions() - 1);
+        self.apply_switch(switch);
+    }
+
+    /// The knobs a macro knob turns; empty for any other knob. A wrapper
+    /// reads their new values back after moving the macro.
+    pub fn macro_targets(&self, knob: KnobId) -> &[MacroTarget] {
+        match MACRO_KNOBS
+            .iter()
+            .position(|macro_knob| *macro_knob == knob)
+        {
+            Some(index) => self.macros[index].targets(),
+            None => &[],
+        }
+    }
+
+    /// Loads a whole patch. Its macros count as already applied, so loading
+    /// doesn't turn their targets again.
+    pub fn load_values(
+        &mut self,
+        values: [f32; KNOB_COUNT],
+        switches: [usize; SWITCH_COUNT],
+        macros: [MacroAssignment; MACRO_COUNT],
+    ) {
+        self.params = SynthParams::from_values(values);
+        self.switches = switches;
+        self.macros = macros;
+        self.macro_applied = MACRO_KNOBS.map(|knob| values[knob as usize]);
+        self.apply_all();
+    }
+
+    fn apply_all(&mut self) {
+        for (_, knob) in host_knobs() {
+            self.apply_knob(knob);
+        }
+        for switch in HostSwitch::ALL {
+            self.apply_switch(switch);
+        }
+    }
+
+    fn apply_switch(&mut self, switch: HostSwitch) {
+        match switch {
+            HostSwitch::Osc3Lfo => {
+                self.send_voltages();
+                self.send_modulation();
+            }
+            HostSwitch::Osc3Control | HostSwitch::BendRange => self.send_voltages(),
+            HostSwitch::OscModulation
+            | HostSwitch::FilterModulation
+            | HostSwitch::ModSourceNoise
+            | HostSwitch::LfoKeySync => self.send_modulation(),
+            _ => {
+                if let Some(command) = switch.engine_command(self.switch(switch)) {
+                    self.pipeline.apply(command);
+                }
+            }
+        }
+    }
+
+    fn apply_knob(&mut self, knob: KnobId) {
+        match knob {
+            KnobId::ControllersTune => self.retarget(),
+            KnobId::OscRange1 | KnobId::OscRange2 | KnobId::OscRange3 => self.send_voltages(),
+            KnobId::Macro1 => self.apply_macro(0),
+            KnobId::Macro2 => self.apply_macro(1),
+            KnobId::Macro3 => self.apply_macro(2),
+            KnobId::Macro4 => self.apply_macro(3),
+            _ if MODULATION_KNOBS.contains(&knob) => self.send_modulation(),
+            _ => {
+                if let Some(command) = self.params.engine_command(knob) {
+                    self.pipeline.apply(command);
+                }
+            }
+        }
+    }
+
+    // Turns the macro's targets by however far it has moved since the last
+    // call, as the desktop panel does.
+    fn apply_macro(&mut self, index: usize) {
+        let value = self.value(MACRO_KNOBS[index]);
+        let delta = value - self.macro_applied[index];
+        self.macro_applied[index] = value;
+        if delta == 0.0 {
+            return;
+        }
+        let assignment = self.macros[index];
+        for target in assignment.targets() {
+            let value = (self.value(target.knob) + delta * target.depth).clamp(0.0, 1.0);
+            self.set_knob(target.knob, value);
+        }
+    }
+
+    fn send_modulation(&mut self) {
+        let on = |switch| self.switch(switch) > 0;
+        let routing = ModRouting {
+            pitch: on(HostSwitch::OscModulation),
+            filter: on(HostSwitch::FilterModulation),
+            noise: on(HostSwitch::ModSourceNoise),
+            osc3: on(HostSwitch::Osc3Lfo),
+            key_sync: on(HostSwitch::LfoKeySync),
+            // Hosts don't share their tempo with the instrument yet.
+            division: None,
+            wheel: self.mod_wheel,
+        };
+        self.pipeline
+            .apply(EngineCommand::Modulation(ModulationParams::new(
+                &self.params,
+                routing,
+            )));
+    }
+
+    fn retarget(&mut self) {
+        if let Some(key) = self.held.last() {
+            self.pitch_target =
+                midi_to_voltage(*key as i32) + knob_to_tune(self.value(KnobId::ControllersTune));
+        }
+    }
+
+    fn send_voltages(&mut self) {
+        let ranges = [KnobId::OscRange1, KnobId::OscRange2, KnobId::OscRange3];
+        let semitones = BEND_RANGES[self.switch(HostSwitch::BendRange)] as f32;
+        let bend = self.pitch_bend * semitones / 12.0;
+        for (index, range) in ranges.iter().enumerate() {
+            let offset = range_setting_from_value(self.value(*range)).octave_offset;
+            // OSC 3 drops into the LFO range, where the bend leaves it alone,
+            // or stops tracking the keyboard with OSC 3 CONTROL off.
+            let voltage = if index < 2 {
+                self.pitch_current + offset + bend
+            } else if self.switch(HostSwitch::Osc3Lfo) > 0 {
+                offset + OSC3_LFO_OFFSET
+            } else if self.switch(HostSwitch::Osc3Control) > 0 {
+                self.pitch_current + offset + bend
+            } else {
+                offset + bend
+            };
+            self.pipeline
+                .apply(EngineCommand::Vco(index, VcoCommand::SetVoltage(voltage)));
+        }
+    }
+
+    /// `velocity` runs 0 to 1; the VELOCITY switch picks what it scales.
+    pub fn note_on(&mut self, key: u8, velocity: f32) {
+        self.held.retain(|held| *held != key);
+        self.held.push(key);
+        self.retarget();
+        self.pipeline
+            .apply(EngineCommand::Velocity(velocity.clamp(0.0, 1.0)));
+        if self.gate {
+            return;
+        }
+        // LEGATO only glides between overlapping notes.
+        if self.switch(HostSwitch::Glide) == GLIDE_LEGATO {
+            self.pitch_current = self.pitch_target;
+            self.send_voltages();
+        }
+        self.gate = true;
+        self.pipeline.apply(EngineCommand::Gate(true));
+    }
+
+    /// With the sustain pedal down, the last note keeps sounding after its
+    /// key is let go.
+    pub fn note_off(&mut self, key: u8) {
+        self.held.retain(|held| *held != key);
+        if !self.held.is_empty() {
+            self.retarget();
+        } else if !self.sustain {
+            self.close_gate();
+        }
+    }
+
+    pub fn set_sustain(&mut self, down: bool) {
+        self.sustain = down;
+        if !down && self.held.is_empty() {
+            self.close_gate();
+        }
+    }
+
+    /// Forgets every held note and lifts the sustain pedal.
+    pub fn all_notes_off(&mut self) {
+        self.held.clear();
+        self.set_sustain(false);
+    }
+
+    /// -1 to 1, scaled by the BEND RANGE switch.
+    pub fn set_pitch_bend(&mut self, amount: f32) {
+        self.pitch_bend = amount.clamp(-1.0, 1.0);
+        self.send_voltages();
+    }
+
+    /// 0 to 1; scales MOD AMOUNT.
+    pub fn set_mod_wheel(&mut self, amount: f32) {
+        self.mod_wheel = amount.clamp(0.0, 1.0);
+        self.send_modulation();
+    }
+
+    pub fn handle_midi(&mut self, data: [u8; 3]) {
+        match MidiMessage::parse(&data) {
+            Some(MidiMessage::NoteOn { note, velocity }) => {
+                self.note_on(note, f32::from(velocity) / 127.0)
+            }
+            Some(MidiMessage::NoteOff { note }) => self.note_off(note),
+            Some(MidiMessage::ControlChange {
+                controller: CC_MOD_WHEEL,
+                value,
+            }) => self.set_mod_wheel(f32::from(value) / 127.0),
+            Some(MidiMessage::ControlChange {
+                controller: CC_SUSTAIN,
+                value,
+            }) => self.set_sustain(value >= 64),
+            Some(MidiMessage::ControlChange {
+                controller: CC_ALL_NOTES_OFF,
+                ..
+            }) => self.all_notes_off(),
+            Some(MidiMessage::PitchBend { value }) => self.set_pitch_bend(bend_amount(value)),
+            _ => {}
+        }
+    }
+
+    fn close_gate(&mut self) {
+        if self.gate {
+            self.gate = false;
+            self.pipeline.apply(EngineCommand::Gate(false));
+        }
+    }
+
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (left, right) in left
+            .chunks_mut(GLIDE_STEP_FRAMES)
+            .zip(right.chunks_mut(GLIDE_STEP_FRAMES))
+        {
+            self.step_glide(left.len());
+            let stereo = &mut self.scratch[..left.len() * 2];
+            self.pipeline.process(stereo);
+            for (frame, (l, r)) in stereo.chunks_exact(2).zip(left.iter_mut().zip(right)) {
+                *l = frame[0];
+                *r = frame[1];
+            }
+        }
+    }
+
+    fn step_glide(&mut self, frames: usize) {
+        if self.pitch_current == self.pitch_target {
+            return;
+        }
+        let glide = if self.switch(HostSwitch::Glide) == 0 {
+            0.0
+        } else {
+            knob_to_glide(self.value(KnobId::ControllersGlide))
+        };
+        let dt = frames as f32 / self.sample_rate;
+        if glide <= 0.0001 {
+            self.pitch_current = self.pitch_target;
+        } else {
+            let remaining = self.pitch_target - self.pitch_current;
+            self.pitch_current += remaining * (dt / glide).clamp(0.0, 1.0);
+            if (self.pitch_target - self.pitch_current).abs() < 1e-5 {
+                self.pitch_current = self.pitch_target;
+            }
+        }
+        self.send_voltages();
+    }
+}
+
+/// Plugin state is a whole `.mrp` preset: `base` is the preset last loaded,
+/// so keys the plugin has no parameter for (keyboard, macro assignments)
+/// come back out as they went in, with every knob and switch written over it.
+pub fn state_preset(
+    base: &Preset,
+    values: &[f32; KNOB_COUNT],
+    switches: &[usize; SWITCH_COUNT],
+) -> Preset {
+    let mut preset = base.clone();
+    for knob in KnobId::ALL {
+        preset.set(knob.preset_key(), values[knob as usize]);
+    }
+    for switch in HostSwitch::ALL {
+        preset.set(
+            switch.preset_key(),
+            switch.preset_value(switches[switch as usize]),
+        );
+    }
+    preset
+}
+
+pub fn load_state(
+    preset: &Preset,
+    values: &mut [f32; KNOB_COUNT],
+    switches: &mut [usize; SWITCH_COUNT],
+) {
+    for knob in KnobId::ALL {
+        if let Some(value) = preset.get(knob.preset_key()) {
+            values[knob as usize] = value.clamp(0.0, 1.0);
+        }
+    }
+    for switch in HostSwitch::ALL {
+        if let Some(value) = preset.get(switch.preset_key()) {
+            switches[switch as usize] = switch.preset_position(value);
+        }
+    }
+}
+
+pub fn load_macros(preset: &Preset) -> [MacroAssignment; MACRO_COUNT] {
+    std::array::from_fn(|index| MacroAssignment::load(preset, index))
+}
+
+pub fn default_values() -> [f32; KNOB_COUNT] {
+    *SynthParams::new().values()
+}
+
+pub fn default_switches() -> [usize; SWITCH_COUNT] {
+    HostSwitch::ALL.map(HostSwitch::default_position)
+}
@@ -0,0 +1,41 @@
+pub mod bench;
+pub mod clock;
+pub mod command;
+pub mod cvgate;
+#[cfg(not(target_arch = "wasm32"))]
+mod device;
+pub mod effects;
+pub mod filter;
+pub mod follower;
+pub mod gamepad;
+pub mod httpapi;
+pub mod instrument;
+pub mod macros;
+pub mod metronome;
+pub mod midi;
+pub mod mixer;
+pub mod modifiers;
+pub mod modulation;
+pub mod noise;
+pub mod oscillatorbank;
+pub mod oscserver;
+pub mod output;
+pub mod outputstage;
+pub mod oversample;
+pub mod params;
+pub mod patchsheet;
+pub mod preset;
+pub mod probe;
+pub mod recorder;
+pub mod resampler;
+pub mod rtaudit;
+pub mod scopeexport;
+pub mod smf;
+pub mod synthparams;
+pub mod testsignal;
+pub mod tuner;
+pub mod tuning;
+pub mod vco;
+pub mod wavetable;
+#[cfg(target_arch = "wasm32")]
+mod webaudio;
@@ -0,0 +1,66 @@
+use std::path::Path;
+
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::Context;
+use anyhow::Result;
+use tracing::{Subscriber, error, level_filters::LevelFilter};
+use tracing_subscriber::{Layer, Registry, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+
+const LOG_FILE_PREFIX: &str = "miniroog";
+
+// Only the newest week of daily files is kept.
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_LOG_FILES: usize = 7;
+
+type FileLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Console-only logging for the stretch before the config has been read.
+pub fn startup_logger() -> impl Subscriber + Send + Sync {
+    fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(LevelFilter::INFO)
+        .finish()
+}
+
+/// Installs the global logger: stderr always, plus a daily-rotated
+/// `miniroog.<date>.log` in `dir` when one is configured. A log directory
+/// that can't be opened is reported and skipped rather than fatal.
+pub fn init(level: LevelFilter, dir: Option<&Path>) {
+    let (file, problem) = match dir.map(file_layer) {
+        Some(Ok(layer)) => (Some(layer), None),
+        Some(Err(err)) => (None, Some(err)),
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(file)
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .with(level)
+        .init();
+    if let Some(err) = problem {
+        error!("Log file unavailable: {err:#}");
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn file_layer(dir: &Path) -> Result<FileLayer> {
+    use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+    std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix("log")
+        .max_log_files(MAX_LOG_FILES)
+        .build(dir)
+        .with_context(|| format!("opening log directory {}", dir.display()))?;
+    Ok(Box::new(
+        fmt::layer().with_ansi(false).with_writer(appender),
+    ))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn file_layer(_dir: &Path) -> Result<FileLayer> {
+    Err(anyhow::anyhow!(
+        "log files are not supported in the browser build"
+    ))
+}
@@ -0,0 +1,127 @@
+use crate::{params::KnobId, preset::Preset};
+
+pub const MACRO_COUNT: usize = 4;
+pub const MACRO_KNOBS: [KnobId; MACRO_COUNT] = [
+    KnobId::Macro1,
+    KnobId::Macro2,
+    KnobId::Macro3,
+    KnobId::Macro4,
+];
+pub const MAX_MACRO_TARGETS: usize = 6;
+pub const MACRO_DEPTH_STEP: f32 = 0.05;
+
+const DEFAULT_DEPTH: f32 = 0.5;
+
+/// One knob a macro turns. A full sweep of the macro moves it by `depth` of
+/// its travel; a negative depth turns it the other way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MacroTarget {
+    pub knob: KnobId,
+    pub depth: f32,
+}
+
+/// The knobs one macro drives. It holds its targets inline so a copy can be
+/// handed to the audio thread without allocating.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MacroAssignment {
+    targets: [MacroTarget; MAX_MACRO_TARGETS],
+    len: usize,
+}
+
+impl Default for MacroAssignment {
+    fn default() -> Self {
+        Self {
+            targets: [MacroTarget {
+                knob: KnobId::ControllersTune,
+                depth: 0.0,
+            }; MAX_MACRO_TARGETS],
+            len: 0,
+        }
+    }
+}
+
+impl MacroAssignment {
+    pub fn targets(&self) -> &[MacroTarget] {
+        &self.targets[..self.len]
+    }
+
+    /// Assigns `knob` at the default depth, or unassigns it if it already
+    /// is. Returns whether it is assigned afterwards; macros never drive
+    /// each other, and a full macro takes no more knobs.
+    pub fn toggle(&mut self, knob: KnobId) -> bool {
+        if self.position(knob).is_some() {
+            self.remove(knob);
+            return false;
+        }
+        if MACRO_KNOBS.contains(&knob) || self.len >= MAX_MACRO_TARGETS {
+            return false;
+        }
+        self.push(MacroTarget {
+            knob,
+            depth: DEFAULT_DEPTH,
+        });
+        true
+    }
+
+    pub fn remove(&mut self, knob: KnobId) {
+        if let Some(index) = self.position(knob) {
+            self.targets.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+        }
+    }
+
+    /// Moves a target's depth by `steps` of `MACRO_DEPTH_STEP`, through zero
+    /// into the opposite polarity.
+    pub fn nudge(&mut self, knob: KnobId, steps: f32) {
+        if let Some(index) = self.position(knob) {
+            let depth = self.targets[index].depth + steps * MACRO_DEPTH_STEP;
+            self.targets[index].depth = depth.clamp(-1.0, 1.0);
+        }
+    }
+
+    pub fn flip(&mut self, knob: KnobId) {
+        if let Some(index) = self.position(knob) {
+            self.targets[index].depth = -self.targets[index].depth;
+        }
+    }
+
+    /// One `macro<n>.<knob key> = depth` line per target.
+    pub fn save(&self, preset: &mut Preset, index: usize) {
+        for target in self.targets() {
+            preset.set(&target_key(index, target.knob), target.depth);
+        }
+    }
+
+    pub fn load(preset: &Preset, index: usize) -> Self {
+        let mut assignment = Self::default();
+        let targets = KnobId::ALL
+            .into_iter()
+            .filter(|knob| !MACRO_KNOBS.contains(knob))
+            .filter_map(|knob| {
+                preset
+                    .get(&target_key(index, knob))
+                    .map(|depth| MacroTarget {
+                        knob,
+                        depth: depth.clamp(-1.0, 1.0),
+                    })
+            })
+            .take(MAX_MACRO_TARGETS);
+        for target in targets {
+            assignment.push(target);
+        }
+        assignment
+    }
+
+    fn push(&mut self, target: MacroTarget) {
+        self.targets[self.len] = target;
+        self.len += 1;
+    }
+
+    fn position(&self, knob: KnobId) -> Option<usize> {
+        self.targets().iter().position(|target| target.knob == knob)
+    }
+}
+
+fn target_key(index: usize, knob: KnobId) -> String {
+    format!("macro{}.{}", index + 1, knob.preset_key())
+}
@@ -1,37 +1,120 @@
+mod config;
 mod controllers;
-mod mixer;
-mod modifiers;
-mod noise;
-mod oscillatorbank;
-mod output;
-mod vco;
-
-use std::sync::{Arc, Mutex};
+mod headless;
+mod logging;
+mod session;
+
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use controllers::KeyboardController;
+use anyhow::Context;
+use clap::Parser;
+use config::{CONFIG_FILE, Config, parse_log_level};
+use controllers::{
+    ControllerMessage, KeyLayout, KeySplit, KeyboardController, Scale, UpperZone, WHITE_KEY_SLOTS,
+};
 use macroquad::{prelude::*, text::measure_text};
-use modifiers::{compute_spectrum, knob_to_env_time};
-use noise::{NoiseColor, NoiseGenerator};
-use oscillatorbank::OscillatorBank;
-use output::{AudioEngine, DebugData, SharedPipeline, SynthPipeline};
-use tokio::runtime::Runtime;
-use vco::{VcoCommand, VcoHandle, Waveform, spawn_vco, voltage_to_frequency};
-
-const SCREEN_WIDTH: f32 = 1280.0;
-const SCREEN_HEIGHT: f32 = 720.0;
+use miniroog_model_r::{
+    bench,
+    clock::{BEATS_PER_BAR, ClockSource, SyncDivision, TempoClock},
+    command::{CommandSender, EngineCommand, PipelineJob},
+    filter::{FilterSlope, FilterType, magnitude_response},
+    gamepad::{GamepadAction, GamepadInput},
+    httpapi::{ApiCall, ApiReply, ApiRequest, ApiServer},
+    macros::{MACRO_COUNT, MACRO_KNOBS, MAX_MACRO_TARGETS, MacroAssignment},
+    midi::{
+        CC_ALL_NOTES_OFF, CC_MOD_WHEEL, CC_SUSTAIN, MidiInput, MidiMessage, MidiOutput, bend_amount,
+    },
+    mixer::Mixer,
+    modifiers::{
+        EnvStage, EnvelopeCurve, EnvelopeParams, EnvelopeShape, EnvelopeState, Modifiers,
+        SpectrumAnalyzer, VelocityRoute, contour_cutoff, knob_to_env_time,
+    },
+    modulation::{ModRouting, ModulationParams},
+    noise::NoiseColor,
+    oscillatorbank::{OscillatorBank, PhaseMode},
+    oscserver::{OscControl, OscServer},
+    output::{
+        AudioEngine, BufferRequest, DebugData, DeviceRequest, Glitch, MeterReading, SharedPipeline,
+        SynthPipeline,
+    },
+    oversample::Oversampling,
+    params::{
+        BEND_RANGES, DEFAULT_BEND_RANGE, FILTER_ATTACK_MAX, FILTER_ATTACK_MIN, FILTER_DECAY_MAX,
+        FILTER_DECAY_MIN, FILTER_MAX_HZ, FILTER_MIN_HZ, FILTER_MOD_MAX_DEPTH, KnobId,
+        LOUD_ATTACK_MAX, LOUD_ATTACK_MIN, LOUD_DECAY_MAX, LOUD_DECAY_MIN, OSC3_LFO_OFFSET,
+        PITCH_MOD_MAX_OCT, RangeSetting, TUNE_RANGE_OCT, knob_to_cutoff, knob_to_detune,
+        knob_to_env_seconds, knob_to_glide, knob_to_lfo_rate, knob_to_pan, knob_to_snapped_detune,
+        knob_to_tune, range_setting_from_value, value_to_waveform,
+    },
+    patchsheet::{self, PATCH_SHEET_EXTENSION},
+    preset::{PRESET_DIR, Preset, list_presets, preset_path},
+    probe::{ProbeBuffer, ProbePoint},
+    recorder::{RECORDING_DIR, Recording, write_bounce},
+    scopeexport::{EXPORT_DIR, export_scope},
+    smf::{MIDI_FILE_EXTENSIONS, MidiFile, MidiPlayer, Performance},
+    synthparams::{SynthParams, param_specs},
+    testsignal::TestSignal,
+    tuner::{Tuner, TunerReading},
+    tuning::{
+        DEFAULT_REFERENCE_HZ, KEYMAP_EXTENSION, KeyboardMap, MAX_REFERENCE_HZ, MIN_REFERENCE_HZ,
+        SCALA_EXTENSION, ScalaScale, Temperament, Tuning,
+    },
+    vco::{
+        OscEngine, VcoCommand, frequency_to_midi, note_name, pitch_class_name, voltage_to_frequency,
+    },
+};
+use session::{SESSION_FILE, Session};
+use tracing::{error, info, level_filters::LevelFilter, warn};
+
+pub(crate) const SCREEN_WIDTH: f32 = 1280.0;
+pub(crate) const SCREEN_HEIGHT: f32 = 720.0;
 const PANEL_HEIGHT: f32 = 360.0;
+const BOUNCE_SECONDS: f32 = 8.0;
+const VCO_COUNT: usize = 3;
+const AUDIO_DEVICE_POLL_SEC: f32 = 2.0;
+const AUDIO_RETRY_SEC: f32 = 3.0;
+const PANEL_TEXTURE: &str = "assets/synth-ui-style.png";
+const STARTUP_ERROR_COLUMNS: usize = 100;
+// Room on the status line before it runs into the debug button.
+const AUDIO_STATUS_CHARS: usize = 80;
+const NOISE_SEED_ENV: &str = "MINIROOG_SEED";
 const KEY_FONT_SIZE: u16 = 35;
+const DEBUG_CAPTURE_SAMPLES: usize = 4096;
+const SCOPE_SAMPLES: usize = 1024;
+const ANALYZER_FFT_SIZE: usize = 2048;
+// Load readouts: how fast the DSP peak falls back, how heavily UI frame
+// times are smoothed, and the load that turns the readout red.
+const LOAD_PEAK_FALL_PER_SEC: f32 = 0.2;
+const UI_FRAME_SMOOTHING: f32 = 0.1;
+const LOAD_WARNING: f32 = 0.8;
+const GLITCH_LOG_LINES: usize = 8;
 const MAX_ANALYZER_FREQ: f32 = 25_000.0;
+const LOG_ANALYZER_MIN_FREQ: f32 = 20.0;
+const LOG_ANALYZER_MAX_FREQ: f32 = 20_000.0;
+const WATERFALL_COLUMNS: u16 = 256;
+const WATERFALL_ROWS: u16 = 128;
+const WATERFALL_MIN_DB: f32 = -80.0;
+const WATERFALL_MAX_DB: f32 = 0.0;
+const RESPONSE_MIN_DB: f32 = -48.0;
+const RESPONSE_MAX_DB: f32 = 24.0;
 const MIN_ANALYZER_DB: f32 = -20.0;
 const MAX_ANALYZER_DB: f32 = 20.0;
-const TUNE_RANGE_OCT: f32 = 1.0;
-const GLIDE_MIN_SEC: f32 = 0.0;
-const GLIDE_MAX_SEC: f32 = 0.6;
-const MOD_DEPTH: f32 = 0.3;
 const CONTROLLER_KNOB_SPACING: f32 = 1.2;
-const OSC_MOD_DEPTH: f32 = 0.18;
-const LFO_RATE_MIN: f32 = 0.2;
-const LFO_RATE_MAX: f32 = 12.0;
+// Distance from the target, a cent in volts, below which the glide lamp goes out.
+const GLIDE_LAMP_VOLTS: f32 = 1.0 / 1200.0;
+// Vertical drag on a held key that reaches full bend.
+const BEND_DRAG_PIXELS: f32 = 80.0;
+// Velocity of a click at the very top of a key; the bottom edge is full.
+const MIN_CLICK_VELOCITY: f32 = 0.2;
+const KNOB_DRAG_PER_PIXEL: f32 = 0.005;
+const KNOB_FINE_DIVISOR: f32 = 10.0;
+const DOUBLE_CLICK_SEC: f64 = 0.3;
+/// How far the gamepad triggers move the cutoff either way.
+const EXPRESSION_CUTOFF_OCT: f32 = 3.0;
 
 const AMBER: Color = Color {
     r: 0.98,
@@ -51,161 +134,629 @@ const BACKGROUND: Color = Color {
     b: 0.02,
     a: 1.0,
 };
-const DETUNE_RANGE: f32 = 8.0;
-const FILTER_MIN_HZ: f32 = 200.0;
-const FILTER_MAX_HZ: f32 = 5_000.0;
-const FILTER_ATTACK_MIN: f32 = 0.0015;
-const FILTER_ATTACK_MAX: f32 = 3.0;
-const FILTER_DECAY_MIN: f32 = 0.005;
-const FILTER_DECAY_MAX: f32 = 4.0;
-const LOUD_ATTACK_MIN: f32 = 0.001;
-const LOUD_ATTACK_MAX: f32 = 4.5;
-const LOUD_DECAY_MIN: f32 = 0.01;
-const LOUD_DECAY_MAX: f32 = 6.0;
-#[derive(Clone, Copy)]
-struct RangeSetting {
-    label: &'static str,
-    octave_offset: f32,
+const PRESET_ROW_HEIGHT: f32 = 26.0;
+const PRESET_VISIBLE_ROWS: usize = 10;
+const PRESET_NAME_MAX: usize = 24;
+const OPTION_ROW_HEIGHT: f32 = 28.0;
+const OPTION_COLUMNS: usize = 2;
+const OPTION_SELECTOR_ROWS: usize = 15;
+const OPTION_KNOBS_PER_ROW: usize = 5;
+const OPTION_KNOB_ROW_HEIGHT: f32 = 100.0;
+// Where the wheel starts SPLIT AT from when there is no split yet.
+const SPLIT_DEFAULT_NOTE: i32 = 60;
+// Click level with PHONES fully up; the click is a full-scale sine burst.
+const CLICK_MAX_LEVEL: f32 = 0.5;
+const GAIN_REDUCTION_RANGE_DB: f32 = 12.0;
+const METER_FLOOR_DB: f32 = -48.0;
+const METER_FALL_SEC: f32 = 0.3;
+const METER_CLIP_HOLD_SEC: f32 = 1.0;
+// Knobs whose engine value also depends on panel switches or modulation
+// (FREQ snap, mixer on/off, cutoff expression, the Decay switch), so
+// sync_audio_from_panel sends them itself instead of through SynthParams.
+const PANEL_DRIVEN_KNOBS: [KnobId; 19] = [
+    KnobId::OscFreq1,
+    KnobId::OscFreq2,
+    KnobId::OscFreq3,
+    KnobId::MixerOsc1,
+    KnobId::MixerOsc2,
+    KnobId::MixerOsc3,
+    KnobId::FilterCutoff,
+    KnobId::FilterDelay,
+    KnobId::FilterAttack,
+    KnobId::FilterHold,
+    KnobId::FilterDecay,
+    KnobId::FilterSustain,
+    KnobId::FilterRelease,
+    KnobId::LoudnessDelay,
+    KnobId::LoudnessAttack,
+    KnobId::LoudnessHold,
+    KnobId::LoudnessDecay,
+    KnobId::LoudnessSustain,
+    KnobId::LoudnessRelease,
+];
+/// Minimoog Model D-style synthesizer. Flags override the config file for
+/// this run only.
+#[derive(Parser)]
+#[command(version)]
+struct LaunchOptions {
+    /// Run without a window, played over MIDI, OSC and HTTP.
+    #[arg(long)]
+    headless: bool,
+    /// Config file to read, created with defaults if missing.
+    #[arg(long = "config", value_name = "PATH")]
+    config_path: Option<PathBuf>,
+    /// Patch to load at startup (`.mrp` or `.chart`).
+    #[arg(long = "preset", value_name = "PATH")]
+    preset_path: Option<PathBuf>,
+    /// Output device, matched by name.
+    #[arg(long, value_name = "NAME")]
+    device: Option<String>,
+    /// Output sample rate to ask the device for.
+    #[arg(long, value_name = "HZ", value_parser = clap::value_parser!(u32).range(1..))]
+    sample_rate: Option<u32>,
+    /// 0 (device default), 64, 128, 256, 512 or 1024.
+    #[arg(long = "buffer-size", value_name = "FRAMES", value_parser = parse_buffer_size)]
+    buffer: Option<BufferRequest>,
+    /// Open the window fullscreen.
+    #[arg(long)]
+    fullscreen: bool,
+    /// UDP port for the OSC listener.
+    #[arg(long, value_name = "PORT")]
+    osc_port: Option<u16>,
+    /// TCP port for the JSON API on 127.0.0.1.
+    #[arg(long, value_name = "PORT")]
+    http_port: Option<u16>,
+    /// Browser origin allowed to call the HTTP API.
+    #[arg(long, value_name = "ORIGIN")]
+    http_origin: Option<String>,
+    /// off, error, warn, info, debug or trace.
+    #[arg(long, value_name = "LEVEL", value_parser = parse_level)]
+    log_level: Option<LevelFilter>,
+    /// Standard MIDI File to play on start.
+    #[arg(long, value_name = "PATH")]
+    midi_file: Option<PathBuf>,
+    /// MIDI output port, matched by name.
+    #[arg(long = "midi-out", value_name = "PORT")]
+    midi_output: Option<String>,
+    /// Render a fixed patch offline for this long and print timings.
+    #[arg(long = "bench", value_name = "SECONDS", value_parser = parse_bench_seconds)]
+    bench_seconds: Option<f32>,
 }
 
-const OSC1_WAVES: [Waveform; 6] = [
-    Waveform::Triangle,
-    Waveform::TriangleSaw,
-    Waveform::Saw,
-    Waveform::PulseSquare,
-    Waveform::PulseWide,
-    Waveform::PulseNarrow,
-];
+fn parse_buffer_size(frames: &str) -> Result<BufferRequest, String> {
+    frames
+        .parse()
+        .ok()
+        .and_then(BufferRequest::from_frames)
+        .ok_or_else(|| "use 0, 64, 128, 256, 512 or 1024".to_string())
+}
 
-const OSC2_WAVES: [Waveform; 6] = [
-    Waveform::Triangle,
-    Waveform::TriangleSaw,
-    Waveform::Saw,
-    Waveform::PulseSquare,
-    Waveform::PulseWide,
-    Waveform::PulseNarrow,
-];
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    parse_log_level("level", level).map_err(|_| "use off, error, warn, info, debug or trace".into())
+}
 
-const OSC3_WAVES: [Waveform; 6] = [
-    Waveform::Triangle,
-    Waveform::ReverseSaw,
-    Waveform::Saw,
-    Waveform::PulseSquare,
-    Waveform::PulseWide,
-    Waveform::PulseNarrow,
-];
+fn parse_bench_seconds(seconds: &str) -> Result<f32, String> {
+    match seconds.parse() {
+        Ok(seconds) if seconds > 0.0 => Ok(seconds),
+        _ => Err("expected a length in seconds".to_string()),
+    }
+}
 
-const OSC_RANGE_SETTINGS: [RangeSetting; 6] = [
-    RangeSetting {
-        label: "LO",
-        octave_offset: -5.0,
-    },
-    RangeSetting {
-        label: "32'",
-        octave_offset: -2.0,
-    },
-    RangeSetting {
-        label: "16'",
-        octave_offset: -1.0,
-    },
-    RangeSetting {
-        label: "8'",
-        octave_offset: 0.0,
-    },
-    RangeSetting {
-        label: "4'",
-        octave_offset: 1.0,
-    },
-    RangeSetting {
-        label: "2'",
-        octave_offset: 2.0,
-    },
-];
+fn main() {
+    let mut options = LaunchOptions::parse();
+    // The bench ignores the config and devices, so its numbers only move
+    // when the engine does.
+    if let Some(seconds) = options.bench_seconds {
+        let rate = options.sample_rate.unwrap_or(bench::BENCH_SAMPLE_RATE);
+        print!("{}", bench::run(seconds, rate));
+        return;
+    }
+    let config_path = options
+        .config_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE));
+    // The log level and file come from the config, so reading it is
+    // reported through a plain console logger.
+    let mut config = tracing::subscriber::with_default(logging::startup_logger(), || {
+        Config::load_or_create(&config_path)
+    });
+    apply_overrides(&mut options, &mut config);
+    logging::init(config.log_level, config.log_dir.as_deref());
+    if options.headless {
+        if let Err(err) = headless::run(&options, &config) {
+            error!("Headless mode failed: {err:#}");
+            std::process::exit(1);
+        }
+    } else {
+        macroquad::Window::from_config(window_conf(&config), run_ui(options, config));
+    }
+}
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let runtime = Runtime::new().expect("tokio runtime");
-    let vcos: Vec<VcoHandle> = (0..3).map(|_| spawn_vco(&runtime)).collect();
-
-    let states = vcos.iter().map(|(state, _)| state.clone()).collect();
-    let bank = OscillatorBank::new(states);
-    let mixer = mixer::Mixer::new();
-    let modifiers = modifiers::Modifiers::new();
-    let pipeline = Arc::new(Mutex::new(SynthPipeline::new(bank, mixer, modifiers)));
-    let debug_data = Arc::new(Mutex::new(DebugData::new(1024)));
-    let _audio =
-        AudioEngine::start(pipeline.clone(), debug_data.clone()).expect("audio output stream");
-
-    let mut controller = KeyboardController::new();
-    let mut panel_state = PanelState::new();
-    let mut knob_drag = KnobDragState::default();
-    let mut debug_window = DebugWindowState::new();
-    sync_audio_from_panel(&panel_state, &vcos, &pipeline);
-    panel_state.refresh_pitch_target();
-    panel_state.apply_pitch(0.0, &vcos);
+// Flags given on the command line win over the config file. The default
+// preset is left to each mode, since the window prefers the saved session.
+fn apply_overrides(options: &mut LaunchOptions, config: &mut Config) {
+    if let Some(name) = options.device.take() {
+        config.device.name = Some(name);
+    }
+    if let Some(rate) = options.sample_rate {
+        config.device.sample_rate = Some(rate);
+    }
+    if let Some(buffer) = options.buffer {
+        config.buffer = buffer;
+    }
+    if let Some(level) = options.log_level {
+        config.log_level = level;
+    }
+    if let Some(name) = options.midi_output.take() {
+        config.midi_output = Some(name);
+    }
+    config.fullscreen |= options.fullscreen;
+}
+
+fn build_pipeline(config: &Config) -> (SharedPipeline, DebugData) {
+    let bank = OscillatorBank::new(VCO_COUNT);
+    let mixer = Mixer::new();
+    let modifiers = Modifiers::new();
+    let mut synth = match noise_seed_override() {
+        Some(seed) => {
+            log_mode("Noise seed", &seed.to_string());
+            SynthPipeline::with_seed(bank, mixer, modifiers, seed)
+        }
+        None => SynthPipeline::new(bank, mixer, modifiers),
+    };
+    if let Some(cv_gate) = config.cv_gate {
+        synth.set_cv_gate(Some(cv_gate));
+        log_mode(
+            "CV/gate out",
+            &format!(
+                "pitch on channel {}, gate on {}",
+                cv_gate.pitch_channel, cv_gate.gate_channel
+            ),
+        );
+    }
+    (
+        SharedPipeline::new(synth),
+        DebugData::new(DEBUG_CAPTURE_SAMPLES),
+    )
+}
+
+fn load_startup_preset(panel_state: &mut PanelState, path: &Path) -> anyhow::Result<()> {
+    let preset = Preset::load(path)?;
+    panel_state.load_patch(&preset);
+    log_mode("Preset", &preset.name);
+    Ok(())
+}
+
+fn save_session(
+    panel_state: &PanelState,
+    controller: &KeyboardController,
+    debug_window: &DebugWindowState,
+) {
+    let session = Session {
+        patch: panel_state.to_preset("Session"),
+        debug_open: debug_window.open,
+        debug_position: debug_window.rect.point(),
+        octave_shift: controller.octave_shift(),
+        tempo: panel_state.clock.bpm(),
+        clock_source: panel_state.clock.source(),
+        metronome: panel_state.metronome,
+        temperament: panel_state.temperament,
+        reference_pitch: panel_state.reference_pitch,
+        tune_calibration: panel_state.tune_calibration * 1200.0,
+        key_layout: panel_state.key_layout,
+        white_keys: controller
+            .white_keys()
+            .iter()
+            .map(|key| key.keycode)
+            .collect(),
+        black_keys: controller
+            .black_keys()
+            .iter()
+            .map(|key| key.keycode)
+            .collect(),
+    };
+    if let Err(err) = session.save(Path::new(SESSION_FILE)) {
+        error!("Session save failed: {err:#}");
+    }
+}
+
+fn open_api_server(port: Option<u16>, origin: Option<&str>) -> Option<ApiServer> {
+    match ApiServer::bind(port?, origin.map(str::to_string)) {
+        Ok(server) => {
+            log_mode("HTTP API listening on", server.address());
+            Some(server)
+        }
+        Err(err) => {
+            warn!("HTTP API unavailable: {err}");
+            None
+        }
+    }
+}
 
-    let panel_texture = load_texture("assets/synth-ui-style.png")
+fn open_gamepad(config: &Config) -> Option<GamepadInput> {
+    if !config.gamepad {
+        return None;
+    }
+    match GamepadInput::open(config.gamepad_device.as_deref()) {
+        Ok(pad) => {
+            log_mode("Gamepad", pad.name());
+            Some(pad)
+        }
+        Err(err) => {
+            warn!("Gamepad unavailable: {err:#}");
+            None
+        }
+    }
+}
+
+fn open_osc_server(port: Option<u16>) -> Option<OscServer> {
+    match OscServer::bind(port?) {
+        Ok(server) => {
+            log_mode("OSC listening on", server.address());
+            Some(server)
+        }
+        Err(err) => {
+            warn!("OSC server unavailable: {err}");
+            None
+        }
+    }
+}
+
+async fn run_ui(options: LaunchOptions, config: Config) {
+    if let Err(err) = run_panel(options, config).await {
+        error!("Startup failed: {err:#}");
+        show_startup_error(&err).await;
+    }
+}
+
+// Everything the window can't run without is loaded here, before the audio
+// stream opens, so a broken install fails before it makes a sound.
+async fn run_panel(options: LaunchOptions, config: Config) -> anyhow::Result<()> {
+    let panel_texture = load_texture(PANEL_TEXTURE)
         .await
-        .expect("synth texture");
+        .with_context(|| format!("loading the panel artwork {PANEL_TEXTURE}"))?;
     panel_texture.set_filter(FilterMode::Linear);
 
-    let mut waveform_cache = Vec::new();
-    let mut spectrum_cache = Vec::new();
-
-    if let Ok(synth) = pipeline.lock() {
-        debug_window.set_sample_rate(synth.sample_rate());
+    let (pipeline, mut debug_data) = build_pipeline(&config);
+    let mut controller = KeyboardController::with_keys(&config.white_keys, &config.black_keys);
+    controller.set_base_layout(config.key_layout);
+    let mut panel_state = PanelState::new();
+    panel_state.buffer_request = config.buffer;
+    panel_state.key_layout = config.key_layout;
+    panel_state.scala_tuning = load_config_tuning(&config);
+    panel_state.retune();
+    let mut debug_window = DebugWindowState::new();
+    let session = match Session::load(Path::new(SESSION_FILE)) {
+        Some(Ok(session)) => Some(session),
+        Some(Err(err)) => {
+            warn!("Session restore failed: {err:#}");
+            None
+        }
+        None => None,
+    };
+    if let Some(session) = &session {
+        panel_state.load_patch(&session.patch);
+        controller.set_octave_shift(session.octave_shift);
+        panel_state.clock.set_bpm(session.tempo);
+        panel_state.clock.set_source(session.clock_source);
+        panel_state.metronome = session.metronome;
+        panel_state.temperament = session.temperament;
+        panel_state.set_reference_pitch(session.reference_pitch);
+        panel_state.set_tune_calibration(session.tune_calibration / 1200.0);
+        panel_state.key_layout = session.key_layout;
+        controller.set_base_layout(session.key_layout);
+        if !session.white_keys.is_empty() || !session.black_keys.is_empty() {
+            controller.set_keys(&session.white_keys, &session.black_keys);
+        }
+        debug_window.open = session.debug_open;
+        debug_window.move_to(session.debug_position);
+        log_mode("Session", SESSION_FILE);
+    }
+    // An explicit --preset beats the session; the config default only
+    // fills in when there is no session to restore.
+    let startup_preset = match (&options.preset_path, &session) {
+        (Some(path), _) => Some(path),
+        (None, None) => config.default_preset.as_ref(),
+        (None, Some(_)) => None,
+    };
+    if let Some(path) = startup_preset
+        && let Err(err) = load_startup_preset(&mut panel_state, path)
+    {
+        error!("Preset load failed: {err:#}");
     }
+    let (mut audio, mut commands) = AudioLink::start(
+        &pipeline,
+        &mut debug_data,
+        &panel_state,
+        config.device.clone(),
+    );
+    let mut knob_drag = KnobDragState::default();
+    let mut preset_browser = PresetBrowserState::new();
+    let mut options_window = OptionsWindowState::new();
+    let mut macro_window = MacroWindowState::new();
+    let mut envelope_editor = EnvelopeEditorState::new();
+    let mut follower_window = FollowerWindowState::new();
+    let mut chorus_window = ChorusWindowState::new();
+    let mut delay_window = DelayWindowState::new();
+    let mut reverb_window = ReverbWindowState::new();
+    let mut recording: Option<Recording> = None;
+    // Notes played during a REC take, saved beside its WAV.
+    let mut performance: Option<Performance> = None;
+    let mut played_note: Option<u8> = None;
+    let midi_input = match MidiInput::open(config.midi_port.as_deref()) {
+        Ok(input) => {
+            log_mode("MIDI input", input.port_name());
+            Some(input)
+        }
+        Err(err) => {
+            warn!("MIDI input unavailable: {err}");
+            None
+        }
+    };
+    let mut midi_output = open_midi_output(&config);
+    let mut gamepad = open_gamepad(&config);
+    let osc_server = open_osc_server(options.osc_port);
+    let api_server = open_api_server(options.http_port, options.http_origin.as_deref());
+    let mut midi_player = options.midi_file.as_deref().and_then(open_midi_file);
+    sync_audio_from_panel(&mut panel_state, &mut commands);
+    panel_state.refresh_pitch_target();
+    panel_state.apply_pitch(0.0);
 
+    let mut waveform_cache = Vec::new();
+    let mut analyzer = SpectrumAnalyzer::new(ANALYZER_FFT_SIZE);
+    let mut waterfall = Waterfall::new();
+
+    // Only set while the scope looks somewhere other than the output.
+    let mut scope_probe: Option<ProbeBuffer> = None;
+    let (mut tuner, probe_tap) = Tuner::new();
+    debug_window.set_sample_rate(pipeline.sample_rate());
+    commands.send_job(PipelineJob::ProbeTap(Some((
+        debug_window.probe_point,
+        probe_tap,
+    ))));
+
+    prevent_quit();
     loop {
+        if is_quit_requested() {
+            save_session(&panel_state, &controller, &debug_window);
+            break;
+        }
         let dt = get_frame_time();
+        let frame_started = get_time();
+        set_camera(&panel_camera());
         let layout = compute_panel_layout();
         let keyboard_layout = build_keyboard_layout(&controller);
         let mouse_pos = mouse_position_vec();
-        let mouse_changed = controller.handle_mouse_keys(
-            keyboard_layout.hit_test(mouse_pos),
-            is_mouse_button_pressed(MouseButton::Left),
-            is_mouse_button_down(MouseButton::Left),
-            is_mouse_button_released(MouseButton::Left),
+        let ui_blocked = preset_browser.captures(mouse_pos)
+            || options_window.captures(mouse_pos)
+            || macro_window.captures(mouse_pos)
+            || envelope_editor.captures(mouse_pos)
+            || follower_window.captures(mouse_pos)
+            || chorus_window.captures(mouse_pos)
+            || delay_window.captures(mouse_pos)
+            || reverb_window.captures(mouse_pos)
+            || debug_window.captures(mouse_pos);
+        knob_drag.input_blocked = ui_blocked;
+        // A key held with the mouse keeps its row, so dragging up and down
+        // bends instead of jumping between the black and white keys.
+        let hovered_key = match controller.mouse_key() {
+            _ if ui_blocked => None,
+            Some(held) => keyboard_layout.hit_test_row(mouse_pos, held),
+            None => keyboard_layout.hit_test(mouse_pos),
+        };
+        // Touches also arrive as emulated mouse events, so the mouse path is
+        // skipped while a finger is down to keep each key held only once.
+        let touches = touches();
+        let touched_keys: Vec<KeyCode> = touches
+            .iter()
+            .filter(|touch| !matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled))
+            .filter(|_| !ui_blocked)
+            .filter_map(|touch| keyboard_layout.hit_test(screen_to_panel(touch.position)))
+            .collect();
+        let mut keyboard_changed = controller.handle_touch_keys(&touched_keys);
+        if let Some(point) = controller.take_learned_split() {
+            panel_state.split.point = Some(point);
+            panel_state.split_learning = false;
+            log_mode("Split at", &note_name(point));
+        }
+        controller.learn_split(panel_state.split_learning);
+        keyboard_changed |= controller.set_split(panel_state.split);
+        keyboard_changed |= controller.set_scale(panel_state.scale, panel_state.scale_root);
+        keyboard_changed |= controller.set_tuning(&panel_state.tuning);
+        keyboard_changed |= controller.set_layout(panel_state.key_layout);
+        // Right-click a key to bind it to the next computer key pressed.
+        if !ui_blocked
+            && is_mouse_button_pressed(MouseButton::Right)
+            && let Some(key) = keyboard_layout.hit_test(mouse_pos)
+        {
+            controller.learn_key(key);
+            log_mode(
+                "Press a key to bind, Esc cancels",
+                controllers::key_name(key),
+            );
+        }
+        if touches.is_empty() {
+            keyboard_changed |= handle_octave_buttons(&mut controller, mouse_pos, ui_blocked);
+            let velocity = keyboard_layout.click_velocity(mouse_pos);
+            keyboard_changed |= controller.handle_mouse_keys(
+                hovered_key,
+                velocity,
+                is_mouse_button_pressed(MouseButton::Left),
+                is_mouse_button_down(MouseButton::Left),
+                is_mouse_button_released(MouseButton::Left),
+            );
+        }
+        let typing = preset_browser.is_typing();
+        keyboard_changed |= handle_panic(
+            &mut panel_state,
+            &mut controller,
+            mouse_pos,
+            ui_blocked,
+            typing,
         );
-        if is_key_pressed(KeyCode::Tab) {
+        if !typing && is_key_pressed(KeyCode::Tab) {
             panel_state.cycle_noise_color();
+            log_mode("Noise generator", panel_state.noise_color().label());
+        }
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if !typing && ctrl && is_key_pressed(KeyCode::T) {
+            if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+                panel_state.set_tune_calibration(0.0);
+            } else {
+                panel_state.calibrate_tune();
+            }
             log_mode(
-                "Noise generator",
-                panel_state.mixer_panel.noise_color.label(),
+                "Tune calibration",
+                &format!("{:+.1} cents", panel_state.tune_calibration * 1200.0),
             );
         }
-        if let Some(message) = controller.poll(mouse_changed) {
-            panel_state.last_midi = message.midi_note;
-            panel_state.last_voltage = message.voltage;
-            if let Ok(mut synth) = pipeline.lock() {
-                synth.set_gate(message.gate);
+        if let Some(input) = &midi_input {
+            for message in input.drain() {
+                keyboard_changed |= handle_midi_message(&mut panel_state, &mut controller, message);
+            }
+        }
+        if let Some(pad) = &mut gamepad {
+            for action in pad.drain() {
+                keyboard_changed |=
+                    handle_gamepad_action(&mut panel_state, &mut controller, action);
+            }
+        }
+        // File playback goes through the same path as a MIDI keyboard.
+        let dropped = get_dropped_files();
+        handle_dropped_tuning(&mut panel_state, &dropped);
+        handle_dropped_patch_sheet(&mut panel_state, &mut preset_browser, &dropped);
+        let mut file_messages = handle_dropped_midi_file(&mut midi_player, &dropped);
+        if !ui_blocked {
+            file_messages.extend(handle_midi_file_button(&mut midi_player, mouse_pos));
+        }
+        if let Some(player) = &mut midi_player {
+            file_messages.extend(player.advance(dt as f64));
+        }
+        for message in file_messages {
+            keyboard_changed |= handle_midi_message(&mut panel_state, &mut controller, message);
+        }
+        if let Some(server) = &osc_server {
+            for control in server.drain() {
+                handle_osc_control(&mut panel_state, control);
+            }
+        }
+        if let Some(server) = &api_server {
+            for call in server.drain() {
+                handle_api_call(&mut panel_state, call);
+            }
+        }
+        handle_mod_wheel(&mut panel_state, mouse_pos, ui_blocked);
+        handle_key_bend(&mut panel_state, &controller, mouse_pos);
+        if let Some(message) = controller.poll(keyboard_changed, !typing) {
+            let retrigger = panel_state.note_event(&message);
+            commands.send(EngineCommand::Velocity(message.velocity));
+            commands.send(EngineCommand::Gate(message.gate));
+            if retrigger {
+                commands.send(EngineCommand::Retrigger);
+            }
+            played_note = message.gate.then_some(message.midi_note as u8);
+            if let Some(output) = &mut midi_output {
+                let note = played_note.filter(|_| message.local);
+                if let Err(err) = output.play(note, message.velocity) {
+                    warn!("{err:#}");
+                }
+            }
+            if let Some(take) = &mut performance {
+                take.set_note(get_time(), played_note);
             }
         }
 
         handle_debug_toggle(&mut debug_window, mouse_pos);
-        handle_mixer_switches(&mut panel_state, &layout);
-        handle_controller_switches(&mut panel_state, &layout);
+        handle_analyzer_buttons(&mut debug_window, mouse_pos);
+        commands.send_changed(EngineCommand::TestSignal(debug_window.active_test_signal()));
+        handle_tuner_source(&mut debug_window, &mut tuner, &mut commands, mouse_pos);
+        handle_scope_source(
+            &mut debug_window,
+            &mut scope_probe,
+            &mut commands,
+            mouse_pos,
+        );
+        handle_scope_export(
+            &debug_window,
+            mouse_pos,
+            &waveform_cache,
+            analyzer.spectrum(),
+        );
+        handle_preset_browser(&mut preset_browser, &mut panel_state, mouse_pos);
+        handle_options_window(&mut options_window, &mut panel_state, mouse_pos);
+        handle_macro_window(&mut macro_window, &mut panel_state, mouse_pos);
+        handle_envelope_editor(&mut envelope_editor, &layout, mouse_pos, ui_blocked);
+        handle_follower_window(&mut follower_window, &layout, mouse_pos, ui_blocked);
+        handle_chorus_window(&mut chorus_window, &layout, mouse_pos, ui_blocked);
+        handle_delay_window(
+            &mut delay_window,
+            &mut panel_state,
+            &layout,
+            mouse_pos,
+            ui_blocked,
+        );
+        handle_reverb_window(&mut reverb_window, &layout, mouse_pos, ui_blocked);
+        if !ui_blocked {
+            handle_record_button(
+                &mut recording,
+                &mut performance,
+                played_note,
+                &pipeline,
+                &mut commands,
+                mouse_pos,
+            );
+            handle_oscillator_switches(&mut panel_state, &layout);
+            handle_mixer_switches(&mut panel_state, &layout);
+            handle_controller_switches(&mut panel_state, &layout);
+            handle_modifier_switches(&mut panel_state, &layout);
+            handle_output_switches(&mut panel_state, &layout);
+        }
         if panel_state.take_s_trigger() {
-            if let Ok(mut synth) = pipeline.lock() {
-                synth.trigger_envelopes();
-            }
+            commands.send(EngineCommand::TriggerEnvelopes);
         }
+        debug_data.drain();
+        panel_state.envelope_states = debug_data.envelopes();
         panel_state.refresh_pitch_target();
-        panel_state.update_modulation(dt);
-        panel_state.apply_pitch(dt, &vcos);
+        panel_state.clock.advance(dt);
+        sync_click(&mut panel_state, &mut commands);
+        panel_state.apply_pitch(dt);
 
         {
-            let (snapshot, overload_flag) = {
-                let mut guard = debug_data.lock().expect("debug lock");
-                let data = guard.snapshot();
-                let overload = guard.take_overload();
-                (data, overload)
+            let overload_flag = debug_data.take_overload();
+            let gain_reduction = debug_data.take_gain_reduction();
+            let meters = debug_data.take_meters();
+            (debug_window.block_frames, debug_window.latency) = debug_data.latency();
+            debug_window.record_dsp_load(debug_data.take_callback_load(), dt);
+            for glitch in debug_data.take_glitches() {
+                debug_window.record_glitch(glitch);
+            }
+            let snapshot = match &mut scope_probe {
+                Some(probe) => {
+                    probe.drain();
+                    probe.snapshot()
+                }
+                None => debug_data.snapshot(),
             };
             if !snapshot.is_empty() {
                 waveform_cache = snapshot;
-                spectrum_cache = compute_spectrum(&waveform_cache);
+                analyzer.update(&waveform_cache, dt);
+                if debug_window.open && debug_window.spectrum_view == SpectrumView::Waterfall {
+                    waterfall.push(
+                        analyzer.spectrum(),
+                        debug_window.sample_rate,
+                        debug_window.frequency_axis,
+                    );
+                }
             }
+            tuner.drain();
+            debug_window.tuner_reading = if debug_window.open {
+                tuner.detect(debug_window.sample_rate, &panel_state.tuning)
+            } else {
+                None
+            };
             panel_state.set_overload(overload_flag);
+            panel_state.gain_reduction_db = gain_reduction;
+            panel_state.update_meters(&meters, dt);
         }
 
         draw_scene(
@@ -216,24 +767,119 @@ async fn main() {
             &layout,
             &keyboard_layout,
             &waveform_cache,
-            &spectrum_cache,
+            &analyzer,
+            &waterfall,
             &debug_window,
+            &preset_browser,
+            &options_window,
+            &macro_window,
+            &envelope_editor,
+            &follower_window,
+            &chorus_window,
+            &delay_window,
+            &reverb_window,
+            recording.as_ref(),
+            midi_player.as_ref(),
+            &audio,
+        );
+        if let Some(knob) = knob_drag.pressed.take() {
+            handle_macro_learn(&macro_window, &mut panel_state, &mut knob_drag, knob);
+        }
+        panel_state.apply_macros();
+        if let Some(output) = &mut midi_output {
+            send_knob_ccs(&mut panel_state, output);
+        }
+
+        audio.update(dt, &pipeline, &mut debug_data, &panel_state, &mut commands);
+        follow_sample_rate(
+            &pipeline,
+            &mut debug_window,
+            &mut tuner,
+            &mut recording,
+            &mut performance,
+            played_note,
+            &mut commands,
         );
+        sync_audio_from_panel(&mut panel_state, &mut commands);
 
-        sync_audio_from_panel(&panel_state, &vcos, &pipeline);
-        feed_stub_knobs(&panel_state);
+        debug_window.record_ui_frame((get_time() - frame_started) as f32, dt);
+        next_frame().await;
+    }
+    Ok(())
+}
 
+// Shown in place of the panel when it can't start, until the window is
+// closed or Esc is pressed.
+async fn show_startup_error(err: &anyhow::Error) {
+    let causes: Vec<String> = err.chain().map(ToString::to_string).collect();
+    let text = |line: &str, y: f32, size: u16, color: Color| {
+        draw_text_ex(
+            line,
+            40.0,
+            y,
+            TextParams {
+                font_size: size,
+                color,
+                ..Default::default()
+            },
+        );
+    };
+    loop {
+        if is_key_pressed(KeyCode::Escape) || is_quit_requested() {
+            break;
+        }
+        set_camera(&panel_camera());
+        clear_background(BACKGROUND);
+        text(
+            "MINIROOG MODEL R COULD NOT START",
+            80.0,
+            32,
+            Color::new(0.9, 0.15, 0.1, 1.0),
+        );
+        let mut y = 130.0;
+        for (depth, cause) in causes.iter().enumerate() {
+            let prefix = if depth == 0 { "" } else { "caused by: " };
+            for line in wrap_text(&format!("{prefix}{cause}"), STARTUP_ERROR_COLUMNS) {
+                text(&line, y, 20, AMBER);
+                y += 26.0;
+            }
+            y += 8.0;
+        }
+        text(
+            "Check the install and the log, then restart. Esc closes this window.",
+            SCREEN_HEIGHT - 40.0,
+            18,
+            AMBER_DIM,
+        );
         next_frame().await;
     }
 }
 
-fn window_conf() -> Conf {
+fn wrap_text(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > columns {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn window_conf(config: &Config) -> Conf {
     Conf {
         window_title: "MiniRoog Model R".into(),
-        fullscreen: false,
+        fullscreen: config.fullscreen,
         sample_count: 1,
-        window_width: SCREEN_WIDTH as i32,
-        window_height: SCREEN_HEIGHT as i32,
+        window_width: config.window_width,
+        window_height: config.window_height,
         high_dpi: false,
         ..Default::default()
     }
@@ -260,15 +906,32 @@ struct PanelLayout {
     osc_range_knobs: [Rect; 3],
     osc_freq_knobs: [Rect; 3],
     osc_wave_knobs: [Rect; 3],
+    freq_snap_rect: Rect,
     mixer_osc_knobs: [Rect; 3],
     mixer_extra_knobs: [Rect; 2],
     mixer_toggle_rects: [Rect; 5],
     noise_selector_rect: Rect,
     overload_rect: Rect,
+    ext_label_rect: Rect,
     filter_knobs: [Rect; 3],
     filter_env_knobs: [Rect; 3],
     loudness_knobs: [Rect; 3],
     output_knobs: [Rect; 2],
+    chorus_switch_rect: Rect,
+    chorus_label_rect: Rect,
+    delay_switch_rect: Rect,
+    delay_label_rect: Rect,
+    reverb_switch_rect: Rect,
+    reverb_label_rect: Rect,
+    gain_reduction_rect: Rect,
+    output_meter_rects: [Rect; 2],
+    filter_type_rect: Rect,
+    filter_slope_rect: Rect,
+    filter_curve_rect: Rect,
+    filter_loop_rect: Rect,
+    filter_title_rect: Rect,
+    loudness_title_rect: Rect,
+    loudness_curve_rect: Rect,
 }
 
 fn compute_panel_layout() -> PanelLayout {
@@ -413,11 +1076,16 @@ fn compute_panel_layout() -> PanelLayout {
             knob_size,
         );
     }
+    let freq_snap_rect = Rect::new(
+        oscillator_rect.x + oscillator_rect.w - 96.0,
+        oscillator_rect.y - 26.0,
+        96.0,
+        22.0,
+    );
 
     let mut mixer_osc_knobs = [Rect::new(0.0, 0.0, 0.0, 0.0); 3];
     let mut mixer_extra_knobs = [Rect::new(0.0, 0.0, 0.0, 0.0); 2];
     let mut mixer_toggle_rects = [Rect::new(0.0, 0.0, 0.0, 0.0); 5];
-    let noise_selector_rect;
     let row_spacing = knob_size + 25.0;
     let osc_x = mixer_rect.x + 20.0;
     let extra_x = mixer_rect.x + mixer_rect.w * 0.55;
@@ -432,9 +1100,9 @@ fn compute_panel_layout() -> PanelLayout {
             toggle_size.y,
         );
     }
-    for index in 0..2 {
+    for (index, knob) in mixer_extra_knobs.iter_mut().enumerate() {
         let y = mixer_rect.y + 20.0 + index as f32 * row_spacing;
-        mixer_extra_knobs[index] = Rect::new(extra_x, y, knob_size, knob_size);
+        *knob = Rect::new(extra_x, y, knob_size, knob_size);
         let toggle_index = 3 + index;
         mixer_toggle_rects[toggle_index] = Rect::new(
             extra_x + knob_size + 14.0,
@@ -446,7 +1114,8 @@ fn compute_panel_layout() -> PanelLayout {
     let noise_button = vec2(100.0, 32.0);
     let noise_start_x = mixer_rect.x + mixer_rect.w * 0.58;
     let noise_start_y = mixer_extra_knobs[1].y + knob_size + 36.0;
-    noise_selector_rect = Rect::new(noise_start_x, noise_start_y, noise_button.x, noise_button.y);
+    let noise_selector_rect =
+        Rect::new(noise_start_x, noise_start_y, noise_button.x, noise_button.y);
     let overload_rect = Rect::new(
         mixer_extra_knobs[0].x + knob_size * 0.5 - 12.0,
         mixer_rect.y + 2.0,
@@ -454,23 +1123,77 @@ fn compute_panel_layout() -> PanelLayout {
         24.0,
     );
 
+    // The EXT INPUT label under its knob opens the envelope follower.
+    let ext_label_rect = Rect::new(
+        mixer_extra_knobs[0].x,
+        mixer_extra_knobs[0].y + knob_size + 4.0,
+        knob_size,
+        18.0,
+    );
+
     let mut filter_knobs = [Rect::new(0.0, 0.0, 0.0, 0.0); 3];
     let mut filter_env_knobs = [Rect::new(0.0, 0.0, 0.0, 0.0); 3];
     let mut loudness_knobs = [Rect::new(0.0, 0.0, 0.0, 0.0); 3];
     let column_spacing = (modifier_rect.w - knob_size * 3.0) / 2.0;
-    for index in 0..3 {
+    for (index, knob) in filter_knobs.iter_mut().enumerate() {
         let x = modifier_rect.x + index as f32 * (knob_size + column_spacing);
-        filter_knobs[index] = Rect::new(x, modifier_rect.y + 20.0, knob_size, knob_size);
+        *knob = Rect::new(x, modifier_rect.y + 20.0, knob_size, knob_size);
     }
+    let filter_type_rect = Rect::new(
+        modifier_rect.x + modifier_rect.w - 96.0,
+        modifier_rect.y - 26.0,
+        96.0,
+        22.0,
+    );
+    let filter_slope_rect = Rect::new(
+        filter_type_rect.x - 80.0,
+        filter_type_rect.y,
+        72.0,
+        filter_type_rect.h,
+    );
     let filter_env_divider = modifier_rect.y + knob_size + 60.0;
-    for index in 0..3 {
+    for (index, knob) in filter_env_knobs.iter_mut().enumerate() {
         let x = modifier_rect.x + index as f32 * (knob_size + column_spacing);
-        filter_env_knobs[index] = Rect::new(x, filter_env_divider + 24.0, knob_size, knob_size);
+        *knob = Rect::new(x, filter_env_divider + 24.0, knob_size, knob_size);
     }
     let loudness_split = modifier_rect.y + modifier_rect.h * 0.58;
-    for index in 0..3 {
+    // Curve selectors sit at the right end of each contour's title row.
+    let curve_size = vec2(48.0, 18.0);
+    let filter_curve_rect = Rect::new(
+        modifier_rect.x + modifier_rect.w - curve_size.x - 4.0,
+        filter_env_divider + 4.0,
+        curve_size.x,
+        curve_size.y,
+    );
+    let filter_loop_rect = Rect::new(
+        modifier_rect.x + 4.0,
+        filter_curve_rect.y,
+        curve_size.x,
+        curve_size.y,
+    );
+    // The contour titles open their DAHDSR editors; they stop short of the
+    // buttons at either end of the row.
+    let filter_title_rect = Rect::new(
+        modifier_rect.x + 60.0,
+        filter_curve_rect.y,
+        modifier_rect.w - 120.0,
+        curve_size.y,
+    );
+    let loudness_title_rect = Rect::new(
+        filter_title_rect.x,
+        loudness_split + 52.0,
+        filter_title_rect.w,
+        curve_size.y,
+    );
+    let loudness_curve_rect = Rect::new(
+        filter_curve_rect.x,
+        loudness_split + 52.0,
+        curve_size.x,
+        curve_size.y,
+    );
+    for (index, knob) in loudness_knobs.iter_mut().enumerate() {
         let x = modifier_rect.x + index as f32 * (knob_size + column_spacing);
-        loudness_knobs[index] = Rect::new(x, loudness_split + 80.0, knob_size, knob_size);
+        *knob = Rect::new(x, loudness_split + 80.0, knob_size, knob_size);
     }
 
     let output_knobs = [
@@ -488,6 +1211,41 @@ fn compute_panel_layout() -> PanelLayout {
         ),
     ];
 
+    // The effect bypasses sit in the strip above MAIN VOL, one row each,
+    // and each label opens that effect's knobs; the meters start below them.
+    let effect_row = |row: f32| {
+        let y = output_rect.y + 4.0 + row * 16.0;
+        (
+            Rect::new(output_rect.x + 6.0, y, 28.0, 14.0),
+            Rect::new(output_rect.x + 38.0, y, 56.0, 14.0),
+        )
+    };
+    let (chorus_switch_rect, chorus_label_rect) = effect_row(0.0);
+    let (delay_switch_rect, delay_label_rect) = effect_row(1.0);
+    let (reverb_switch_rect, reverb_label_rect) = effect_row(2.0);
+
+    let gain_reduction_rect = Rect::new(
+        output_rect.x + output_rect.w - 16.0,
+        output_rect.y + 24.0,
+        8.0,
+        output_rect.h - 48.0,
+    );
+
+    let output_meter_rects = [
+        Rect::new(
+            output_rect.x + 6.0,
+            output_rect.y + 66.0,
+            8.0,
+            output_rect.h - 90.0,
+        ),
+        Rect::new(
+            output_rect.x + 18.0,
+            output_rect.y + 66.0,
+            8.0,
+            output_rect.h - 90.0,
+        ),
+    ];
+
     PanelLayout {
         controller_rect,
         oscillator_rect,
@@ -508,15 +1266,32 @@ fn compute_panel_layout() -> PanelLayout {
         osc_range_knobs,
         osc_freq_knobs,
         osc_wave_knobs,
+        freq_snap_rect,
         mixer_osc_knobs,
         mixer_extra_knobs,
         mixer_toggle_rects,
         noise_selector_rect,
         overload_rect,
+        ext_label_rect,
         filter_knobs,
         filter_env_knobs,
         loudness_knobs,
         output_knobs,
+        chorus_switch_rect,
+        chorus_label_rect,
+        delay_switch_rect,
+        delay_label_rect,
+        reverb_switch_rect,
+        reverb_label_rect,
+        gain_reduction_rect,
+        output_meter_rects,
+        filter_type_rect,
+        filter_slope_rect,
+        filter_curve_rect,
+        filter_loop_rect,
+        filter_title_rect,
+        loudness_title_rect,
+        loudness_curve_rect,
     }
 }
 
@@ -531,18 +1306,73 @@ struct PanelState {
     last_voltage: f32,
     pitch_target: f32,
     pitch_current: f32,
-    mod_phase: f32,
-    mod_signal: f32,
+    mod_wheel: f32,
+    mod_wheel_drag: bool,
+    /// -1 to 1, scaled by `bend_range` semitones.
+    pitch_bend: f32,
+    bend_range: i32,
+    bend_anchor: Option<f32>,
+    /// -1 to 1 from the gamepad triggers, in `EXPRESSION_CUTOFF_OCT`s.
+    cutoff_expression: f32,
+    velocity_route: VelocityRoute,
+    macro_knobs: [KnobValue; MACRO_COUNT],
+    macros: [MacroAssignment; MACRO_COUNT],
+    /// Macro positions already passed on to their targets.
+    macro_applied: [f32; MACRO_COUNT],
+    key_layout: KeyLayout,
     osc_modulation: bool,
     osc3_control: bool,
+    osc3_lfo: bool,
+    osc_engines: [OscEngine; 3],
+    osc_sync: [bool; 3],
+    // OSC 2/3 FREQ lands on whole semitones.
+    freq_snap: bool,
+    phase_mode: PhaseMode,
+    oversampling: Oversampling,
+    rumble_filter: bool,
+    limiter_enabled: bool,
+    feedback_patch: bool,
+    buffer_request: BufferRequest,
+    gain_reduction_db: f32,
+    envelope_states: [EnvelopeState; 2],
+    meter_peak: [f32; 2],
+    meter_rms: [f32; 2],
+    clip_hold: [f32; 2],
     mod_source_noise: bool,
     mod_target_filter: bool,
-    glide_enabled: bool,
+    glide_mode: GlideMode,
+    trigger_mode: TriggerMode,
+    sustain: bool,
+    glide_curve: GlideCurve,
+    glide_from: f32,
+    glide_to: f32,
+    glide_elapsed: f32,
+    gate_held: bool,
+    glide_snap: bool,
+    // After a panic the next note starts at its own pitch.
+    glide_reset: bool,
+    gliding: bool,
     decay_enabled: bool,
     filter_overload: bool,
     s_trigger_request: bool,
-    mod_noise_color: NoiseColor,
-    mod_noise: NoiseGenerator,
+    clock: TempoClock,
+    lfo_sync: bool,
+    // Each new note restarts the free-running LFO from the top.
+    lfo_key_sync: bool,
+    metronome: bool,
+    scale: Scale,
+    scale_root: i32,
+    split: KeySplit,
+    // SPLIT AT is waiting for a key to be played.
+    split_learning: bool,
+    temperament: Temperament,
+    reference_pitch: f32,
+    /// Octaves added under TUNE for every patch, kept in the session rather
+    /// than the preset.
+    tune_calibration: f32,
+    // A loaded Scala tuning wins over the equal temperament.
+    scala_tuning: Option<Tuning>,
+    tuning: Arc<Tuning>,
 }
 
 impl PanelState {
@@ -557,18 +1387,63 @@ impl PanelState {
             last_voltage: 0.0,
             pitch_target: 0.0,
             pitch_current: 0.0,
-            mod_phase: 0.0,
-            mod_signal: 0.0,
+            mod_wheel: 1.0,
+            mod_wheel_drag: false,
+            pitch_bend: 0.0,
+            bend_range: DEFAULT_BEND_RANGE,
+            bend_anchor: None,
+            cutoff_expression: 0.0,
+            velocity_route: VelocityRoute::Off,
+            macro_knobs: MACRO_KNOBS.map(KnobValue::default_for),
+            macros: Default::default(),
+            macro_applied: MACRO_KNOBS.map(KnobId::default_value),
+            key_layout: KeyLayout::Qwerty,
             osc_modulation: false,
             osc3_control: true,
+            osc3_lfo: false,
+            osc_engines: [OscEngine::Classic; 3],
+            osc_sync: [false; 3],
+            freq_snap: false,
+            phase_mode: PhaseMode::FreeRun,
+            oversampling: Oversampling::Off,
+            rumble_filter: false,
+            limiter_enabled: false,
+            feedback_patch: false,
+            buffer_request: BufferRequest::Auto,
+            gain_reduction_db: 0.0,
+            envelope_states: [EnvelopeState::default(); 2],
+            meter_peak: [0.0; 2],
+            meter_rms: [0.0; 2],
+            clip_hold: [0.0; 2],
             mod_source_noise: true,
             mod_target_filter: true,
-            glide_enabled: true,
+            glide_mode: GlideMode::On,
+            trigger_mode: TriggerMode::Single,
+            sustain: false,
+            glide_curve: GlideCurve::Exponential,
+            glide_from: 0.0,
+            glide_to: 0.0,
+            glide_elapsed: 0.0,
+            gate_held: false,
+            glide_snap: false,
+            glide_reset: false,
+            gliding: false,
             decay_enabled: true,
             filter_overload: false,
             s_trigger_request: false,
-            mod_noise_color: NoiseColor::White,
-            mod_noise: NoiseGenerator::new(),
+            clock: TempoClock::new(),
+            lfo_sync: false,
+            lfo_key_sync: false,
+            metronome: false,
+            scale: Scale::Chromatic,
+            scale_root: 0,
+            split: KeySplit::default(),
+            split_learning: false,
+            temperament: Temperament::Twelve,
+            reference_pitch: DEFAULT_REFERENCE_HZ,
+            tune_calibration: 0.0,
+            scala_tuning: None,
+            tuning: Arc::new(Tuning::default()),
         }
     }
 
@@ -586,13 +1461,19 @@ impl PanelState {
         self.osc_range_setting(index).octave_offset
     }
 
+    /// The color nearest the COLOR knob; the knob itself may sit between
+    /// two.
+    fn noise_color(&self) -> NoiseColor {
+        NoiseColor::nearest(self.mixer_panel.noise_color.value)
+    }
+
+    /// Snaps the COLOR knob onto `color`.
     fn set_noise_color(&mut self, color: NoiseColor) {
-        self.mod_noise_color = color;
-        self.mixer_panel.noise_color = color;
+        self.mixer_panel.noise_color.value = color.morph_position();
     }
 
     fn cycle_noise_color(&mut self) {
-        let next = self.mod_noise_color.next();
+        let next = self.noise_color().next();
         self.set_noise_color(next);
     }
 
@@ -617,14 +1498,9 @@ impl PanelState {
     }
 
     fn cutoff_hz(&self) -> f32 {
-        let base =
-            FILTER_MIN_HZ + self.modifiers_panel.filter[0].value * (FILTER_MAX_HZ - FILTER_MIN_HZ);
-        if self.mod_target_filter {
-            let modulated = base * (1.0 + self.mod_signal * MOD_DEPTH);
-            modulated.clamp(FILTER_MIN_HZ, FILTER_MAX_HZ)
-        } else {
-            base
-        }
+        (knob_to_cutoff(self.modifiers_panel.filter[0].value)
+            * (self.cutoff_expression * EXPRESSION_CUTOFF_OCT).exp2())
+        .clamp(FILTER_MIN_HZ, FILTER_MAX_HZ)
     }
 
     fn master_level(&self) -> f32 {
@@ -633,52 +1509,326 @@ impl PanelState {
 
     fn osc_detune(&self, index: usize) -> f32 {
         let value = self.oscillator.freq[index].value;
-        (value * 2.0 - 1.0) * DETUNE_RANGE
+        if self.freq_snap && index > 0 {
+            knob_to_snapped_detune(value)
+        } else {
+            knob_to_detune(value)
+        }
     }
 
     fn tune_offset(&self) -> f32 {
-        (self.controllers.tune.value - 0.5) * TUNE_RANGE_OCT
+        knob_to_tune(self.controllers.tune.value)
     }
 
     fn refresh_pitch_target(&mut self) {
-        self.pitch_target = self.last_voltage + self.tune_offset();
+        self.pitch_target = self.last_voltage + self.tune_calibration + self.tune_offset();
+    }
+
+    fn set_tune_calibration(&mut self, octaves: f32) {
+        let limit = TUNE_RANGE_OCT * 0.5;
+        self.tune_calibration = octaves.clamp(-limit, limit);
+    }
+
+    /// Moves TUNE's offset into the calibration and centres the knob, so
+    /// the pitch stays put and later patches play in tune with it.
+    fn calibrate_tune(&mut self) {
+        self.set_tune_calibration(self.tune_calibration + self.tune_offset());
+        self.controllers.tune.value = KnobId::ControllersTune.default_value();
     }
 
     fn glide_time(&self) -> f32 {
-        GLIDE_MIN_SEC + self.controllers.glide.value * (GLIDE_MAX_SEC - GLIDE_MIN_SEC)
+        knob_to_glide(self.controllers.glide.value)
+    }
+
+    fn note_event(&mut self, message: &ControllerMessage) -> bool {
+        let legato = message.gate && self.gate_held;
+        let new_note = message.midi_note != self.last_midi;
+        if message.gate && !legato && self.glide_mode == GlideMode::Legato {
+            self.glide_snap = true;
+        }
+        if message.gate && std::mem::take(&mut self.glide_reset) {
+            self.glide_snap = true;
+        }
+        self.gate_held = message.gate;
+        self.sustain = message.sustain;
+        self.last_midi = message.midi_note;
+        self.last_voltage = message.voltage;
+        legato && new_note && self.trigger_mode == TriggerMode::Multi
+    }
+
+    /// Drops the held gate and any glide in progress; the keyboard side is
+    /// `KeyboardController::panic`.
+    fn panic(&mut self) {
+        self.sustain = false;
+        self.split_learning = false;
+        self.glide_snap = true;
+        self.glide_reset = true;
+        self.glide_from = self.pitch_target;
+        self.glide_to = self.pitch_target;
+        self.glide_elapsed = 0.0;
     }
 
-    fn apply_pitch(&mut self, dt: f32, _vcos: &[VcoHandle]) {
-        if !self.glide_enabled || dt <= 0.0 || self.glide_time() <= 0.0001 {
+    fn apply_pitch(&mut self, dt: f32) {
+        let snap = std::mem::take(&mut self.glide_snap);
+        if snap || self.glide_mode == GlideMode::Off || dt <= 0.0 || self.glide_time() <= 0.0001 {
             self.pitch_current = self.pitch_target;
         } else {
             let glide = self.glide_time().max(0.0001);
-            let step = (dt / glide).clamp(0.0, 1.0);
-            self.pitch_current += (self.pitch_target - self.pitch_current) * step;
+            let remaining = self.pitch_target - self.pitch_current;
+            match self.glide_curve {
+                GlideCurve::Exponential => {
+                    let step = (dt / glide).clamp(0.0, 1.0);
+                    self.pitch_current += remaining * step;
+                }
+                GlideCurve::ConstantRate => {
+                    let step = dt / glide;
+                    self.pitch_current += remaining.clamp(-step, step);
+                }
+                GlideCurve::ConstantTime => {
+                    if self.glide_to != self.pitch_target {
+                        self.glide_from = self.pitch_current;
+                        self.glide_to = self.pitch_target;
+                        self.glide_elapsed = 0.0;
+                    }
+                    self.glide_elapsed += dt;
+                    let progress = (self.glide_elapsed / glide).min(1.0);
+                    self.pitch_current =
+                        self.glide_from + (self.glide_to - self.glide_from) * progress;
+                }
+            }
+        }
+        if self.glide_curve != GlideCurve::ConstantTime {
+            self.glide_to = self.pitch_current;
+        }
+        self.gliding = (self.pitch_target - self.pitch_current).abs() > GLIDE_LAMP_VOLTS;
+    }
+
+    fn modulation_routing(&self) -> ModRouting {
+        ModRouting {
+            pitch: self.osc_modulation,
+            filter: self.mod_target_filter,
+            noise: self.mod_source_noise,
+            osc3: self.osc3_lfo,
+            key_sync: self.lfo_key_sync,
+            division: self.lfo_division(),
+            wheel: self.mod_wheel,
+        }
+    }
+
+    fn bend_offset(&self) -> f32 {
+        self.pitch_bend * self.bend_range as f32 / 12.0
+    }
+
+    fn pitch_mod_depth(&self) -> f32 {
+        self.controllers.pitch_mod_depth.value * PITCH_MOD_MAX_OCT
+    }
+
+    fn filter_mod_depth(&self) -> f32 {
+        self.controllers.filter_mod_depth.value * FILTER_MOD_MAX_DEPTH
+    }
+
+    fn mod_destination_label(&self) -> &'static str {
+        match (self.osc_modulation, self.mod_target_filter) {
+            (false, false) => "OFF",
+            (true, false) => "PITCH",
+            (false, true) => "FILTER",
+            (true, true) => "BOTH",
         }
     }
 
-    fn update_modulation(&mut self, dt: f32) {
-        let rate = self.mod_lfo_rate();
-        self.mod_phase = (self.mod_phase + dt * rate).fract();
-        let sine = (self.mod_phase * std::f32::consts::TAU).sin();
-        let noise = self.mod_noise.sample(self.mod_noise_color);
-        let blended = sine * (1.0 - self.controllers.modulation_mix.value)
-            + noise * self.controllers.modulation_mix.value;
-        let source = if self.mod_source_noise { blended } else { sine };
-        self.mod_signal = source * self.mod_amount();
+    /// The division MOD RATE picks while the LFO follows the tempo clock.
+    fn lfo_division(&self) -> Option<SyncDivision> {
+        (self.lfo_sync && !self.osc3_lfo)
+            .then(|| SyncDivision::from_knob(self.controllers.modulation_rate.value))
+    }
+
+    /// The division TIME picks while the delay follows the tempo clock.
+    fn delay_division(&self) -> Option<SyncDivision> {
+        let output = &self.output_panel;
+        output
+            .delay_sync
+            .then(|| SyncDivision::from_knob(output.delay[0].value))
     }
 
-    fn modulation_pitch_offset(&self) -> f32 {
-        if self.osc_modulation {
-            self.mod_signal * OSC_MOD_DEPTH
+    fn click_level(&self) -> f32 {
+        if self.metronome {
+            self.output_panel.phones_volume.value * CLICK_MAX_LEVEL
         } else {
             0.0
         }
     }
 
     fn mod_lfo_rate(&self) -> f32 {
-        LFO_RATE_MIN + self.controllers.modulation_rate.value * (LFO_RATE_MAX - LFO_RATE_MIN)
+        knob_to_lfo_rate(self.controllers.modulation_rate.value)
+    }
+
+    fn osc3_voltage(&self) -> f32 {
+        if self.osc3_lfo {
+            self.osc_range_offset(2) + OSC3_LFO_OFFSET
+        } else if self.osc3_control {
+            self.pitch_current + self.osc_range_offset(2)
+        } else {
+            self.osc_range_offset(2)
+        }
+    }
+
+    fn option_value(&self, option: PanelOption) -> &'static str {
+        match option {
+            PanelOption::Osc3Lfo => {
+                if self.osc3_lfo {
+                    "LFO"
+                } else {
+                    "AUDIO"
+                }
+            }
+            PanelOption::ModDestination => self.mod_destination_label(),
+            PanelOption::Osc1Engine => self.osc_engines[0].label(),
+            PanelOption::Osc2Engine => self.osc_engines[1].label(),
+            PanelOption::Osc3Engine => self.osc_engines[2].label(),
+            PanelOption::Osc2Sync => on_off(self.osc_sync[1]),
+            PanelOption::Osc3Sync => on_off(self.osc_sync[2]),
+            PanelOption::RingMod => on_off(self.mixer_panel.ring_enabled),
+            PanelOption::OscPhase => self.phase_mode.label(),
+            PanelOption::GlideCurve => self.glide_curve.label(),
+            PanelOption::KeyTrigger => self.trigger_mode.label(),
+            PanelOption::Oversampling => self.oversampling.label(),
+            PanelOption::RumbleFilter => on_off(self.rumble_filter),
+            PanelOption::Limiter => on_off(self.limiter_enabled),
+            PanelOption::FeedbackPatch => on_off(self.feedback_patch),
+            PanelOption::AudioBuffer => self.buffer_request.label(),
+            PanelOption::LfoSync => on_off(self.lfo_sync),
+            PanelOption::LfoKeySync => on_off(self.lfo_key_sync),
+            PanelOption::ClockSource => self.clock.source().label(),
+            PanelOption::Metronome => on_off(self.metronome),
+            PanelOption::Tempo => "TAP",
+            PanelOption::Scale => self.scale.label(),
+            PanelOption::ScaleRoot => pitch_class_name(self.scale_root),
+            PanelOption::SplitPoint if self.split_learning => "PLAY KEY",
+            PanelOption::SplitPoint => "OFF",
+            PanelOption::UpperZone => "",
+            PanelOption::Temperament if self.scala_tuning.is_some() => "SCALA",
+            PanelOption::Temperament => self.temperament.label(),
+            PanelOption::ReferencePitch => "A4",
+            PanelOption::BendRange => "ST",
+            PanelOption::VelocityRoute => self.velocity_route.label(),
+            PanelOption::KeyLayout => self.key_layout.label(),
+        }
+    }
+
+    /// What an option's button shows; tempo and reference pitch are live
+    /// numbers.
+    fn option_text(&self, option: PanelOption) -> String {
+        match option {
+            PanelOption::Tempo => format!("{:.1}", self.clock.bpm()),
+            PanelOption::ReferencePitch => format!("{:.0} Hz", self.reference_pitch),
+            PanelOption::BendRange => format!("±{} ST", self.bend_range),
+            PanelOption::SplitPoint if !self.split_learning => self
+                .split
+                .point
+                .map_or_else(|| "OFF".to_string(), note_name),
+            PanelOption::UpperZone => self.split.upper.label(),
+            _ => self.option_value(option).to_string(),
+        }
+    }
+
+    /// Rebuilds the tuning table. The reference pitch applies to the equal
+    /// temperaments and to Scala scales without a `.kbm`, which carries
+    /// its own reference.
+    fn retune(&mut self) {
+        let tuning = match &self.scala_tuning {
+            Some(scala) if scala.has_keyboard_map() => scala.clone(),
+            Some(scala) => scala.with_reference(self.reference_pitch),
+            None => Tuning::equal(self.temperament.steps()).with_reference(self.reference_pitch),
+        };
+        self.tuning = Arc::new(tuning);
+    }
+
+    fn set_reference_pitch(&mut self, hz: f32) {
+        self.reference_pitch = hz.clamp(MIN_REFERENCE_HZ, MAX_REFERENCE_HZ);
+        self.retune();
+    }
+
+    fn cycle_option(&mut self, option: PanelOption) {
+        match option {
+            PanelOption::Osc3Lfo => self.osc3_lfo = !self.osc3_lfo,
+            PanelOption::ModDestination => {
+                let (pitch, filter) = match (self.osc_modulation, self.mod_target_filter) {
+                    (false, false) => (true, false),
+                    (true, false) => (false, true),
+                    (false, true) => (true, true),
+                    (true, true) => (false, false),
+                };
+                self.osc_modulation = pitch;
+                self.mod_target_filter = filter;
+            }
+            PanelOption::Osc1Engine => self.cycle_osc_engine(0),
+            PanelOption::Osc2Engine => self.cycle_osc_engine(1),
+            PanelOption::Osc3Engine => self.cycle_osc_engine(2),
+            PanelOption::Osc2Sync => self.osc_sync[1] = !self.osc_sync[1],
+            PanelOption::Osc3Sync => self.osc_sync[2] = !self.osc_sync[2],
+            PanelOption::RingMod => self.mixer_panel.ring_enabled = !self.mixer_panel.ring_enabled,
+            PanelOption::OscPhase => self.phase_mode = self.phase_mode.next(),
+            PanelOption::GlideCurve => self.glide_curve = self.glide_curve.next(),
+            PanelOption::Oversampling => self.oversampling = self.oversampling.next(),
+            PanelOption::RumbleFilter => self.rumble_filter = !self.rumble_filter,
+            PanelOption::Limiter => self.limiter_enabled = !self.limiter_enabled,
+            PanelOption::FeedbackPatch => self.feedback_patch = !self.feedback_patch,
+            PanelOption::AudioBuffer => self.buffer_request = self.buffer_request.next(),
+            PanelOption::LfoSync => self.lfo_sync = !self.lfo_sync,
+            PanelOption::LfoKeySync => self.lfo_key_sync = !self.lfo_key_sync,
+            PanelOption::ClockSource => self.clock.set_source(self.clock.source().next()),
+            PanelOption::Metronome => self.metronome = !self.metronome,
+            PanelOption::Tempo => self.clock.tap(),
+            PanelOption::Scale => self.scale = self.scale.next(),
+            PanelOption::ScaleRoot => self.scale_root = (self.scale_root + 1) % 12,
+            // Click to play the split key; clicking again before one is
+            // played takes the split away.
+            PanelOption::SplitPoint => {
+                if self.split_learning {
+                    self.split.point = None;
+                }
+                self.split_learning = !self.split_learning;
+            }
+            PanelOption::UpperZone => self.split.upper = self.split.upper.next(),
+            PanelOption::Temperament => {
+                // Leaving a Scala tuning starts over from 12-TET.
+                if self.scala_tuning.take().is_none() {
+                    self.temperament = self.temperament.next();
+                }
+                self.retune();
+            }
+            PanelOption::ReferencePitch => {
+                let next = self.reference_pitch.round() + 1.0;
+                self.set_reference_pitch(if next > MAX_REFERENCE_HZ {
+                    MIN_REFERENCE_HZ
+                } else {
+                    next
+                });
+            }
+            PanelOption::VelocityRoute => self.velocity_route = self.velocity_route.next(),
+            PanelOption::KeyLayout => self.key_layout = self.key_layout.next(),
+            PanelOption::BendRange => {
+                let index = BEND_RANGES
+                    .iter()
+                    .position(|range| *range == self.bend_range)
+                    .map_or(0, |index| (index + 1) % BEND_RANGES.len());
+                self.bend_range = BEND_RANGES[index];
+            }
+            PanelOption::KeyTrigger => {
+                self.trigger_mode = match self.trigger_mode {
+                    TriggerMode::Single => TriggerMode::Multi,
+                    TriggerMode::Multi => TriggerMode::Single,
+                };
+            }
+        }
+        log_mode(option.label(), &self.option_text(option));
+    }
+
+    fn cycle_osc_engine(&mut self, index: usize) {
+        self.osc_engines[index] = match self.osc_engines[index] {
+            OscEngine::Classic => OscEngine::Wavetable,
+            OscEngine::Wavetable => OscEngine::Classic,
+        };
     }
 
     fn mod_amount(&self) -> f32 {
@@ -705,8 +1855,12 @@ impl PanelState {
         self.modifiers_panel.filter_env[2].value
     }
 
+    // A release knob left at zero follows the Decay switch, as on the panel.
     fn filter_release_time(&self) -> f32 {
-        if self.decay_enabled {
+        let release = self.modifiers_panel.filter_env_stages[2].value;
+        if release > 0.0 {
+            knob_to_env_seconds(KnobId::FilterRelease, release)
+        } else if self.decay_enabled {
             self.filter_decay_time()
         } else {
             0.02
@@ -734,14 +1888,85 @@ impl PanelState {
     }
 
     fn loud_release_time(&self) -> f32 {
-        if self.decay_enabled {
+        let release = self.modifiers_panel.loudness_env_stages[2].value;
+        if release > 0.0 {
+            knob_to_env_seconds(KnobId::LoudnessRelease, release)
+        } else if self.decay_enabled {
             self.loud_decay_time()
         } else {
             0.02
         }
     }
 
-    fn set_overload(&mut self, flag: bool) {
+    fn filter_envelope(&self) -> EnvelopeParams {
+        let stages = &self.modifiers_panel.filter_env_stages;
+        EnvelopeParams {
+            delay: knob_to_env_seconds(KnobId::FilterDelay, stages[0].value),
+            attack: self.filter_attack_time(),
+            hold: knob_to_env_seconds(KnobId::FilterHold, stages[1].value),
+            decay: self.filter_decay_time(),
+            sustain: self.filter_sustain_level(),
+            release: self.filter_release_time(),
+        }
+    }
+
+    fn loud_envelope(&self) -> EnvelopeParams {
+        let stages = &self.modifiers_panel.loudness_env_stages;
+        EnvelopeParams {
+            delay: knob_to_env_seconds(KnobId::LoudnessDelay, stages[0].value),
+            attack: self.loud_attack_time(),
+            hold: knob_to_env_seconds(KnobId::LoudnessHold, stages[1].value),
+            decay: self.loud_decay_time(),
+            sustain: self.loud_sustain_level(),
+            release: self.loud_release_time(),
+        }
+    }
+
+    fn filter_envelope_shape(&self) -> EnvelopeShape {
+        let params = self.filter_envelope();
+        EnvelopeShape {
+            delay: params.delay,
+            hold: params.hold,
+            attack: self.filter_attack_time(),
+            decay: self.filter_decay_time(),
+            // A looping contour never holds, so it is drawn with no sustain.
+            sustain: if self.modifiers_panel.filter_env_loop {
+                0.0
+            } else {
+                self.filter_sustain_level()
+            },
+            release: self.filter_release_time(),
+            curve: self.modifiers_panel.filter_env_curve,
+        }
+    }
+
+    fn loud_envelope_shape(&self) -> EnvelopeShape {
+        let params = self.loud_envelope();
+        EnvelopeShape {
+            delay: params.delay,
+            hold: params.hold,
+            attack: self.loud_attack_time(),
+            decay: self.loud_decay_time(),
+            sustain: self.loud_sustain_level(),
+            release: self.loud_release_time(),
+            curve: self.modifiers_panel.loudness_env_curve,
+        }
+    }
+
+    fn update_meters(&mut self, readings: &[MeterReading; 2], dt: f32) {
+        let fall = (-dt / METER_FALL_SEC).exp();
+        for (channel, reading) in readings.iter().enumerate() {
+            self.meter_peak[channel] = reading.peak.max(self.meter_peak[channel] * fall);
+            self.meter_rms[channel] = reading.rms.max(self.meter_rms[channel] * fall);
+            self.clip_hold[channel] = if reading.clipped {
+                METER_CLIP_HOLD_SEC
+            } else {
+                (self.clip_hold[channel] - dt).max(0.0)
+            };
+        }
+    }
+
+    fn set_overload(&mut self, flag: bool) {
         self.filter_overload = flag;
     }
 
@@ -754,364 +1979,3122 @@ impl PanelState {
         self.s_trigger_request = false;
         pending
     }
+
+    fn synth_params(&mut self) -> SynthParams {
+        let mut params = SynthParams::new();
+        for knob in KnobId::ALL {
+            params.set(knob, self.knob_mut(knob).value);
+        }
+        params
+    }
+
+    fn knob_mut(&mut self, knob: KnobId) -> &mut KnobValue {
+        match knob {
+            KnobId::ControllersTune => &mut self.controllers.tune,
+            KnobId::ControllersGlide => &mut self.controllers.glide,
+            KnobId::ControllersModMix => &mut self.controllers.modulation_mix,
+            KnobId::ControllersModRate => &mut self.controllers.modulation_rate,
+            KnobId::ControllersModAmount => &mut self.controllers.modulation_amount,
+            KnobId::ModPitchDepth => &mut self.controllers.pitch_mod_depth,
+            KnobId::ModFilterDepth => &mut self.controllers.filter_mod_depth,
+            KnobId::ModFade => &mut self.controllers.mod_fade,
+            KnobId::OscRange1 => &mut self.oscillator.range[0],
+            KnobId::OscRange2 => &mut self.oscillator.range[1],
+            KnobId::OscRange3 => &mut self.oscillator.range[2],
+            KnobId::OscFreq1 => &mut self.oscillator.freq[0],
+            KnobId::OscFreq2 => &mut self.oscillator.freq[1],
+            KnobId::OscFreq3 => &mut self.oscillator.freq[2],
+            KnobId::OscWave1 => &mut self.oscillator.waveform[0],
+            KnobId::OscWave2 => &mut self.oscillator.waveform[1],
+            KnobId::OscWave3 => &mut self.oscillator.waveform[2],
+            KnobId::OscFmDepth => &mut self.oscillator.fm_depth,
+            KnobId::MixerExternal => &mut self.mixer_panel.external_input,
+            KnobId::MixerOsc1 => &mut self.mixer_panel.osc[0],
+            KnobId::MixerOsc2 => &mut self.mixer_panel.osc[1],
+            KnobId::MixerOsc3 => &mut self.mixer_panel.osc[2],
+            KnobId::MixerNoise => &mut self.mixer_panel.noise,
+            KnobId::MixerRing => &mut self.mixer_panel.ring_mod,
+            KnobId::MixerPan1 => &mut self.mixer_panel.pan[0],
+            KnobId::MixerPan2 => &mut self.mixer_panel.pan[1],
+            KnobId::MixerPan3 => &mut self.mixer_panel.pan[2],
+            KnobId::MixerNoisePan => &mut self.mixer_panel.noise_pan,
+            KnobId::MixerNoiseColor => &mut self.mixer_panel.noise_color,
+            KnobId::FollowerRelease => &mut self.mixer_panel.follower[0],
+            KnobId::FollowerCutoff => &mut self.mixer_panel.follower[1],
+            KnobId::FollowerVca => &mut self.mixer_panel.follower[2],
+            KnobId::FilterCutoff => &mut self.modifiers_panel.filter[0],
+            KnobId::FilterEmphasis => &mut self.modifiers_panel.filter[1],
+            KnobId::FilterContour => &mut self.modifiers_panel.filter[2],
+            KnobId::FilterAttack => &mut self.modifiers_panel.filter_env[0],
+            KnobId::FilterDecay => &mut self.modifiers_panel.filter_env[1],
+            KnobId::FilterSustain => &mut self.modifiers_panel.filter_env[2],
+            KnobId::LoudnessAttack => &mut self.modifiers_panel.loudness_env[0],
+            KnobId::LoudnessDecay => &mut self.modifiers_panel.loudness_env[1],
+            KnobId::LoudnessSustain => &mut self.modifiers_panel.loudness_env[2],
+            KnobId::FilterDelay => &mut self.modifiers_panel.filter_env_stages[0],
+            KnobId::FilterHold => &mut self.modifiers_panel.filter_env_stages[1],
+            KnobId::FilterRelease => &mut self.modifiers_panel.filter_env_stages[2],
+            KnobId::LoudnessDelay => &mut self.modifiers_panel.loudness_env_stages[0],
+            KnobId::LoudnessHold => &mut self.modifiers_panel.loudness_env_stages[1],
+            KnobId::LoudnessRelease => &mut self.modifiers_panel.loudness_env_stages[2],
+            KnobId::OutputVolume => &mut self.output_panel.main_volume,
+            KnobId::OutputPhones => &mut self.output_panel.phones_volume,
+            KnobId::ChorusRate => &mut self.output_panel.chorus[0],
+            KnobId::ChorusDepth => &mut self.output_panel.chorus[1],
+            KnobId::ChorusMix => &mut self.output_panel.chorus[2],
+            KnobId::DelayTime => &mut self.output_panel.delay[0],
+            KnobId::DelayFeedback => &mut self.output_panel.delay[1],
+            KnobId::DelayDamping => &mut self.output_panel.delay[2],
+            KnobId::DelayMix => &mut self.output_panel.delay[3],
+            KnobId::ReverbSize => &mut self.output_panel.reverb[0],
+            KnobId::ReverbDamping => &mut self.output_panel.reverb[1],
+            KnobId::ReverbMix => &mut self.output_panel.reverb[2],
+            KnobId::Macro1 => &mut self.macro_knobs[0],
+            KnobId::Macro2 => &mut self.macro_knobs[1],
+            KnobId::Macro3 => &mut self.macro_knobs[2],
+            KnobId::Macro4 => &mut self.macro_knobs[3],
+        }
+    }
+
+    /// Turns each macro's targets by however far the macro has moved since
+    /// the last call. Knobs stay free to be set by hand in between.
+    fn apply_macros(&mut self) {
+        for index in 0..MACRO_COUNT {
+            let value = self.macro_knobs[index].value;
+            let delta = value - self.macro_applied[index];
+            self.macro_applied[index] = value;
+            if delta == 0.0 {
+                continue;
+            }
+            for target in self.macros[index].targets().to_vec() {
+                let knob = self.knob_mut(target.knob);
+                knob.value = (knob.value + delta * target.depth).clamp(0.0, 1.0);
+            }
+        }
+    }
+
+    /// A whole patch, macro assignments included. Its macros count as
+    /// already applied, so loading doesn't turn their targets again.
+    fn load_patch(&mut self, preset: &Preset) {
+        self.apply_preset(preset);
+        for index in 0..MACRO_COUNT {
+            self.macros[index] = MacroAssignment::load(preset, index);
+            self.macro_applied[index] = self.macro_knobs[index].value;
+        }
+    }
+
+    fn to_preset(&self, name: &str) -> Preset {
+        let mut preset = Preset::new(name);
+        let controllers = &self.controllers;
+        preset.set("controllers.tune", controllers.tune.value);
+        preset.set("controllers.glide", controllers.glide.value);
+        preset.set("controllers.mod_mix", controllers.modulation_mix.value);
+        preset.set("controllers.mod_rate", controllers.modulation_rate.value);
+        preset.set(
+            "controllers.mod_amount",
+            controllers.modulation_amount.value,
+        );
+        preset.set(
+            "controllers.pitch_mod_depth",
+            controllers.pitch_mod_depth.value,
+        );
+        preset.set(
+            "controllers.filter_mod_depth",
+            controllers.filter_mod_depth.value,
+        );
+        preset.set("controllers.mod_fade", controllers.mod_fade.value);
+        for index in 0..3 {
+            let osc = index + 1;
+            preset.set(
+                &format!("osc{osc}.range"),
+                self.oscillator.range[index].value,
+            );
+            preset.set(&format!("osc{osc}.freq"), self.oscillator.freq[index].value);
+            preset.set(
+                &format!("osc{osc}.wave"),
+                self.oscillator.waveform[index].value,
+            );
+            preset.set(
+                &format!("mixer.osc{osc}"),
+                self.mixer_panel.osc[index].value,
+            );
+            preset.set_flag(
+                &format!("mixer.osc{osc}.on"),
+                self.mixer_panel.osc_enabled[index],
+            );
+            preset.set(
+                &format!("mixer.osc{osc}.pan"),
+                self.mixer_panel.pan[index].value,
+            );
+            preset.set_flag(&format!("osc{osc}.sync"), self.osc_sync[index]);
+            preset.set_flag(
+                &format!("osc{osc}.wavetable"),
+                self.osc_engines[index] == OscEngine::Wavetable,
+            );
+        }
+        preset.set("osc.fm_depth", self.oscillator.fm_depth.value);
+        preset.set_flag("osc.freq_snap", self.freq_snap);
+        preset.set(
+            "engine.oversampling",
+            Oversampling::VALUES
+                .iter()
+                .position(|mode| *mode == self.oversampling)
+                .unwrap_or(0) as f32,
+        );
+        preset.set(
+            "osc.phase_mode",
+            PhaseMode::VALUES
+                .iter()
+                .position(|mode| *mode == self.phase_mode)
+                .unwrap_or(0) as f32,
+        );
+        preset.set("mixer.noise.pan", self.mixer_panel.noise_pan.value);
+        preset.set("mixer.external", self.mixer_panel.external_input.value);
+        preset.set_flag("mixer.external.on", self.mixer_panel.ext_enabled);
+        preset.set_flag("mixer.feedback_patch", self.feedback_patch);
+        preset.set("mixer.noise", self.mixer_panel.noise.value);
+        preset.set_flag("mixer.noise.on", self.mixer_panel.noise_enabled);
+        preset.set("mixer.ring", self.mixer_panel.ring_mod.value);
+        preset.set_flag("mixer.ring.on", self.mixer_panel.ring_enabled);
+        let color_index = NoiseColor::VALUES
+            .iter()
+            .position(|color| *color == self.noise_color())
+            .unwrap_or(0);
+        preset.set("mixer.noise_color", color_index as f32);
+        preset.set("mixer.noise.color", self.mixer_panel.noise_color.value);
+        for (knob, value) in FollowerWindowState::KNOBS
+            .iter()
+            .zip(&self.mixer_panel.follower)
+        {
+            preset.set(knob.preset_key(), value.value);
+        }
+        let modifiers = &self.modifiers_panel;
+        preset.set("filter.cutoff", modifiers.filter[0].value);
+        preset.set("filter.emphasis", modifiers.filter[1].value);
+        preset.set("filter.contour", modifiers.filter[2].value);
+        preset.set(
+            "filter.slope",
+            FilterSlope::VALUES
+                .iter()
+                .position(|slope| *slope == modifiers.filter_slope)
+                .unwrap_or(0) as f32,
+        );
+        preset.set(
+            "filter.type",
+            FilterType::VALUES
+                .iter()
+                .position(|kind| *kind == modifiers.filter_type)
+                .unwrap_or(0) as f32,
+        );
+        preset.set("filter_env.attack", modifiers.filter_env[0].value);
+        preset.set("filter_env.decay", modifiers.filter_env[1].value);
+        preset.set("filter_env.sustain", modifiers.filter_env[2].value);
+        preset.set("loudness_env.attack", modifiers.loudness_env[0].value);
+        preset.set("loudness_env.decay", modifiers.loudness_env[1].value);
+        preset.set("loudness_env.sustain", modifiers.loudness_env[2].value);
+        preset.set("filter_env.delay", modifiers.filter_env_stages[0].value);
+        preset.set("filter_env.hold", modifiers.filter_env_stages[1].value);
+        preset.set("filter_env.release", modifiers.filter_env_stages[2].value);
+        preset.set("loudness_env.delay", modifiers.loudness_env_stages[0].value);
+        preset.set("loudness_env.hold", modifiers.loudness_env_stages[1].value);
+        preset.set(
+            "loudness_env.release",
+            modifiers.loudness_env_stages[2].value,
+        );
+        preset.set(
+            "filter_env.curve",
+            modifiers.filter_env_curve.index() as f32,
+        );
+        preset.set_flag("filter_env.loop", modifiers.filter_env_loop);
+        preset.set(
+            "loudness_env.curve",
+            modifiers.loudness_env_curve.index() as f32,
+        );
+        preset.set("output.main_volume", self.output_panel.main_volume.value);
+        preset.set(
+            "output.phones_volume",
+            self.output_panel.phones_volume.value,
+        );
+        preset.set_flag("output.rumble_filter", self.rumble_filter);
+        preset.set_flag("output.limiter", self.limiter_enabled);
+        preset.set_flag("output.chorus", self.output_panel.chorus_enabled);
+        for (knob, value) in ChorusWindowState::KNOBS
+            .iter()
+            .zip(&self.output_panel.chorus)
+        {
+            preset.set(knob.preset_key(), value.value);
+        }
+        preset.set_flag("output.delay", self.output_panel.delay_enabled);
+        preset.set_flag("delay.sync", self.output_panel.delay_sync);
+        for (knob, value) in DelayWindowState::KNOBS.iter().zip(&self.output_panel.delay) {
+            preset.set(knob.preset_key(), value.value);
+        }
+        preset.set_flag("output.reverb", self.output_panel.reverb_enabled);
+        for (knob, value) in ReverbWindowState::KNOBS
+            .iter()
+            .zip(&self.output_panel.reverb)
+        {
+            preset.set(knob.preset_key(), value.value);
+        }
+        preset.set_flag("switch.osc_modulation", self.osc_modulation);
+        preset.set_flag("switch.osc3_control", self.osc3_control);
+        preset.set_flag("switch.osc3_lfo", self.osc3_lfo);
+        preset.set_flag("switch.lfo_sync", self.lfo_sync);
+        preset.set_flag("switch.lfo_key_sync", self.lfo_key_sync);
+        preset.set_flag("switch.mod_source_noise", self.mod_source_noise);
+        preset.set_flag("switch.mod_target_filter", self.mod_target_filter);
+        preset.set("switch.glide", self.glide_mode.index() as f32);
+        preset.set("controllers.glide_curve", self.glide_curve.index() as f32);
+        preset.set_flag(
+            "keyboard.multi_trigger",
+            self.trigger_mode == TriggerMode::Multi,
+        );
+        preset.set_flag("switch.decay", self.decay_enabled);
+        preset.set("keyboard.scale", self.scale.index() as f32);
+        preset.set("keyboard.scale_root", self.scale_root as f32);
+        preset.set(
+            "keyboard.split",
+            self.split.point.map_or(-1.0, |note| note as f32),
+        );
+        match self.split.upper {
+            UpperZone::Transpose(semitones) => {
+                preset.set_flag("keyboard.upper_mute", false);
+                preset.set("keyboard.upper_transpose", semitones as f32);
+            }
+            UpperZone::Mute => preset.set_flag("keyboard.upper_mute", true),
+        }
+        preset.set("keyboard.bend_range", self.bend_range as f32);
+        preset.set(
+            "keyboard.velocity_route",
+            self.velocity_route.index() as f32,
+        );
+        for (index, (knob, assignment)) in self.macro_knobs.iter().zip(&self.macros).enumerate() {
+            preset.set(MACRO_KNOBS[index].preset_key(), knob.value);
+            assignment.save(&mut preset, index);
+        }
+        preset
+    }
+
+    fn apply_preset(&mut self, preset: &Preset) {
+        fn load(knob: &mut KnobValue, preset: &Preset, key: &str) {
+            if let Some(value) = preset.get(key) {
+                knob.value = value.clamp(0.0, 1.0);
+            }
+        }
+        fn load_flag(flag: &mut bool, preset: &Preset, key: &str) {
+            if let Some(value) = preset.get_flag(key) {
+                *flag = value;
+            }
+        }
+
+        let controllers = &mut self.controllers;
+        load(&mut controllers.tune, preset, "controllers.tune");
+        load(&mut controllers.glide, preset, "controllers.glide");
+        load(
+            &mut controllers.modulation_mix,
+            preset,
+            "controllers.mod_mix",
+        );
+        load(
+            &mut controllers.modulation_rate,
+            preset,
+            "controllers.mod_rate",
+        );
+        load(
+            &mut controllers.modulation_amount,
+            preset,
+            "controllers.mod_amount",
+        );
+        load(
+            &mut controllers.pitch_mod_depth,
+            preset,
+            "controllers.pitch_mod_depth",
+        );
+        load(
+            &mut controllers.filter_mod_depth,
+            preset,
+            "controllers.filter_mod_depth",
+        );
+        load(&mut controllers.mod_fade, preset, "controllers.mod_fade");
+        for index in 0..3 {
+            let osc = index + 1;
+            load(
+                &mut self.oscillator.range[index],
+                preset,
+                &format!("osc{osc}.range"),
+            );
+            load(
+                &mut self.oscillator.freq[index],
+                preset,
+                &format!("osc{osc}.freq"),
+            );
+            load(
+                &mut self.oscillator.waveform[index],
+                preset,
+                &format!("osc{osc}.wave"),
+            );
+            load(
+                &mut self.mixer_panel.osc[index],
+                preset,
+                &format!("mixer.osc{osc}"),
+            );
+            load_flag(
+                &mut self.mixer_panel.osc_enabled[index],
+                preset,
+                &format!("mixer.osc{osc}.on"),
+            );
+            load(
+                &mut self.mixer_panel.pan[index],
+                preset,
+                &format!("mixer.osc{osc}.pan"),
+            );
+            load_flag(&mut self.osc_sync[index], preset, &format!("osc{osc}.sync"));
+            if let Some(wavetable) = preset.get_flag(&format!("osc{osc}.wavetable")) {
+                self.osc_engines[index] = if wavetable {
+                    OscEngine::Wavetable
+                } else {
+                    OscEngine::Classic
+                };
+            }
+        }
+        load(
+            &mut self.mixer_panel.external_input,
+            preset,
+            "mixer.external",
+        );
+        load_flag(
+            &mut self.mixer_panel.ext_enabled,
+            preset,
+            "mixer.external.on",
+        );
+        load_flag(&mut self.feedback_patch, preset, "mixer.feedback_patch");
+        load(&mut self.mixer_panel.noise, preset, "mixer.noise");
+        load(&mut self.mixer_panel.noise_pan, preset, "mixer.noise.pan");
+        load_flag(
+            &mut self.mixer_panel.noise_enabled,
+            preset,
+            "mixer.noise.on",
+        );
+        load(&mut self.oscillator.fm_depth, preset, "osc.fm_depth");
+        load_flag(&mut self.freq_snap, preset, "osc.freq_snap");
+        if let Some(mode) = preset
+            .get("engine.oversampling")
+            .and_then(|index| Oversampling::VALUES.get(index.round().max(0.0) as usize))
+        {
+            self.oversampling = *mode;
+        }
+        if let Some(mode) = preset
+            .get("osc.phase_mode")
+            .and_then(|index| PhaseMode::VALUES.get(index.max(0.0) as usize))
+        {
+            self.phase_mode = *mode;
+        }
+        load(&mut self.mixer_panel.ring_mod, preset, "mixer.ring");
+        load_flag(&mut self.mixer_panel.ring_enabled, preset, "mixer.ring.on");
+        if let Some(color) = preset
+            .get("mixer.noise_color")
+            .and_then(|index| NoiseColor::VALUES.get(index.max(0.0) as usize))
+        {
+            self.set_noise_color(*color);
+        }
+        // Patches from before the COLOR knob only carry the color index.
+        load(
+            &mut self.mixer_panel.noise_color,
+            preset,
+            "mixer.noise.color",
+        );
+        for (knob, value) in FollowerWindowState::KNOBS
+            .iter()
+            .zip(&mut self.mixer_panel.follower)
+        {
+            load(value, preset, knob.preset_key());
+        }
+        let modifiers = &mut self.modifiers_panel;
+        load(&mut modifiers.filter[0], preset, "filter.cutoff");
+        load(&mut modifiers.filter[1], preset, "filter.emphasis");
+        load(&mut modifiers.filter[2], preset, "filter.contour");
+        if let Some(slope) = preset
+            .get("filter.slope")
+            .and_then(|index| FilterSlope::VALUES.get(index.round().max(0.0) as usize))
+        {
+            modifiers.filter_slope = *slope;
+        }
+        if let Some(kind) = preset
+            .get("filter.type")
+            .and_then(|index| FilterType::VALUES.get(index.round().max(0.0) as usize))
+        {
+            modifiers.filter_type = *kind;
+        }
+        load(&mut modifiers.filter_env[0], preset, "filter_env.attack");
+        load(&mut modifiers.filter_env[1], preset, "filter_env.decay");
+        load(&mut modifiers.filter_env[2], preset, "filter_env.sustain");
+        load(
+            &mut modifiers.loudness_env[0],
+            preset,
+            "loudness_env.attack",
+        );
+        load(&mut modifiers.loudness_env[1], preset, "loudness_env.decay");
+        load(
+            &mut modifiers.loudness_env[2],
+            preset,
+            "loudness_env.sustain",
+        );
+        if let Some(curve) = preset
+            .get("filter_env.curve")
+            .and_then(|index| EnvelopeCurve::VALUES.get(index.round().max(0.0) as usize))
+        {
+            modifiers.filter_env_curve = *curve;
+        }
+        load(
+            &mut modifiers.filter_env_stages[0],
+            preset,
+            "filter_env.delay",
+        );
+        load(
+            &mut modifiers.filter_env_stages[1],
+            preset,
+            "filter_env.hold",
+        );
+        load(
+            &mut modifiers.filter_env_stages[2],
+            preset,
+            "filter_env.release",
+        );
+        load(
+            &mut modifiers.loudness_env_stages[0],
+            preset,
+            "loudness_env.delay",
+        );
+        load(
+            &mut modifiers.loudness_env_stages[1],
+            preset,
+            "loudness_env.hold",
+        );
+        load(
+            &mut modifiers.loudness_env_stages[2],
+            preset,
+            "loudness_env.release",
+        );
+        load_flag(&mut modifiers.filter_env_loop, preset, "filter_env.loop");
+        if let Some(curve) = preset
+            .get("loudness_env.curve")
+            .and_then(|index| EnvelopeCurve::VALUES.get(index.round().max(0.0) as usize))
+        {
+            modifiers.loudness_env_curve = *curve;
+        }
+        load(
+            &mut self.output_panel.main_volume,
+            preset,
+            "output.main_volume",
+        );
+        load(
+            &mut self.output_panel.phones_volume,
+            preset,
+            "output.phones_volume",
+        );
+        load_flag(&mut self.rumble_filter, preset, "output.rumble_filter");
+        load_flag(&mut self.limiter_enabled, preset, "output.limiter");
+        load_flag(
+            &mut self.output_panel.chorus_enabled,
+            preset,
+            "output.chorus",
+        );
+        for (knob, value) in ChorusWindowState::KNOBS
+            .iter()
+            .zip(&mut self.output_panel.chorus)
+        {
+            load(value, preset, knob.preset_key());
+        }
+        load_flag(&mut self.output_panel.delay_enabled, preset, "output.delay");
+        load_flag(&mut self.output_panel.delay_sync, preset, "delay.sync");
+        for (knob, value) in DelayWindowState::KNOBS
+            .iter()
+            .zip(&mut self.output_panel.delay)
+        {
+            load(value, preset, knob.preset_key());
+        }
+        load_flag(
+            &mut self.output_panel.reverb_enabled,
+            preset,
+            "output.reverb",
+        );
+        for (knob, value) in ReverbWindowState::KNOBS
+            .iter()
+            .zip(&mut self.output_panel.reverb)
+        {
+            load(value, preset, knob.preset_key());
+        }
+        load_flag(&mut self.osc_modulation, preset, "switch.osc_modulation");
+        load_flag(&mut self.osc3_control, preset, "switch.osc3_control");
+        load_flag(&mut self.osc3_lfo, preset, "switch.osc3_lfo");
+        load_flag(&mut self.lfo_sync, preset, "switch.lfo_sync");
+        load_flag(&mut self.lfo_key_sync, preset, "switch.lfo_key_sync");
+        load_flag(
+            &mut self.mod_source_noise,
+            preset,
+            "switch.mod_source_noise",
+        );
+        load_flag(
+            &mut self.mod_target_filter,
+            preset,
+            "switch.mod_target_filter",
+        );
+        if let Some(mode) = preset
+            .get("switch.glide")
+            .and_then(|index| GlideMode::VALUES.get(index.round().max(0.0) as usize))
+        {
+            self.glide_mode = *mode;
+        }
+        if let Some(curve) = preset
+            .get("controllers.glide_curve")
+            .and_then(|index| GlideCurve::VALUES.get(index.round().max(0.0) as usize))
+        {
+            self.glide_curve = *curve;
+        }
+        if let Some(multi) = preset.get_flag("keyboard.multi_trigger") {
+            self.trigger_mode = if multi {
+                TriggerMode::Multi
+            } else {
+                TriggerMode::Single
+            };
+        }
+        load_flag(&mut self.decay_enabled, preset, "switch.decay");
+        if let Some(scale) = preset
+            .get("keyboard.scale")
+            .and_then(|index| Scale::VALUES.get(index.round().max(0.0) as usize))
+        {
+            self.scale = *scale;
+        }
+        if let Some(root) = preset.get("keyboard.scale_root") {
+            self.scale_root = (root.round() as i32).rem_euclid(12);
+        }
+        if let Some(point) = preset.get("keyboard.split") {
+            self.split.point = (point >= 0.0).then(|| (point.round() as i32).clamp(0, 127));
+        }
+        if let Some(semitones) = preset.get("keyboard.upper_transpose") {
+            let max = UpperZone::MAX_TRANSPOSE;
+            self.split.upper = UpperZone::Transpose((semitones.round() as i32).clamp(-max, max));
+        }
+        if preset.get_flag("keyboard.upper_mute") == Some(true) {
+            self.split.upper = UpperZone::Mute;
+        }
+        if let Some(range) = preset.get("keyboard.bend_range") {
+            self.bend_range = (range.round() as i32).clamp(1, 12);
+        }
+        if let Some(route) = preset
+            .get("keyboard.velocity_route")
+            .and_then(|index| VelocityRoute::VALUES.get(index.round().max(0.0) as usize))
+        {
+            self.velocity_route = *route;
+        }
+        // Only the amounts: a partial update turns the targets as if the
+        // macro were moved by hand.
+        for (knob, id) in self.macro_knobs.iter_mut().zip(MACRO_KNOBS) {
+            load(knob, preset, id.preset_key());
+        }
+    }
 }
 
-struct DebugWindowState {
+struct PresetBrowserState {
     open: bool,
     rect: Rect,
-    sample_rate: f32,
+    entries: Vec<PathBuf>,
+    selected: Option<usize>,
+    scroll: usize,
+    naming: Option<String>,
+    current_name: String,
+    status: String,
 }
 
-impl DebugWindowState {
+impl PresetBrowserState {
     fn new() -> Self {
         Self {
-            open: true,
-            rect: Rect::new(20.0, 20.0, 400.0, 400.0),
-            sample_rate: 44_100.0,
+            open: false,
+            rect: Rect::new(380.0, 40.0, 400.0, 430.0),
+            entries: Vec::new(),
+            selected: None,
+            scroll: 0,
+            naming: None,
+            current_name: "INIT".into(),
+            status: String::new(),
         }
     }
 
-    fn set_sample_rate(&mut self, sr: f32) {
-        self.sample_rate = sr;
+    fn refresh(&mut self) {
+        self.entries = list_presets(Path::new(PRESET_DIR));
+        if self.entries.is_empty() {
+            self.selected = None;
+        } else if let Some(selected) = self.selected {
+            self.selected = Some(selected.min(self.entries.len() - 1));
+        }
+        self.scroll = self.scroll.min(self.entries.len().saturating_sub(1));
     }
-}
 
-#[derive(Default)]
-struct KnobDragState {
-    active_knob: Option<KnobId>,
-    origin_value: f32,
-    origin_y: f32,
+    fn is_typing(&self) -> bool {
+        self.open && self.naming.is_some()
+    }
+
+    fn captures(&self, point: Vec2) -> bool {
+        self.open && self.rect.contains(point)
+    }
+
+    fn entry_name(path: &Path) -> String {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    fn close_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 32.0,
+            self.rect.y + 8.0,
+            24.0,
+            24.0,
+        )
+    }
+
+    fn list_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + 16.0,
+            self.rect.y + 44.0,
+            self.rect.w - 32.0,
+            PRESET_VISIBLE_ROWS as f32 * PRESET_ROW_HEIGHT,
+        )
+    }
+
+    fn row_rect(&self, row: usize) -> Rect {
+        let list = self.list_rect();
+        Rect::new(
+            list.x,
+            list.y + row as f32 * PRESET_ROW_HEIGHT,
+            list.w,
+            PRESET_ROW_HEIGHT,
+        )
+    }
+
+    fn save_button_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + 16.0,
+            self.rect.y + self.rect.h - 48.0,
+            110.0,
+            32.0,
+        )
+    }
+
+    fn name_field_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + 16.0,
+            self.rect.y + self.rect.h - 92.0,
+            self.rect.w - 32.0,
+            30.0,
+        )
+    }
+
+    fn ensure_visible(&mut self, index: usize) {
+        if index < self.scroll {
+            self.scroll = index;
+        } else if index >= self.scroll + PRESET_VISIBLE_ROWS {
+            self.scroll = index + 1 - PRESET_VISIBLE_ROWS;
+        }
+    }
 }
 
-#[derive(Clone)]
-struct KnobValue {
-    value: f32,
-    implemented: bool,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GlideMode {
+    Off,
+    On,
+    Legato,
 }
 
-impl KnobValue {
-    fn implemented(value: f32) -> Self {
-        Self {
-            value,
-            implemented: true,
-        }
+impl GlideMode {
+    const VALUES: [GlideMode; 3] = [GlideMode::Off, GlideMode::On, GlideMode::Legato];
+
+    fn index(self) -> usize {
+        Self::VALUES
+            .iter()
+            .position(|mode| *mode == self)
+            .unwrap_or(0)
     }
 
-    fn stub(value: f32) -> Self {
-        Self {
-            value,
-            implemented: false,
+    fn next(self) -> Self {
+        Self::VALUES[(self.index() + 1) % Self::VALUES.len()]
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            GlideMode::Off => "OFF",
+            GlideMode::On => "ON",
+            GlideMode::Legato => "LEGATO",
         }
     }
 }
 
-#[derive(Clone)]
-struct ControllerKnobs {
-    tune: KnobValue,
-    glide: KnobValue,
-    modulation_mix: KnobValue,
-    modulation_rate: KnobValue,
-    modulation_amount: KnobValue,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TriggerMode {
+    Single,
+    Multi,
 }
 
-impl ControllerKnobs {
-    fn new() -> Self {
-        Self {
-            tune: KnobValue::implemented(0.5),
-            glide: KnobValue::implemented(0.3),
-            modulation_mix: KnobValue::implemented(0.5),
-            modulation_rate: KnobValue::implemented(0.5),
-            modulation_amount: KnobValue::implemented(0.6),
+impl TriggerMode {
+    fn label(self) -> &'static str {
+        match self {
+            TriggerMode::Single => "SINGLE",
+            TriggerMode::Multi => "MULTI",
         }
     }
 }
 
-#[derive(Clone)]
-struct OscillatorKnobs {
-    range: [KnobValue; 3],
-    freq: [KnobValue; 3],
-    waveform: [KnobValue; 3],
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GlideCurve {
+    Exponential,
+    ConstantRate,
+    ConstantTime,
 }
 
-impl OscillatorKnobs {
-    fn new() -> Self {
-        Self {
-            range: std::array::from_fn(|_| KnobValue::implemented(range_value_from_index(3))),
-            freq: [
-                KnobValue::implemented(0.5),
-                KnobValue::implemented(detune_to_value(0.03)),
-                KnobValue::implemented(detune_to_value(-0.02)),
-            ],
-            waveform: [
-                KnobValue::implemented(waveform_to_value(Waveform::Triangle, &OSC1_WAVES)),
-                KnobValue::implemented(waveform_to_value(Waveform::Triangle, &OSC2_WAVES)),
-                KnobValue::implemented(waveform_to_value(Waveform::Triangle, &OSC3_WAVES)),
-            ],
+impl GlideCurve {
+    const VALUES: [GlideCurve; 3] = [
+        GlideCurve::Exponential,
+        GlideCurve::ConstantRate,
+        GlideCurve::ConstantTime,
+    ];
+
+    fn index(self) -> usize {
+        Self::VALUES
+            .iter()
+            .position(|curve| *curve == self)
+            .unwrap_or(0)
+    }
+
+    fn next(self) -> Self {
+        Self::VALUES[(self.index() + 1) % Self::VALUES.len()]
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            GlideCurve::Exponential => "EXP",
+            GlideCurve::ConstantRate => "RATE",
+            GlideCurve::ConstantTime => "TIME",
         }
     }
 }
 
-#[derive(Clone)]
-struct MixerKnobs {
-    external_input: KnobValue,
-    osc: [KnobValue; 3],
-    noise: KnobValue,
-    osc_enabled: [bool; 3],
-    ext_enabled: bool,
-    noise_enabled: bool,
-    noise_color: NoiseColor,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PanelOption {
+    Osc3Lfo,
+    ModDestination,
+    Osc1Engine,
+    Osc2Engine,
+    Osc3Engine,
+    Osc2Sync,
+    Osc3Sync,
+    RingMod,
+    OscPhase,
+    GlideCurve,
+    KeyTrigger,
+    Oversampling,
+    RumbleFilter,
+    Limiter,
+    FeedbackPatch,
+    AudioBuffer,
+    LfoSync,
+    LfoKeySync,
+    ClockSource,
+    Metronome,
+    Tempo,
+    Scale,
+    ScaleRoot,
+    SplitPoint,
+    UpperZone,
+    Temperament,
+    ReferencePitch,
+    BendRange,
+    VelocityRoute,
+    KeyLayout,
 }
 
-impl MixerKnobs {
-    fn new() -> Self {
-        Self {
-            external_input: KnobValue::stub(0.0),
-            osc: [
-                KnobValue::implemented(0.85),
-                KnobValue::implemented(0.7),
-                KnobValue::implemented(0.55),
-            ],
-            noise: KnobValue::implemented(0.0),
-            osc_enabled: [true; 3],
-            ext_enabled: true,
-            noise_enabled: true,
-            noise_color: NoiseColor::White,
+impl PanelOption {
+    const ALL: [PanelOption; 30] = [
+        PanelOption::Osc3Lfo,
+        PanelOption::ModDestination,
+        PanelOption::Osc1Engine,
+        PanelOption::Osc2Engine,
+        PanelOption::Osc3Engine,
+        PanelOption::Osc2Sync,
+        PanelOption::Osc3Sync,
+        PanelOption::RingMod,
+        PanelOption::OscPhase,
+        PanelOption::GlideCurve,
+        PanelOption::KeyTrigger,
+        PanelOption::Oversampling,
+        PanelOption::RumbleFilter,
+        PanelOption::Limiter,
+        PanelOption::FeedbackPatch,
+        PanelOption::AudioBuffer,
+        PanelOption::LfoSync,
+        PanelOption::LfoKeySync,
+        PanelOption::ClockSource,
+        PanelOption::Metronome,
+        PanelOption::Tempo,
+        PanelOption::Scale,
+        PanelOption::ScaleRoot,
+        PanelOption::SplitPoint,
+        PanelOption::UpperZone,
+        PanelOption::Temperament,
+        PanelOption::ReferencePitch,
+        PanelOption::BendRange,
+        PanelOption::VelocityRoute,
+        PanelOption::KeyLayout,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            PanelOption::Osc3Lfo => "OSC 3 MODE",
+            PanelOption::ModDestination => "MOD DEST",
+            PanelOption::Osc1Engine => "OSC 1 CORE",
+            PanelOption::Osc2Engine => "OSC 2 CORE",
+            PanelOption::Osc3Engine => "OSC 3 CORE",
+            PanelOption::Osc2Sync => "OSC 2 SYNC",
+            PanelOption::Osc3Sync => "OSC 3 SYNC",
+            PanelOption::RingMod => "RING MOD 1x2",
+            PanelOption::OscPhase => "OSC PHASE",
+            PanelOption::GlideCurve => "GLIDE CURVE",
+            PanelOption::KeyTrigger => "KEY TRIGGER",
+            PanelOption::Oversampling => "OVERSAMPLE",
+            PanelOption::RumbleFilter => "20 Hz HPF",
+            PanelOption::Limiter => "LIMITER",
+            PanelOption::FeedbackPatch => "OUT > EXT IN",
+            PanelOption::AudioBuffer => "AUDIO BUFFER",
+            PanelOption::LfoSync => "LFO SYNC",
+            PanelOption::LfoKeySync => "LFO KEY SYNC",
+            PanelOption::ClockSource => "CLOCK",
+            PanelOption::Metronome => "METRONOME",
+            PanelOption::Tempo => "TEMPO (TAP)",
+            PanelOption::Scale => "SCALE",
+            PanelOption::ScaleRoot => "SCALE ROOT",
+            PanelOption::SplitPoint => "SPLIT AT",
+            PanelOption::UpperZone => "UPPER ZONE",
+            PanelOption::Temperament => "TEMPERAMENT",
+            PanelOption::ReferencePitch => "REFERENCE",
+            PanelOption::BendRange => "MOUSE BEND",
+            PanelOption::VelocityRoute => "VELOCITY",
+            PanelOption::KeyLayout => "KEY LAYOUT",
         }
     }
 }
 
-#[derive(Clone)]
-struct ModifierKnobs {
-    filter: [KnobValue; 3],
-    filter_env: [KnobValue; 3],
-    loudness_env: [KnobValue; 3],
+struct OptionsWindowState {
+    open: bool,
+    rect: Rect,
 }
 
-impl ModifierKnobs {
+impl OptionsWindowState {
     fn new() -> Self {
         Self {
-            filter: [
-                KnobValue::implemented((2200.0 - FILTER_MIN_HZ) / (FILTER_MAX_HZ - FILTER_MIN_HZ)),
-                KnobValue::implemented(0.4),
-                KnobValue::implemented(0.5),
-            ],
-            filter_env: [
-                KnobValue::implemented(0.2),
-                KnobValue::implemented(0.5),
-                KnobValue::implemented(0.5),
-            ],
-            loudness_env: [
-                KnobValue::implemented(0.2),
-                KnobValue::implemented(0.5),
-                KnobValue::implemented(0.5),
-            ],
+            open: false,
+            rect: Rect::new(SCREEN_WIDTH - 470.0, 12.0, 450.0, 700.0),
         }
     }
+
+    fn captures(&self, point: Vec2) -> bool {
+        self.open && self.rect.contains(point)
+    }
+
+    fn close_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 32.0,
+            self.rect.y + 8.0,
+            24.0,
+            24.0,
+        )
+    }
+
+    fn knob_rect(&self, slot: usize) -> Rect {
+        let knob_size = 64.0;
+        let column = slot % OPTION_KNOBS_PER_ROW;
+        let row = slot / OPTION_KNOBS_PER_ROW;
+        let top = self.rect.y
+            + 48.0
+            + OPTION_SELECTOR_ROWS as f32 * OPTION_ROW_HEIGHT
+            + 24.0
+            + row as f32 * OPTION_KNOB_ROW_HEIGHT;
+        Rect::new(
+            self.rect.x + 24.0 + column as f32 * (knob_size + 20.0),
+            top,
+            knob_size,
+            knob_size,
+        )
+    }
+
+    fn value_rect(&self, slot: usize) -> Rect {
+        let column_width = (self.rect.w - 32.0) / OPTION_COLUMNS as f32;
+        let column = slot % OPTION_COLUMNS;
+        let row = slot / OPTION_COLUMNS;
+        Rect::new(
+            self.rect.x + 16.0 + column as f32 * column_width + column_width - 100.0,
+            self.rect.y + 48.0 + row as f32 * OPTION_ROW_HEIGHT,
+            90.0,
+            OPTION_ROW_HEIGHT - 6.0,
+        )
+    }
 }
 
-#[derive(Clone)]
-struct OutputKnobs {
-    main_volume: KnobValue,
-    phones_volume: KnobValue,
+struct MacroWindowState {
+    open: bool,
+    rect: Rect,
+    /// Macro whose ASSIGN button is lit; clicking a panel knob adds it to
+    /// or removes it from that macro.
+    learning: Option<usize>,
 }
 
-impl OutputKnobs {
+impl MacroWindowState {
     fn new() -> Self {
         Self {
-            main_volume: KnobValue::implemented(0.7),
-            phones_volume: KnobValue::stub(0.7),
-        }
-    }
-}
-
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-enum KnobId {
-    ControllersTune,
-    ControllersGlide,
-    ControllersModMix,
-    ControllersModRate,
-    ControllersModAmount,
-    OscRange1,
-    OscRange2,
-    OscRange3,
-    OscFreq1,
-    OscFreq2,
-    OscFreq3,
-    OscWave1,
-    OscWave2,
-    OscWave3,
-    MixerExternal,
-    MixerOsc1,
-    MixerOsc2,
-    MixerOsc3,
-    MixerNoise,
-    FilterCutoff,
-    FilterEmphasis,
-    FilterContour,
-    FilterAttack,
-    FilterDecay,
-    FilterSustain,
-    LoudnessAttack,
-    LoudnessDecay,
-    LoudnessSustain,
-    OutputVolume,
-    OutputPhones,
-}
-
-fn detune_to_value(detune: f32) -> f32 {
-    ((detune / DETUNE_RANGE) + 1.0) * 0.5
-}
+            open: false,
+            rect: Rect::new(20.0, PANEL_HEIGHT + 56.0, 780.0, 296.0),
+            learning: None,
+        }
+    }
 
-fn mouse_position_vec() -> Vec2 {
-    let (x, y) = mouse_position();
-    vec2(x, y)
-}
+    fn captures(&self, point: Vec2) -> bool {
+        self.open && self.rect.contains(point)
+    }
 
-fn log_toggle(name: &str, state: bool) {
-    let value = if state { "ON" } else { "OFF" };
-    println!("{name} set to {value}");
+    fn close_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 32.0,
+            self.rect.y + 8.0,
+            24.0,
+            24.0,
+        )
+    }
+
+    fn column_x(&self, index: usize) -> f32 {
+        self.rect.x + 16.0 + index as f32 * self.column_width()
+    }
+
+    fn column_width(&self) -> f32 {
+        (self.rect.w - 32.0) / MACRO_COUNT as f32
+    }
+
+    fn knob_rect(&self, index: usize) -> Rect {
+        Rect::new(self.column_x(index) + 8.0, self.rect.y + 56.0, 64.0, 64.0)
+    }
+
+    fn assign_rect(&self, index: usize) -> Rect {
+        Rect::new(
+            self.column_x(index) + 84.0,
+            self.rect.y + 74.0,
+            self.column_width() - 96.0,
+            28.0,
+        )
+    }
+
+    fn target_rect(&self, index: usize, row: usize) -> Rect {
+        Rect::new(
+            self.column_x(index),
+            self.rect.y + 152.0 + row as f32 * 22.0,
+            self.column_width() - 12.0,
+            20.0,
+        )
+    }
 }
 
-fn log_mode(name: &str, value: &str) {
-    println!("{name} set to {value}");
+/// Delay, hold and release for one contour, the stages the panel's ADS
+/// knobs leave out. Opened by clicking the contour's title.
+struct EnvelopeEditorState {
+    /// 0 for the filter contour, 1 for loudness.
+    open: Option<usize>,
+    rect: Rect,
 }
 
-fn handle_debug_toggle(state: &mut DebugWindowState, mouse: Vec2) {
-    let button_rect = Rect::new(SCREEN_WIDTH - 170.0, PANEL_HEIGHT + 25.0, 140.0, 36.0);
-    if state.open {
-        let close_rect = Rect::new(
-            state.rect.x + state.rect.w - 32.0,
-            state.rect.y + 8.0,
+impl EnvelopeEditorState {
+    const KNOBS: [[KnobId; 3]; 2] = [
+        [
+            KnobId::FilterDelay,
+            KnobId::FilterHold,
+            KnobId::FilterRelease,
+        ],
+        [
+            KnobId::LoudnessDelay,
+            KnobId::LoudnessHold,
+            KnobId::LoudnessRelease,
+        ],
+    ];
+
+    fn new() -> Self {
+        Self {
+            open: None,
+            rect: Rect::new(SCREEN_WIDTH - 540.0, PANEL_HEIGHT + 56.0, 380.0, 176.0),
+        }
+    }
+
+    fn captures(&self, point: Vec2) -> bool {
+        self.open.is_some() && self.rect.contains(point)
+    }
+
+    fn close_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 32.0,
+            self.rect.y + 8.0,
             24.0,
             24.0,
-        );
-        if close_rect.contains(mouse) && is_mouse_button_pressed(MouseButton::Left) {
-            state.open = false;
-        }
-    } else if button_rect.contains(mouse) && is_mouse_button_pressed(MouseButton::Left) {
-        state.open = true;
+        )
     }
-}
 
-fn handle_mixer_switches(panel_state: &mut PanelState, layout: &PanelLayout) {
-    if !is_mouse_button_pressed(MouseButton::Left) {
-        return;
+    fn knob_rect(&self, index: usize) -> Rect {
+        let column = (self.rect.w - 32.0) / 3.0;
+        Rect::new(
+            self.rect.x + 16.0 + column * (index as f32 + 0.5) - 32.0,
+            self.rect.y + 52.0,
+            64.0,
+            64.0,
+        )
     }
-    let mouse = mouse_position_vec();
-    for (index, rect) in layout.mixer_toggle_rects.iter().enumerate() {
-        if rect.contains(mouse) {
-            match index {
-                0..=2 => {
-                    let flag = &mut panel_state.mixer_panel.osc_enabled[index];
-                    *flag = !*flag;
-                }
-                3 => {
-                    panel_state.mixer_panel.ext_enabled = !panel_state.mixer_panel.ext_enabled;
-                }
-                4 => {
-                    panel_state.mixer_panel.noise_enabled = !panel_state.mixer_panel.noise_enabled;
-                }
-                _ => {}
-            }
+}
+
+/// The envelope follower on EXT INPUT, opened by clicking that knob's label.
+struct FollowerWindowState {
+    open: bool,
+    rect: Rect,
+}
+
+impl FollowerWindowState {
+    const KNOBS: [KnobId; 3] = [
+        KnobId::FollowerRelease,
+        KnobId::FollowerCutoff,
+        KnobId::FollowerVca,
+    ];
+
+    fn new() -> Self {
+        Self {
+            open: false,
+            rect: Rect::new(SCREEN_WIDTH - 940.0, PANEL_HEIGHT + 56.0, 380.0, 176.0),
         }
     }
-    if layout.noise_selector_rect.contains(mouse) {
-        panel_state.cycle_noise_color();
-        log_mode(
-            "Noise generator",
-            panel_state.mixer_panel.noise_color.label(),
-        );
+
+    fn captures(&self, point: Vec2) -> bool {
+        self.open && self.rect.contains(point)
+    }
+
+    fn close_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 32.0,
+            self.rect.y + 8.0,
+            24.0,
+            24.0,
+        )
+    }
+
+    fn knob_rect(&self, index: usize) -> Rect {
+        let column = (self.rect.w - 32.0) / 3.0;
+        Rect::new(
+            self.rect.x + 16.0 + column * (index as f32 + 0.5) - 32.0,
+            self.rect.y + 52.0,
+            64.0,
+            64.0,
+        )
     }
 }
 
-fn handle_controller_switches(panel_state: &mut PanelState, layout: &PanelLayout) {
-    if !is_mouse_button_pressed(MouseButton::Left) {
-        return;
+/// The chorus rate, depth and mix, opened by clicking the CHORUS label
+/// beside its bypass switch.
+struct ChorusWindowState {
+    open: bool,
+    rect: Rect,
+}
+
+impl ChorusWindowState {
+    const KNOBS: [KnobId; 3] = [KnobId::ChorusRate, KnobId::ChorusDepth, KnobId::ChorusMix];
+
+    fn new() -> Self {
+        Self {
+            open: false,
+            rect: Rect::new(SCREEN_WIDTH - 420.0, PANEL_HEIGHT + 56.0, 380.0, 176.0),
+        }
     }
-    let mouse = mouse_position_vec();
-    if layout.controller_mod_toggle.contains(mouse) {
-        panel_state.osc_modulation = !panel_state.osc_modulation;
-        log_toggle("Oscillator modulation", panel_state.osc_modulation);
+
+    fn captures(&self, point: Vec2) -> bool {
+        self.open && self.rect.contains(point)
     }
-    if layout.controller_osc3_toggle.contains(mouse) {
-        panel_state.osc3_control = !panel_state.osc3_control;
-        log_toggle("Oscillator 3 control", panel_state.osc3_control);
+
+    fn close_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 32.0,
+            self.rect.y + 8.0,
+            24.0,
+            24.0,
+        )
     }
-    if layout.controller_mod_source_toggle.contains(mouse) {
-        panel_state.mod_source_noise = !panel_state.mod_source_noise;
-        log_mode(
-            "Mod source",
-            if panel_state.mod_source_noise {
-                "NOISE"
-            } else {
-                "LFO"
-            },
-        );
+
+    fn knob_rect(&self, index: usize) -> Rect {
+        let column = (self.rect.w - 32.0) / 3.0;
+        Rect::new(
+            self.rect.x + 16.0 + column * (index as f32 + 0.5) - 32.0,
+            self.rect.y + 52.0,
+            64.0,
+            64.0,
+        )
     }
-    if layout.controller_mod_target_toggle.contains(mouse) {
-        panel_state.mod_target_filter = !panel_state.mod_target_filter;
-        log_mode(
-            "Mod destination",
-            if panel_state.mod_target_filter {
-                "FILTER EG"
-            } else {
-                "OSC 3"
-            },
-        );
+}
+
+/// The delay's time, feedback, damping and mix, opened by clicking the
+/// DELAY label beside its bypass switch. SYNC turns TIME into a note length
+/// at the clock tempo.
+struct DelayWindowState {
+    open: bool,
+    rect: Rect,
+}
+
+impl DelayWindowState {
+    const KNOBS: [KnobId; 4] = [
+        KnobId::DelayTime,
+        KnobId::DelayFeedback,
+        KnobId::DelayDamping,
+        KnobId::DelayMix,
+    ];
+
+    fn new() -> Self {
+        Self {
+            open: false,
+            rect: Rect::new(SCREEN_WIDTH - 880.0, PANEL_HEIGHT + 56.0, 440.0, 176.0),
+        }
     }
-    if layout.controller_glide_switch.contains(mouse) {
-        panel_state.glide_enabled = !panel_state.glide_enabled;
-        log_toggle("Glide", panel_state.glide_enabled);
+
+    fn captures(&self, point: Vec2) -> bool {
+        self.open && self.rect.contains(point)
     }
-    if layout.controller_decay_switch.contains(mouse) {
-        panel_state.decay_enabled = !panel_state.decay_enabled;
-        log_toggle("Decay", panel_state.decay_enabled);
+
+    fn close_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 32.0,
+            self.rect.y + 8.0,
+            24.0,
+            24.0,
+        )
     }
-    if layout.controller_s_trigger_button.contains(mouse) {
-        panel_state.request_s_trigger();
-        println!("S-TRIG fired");
+
+    fn sync_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 104.0,
+            self.rect.y + 8.0,
+            64.0,
+            24.0,
+        )
+    }
+
+    fn knob_rect(&self, index: usize) -> Rect {
+        let column = (self.rect.w - 32.0) / 4.0;
+        Rect::new(
+            self.rect.x + 16.0 + column * (index as f32 + 0.5) - 32.0,
+            self.rect.y + 52.0,
+            64.0,
+            64.0,
+        )
     }
 }
 
-fn draw_scene(
-    texture: &Texture2D,
-    panel_state: &mut PanelState,
-    knob_drag: &mut KnobDragState,
-    controller: &KeyboardController,
-    layout: &PanelLayout,
-    keyboard_layout: &KeyboardLayout,
-    waveform: &[f32],
-    spectrum: &[f32],
-    debug_window: &DebugWindowState,
-) {
-    clear_background(BACKGROUND);
-    draw_texture_ex(
-        texture,
-        0.0,
-        0.0,
-        Color::new(1.0, 1.0, 1.0, 0.6),
-        DrawTextureParams {
-            dest_size: Some(vec2(SCREEN_WIDTH, PANEL_HEIGHT)),
-            source: Some(Rect::new(0.0, 0.0, texture.width(), texture.height())),
-            ..Default::default()
-        },
-    );
+/// The reverb's size, damping and mix, opened by clicking the REVERB label
+/// beside its bypass switch.
+struct ReverbWindowState {
+    open: bool,
+    rect: Rect,
+}
 
-    draw_section(&layout.controller_rect, "CONTROLLERS");
-    draw_section(&layout.oscillator_rect, "OSCILLATOR BANK");
-    draw_section(&layout.mixer_rect, "MIXER");
-    draw_section(&layout.modifier_rect, "MODIFIERS");
-    draw_section(&layout.output_rect, "OUTPUT");
+impl ReverbWindowState {
+    const KNOBS: [KnobId; 3] = [KnobId::ReverbSize, KnobId::ReverbDamping, KnobId::ReverbMix];
 
-    draw_controllers_panel(panel_state, knob_drag, layout);
-    draw_oscillators(panel_state, knob_drag, layout);
-    draw_mixer(panel_state, knob_drag, layout);
-    draw_modifiers(panel_state, knob_drag, layout);
-    draw_output_panel(panel_state, knob_drag, layout);
-    draw_keyboard(controller, keyboard_layout);
-    draw_debug_button(debug_window);
-    if debug_window.open {
-        draw_debug_window(debug_window, waveform, spectrum);
+    fn new() -> Self {
+        Self {
+            open: false,
+            rect: Rect::new(SCREEN_WIDTH - 400.0, PANEL_HEIGHT + 96.0, 380.0, 176.0),
+        }
+    }
+
+    fn captures(&self, point: Vec2) -> bool {
+        self.open && self.rect.contains(point)
+    }
+
+    fn close_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 32.0,
+            self.rect.y + 8.0,
+            24.0,
+            24.0,
+        )
+    }
+
+    fn knob_rect(&self, index: usize) -> Rect {
+        let column = (self.rect.w - 32.0) / 3.0;
+        Rect::new(
+            self.rect.x + 16.0 + column * (index as f32 + 0.5) - 32.0,
+            self.rect.y + 52.0,
+            64.0,
+            64.0,
+        )
+    }
+}
+
+struct DebugWindowState {
+    open: bool,
+    rect: Rect,
+    sample_rate: f32,
+    block_frames: usize,
+    latency: f32,
+    drag_offset: Option<Vec2>,
+    frequency_axis: FrequencyAxis,
+    spectrum_view: SpectrumView,
+    probe_point: ProbePoint,
+    scope_point: ProbePoint,
+    tuner_reading: Option<TunerReading>,
+    test_signal: TestSignal,
+    dsp_load: f32,
+    dsp_peak: f32,
+    ui_work: f32,
+    ui_frame: f32,
+    glitches: GlitchLog,
+}
+
+impl DebugWindowState {
+    fn new() -> Self {
+        Self {
+            open: true,
+            rect: Rect::new(20.0, 20.0, 400.0, 400.0),
+            sample_rate: 44_100.0,
+            block_frames: 0,
+            latency: 0.0,
+            drag_offset: None,
+            frequency_axis: FrequencyAxis::Log,
+            spectrum_view: SpectrumView::Graph,
+            probe_point: ProbePoint::Output,
+            scope_point: ProbePoint::Output,
+            tuner_reading: None,
+            test_signal: TestSignal::Off,
+            dsp_load: 0.0,
+            dsp_peak: 0.0,
+            ui_work: 0.0,
+            ui_frame: 0.0,
+            glitches: GlitchLog::default(),
+        }
+    }
+
+    fn title_rect(&self) -> Rect {
+        Rect::new(self.rect.x, self.rect.y, self.rect.w, 40.0)
+    }
+
+    fn close_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 32.0,
+            self.rect.y + 8.0,
+            24.0,
+            24.0,
+        )
+    }
+
+    /// The XRUN tally under the title; hovering lists the recent glitches,
+    /// clicking clears them.
+    fn glitch_rect(&self) -> Rect {
+        Rect::new(self.rect.x + 12.0, self.rect.y + 24.0, 120.0, 14.0)
+    }
+
+    fn export_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.rect.w - 100.0,
+            self.rect.y + 8.0,
+            60.0,
+            24.0,
+        )
+    }
+
+    fn scope_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.x + 16.0,
+            self.rect.y + 52.0,
+            self.rect.w - 32.0,
+            110.0,
+        )
+    }
+
+    fn spectrum_rect(&self) -> Rect {
+        let scope = self.scope_rect();
+        Rect::new(
+            self.rect.x + 16.0,
+            scope.y + scope.h + 24.0,
+            self.rect.w - 32.0,
+            self.rect.h - scope.h - 90.0,
+        )
+    }
+
+    fn scope_button_rect(&self) -> Rect {
+        let scope = self.scope_rect();
+        Rect::new(scope.x + scope.w - 52.0, scope.y + 4.0, 48.0, 20.0)
+    }
+
+    fn axis_button_rect(&self) -> Rect {
+        let spectrum = self.spectrum_rect();
+        Rect::new(spectrum.x + spectrum.w - 44.0, spectrum.y + 4.0, 40.0, 20.0)
+    }
+
+    // Sits in the gap between the scope and the spectrum.
+    fn tuner_button_rect(&self) -> Rect {
+        let scope = self.scope_rect();
+        Rect::new(scope.x, scope.y + scope.h + 2.0, 48.0, 20.0)
+    }
+
+    fn view_button_rect(&self) -> Rect {
+        let axis = self.axis_button_rect();
+        Rect::new(axis.x - 64.0, axis.y, 60.0, axis.h)
+    }
+
+    fn generator_button_rect(&self) -> Rect {
+        let view = self.view_button_rect();
+        Rect::new(view.x - 84.0, view.y, 80.0, view.h)
+    }
+
+    /// Closing the window silences the generator so it can't keep playing
+    /// out of sight.
+    fn active_test_signal(&self) -> TestSignal {
+        if self.open {
+            self.test_signal
+        } else {
+            TestSignal::Off
+        }
+    }
+
+    fn captures(&self, point: Vec2) -> bool {
+        self.drag_offset.is_some()
+            || (self.open
+                && (self.title_rect().contains(point)
+                    || self.axis_button_rect().contains(point)
+                    || self.view_button_rect().contains(point)
+                    || self.generator_button_rect().contains(point)
+                    || self.scope_button_rect().contains(point)
+                    || self.tuner_button_rect().contains(point)))
+    }
+
+    /// Keeps the title bar on screen so a restored position can't strand
+    /// the window out of reach.
+    fn move_to(&mut self, position: Vec2) {
+        self.rect.x = position.x.clamp(0.0, SCREEN_WIDTH - self.rect.w);
+        self.rect.y = position.y.clamp(0.0, SCREEN_HEIGHT - 36.0);
+    }
+
+    fn set_sample_rate(&mut self, sr: f32) {
+        self.sample_rate = sr;
+    }
+
+    // The peak holds and falls slowly so a single slow callback stays
+    // readable.
+    fn record_dsp_load(&mut self, (load, peak): (f32, f32), dt: f32) {
+        self.dsp_load = load;
+        self.dsp_peak = peak.max(self.dsp_peak - dt * LOAD_PEAK_FALL_PER_SEC);
+    }
+
+    /// `work` is the time the UI loop spent before handing the frame off,
+    /// `frame` the full frame period including the wait for vsync.
+    fn record_ui_frame(&mut self, work: f32, frame: f32) {
+        self.ui_work += (work - self.ui_work) * UI_FRAME_SMOOTHING;
+        self.ui_frame += (frame - self.ui_frame) * UI_FRAME_SMOOTHING;
+    }
+
+    // Stamped on arrival; the UI sees glitches within a frame of them
+    // happening, close enough to line them up with setting changes.
+    fn record_glitch(&mut self, glitch: Glitch) {
+        let entry = GlitchEntry {
+            time: chrono::Local::now().format("%H:%M:%S%.3f").to_string(),
+            glitch,
+            block_frames: self.block_frames,
+            sample_rate: self.sample_rate,
+        };
+        warn!(
+            block_frames = entry.block_frames,
+            sample_rate = entry.sample_rate,
+            "Audio glitch: {} at {}",
+            glitch.label(),
+            entry.time
+        );
+        self.glitches.total += 1;
+        if self.glitches.recent.len() == GLITCH_LOG_LINES {
+            self.glitches.recent.pop_front();
+        }
+        self.glitches.recent.push_back(entry);
+    }
+
+    fn ui_load(&self) -> f32 {
+        self.ui_work / self.ui_frame.max(f32::EPSILON)
+    }
+}
+
+struct GlitchEntry {
+    time: String,
+    glitch: Glitch,
+    block_frames: usize,
+    sample_rate: f32,
+}
+
+#[derive(Default)]
+struct GlitchLog {
+    total: usize,
+    recent: VecDeque<GlitchEntry>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FrequencyAxis {
+    Linear,
+    Log,
+}
+
+impl FrequencyAxis {
+    fn next(self) -> Self {
+        match self {
+            FrequencyAxis::Linear => FrequencyAxis::Log,
+            FrequencyAxis::Log => FrequencyAxis::Linear,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            FrequencyAxis::Linear => "LIN",
+            FrequencyAxis::Log => "LOG",
+        }
+    }
+
+    /// Horizontal position across the plot, 0..1, or `None` when the
+    /// frequency falls outside the axis.
+    fn position(self, freq: f32) -> Option<f32> {
+        let ratio = match self {
+            FrequencyAxis::Linear => freq / MAX_ANALYZER_FREQ,
+            FrequencyAxis::Log => {
+                if freq <= 0.0 {
+                    return None;
+                }
+                (freq / LOG_ANALYZER_MIN_FREQ).log10()
+                    / (LOG_ANALYZER_MAX_FREQ / LOG_ANALYZER_MIN_FREQ).log10()
+            }
+        };
+        (0.0..=1.0).contains(&ratio).then_some(ratio)
+    }
+
+    fn frequency_at(self, ratio: f32) -> f32 {
+        match self {
+            FrequencyAxis::Linear => ratio * MAX_ANALYZER_FREQ,
+            FrequencyAxis::Log => {
+                LOG_ANALYZER_MIN_FREQ * (LOG_ANALYZER_MAX_FREQ / LOG_ANALYZER_MIN_FREQ).powf(ratio)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SpectrumView {
+    Graph,
+    Waterfall,
+    Filter,
+}
+
+impl SpectrumView {
+    fn next(self) -> Self {
+        match self {
+            SpectrumView::Graph => SpectrumView::Waterfall,
+            SpectrumView::Waterfall => SpectrumView::Filter,
+            SpectrumView::Filter => SpectrumView::Graph,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SpectrumView::Graph => "GRAPH",
+            SpectrumView::Waterfall => "WFALL",
+            SpectrumView::Filter => "FILTER",
+        }
+    }
+}
+
+/// The panel's filter settings, as the FILTER view plots them.
+struct FilterCurve {
+    kind: FilterType,
+    slope: FilterSlope,
+    cutoff: f32,
+    emphasis: f32,
+    contour: f32,
+}
+
+impl FilterCurve {
+    fn from_panel(panel_state: &PanelState) -> Self {
+        let modifiers = &panel_state.modifiers_panel;
+        Self {
+            kind: modifiers.filter_type,
+            slope: modifiers.filter_slope,
+            cutoff: panel_state.cutoff_hz(),
+            emphasis: modifiers.filter[1].value,
+            contour: modifiers.filter[2].value,
+        }
+    }
+}
+
+/// Scrolling spectrogram: each analyzer frame becomes one row, newest on
+/// top, with columns laid out on the same frequency axis as the graph.
+struct Waterfall {
+    image: Image,
+    texture: Texture2D,
+    axis: FrequencyAxis,
+}
+
+impl Waterfall {
+    fn new() -> Self {
+        let image = Image::gen_image_color(WATERFALL_COLUMNS, WATERFALL_ROWS, BLACK);
+        let texture = Texture2D::from_image(&image);
+        texture.set_filter(FilterMode::Linear);
+        Self {
+            image,
+            texture,
+            axis: FrequencyAxis::Log,
+        }
+    }
+
+    fn push(&mut self, spectrum: &[f32], sample_rate: f32, axis: FrequencyAxis) {
+        if spectrum.is_empty() {
+            return;
+        }
+        // Rows drawn against the other axis would no longer line up.
+        if axis != self.axis {
+            self.axis = axis;
+            for pixel in self.image.bytes.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[0, 0, 0, 255]);
+            }
+        }
+        let row_bytes = WATERFALL_COLUMNS as usize * 4;
+        let total = self.image.bytes.len();
+        self.image
+            .bytes
+            .copy_within(0..total - row_bytes, row_bytes);
+
+        let bin_hz = sample_rate * 0.5 / spectrum.len() as f32;
+        let last_bin = spectrum.len() - 1;
+        for column in 0..WATERFALL_COLUMNS as usize {
+            // Loudest bin under the column, so narrow peaks survive on the
+            // compressed top end of the log axis.
+            let edge = |offset: usize| {
+                axis.frequency_at((column + offset) as f32 / WATERFALL_COLUMNS as f32) / bin_hz
+            };
+            let low = (edge(0).round() as usize).min(last_bin);
+            let high = (edge(1).round() as usize).clamp(low, last_bin);
+            let magnitude = spectrum[low..=high].iter().copied().fold(0.0, f32::max);
+            let db = 20.0 * magnitude.max(1e-9).log10();
+            let level =
+                ((db - WATERFALL_MIN_DB) / (WATERFALL_MAX_DB - WATERFALL_MIN_DB)).clamp(0.0, 1.0);
+            self.image.bytes[column * 4..column * 4 + 4].copy_from_slice(&heat_color(level));
+        }
+        self.texture.update(&self.image);
+    }
+}
+
+// Black through dim amber to near white, matching the panel palette.
+fn heat_color(level: f32) -> [u8; 4] {
+    let (r, g, b) = if level < 0.5 {
+        let t = level * 2.0;
+        (0.78 * t, 0.35 * t, 0.04 * t)
+    } else {
+        let t = (level - 0.5) * 2.0;
+        (0.78 + 0.22 * t, 0.35 + 0.55 * t, 0.04 + 0.6 * t)
+    };
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8, 255]
+}
+
+#[derive(Default)]
+struct KnobDragState {
+    active_knob: Option<KnobId>,
+    /// Pointer height last frame; the drag moves the knob incrementally so
+    /// switching to fine control mid-drag doesn't make it jump.
+    last_y: f32,
+    /// Dragged with the right button, which is always fine.
+    right_drag: bool,
+    last_click: Option<(KnobId, f64)>,
+    /// The knob under the pointer (or being dragged) this frame, for its
+    /// tooltip.
+    tooltip: Option<(KnobId, f32, Rect)>,
+    /// A knob clicked this frame, for macro assignment.
+    pressed: Option<KnobId>,
+    input_blocked: bool,
+}
+
+#[derive(Clone)]
+struct KnobValue {
+    value: f32,
+    implemented: bool,
+}
+
+impl KnobValue {
+    fn implemented(value: f32) -> Self {
+        Self {
+            value,
+            implemented: true,
+        }
+    }
+
+    fn default_for(id: KnobId) -> Self {
+        Self::implemented(id.default_value())
+    }
+}
+
+#[derive(Clone)]
+struct ControllerKnobs {
+    tune: KnobValue,
+    glide: KnobValue,
+    modulation_mix: KnobValue,
+    modulation_rate: KnobValue,
+    modulation_amount: KnobValue,
+    pitch_mod_depth: KnobValue,
+    filter_mod_depth: KnobValue,
+    mod_fade: KnobValue,
+}
+
+impl ControllerKnobs {
+    fn new() -> Self {
+        Self {
+            tune: KnobValue::default_for(KnobId::ControllersTune),
+            glide: KnobValue::default_for(KnobId::ControllersGlide),
+            modulation_mix: KnobValue::default_for(KnobId::ControllersModMix),
+            modulation_rate: KnobValue::default_for(KnobId::ControllersModRate),
+            modulation_amount: KnobValue::default_for(KnobId::ControllersModAmount),
+            pitch_mod_depth: KnobValue::default_for(KnobId::ModPitchDepth),
+            filter_mod_depth: KnobValue::default_for(KnobId::ModFilterDepth),
+            mod_fade: KnobValue::default_for(KnobId::ModFade),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct OscillatorKnobs {
+    range: [KnobValue; 3],
+    freq: [KnobValue; 3],
+    waveform: [KnobValue; 3],
+    fm_depth: KnobValue,
+}
+
+impl OscillatorKnobs {
+    fn new() -> Self {
+        Self {
+            range: [
+                KnobValue::default_for(KnobId::OscRange1),
+                KnobValue::default_for(KnobId::OscRange2),
+                KnobValue::default_for(KnobId::OscRange3),
+            ],
+            freq: [
+                KnobValue::default_for(KnobId::OscFreq1),
+                KnobValue::default_for(KnobId::OscFreq2),
+                KnobValue::default_for(KnobId::OscFreq3),
+            ],
+            waveform: [
+                KnobValue::default_for(KnobId::OscWave1),
+                KnobValue::default_for(KnobId::OscWave2),
+                KnobValue::default_for(KnobId::OscWave3),
+            ],
+            fm_depth: KnobValue::default_for(KnobId::OscFmDepth),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct MixerKnobs {
+    external_input: KnobValue,
+    osc: [KnobValue; 3],
+    noise: KnobValue,
+    ring_mod: KnobValue,
+    pan: [KnobValue; 3],
+    noise_pan: KnobValue,
+    osc_enabled: [bool; 3],
+    ext_enabled: bool,
+    noise_enabled: bool,
+    ring_enabled: bool,
+    noise_color: KnobValue,
+    /// Release, cutoff depth and VCA depth of the EXT INPUT follower.
+    follower: [KnobValue; 3],
+}
+
+impl MixerKnobs {
+    fn new() -> Self {
+        Self {
+            external_input: KnobValue::default_for(KnobId::MixerExternal),
+            osc: [
+                KnobValue::default_for(KnobId::MixerOsc1),
+                KnobValue::default_for(KnobId::MixerOsc2),
+                KnobValue::default_for(KnobId::MixerOsc3),
+            ],
+            noise: KnobValue::default_for(KnobId::MixerNoise),
+            ring_mod: KnobValue::default_for(KnobId::MixerRing),
+            pan: [
+                KnobValue::default_for(KnobId::MixerPan1),
+                KnobValue::default_for(KnobId::MixerPan2),
+                KnobValue::default_for(KnobId::MixerPan3),
+            ],
+            noise_pan: KnobValue::default_for(KnobId::MixerNoisePan),
+            osc_enabled: [true; 3],
+            ext_enabled: true,
+            noise_enabled: true,
+            ring_enabled: false,
+            noise_color: KnobValue::default_for(KnobId::MixerNoiseColor),
+            follower: FollowerWindowState::KNOBS.map(KnobValue::default_for),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ModifierKnobs {
+    filter: [KnobValue; 3],
+    filter_env: [KnobValue; 3],
+    loudness_env: [KnobValue; 3],
+    // Delay, hold and release, set in the contour editors.
+    filter_env_stages: [KnobValue; 3],
+    loudness_env_stages: [KnobValue; 3],
+    filter_type: FilterType,
+    filter_slope: FilterSlope,
+    filter_env_curve: EnvelopeCurve,
+    loudness_env_curve: EnvelopeCurve,
+    filter_env_loop: bool,
+}
+
+impl ModifierKnobs {
+    fn new() -> Self {
+        Self {
+            filter: [
+                KnobValue::default_for(KnobId::FilterCutoff),
+                KnobValue::default_for(KnobId::FilterEmphasis),
+                KnobValue::default_for(KnobId::FilterContour),
+            ],
+            filter_env: [
+                KnobValue::default_for(KnobId::FilterAttack),
+                KnobValue::default_for(KnobId::FilterDecay),
+                KnobValue::default_for(KnobId::FilterSustain),
+            ],
+            loudness_env: [
+                KnobValue::default_for(KnobId::LoudnessAttack),
+                KnobValue::default_for(KnobId::LoudnessDecay),
+                KnobValue::default_for(KnobId::LoudnessSustain),
+            ],
+            filter_env_stages: [
+                KnobValue::default_for(KnobId::FilterDelay),
+                KnobValue::default_for(KnobId::FilterHold),
+                KnobValue::default_for(KnobId::FilterRelease),
+            ],
+            loudness_env_stages: [
+                KnobValue::default_for(KnobId::LoudnessDelay),
+                KnobValue::default_for(KnobId::LoudnessHold),
+                KnobValue::default_for(KnobId::LoudnessRelease),
+            ],
+            filter_type: FilterType::LowPass,
+            filter_slope: FilterSlope::Db24,
+            filter_env_curve: EnvelopeCurve::Analog,
+            loudness_env_curve: EnvelopeCurve::Analog,
+            filter_env_loop: false,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct OutputKnobs {
+    main_volume: KnobValue,
+    phones_volume: KnobValue,
+    chorus_enabled: bool,
+    /// Rate, depth and mix of the chorus, in `ChorusWindowState::KNOBS` order.
+    chorus: [KnobValue; 3],
+    delay_enabled: bool,
+    delay_sync: bool,
+    /// Time, feedback, damping and mix, in `DelayWindowState::KNOBS` order.
+    delay: [KnobValue; 4],
+    reverb_enabled: bool,
+    /// Size, damping and mix, in `ReverbWindowState::KNOBS` order.
+    reverb: [KnobValue; 3],
+}
+
+impl OutputKnobs {
+    fn new() -> Self {
+        Self {
+            main_volume: KnobValue::default_for(KnobId::OutputVolume),
+            phones_volume: KnobValue::default_for(KnobId::OutputPhones),
+            chorus_enabled: false,
+            chorus: ChorusWindowState::KNOBS.map(KnobValue::default_for),
+            delay_enabled: false,
+            delay_sync: false,
+            delay: DelayWindowState::KNOBS.map(KnobValue::default_for),
+            reverb_enabled: false,
+            reverb: ReverbWindowState::KNOBS.map(KnobValue::default_for),
+        }
+    }
+}
+
+fn format_pan(value: f32) -> String {
+    let pan = knob_to_pan(value);
+    if pan.abs() < 0.02 {
+        "C".to_string()
+    } else if pan < 0.0 {
+        format!("L{:.0}", -pan * 100.0)
+    } else {
+        format!("R{:.0}", pan * 100.0)
+    }
+}
+
+/// Fits the fixed-size panel into the window, letterboxing whichever axis
+/// has room to spare.
+fn panel_camera() -> Camera2D {
+    let scale = (screen_width() / SCREEN_WIDTH).min(screen_height() / SCREEN_HEIGHT);
+    let width = screen_width() / scale;
+    let height = screen_height() / scale;
+    let mut camera = Camera2D::from_display_rect(Rect::new(
+        (SCREEN_WIDTH - width) * 0.5,
+        (SCREEN_HEIGHT - height) * 0.5,
+        width,
+        height,
+    ));
+    // from_display_rect assumes a render target; flip back to y-down for the
+    // window.
+    camera.zoom.y = -camera.zoom.y;
+    camera
+}
+
+fn screen_to_panel(point: Vec2) -> Vec2 {
+    panel_camera().screen_to_world(point)
+}
+
+fn mouse_position_vec() -> Vec2 {
+    let (x, y) = mouse_position();
+    screen_to_panel(vec2(x, y))
+}
+
+fn log_toggle(name: &str, state: bool) {
+    info!("{name} set to {}", on_off(state));
+}
+
+fn on_off(state: bool) -> &'static str {
+    if state { "ON" } else { "OFF" }
+}
+
+fn log_mode(name: &str, value: &str) {
+    info!("{name} set to {value}");
+}
+
+fn handle_debug_toggle(state: &mut DebugWindowState, mouse: Vec2) {
+    let button_rect = Rect::new(SCREEN_WIDTH - 170.0, PANEL_HEIGHT + 25.0, 140.0, 36.0);
+    let clicked = is_mouse_button_pressed(MouseButton::Left);
+    if state.open {
+        if clicked && state.close_rect().contains(mouse) {
+            state.open = false;
+        } else if clicked && state.glitch_rect().contains(mouse) {
+            state.glitches = GlitchLog::default();
+        } else if clicked
+            && state.title_rect().contains(mouse)
+            && !state.export_rect().contains(mouse)
+        {
+            state.drag_offset = Some(mouse - state.rect.point());
+        }
+        if let Some(offset) = state.drag_offset {
+            if is_mouse_button_down(MouseButton::Left) {
+                state.move_to(mouse - offset);
+            } else {
+                state.drag_offset = None;
+            }
+        }
+    } else if button_rect.contains(mouse) && clicked {
+        state.open = true;
+    }
+}
+
+fn handle_analyzer_buttons(state: &mut DebugWindowState, mouse: Vec2) {
+    if !state.open || !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    if state.axis_button_rect().contains(mouse) {
+        state.frequency_axis = state.frequency_axis.next();
+    } else if state.view_button_rect().contains(mouse) {
+        state.spectrum_view = state.spectrum_view.next();
+    } else if state.generator_button_rect().contains(mouse) {
+        state.test_signal = state.test_signal.next();
+        log_mode("Test signal", state.test_signal.label());
+    }
+}
+
+fn handle_tuner_source(
+    state: &mut DebugWindowState,
+    tuner: &mut Tuner,
+    commands: &mut CommandSender,
+    mouse: Vec2,
+) {
+    if !state.open
+        || !is_mouse_button_pressed(MouseButton::Left)
+        || !state.tuner_button_rect().contains(mouse)
+    {
+        return;
+    }
+    state.probe_point = state.probe_point.next(VCO_COUNT);
+    commands.send_job(PipelineJob::ProbeTap(Some((
+        state.probe_point,
+        tuner.reset(),
+    ))));
+    log_mode("Tuner source", &state.probe_point.label());
+}
+
+fn handle_scope_source(
+    state: &mut DebugWindowState,
+    scope_probe: &mut Option<ProbeBuffer>,
+    commands: &mut CommandSender,
+    mouse: Vec2,
+) {
+    if !state.open
+        || !is_mouse_button_pressed(MouseButton::Left)
+        || !state.scope_button_rect().contains(mouse)
+    {
+        return;
+    }
+    state.scope_point = state.scope_point.next(VCO_COUNT);
+    // The output is already captured for the meters, so it needs no tap.
+    if state.scope_point == ProbePoint::Output {
+        *scope_probe = None;
+        commands.send_job(PipelineJob::ScopeTap(None));
+    } else {
+        let (probe, tap) = ProbeBuffer::new(DEBUG_CAPTURE_SAMPLES);
+        *scope_probe = Some(probe);
+        commands.send_job(PipelineJob::ScopeTap(Some((state.scope_point, tap))));
+    }
+    log_mode("Scope source", &state.scope_point.label());
+}
+
+// Shift+click also renders a PNG of both plots next to the CSVs.
+fn handle_scope_export(state: &DebugWindowState, mouse: Vec2, waveform: &[f32], spectrum: &[f32]) {
+    if !state.open
+        || !is_mouse_button_pressed(MouseButton::Left)
+        || !state.export_rect().contains(mouse)
+    {
+        return;
+    }
+    let with_image = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+    match export_scope(
+        Path::new(EXPORT_DIR),
+        state.sample_rate,
+        waveform,
+        spectrum,
+        with_image,
+    ) {
+        Ok(paths) => {
+            for path in paths {
+                log_mode("Scope export", &path.display().to_string());
+            }
+        }
+        Err(err) => error!("Scope export failed: {err:#}"),
+    }
+}
+
+fn options_button_rect() -> Rect {
+    Rect::new(SCREEN_WIDTH - 470.0, PANEL_HEIGHT + 25.0, 140.0, 36.0)
+}
+
+fn record_button_rect() -> Rect {
+    Rect::new(SCREEN_WIDTH - 620.0, PANEL_HEIGHT + 25.0, 140.0, 36.0)
+}
+
+fn handle_record_button(
+    recording: &mut Option<Recording>,
+    performance: &mut Option<Performance>,
+    played_note: Option<u8>,
+    pipeline: &SharedPipeline,
+    commands: &mut CommandSender,
+    mouse: Vec2,
+) {
+    if !is_mouse_button_pressed(MouseButton::Left) || !record_button_rect().contains(mouse) {
+        return;
+    }
+    if recording.is_none() && (is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift))
+    {
+        bounce_offline(pipeline);
+        return;
+    }
+    if recording.is_some() {
+        stop_recording(recording, performance, commands);
+    } else {
+        start_recording(recording, performance, played_note, pipeline, commands);
+    }
+}
+
+fn stop_recording(
+    recording: &mut Option<Recording>,
+    performance: &mut Option<Performance>,
+    commands: &mut CommandSender,
+) {
+    let Some(active) = recording.take() else {
+        return;
+    };
+    commands.send_job(PipelineJob::RecordTap(None));
+    let notes_path = active.path().with_extension("mid");
+    match active.finish() {
+        Ok(path) => log_mode("Recording saved", &path.display().to_string()),
+        Err(err) => error!("Recording failed: {err:#}"),
+    }
+    // A take without any notes (knob sweeps, drones) gets no MIDI file.
+    if let Some(take) = performance.take().filter(|take| !take.is_empty()) {
+        match take.save(get_time(), &notes_path) {
+            Ok(()) => log_mode("Performance saved", &notes_path.display().to_string()),
+            Err(err) => error!("Performance save failed: {err:#}"),
+        }
+    }
+}
+
+fn start_recording(
+    recording: &mut Option<Recording>,
+    performance: &mut Option<Performance>,
+    played_note: Option<u8>,
+    pipeline: &SharedPipeline,
+    commands: &mut CommandSender,
+) {
+    match Recording::start(Path::new(RECORDING_DIR), pipeline.sample_rate()) {
+        Ok((active, tap)) => {
+            commands.send_job(PipelineJob::RecordTap(Some(tap)));
+            log_mode("Recording", &active.path().display().to_string());
+            *recording = Some(active);
+            let mut take = Performance::new(get_time());
+            take.set_note(get_time(), played_note);
+            *performance = Some(take);
+        }
+        Err(err) => error!("Recording failed: {err:#}"),
+    }
+}
+
+fn midi_file_button_rect() -> Rect {
+    Rect::new(SCREEN_WIDTH - 770.0, PANEL_HEIGHT + 25.0, 140.0, 36.0)
+}
+
+fn open_midi_file(path: &Path) -> Option<MidiPlayer> {
+    match MidiFile::load(path) {
+        Ok(file) => {
+            log_mode("MIDI file", &path.display().to_string());
+            Some(MidiPlayer::new(file))
+        }
+        Err(err) => {
+            error!("MIDI file load failed: {err:#}");
+            None
+        }
+    }
+}
+
+fn is_midi_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            MIDI_FILE_EXTENSIONS
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+// A dropped file replaces the loaded one; the browser build only gets the
+// bytes, so those are parsed directly when there is no path to read.
+fn handle_dropped_midi_file(
+    player: &mut Option<MidiPlayer>,
+    dropped: &[DroppedFile],
+) -> Vec<MidiMessage> {
+    let mut released = Vec::new();
+    for dropped in dropped {
+        let Some(path) = dropped.path.clone().filter(|path| is_midi_file(path)) else {
+            continue;
+        };
+        let loaded = match &dropped.bytes {
+            Some(bytes) => {
+                let name = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                match MidiFile::parse(bytes, name) {
+                    Ok(file) => {
+                        log_mode("MIDI file", &path.display().to_string());
+                        Some(MidiPlayer::new(file))
+                    }
+                    Err(err) => {
+                        error!("MIDI file load failed: {err:#}");
+                        None
+                    }
+                }
+            }
+            None => open_midi_file(&path),
+        };
+        if let Some(loaded) = loaded {
+            if let Some(old) = player.as_mut() {
+                released.extend(old.stop());
+            }
+            *player = Some(loaded);
+        }
+    }
+    released
+}
+
+/// The Scala tuning named in the config; `None` keeps the equal
+/// temperament when none is set or it fails to load.
+fn load_config_tuning(config: &Config) -> Option<Tuning> {
+    let scale = config.tuning_scale.as_ref()?;
+    match Tuning::load(scale, config.tuning_map.as_deref()) {
+        Ok(tuning) => {
+            log_mode("Tuning", tuning.name());
+            Some(tuning)
+        }
+        Err(err) => {
+            error!("Tuning load failed: {err:#}");
+            None
+        }
+    }
+}
+
+fn has_extension(path: &Path, wanted: &str) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(wanted))
+}
+
+// A dropped .scl retunes with the standard mapping from middle C; a dropped
+// .kbm remaps whichever scale is loaded.
+fn handle_dropped_tuning(panel_state: &mut PanelState, dropped: &[DroppedFile]) {
+    for dropped in dropped {
+        let Some(path) = &dropped.path else {
+            continue;
+        };
+        let is_scale = has_extension(path, SCALA_EXTENSION);
+        if !is_scale && !has_extension(path, KEYMAP_EXTENSION) {
+            continue;
+        }
+        let text = match &dropped.bytes {
+            Some(bytes) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            None => {
+                std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))
+            }
+        };
+        let tuning = text.and_then(|text| {
+            if is_scale {
+                let name = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                Tuning::new(name, ScalaScale::parse(&text)?, KeyboardMap::default())
+            } else {
+                panel_state.tuning.with_map(KeyboardMap::parse(&text)?)
+            }
+        });
+        match tuning {
+            Ok(tuning) => {
+                log_mode("Tuning", &path.display().to_string());
+                panel_state.scala_tuning = Some(tuning);
+                panel_state.retune();
+            }
+            Err(err) => error!("Tuning load failed: {err:#}"),
+        }
+    }
+}
+
+fn handle_dropped_patch_sheet(
+    panel_state: &mut PanelState,
+    browser: &mut PresetBrowserState,
+    dropped: &[DroppedFile],
+) {
+    for dropped in dropped {
+        let Some(path) = &dropped.path else {
+            continue;
+        };
+        if !has_extension(path, PATCH_SHEET_EXTENSION) {
+            continue;
+        }
+        let preset = match &dropped.bytes {
+            Some(bytes) => patchsheet::parse(&String::from_utf8_lossy(bytes)),
+            None => Preset::load(path),
+        };
+        match preset {
+            Ok(preset) => {
+                panel_state.load_patch(&preset);
+                browser.current_name = preset.name.clone();
+                log_mode("Patch chart", &preset.name);
+            }
+            Err(err) => error!("Patch chart import failed: {err:#}"),
+        }
+    }
+}
+
+// Click toggles play and pause; Shift+click stops and rewinds.
+fn handle_midi_file_button(player: &mut Option<MidiPlayer>, mouse: Vec2) -> Vec<MidiMessage> {
+    if !is_mouse_button_pressed(MouseButton::Left) || !midi_file_button_rect().contains(mouse) {
+        return Vec::new();
+    }
+    let Some(player) = player else {
+        return Vec::new();
+    };
+    if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+        return player.stop();
+    }
+    if player.is_playing() {
+        player.pause()
+    } else {
+        log_mode("Playing MIDI file", player.file().name());
+        player.play();
+        Vec::new()
+    }
+}
+
+fn noise_seed_override() -> Option<u64> {
+    std::env::var(NOISE_SEED_ENV).ok()?.trim().parse().ok()
+}
+
+fn bounce_offline(pipeline: &SharedPipeline) {
+    let Some((samples, sample_rate)) =
+        pipeline.borrow(|synth| (synth.render(BOUNCE_SECONDS), synth.sample_rate()))
+    else {
+        error!("Bounce failed: the audio stream did not hand over the synth");
+        return;
+    };
+    match write_bounce(Path::new(RECORDING_DIR), sample_rate, &samples) {
+        Ok(path) => log_mode("Bounce saved", &path.display().to_string()),
+        Err(err) => error!("Bounce failed: {err:#}"),
+    }
+}
+
+fn handle_options_window(
+    state: &mut OptionsWindowState,
+    panel_state: &mut PanelState,
+    mouse: Vec2,
+) {
+    // The wheel nudges the numeric options by one step.
+    let (_, wheel) = mouse_wheel();
+    let hovered = PanelOption::ALL
+        .iter()
+        .enumerate()
+        .find(|(slot, _)| state.value_rect(*slot).contains(mouse));
+    if state.open
+        && wheel != 0.0
+        && let Some((_, option)) = hovered
+    {
+        match option {
+            PanelOption::Tempo if panel_state.clock.source() == ClockSource::Internal => {
+                let bpm = panel_state.clock.bpm().round() + wheel.signum();
+                panel_state.clock.set_bpm(bpm);
+            }
+            PanelOption::ReferencePitch => {
+                let hz = panel_state.reference_pitch.round() + wheel.signum();
+                panel_state.set_reference_pitch(hz);
+            }
+            PanelOption::SplitPoint => {
+                let point = panel_state.split.point.unwrap_or(SPLIT_DEFAULT_NOTE);
+                panel_state.split.point = Some(
+                    (point + wheel.signum() as i32)
+                        .clamp(controllers::MIDI_MIN, controllers::MIDI_MAX),
+                );
+                panel_state.split_learning = false;
+            }
+            PanelOption::UpperZone => {
+                panel_state.split.upper = panel_state.split.upper.nudge(wheel.signum() as i32);
+            }
+            _ => {}
+        }
+    }
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    if options_button_rect().contains(mouse) {
+        state.open = !state.open;
+        return;
+    }
+    if !state.open {
+        return;
+    }
+    if state.close_rect().contains(mouse) {
+        state.open = false;
+        return;
+    }
+    for (slot, option) in PanelOption::ALL.iter().enumerate() {
+        if state.value_rect(slot).contains(mouse) {
+            panel_state.cycle_option(*option);
+        }
+    }
+}
+
+fn macro_button_rect() -> Rect {
+    Rect::new(SCREEN_WIDTH - 920.0, PANEL_HEIGHT + 25.0, 140.0, 36.0)
+}
+
+// Each target row: click flips the polarity, the wheel sets the depth and a
+// right-click unassigns it.
+fn handle_macro_window(state: &mut MacroWindowState, panel_state: &mut PanelState, mouse: Vec2) {
+    let clicked = is_mouse_button_pressed(MouseButton::Left);
+    if clicked && macro_button_rect().contains(mouse) {
+        state.open = !state.open;
+        state.learning = None;
+        return;
+    }
+    if !state.open {
+        return;
+    }
+    if clicked && state.close_rect().contains(mouse) {
+        state.open = false;
+        state.learning = None;
+        return;
+    }
+    let (_, wheel) = mouse_wheel();
+    for index in 0..MACRO_COUNT {
+        if clicked && state.assign_rect(index).contains(mouse) {
+            state.learning = (state.learning != Some(index)).then_some(index);
+        }
+        let targets = panel_state.macros[index].targets().to_vec();
+        for (row, target) in targets.iter().enumerate() {
+            if !state.target_rect(index, row).contains(mouse) {
+                continue;
+            }
+            let assignment = &mut panel_state.macros[index];
+            if clicked {
+                assignment.flip(target.knob);
+            } else if is_mouse_button_pressed(MouseButton::Right) {
+                assignment.remove(target.knob);
+            } else if wheel != 0.0 {
+                assignment.nudge(target.knob, wheel.signum());
+            }
+        }
+    }
+}
+
+fn handle_macro_learn(
+    state: &MacroWindowState,
+    panel_state: &mut PanelState,
+    knob_drag: &mut KnobDragState,
+    knob: KnobId,
+) {
+    let Some(index) = state.learning else {
+        return;
+    };
+    if MACRO_KNOBS.contains(&knob) {
+        return;
+    }
+    // The click assigns rather than turning the knob.
+    knob_drag.active_knob = None;
+    let assignment = &mut panel_state.macros[index];
+    let was_assigned = assignment.targets().iter().any(|t| t.knob == knob);
+    let mode = format!("Macro {}", index + 1);
+    if assignment.toggle(knob) {
+        log_mode(&format!("{mode} assigned"), knob.label());
+    } else if was_assigned {
+        log_mode(&format!("{mode} unassigned"), knob.label());
+    } else {
+        log_mode(
+            &format!("{mode} is full"),
+            &format!("{MAX_MACRO_TARGETS} knobs"),
+        );
+    }
+}
+
+fn handle_envelope_editor(
+    state: &mut EnvelopeEditorState,
+    layout: &PanelLayout,
+    mouse: Vec2,
+    blocked: bool,
+) {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    if state.open.is_some() && state.close_rect().contains(mouse) {
+        state.open = None;
+        return;
+    }
+    if blocked {
+        return;
+    }
+    for (index, title) in [layout.filter_title_rect, layout.loudness_title_rect]
+        .into_iter()
+        .enumerate()
+    {
+        if title.contains(mouse) {
+            state.open = (state.open != Some(index)).then_some(index);
+        }
+    }
+}
+
+fn handle_follower_window(
+    state: &mut FollowerWindowState,
+    layout: &PanelLayout,
+    mouse: Vec2,
+    blocked: bool,
+) {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    if state.open && state.close_rect().contains(mouse) {
+        state.open = false;
+    } else if !blocked && layout.ext_label_rect.contains(mouse) {
+        state.open = !state.open;
+    }
+}
+
+fn handle_chorus_window(
+    state: &mut ChorusWindowState,
+    layout: &PanelLayout,
+    mouse: Vec2,
+    blocked: bool,
+) {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    if state.open && state.close_rect().contains(mouse) {
+        state.open = false;
+    } else if !blocked && layout.chorus_label_rect.contains(mouse) {
+        state.open = !state.open;
+    }
+}
+
+fn handle_delay_window(
+    state: &mut DelayWindowState,
+    panel_state: &mut PanelState,
+    layout: &PanelLayout,
+    mouse: Vec2,
+    blocked: bool,
+) {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    if state.open && state.close_rect().contains(mouse) {
+        state.open = false;
+    } else if state.open && state.sync_rect().contains(mouse) {
+        let output = &mut panel_state.output_panel;
+        output.delay_sync = !output.delay_sync;
+        log_toggle("Delay sync", output.delay_sync);
+    } else if !blocked && layout.delay_label_rect.contains(mouse) {
+        state.open = !state.open;
+    }
+}
+
+fn handle_reverb_window(
+    state: &mut ReverbWindowState,
+    layout: &PanelLayout,
+    mouse: Vec2,
+    blocked: bool,
+) {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    if state.open && state.close_rect().contains(mouse) {
+        state.open = false;
+    } else if !blocked && layout.reverb_label_rect.contains(mouse) {
+        state.open = !state.open;
+    }
+}
+
+fn preset_button_rect() -> Rect {
+    Rect::new(SCREEN_WIDTH - 320.0, PANEL_HEIGHT + 25.0, 140.0, 36.0)
+}
+
+fn handle_preset_browser(
+    state: &mut PresetBrowserState,
+    panel_state: &mut PanelState,
+    mouse: Vec2,
+) {
+    let clicked = is_mouse_button_pressed(MouseButton::Left);
+    if clicked && preset_button_rect().contains(mouse) {
+        state.open = !state.open;
+        state.naming = None;
+        if state.open {
+            state.refresh();
+        }
+        return;
+    }
+    if !state.open {
+        return;
+    }
+
+    if let Some(name) = state.naming.as_mut() {
+        let mut typed = Vec::new();
+        while let Some(ch) = get_char_pressed() {
+            typed.push(ch);
+        }
+        for ch in typed.into_iter().rev() {
+            if !ch.is_control() && name.chars().count() < PRESET_NAME_MAX {
+                name.push(ch);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            name.pop();
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            state.naming = None;
+        } else if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+            let name = name.trim().to_string();
+            save_preset_as(state, panel_state, &name);
+        }
+        return;
+    }
+    clear_input_queue();
+
+    if !state.entries.is_empty() {
+        let last = state.entries.len() - 1;
+        let step = if is_key_pressed(KeyCode::Down) {
+            Some(state.selected.map_or(0, |index| (index + 1).min(last)))
+        } else if is_key_pressed(KeyCode::Up) {
+            Some(state.selected.map_or(0, |index| index.saturating_sub(1)))
+        } else {
+            None
+        };
+        if let Some(index) = step {
+            audition_preset(state, panel_state, index);
+        }
+    }
+
+    if !state.rect.contains(mouse) {
+        return;
+    }
+    let (_x, wheel) = mouse_wheel();
+    if state.list_rect().contains(mouse) && wheel.abs() > f32::EPSILON {
+        let max_scroll = state.entries.len().saturating_sub(PRESET_VISIBLE_ROWS);
+        state.scroll = if wheel > 0.0 {
+            state.scroll.saturating_sub(1)
+        } else {
+            (state.scroll + 1).min(max_scroll)
+        };
+    }
+    if !clicked {
+        return;
+    }
+    if state.close_rect().contains(mouse) {
+        state.open = false;
+        return;
+    }
+    if state.save_button_rect().contains(mouse) {
+        state.naming = Some(state.current_name.clone());
+        clear_input_queue();
+        return;
+    }
+    for row in 0..PRESET_VISIBLE_ROWS {
+        let index = state.scroll + row;
+        if index < state.entries.len() && state.row_rect(row).contains(mouse) {
+            audition_preset(state, panel_state, index);
+            break;
+        }
+    }
+}
+
+fn audition_preset(state: &mut PresetBrowserState, panel_state: &mut PanelState, index: usize) {
+    let Some(path) = state.entries.get(index).cloned() else {
+        return;
+    };
+    state.selected = Some(index);
+    state.ensure_visible(index);
+    match Preset::load(&path) {
+        Ok(preset) => {
+            panel_state.load_patch(&preset);
+            state.current_name = preset.name.clone();
+            state.status = format!("LOADED {}", preset.name);
+            log_mode("Preset", &preset.name);
+        }
+        Err(err) => {
+            state.status = "LOAD FAILED".into();
+            error!("Preset load failed: {err:#}");
+        }
+    }
+}
+
+fn save_preset_as(state: &mut PresetBrowserState, panel_state: &PanelState, name: &str) {
+    let preset = panel_state.to_preset(name);
+    match preset.save(Path::new(PRESET_DIR)) {
+        Ok(path) => {
+            state.naming = None;
+            state.current_name = name.to_string();
+            state.status = format!("SAVED {name}");
+            log_mode("Preset saved", &path.display().to_string());
+            state.refresh();
+            if let Some(index) = state.entries.iter().position(|entry| *entry == path) {
+                state.selected = Some(index);
+                state.ensure_visible(index);
+            }
+        }
+        Err(err) => {
+            state.status = "SAVE FAILED".into();
+            error!("Preset save failed: {err:#}");
+        }
+    }
+}
+
+fn open_midi_output(config: &Config) -> Option<MidiOutput> {
+    let name = config.midi_output.as_deref()?;
+    match MidiOutput::open(name) {
+        Ok(output) => {
+            log_mode("MIDI output", output.port_name());
+            Some(output)
+        }
+        Err(err) => {
+            warn!("MIDI output unavailable: {err}");
+            None
+        }
+    }
+}
+
+/// Sends every knob that moved since the last frame, whatever moved it, on
+/// its controller number; the first call sends the whole panel.
+fn send_knob_ccs(panel_state: &mut PanelState, output: &mut MidiOutput) {
+    let params = panel_state.synth_params();
+    for spec in param_specs() {
+        if let Some(cc) = spec.cc
+            && let Err(err) = output.control(cc, params.get(spec.knob))
+        {
+            warn!("{err:#}");
+            return;
+        }
+    }
+}
+
+fn handle_midi_message(
+    panel_state: &mut PanelState,
+    controller: &mut KeyboardController,
+    message: MidiMessage,
+) -> bool {
+    match message {
+        MidiMessage::NoteOn { note, velocity } => controller.note_on(note, velocity),
+        MidiMessage::NoteOff { note } => controller.note_off(note),
+        MidiMessage::ControlChange {
+            controller: CC_MOD_WHEEL,
+            value,
+        } => {
+            panel_state.mod_wheel = value as f32 / 127.0;
+            false
+        }
+        MidiMessage::ControlChange {
+            controller: CC_SUSTAIN,
+            value,
+        } => controller.set_sustain_pedal(value >= 64),
+        MidiMessage::ControlChange {
+            controller: CC_ALL_NOTES_OFF,
+            ..
+        } => panic(panel_state, controller),
+        MidiMessage::ControlChange { .. } => false,
+        MidiMessage::PitchBend { value } => {
+            panel_state.pitch_bend = bend_amount(value);
+            false
+        }
+        MidiMessage::Clock => {
+            panel_state.clock.midi_pulse();
+            false
+        }
+        MidiMessage::Start => {
+            panel_state.clock.midi_start();
+            false
+        }
+        MidiMessage::Continue => {
+            panel_state.clock.midi_continue();
+            false
+        }
+        MidiMessage::Stop => {
+            panel_state.clock.midi_stop();
+            false
+        }
+    }
+}
+
+// The pad plays like a small MIDI keyboard with a joystick: buttons are full
+// velocity notes, and the sticks and triggers hold their value until moved.
+fn handle_gamepad_action(
+    panel_state: &mut PanelState,
+    controller: &mut KeyboardController,
+    action: GamepadAction,
+) -> bool {
+    match action {
+        GamepadAction::NoteOn(note) => controller.note_on(note, 127),
+        GamepadAction::NoteOff(note) => controller.note_off(note),
+        GamepadAction::Bend(amount) => {
+            panel_state.pitch_bend = amount;
+            false
+        }
+        GamepadAction::ModWheel(amount) => {
+            panel_state.mod_wheel = amount;
+            false
+        }
+        GamepadAction::Cutoff(amount) => {
+            panel_state.cutoff_expression = amount;
+            false
+        }
+    }
+}
+
+fn handle_osc_control(panel_state: &mut PanelState, control: OscControl) {
+    panel_state.knob_mut(control.knob).value = control.value;
+}
+
+fn handle_api_call(panel_state: &mut PanelState, call: ApiCall) {
+    let name = match &call.request {
+        ApiRequest::LoadPreset(name) | ApiRequest::SavePreset(name) => name.clone(),
+        ApiRequest::GetParams | ApiRequest::SetParams(_) => "Current".to_string(),
+    };
+    let reply: ApiReply = match &call.request {
+        ApiRequest::GetParams => Ok(()),
+        ApiRequest::SetParams(params) => {
+            let known = panel_state.to_preset(&name);
+            match params.values().find(|(key, _)| known.get(key).is_none()) {
+                Some((key, _)) => Err(format!("unknown parameter {key}")),
+                None => {
+                    panel_state.apply_preset(params);
+                    Ok(())
+                }
+            }
+        }
+        ApiRequest::LoadPreset(name) => {
+            let path = preset_path(Path::new(PRESET_DIR), name);
+            load_startup_preset(panel_state, &path).map_err(|err| format!("{err:#}"))
+        }
+        ApiRequest::SavePreset(name) => {
+            match panel_state.to_preset(name).save(Path::new(PRESET_DIR)) {
+                Ok(path) => {
+                    log_mode("Preset saved", &path.display().to_string());
+                    Ok(())
+                }
+                Err(err) => Err(format!("{err:#}")),
+            }
+        }
+    }
+    .map(|()| panel_state.to_preset(&name));
+    call.respond(reply);
+}
+
+fn controller_strip_rect() -> Rect {
+    Rect::new(
+        40.0,
+        PANEL_HEIGHT + 100.0,
+        60.0,
+        SCREEN_HEIGHT - PANEL_HEIGHT - 140.0,
+    )
+}
+
+// Down, readout and up, above the MOD wheel.
+fn octave_button_rects() -> [Rect; 3] {
+    let y = PANEL_HEIGHT + 62.0;
+    [
+        Rect::new(40.0, y, 28.0, 28.0),
+        Rect::new(68.0, y, 44.0, 28.0),
+        Rect::new(112.0, y, 28.0, 28.0),
+    ]
+}
+
+/// All notes off: clears every held note and the sustain pedal so the gate
+/// closes and the envelopes release. Returns true so the closed gate is
+/// sent.
+fn panic(panel_state: &mut PanelState, controller: &mut KeyboardController) -> bool {
+    panel_state.panic();
+    controller.panic();
+    log_mode("Panic", "all notes off");
+    true
+}
+
+fn panic_button_rect() -> Rect {
+    Rect::new(40.0, SCREEN_HEIGHT - 34.0, 100.0, 26.0)
+}
+
+// The PANIC button, or Esc when nothing else is waiting for it.
+fn handle_panic(
+    panel_state: &mut PanelState,
+    controller: &mut KeyboardController,
+    mouse: Vec2,
+    ui_blocked: bool,
+    typing: bool,
+) -> bool {
+    let clicked = !ui_blocked
+        && is_mouse_button_pressed(MouseButton::Left)
+        && panic_button_rect().contains(mouse);
+    let escape = !typing && controller.learning_key().is_none() && is_key_pressed(KeyCode::Escape);
+    (clicked || escape) && panic(panel_state, controller)
+}
+
+/// Same as the `-` and `=` keys; returns whether the shift moved so held
+/// notes follow it.
+fn handle_octave_buttons(
+    controller: &mut KeyboardController,
+    mouse: Vec2,
+    ui_blocked: bool,
+) -> bool {
+    if ui_blocked || !is_mouse_button_pressed(MouseButton::Left) {
+        return false;
+    }
+    let [down, _, up] = octave_button_rects();
+    let before = controller.octave_shift();
+    if down.contains(mouse) {
+        controller.set_octave_shift(before - 1);
+    } else if up.contains(mouse) {
+        controller.set_octave_shift(before + 1);
+    }
+    controller.octave_shift() != before
+}
+
+fn mod_wheel_rect() -> Rect {
+    let strip = controller_strip_rect();
+    Rect::new(strip.x + 12.0, strip.y + 24.0, 36.0, strip.h - 48.0)
+}
+
+fn handle_mod_wheel(panel_state: &mut PanelState, mouse: Vec2, ui_blocked: bool) {
+    let wheel = mod_wheel_rect();
+    if is_mouse_button_pressed(MouseButton::Left) && !ui_blocked && wheel.contains(mouse) {
+        panel_state.mod_wheel_drag = true;
+    }
+    if !is_mouse_button_down(MouseButton::Left) {
+        panel_state.mod_wheel_drag = false;
+    }
+    if panel_state.mod_wheel_drag {
+        panel_state.mod_wheel = (1.0 - (mouse.y - wheel.y) / wheel.h).clamp(0.0, 1.0);
+    }
+}
+
+// Dragging up or down from where a key was clicked bends like a ribbon;
+// letting go of the key springs back to centre. Between drags the bend is
+// left to the gamepad stick.
+fn handle_key_bend(panel_state: &mut PanelState, controller: &KeyboardController, mouse: Vec2) {
+    if controller.mouse_key().is_none() {
+        if panel_state.bend_anchor.take().is_some() {
+            panel_state.pitch_bend = 0.0;
+        }
+        return;
+    }
+    let anchor = *panel_state.bend_anchor.get_or_insert(mouse.y);
+    panel_state.pitch_bend = ((anchor - mouse.y) / BEND_DRAG_PIXELS).clamp(-1.0, 1.0);
+}
+
+fn handle_oscillator_switches(panel_state: &mut PanelState, layout: &PanelLayout) {
+    if is_mouse_button_pressed(MouseButton::Left)
+        && layout.freq_snap_rect.contains(mouse_position_vec())
+    {
+        panel_state.freq_snap = !panel_state.freq_snap;
+        log_mode(
+            "OSC 2/3 frequency",
+            if panel_state.freq_snap {
+                "semitones"
+            } else {
+                "free"
+            },
+        );
+    }
+}
+
+fn handle_mixer_switches(panel_state: &mut PanelState, layout: &PanelLayout) {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    let mouse = mouse_position_vec();
+    for (index, rect) in layout.mixer_toggle_rects.iter().enumerate() {
+        if rect.contains(mouse) {
+            match index {
+                0..=2 => {
+                    let flag = &mut panel_state.mixer_panel.osc_enabled[index];
+                    *flag = !*flag;
+                }
+                3 => {
+                    panel_state.mixer_panel.ext_enabled = !panel_state.mixer_panel.ext_enabled;
+                }
+                4 => {
+                    panel_state.mixer_panel.noise_enabled = !panel_state.mixer_panel.noise_enabled;
+                }
+                _ => {}
+            }
+        }
+    }
+    if layout.noise_selector_rect.contains(mouse) {
+        panel_state.cycle_noise_color();
+        log_mode("Noise generator", panel_state.noise_color().label());
+    }
+}
+
+fn handle_modifier_switches(panel_state: &mut PanelState, layout: &PanelLayout) {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    let mouse = mouse_position_vec();
+    if layout.filter_type_rect.contains(mouse) {
+        let modifiers = &mut panel_state.modifiers_panel;
+        modifiers.filter_type = modifiers.filter_type.next();
+        log_mode("Filter type", modifiers.filter_type.label());
+    }
+    if layout.filter_slope_rect.contains(mouse) {
+        let modifiers = &mut panel_state.modifiers_panel;
+        modifiers.filter_slope = modifiers.filter_slope.next();
+        log_mode("Filter slope", modifiers.filter_slope.label());
+    }
+    if layout.filter_curve_rect.contains(mouse) {
+        let modifiers = &mut panel_state.modifiers_panel;
+        modifiers.filter_env_curve = modifiers.filter_env_curve.next();
+        log_mode("Filter contour curve", modifiers.filter_env_curve.label());
+    }
+    if layout.filter_loop_rect.contains(mouse) {
+        let modifiers = &mut panel_state.modifiers_panel;
+        modifiers.filter_env_loop = !modifiers.filter_env_loop;
+        log_mode(
+            "Filter contour loop",
+            if modifiers.filter_env_loop {
+                "ON"
+            } else {
+                "OFF"
+            },
+        );
+    }
+    if layout.loudness_curve_rect.contains(mouse) {
+        let modifiers = &mut panel_state.modifiers_panel;
+        modifiers.loudness_env_curve = modifiers.loudness_env_curve.next();
+        log_mode(
+            "Loudness contour curve",
+            modifiers.loudness_env_curve.label(),
+        );
+    }
+}
+
+fn handle_output_switches(panel_state: &mut PanelState, layout: &PanelLayout) {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    let mouse = mouse_position_vec();
+    let output = &mut panel_state.output_panel;
+    if layout.chorus_switch_rect.contains(mouse) {
+        output.chorus_enabled = !output.chorus_enabled;
+        log_toggle("Chorus", output.chorus_enabled);
+    }
+    if layout.delay_switch_rect.contains(mouse) {
+        output.delay_enabled = !output.delay_enabled;
+        log_toggle("Delay", output.delay_enabled);
+    }
+    if layout.reverb_switch_rect.contains(mouse) {
+        output.reverb_enabled = !output.reverb_enabled;
+        log_toggle("Reverb", output.reverb_enabled);
+    }
+}
+
+fn handle_controller_switches(panel_state: &mut PanelState, layout: &PanelLayout) {
+    if !is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    let mouse = mouse_position_vec();
+    if layout.controller_mod_toggle.contains(mouse) {
+        panel_state.osc_modulation = !panel_state.osc_modulation;
+        log_toggle("Oscillator modulation", panel_state.osc_modulation);
+    }
+    if layout.controller_osc3_toggle.contains(mouse) {
+        panel_state.osc3_control = !panel_state.osc3_control;
+        log_toggle("Oscillator 3 control", panel_state.osc3_control);
+    }
+    if layout.controller_mod_source_toggle.contains(mouse) {
+        panel_state.mod_source_noise = !panel_state.mod_source_noise;
+        log_mode(
+            "Mod source",
+            if panel_state.mod_source_noise {
+                "NOISE"
+            } else {
+                "LFO"
+            },
+        );
+    }
+    if layout.controller_mod_target_toggle.contains(mouse) {
+        panel_state.mod_target_filter = !panel_state.mod_target_filter;
+        log_toggle("Filter modulation", panel_state.mod_target_filter);
+    }
+    if layout.controller_glide_switch.contains(mouse) {
+        panel_state.glide_mode = panel_state.glide_mode.next();
+        log_mode("Glide", panel_state.glide_mode.label());
+    }
+    if layout.controller_decay_switch.contains(mouse) {
+        panel_state.decay_enabled = !panel_state.decay_enabled;
+        log_toggle("Decay", panel_state.decay_enabled);
+    }
+    if layout.controller_s_trigger_button.contains(mouse) {
+        panel_state.request_s_trigger();
+        info!("S-TRIG fired");
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_scene(
+    texture: &Texture2D,
+    panel_state: &mut PanelState,
+    knob_drag: &mut KnobDragState,
+    controller: &KeyboardController,
+    layout: &PanelLayout,
+    keyboard_layout: &KeyboardLayout,
+    waveform: &[f32],
+    analyzer: &SpectrumAnalyzer,
+    waterfall: &Waterfall,
+    debug_window: &DebugWindowState,
+    preset_browser: &PresetBrowserState,
+    options_window: &OptionsWindowState,
+    macro_window: &MacroWindowState,
+    envelope_editor: &EnvelopeEditorState,
+    follower_window: &FollowerWindowState,
+    chorus_window: &ChorusWindowState,
+    delay_window: &DelayWindowState,
+    reverb_window: &ReverbWindowState,
+    recording: Option<&Recording>,
+    midi_player: Option<&MidiPlayer>,
+    audio: &AudioLink,
+) {
+    clear_background(BACKGROUND);
+    draw_texture_ex(
+        texture,
+        0.0,
+        0.0,
+        Color::new(1.0, 1.0, 1.0, 0.6),
+        DrawTextureParams {
+            dest_size: Some(vec2(SCREEN_WIDTH, PANEL_HEIGHT)),
+            source: Some(Rect::new(0.0, 0.0, texture.width(), texture.height())),
+            ..Default::default()
+        },
+    );
+
+    draw_section(&layout.controller_rect, "CONTROLLERS");
+    draw_section(&layout.oscillator_rect, "OSCILLATOR BANK");
+    draw_section(&layout.mixer_rect, "MIXER");
+    draw_section(&layout.modifier_rect, "MODIFIERS");
+    draw_section(&layout.output_rect, "OUTPUT");
+
+    draw_controllers_panel(panel_state, knob_drag, layout);
+    draw_oscillators(panel_state, knob_drag, layout);
+    draw_mixer(panel_state, knob_drag, layout);
+    draw_modifiers(panel_state, knob_drag, layout);
+    draw_output_panel(panel_state, knob_drag, layout);
+    draw_keyboard(controller, keyboard_layout);
+    draw_controller_strip(panel_state);
+    draw_octave_buttons(controller);
+    draw_button(panic_button_rect(), "PANIC");
+    draw_debug_button(debug_window);
+    draw_preset_button(preset_browser);
+    draw_options_button(options_window);
+    draw_macro_button(macro_window);
+    draw_record_button(recording);
+    draw_midi_file_button(midi_player);
+    draw_audio_status(audio);
+    if debug_window.open {
+        draw_debug_window(
+            debug_window,
+            waveform,
+            analyzer,
+            waterfall,
+            &FilterCurve::from_panel(panel_state),
+        );
+    }
+    if preset_browser.open {
+        draw_preset_browser(preset_browser);
+    }
+    if options_window.open {
+        knob_drag.input_blocked = false;
+        draw_options_window(options_window, panel_state, knob_drag);
+    }
+    if macro_window.open {
+        knob_drag.input_blocked = false;
+        draw_macro_window(macro_window, panel_state, knob_drag);
+    }
+    if envelope_editor.open.is_some() {
+        knob_drag.input_blocked = false;
+        draw_envelope_editor(envelope_editor, panel_state, knob_drag);
+    }
+    if follower_window.open {
+        knob_drag.input_blocked = false;
+        draw_follower_window(follower_window, panel_state, knob_drag);
+    }
+    if chorus_window.open {
+        knob_drag.input_blocked = false;
+        draw_chorus_window(chorus_window, panel_state, knob_drag);
+    }
+    if delay_window.open {
+        knob_drag.input_blocked = false;
+        draw_delay_window(delay_window, panel_state, knob_drag);
+    }
+    if reverb_window.open {
+        knob_drag.input_blocked = false;
+        draw_reverb_window(reverb_window, panel_state, knob_drag);
+    }
+    if let Some((knob_id, value, rect)) = knob_drag.tooltip.take() {
+        draw_knob_tooltip(knob_id, value, rect);
     }
 }
 
@@ -1121,960 +5104,2173 @@ fn draw_section(rect: &Rect, label: &str) {
         rect.y,
         rect.w,
         rect.h,
-        Color::new(0.05, 0.03, 0.02, 0.65),
+        Color::new(0.05, 0.03, 0.02, 0.65),
+    );
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    let text = label.to_string();
+    draw_text_ex(
+        &text,
+        rect.x + 6.0,
+        rect.y - 6.0,
+        TextParams {
+            font_size: 18,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
+}
+
+fn draw_controllers_panel(
+    panel_state: &mut PanelState,
+    knob_drag: &mut KnobDragState,
+    layout: &PanelLayout,
+) {
+    draw_knob_widget(
+        knob_drag,
+        KnobId::ControllersTune,
+        layout.controller_knobs[0],
+        &mut panel_state.controllers.tune,
+        "TUNE",
+        (panel_state.tune_calibration != 0.0)
+            .then(|| format!("CAL {:+.0} CT", panel_state.tune_calibration * 1200.0))
+            .as_deref(),
+    );
+    draw_knob_widget(
+        knob_drag,
+        KnobId::ControllersGlide,
+        layout.controller_knobs[1],
+        &mut panel_state.controllers.glide,
+        "GLIDE",
+        None,
+    );
+    draw_knob_widget(
+        knob_drag,
+        KnobId::ControllersModMix,
+        layout.controller_knobs[2],
+        &mut panel_state.controllers.modulation_mix,
+        "MOD MIX",
+        None,
+    );
+    let mod_rate_label = if panel_state.osc3_lfo {
+        "OSC 3".to_string()
+    } else if let Some(division) = panel_state.lfo_division() {
+        division.label().to_string()
+    } else {
+        format!("{:.1} Hz", panel_state.mod_lfo_rate())
+    };
+    draw_knob_widget(
+        knob_drag,
+        KnobId::ControllersModRate,
+        layout.controller_extra_knobs[0],
+        &mut panel_state.controllers.modulation_rate,
+        "MOD RATE",
+        Some(&mod_rate_label),
+    );
+    let mod_amt_label = format_percent(panel_state.mod_amount());
+    draw_knob_widget(
+        knob_drag,
+        KnobId::ControllersModAmount,
+        layout.controller_extra_knobs[1],
+        &mut panel_state.controllers.modulation_amount,
+        "MOD AMT",
+        Some(&mod_amt_label),
+    );
+    draw_controller_info(panel_state, &layout.controller_rect);
+
+    draw_text_ex(
+        "OSCILLATION MOD",
+        layout.controller_mod_toggle.x,
+        layout.controller_mod_toggle.y - 6.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+    draw_toggle_switch(
+        layout.controller_mod_toggle,
+        panel_state.osc_modulation,
+        "ON",
+    );
+
+    draw_text_ex(
+        "OSC. 3 CONTROL",
+        layout.controller_osc3_toggle.x,
+        layout.controller_osc3_toggle.y - 6.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+    draw_toggle_switch(
+        layout.controller_osc3_toggle,
+        panel_state.osc3_control,
+        "ON",
+    );
+    draw_text_ex(
+        "GLIDE",
+        layout.controller_glide_switch.x,
+        layout.controller_glide_switch.y - 6.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+    draw_toggle_switch(
+        layout.controller_glide_switch,
+        panel_state.glide_mode != GlideMode::Off,
+        panel_state.glide_mode.label(),
+    );
+    draw_glide_lamp(layout.controller_glide_switch, panel_state.gliding);
+
+    draw_text_ex(
+        "MOD SOURCE",
+        layout.controller_mod_source_toggle.x,
+        layout.controller_mod_source_toggle.y - 6.0,
+        TextParams {
+            font_size: 12,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+    draw_toggle_switch(
+        layout.controller_mod_source_toggle,
+        panel_state.mod_source_noise,
+        if panel_state.mod_source_noise {
+            "NOISE"
+        } else {
+            "LFO"
+        },
+    );
+
+    draw_text_ex(
+        "FILTER MOD",
+        layout.controller_mod_target_toggle.x,
+        layout.controller_mod_target_toggle.y - 6.0,
+        TextParams {
+            font_size: 12,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+    draw_toggle_switch(
+        layout.controller_mod_target_toggle,
+        panel_state.mod_target_filter,
+        "ON",
+    );
+
+    draw_text_ex(
+        "DECAY",
+        layout.controller_decay_switch.x,
+        layout.controller_decay_switch.y - 6.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+    draw_toggle_switch(
+        layout.controller_decay_switch,
+        panel_state.decay_enabled,
+        "ON",
+    );
+
+    draw_button(layout.controller_s_trigger_button, "S-TRIG");
+}
+
+fn draw_controller_info(panel_state: &PanelState, rect: &Rect) {
+    draw_text_block(
+        rect.x + 16.0,
+        rect.y + 40.0,
+        &format!(
+            "GATE {}{}\nLAST NOTE {}\nVOLTAGE {:.2} V\nFREQUENCY {:.1} Hz\nTUNING {}",
+            if panel_state.last_midi >= 0 {
+                "OPEN"
+            } else {
+                "IDLE"
+            },
+            if panel_state.sustain { " HOLD" } else { "" },
+            if panel_state.last_midi >= 0 {
+                panel_state.last_midi.to_string()
+            } else {
+                "-".into()
+            },
+            panel_state.last_voltage,
+            voltage_to_frequency(panel_state.last_voltage),
+            panel_state.tuning.name()
+        ),
+    );
+    draw_text_block(
+        rect.x + 16.0,
+        rect.y + rect.h - 60.0,
+        &format!(
+            "TUNE {:+.2} OCT\nGLIDE {:.2} s {}\nMOD NOISE {}",
+            panel_state.tune_offset(),
+            panel_state.glide_time(),
+            panel_state.glide_curve.label(),
+            panel_state.noise_color().label()
+        ),
+    );
+}
+
+fn draw_text_block(x: f32, mut y: f32, text: &str) {
+    for line in text.lines() {
+        draw_text_ex(
+            line,
+            x,
+            y,
+            TextParams {
+                font_size: 18,
+                color: AMBER,
+                ..Default::default()
+            },
+        );
+        y += 22.0;
+    }
+}
+
+fn draw_oscillators(
+    panel_state: &mut PanelState,
+    knob_drag: &mut KnobDragState,
+    layout: &PanelLayout,
+) {
+    draw_button(
+        layout.freq_snap_rect,
+        if panel_state.freq_snap {
+            "FREQ SEMI"
+        } else {
+            "FREQ FREE"
+        },
+    );
+    for index in 0..3 {
+        let range_label = panel_state.osc_range_setting(index).label;
+        draw_knob_widget(
+            knob_drag,
+            match index {
+                0 => KnobId::OscRange1,
+                1 => KnobId::OscRange2,
+                _ => KnobId::OscRange3,
+            },
+            layout.osc_range_knobs[index],
+            &mut panel_state.oscillator.range[index],
+            &format!("OSC {} RANGE", index + 1),
+            Some(range_label),
+        );
+        let freq_rect = layout.osc_freq_knobs[index];
+        let wave_rect = layout.osc_wave_knobs[index];
+        let detune = panel_state.osc_detune(index);
+        let freq_label = format!("OSC {} FREQ", index + 1);
+        // Snapped intervals read in cents, so +700 is an exact fifth.
+        let detune_label = if panel_state.freq_snap && index > 0 {
+            format!("{:+.0} CT", detune * 1200.0)
+        } else {
+            format!("{:+.2} OCT", detune)
+        };
+        draw_knob_widget(
+            knob_drag,
+            match index {
+                0 => KnobId::OscFreq1,
+                1 => KnobId::OscFreq2,
+                _ => KnobId::OscFreq3,
+            },
+            freq_rect,
+            &mut panel_state.oscillator.freq[index],
+            &freq_label,
+            Some(&detune_label),
+        );
+        let waveform = value_to_waveform(index, panel_state.oscillator.waveform[index].value);
+        let wave_label = format!("OSC {} WAVE", index + 1);
+        draw_knob_widget(
+            knob_drag,
+            match index {
+                0 => KnobId::OscWave1,
+                1 => KnobId::OscWave2,
+                _ => KnobId::OscWave3,
+            },
+            wave_rect,
+            &mut panel_state.oscillator.waveform[index],
+            &wave_label,
+            Some(waveform.label()),
+        );
+    }
+}
+
+fn draw_mixer(panel_state: &mut PanelState, knob_drag: &mut KnobDragState, layout: &PanelLayout) {
+    draw_text_ex(
+        "VOLUME",
+        layout.mixer_rect.x + 10.0,
+        layout.mixer_rect.y + 16.0,
+        TextParams {
+            font_size: 18,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
+    let osc_labels = ["OSC 1", "OSC 2", "OSC 3"];
+    for (index, osc_label) in osc_labels.iter().enumerate() {
+        let value_text = format!("{:.1}", panel_state.mixer_panel.osc[index].value * 10.0);
+        draw_knob_widget(
+            knob_drag,
+            match index {
+                0 => KnobId::MixerOsc1,
+                1 => KnobId::MixerOsc2,
+                _ => KnobId::MixerOsc3,
+            },
+            layout.mixer_osc_knobs[index],
+            &mut panel_state.mixer_panel.osc[index],
+            osc_label,
+            Some(&format!("{value_text}/10")),
+        );
+        draw_knob_scale(layout.mixer_osc_knobs[index]);
+        draw_toggle_switch(
+            layout.mixer_toggle_rects[index],
+            panel_state.mixer_panel.osc_enabled[index],
+            "ON",
+        );
+    }
+    let extra_labels = ["EXT INPUT", "NOISE"];
+    let mut extra_knobs = [
+        &mut panel_state.mixer_panel.external_input,
+        &mut panel_state.mixer_panel.noise,
+    ];
+    for index in 0..2 {
+        let knob = &mut extra_knobs[index];
+        let label = extra_labels[index];
+        draw_knob_widget(
+            knob_drag,
+            if index == 0 {
+                KnobId::MixerExternal
+            } else {
+                KnobId::MixerNoise
+            },
+            layout.mixer_extra_knobs[index],
+            knob,
+            label,
+            Some(&format!("{:.1}/10", knob.value * 10.0)),
+        );
+        draw_knob_scale(layout.mixer_extra_knobs[index]);
+        let toggle_index = 3 + index;
+        let enabled = if index == 0 {
+            panel_state.mixer_panel.ext_enabled
+        } else {
+            panel_state.mixer_panel.noise_enabled
+        };
+        draw_toggle_switch(layout.mixer_toggle_rects[toggle_index], enabled, "ON");
+    }
+    draw_noise_selector(
+        layout.noise_selector_rect,
+        &NoiseColor::describe_position(panel_state.mixer_panel.noise_color.value),
+    );
+    draw_overload_lamp(layout.overload_rect, panel_state.filter_overload);
+}
+
+fn draw_knob_scale(rect: Rect) {
+    draw_text_ex(
+        "10",
+        rect.x + rect.w + 8.0,
+        rect.y + 14.0,
+        TextParams {
+            font_size: 12,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+    draw_text_ex(
+        "0",
+        rect.x + rect.w + 14.0,
+        rect.y + rect.h - 4.0,
+        TextParams {
+            font_size: 12,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+}
+
+fn draw_toggle_switch(rect: Rect, on: bool, label: &str) {
+    let color = if on {
+        AMBER
+    } else {
+        Color::new(0.1, 0.08, 0.05, 1.0)
+    };
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 1.0),
+    );
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_rectangle(
+        rect.x + 2.0,
+        rect.y + 2.0,
+        rect.w - 4.0,
+        rect.h - 4.0,
+        color,
+    );
+    draw_text_ex(
+        label,
+        rect.x + 4.0,
+        rect.y + rect.h - 4.0,
+        TextParams {
+            font_size: 12,
+            color: BACKGROUND,
+            ..Default::default()
+        },
+    );
+}
+
+fn draw_button(rect: Rect, label: &str) {
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.08, 0.05, 0.03, 1.0),
+    );
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_centered_text(label, rect, 16);
+}
+
+fn draw_noise_selector(rect: Rect, selection: &str) {
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.08, 0.05, 0.03, 1.0),
+    );
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    let label_rect = Rect::new(rect.x, rect.y + 1.0, rect.w, 18.0);
+    draw_centered_text("NOISE", label_rect, 16);
+    let color_rect = Rect::new(rect.x, rect.y + rect.h - 22.0, rect.w, 18.0);
+    draw_centered_text(selection, color_rect, 16);
+    draw_text_ex(
+        "CLICK / TAB TO CYCLE",
+        rect.x,
+        rect.y - 8.0,
+        TextParams {
+            font_size: 12,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+}
+
+// Lit while the pitch is still sliding toward the played note.
+fn draw_glide_lamp(switch: Rect, active: bool) {
+    let color = if active {
+        AMBER
+    } else {
+        Color::new(0.1, 0.08, 0.05, 1.0)
+    };
+    let center = vec2(switch.x + switch.w - 6.0, switch.y - 11.0);
+    draw_circle(center.x, center.y, 4.0, color);
+    draw_circle_lines(center.x, center.y, 4.0, 1.0, AMBER_DIM);
+}
+
+fn draw_overload_lamp(rect: Rect, active: bool) {
+    let color = if active {
+        AMBER
+    } else {
+        Color::new(0.1, 0.08, 0.05, 1.0)
+    };
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 1.0),
     );
     draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
-    let text = label.to_string();
+    draw_circle(
+        rect.x + rect.w * 0.5,
+        rect.y + rect.h * 0.5,
+        rect.w.min(rect.h) * 0.3,
+        color,
+    );
     draw_text_ex(
-        &text,
-        rect.x + 6.0,
-        rect.y - 6.0,
+        "OVERLOAD",
+        rect.x - 10.0,
+        rect.y - 4.0,
         TextParams {
-            font_size: 18,
+            font_size: 12,
             color: AMBER,
             ..Default::default()
         },
     );
 }
 
-fn draw_controllers_panel(
+fn draw_modifiers(
     panel_state: &mut PanelState,
     knob_drag: &mut KnobDragState,
     layout: &PanelLayout,
 ) {
+    draw_button(
+        layout.filter_type_rect,
+        &format!("FILTER {}", panel_state.modifiers_panel.filter_type.label()),
+    );
+    draw_button(
+        layout.filter_slope_rect,
+        panel_state.modifiers_panel.filter_slope.label(),
+    );
+    draw_button(
+        layout.filter_curve_rect,
+        panel_state.modifiers_panel.filter_env_curve.label(),
+    );
+    draw_button(
+        layout.filter_loop_rect,
+        if panel_state.modifiers_panel.filter_env_loop {
+            "LOOP"
+        } else {
+            "ONCE"
+        },
+    );
+    draw_button(
+        layout.loudness_curve_rect,
+        panel_state.modifiers_panel.loudness_env_curve.label(),
+    );
+    let filter_line = layout.filter_env_divider;
+    draw_line(
+        layout.modifier_rect.x + 8.0,
+        filter_line,
+        layout.modifier_rect.x + layout.modifier_rect.w - 8.0,
+        filter_line,
+        1.0,
+        AMBER_DIM,
+    );
+    let filter_label = "FILTER CONTOUR";
+    let filter_metrics = measure_text(filter_label, None, 18, 1.0);
+    draw_text_ex(
+        filter_label,
+        layout.modifier_rect.x + layout.modifier_rect.w * 0.5 - filter_metrics.width * 0.5,
+        filter_line + 20.0,
+        TextParams {
+            font_size: 18,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
+
+    let line_y = layout.modifier_loudness_split + 10.0;
+    draw_line(
+        layout.modifier_rect.x + 8.0,
+        line_y,
+        layout.modifier_rect.x + layout.modifier_rect.w - 8.0,
+        line_y,
+        1.0,
+        AMBER_DIM,
+    );
+    let loudness_label = "LOUDNESS CONTOUR";
+    let label_metrics = measure_text(loudness_label, None, 18, 1.0);
+    draw_text_ex(
+        loudness_label,
+        layout.modifier_rect.x + layout.modifier_rect.w * 0.5 - label_metrics.width * 0.5,
+        line_y + 58.0,
+        TextParams {
+            font_size: 18,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
+    // Drawn first so the knobs sit on top of the curves.
+    let env_row = |knob: Rect| {
+        Rect::new(
+            layout.modifier_rect.x,
+            knob.y,
+            layout.modifier_rect.w,
+            knob.h,
+        )
+    };
+    draw_envelope_graph(
+        env_row(layout.filter_env_knobs[0]),
+        &panel_state.filter_envelope_shape(),
+        panel_state.envelope_states[0],
+    );
+    draw_envelope_graph(
+        env_row(layout.loudness_knobs[0]),
+        &panel_state.loud_envelope_shape(),
+        panel_state.envelope_states[1],
+    );
+    let cutoff_text = format!("{:.0} Hz", panel_state.cutoff_hz());
     draw_knob_widget(
         knob_drag,
-        KnobId::ControllersTune,
-        layout.controller_knobs[0],
-        &mut panel_state.controllers.tune,
-        "TUNE",
-        None,
+        KnobId::FilterCutoff,
+        layout.filter_knobs[0],
+        &mut panel_state.modifiers_panel.filter[0],
+        "CUTOFF FRQ",
+        Some(&cutoff_text),
     );
     draw_knob_widget(
         knob_drag,
-        KnobId::ControllersGlide,
-        layout.controller_knobs[1],
-        &mut panel_state.controllers.glide,
-        "GLIDE",
+        KnobId::FilterEmphasis,
+        layout.filter_knobs[1],
+        &mut panel_state.modifiers_panel.filter[1],
+        "EMPHASIS",
         None,
     );
     draw_knob_widget(
         knob_drag,
-        KnobId::ControllersModMix,
-        layout.controller_knobs[2],
-        &mut panel_state.controllers.modulation_mix,
-        "MOD MIX",
+        KnobId::FilterContour,
+        layout.filter_knobs[2],
+        &mut panel_state.modifiers_panel.filter[2],
+        "AMOUNT CONTOUR",
         None,
     );
-    let mod_rate_label = format!("{:.1} Hz", panel_state.mod_lfo_rate());
+
+    let filter_attack_label = format_env_time(panel_state.filter_attack_time());
     draw_knob_widget(
         knob_drag,
-        KnobId::ControllersModRate,
-        layout.controller_extra_knobs[0],
-        &mut panel_state.controllers.modulation_rate,
-        "MOD RATE",
-        Some(&mod_rate_label),
+        KnobId::FilterAttack,
+        layout.filter_env_knobs[0],
+        &mut panel_state.modifiers_panel.filter_env[0],
+        "ATTACK TIME",
+        Some(&filter_attack_label),
     );
-    let mod_amt_label = format_percent(panel_state.mod_amount());
+    let filter_decay_label = format_env_time(panel_state.filter_decay_time());
     draw_knob_widget(
         knob_drag,
-        KnobId::ControllersModAmount,
-        layout.controller_extra_knobs[1],
-        &mut panel_state.controllers.modulation_amount,
-        "MOD AMT",
-        Some(&mod_amt_label),
+        KnobId::FilterDecay,
+        layout.filter_env_knobs[1],
+        &mut panel_state.modifiers_panel.filter_env[1],
+        "DECAY TIME",
+        Some(&filter_decay_label),
+    );
+    let filter_sustain_label = format_percent(panel_state.filter_sustain_level());
+    draw_knob_widget(
+        knob_drag,
+        KnobId::FilterSustain,
+        layout.filter_env_knobs[2],
+        &mut panel_state.modifiers_panel.filter_env[2],
+        "SUSTAIN LVL",
+        Some(&filter_sustain_label),
     );
-    draw_controller_info(panel_state, &layout.controller_rect);
 
-    draw_text_ex(
-        "OSCILLATION MOD",
-        layout.controller_mod_toggle.x,
-        layout.controller_mod_toggle.y - 6.0,
-        TextParams {
-            font_size: 14,
-            color: AMBER_DIM,
-            ..Default::default()
-        },
+    let loud_attack_label = format_env_time(panel_state.loud_attack_time());
+    draw_knob_widget(
+        knob_drag,
+        KnobId::LoudnessAttack,
+        layout.loudness_knobs[0],
+        &mut panel_state.modifiers_panel.loudness_env[0],
+        "LOUD ATTACK",
+        Some(&loud_attack_label),
+    );
+    let loud_decay_label = format_env_time(panel_state.loud_decay_time());
+    draw_knob_widget(
+        knob_drag,
+        KnobId::LoudnessDecay,
+        layout.loudness_knobs[1],
+        &mut panel_state.modifiers_panel.loudness_env[1],
+        "LOUD DECAY",
+        Some(&loud_decay_label),
+    );
+    let loud_sustain_label = format_percent(panel_state.loud_sustain_level());
+    draw_knob_widget(
+        knob_drag,
+        KnobId::LoudnessSustain,
+        layout.loudness_knobs[2],
+        &mut panel_state.modifiers_panel.loudness_env[2],
+        "LOUD SUSTAIN",
+        Some(&loud_sustain_label),
+    );
+}
+
+// The timed stages share the width in proportion to their real
+// lengths; sustain gets a fixed slice since it lasts as long as the key.
+fn draw_envelope_graph(rect: Rect, shape: &EnvelopeShape, state: EnvelopeState) {
+    const SUSTAIN_SHARE: f32 = 0.2;
+    const STEPS: usize = 24;
+    const STAGES: [EnvStage; 6] = [
+        EnvStage::Delay,
+        EnvStage::Attack,
+        EnvStage::Hold,
+        EnvStage::Decay,
+        EnvStage::Sustain,
+        EnvStage::Release,
+    ];
+    // Sustain has no length, so it drops out of the sum.
+    let timed: f32 = STAGES.iter().map(|stage| shape.stage_length(*stage)).sum();
+    let scale = rect.w * (1.0 - SUSTAIN_SHARE) / timed.max(f32::EPSILON);
+    let color = Color::new(AMBER.r, AMBER.g, AMBER.b, 0.3);
+    let point = |x: f32, level: f32| vec2(rect.x + x, rect.y + rect.h * (1.0 - level));
+
+    let mut start = 0.0;
+    let mut prev = point(0.0, 0.0);
+    let mut playhead = None;
+    for stage in STAGES {
+        let length = shape.stage_length(stage);
+        let width = if stage == EnvStage::Sustain {
+            rect.w * SUSTAIN_SHARE
+        } else {
+            length * scale
+        };
+        for step in 1..=STEPS {
+            let ratio = step as f32 / STEPS as f32;
+            let next = point(start + ratio * width, shape.level_at(stage, ratio * length));
+            draw_line(prev.x, prev.y, next.x, next.y, 1.0, color);
+            prev = next;
+        }
+        if stage == state.stage {
+            let progress = if stage == EnvStage::Sustain {
+                0.5
+            } else {
+                shape.time_in_stage(state) / length.max(f32::EPSILON)
+            };
+            playhead = Some(point(start + progress.min(1.0) * width, state.level));
+        }
+        start += width;
+    }
+    if let Some(head) = playhead {
+        draw_line(head.x, rect.y, head.x, rect.y + rect.h, 1.0, color);
+        draw_circle(head.x, head.y, 3.0, AMBER);
+    }
+}
+
+fn draw_output_panel(
+    panel_state: &mut PanelState,
+    knob_drag: &mut KnobDragState,
+    layout: &PanelLayout,
+) {
+    let master = format!("{:.0}%", panel_state.master_level() * 100.0);
+    draw_knob_widget(
+        knob_drag,
+        KnobId::OutputVolume,
+        layout.output_knobs[0],
+        &mut panel_state.output_panel.main_volume,
+        "MAIN VOL",
+        Some(&master),
+    );
+    let phones = format!(
+        "{:.0}%",
+        panel_state.output_panel.phones_volume.value * 100.0
+    );
+    draw_knob_widget(
+        knob_drag,
+        KnobId::OutputPhones,
+        layout.output_knobs[1],
+        &mut panel_state.output_panel.phones_volume,
+        "PHONES",
+        Some(&phones),
     );
+    for (channel, rect) in layout.output_meter_rects.iter().enumerate() {
+        draw_level_meter(
+            *rect,
+            panel_state.meter_peak[channel],
+            panel_state.meter_rms[channel],
+            panel_state.clip_hold[channel] > 0.0,
+            if channel == 0 { "L" } else { "R" },
+        );
+    }
     draw_toggle_switch(
-        layout.controller_mod_toggle,
-        panel_state.osc_modulation,
+        layout.chorus_switch_rect,
+        panel_state.output_panel.chorus_enabled,
         "ON",
     );
-
-    draw_text_ex(
-        "OSC. 3 CONTROL",
-        layout.controller_osc3_toggle.x,
-        layout.controller_osc3_toggle.y - 6.0,
-        TextParams {
-            font_size: 14,
-            color: AMBER_DIM,
-            ..Default::default()
-        },
+    draw_centered_text("CHORUS", layout.chorus_label_rect, 14);
+    draw_toggle_switch(
+        layout.delay_switch_rect,
+        panel_state.output_panel.delay_enabled,
+        "ON",
     );
+    draw_centered_text("DELAY", layout.delay_label_rect, 14);
     draw_toggle_switch(
-        layout.controller_osc3_toggle,
-        panel_state.osc3_control,
+        layout.reverb_switch_rect,
+        panel_state.output_panel.reverb_enabled,
         "ON",
     );
-    draw_text_ex(
-        "GLIDE ON",
-        layout.controller_glide_switch.x,
-        layout.controller_glide_switch.y - 6.0,
-        TextParams {
-            font_size: 14,
-            color: AMBER_DIM,
-            ..Default::default()
-        },
+    draw_centered_text("REVERB", layout.reverb_label_rect, 14);
+    if panel_state.limiter_enabled {
+        draw_gain_reduction_meter(layout.gain_reduction_rect, panel_state.gain_reduction_db);
+    }
+}
+
+fn meter_fraction(level: f32) -> f32 {
+    let db = 20.0 * level.max(1e-6).log10();
+    ((db - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0)
+}
+
+fn draw_level_meter(rect: Rect, peak: f32, rms: f32, clipped: bool, label: &str) {
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 1.0),
     );
-    draw_toggle_switch(
-        layout.controller_glide_switch,
-        panel_state.glide_enabled,
-        "ON",
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER_DIM);
+    let rms_height = meter_fraction(rms) * rect.h;
+    draw_rectangle(
+        rect.x + 1.0,
+        rect.y + rect.h - rms_height,
+        rect.w - 2.0,
+        rms_height,
+        AMBER_DIM,
+    );
+    let peak_y = rect.y + rect.h - meter_fraction(peak) * rect.h;
+    draw_line(rect.x, peak_y, rect.x + rect.w, peak_y, 2.0, AMBER);
+    let clip_rect = Rect::new(rect.x, rect.y - 12.0, rect.w, 8.0);
+    let clip_color = if clipped {
+        Color::new(0.9, 0.2, 0.1, 1.0)
+    } else {
+        Color::new(0.1, 0.08, 0.05, 1.0)
+    };
+    draw_rectangle(
+        clip_rect.x,
+        clip_rect.y,
+        clip_rect.w,
+        clip_rect.h,
+        clip_color,
     );
-
     draw_text_ex(
-        "MOD SOURCE",
-        layout.controller_mod_source_toggle.x,
-        layout.controller_mod_source_toggle.y - 6.0,
+        label,
+        rect.x,
+        rect.y + rect.h + 12.0,
         TextParams {
             font_size: 12,
             color: AMBER_DIM,
             ..Default::default()
         },
     );
-    draw_toggle_switch(
-        layout.controller_mod_source_toggle,
-        panel_state.mod_source_noise,
-        if panel_state.mod_source_noise {
-            "NOISE"
-        } else {
-            "LFO"
-        },
-    );
+}
 
+fn draw_gain_reduction_meter(rect: Rect, reduction_db: f32) {
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 1.0),
+    );
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER_DIM);
+    let fill = (reduction_db / GAIN_REDUCTION_RANGE_DB).clamp(0.0, 1.0) * rect.h;
+    draw_rectangle(rect.x + 1.0, rect.y, rect.w - 2.0, fill, AMBER);
     draw_text_ex(
-        "OSC.3 / FILTER EG",
-        layout.controller_mod_target_toggle.x,
-        layout.controller_mod_target_toggle.y - 6.0,
+        "GR",
+        rect.x - 4.0,
+        rect.y - 4.0,
         TextParams {
             font_size: 12,
             color: AMBER_DIM,
             ..Default::default()
         },
     );
-    draw_toggle_switch(
-        layout.controller_mod_target_toggle,
-        panel_state.mod_target_filter,
-        if panel_state.mod_target_filter {
-            "FILTER"
-        } else {
-            "OSC3"
-        },
-    );
+}
 
-    draw_text_ex(
-        "DECAY",
-        layout.controller_decay_switch.x,
-        layout.controller_decay_switch.y - 6.0,
-        TextParams {
-            font_size: 14,
-            color: AMBER_DIM,
-            ..Default::default()
-        },
+fn draw_knob_widget(
+    knob_drag: &mut KnobDragState,
+    knob_id: KnobId,
+    rect: Rect,
+    knob: &mut KnobValue,
+    label: &str,
+    display: Option<&str>,
+) {
+    handle_knob_drag(knob_drag, knob_id, rect, knob);
+    let center = vec2(rect.x + rect.w * 0.5, rect.y + rect.h * 0.5);
+    let radius = rect.w.min(rect.h) * 0.35;
+    draw_circle(
+        center.x,
+        center.y,
+        radius + 6.0,
+        Color::new(0.05, 0.03, 0.02, 1.0),
     );
-    draw_toggle_switch(
-        layout.controller_decay_switch,
-        panel_state.decay_enabled,
-        "ON",
+    draw_circle(
+        center.x,
+        center.y,
+        radius,
+        Color::new(0.12, 0.12, 0.12, 1.0),
+    );
+    draw_circle(
+        center.x,
+        center.y,
+        radius * 0.65,
+        Color::new(0.2, 0.2, 0.2, 1.0),
+    );
+    draw_circle_lines(center.x, center.y, radius + 6.0, 1.0, AMBER_DIM);
+    draw_circle_lines(
+        center.x,
+        center.y,
+        radius,
+        1.0,
+        Color::new(0.4, 0.4, 0.4, 0.3),
+    );
+    let angle_range = 270.0f32.to_radians();
+    let start_angle = -std::f32::consts::FRAC_PI_2 - angle_range * 0.5;
+    let theta = start_angle + knob.value.clamp(0.0, 1.0) * angle_range;
+    let pointer = vec2(theta.cos(), theta.sin()) * radius * 0.8;
+    draw_line(
+        center.x,
+        center.y,
+        center.x + pointer.x,
+        center.y + pointer.y,
+        3.0,
+        AMBER,
+    );
+    if !knob.implemented {
+        draw_centered_text(
+            "!",
+            Rect::new(rect.x, rect.y + rect.h * 0.5 - 12.0, rect.w, 24.0),
+            30,
+        );
+    }
+    if let Some(text) = display {
+        draw_centered_text(text, Rect::new(rect.x, rect.y - 12.0, rect.w, 20.0), 14);
+    }
+    draw_centered_text(
+        label,
+        Rect::new(rect.x, rect.y + rect.h + 4.0, rect.w, 18.0),
+        16,
     );
+}
 
-    draw_button(layout.controller_s_trigger_button, "S-TRIG");
+fn handle_knob_drag(
+    knob_drag: &mut KnobDragState,
+    knob_id: KnobId,
+    rect: Rect,
+    knob: &mut KnobValue,
+) {
+    let mouse = mouse_position_vec();
+    let hovered = rect.contains(mouse) && !knob_drag.input_blocked;
+    let fine = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+    if is_mouse_button_pressed(MouseButton::Left) && hovered {
+        let now = get_time();
+        let double_click = knob_drag
+            .last_click
+            .is_some_and(|(last, at)| last == knob_id && now - at < DOUBLE_CLICK_SEC);
+        if double_click {
+            // A double-click resets rather than starting another drag.
+            knob.value = knob_id.default_value();
+            knob_drag.last_click = None;
+            return;
+        }
+        knob_drag.last_click = Some((knob_id, now));
+        knob_drag.pressed = Some(knob_id);
+        knob_drag.active_knob = Some(knob_id);
+        knob_drag.right_drag = false;
+        knob_drag.last_y = mouse.y;
+    }
+    if is_mouse_button_pressed(MouseButton::Right) && hovered {
+        knob_drag.active_knob = Some(knob_id);
+        knob_drag.right_drag = true;
+        knob_drag.last_y = mouse.y;
+    }
+    if knob_drag.active_knob == Some(knob_id) {
+        let button = if knob_drag.right_drag {
+            MouseButton::Right
+        } else {
+            MouseButton::Left
+        };
+        if is_mouse_button_down(button) {
+            let mut delta = (knob_drag.last_y - mouse.y) * KNOB_DRAG_PER_PIXEL;
+            if fine || knob_drag.right_drag {
+                delta /= KNOB_FINE_DIVISOR;
+            }
+            knob.value = (knob.value + delta).clamp(0.0, 1.0);
+            knob_drag.last_y = mouse.y;
+        } else {
+            knob_drag.active_knob = None;
+        }
+    }
+    let (_x, wheel) = mouse_wheel();
+    if hovered && wheel.abs() > f32::EPSILON {
+        let step = if fine { 0.03 / KNOB_FINE_DIVISOR } else { 0.03 };
+        knob.value = (knob.value + wheel * step).clamp(0.0, 1.0);
+    }
+    if hovered || knob_drag.active_knob == Some(knob_id) {
+        knob_drag.tooltip = Some((knob_id, knob.value, rect));
+    }
 }
 
-fn draw_controller_info(panel_state: &PanelState, rect: &Rect) {
-    draw_text_block(
-        rect.x + 16.0,
-        rect.y + 40.0,
-        &format!(
-            "GATE {}\nLAST NOTE {}\nVOLTAGE {:.2} V\nFREQUENCY {:.1} Hz",
-            if panel_state.last_midi >= 0 {
-                "OPEN"
-            } else {
-                "IDLE"
-            },
-            if panel_state.last_midi >= 0 {
-                panel_state.last_midi.to_string()
-            } else {
-                "-".into()
-            },
-            panel_state.last_voltage,
-            voltage_to_frequency(panel_state.last_voltage)
+// Drawn last so it sits over neighbouring knobs and windows, kept on screen.
+fn draw_knob_tooltip(knob_id: KnobId, value: f32, knob_rect: Rect) {
+    let (min, max) = knob_id.range_text();
+    let lines = [
+        format!(
+            "{} {}",
+            knob_id.label().to_uppercase(),
+            knob_id.format_value(value)
         ),
+        format!("{min} to {max}"),
+    ];
+    let width = lines
+        .iter()
+        .map(|line| measure_text(line, None, 16, 1.0).width)
+        .fold(0.0, f32::max)
+        + 16.0;
+    let height = 44.0;
+    let x = (knob_rect.x + knob_rect.w * 0.5 - width * 0.5).clamp(4.0, SCREEN_WIDTH - width - 4.0);
+    let y = if knob_rect.y > height + 20.0 {
+        knob_rect.y - height - 16.0
+    } else {
+        knob_rect.y + knob_rect.h + 24.0
+    };
+    draw_rectangle(x, y, width, height, Color::new(0.02, 0.02, 0.02, 0.95));
+    draw_rectangle_lines(x, y, width, height, 1.0, AMBER);
+    for (row, (line, color)) in lines.iter().zip([AMBER, AMBER_DIM]).enumerate() {
+        draw_text_ex(
+            line,
+            x + 8.0,
+            y + 18.0 + row as f32 * 18.0,
+            TextParams {
+                font_size: 16,
+                color,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+struct KeyVisual {
+    rect: Rect,
+    keycode: KeyCode,
+    label: &'static str,
+}
+
+struct KeyboardLayout {
+    white: Vec<KeyVisual>,
+    black: Vec<KeyVisual>,
+}
+
+impl KeyboardLayout {
+    fn hit_test(&self, point: Vec2) -> Option<KeyCode> {
+        for key in &self.black {
+            if key.rect.contains(point) {
+                return Some(key.keycode);
+            }
+        }
+        for key in &self.white {
+            if key.rect.contains(point) {
+                return Some(key.keycode);
+            }
+        }
+        None
+    }
+
+    /// Lower on a key plays louder, like striking it harder.
+    fn click_velocity(&self, point: Vec2) -> f32 {
+        self.black
+            .iter()
+            .chain(&self.white)
+            .find(|key| key.rect.contains(point))
+            .map_or(1.0, |key| {
+                let depth = ((point.y - key.rect.y) / key.rect.h).clamp(0.0, 1.0);
+                MIN_CLICK_VELOCITY + (1.0 - MIN_CLICK_VELOCITY) * depth
+            })
+    }
+
+    /// Hit test along the row of `held`, ignoring the pointer's height.
+    fn hit_test_row(&self, point: Vec2, held: KeyCode) -> Option<KeyCode> {
+        let row = self
+            .white
+            .iter()
+            .chain(&self.black)
+            .find(|key| key.keycode == held)?
+            .rect;
+        self.hit_test(vec2(point.x, row.center().y))
+    }
+}
+
+fn build_keyboard_layout(controller: &KeyboardController) -> KeyboardLayout {
+    let strip = controller_strip_rect();
+    let area_x = strip.x + strip.w + 20.0;
+    let area = Rect::new(
+        area_x,
+        PANEL_HEIGHT + 40.0,
+        SCREEN_WIDTH - 40.0 - area_x,
+        SCREEN_HEIGHT - PANEL_HEIGHT - 80.0,
     );
-    draw_text_block(
-        rect.x + 16.0,
-        rect.y + rect.h - 60.0,
-        &format!(
-            "TUNE {:+.2} OCT\nGLIDE {:.2} s\nMOD NOISE {}",
-            panel_state.tune_offset(),
-            panel_state.glide_time(),
-            panel_state.mod_noise_color.label()
-        ),
+    let spacing = 18.0;
+    let white_count = controller.white_keys().len() as f32;
+    let max_size_width = (area.w - spacing * (white_count - 1.0)) / white_count;
+    let max_size_height = (area.h - spacing * 3.0) / 2.0;
+    let key_size = max_size_width.min(max_size_height).max(40.0);
+    let total_width = white_count * key_size + (white_count - 1.0) * spacing;
+    // Black key positions are fractions of a full row of white keys, so
+    // they stay between the right notes when a layout binds fewer.
+    let slots = WHITE_KEY_SLOTS as f32;
+    let slot_width = slots * key_size + (slots - 1.0) * spacing;
+    let start_x = area.x + (area.w - total_width) * 0.5;
+    let white_y = area.y + area.h - key_size;
+    let black_y = white_y - key_size - spacing * 0.7;
+
+    let mut white = Vec::new();
+    for (index, binding) in controller.white_keys().iter().enumerate() {
+        let x = start_x + index as f32 * (key_size + spacing);
+        let rect = Rect::new(x, white_y, key_size, key_size);
+        white.push(KeyVisual {
+            rect,
+            keycode: binding.keycode,
+            label: binding.label,
+        });
+    }
+
+    let mut black = Vec::new();
+    for binding in controller.black_keys() {
+        let center = start_x + binding.position_hint * slot_width;
+        let rect = Rect::new(center - key_size * 0.5, black_y, key_size, key_size);
+        if rect.x + rect.w >= area.x && rect.x <= area.x + area.w {
+            black.push(KeyVisual {
+                rect,
+                keycode: binding.keycode,
+                label: binding.label,
+            });
+        }
+    }
+
+    KeyboardLayout { white, black }
+}
+
+fn draw_controller_strip(panel_state: &PanelState) {
+    let strip = controller_strip_rect();
+    draw_rectangle(
+        strip.x,
+        strip.y,
+        strip.w,
+        strip.h,
+        Color::new(0.05, 0.03, 0.02, 0.65),
+    );
+    draw_rectangle_lines(strip.x, strip.y, strip.w, strip.h, 1.0, AMBER);
+    draw_centered_text("MOD", Rect::new(strip.x, strip.y + 4.0, strip.w, 16.0), 14);
+
+    let wheel = mod_wheel_rect();
+    draw_rectangle(
+        wheel.x,
+        wheel.y,
+        wheel.w,
+        wheel.h,
+        Color::new(0.02, 0.02, 0.02, 1.0),
+    );
+    let fill = wheel.h * panel_state.mod_wheel.clamp(0.0, 1.0);
+    draw_rectangle(
+        wheel.x + 2.0,
+        wheel.y + wheel.h - fill,
+        wheel.w - 4.0,
+        fill,
+        AMBER_DIM,
     );
-}
-
-fn draw_text_block(x: f32, mut y: f32, text: &str) {
-    for line in text.lines() {
-        draw_text_ex(
-            line,
-            x,
+    for step in 1..8 {
+        let y = wheel.y + wheel.h * step as f32 / 8.0;
+        draw_line(
+            wheel.x + 4.0,
             y,
-            TextParams {
-                font_size: 18,
-                color: AMBER,
-                ..Default::default()
-            },
+            wheel.x + wheel.w - 4.0,
+            y,
+            1.0,
+            Color::new(0.2, 0.1, 0.03, 0.6),
         );
-        y += 22.0;
     }
+    let marker_y = wheel.y + wheel.h - fill;
+    draw_line(
+        wheel.x - 3.0,
+        marker_y,
+        wheel.x + wheel.w + 3.0,
+        marker_y,
+        3.0,
+        AMBER,
+    );
+    draw_rectangle_lines(wheel.x, wheel.y, wheel.w, wheel.h, 1.0, AMBER);
+    draw_centered_text(
+        &format_percent(panel_state.mod_wheel),
+        Rect::new(strip.x, strip.y + strip.h - 20.0, strip.w, 16.0),
+        14,
+    );
 }
 
-fn draw_oscillators(
-    panel_state: &mut PanelState,
-    knob_drag: &mut KnobDragState,
-    layout: &PanelLayout,
-) {
-    for index in 0..3 {
-        let range_label = panel_state.osc_range_setting(index).label;
-        draw_knob_widget(
-            knob_drag,
-            match index {
-                0 => KnobId::OscRange1,
-                1 => KnobId::OscRange2,
-                _ => KnobId::OscRange3,
-            },
-            layout.osc_range_knobs[index],
-            &mut panel_state.oscillator.range[index],
-            &format!("OSC {} RANGE", index + 1),
-            Some(range_label),
+// The readout names the lowest on-screen key, so the octave shift reads
+// as an absolute position.
+fn draw_octave_buttons(controller: &KeyboardController) {
+    let [down, readout, up] = octave_button_rects();
+    draw_button(down, "-");
+    draw_button(up, "+");
+    let lowest = controller
+        .white_keys()
+        .first()
+        .map_or(0, |binding| binding.midi + controller.octave_shift() * 12);
+    draw_centered_text(&note_name(lowest), readout, 16);
+}
+
+fn draw_keyboard(controller: &KeyboardController, layout: &KeyboardLayout) {
+    // A key waiting for a new binding shows as held, with a question mark.
+    let learning = |key: &KeyVisual| controller.learning_key() == Some(key.keycode);
+    let label = |key: &KeyVisual| if learning(key) { "?" } else { key.label };
+    let active = |key: &KeyVisual| controller.is_pressed(key.keycode) || learning(key);
+    for key in &layout.white {
+        draw_key(
+            key.rect,
+            active(key),
+            false,
+            controller.in_scale(key.keycode) && !controller.muted(key.keycode),
+            label(key),
         );
-        let freq_rect = layout.osc_freq_knobs[index];
-        let wave_rect = layout.osc_wave_knobs[index];
-        let detune = panel_state.osc_detune(index);
-        let freq_label = format!("OSC {} FREQ", index + 1);
-        let detune_label = format!("{:+.2} OCT", detune);
-        draw_knob_widget(
-            knob_drag,
-            match index {
-                0 => KnobId::OscFreq1,
-                1 => KnobId::OscFreq2,
-                _ => KnobId::OscFreq3,
-            },
-            freq_rect,
-            &mut panel_state.oscillator.freq[index],
-            &freq_label,
-            Some(&detune_label),
+    }
+    for key in &layout.black {
+        draw_key(
+            key.rect,
+            active(key),
+            true,
+            controller.in_scale(key.keycode) && !controller.muted(key.keycode),
+            label(key),
         );
-        let waveform = value_to_waveform(index, panel_state.oscillator.waveform[index].value);
-        let wave_label = format!("OSC {} WAVE", index + 1);
-        draw_knob_widget(
-            knob_drag,
-            match index {
-                0 => KnobId::OscWave1,
-                1 => KnobId::OscWave2,
-                _ => KnobId::OscWave3,
-            },
-            wave_rect,
-            &mut panel_state.oscillator.waveform[index],
-            &wave_label,
-            Some(waveform.label()),
+    }
+    // A bar over the lowest key of the upper zone marks the split.
+    if let Some(key) = layout
+        .white
+        .iter()
+        .chain(&layout.black)
+        .find(|key| controller.is_split_key(key.keycode))
+    {
+        draw_rectangle(
+            key.rect.x + 4.0,
+            key.rect.y - 8.0,
+            key.rect.w - 8.0,
+            4.0,
+            AMBER,
         );
     }
 }
 
-fn draw_mixer(panel_state: &mut PanelState, knob_drag: &mut KnobDragState, layout: &PanelLayout) {
-    draw_text_ex(
-        "VOLUME",
-        layout.mixer_rect.x + 10.0,
-        layout.mixer_rect.y + 16.0,
-        TextParams {
-            font_size: 18,
-            color: AMBER,
-            ..Default::default()
-        },
+// Keys outside the selected scale get a dim outline: they still play, but
+// snap to a neighbouring scale note. Keys in a muted upper zone are dimmed
+// the same way.
+fn draw_key(rect: Rect, active: bool, filled: bool, in_scale: bool, label: &str) {
+    let fill_color = if active {
+        Color::new(0.3, 0.2, 0.07, 0.9)
+    } else if filled {
+        Color::new(0.08, 0.05, 0.03, 0.95)
+    } else {
+        Color::new(0.02, 0.02, 0.02, 0.95)
+    };
+    draw_rounded_rect(rect, 10.0, fill_color);
+    draw_rounded_rect_lines(rect, 10.0, if in_scale { AMBER } else { AMBER_DIM });
+    draw_centered_text(label, rect, KEY_FONT_SIZE);
+}
+
+fn draw_rounded_rect(rect: Rect, radius: f32, color: Color) {
+    draw_rectangle(
+        rect.x + radius,
+        rect.y,
+        rect.w - 2.0 * radius,
+        rect.h,
+        color,
     );
-    let osc_labels = ["OSC 1", "OSC 2", "OSC 3"];
-    for index in 0..3 {
-        let value_text = format!("{:.1}", panel_state.mixer_panel.osc[index].value * 10.0);
-        draw_knob_widget(
-            knob_drag,
-            match index {
-                0 => KnobId::MixerOsc1,
-                1 => KnobId::MixerOsc2,
-                _ => KnobId::MixerOsc3,
-            },
-            layout.mixer_osc_knobs[index],
-            &mut panel_state.mixer_panel.osc[index],
-            osc_labels[index],
-            Some(&format!("{value_text}/10")),
-        );
-        draw_knob_scale(layout.mixer_osc_knobs[index]);
-        draw_toggle_switch(
-            layout.mixer_toggle_rects[index],
-            panel_state.mixer_panel.osc_enabled[index],
-            "ON",
-        );
+    draw_rectangle(
+        rect.x,
+        rect.y + radius,
+        rect.w,
+        rect.h - 2.0 * radius,
+        color,
+    );
+    draw_circle(rect.x + radius, rect.y + radius, radius, color);
+    draw_circle(rect.x + rect.w - radius, rect.y + radius, radius, color);
+    draw_circle(rect.x + radius, rect.y + rect.h - radius, radius, color);
+    draw_circle(
+        rect.x + rect.w - radius,
+        rect.y + rect.h - radius,
+        radius,
+        color,
+    );
+}
+
+fn draw_rounded_rect_lines(rect: Rect, radius: f32, color: Color) {
+    let top = rect.y;
+    let bottom = rect.y + rect.h;
+    let left = rect.x;
+    let right = rect.x + rect.w;
+    let left_x = left + radius;
+    let right_x = right - radius;
+    let top_y = top + radius;
+    let bottom_y = bottom - radius;
+
+    draw_line(left_x, top, right_x, top, 1.0, color);
+    draw_line(left_x, bottom, right_x, bottom, 1.0, color);
+    draw_line(left, top_y, left, bottom_y, 1.0, color);
+    draw_line(right, top_y, right, bottom_y, 1.0, color);
+
+    draw_corner_arc(
+        vec2(left_x, top_y),
+        std::f32::consts::PI,
+        1.5 * std::f32::consts::PI,
+        radius,
+        color,
+    );
+    draw_corner_arc(
+        vec2(right_x, top_y),
+        1.5 * std::f32::consts::PI,
+        0.0,
+        radius,
+        color,
+    );
+    draw_corner_arc(
+        vec2(right_x, bottom_y),
+        0.0,
+        0.5 * std::f32::consts::PI,
+        radius,
+        color,
+    );
+    draw_corner_arc(
+        vec2(left_x, bottom_y),
+        0.5 * std::f32::consts::PI,
+        std::f32::consts::PI,
+        radius,
+        color,
+    );
+}
+
+fn draw_corner_arc(center: Vec2, start: f32, end: f32, radius: f32, color: Color) {
+    let tau = std::f32::consts::TAU;
+    let normalized_start = start.rem_euclid(tau);
+    let normalized_end = end.rem_euclid(tau);
+    let mut sweep = normalized_end - normalized_start;
+    if sweep <= 0.0 {
+        sweep += tau;
     }
-    let extra_labels = ["EXT INPUT", "NOISE"];
-    let mut extra_knobs = [
-        &mut panel_state.mixer_panel.external_input,
-        &mut panel_state.mixer_panel.noise,
-    ];
-    for index in 0..2 {
-        let knob = &mut extra_knobs[index];
-        let label = extra_labels[index];
-        draw_knob_widget(
-            knob_drag,
-            if index == 0 {
-                KnobId::MixerExternal
-            } else {
-                KnobId::MixerNoise
-            },
-            layout.mixer_extra_knobs[index],
-            knob,
-            label,
-            Some(&format!("{:.1}/10", knob.value * 10.0)),
+    let steps = 10;
+    let mut prev = center
+        + vec2(
+            normalized_start.cos() * radius,
+            normalized_start.sin() * radius,
         );
-        draw_knob_scale(layout.mixer_extra_knobs[index]);
-        let toggle_index = 3 + index;
-        let enabled = if index == 0 {
-            panel_state.mixer_panel.ext_enabled
-        } else {
-            panel_state.mixer_panel.noise_enabled
-        };
-        draw_toggle_switch(layout.mixer_toggle_rects[toggle_index], enabled, "ON");
+    for idx in 1..=steps {
+        let angle = normalized_start + sweep * (idx as f32 / steps as f32);
+        let norm_angle = angle.rem_euclid(tau);
+        let next = center + vec2(norm_angle.cos() * radius, norm_angle.sin() * radius);
+        draw_line(prev.x, prev.y, next.x, next.y, 1.0, color);
+        prev = next;
     }
-    draw_noise_selector(
-        layout.noise_selector_rect,
-        panel_state.mixer_panel.noise_color,
-    );
-    draw_overload_lamp(layout.overload_rect, panel_state.filter_overload);
 }
 
-fn draw_knob_scale(rect: Rect) {
-    draw_text_ex(
-        "10",
-        rect.x + rect.w + 8.0,
-        rect.y + 14.0,
-        TextParams {
-            font_size: 12,
-            color: AMBER_DIM,
-            ..Default::default()
-        },
-    );
+fn draw_centered_text(text: &str, rect: Rect, size: u16) {
+    let measure = measure_text(text, None, size, 1.0);
+    let x = rect.x + rect.w * 0.5 - measure.width * 0.5;
+    let y = rect.y + rect.h * 0.5 + measure.height * 0.5;
     draw_text_ex(
-        "0",
-        rect.x + rect.w + 14.0,
-        rect.y + rect.h - 4.0,
+        text,
+        x,
+        y,
         TextParams {
-            font_size: 12,
-            color: AMBER_DIM,
+            font_size: size,
+            color: AMBER,
             ..Default::default()
         },
     );
 }
 
-fn draw_toggle_switch(rect: Rect, on: bool, label: &str) {
-    let color = if on {
-        AMBER
+fn draw_debug_button(state: &DebugWindowState) {
+    let rect = Rect::new(SCREEN_WIDTH - 170.0, PANEL_HEIGHT + 25.0, 140.0, 36.0);
+    if state.open {
+        draw_text_ex(
+            "DEBUG OPEN",
+            rect.x,
+            rect.y - 6.0,
+            TextParams {
+                font_size: 18,
+                color: AMBER,
+                ..Default::default()
+            },
+        );
     } else {
-        Color::new(0.1, 0.08, 0.05, 1.0)
-    };
+        draw_rectangle(
+            rect.x,
+            rect.y,
+            rect.w,
+            rect.h,
+            Color::new(0.05, 0.03, 0.02, 1.0),
+        );
+        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+        draw_centered_text("DEBUGGER", rect, 18);
+    }
+}
+
+fn draw_record_button(recording: Option<&Recording>) {
+    let rect = record_button_rect();
     draw_rectangle(
         rect.x,
         rect.y,
         rect.w,
         rect.h,
-        Color::new(0.02, 0.02, 0.02, 1.0),
+        Color::new(0.05, 0.03, 0.02, 1.0),
     );
     draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
-    draw_rectangle(
-        rect.x + 2.0,
-        rect.y + 2.0,
-        rect.w - 4.0,
-        rect.h - 4.0,
-        color,
+    let Some(active) = recording else {
+        draw_centered_text("REC", rect, 18);
+        return;
+    };
+    let seconds = active.elapsed().as_secs();
+    draw_circle(
+        rect.x + 18.0,
+        rect.y + rect.h * 0.5,
+        7.0,
+        Color::new(0.9, 0.15, 0.1, 1.0),
     );
-    draw_text_ex(
-        label,
-        rect.x + 4.0,
-        rect.y + rect.h - 4.0,
-        TextParams {
-            font_size: 12,
-            color: BACKGROUND,
-            ..Default::default()
-        },
+    draw_centered_text(
+        &format!("STOP {:02}:{:02}", seconds / 60, seconds % 60),
+        Rect::new(rect.x + 16.0, rect.y, rect.w - 16.0, rect.h),
+        18,
     );
 }
 
-fn draw_button(rect: Rect, label: &str) {
+fn draw_midi_file_button(player: Option<&MidiPlayer>) {
+    let rect = midi_file_button_rect();
     draw_rectangle(
         rect.x,
         rect.y,
         rect.w,
         rect.h,
-        Color::new(0.08, 0.05, 0.03, 1.0),
+        Color::new(0.05, 0.03, 0.02, 1.0),
     );
     draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
-    draw_centered_text(label, rect, 16);
+    let Some(player) = player else {
+        draw_centered_text("DROP .MID", rect, 18);
+        return;
+    };
+    let seconds = player.position() as u64;
+    let action = if player.is_playing() { "PAUSE" } else { "PLAY" };
+    draw_centered_text(
+        &format!("{action} {:02}:{:02}", seconds / 60, seconds % 60),
+        rect,
+        18,
+    );
+}
+
+fn draw_audio_status(audio: &AudioLink) {
+    let (text, color) = if audio.engine.is_null() {
+        let reason: String = audio
+            .error
+            .as_deref()
+            .unwrap_or("no output device")
+            .chars()
+            .take(AUDIO_STATUS_CHARS)
+            .collect();
+        (
+            format!("NO AUDIO - RUNNING SILENT, RETRYING: {reason}"),
+            Color::new(0.9, 0.15, 0.1, 1.0),
+        )
+    } else {
+        (format!("AUDIO: {}", audio.engine.device_name()), AMBER)
+    };
+    draw_text_ex(
+        &text,
+        40.0,
+        PANEL_HEIGHT + 48.0,
+        TextParams {
+            font_size: 18,
+            color,
+            ..Default::default()
+        },
+    );
 }
 
-fn draw_noise_selector(rect: Rect, selection: NoiseColor) {
+fn draw_options_button(state: &OptionsWindowState) {
+    let rect = options_button_rect();
     draw_rectangle(
         rect.x,
         rect.y,
         rect.w,
         rect.h,
-        Color::new(0.08, 0.05, 0.03, 1.0),
+        Color::new(0.05, 0.03, 0.02, 1.0),
     );
     draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
-    let label_rect = Rect::new(rect.x, rect.y + 1.0, rect.w, 18.0);
-    draw_centered_text("NOISE", label_rect, 16);
-    let color_rect = Rect::new(rect.x, rect.y + rect.h - 22.0, rect.w, 18.0);
-    draw_centered_text(selection.label(), color_rect, 16);
-    draw_text_ex(
-        "CLICK / TAB TO CYCLE",
-        rect.x,
-        rect.y - 8.0,
-        TextParams {
-            font_size: 12,
-            color: AMBER_DIM,
-            ..Default::default()
-        },
-    );
+    draw_centered_text(if state.open { "CLOSE" } else { "OPTIONS" }, rect, 18);
 }
 
-fn draw_overload_lamp(rect: Rect, active: bool) {
-    let color = if active {
-        AMBER
-    } else {
-        Color::new(0.1, 0.08, 0.05, 1.0)
-    };
+fn draw_macro_button(state: &MacroWindowState) {
+    let rect = macro_button_rect();
     draw_rectangle(
         rect.x,
         rect.y,
         rect.w,
         rect.h,
-        Color::new(0.02, 0.02, 0.02, 1.0),
+        Color::new(0.05, 0.03, 0.02, 1.0),
     );
     draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
-    draw_circle(
-        rect.x + rect.w * 0.5,
-        rect.y + rect.h * 0.5,
-        rect.w.min(rect.h) * 0.3,
-        color,
-    );
-    draw_text_ex(
-        "OVERLOAD",
-        rect.x - 10.0,
-        rect.y - 4.0,
-        TextParams {
-            font_size: 12,
-            color: AMBER,
-            ..Default::default()
-        },
-    );
+    draw_centered_text(if state.open { "CLOSE" } else { "MACROS" }, rect, 18);
 }
 
-fn draw_modifiers(
+fn draw_macro_window(
+    state: &MacroWindowState,
     panel_state: &mut PanelState,
     knob_drag: &mut KnobDragState,
-    layout: &PanelLayout,
 ) {
-    let filter_line = layout.filter_env_divider;
-    draw_line(
-        layout.modifier_rect.x + 8.0,
-        filter_line,
-        layout.modifier_rect.x + layout.modifier_rect.w - 8.0,
-        filter_line,
-        1.0,
-        AMBER_DIM,
+    let rect = state.rect;
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 0.95),
     );
-    let filter_label = "FILTER CONTOUR";
-    let filter_metrics = measure_text(filter_label, None, 18, 1.0);
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
     draw_text_ex(
-        filter_label,
-        layout.modifier_rect.x + layout.modifier_rect.w * 0.5 - filter_metrics.width * 0.5,
-        filter_line + 20.0,
+        "MACROS",
+        rect.x + 12.0,
+        rect.y + 26.0,
         TextParams {
-            font_size: 18,
+            font_size: 20,
             color: AMBER,
             ..Default::default()
         },
     );
+    let close = state.close_rect();
+    draw_rectangle_lines(close.x, close.y, close.w, close.h, 1.0, AMBER);
+    draw_centered_text("X", close, 20);
 
-    let line_y = layout.modifier_loudness_split + 10.0;
-    draw_line(
-        layout.modifier_rect.x + 8.0,
-        line_y,
-        layout.modifier_rect.x + layout.modifier_rect.w - 8.0,
-        line_y,
-        1.0,
-        AMBER_DIM,
-    );
-    let loudness_label = "LOUDNESS CONTOUR";
-    let label_metrics = measure_text(loudness_label, None, 18, 1.0);
+    for (index, knob_id) in MACRO_KNOBS.into_iter().enumerate() {
+        let amount = format_percent(panel_state.macro_knobs[index].value);
+        draw_knob_widget(
+            knob_drag,
+            knob_id,
+            state.knob_rect(index),
+            &mut panel_state.macro_knobs[index],
+            &format!("MACRO {}", index + 1),
+            Some(&amount),
+        );
+        let assign = state.assign_rect(index);
+        if state.learning == Some(index) {
+            draw_rectangle(assign.x, assign.y, assign.w, assign.h, AMBER_DIM);
+        }
+        draw_rectangle_lines(assign.x, assign.y, assign.w, assign.h, 1.0, AMBER);
+        draw_centered_text("ASSIGN", assign, 16);
+
+        let targets = panel_state.macros[index].targets();
+        if targets.is_empty() {
+            let row = state.target_rect(index, 0);
+            draw_text_ex(
+                "NO KNOBS",
+                row.x + 6.0,
+                row.y + 15.0,
+                TextParams {
+                    font_size: 14,
+                    color: AMBER_DIM,
+                    ..Default::default()
+                },
+            );
+        }
+        for (row, target) in targets.iter().enumerate() {
+            let row = state.target_rect(index, row);
+            draw_rectangle_lines(row.x, row.y, row.w, row.h, 1.0, AMBER_DIM);
+            draw_text_ex(
+                &format!(
+                    "{} {:+.0}%",
+                    target.knob.label().to_uppercase(),
+                    target.depth * 100.0
+                ),
+                row.x + 6.0,
+                row.y + 15.0,
+                TextParams {
+                    font_size: 14,
+                    color: AMBER,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+    let hint = if state.learning.is_some() {
+        "CLICK PANEL KNOBS TO ADD OR REMOVE THEM, THEN ASSIGN AGAIN TO FINISH"
+    } else {
+        "CLICK A ROW TO FLIP IT, SCROLL FOR DEPTH, RIGHT-CLICK TO REMOVE"
+    };
     draw_text_ex(
-        loudness_label,
-        layout.modifier_rect.x + layout.modifier_rect.w * 0.5 - label_metrics.width * 0.5,
-        line_y + 58.0,
+        hint,
+        rect.x + 16.0,
+        rect.y + rect.h - 12.0,
         TextParams {
-            font_size: 18,
-            color: AMBER,
-            ..Default::default()
-        },
-    );
-    let cutoff_text = format!("{:.0} Hz", panel_state.cutoff_hz());
-    draw_knob_widget(
-        knob_drag,
-        KnobId::FilterCutoff,
-        layout.filter_knobs[0],
-        &mut panel_state.modifiers_panel.filter[0],
-        "CUTOFF FRQ",
-        Some(&cutoff_text),
-    );
-    draw_knob_widget(
-        knob_drag,
-        KnobId::FilterEmphasis,
-        layout.filter_knobs[1],
-        &mut panel_state.modifiers_panel.filter[1],
-        "EMPHASIS",
-        None,
-    );
-    draw_knob_widget(
-        knob_drag,
-        KnobId::FilterContour,
-        layout.filter_knobs[2],
-        &mut panel_state.modifiers_panel.filter[2],
-        "AMOUNT CONTOUR",
-        None,
-    );
-
-    let filter_attack_label = format_env_time(panel_state.filter_attack_time());
-    draw_knob_widget(
-        knob_drag,
-        KnobId::FilterAttack,
-        layout.filter_env_knobs[0],
-        &mut panel_state.modifiers_panel.filter_env[0],
-        "ATTACK TIME",
-        Some(&filter_attack_label),
-    );
-    let filter_decay_label = format_env_time(panel_state.filter_decay_time());
-    draw_knob_widget(
-        knob_drag,
-        KnobId::FilterDecay,
-        layout.filter_env_knobs[1],
-        &mut panel_state.modifiers_panel.filter_env[1],
-        "DECAY TIME",
-        Some(&filter_decay_label),
-    );
-    let filter_sustain_label = format_percent(panel_state.filter_sustain_level());
-    draw_knob_widget(
-        knob_drag,
-        KnobId::FilterSustain,
-        layout.filter_env_knobs[2],
-        &mut panel_state.modifiers_panel.filter_env[2],
-        "SUSTAIN LVL",
-        Some(&filter_sustain_label),
-    );
-
-    let loud_attack_label = format_env_time(panel_state.loud_attack_time());
-    draw_knob_widget(
-        knob_drag,
-        KnobId::LoudnessAttack,
-        layout.loudness_knobs[0],
-        &mut panel_state.modifiers_panel.loudness_env[0],
-        "LOUD ATTACK",
-        Some(&loud_attack_label),
-    );
-    let loud_decay_label = format_env_time(panel_state.loud_decay_time());
-    draw_knob_widget(
-        knob_drag,
-        KnobId::LoudnessDecay,
-        layout.loudness_knobs[1],
-        &mut panel_state.modifiers_panel.loudness_env[1],
-        "LOUD DECAY",
-        Some(&loud_decay_label),
-    );
-    let loud_sustain_label = format_percent(panel_state.loud_sustain_level());
-    draw_knob_widget(
-        knob_drag,
-        KnobId::LoudnessSustain,
-        layout.loudness_knobs[2],
-        &mut panel_state.modifiers_panel.loudness_env[2],
-        "LOUD SUSTAIN",
-        Some(&loud_sustain_label),
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
     );
 }
 
-fn draw_output_panel(
+fn draw_envelope_editor(
+    state: &EnvelopeEditorState,
     panel_state: &mut PanelState,
     knob_drag: &mut KnobDragState,
-    layout: &PanelLayout,
 ) {
-    let master = format!("{:.0}%", panel_state.master_level() * 100.0);
-    draw_knob_widget(
-        knob_drag,
-        KnobId::OutputVolume,
-        layout.output_knobs[0],
-        &mut panel_state.output_panel.main_volume,
-        "MAIN VOL",
-        Some(&master),
+    let Some(contour) = state.open else {
+        return;
+    };
+    let rect = state.rect;
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 0.95),
     );
-    let phones = format!(
-        "{:.0}%",
-        panel_state.output_panel.phones_volume.value * 100.0
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_text_ex(
+        if contour == 0 {
+            "FILTER CONTOUR STAGES"
+        } else {
+            "LOUDNESS CONTOUR STAGES"
+        },
+        rect.x + 12.0,
+        rect.y + 26.0,
+        TextParams {
+            font_size: 20,
+            color: AMBER,
+            ..Default::default()
+        },
     );
-    draw_knob_widget(
-        knob_drag,
-        KnobId::OutputPhones,
-        layout.output_knobs[1],
-        &mut panel_state.output_panel.phones_volume,
-        "PHONES",
-        Some(&phones),
+    let close = state.close_rect();
+    draw_rectangle_lines(close.x, close.y, close.w, close.h, 1.0, AMBER);
+    draw_centered_text("X", close, 20);
+
+    let modifiers = &mut panel_state.modifiers_panel;
+    let knobs = if contour == 0 {
+        &mut modifiers.filter_env_stages
+    } else {
+        &mut modifiers.loudness_env_stages
+    };
+    for (index, (knob_id, knob)) in EnvelopeEditorState::KNOBS[contour]
+        .into_iter()
+        .zip(knobs.iter_mut())
+        .enumerate()
+    {
+        let readout = match index {
+            2 if knob.value <= 0.0 => "DECAY SW".to_string(),
+            _ => knob_id.format_value(knob.value),
+        };
+        draw_knob_widget(
+            knob_drag,
+            knob_id,
+            state.knob_rect(index),
+            knob,
+            ["DELAY", "HOLD", "RELEASE"][index],
+            Some(&readout),
+        );
+    }
+    draw_text_ex(
+        "AT ZERO: NO DELAY OR HOLD, RELEASE FOLLOWS DECAY",
+        rect.x + 16.0,
+        rect.y + rect.h - 12.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
     );
 }
 
-fn draw_knob_widget(
+fn draw_follower_window(
+    state: &FollowerWindowState,
+    panel_state: &mut PanelState,
     knob_drag: &mut KnobDragState,
-    knob_id: KnobId,
-    rect: Rect,
-    knob: &mut KnobValue,
-    label: &str,
-    display: Option<&str>,
 ) {
-    handle_knob_drag(knob_drag, knob_id, rect, knob);
-    let center = vec2(rect.x + rect.w * 0.5, rect.y + rect.h * 0.5);
-    let radius = rect.w.min(rect.h) * 0.35;
-    draw_circle(
-        center.x,
-        center.y,
-        radius + 6.0,
-        Color::new(0.05, 0.03, 0.02, 1.0),
-    );
-    draw_circle(
-        center.x,
-        center.y,
-        radius,
-        Color::new(0.12, 0.12, 0.12, 1.0),
-    );
-    draw_circle(
-        center.x,
-        center.y,
-        radius * 0.65,
-        Color::new(0.2, 0.2, 0.2, 1.0),
-    );
-    draw_circle_lines(center.x, center.y, radius + 6.0, 1.0, AMBER_DIM);
-    draw_circle_lines(
-        center.x,
-        center.y,
-        radius,
-        1.0,
-        Color::new(0.4, 0.4, 0.4, 0.3),
+    let rect = state.rect;
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 0.95),
     );
-    let angle_range = 270.0f32.to_radians();
-    let start_angle = -std::f32::consts::FRAC_PI_2 - angle_range * 0.5;
-    let theta = start_angle + knob.value.clamp(0.0, 1.0) * angle_range;
-    let pointer = vec2(theta.cos(), theta.sin()) * radius * 0.8;
-    draw_line(
-        center.x,
-        center.y,
-        center.x + pointer.x,
-        center.y + pointer.y,
-        3.0,
-        AMBER,
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_text_ex(
+        "EXT INPUT FOLLOWER",
+        rect.x + 12.0,
+        rect.y + 26.0,
+        TextParams {
+            font_size: 20,
+            color: AMBER,
+            ..Default::default()
+        },
     );
-    if !knob.implemented {
-        draw_centered_text(
-            "!",
-            Rect::new(rect.x, rect.y + rect.h * 0.5 - 12.0, rect.w, 24.0),
-            30,
+    let close = state.close_rect();
+    draw_rectangle_lines(close.x, close.y, close.w, close.h, 1.0, AMBER);
+    draw_centered_text("X", close, 20);
+
+    for (index, (knob_id, knob)) in FollowerWindowState::KNOBS
+        .into_iter()
+        .zip(panel_state.mixer_panel.follower.iter_mut())
+        .enumerate()
+    {
+        let readout = knob_id.format_value(knob.value);
+        draw_knob_widget(
+            knob_drag,
+            knob_id,
+            state.knob_rect(index),
+            knob,
+            ["RELEASE", "CUTOFF", "VCA"][index],
+            Some(&readout),
         );
     }
-    if let Some(text) = display {
-        draw_centered_text(text, Rect::new(rect.x, rect.y - 12.0, rect.w, 20.0), 14);
-    }
-    draw_centered_text(
-        label,
-        Rect::new(rect.x, rect.y + rect.h + 4.0, rect.w, 18.0),
-        16,
+    draw_text_ex(
+        "FOLLOWS EXT INPUT EVEN WITH ITS LEVEL DOWN",
+        rect.x + 16.0,
+        rect.y + rect.h - 12.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
     );
 }
 
-fn handle_knob_drag(
+fn draw_chorus_window(
+    state: &ChorusWindowState,
+    panel_state: &mut PanelState,
     knob_drag: &mut KnobDragState,
-    knob_id: KnobId,
-    rect: Rect,
-    knob: &mut KnobValue,
 ) {
-    let mouse = mouse_position_vec();
-    if is_mouse_button_pressed(MouseButton::Left) && rect.contains(mouse) {
-        knob_drag.active_knob = Some(knob_id);
-        knob_drag.origin_value = knob.value;
-        knob_drag.origin_y = mouse.y;
-    }
-    if let Some(active) = knob_drag.active_knob {
-        if active == knob_id {
-            if is_mouse_button_down(MouseButton::Left) {
-                let delta = (knob_drag.origin_y - mouse.y) * 0.005;
-                knob.value = (knob_drag.origin_value + delta).clamp(0.0, 1.0);
-            } else {
-                knob_drag.active_knob = None;
-            }
-        }
-    }
-    if is_mouse_button_released(MouseButton::Left) && knob_drag.active_knob == Some(knob_id) {
-        knob_drag.active_knob = None;
-    }
-    let (_x, wheel) = mouse_wheel();
-    if rect.contains(mouse) && wheel.abs() > f32::EPSILON {
-        knob.value = (knob.value + wheel * 0.03).clamp(0.0, 1.0);
-    }
-}
-
-struct KeyVisual {
-    rect: Rect,
-    keycode: KeyCode,
-    label: &'static str,
-}
-
-struct KeyboardLayout {
-    white: Vec<KeyVisual>,
-    black: Vec<KeyVisual>,
-}
-
-impl KeyboardLayout {
-    fn hit_test(&self, point: Vec2) -> Option<KeyCode> {
-        for key in &self.black {
-            if key.rect.contains(point) {
-                return Some(key.keycode);
-            }
-        }
-        for key in &self.white {
-            if key.rect.contains(point) {
-                return Some(key.keycode);
-            }
-        }
-        None
-    }
-}
-
-fn build_keyboard_layout(controller: &KeyboardController) -> KeyboardLayout {
-    let area = Rect::new(
-        40.0,
-        PANEL_HEIGHT + 40.0,
-        SCREEN_WIDTH - 80.0,
-        SCREEN_HEIGHT - PANEL_HEIGHT - 80.0,
+    let rect = state.rect;
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 0.95),
     );
-    let spacing = 18.0;
-    let white_count = controller.white_keys().len() as f32;
-    let max_size_width = (area.w - spacing * (white_count - 1.0)) / white_count;
-    let max_size_height = (area.h - spacing * 3.0) / 2.0;
-    let key_size = max_size_width.min(max_size_height).max(40.0);
-    let total_width = white_count * key_size + (white_count - 1.0) * spacing;
-    let start_x = area.x + (area.w - total_width) * 0.5;
-    let white_y = area.y + area.h - key_size;
-    let black_y = white_y - key_size - spacing * 0.7;
-
-    let mut white = Vec::new();
-    for (index, binding) in controller.white_keys().iter().enumerate() {
-        let x = start_x + index as f32 * (key_size + spacing);
-        let rect = Rect::new(x, white_y, key_size, key_size);
-        white.push(KeyVisual {
-            rect,
-            keycode: binding.keycode,
-            label: binding.label,
-        });
-    }
-
-    let mut black = Vec::new();
-    for binding in controller.black_keys() {
-        let center = start_x + binding.position_hint * total_width;
-        let rect = Rect::new(center - key_size * 0.5, black_y, key_size, key_size);
-        if rect.x + rect.w >= area.x && rect.x <= area.x + area.w {
-            black.push(KeyVisual {
-                rect,
-                keycode: binding.keycode,
-                label: binding.label,
-            });
-        }
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_text_ex(
+        "CHORUS",
+        rect.x + 12.0,
+        rect.y + 26.0,
+        TextParams {
+            font_size: 20,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
+    let close = state.close_rect();
+    draw_rectangle_lines(close.x, close.y, close.w, close.h, 1.0, AMBER);
+    draw_centered_text("X", close, 20);
+
+    for (index, (knob_id, knob)) in ChorusWindowState::KNOBS
+        .into_iter()
+        .zip(panel_state.output_panel.chorus.iter_mut())
+        .enumerate()
+    {
+        let readout = knob_id.format_value(knob.value);
+        draw_knob_widget(
+            knob_drag,
+            knob_id,
+            state.knob_rect(index),
+            knob,
+            ["RATE", "DEPTH", "MIX"][index],
+            Some(&readout),
+        );
     }
-
-    KeyboardLayout { white, black }
+    draw_text_ex(
+        "BYPASS WITH THE SWITCH IN THE OUTPUT SECTION",
+        rect.x + 16.0,
+        rect.y + rect.h - 12.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
 }
 
-fn draw_keyboard(controller: &KeyboardController, layout: &KeyboardLayout) {
-    for key in &layout.white {
-        let active = controller.is_pressed(key.keycode);
-        draw_key(key.rect, active, false, key.label);
-    }
-    for key in &layout.black {
-        let active = controller.is_pressed(key.keycode);
-        draw_key(key.rect, active, true, key.label);
-    }
-}
+fn draw_delay_window(
+    state: &DelayWindowState,
+    panel_state: &mut PanelState,
+    knob_drag: &mut KnobDragState,
+) {
+    let rect = state.rect;
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 0.95),
+    );
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_text_ex(
+        "DELAY",
+        rect.x + 12.0,
+        rect.y + 26.0,
+        TextParams {
+            font_size: 20,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
+    let close = state.close_rect();
+    draw_rectangle_lines(close.x, close.y, close.w, close.h, 1.0, AMBER);
+    draw_centered_text("X", close, 20);
+    draw_toggle_switch(
+        state.sync_rect(),
+        panel_state.output_panel.delay_sync,
+        "SYNC",
+    );
 
-fn draw_key(rect: Rect, active: bool, filled: bool, label: &str) {
-    let fill_color = if active {
-        Color::new(0.3, 0.2, 0.07, 0.9)
-    } else if filled {
-        Color::new(0.08, 0.05, 0.03, 0.95)
-    } else {
-        Color::new(0.02, 0.02, 0.02, 0.95)
-    };
-    draw_rounded_rect(rect, 10.0, fill_color);
-    draw_rounded_rect_lines(rect, 10.0, AMBER);
-    draw_centered_text(label, rect, KEY_FONT_SIZE);
+    let division = panel_state.delay_division();
+    for (index, (knob_id, knob)) in DelayWindowState::KNOBS
+        .into_iter()
+        .zip(panel_state.output_panel.delay.iter_mut())
+        .enumerate()
+    {
+        let readout = match division {
+            Some(division) if knob_id == KnobId::DelayTime => division.label().to_string(),
+            _ => knob_id.format_value(knob.value),
+        };
+        draw_knob_widget(
+            knob_drag,
+            knob_id,
+            state.knob_rect(index),
+            knob,
+            ["TIME", "FEEDBACK", "DAMPING", "MIX"][index],
+            Some(&readout),
+        );
+    }
+    draw_text_ex(
+        "BYPASS WITH THE SWITCH IN THE OUTPUT SECTION",
+        rect.x + 16.0,
+        rect.y + rect.h - 12.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
 }
 
-fn draw_rounded_rect(rect: Rect, radius: f32, color: Color) {
+fn draw_reverb_window(
+    state: &ReverbWindowState,
+    panel_state: &mut PanelState,
+    knob_drag: &mut KnobDragState,
+) {
+    let rect = state.rect;
     draw_rectangle(
-        rect.x + radius,
+        rect.x,
         rect.y,
-        rect.w - 2.0 * radius,
+        rect.w,
         rect.h,
-        color,
+        Color::new(0.02, 0.02, 0.02, 0.95),
+    );
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_text_ex(
+        "REVERB",
+        rect.x + 12.0,
+        rect.y + 26.0,
+        TextParams {
+            font_size: 20,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
+    let close = state.close_rect();
+    draw_rectangle_lines(close.x, close.y, close.w, close.h, 1.0, AMBER);
+    draw_centered_text("X", close, 20);
+
+    for (index, (knob_id, knob)) in ReverbWindowState::KNOBS
+        .into_iter()
+        .zip(panel_state.output_panel.reverb.iter_mut())
+        .enumerate()
+    {
+        let readout = knob_id.format_value(knob.value);
+        draw_knob_widget(
+            knob_drag,
+            knob_id,
+            state.knob_rect(index),
+            knob,
+            ["SIZE", "DAMPING", "MIX"][index],
+            Some(&readout),
+        );
+    }
+    draw_text_ex(
+        "BYPASS WITH THE SWITCH IN THE OUTPUT SECTION",
+        rect.x + 16.0,
+        rect.y + rect.h - 12.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
     );
+}
+
+fn draw_options_window(
+    state: &OptionsWindowState,
+    panel_state: &mut PanelState,
+    knob_drag: &mut KnobDragState,
+) {
+    let rect = state.rect;
     draw_rectangle(
         rect.x,
-        rect.y + radius,
+        rect.y,
         rect.w,
-        rect.h - 2.0 * radius,
-        color,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 0.95),
     );
-    draw_circle(rect.x + radius, rect.y + radius, radius, color);
-    draw_circle(rect.x + rect.w - radius, rect.y + radius, radius, color);
-    draw_circle(rect.x + radius, rect.y + rect.h - radius, radius, color);
-    draw_circle(
-        rect.x + rect.w - radius,
-        rect.y + rect.h - radius,
-        radius,
-        color,
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_text_ex(
+        "OPTIONS",
+        rect.x + 12.0,
+        rect.y + 26.0,
+        TextParams {
+            font_size: 20,
+            color: AMBER,
+            ..Default::default()
+        },
     );
-}
-
-fn draw_rounded_rect_lines(rect: Rect, radius: f32, color: Color) {
-    let top = rect.y;
-    let bottom = rect.y + rect.h;
-    let left = rect.x;
-    let right = rect.x + rect.w;
-    let left_x = left + radius;
-    let right_x = right - radius;
-    let top_y = top + radius;
-    let bottom_y = bottom - radius;
-
-    draw_line(left_x, top, right_x, top, 1.0, color);
-    draw_line(left_x, bottom, right_x, bottom, 1.0, color);
-    draw_line(left, top_y, left, bottom_y, 1.0, color);
-    draw_line(right, top_y, right, bottom_y, 1.0, color);
+    let close = state.close_rect();
+    draw_rectangle_lines(close.x, close.y, close.w, close.h, 1.0, AMBER);
+    draw_centered_text("X", close, 20);
+
+    let column_width = (rect.w - 32.0) / OPTION_COLUMNS as f32;
+    for (slot, option) in PanelOption::ALL.iter().enumerate() {
+        let value_rect = state.value_rect(slot);
+        let column = slot % OPTION_COLUMNS;
+        draw_text_ex(
+            option.label(),
+            rect.x + 16.0 + column as f32 * column_width,
+            value_rect.y + value_rect.h - 6.0,
+            TextParams {
+                font_size: 14,
+                color: AMBER_DIM,
+                ..Default::default()
+            },
+        );
+        draw_button(value_rect, &panel_state.option_text(*option));
+    }
 
-    draw_corner_arc(
-        vec2(left_x, top_y),
-        std::f32::consts::PI,
-        1.5 * std::f32::consts::PI,
-        radius,
-        color,
+    let pitch_depth_label = format!("{:.2} OCT", panel_state.pitch_mod_depth());
+    draw_knob_widget(
+        knob_drag,
+        KnobId::ModPitchDepth,
+        state.knob_rect(0),
+        &mut panel_state.controllers.pitch_mod_depth,
+        "PITCH DEPTH",
+        Some(&pitch_depth_label),
     );
-    draw_corner_arc(
-        vec2(right_x, top_y),
-        1.5 * std::f32::consts::PI,
-        0.0,
-        radius,
-        color,
+    let filter_depth_label = format_percent(panel_state.filter_mod_depth());
+    draw_knob_widget(
+        knob_drag,
+        KnobId::ModFilterDepth,
+        state.knob_rect(1),
+        &mut panel_state.controllers.filter_mod_depth,
+        "FILTER DEPTH",
+        Some(&filter_depth_label),
     );
-    draw_corner_arc(
-        vec2(right_x, bottom_y),
-        0.0,
-        0.5 * std::f32::consts::PI,
-        radius,
-        color,
+    let ring_label = format!("{:.1}/10", panel_state.mixer_panel.ring_mod.value * 10.0);
+    draw_knob_widget(
+        knob_drag,
+        KnobId::MixerRing,
+        state.knob_rect(2),
+        &mut panel_state.mixer_panel.ring_mod,
+        "RING MOD",
+        Some(&ring_label),
     );
-    draw_corner_arc(
-        vec2(left_x, bottom_y),
-        0.5 * std::f32::consts::PI,
-        std::f32::consts::PI,
-        radius,
-        color,
+    let fm_label = format_percent(panel_state.oscillator.fm_depth.value);
+    draw_knob_widget(
+        knob_drag,
+        KnobId::OscFmDepth,
+        state.knob_rect(3),
+        &mut panel_state.oscillator.fm_depth,
+        "FM 3>1+2",
+        Some(&fm_label),
+    );
+    for index in 0..3 {
+        let pan_label = format_pan(panel_state.mixer_panel.pan[index].value);
+        draw_knob_widget(
+            knob_drag,
+            match index {
+                0 => KnobId::MixerPan1,
+                1 => KnobId::MixerPan2,
+                _ => KnobId::MixerPan3,
+            },
+            state.knob_rect(OPTION_KNOBS_PER_ROW + index),
+            &mut panel_state.mixer_panel.pan[index],
+            &format!("OSC {} PAN", index + 1),
+            Some(&pan_label),
+        );
+    }
+    let noise_color_label =
+        NoiseColor::describe_position(panel_state.mixer_panel.noise_color.value);
+    draw_knob_widget(
+        knob_drag,
+        KnobId::MixerNoiseColor,
+        state.knob_rect(4),
+        &mut panel_state.mixer_panel.noise_color,
+        "NOISE COLOR",
+        Some(&noise_color_label),
+    );
+    let noise_pan_label = format_pan(panel_state.mixer_panel.noise_pan.value);
+    draw_knob_widget(
+        knob_drag,
+        KnobId::MixerNoisePan,
+        state.knob_rect(OPTION_KNOBS_PER_ROW + 3),
+        &mut panel_state.mixer_panel.noise_pan,
+        "NOISE PAN",
+        Some(&noise_pan_label),
+    );
+    let fade_label = KnobId::ModFade.format_value(panel_state.controllers.mod_fade.value);
+    draw_knob_widget(
+        knob_drag,
+        KnobId::ModFade,
+        state.knob_rect(OPTION_KNOBS_PER_ROW + 4),
+        &mut panel_state.controllers.mod_fade,
+        "MOD FADE",
+        Some(&fade_label),
     );
 }
 
-fn draw_corner_arc(center: Vec2, start: f32, end: f32, radius: f32, color: Color) {
-    let tau = std::f32::consts::TAU;
-    let normalized_start = start.rem_euclid(tau);
-    let normalized_end = end.rem_euclid(tau);
-    let mut sweep = normalized_end - normalized_start;
-    if sweep <= 0.0 {
-        sweep += tau;
+fn draw_preset_button(state: &PresetBrowserState) {
+    let rect = preset_button_rect();
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.05, 0.03, 0.02, 1.0),
+    );
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_centered_text(if state.open { "CLOSE" } else { "PRESETS" }, rect, 18);
+    draw_text_ex(
+        &state.current_name,
+        rect.x,
+        rect.y - 6.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER_DIM,
+            ..Default::default()
+        },
+    );
+}
+
+fn draw_preset_browser(state: &PresetBrowserState) {
+    let rect = state.rect;
+    draw_rectangle(
+        rect.x,
+        rect.y,
+        rect.w,
+        rect.h,
+        Color::new(0.02, 0.02, 0.02, 0.95),
+    );
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
+    draw_text_ex(
+        "PRESETS",
+        rect.x + 12.0,
+        rect.y + 26.0,
+        TextParams {
+            font_size: 20,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
+    let close = state.close_rect();
+    draw_rectangle_lines(close.x, close.y, close.w, close.h, 1.0, AMBER);
+    draw_centered_text("X", close, 20);
+
+    let list = state.list_rect();
+    draw_rectangle_lines(list.x, list.y, list.w, list.h, 1.0, AMBER_DIM);
+    if state.entries.is_empty() {
+        draw_centered_text(
+            "NO PRESETS IN ./presets",
+            Rect::new(list.x, list.y, list.w, PRESET_ROW_HEIGHT),
+            16,
+        );
     }
-    let steps = 10;
-    let mut prev = center
-        + vec2(
-            normalized_start.cos() * radius,
-            normalized_start.sin() * radius,
+    for row in 0..PRESET_VISIBLE_ROWS {
+        let index = state.scroll + row;
+        let Some(path) = state.entries.get(index) else {
+            break;
+        };
+        let row_rect = state.row_rect(row);
+        let selected = state.selected == Some(index);
+        if selected {
+            draw_rectangle(
+                row_rect.x + 1.0,
+                row_rect.y + 1.0,
+                row_rect.w - 2.0,
+                row_rect.h - 2.0,
+                Color::new(0.3, 0.2, 0.07, 0.9),
+            );
+        }
+        draw_text_ex(
+            &PresetBrowserState::entry_name(path),
+            row_rect.x + 8.0,
+            row_rect.y + row_rect.h - 8.0,
+            TextParams {
+                font_size: 18,
+                color: if selected { AMBER } else { AMBER_DIM },
+                ..Default::default()
+            },
+        );
+    }
+    if state.entries.len() > PRESET_VISIBLE_ROWS {
+        draw_text_ex(
+            &format!(
+                "{}-{} OF {}",
+                state.scroll + 1,
+                (state.scroll + PRESET_VISIBLE_ROWS).min(state.entries.len()),
+                state.entries.len()
+            ),
+            list.x + list.w - 90.0,
+            list.y + list.h + 14.0,
+            TextParams {
+                font_size: 12,
+                color: AMBER_DIM,
+                ..Default::default()
+            },
         );
-    for idx in 1..=steps {
-        let angle = normalized_start + sweep * (idx as f32 / steps as f32);
-        let norm_angle = angle.rem_euclid(tau);
-        let next = center + vec2(norm_angle.cos() * radius, norm_angle.sin() * radius);
-        draw_line(prev.x, prev.y, next.x, next.y, 1.0, color);
-        prev = next;
     }
-}
-
-fn draw_centered_text(text: &str, rect: Rect, size: u16) {
-    let measure = measure_text(text, None, size, 1.0);
-    let x = rect.x + rect.w * 0.5 - measure.width * 0.5;
-    let y = rect.y + rect.h * 0.5 + measure.height * 0.5;
-    draw_text_ex(
-        text,
-        x,
-        y,
-        TextParams {
-            font_size: size,
-            color: AMBER,
-            ..Default::default()
-        },
-    );
-}
 
-fn draw_debug_button(state: &DebugWindowState) {
-    let rect = Rect::new(SCREEN_WIDTH - 170.0, PANEL_HEIGHT + 25.0, 140.0, 36.0);
-    if state.open {
+    let field = state.name_field_rect();
+    if let Some(name) = &state.naming {
+        draw_rectangle_lines(field.x, field.y, field.w, field.h, 1.0, AMBER);
+        let cursor = if (get_time() * 2.0) as i64 % 2 == 0 {
+            "_"
+        } else {
+            ""
+        };
         draw_text_ex(
-            "DEBUG OPEN",
-            rect.x,
-            rect.y - 6.0,
+            &format!("{name}{cursor}"),
+            field.x + 8.0,
+            field.y + field.h - 8.0,
             TextParams {
                 font_size: 18,
                 color: AMBER,
                 ..Default::default()
             },
         );
+        draw_text_ex(
+            "ENTER TO SAVE / ESC TO CANCEL",
+            field.x,
+            field.y - 6.0,
+            TextParams {
+                font_size: 12,
+                color: AMBER_DIM,
+                ..Default::default()
+            },
+        );
     } else {
-        draw_rectangle(
-            rect.x,
-            rect.y,
-            rect.w,
-            rect.h,
-            Color::new(0.05, 0.03, 0.02, 1.0),
+        draw_text_ex(
+            "UP/DOWN OR CLICK TO AUDITION",
+            field.x,
+            field.y + field.h - 8.0,
+            TextParams {
+                font_size: 14,
+                color: AMBER_DIM,
+                ..Default::default()
+            },
         );
-        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, AMBER);
-        draw_centered_text("DEBUGGER", rect, 18);
     }
+
+    draw_button(state.save_button_rect(), "SAVE AS");
+    let save = state.save_button_rect();
+    draw_text_ex(
+        &state.status,
+        save.x + save.w + 12.0,
+        save.y + save.h - 10.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
 }
 
-fn draw_debug_window(state: &DebugWindowState, waveform: &[f32], spectrum: &[f32]) {
+fn draw_debug_window(
+    state: &DebugWindowState,
+    waveform: &[f32],
+    analyzer: &SpectrumAnalyzer,
+    waterfall: &Waterfall,
+    filter: &FilterCurve,
+) {
     let rect = state.rect;
     draw_rectangle(
         rect.x,
@@ -2087,21 +7283,85 @@ fn draw_debug_window(state: &DebugWindowState, waveform: &[f32], spectrum: &[f32
     draw_text_ex(
         "DEBUG SCOPE",
         rect.x + 12.0,
-        rect.y + 26.0,
+        rect.y + 20.0,
         TextParams {
             font_size: 20,
             color: AMBER,
             ..Default::default()
         },
     );
-    draw_rectangle_lines(rect.x + rect.w - 32.0, rect.y + 8.0, 24.0, 24.0, 1.0, AMBER);
-    draw_centered_text(
-        "X",
-        Rect::new(rect.x + rect.w - 32.0, rect.y + 8.0, 24.0, 24.0),
-        20,
+    draw_text_ex(
+        &format!("XRUN {}", state.glitches.total),
+        rect.x + 12.0,
+        rect.y + 35.0,
+        TextParams {
+            font_size: 14,
+            color: if state.glitches.total > 0 {
+                Color::new(0.9, 0.15, 0.1, 1.0)
+            } else {
+                AMBER
+            },
+            ..Default::default()
+        },
+    );
+    if state.block_frames > 0 {
+        draw_text_ex(
+            &format!(
+                "BUF {}  LAT {:.1} ms",
+                state.block_frames,
+                state.latency * 1000.0
+            ),
+            rect.x + 150.0,
+            rect.y + 18.0,
+            TextParams {
+                font_size: 14,
+                color: AMBER,
+                ..Default::default()
+            },
+        );
+    }
+    let load_color = if state.dsp_peak >= LOAD_WARNING || state.ui_load() >= LOAD_WARNING {
+        Color::new(0.9, 0.15, 0.1, 1.0)
+    } else {
+        AMBER
+    };
+    draw_text_ex(
+        &format!(
+            "DSP {:.0}% pk {:.0}%  UI {:.0}%",
+            state.dsp_load * 100.0,
+            state.dsp_peak * 100.0,
+            state.ui_load() * 100.0
+        ),
+        rect.x + 150.0,
+        rect.y + 34.0,
+        TextParams {
+            font_size: 14,
+            color: load_color,
+            ..Default::default()
+        },
+    );
+    let export_rect = state.export_rect();
+    draw_rectangle_lines(
+        export_rect.x,
+        export_rect.y,
+        export_rect.w,
+        export_rect.h,
+        1.0,
+        AMBER,
+    );
+    draw_centered_text("EXPORT", export_rect, 16);
+    let close_rect = state.close_rect();
+    draw_rectangle_lines(
+        close_rect.x,
+        close_rect.y,
+        close_rect.w,
+        close_rect.h,
+        1.0,
+        AMBER,
     );
+    draw_centered_text("X", close_rect, 20);
 
-    let scope_rect = Rect::new(rect.x + 16.0, rect.y + 52.0, rect.w - 32.0, 110.0);
+    let scope_rect = state.scope_rect();
     draw_rectangle_lines(
         scope_rect.x,
         scope_rect.y,
@@ -2110,14 +7370,16 @@ fn draw_debug_window(state: &DebugWindowState, waveform: &[f32], spectrum: &[f32
         1.0,
         AMBER,
     );
-    draw_waveform(scope_rect, waveform);
-
-    let freq_rect = Rect::new(
-        rect.x + 16.0,
-        scope_rect.y + scope_rect.h + 24.0,
-        rect.w - 32.0,
-        rect.h - scope_rect.h - 90.0,
+    // The capture is longer than the scope so the analyzer gets several
+    // overlapping frames; the scope only shows the newest stretch.
+    draw_waveform(
+        scope_rect,
+        &waveform[waveform.len().saturating_sub(SCOPE_SAMPLES)..],
     );
+
+    draw_tuner(state);
+
+    let freq_rect = state.spectrum_rect();
     draw_rectangle_lines(
         freq_rect.x,
         freq_rect.y,
@@ -2126,7 +7388,148 @@ fn draw_debug_window(state: &DebugWindowState, waveform: &[f32], spectrum: &[f32
         1.0,
         AMBER,
     );
-    draw_frequency(freq_rect, spectrum, state.sample_rate);
+    match state.spectrum_view {
+        SpectrumView::Graph => draw_frequency(
+            freq_rect,
+            analyzer.spectrum(),
+            analyzer.peaks(),
+            state.sample_rate,
+            state.frequency_axis,
+        ),
+        SpectrumView::Waterfall => {
+            draw_texture_ex(
+                &waterfall.texture,
+                freq_rect.x,
+                freq_rect.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(freq_rect.w, freq_rect.h)),
+                    ..Default::default()
+                },
+            );
+            draw_frequency_axis(freq_rect, state.frequency_axis);
+        }
+        SpectrumView::Filter => {
+            draw_filter_response(freq_rect, filter, state.sample_rate, state.frequency_axis)
+        }
+    }
+    if state.spectrum_view != SpectrumView::Filter {
+        draw_spectrum_cursor(
+            freq_rect,
+            analyzer.spectrum(),
+            state.sample_rate,
+            state.frequency_axis,
+            state.spectrum_view == SpectrumView::Graph,
+        );
+    }
+    let scope_label = state.scope_point.label();
+    let generator_label = match state.test_signal {
+        TestSignal::Off => "GEN OFF",
+        signal => signal.label(),
+    };
+    for (button, label) in [
+        (state.axis_button_rect(), state.frequency_axis.label()),
+        (state.view_button_rect(), state.spectrum_view.label()),
+        (state.generator_button_rect(), generator_label),
+        (state.scope_button_rect(), &scope_label),
+    ] {
+        draw_rectangle(
+            button.x,
+            button.y,
+            button.w,
+            button.h,
+            Color::new(0.02, 0.02, 0.02, 0.8),
+        );
+        draw_rectangle_lines(button.x, button.y, button.w, button.h, 1.0, AMBER);
+        draw_centered_text(label, button, 14);
+    }
+    if state.glitch_rect().contains(mouse_position_vec()) {
+        draw_glitch_log(state);
+    }
+}
+
+fn draw_glitch_log(state: &DebugWindowState) {
+    let anchor = state.glitch_rect();
+    let lines = state.glitches.recent.len().max(1);
+    let panel = Rect::new(
+        anchor.x,
+        anchor.y + anchor.h + 4.0,
+        300.0,
+        10.0 + lines as f32 * 16.0,
+    );
+    draw_rectangle(
+        panel.x,
+        panel.y,
+        panel.w,
+        panel.h,
+        Color::new(0.02, 0.02, 0.02, 0.95),
+    );
+    draw_rectangle_lines(panel.x, panel.y, panel.w, panel.h, 1.0, AMBER);
+    let text_params = TextParams {
+        font_size: 14,
+        color: AMBER,
+        ..Default::default()
+    };
+    if state.glitches.recent.is_empty() {
+        draw_text_ex("no glitches", panel.x + 8.0, panel.y + 18.0, text_params);
+        return;
+    }
+    // Newest first.
+    for (line, entry) in state.glitches.recent.iter().rev().enumerate() {
+        draw_text_ex(
+            &format!(
+                "{}  {}  BUF {}  {:.0} Hz",
+                entry.time,
+                entry.glitch.label(),
+                entry.block_frames,
+                entry.sample_rate
+            ),
+            panel.x + 8.0,
+            panel.y + 18.0 + line as f32 * 16.0,
+            text_params.clone(),
+        );
+    }
+}
+
+fn draw_tuner(state: &DebugWindowState) {
+    let button = state.tuner_button_rect();
+    draw_rectangle_lines(button.x, button.y, button.w, button.h, 1.0, AMBER);
+    draw_centered_text(&state.probe_point.label(), button, 14);
+    let text = match state.tuner_reading {
+        Some(reading) => format!(
+            "{:.2} Hz  {} {:+.1} ct",
+            reading.frequency,
+            note_name(reading.note),
+            reading.cents
+        ),
+        None => "-- no pitch --".to_string(),
+    };
+    draw_text_ex(
+        &text,
+        button.x + button.w + 8.0,
+        button.y + 15.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
+
+    // Cents meter: ±50 ct across, centre tick is in tune.
+    let scope = state.scope_rect();
+    let meter = Rect::new(scope.x + scope.w - 120.0, button.y + 4.0, 120.0, 12.0);
+    draw_rectangle_lines(meter.x, meter.y, meter.w, meter.h, 1.0, AMBER_DIM);
+    let centre = meter.x + meter.w * 0.5;
+    draw_line(centre, meter.y, centre, meter.y + meter.h, 1.0, AMBER_DIM);
+    if let Some(reading) = state.tuner_reading {
+        let x = centre + (reading.cents / 50.0).clamp(-1.0, 1.0) * meter.w * 0.5;
+        let color = if reading.cents.abs() < 2.0 {
+            AMBER
+        } else {
+            AMBER_DIM
+        };
+        draw_rectangle(x - 2.0, meter.y, 4.0, meter.h, color);
+    }
 }
 
 fn draw_waveform(rect: Rect, samples: &[f32]) {
@@ -2142,31 +7545,18 @@ fn draw_waveform(rect: Rect, samples: &[f32]) {
     }
 }
 
-fn draw_frequency(rect: Rect, spectrum: &[f32], sample_rate: f32) {
+fn draw_frequency(
+    rect: Rect,
+    spectrum: &[f32],
+    peaks: &[f32],
+    sample_rate: f32,
+    axis: FrequencyAxis,
+) {
     if spectrum.is_empty() {
         return;
     }
-    let nyquist = sample_rate * 0.5;
-    let max_freq = MAX_ANALYZER_FREQ.min(nyquist);
-    let freq_ratio = max_freq / nyquist;
-    let usable_bins = ((spectrum.len() as f32) * freq_ratio).max(1.0) as usize;
-    let mut prev = None;
-    for i in 0..usable_bins {
-        let freq = nyquist * (i as f32 / spectrum.len() as f32);
-        if freq > MAX_ANALYZER_FREQ {
-            break;
-        }
-        let x = rect.x + (freq / MAX_ANALYZER_FREQ) * rect.w;
-        let magnitude = spectrum[i].max(1e-6);
-        let db = 20.0 * magnitude.log10();
-        let normalized =
-            ((db - MIN_ANALYZER_DB) / (MAX_ANALYZER_DB - MIN_ANALYZER_DB)).clamp(0.0, 1.0);
-        let y = rect.y + rect.h - normalized * rect.h;
-        if let Some((px, py)) = prev {
-            draw_line(px, py, x, y, 2.0, AMBER_DIM);
-        }
-        prev = Some((x, y));
-    }
+    draw_spectrum_trace(rect, peaks, sample_rate, axis, 1.0, AMBER);
+    draw_spectrum_trace(rect, spectrum, sample_rate, axis, 2.0, AMBER_DIM);
 
     // axis lines
     let zero = (0.0 - MIN_ANALYZER_DB) / (MAX_ANALYZER_DB - MIN_ANALYZER_DB);
@@ -2196,20 +7586,47 @@ fn draw_frequency(rect: Rect, spectrum: &[f32], sample_rate: f32) {
         );
     }
 
-    let freq_labels = [0.0, 5_000.0, 10_000.0, 15_000.0, 20_000.0, 25_000.0];
-    for freq in freq_labels {
-        let ratio = (freq / MAX_ANALYZER_FREQ).clamp(0.0, 1.0);
+    draw_frequency_axis(rect, axis);
+}
+
+fn draw_frequency_axis(rect: Rect, axis: FrequencyAxis) {
+    let grid = Color::new(0.2, 0.1, 0.03, 0.3);
+    let (freq_labels, caption): (&[f32], &str) = match axis {
+        FrequencyAxis::Linear => (
+            &[0.0, 5_000.0, 10_000.0, 15_000.0, 20_000.0, 25_000.0],
+            "FREQUENCY (kHz)",
+        ),
+        FrequencyAxis::Log => {
+            // Faint 2..9 lines inside each decade, brighter ones on the decades.
+            let mut decade = 10.0;
+            while decade < LOG_ANALYZER_MAX_FREQ {
+                for step in 2..10 {
+                    if let Some(ratio) = axis.position(decade * step as f32) {
+                        let x = rect.x + ratio * rect.w;
+                        draw_line(x, rect.y, x, rect.y + rect.h, 0.3, grid);
+                    }
+                }
+                decade *= 10.0;
+            }
+            (
+                &[20.0, 100.0, 1_000.0, 10_000.0, 20_000.0],
+                "FREQUENCY (Hz)",
+            )
+        }
+    };
+    for &freq in freq_labels {
+        let Some(ratio) = axis.position(freq) else {
+            continue;
+        };
         let x = rect.x + ratio * rect.w;
-        draw_line(
-            x,
-            rect.y,
-            x,
-            rect.y + rect.h,
-            0.3,
-            Color::new(0.2, 0.1, 0.03, 0.3),
-        );
+        draw_line(x, rect.y, x, rect.y + rect.h, 0.6, grid);
+        let label = match axis {
+            FrequencyAxis::Linear => format!("{:.0}k", freq / 1000.0),
+            FrequencyAxis::Log if freq >= 1000.0 => format!("{:.0}k", freq / 1000.0),
+            FrequencyAxis::Log => format!("{freq:.0}"),
+        };
         draw_text_ex(
-            &format!("{:.0}k", freq / 1000.0),
+            &label,
             x - 12.0,
             rect.y + rect.h + 16.0,
             TextParams {
@@ -2221,7 +7638,7 @@ fn draw_frequency(rect: Rect, spectrum: &[f32], sample_rate: f32) {
     }
 
     draw_text_ex(
-        "FREQUENCY (kHz)",
+        caption,
         rect.x + rect.w * 0.5 - 70.0,
         rect.y + rect.h + 34.0,
         TextParams {
@@ -2232,45 +7649,143 @@ fn draw_frequency(rect: Rect, spectrum: &[f32], sample_rate: f32) {
     );
 }
 
-fn value_to_waveform(osc_index: usize, value: f32) -> Waveform {
-    let waves = match osc_index {
-        0 => &OSC1_WAVES,
-        1 => &OSC2_WAVES,
-        _ => &OSC3_WAVES,
-    };
-    let mut index = (value.clamp(0.0, 0.999) * waves.len() as f32) as usize;
-    if index >= waves.len() {
-        index = waves.len() - 1;
+// Solid curve at the knob cutoff; with CONTOUR up, a dim one where the
+// filter envelope's peak pushes it.
+fn draw_filter_response(rect: Rect, filter: &FilterCurve, sample_rate: f32, axis: FrequencyAxis) {
+    let level = |db: f32| (db - RESPONSE_MIN_DB) / (RESPONSE_MAX_DB - RESPONSE_MIN_DB);
+    for db in [-36.0, -24.0, -12.0, 0.0, 12.0] {
+        let y = rect.y + rect.h - level(db) * rect.h;
+        let color = if db == 0.0 {
+            AMBER_DIM
+        } else {
+            Color::new(0.2, 0.1, 0.03, 0.4)
+        };
+        draw_line(rect.x, y, rect.x + rect.w, y, 0.5, color);
+        draw_text_ex(
+            &format!("{db:+.0} dB"),
+            rect.x - 60.0,
+            y + 4.0,
+            TextParams {
+                font_size: 14,
+                color: AMBER,
+                ..Default::default()
+            },
+        );
     }
-    waves[index]
-}
 
-fn waveform_to_value(waveform: Waveform, waves: &[Waveform]) -> f32 {
-    if let Some(index) = waves.iter().position(|w| *w == waveform) {
-        (index as f32 + 0.5) / waves.len() as f32
-    } else {
-        0.5
+    let mut curves = vec![(filter.cutoff, 2.0, AMBER)];
+    if filter.contour > 0.0 {
+        let swept = contour_cutoff(filter.cutoff, filter.contour, 1.0);
+        curves.insert(0, (swept, 1.0, AMBER_DIM));
+    }
+    let columns = rect.w.max(2.0) as usize;
+    for (cutoff, thickness, color) in curves {
+        let mut prev = None;
+        for column in 0..=columns {
+            let ratio = column as f32 / columns as f32;
+            let gain = magnitude_response(
+                filter.kind,
+                filter.slope,
+                cutoff,
+                filter.emphasis,
+                axis.frequency_at(ratio),
+                sample_rate,
+            );
+            let db = 20.0 * gain.max(1e-6).log10();
+            let point = (
+                rect.x + ratio * rect.w,
+                rect.y + rect.h - level(db).clamp(0.0, 1.0) * rect.h,
+            );
+            if let Some((px, py)) = prev {
+                draw_line(px, py, point.0, point.1, thickness, color);
+            }
+            prev = Some(point);
+        }
     }
+    draw_frequency_axis(rect, axis);
+}
+
+fn spectrum_level(magnitude: f32) -> f32 {
+    let db = 20.0 * magnitude.max(1e-6).log10();
+    ((db - MIN_ANALYZER_DB) / (MAX_ANALYZER_DB - MIN_ANALYZER_DB)).clamp(0.0, 1.0)
 }
 
-fn range_setting_from_value(value: f32) -> RangeSetting {
-    let mut index = (value.clamp(0.0, 0.999) * OSC_RANGE_SETTINGS.len() as f32) as usize;
-    if index >= OSC_RANGE_SETTINGS.len() {
-        index = OSC_RANGE_SETTINGS.len() - 1;
+fn draw_spectrum_trace(
+    rect: Rect,
+    bins: &[f32],
+    sample_rate: f32,
+    axis: FrequencyAxis,
+    thickness: f32,
+    color: Color,
+) {
+    let nyquist = sample_rate * 0.5;
+    let mut prev = None;
+    for (i, magnitude) in bins.iter().enumerate() {
+        let freq = nyquist * (i as f32 / bins.len() as f32);
+        let Some(ratio) = axis.position(freq) else {
+            if prev.is_some() {
+                break;
+            }
+            continue;
+        };
+        let x = rect.x + ratio * rect.w;
+        let y = rect.y + rect.h - spectrum_level(*magnitude) * rect.h;
+        if let Some((px, py)) = prev {
+            draw_line(px, py, x, y, thickness, color);
+        }
+        prev = Some((x, y));
     }
-    OSC_RANGE_SETTINGS[index]
 }
 
-fn range_value_from_index(index: usize) -> f32 {
-    let max_index = OSC_RANGE_SETTINGS.len().saturating_sub(1).max(1);
-    (index.min(max_index)) as f32 / max_index as f32
+// Reads out the bin under the mouse: frequency, nearest note with its cents
+// offset, and the averaged level.
+fn draw_spectrum_cursor(
+    rect: Rect,
+    spectrum: &[f32],
+    sample_rate: f32,
+    axis: FrequencyAxis,
+    marker: bool,
+) {
+    let mouse = mouse_position_vec();
+    if spectrum.is_empty() || !rect.contains(mouse) {
+        return;
+    }
+    let bin_hz = sample_rate * 0.5 / spectrum.len() as f32;
+    let bin = ((axis.frequency_at((mouse.x - rect.x) / rect.w) / bin_hz).round() as usize)
+        .min(spectrum.len() - 1);
+    let freq = bin as f32 * bin_hz;
+    let Some(ratio) = axis.position(freq) else {
+        return;
+    };
+    let x = rect.x + ratio * rect.w;
+    draw_line(x, rect.y, x, rect.y + rect.h, 1.0, AMBER);
+    if marker {
+        let y = rect.y + rect.h - spectrum_level(spectrum[bin]) * rect.h;
+        draw_circle(x, y, 3.0, AMBER);
+    }
+
+    let db = 20.0 * spectrum[bin].max(1e-6).log10();
+    let note = if freq > 0.0 {
+        let (midi, cents) = frequency_to_midi(freq);
+        format!("  {} {cents:+.0} ct", note_name(midi))
+    } else {
+        String::new()
+    };
+    draw_text_ex(
+        &format!("{freq:.1} Hz{note}  {db:.1} dB"),
+        rect.x + 6.0,
+        rect.y + 16.0,
+        TextParams {
+            font_size: 14,
+            color: AMBER,
+            ..Default::default()
+        },
+    );
 }
 
 fn format_env_time(seconds: f32) -> String {
     if seconds < 0.01 {
         format!("{:.1} ms", seconds * 1_000.0)
-    } else if seconds < 0.1 {
-        format!("{:.2} s", seconds)
     } else if seconds < 1.0 {
         format!("{:.2} s", seconds)
     } else {
@@ -2282,64 +7797,209 @@ fn format_percent(value: f32) -> String {
     format!("{:.0}%", (value * 100.0).clamp(0.0, 100.0))
 }
 
-fn sync_audio_from_panel(panel_state: &PanelState, vcos: &[VcoHandle], pipeline: &SharedPipeline) {
-    let pitch_mod = panel_state.modulation_pitch_offset();
-    for (index, (_, tx)) in vcos.iter().enumerate() {
-        let detune = panel_state.osc_detune(index);
-        let waveform = value_to_waveform(index, panel_state.oscillator.waveform[index].value);
-        let mut base_voltage = if index == 2 && !panel_state.osc3_control {
-            panel_state.osc_range_offset(index)
-        } else {
-            panel_state.pitch_current + panel_state.osc_range_offset(index)
+struct AudioLink {
+    engine: AudioEngine,
+    buffer: BufferRequest,
+    device: DeviceRequest,
+    retry_timer: f32,
+    poll_timer: f32,
+    // Why the last open failed, shown while running silent.
+    error: Option<String>,
+}
+
+impl AudioLink {
+    fn start(
+        pipeline: &SharedPipeline,
+        debug: &mut DebugData,
+        panel_state: &PanelState,
+        device: DeviceRequest,
+    ) -> (Self, CommandSender) {
+        let buffer = panel_state.buffer_request;
+        let started = AudioEngine::start(pipeline.clone(), debug, buffer, &device);
+        let mut error = None;
+        let (engine, commands) = match started {
+            Ok(opened) => opened,
+            Err(err) => {
+                error!("Audio output unavailable, running silent: {err:#}");
+                error = Some(format!("{err:#}"));
+                AudioEngine::null(pipeline.clone(), debug)
+            }
+        };
+        log_mode("Audio output", engine.device_name());
+        let link = Self {
+            engine,
+            buffer,
+            device,
+            retry_timer: AUDIO_RETRY_SEC,
+            poll_timer: 0.0,
+            error,
         };
-        base_voltage += pitch_mod;
-        let _ = tx.send(VcoCommand::SetVoltage(base_voltage));
-        let _ = tx.send(VcoCommand::SetDetune(detune));
-        let _ = tx.send(VcoCommand::SetWaveform(waveform));
+        (link, commands)
     }
-    if let Ok(mut synth) = pipeline.lock() {
-        for (index, level) in panel_state.oscillator_mix_levels().iter().enumerate() {
-            synth.set_mix_level(index, *level);
+
+    fn update(
+        &mut self,
+        dt: f32,
+        pipeline: &SharedPipeline,
+        debug: &mut DebugData,
+        panel_state: &PanelState,
+        commands: &mut CommandSender,
+    ) {
+        #[cfg(target_arch = "wasm32")]
+        self.engine.pump();
+        let mut reopen = panel_state.buffer_request != self.buffer;
+        if self.engine.is_null() {
+            self.retry_timer -= dt;
+            reopen |= self.retry_timer <= 0.0;
+        } else {
+            self.poll_timer += dt;
+            let poll_due = self.poll_timer >= AUDIO_DEVICE_POLL_SEC;
+            if poll_due {
+                self.poll_timer = 0.0;
+            }
+            if self.engine.has_failed() || (poll_due && self.engine.default_device_changed()) {
+                warn!(
+                    "Audio device {} lost or replaced",
+                    self.engine.device_name()
+                );
+                reopen = true;
+            }
         }
-        for (index, enabled) in panel_state.mixer_panel.osc_enabled.iter().enumerate() {
-            synth.set_osc_enabled(index, *enabled);
+        if !reopen {
+            return;
+        }
+        self.buffer = panel_state.buffer_request;
+        self.retry_timer = AUDIO_RETRY_SEC;
+        // Release the old device before opening its replacement; the null sink
+        // keeps the pipeline moving if nothing can be opened.
+        (self.engine, *commands) = AudioEngine::null(pipeline.clone(), debug);
+        match AudioEngine::start(pipeline.clone(), debug, self.buffer, &self.device) {
+            Ok((engine, sender)) => {
+                log_mode("Audio output", engine.device_name());
+                self.engine = engine;
+                *commands = sender;
+                self.error = None;
+            }
+            Err(err) => {
+                warn!("Audio reopen failed, retrying: {err:#}");
+                self.error = Some(format!("{err:#}"));
+                // A failed attempt may have reconnected the capture; hook
+                // it back up to a running null sink.
+                (self.engine, *commands) = AudioEngine::null(pipeline.clone(), debug);
+            }
         }
-        synth.set_noise_level(panel_state.mixer_panel.noise.value);
-        synth.set_noise_enabled(panel_state.mixer_panel.noise_enabled);
-        synth.set_noise_color(panel_state.mixer_panel.noise_color);
-        synth.set_master_level(panel_state.master_level());
-        synth.set_cutoff(panel_state.cutoff_hz());
-        synth.set_filter_emphasis(panel_state.modifiers_panel.filter[1].value);
-        synth.set_filter_contour(panel_state.modifiers_panel.filter[2].value);
-        synth.set_filter_envelope(
-            panel_state.filter_attack_time(),
-            panel_state.filter_decay_time(),
-            panel_state.filter_sustain_level(),
-            panel_state.filter_release_time(),
-        );
-        synth.set_loudness_envelope(
-            panel_state.loud_attack_time(),
-            panel_state.loud_decay_time(),
-            panel_state.loud_sustain_level(),
-            panel_state.loud_release_time(),
-        );
     }
 }
 
-fn feed_stub_knobs(panel_state: &PanelState) {
-    stub_external_input_volume(panel_state.mixer_panel.external_input.value);
-    stub_mixer_external_toggle(panel_state.mixer_panel.ext_enabled);
-    stub_phones_volume(panel_state.output_panel.phones_volume.value);
-}
-
-fn stub_external_input_volume(_value: f32) {
-    // TODO: Mix external input audio stream.
+// The engine re-derives its own coefficients when a stream with a new rate
+// takes over; the UI's readouts and any take in progress follow here. A WAV
+// can't change rate partway, so the take is closed and a new one started.
+fn follow_sample_rate(
+    pipeline: &SharedPipeline,
+    debug_window: &mut DebugWindowState,
+    tuner: &mut Tuner,
+    recording: &mut Option<Recording>,
+    performance: &mut Option<Performance>,
+    played_note: Option<u8>,
+    commands: &mut CommandSender,
+) {
+    let rate = pipeline.sample_rate();
+    if rate == debug_window.sample_rate {
+        return;
+    }
+    log_mode("Engine sample rate", &format!("{rate} Hz"));
+    debug_window.set_sample_rate(rate);
+    commands.send_job(PipelineJob::ProbeTap(Some((
+        debug_window.probe_point,
+        tuner.reset(),
+    ))));
+    if recording.is_some() {
+        stop_recording(recording, performance, commands);
+        start_recording(recording, performance, played_note, pipeline, commands);
+    }
 }
 
-fn stub_mixer_external_toggle(_on: bool) {
-    // TODO: Implement external input enable switch.
+fn sync_audio_from_panel(panel_state: &mut PanelState, commands: &mut CommandSender) {
+    let params = panel_state.synth_params();
+    for knob in KnobId::ALL {
+        if !PANEL_DRIVEN_KNOBS.contains(&knob)
+            && let Some(command) = params.engine_command(knob)
+        {
+            commands.send_changed(command);
+        }
+    }
+    let bend = panel_state.bend_offset();
+    for index in 0..VCO_COUNT {
+        let detune = panel_state.osc_detune(index);
+        let mut base_voltage = if index == 2 {
+            panel_state.osc3_voltage()
+        } else {
+            panel_state.pitch_current + panel_state.osc_range_offset(index)
+        };
+        if !(index == 2 && panel_state.osc3_lfo) {
+            base_voltage += bend;
+        }
+        for command in [
+            VcoCommand::SetVoltage(base_voltage),
+            VcoCommand::SetDetune(detune),
+            VcoCommand::SetEngine(panel_state.osc_engines[index]),
+            VcoCommand::SetSync(panel_state.osc_sync[index]),
+        ] {
+            commands.send_changed(EngineCommand::Vco(index, command));
+        }
+    }
+    commands.send_changed(EngineCommand::Modulation(ModulationParams::new(
+        &params,
+        panel_state.modulation_routing(),
+    )));
+    for (index, level) in panel_state.oscillator_mix_levels().iter().enumerate() {
+        commands.send_changed(EngineCommand::MixLevel(index, *level));
+    }
+    for (index, enabled) in panel_state.mixer_panel.osc_enabled.iter().enumerate() {
+        commands.send_changed(EngineCommand::OscEnabled(index, *enabled));
+    }
+    let mixer = &panel_state.mixer_panel;
+    commands.send_changed(EngineCommand::PitchCv(
+        panel_state.pitch_current + panel_state.bend_offset(),
+    ));
+    commands.send_changed(EngineCommand::ExternalEnabled(mixer.ext_enabled));
+    commands.send_changed(EngineCommand::FeedbackPatch(panel_state.feedback_patch));
+    commands.send_changed(EngineCommand::NoiseEnabled(mixer.noise_enabled));
+    commands.send_changed(EngineCommand::PhaseMode(panel_state.phase_mode));
+    commands.send_changed(EngineCommand::Oversampling(panel_state.oversampling));
+    commands.send_changed(EngineCommand::RumbleFilter(panel_state.rumble_filter));
+    commands.send_changed(EngineCommand::Limiter(panel_state.limiter_enabled));
+    commands.send_changed(EngineCommand::ChorusEnabled(
+        panel_state.output_panel.chorus_enabled,
+    ));
+    commands.send_changed(EngineCommand::DelayEnabled(
+        panel_state.output_panel.delay_enabled,
+    ));
+    commands.send_changed(EngineCommand::DelaySync(panel_state.delay_division()));
+    commands.send_changed(EngineCommand::ReverbEnabled(
+        panel_state.output_panel.reverb_enabled,
+    ));
+    commands.send_changed(EngineCommand::RingEnabled(mixer.ring_enabled));
+    commands.send_changed(EngineCommand::Cutoff(panel_state.cutoff_hz()));
+    let filter = &panel_state.modifiers_panel;
+    commands.send_changed(EngineCommand::FilterType(filter.filter_type));
+    commands.send_changed(EngineCommand::VelocityRoute(panel_state.velocity_route));
+    commands.send_changed(EngineCommand::FilterSlope(filter.filter_slope));
+    commands.send_changed(EngineCommand::FilterEnvelope(panel_state.filter_envelope()));
+    commands.send_changed(EngineCommand::LoudnessEnvelope(panel_state.loud_envelope()));
+    commands.send_changed(EngineCommand::FilterEnvelopeCurve(filter.filter_env_curve));
+    commands.send_changed(EngineCommand::FilterEnvelopeLoop(filter.filter_env_loop));
+    commands.send_changed(EngineCommand::LoudnessEnvelopeCurve(
+        filter.loudness_env_curve,
+    ));
+    commands.send_changed(EngineCommand::Tempo(panel_state.clock.bpm()));
+    commands.send_changed(EngineCommand::ClickLevel(panel_state.click_level()));
 }
 
-fn stub_phones_volume(_value: f32) {
-    // TODO: Apply dedicated headphones gain stage.
+/// Keeps the audio-side click on the clock's beat after a tap or MIDI Start.
+fn sync_click(panel_state: &mut PanelState, commands: &mut CommandSender) {
+    if panel_state.clock.take_restart() {
+        let beat = panel_state.clock.beat().round() as u32 % BEATS_PER_BAR;
+        commands.send(EngineCommand::ClickRestart(beat));
+    }
 }
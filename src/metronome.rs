@@ -0,0 +1,82 @@
+use std::f32::consts::TAU;
+
+use crate::clock::{BEATS_PER_BAR, DEFAULT_BPM};
+
+const ACCENT_HZ: f32 = 1_760.0;
+const BEAT_HZ: f32 = 1_320.0;
+const CLICK_DECAY_SEC: f32 = 0.012;
+const CLICK_SECONDS: f32 = 0.06;
+
+/// Click track rendered in the audio callback: a short decaying sine on
+/// every beat, pitched up on the downbeat. It keeps its own beat phase so
+/// clicks land sample-accurately; the UI clock realigns it on restarts.
+pub struct Metronome {
+    bpm: f32,
+    level: f32,
+    beat_phase: f32,
+    beat_in_bar: u32,
+    click_age: f32,
+    tone_phase: f32,
+}
+
+impl Metronome {
+    pub fn new() -> Self {
+        Self {
+            bpm: DEFAULT_BPM,
+            level: 0.0,
+            beat_phase: 0.0,
+            beat_in_bar: 0,
+            click_age: CLICK_SECONDS,
+            tone_phase: 0.0,
+        }
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = bpm.max(1.0);
+    }
+
+    /// Zero mutes the click.
+    pub fn set_level(&mut self, level: f32) {
+        self.level = level.max(0.0);
+    }
+
+    /// Clicks straight away, as beat `beat_in_bar` of the bar.
+    pub fn restart(&mut self, beat_in_bar: u32) {
+        self.beat_phase = 0.0;
+        self.beat_in_bar = beat_in_bar % BEATS_PER_BAR;
+        self.click_age = 0.0;
+        self.tone_phase = 0.0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.level > 0.0
+    }
+
+    pub fn sample(&mut self, dt: f32) -> f32 {
+        self.beat_phase += dt * self.bpm / 60.0;
+        if self.beat_phase >= 1.0 {
+            self.beat_phase -= 1.0;
+            self.beat_in_bar = (self.beat_in_bar + 1) % BEATS_PER_BAR;
+            self.click_age = 0.0;
+            self.tone_phase = 0.0;
+        }
+        if self.click_age >= CLICK_SECONDS {
+            return 0.0;
+        }
+        let freq = if self.beat_in_bar == 0 {
+            ACCENT_HZ
+        } else {
+            BEAT_HZ
+        };
+        let envelope = (-self.click_age / CLICK_DECAY_SEC).exp();
+        self.tone_phase = (self.tone_phase + freq * dt).fract();
+        self.click_age += dt;
+        (self.tone_phase * TAU).sin() * envelope * self.level
+    }
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Self::new()
+    }
+}
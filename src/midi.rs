@@ -0,0 +1,357 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc;
+
+use anyhow::{Result, anyhow};
+#[cfg(not(target_arch = "wasm32"))]
+use midir::{Ignore, MidiInputConnection, MidiOutputConnection};
+
+use crate::params::KnobId;
+
+pub const CC_MOD_WHEEL: u8 = 1;
+pub const CC_SUSTAIN: u8 = 64;
+pub const CC_ALL_NOTES_OFF: u8 = 123;
+// The 14-bit pitch bend value at rest.
+const BEND_CENTER: u16 = 0x2000;
+
+// Controller numbers for the knobs sent on MIDI output: the General MIDI
+// ones where the meaning matches (effect controls 1 and 2 for delay time and
+// feedback, the effect depths 91 to 95 for the effects), the rest on the
+// undefined CCs 3, 9, 14-31, 85-90 and 102-119, the general-purpose 80 to 83
+// and sound controller 10. PHONES only sets the local monitor level, so it
+// has none.
+const KNOB_CCS: [(KnobId, u8); 62] = [
+    (KnobId::ControllersTune, 14),
+    (KnobId::ControllersGlide, 5),
+    (KnobId::ControllersModMix, 15),
+    (KnobId::ControllersModRate, 16),
+    (KnobId::ControllersModAmount, 17),
+    (KnobId::ModPitchDepth, 18),
+    (KnobId::ModFilterDepth, 19),
+    (KnobId::OscRange1, 20),
+    (KnobId::OscRange2, 21),
+    (KnobId::OscRange3, 22),
+    (KnobId::OscFreq1, 23),
+    (KnobId::OscFreq2, 24),
+    (KnobId::OscFreq3, 25),
+    (KnobId::OscWave1, 26),
+    (KnobId::OscWave2, 27),
+    (KnobId::OscWave3, 28),
+    (KnobId::OscFmDepth, 29),
+    (KnobId::MixerExternal, 30),
+    (KnobId::MixerOsc1, 31),
+    (KnobId::MixerOsc2, 102),
+    (KnobId::MixerOsc3, 103),
+    (KnobId::MixerNoise, 104),
+    (KnobId::MixerRing, 105),
+    (KnobId::MixerPan1, 106),
+    (KnobId::MixerPan2, 107),
+    (KnobId::MixerPan3, 108),
+    (KnobId::MixerNoisePan, 109),
+    (KnobId::FilterCutoff, 74),
+    (KnobId::FilterEmphasis, 71),
+    (KnobId::FilterContour, 110),
+    (KnobId::FilterAttack, 111),
+    (KnobId::FilterDecay, 112),
+    (KnobId::FilterSustain, 113),
+    (KnobId::LoudnessAttack, 73),
+    (KnobId::LoudnessDecay, 75),
+    (KnobId::LoudnessSustain, 114),
+    (KnobId::OutputVolume, 7),
+    (KnobId::Macro1, 115),
+    (KnobId::Macro2, 116),
+    (KnobId::Macro3, 117),
+    (KnobId::Macro4, 118),
+    (KnobId::MixerNoiseColor, 119),
+    (KnobId::FilterDelay, 85),
+    (KnobId::FilterHold, 86),
+    (KnobId::FilterRelease, 87),
+    (KnobId::LoudnessDelay, 88),
+    (KnobId::LoudnessHold, 89),
+    (KnobId::LoudnessRelease, 90),
+    (KnobId::ModFade, 3),
+    (KnobId::FollowerRelease, 9),
+    (KnobId::FollowerCutoff, 80),
+    (KnobId::FollowerVca, 81),
+    (KnobId::ChorusRate, 82),
+    (KnobId::ChorusDepth, 83),
+    (KnobId::ChorusMix, 93),
+    (KnobId::DelayTime, 12),
+    (KnobId::DelayFeedback, 13),
+    (KnobId::DelayDamping, 79),
+    (KnobId::DelayMix, 94),
+    (KnobId::ReverbSize, 92),
+    (KnobId::ReverbDamping, 95),
+    (KnobId::ReverbMix, 91),
+];
+
+/// A pitch bend value as -1 to 1, full down to full up.
+pub fn bend_amount(value: u16) -> f32 {
+    ((f32::from(value) - f32::from(BEND_CENTER)) / f32::from(BEND_CENTER - 1)).clamp(-1.0, 1.0)
+}
+
+/// The controller number a knob is sent on.
+pub fn knob_cc(knob: KnobId) -> Option<u8> {
+    KNOB_CCS
+        .iter()
+        .find(|(id, _)| *id == knob)
+        .map(|(_, cc)| *cc)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOn {
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        note: u8,
+    },
+    ControlChange {
+        controller: u8,
+        value: u8,
+    },
+    /// 14-bit bend, centred on 0x2000.
+    PitchBend {
+        value: u16,
+    },
+    /// Timing clock, 24 pulses per quarter note.
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+impl MidiMessage {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let status = *bytes.first()?;
+        let data1 = bytes.get(1).copied().unwrap_or(0) & 0x7F;
+        let data2 = bytes.get(2).copied().unwrap_or(0) & 0x7F;
+        match status & 0xF0 {
+            0x80 => Some(MidiMessage::NoteOff { note: data1 }),
+            0x90 if data2 == 0 => Some(MidiMessage::NoteOff { note: data1 }),
+            0x90 => Some(MidiMessage::NoteOn {
+                note: data1,
+                velocity: data2,
+            }),
+            0xB0 => Some(MidiMessage::ControlChange {
+                controller: data1,
+                value: data2,
+            }),
+            0xE0 => Some(MidiMessage::PitchBend {
+                value: u16::from(data2) << 7 | u16::from(data1),
+            }),
+            0xF0 => match status {
+                0xF8 => Some(MidiMessage::Clock),
+                0xFA => Some(MidiMessage::Start),
+                0xFB => Some(MidiMessage::Continue),
+                0xFC => Some(MidiMessage::Stop),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Encodes the message, channel messages on channel 1.
+    pub fn bytes(self) -> Vec<u8> {
+        match self {
+            MidiMessage::NoteOn { note, velocity } => vec![0x90, note & 0x7F, velocity & 0x7F],
+            MidiMessage::NoteOff { note } => vec![0x80, note & 0x7F, 0],
+            MidiMessage::ControlChange { controller, value } => {
+                vec![0xB0, controller & 0x7F, value & 0x7F]
+            }
+            MidiMessage::PitchBend { value } => {
+                vec![0xE0, (value & 0x7F) as u8, (value >> 7 & 0x7F) as u8]
+            }
+            MidiMessage::Clock => vec![0xF8],
+            MidiMessage::Start => vec![0xFA],
+            MidiMessage::Continue => vec![0xFB],
+            MidiMessage::Stop => vec![0xFC],
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct MidiInput {
+    _connection: MidiInputConnection<()>,
+    receiver: mpsc::Receiver<MidiMessage>,
+    port_name: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MidiInput {
+    /// Opens the first port whose name contains `wanted` (case-insensitive),
+    /// or the first port at all when no name is given.
+    pub fn open(wanted: Option<&str>) -> Result<Self> {
+        let mut input = midir::MidiInput::new("MiniRoog Model R")?;
+        input.ignore(Ignore::SysexAndActiveSense);
+        let ports = input.ports();
+        let port = match wanted {
+            Some(wanted) => {
+                let wanted = wanted.to_lowercase();
+                ports
+                    .iter()
+                    .find(|port| {
+                        input
+                            .port_name(port)
+                            .is_ok_and(|name| name.to_lowercase().contains(&wanted))
+                    })
+                    .ok_or_else(|| anyhow!("No MIDI input port matching {wanted:?}"))?
+            }
+            None => ports
+                .first()
+                .ok_or_else(|| anyhow!("No MIDI input ports"))?,
+        };
+        let port_name = input.port_name(port).unwrap_or_else(|_| "MIDI".into());
+        let (tx, rx) = mpsc::channel();
+        let connection = input
+            .connect(
+                port,
+                "miniroog-in",
+                move |_stamp, bytes, _| {
+                    if let Some(message) = MidiMessage::parse(bytes) {
+                        let _ = tx.send(message);
+                    }
+                },
+                (),
+            )
+            .map_err(|err| anyhow!("MIDI connect failed: {err}"))?;
+        Ok(Self {
+            _connection: connection,
+            receiver: rx,
+            port_name,
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    pub fn drain(&self) -> Vec<MidiMessage> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+/// Sends the notes played on the window and knob moves to another device.
+/// Notes are mono like the synth: a new note turns the previous one off.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct MidiOutput {
+    connection: MidiOutputConnection,
+    port_name: String,
+    note: Option<u8>,
+    // Last value sent on each controller, so only changes go out.
+    controls: [Option<u8>; 128],
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MidiOutput {
+    /// Opens the first port whose name contains `wanted` (case-insensitive).
+    pub fn open(wanted: &str) -> Result<Self> {
+        let output = midir::MidiOutput::new("MiniRoog Model R")?;
+        let lowered = wanted.to_lowercase();
+        let port = output
+            .ports()
+            .into_iter()
+            .find(|port| {
+                output
+                    .port_name(port)
+                    .is_ok_and(|name| name.to_lowercase().contains(&lowered))
+            })
+            .ok_or_else(|| anyhow!("No MIDI output port matching {wanted:?}"))?;
+        let port_name = output.port_name(&port).unwrap_or_else(|_| "MIDI".into());
+        let connection = output
+            .connect(&port, "miniroog-out")
+            .map_err(|err| anyhow!("MIDI connect failed: {err}"))?;
+        Ok(Self {
+            connection,
+            port_name,
+            note: None,
+            controls: [None; 128],
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Moves the sounding note to `note`, or stops it on `None`.
+    pub fn play(&mut self, note: Option<u8>, velocity: f32) -> Result<()> {
+        if note == self.note {
+            return Ok(());
+        }
+        if let Some(previous) = self.note.take() {
+            self.send(MidiMessage::NoteOff { note: previous })?;
+        }
+        if let Some(note) = note {
+            let velocity = (velocity.clamp(0.0, 1.0) * 127.0).round().max(1.0) as u8;
+            self.send(MidiMessage::NoteOn { note, velocity })?;
+            self.note = Some(note);
+        }
+        Ok(())
+    }
+
+    /// Sends a 0-1 `value` on `controller` if it moved since the last send.
+    pub fn control(&mut self, controller: u8, value: f32) -> Result<()> {
+        let value = (value.clamp(0.0, 1.0) * 127.0).round() as u8;
+        let slot = &mut self.controls[usize::from(controller & 0x7F)];
+        if *slot == Some(value) {
+            return Ok(());
+        }
+        *slot = Some(value);
+        self.send(MidiMessage::ControlChange { controller, value })
+    }
+
+    fn send(&mut self, message: MidiMessage) -> Result<()> {
+        self.connection
+            .send(&message.bytes())
+            .map_err(|err| anyhow!("MIDI send failed: {err}"))
+    }
+}
+
+// Leaves nothing hanging on the other device.
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for MidiOutput {
+    fn drop(&mut self) {
+        let _ = self.play(None, 0.0);
+    }
+}
+
+// midir has no browser backend; Web MIDI would need its own bindings.
+#[cfg(target_arch = "wasm32")]
+pub struct MidiInput;
+
+#[cfg(target_arch = "wasm32")]
+impl MidiInput {
+    pub fn open(_wanted: Option<&str>) -> Result<Self> {
+        Err(anyhow!("MIDI input is not supported in the browser build"))
+    }
+
+    pub fn port_name(&self) -> &str {
+        ""
+    }
+
+    pub fn drain(&self) -> Vec<MidiMessage> {
+        Vec::new()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct MidiOutput;
+
+#[cfg(target_arch = "wasm32")]
+impl MidiOutput {
+    pub fn open(_wanted: &str) -> Result<Self> {
+        Err(anyhow!("MIDI output is not supported in the browser build"))
+    }
+
+    pub fn port_name(&self) -> &str {
+        ""
+    }
+
+    pub fn play(&mut self, _note: Option<u8>, _velocity: f32) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn control(&mut self, _controller: u8, _value: f32) -> Result<()> {
+        Ok(())
+    }
+}
@@ -1,19 +1,41 @@
+use rtrb::Consumer;
+
+const DRIVE_KNEE: f32 = 0.8;
+
+pub type StereoFrame = [f32; 2];
+
+/// Captured audio on its way to EXT INPUT, one frame per output frame at
+/// the engine rate.
+pub type ExternalInput = Consumer<StereoFrame>;
+
 pub struct Mixer {
     levels: [f32; 3],
+    pans: [f32; 3],
+    noise_pan: f32,
     osc_enabled: [bool; 3],
     noise_level: f32,
     noise_enabled: bool,
-    pub master: f32,
+    external_level: f32,
+    external_enabled: bool,
+    ring_level: f32,
+    ring_enabled: bool,
+    overload: bool,
 }
 
 impl Mixer {
     pub fn new() -> Self {
         Self {
             levels: [0.33; 3],
+            pans: [0.0; 3],
+            noise_pan: 0.0,
             osc_enabled: [true; 3],
             noise_level: 0.0,
             noise_enabled: true,
-            master: 0.7,
+            external_level: 0.0,
+            external_enabled: true,
+            ring_level: 0.0,
+            ring_enabled: false,
+            overload: false,
         }
     }
 
@@ -29,6 +51,16 @@ impl Mixer {
         }
     }
 
+    pub fn set_pan(&mut self, index: usize, pan: f32) {
+        if let Some(slot) = self.pans.get_mut(index) {
+            *slot = pan.clamp(-1.0, 1.0);
+        }
+    }
+
+    pub fn set_noise_pan(&mut self, pan: f32) {
+        self.noise_pan = pan.clamp(-1.0, 1.0);
+    }
+
     pub fn set_noise_level(&mut self, value: f32) {
         self.noise_level = value.clamp(0.0, 1.0);
     }
@@ -37,24 +69,103 @@ impl Mixer {
         self.noise_enabled = enabled;
     }
 
-    pub fn mix(&self, oscillator_samples: &[f32], noise_sample: f32) -> f32 {
-        let oscillators = oscillator_samples
-            .iter()
-            .enumerate()
-            .map(|(index, sample)| {
-                if self.osc_enabled.get(index).copied().unwrap_or(false) {
-                    let level = self.levels.get(index).copied().unwrap_or(0.0);
-                    sample * level
-                } else {
-                    0.0
-                }
-            })
-            .sum::<f32>();
-        let noise = if self.noise_enabled {
-            noise_sample * self.noise_level
+    pub fn set_external_level(&mut self, value: f32) {
+        self.external_level = value.clamp(0.0, 1.0);
+    }
+
+    pub fn set_external_enabled(&mut self, enabled: bool) {
+        self.external_enabled = enabled;
+    }
+
+    pub fn set_ring_level(&mut self, value: f32) {
+        self.ring_level = value.clamp(0.0, 1.0);
+    }
+
+    pub fn set_ring_enabled(&mut self, enabled: bool) {
+        self.ring_enabled = enabled;
+    }
+
+    pub fn take_overload(&mut self) -> bool {
+        std::mem::take(&mut self.overload)
+    }
+
+    pub fn mix_block(
+        &mut self,
+        oscillators: &[f32],
+        noise: &[f32],
+        external: &[StereoFrame],
+        out: &mut [StereoFrame],
+    ) {
+        let stride = (oscillators.len() / out.len().max(1)).max(1);
+        for (((frame, samples), noise_sample), external) in out
+            .iter_mut()
+            .zip(oscillators.chunks_exact(stride))
+            .zip(noise)
+            .zip(external)
+        {
+            *frame = self.mix(samples, *noise_sample, *external);
+        }
+    }
+
+    fn mix(
+        &mut self,
+        oscillator_samples: &[f32],
+        noise_sample: f32,
+        external: StereoFrame,
+    ) -> StereoFrame {
+        let mut frame = if self.external_enabled {
+            external.map(|sample| sample * self.external_level)
+        } else {
+            [0.0; 2]
+        };
+        for (index, sample) in oscillator_samples.iter().enumerate() {
+            if self.osc_enabled.get(index).copied().unwrap_or(false) {
+                let level = self.levels.get(index).copied().unwrap_or(0.0);
+                let pan = self.pans.get(index).copied().unwrap_or(0.0);
+                add_panned(&mut frame, sample * level, pan);
+            }
+        }
+        if self.noise_enabled {
+            add_panned(&mut frame, noise_sample * self.noise_level, self.noise_pan);
+        }
+        let ring = if self.ring_enabled {
+            let osc1 = oscillator_samples.first().copied().unwrap_or(0.0);
+            let osc2 = oscillator_samples.get(1).copied().unwrap_or(0.0);
+            osc1 * osc2 * self.ring_level
         } else {
             0.0
         };
-        (oscillators + noise) * self.master
+        frame.map(|channel| {
+            let sum = channel + ring;
+            if sum.abs() > 1.0 {
+                self.overload = true;
+            }
+            drive(sum)
+        })
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Balance law: centre leaves both sides at full level, so a centred patch
+// sounds exactly like the old mono mix.
+fn add_panned(frame: &mut StereoFrame, sample: f32, pan: f32) {
+    frame[0] += sample * (1.0 - pan).min(1.0);
+    frame[1] += sample * (1.0 + pan).min(1.0);
+}
+
+// Linear below the knee, then saturates smoothly towards unity so that
+// pushing the mixer levels past the top of their travel overdrives the filter.
+fn drive(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= DRIVE_KNEE {
+        return sample;
     }
+    let headroom = 1.0 - DRIVE_KNEE;
+    let shaped = DRIVE_KNEE + headroom * ((magnitude - DRIVE_KNEE) / headroom).tanh();
+    shaped.copysign(sample)
 }
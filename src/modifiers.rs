@@ -1,10 +1,141 @@
-use std::f32::consts::PI;
+use std::sync::Arc;
 
-use rustfft::{FftPlanner, num_complex::Complex};
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use crate::{
+    filter::{Filter, FilterSlope, FilterType},
+    mixer::StereoFrame,
+};
 
 const FILTER_MIN_CUTOFF: f32 = 80.0;
 const FILTER_MAX_CUTOFF: f32 = 18_000.0;
 const FILTER_CONTOUR_DEPTH: f32 = 4.0;
+const GATE_RAMP_SECONDS: f32 = 0.0015;
+const SPECTRUM_AVERAGE_SEC: f32 = 0.2;
+const SPECTRUM_PEAK_FALL_DB_PER_SEC: f32 = 6.0;
+// Steepness of the logistic stage curve; higher is closer to a step.
+const LOGISTIC_STEEPNESS: f32 = 10.0;
+
+/// How each envelope stage travels to its target. The analog curve is the
+/// classic exponential approach; the linear and logistic curves cover their
+/// stage in a fixed time, the full-scale length of the analog curve, so a
+/// long attack arrives instead of creeping up on full level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EnvelopeCurve {
+    #[default]
+    Analog,
+    Linear,
+    Logistic,
+}
+
+impl EnvelopeCurve {
+    pub const VALUES: [EnvelopeCurve; 3] = [
+        EnvelopeCurve::Analog,
+        EnvelopeCurve::Linear,
+        EnvelopeCurve::Logistic,
+    ];
+
+    pub fn index(self) -> usize {
+        Self::VALUES
+            .iter()
+            .position(|curve| *curve == self)
+            .unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::VALUES[(self.index() + 1) % Self::VALUES.len()]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EnvelopeCurve::Analog => "EXP",
+            EnvelopeCurve::Linear => "LIN",
+            EnvelopeCurve::Logistic => "S",
+        }
+    }
+
+    // Share of the way from a stage's start to its target after `progress`
+    // of its fixed length; unused by the analog curve.
+    fn shape(self, progress: f32) -> f32 {
+        let progress = progress.clamp(0.0, 1.0);
+        match self {
+            EnvelopeCurve::Analog | EnvelopeCurve::Linear => progress,
+            EnvelopeCurve::Logistic => {
+                let low = logistic(0.0);
+                (logistic(progress) - low) / (logistic(1.0) - low)
+            }
+        }
+    }
+
+    fn unshape(self, share: f32) -> f32 {
+        let share = share.clamp(0.0, 1.0);
+        match self {
+            EnvelopeCurve::Analog | EnvelopeCurve::Linear => share,
+            EnvelopeCurve::Logistic => {
+                let low = logistic(0.0);
+                let level = (low + share * (logistic(1.0) - low)).clamp(f32::EPSILON, 1.0 - 1e-6);
+                0.5 - (1.0 / level - 1.0).ln() / LOGISTIC_STEEPNESS
+            }
+        }
+    }
+}
+
+fn logistic(x: f32) -> f32 {
+    1.0 / (1.0 + (-LOGISTIC_STEEPNESS * (x - 0.5)).exp())
+}
+
+/// Time a fixed-length curve takes over a stage with time constant `time`.
+fn fixed_stage_length(time: f32) -> f32 {
+    time.max(0.0001) * 1000f32.ln()
+}
+
+/// Where note velocity goes: the VCA level, the filter contour amount, or
+/// both.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VelocityRoute {
+    #[default]
+    Off,
+    Loudness,
+    Filter,
+    Both,
+}
+
+impl VelocityRoute {
+    pub const VALUES: [VelocityRoute; 4] = [
+        VelocityRoute::Off,
+        VelocityRoute::Loudness,
+        VelocityRoute::Filter,
+        VelocityRoute::Both,
+    ];
+
+    pub fn index(self) -> usize {
+        Self::VALUES
+            .iter()
+            .position(|route| *route == self)
+            .unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::VALUES[(self.index() + 1) % Self::VALUES.len()]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            VelocityRoute::Off => "OFF",
+            VelocityRoute::Loudness => "VCA",
+            VelocityRoute::Filter => "VCF",
+            VelocityRoute::Both => "VCA+VCF",
+        }
+    }
+
+    fn loudness(self) -> bool {
+        matches!(self, VelocityRoute::Loudness | VelocityRoute::Both)
+    }
+
+    fn filter(self) -> bool {
+        matches!(self, VelocityRoute::Filter | VelocityRoute::Both)
+    }
+}
 
 pub struct Modifiers {
     gate_open: bool,
@@ -15,7 +146,16 @@ pub struct Modifiers {
     loud_params: EnvelopeParams,
     filter_env: AdsrEnvelope,
     loud_env: AdsrEnvelope,
-    ladder: LadderFilter,
+    filter_curve: EnvelopeCurve,
+    loud_curve: EnvelopeCurve,
+    vca_gain: f32,
+    vca_hold: bool,
+    velocity: f32,
+    velocity_route: VelocityRoute,
+    follower_cutoff: f32,
+    follower_vca: f32,
+    mod_cutoff: f32,
+    filters: [Filter; 2],
 }
 
 impl Modifiers {
@@ -29,7 +169,16 @@ impl Modifiers {
             loud_params: EnvelopeParams::default(),
             filter_env: AdsrEnvelope::new(),
             loud_env: AdsrEnvelope::new(),
-            ladder: LadderFilter::new(),
+            filter_curve: EnvelopeCurve::Analog,
+            loud_curve: EnvelopeCurve::Analog,
+            vca_gain: 0.0,
+            vca_hold: false,
+            velocity: 1.0,
+            velocity_route: VelocityRoute::Off,
+            follower_cutoff: 0.0,
+            follower_vca: 0.0,
+            mod_cutoff: 0.0,
+            filters: [Filter::new(), Filter::new()],
         }
     }
 
@@ -52,78 +201,304 @@ impl Modifiers {
         self.emphasis = value.clamp(0.0, 1.0);
     }
 
+    pub fn set_filter_type(&mut self, kind: FilterType) {
+        for filter in &mut self.filters {
+            filter.set_type(kind);
+        }
+    }
+
+    pub fn set_filter_slope(&mut self, slope: FilterSlope) {
+        for filter in &mut self.filters {
+            filter.set_slope(slope);
+        }
+    }
+
     pub fn set_contour_amount(&mut self, value: f32) {
         self.contour_amount = value.clamp(0.0, 1.0);
     }
 
-    pub fn set_filter_envelope(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
-        self.filter_params = EnvelopeParams {
-            attack,
-            decay,
-            sustain: sustain.clamp(0.0, 1.0),
-            release,
-        };
+    pub fn set_filter_envelope(&mut self, params: EnvelopeParams) {
+        self.filter_params = params.clamped();
     }
 
-    pub fn set_loudness_envelope(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
-        self.loud_params = EnvelopeParams {
-            attack,
-            decay,
-            sustain: sustain.clamp(0.0, 1.0),
-            release,
-        };
+    pub fn set_loudness_envelope(&mut self, params: EnvelopeParams) {
+        self.loud_params = params.clamped();
+    }
+
+    pub fn set_filter_curve(&mut self, curve: EnvelopeCurve) {
+        if curve != self.filter_curve {
+            self.filter_curve = curve;
+            self.filter_env.restart_stage();
+        }
+    }
+
+    /// Cycles the filter envelope while the gate is held: once the decay
+    /// reaches zero the attack starts again, making it a second LFO with
+    /// its rise and fall set by the attack and decay knobs.
+    pub fn set_filter_loop(&mut self, looping: bool) {
+        self.filter_env.looping = looping;
+    }
+
+    pub fn set_loudness_curve(&mut self, curve: EnvelopeCurve) {
+        if curve != self.loud_curve {
+            self.loud_curve = curve;
+            self.loud_env.restart_stage();
+        }
+    }
+
+    /// Holds the VCA fully open regardless of the loudness envelope, so a
+    /// test signal passes through without a key held.
+    pub fn set_vca_hold(&mut self, hold: bool) {
+        self.vca_hold = hold;
+    }
+
+    /// Strength of the current note, 0–1.
+    pub fn set_velocity(&mut self, velocity: f32) {
+        self.velocity = velocity.clamp(0.0, 1.0);
+    }
+
+    pub fn set_velocity_route(&mut self, route: VelocityRoute) {
+        self.velocity_route = route;
+    }
+
+    /// Octaves the external input's envelope follower opens the cutoff by
+    /// at full level.
+    pub fn set_follower_cutoff(&mut self, octaves: f32) {
+        self.follower_cutoff = octaves.max(0.0);
+    }
+
+    /// How far the modulation bus at full swing moves the cutoff, as a
+    /// fraction of it.
+    pub fn set_mod_cutoff(&mut self, depth: f32) {
+        self.mod_cutoff = depth;
     }
 
-    pub fn process(&mut self, input: f32, dt: f32) -> f32 {
-        let filter_env = self.filter_env.advance(dt, &self.filter_params);
-        let loud_env = self.loud_env.advance(dt, &self.loud_params);
+    /// 0 leaves the VCA alone; 1 shuts it whenever the external input is
+    /// silent, gating the synth by the input.
+    pub fn set_follower_vca(&mut self, depth: f32) {
+        self.follower_vca = depth.clamp(0.0, 1.0);
+    }
 
-        let contour_scale = 1.0 + self.contour_amount * filter_env * FILTER_CONTOUR_DEPTH;
-        let dynamic_cutoff =
-            (self.cutoff_hz * contour_scale).clamp(FILTER_MIN_CUTOFF, FILTER_MAX_CUTOFF);
-        let filtered = self
-            .ladder
-            .process(input, dynamic_cutoff, self.emphasis, dt);
+    /// Filters and amplifies `frames` in place, copying the filter output
+    /// ahead of the VCA into `post_filter` for probing. `follow` carries
+    /// the envelope follower's level for each frame and `modulation` the
+    /// modulation bus.
+    pub fn process_block(
+        &mut self,
+        frames: &mut [StereoFrame],
+        follow: &[f32],
+        modulation: &[f32],
+        post_filter: &mut [StereoFrame],
+        dt: f32,
+    ) {
+        for (((frame, filtered), follow), modulation) in frames
+            .iter_mut()
+            .zip(post_filter)
+            .zip(follow)
+            .zip(modulation)
+        {
+            (*filtered, *frame) = self.process(*frame, *follow, *modulation, dt);
+        }
+    }
 
-        filtered * loud_env
+    // Returns the filter output and the VCA output.
+    fn process(
+        &mut self,
+        input: StereoFrame,
+        follow: f32,
+        modulation: f32,
+        dt: f32,
+    ) -> (StereoFrame, StereoFrame) {
+        let filter_env = self
+            .filter_env
+            .advance(dt, &self.filter_params, self.filter_curve);
+        let loud_env = self
+            .loud_env
+            .advance(dt, &self.loud_params, self.loud_curve);
+        // Slew the VCA linearly so instant attacks and releases on gate
+        // transitions take at least GATE_RAMP_SECONDS instead of clicking.
+        let max_step = dt / GATE_RAMP_SECONDS;
+        let target = if self.vca_hold {
+            1.0
+        } else if self.velocity_route.loudness() {
+            loud_env * self.velocity
+        } else {
+            loud_env
+        };
+        let target = if self.vca_hold {
+            target
+        } else {
+            target * (1.0 - self.follower_vca * (1.0 - follow))
+        };
+        self.vca_gain += (target - self.vca_gain).clamp(-max_step, max_step);
+
+        let contour_amount = if self.velocity_route.filter() {
+            self.contour_amount * self.velocity
+        } else {
+            self.contour_amount
+        };
+        let dynamic_cutoff = (contour_cutoff(self.cutoff_hz, contour_amount, filter_env)
+            * (follow * self.follower_cutoff).exp2()
+            * (1.0 + modulation * self.mod_cutoff).max(0.0))
+        .min(FILTER_MAX_CUTOFF);
+        let mut filtered = [0.0; 2];
+        for ((filter, sample), out) in self.filters.iter_mut().zip(input).zip(&mut filtered) {
+            *out = filter.process(sample, dynamic_cutoff, self.emphasis, dt);
+        }
+        (filtered, filtered.map(|sample| sample * self.vca_gain))
     }
 
     pub fn force_trigger(&mut self) {
         self.filter_env.trigger();
         self.loud_env.trigger();
     }
+
+    /// Filter then loudness envelope, as of the last processed sample.
+    pub fn envelope_states(&self) -> [EnvelopeState; 2] {
+        [self.filter_env.state(), self.loud_env.state()]
+    }
 }
 
-pub fn compute_spectrum(samples: &[f32]) -> Vec<f32> {
-    if samples.is_empty() {
-        return Vec::new();
+impl Default for Modifiers {
+    fn default() -> Self {
+        Self::new()
     }
-    let size = samples.len().next_power_of_two().max(8);
-    let mut planner = FftPlanner::<f32>::new();
-    let fft = planner.plan_fft_forward(size);
-    let mut buffer = vec![Complex::new(0.0, 0.0); size];
-    for (idx, value) in samples.iter().enumerate().take(size) {
-        buffer[idx].re = *value;
+}
+
+/// Cutoff the filter runs at for a given contour amount and filter
+/// envelope level, so displays can show where the envelope sweeps it.
+pub fn contour_cutoff(cutoff_hz: f32, contour_amount: f32, envelope: f32) -> f32 {
+    let scale = 1.0 + contour_amount.clamp(0.0, 1.0) * envelope * FILTER_CONTOUR_DEPTH;
+    (cutoff_hz.clamp(FILTER_MIN_CUTOFF, FILTER_MAX_CUTOFF) * scale)
+        .clamp(FILTER_MIN_CUTOFF, FILTER_MAX_CUTOFF)
+}
+
+/// Welch-style analyzer: Hann-windowed frames at 50% overlap across each
+/// capture, with the averaged power then smoothed over time so the display
+/// settles instead of flickering frame to frame.
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    window_sum: f32,
+    frame: Vec<Complex<f32>>,
+    capture_power: Vec<f32>,
+    power: Vec<f32>,
+    magnitudes: Vec<f32>,
+    peaks: Vec<f32>,
+    primed: bool,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(size: usize) -> Self {
+        let size = size.next_power_of_two().max(8);
+        let window: Vec<f32> = (0..size)
+            .map(|index| 0.5 - 0.5 * (std::f32::consts::TAU * index as f32 / size as f32).cos())
+            .collect();
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(size),
+            window_sum: window.iter().sum(),
+            window,
+            frame: vec![Complex::new(0.0, 0.0); size],
+            capture_power: vec![0.0; size / 2],
+            power: vec![0.0; size / 2],
+            magnitudes: vec![0.0; size / 2],
+            peaks: vec![0.0; size / 2],
+            primed: false,
+        }
+    }
+
+    /// Analyzes the latest capture and folds it into the running average.
+    /// Magnitudes are scaled by the window sum, so a full-scale sine reads
+    /// 0.5 at its bin just as the old rectangular analysis did.
+    pub fn update(&mut self, samples: &[f32], dt: f32) -> &[f32] {
+        if samples.is_empty() {
+            return &self.magnitudes;
+        }
+        let size = self.window.len();
+        let hop = size / 2;
+        let frames = if samples.len() > size {
+            (samples.len() - size) / hop + 1
+        } else {
+            1
+        };
+        // Anchor the frames at the newest sample so the tail is never skipped.
+        let first = samples.len().saturating_sub(size + (frames - 1) * hop);
+        self.capture_power.fill(0.0);
+        for frame_index in 0..frames {
+            let start = first + frame_index * hop;
+            let end = (start + size).min(samples.len());
+            for (index, slot) in self.frame.iter_mut().enumerate() {
+                let sample = samples[start..end].get(index).copied().unwrap_or(0.0);
+                *slot = Complex::new(sample * self.window[index], 0.0);
+            }
+            self.fft.process(&mut self.frame);
+            for (power, bin) in self.capture_power.iter_mut().zip(&self.frame) {
+                *power += bin.norm_sqr() / frames as f32;
+            }
+        }
+
+        let amount = if self.primed {
+            1.0 - (-dt.max(0.0) / SPECTRUM_AVERAGE_SEC).exp()
+        } else {
+            1.0
+        };
+        self.primed = true;
+        let scale = 1.0 / self.window_sum;
+        let fall = 10f32.powf(-SPECTRUM_PEAK_FALL_DB_PER_SEC * dt.max(0.0) / 20.0);
+        for (((power, magnitude), peak), fresh) in self
+            .power
+            .iter_mut()
+            .zip(self.magnitudes.iter_mut())
+            .zip(self.peaks.iter_mut())
+            .zip(&self.capture_power)
+        {
+            *power += (fresh - *power) * amount;
+            *magnitude = power.sqrt() * scale;
+            *peak = magnitude.max(*peak * fall);
+        }
+        &self.magnitudes
+    }
+
+    pub fn spectrum(&self) -> &[f32] {
+        &self.magnitudes
+    }
+
+    /// Highest recent level per bin, falling back slowly so short
+    /// harmonics stay readable after they pass.
+    pub fn peaks(&self) -> &[f32] {
+        &self.peaks
     }
-    fft.process(&mut buffer);
-    buffer[..size / 2]
-        .iter()
-        .map(|c| c.norm() / size as f32)
-        .collect()
 }
 
-#[derive(Clone, Copy)]
-struct EnvelopeParams {
-    attack: f32,
-    decay: f32,
-    sustain: f32,
-    release: f32,
+/// Stage times in seconds and the sustain level for one DAHDSR envelope.
+/// A zero delay or hold skips that stage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvelopeParams {
+    pub delay: f32,
+    pub attack: f32,
+    pub hold: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl EnvelopeParams {
+    fn clamped(self) -> Self {
+        Self {
+            delay: self.delay.max(0.0),
+            hold: self.hold.max(0.0),
+            sustain: self.sustain.clamp(0.0, 1.0),
+            ..self
+        }
+    }
 }
 
 impl Default for EnvelopeParams {
     fn default() -> Self {
         Self {
+            delay: 0.0,
             attack: 0.01,
+            hold: 0.0,
             decay: 0.2,
             sustain: 0.7,
             release: 0.2,
@@ -131,18 +506,157 @@ impl Default for EnvelopeParams {
     }
 }
 
-#[derive(Clone, Copy)]
-enum EnvStage {
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EnvStage {
+    #[default]
     Idle,
+    Delay,
     Attack,
+    Hold,
     Decay,
     Sustain,
     Release,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EnvelopeState {
+    pub stage: EnvStage,
+    pub level: f32,
+    /// Seconds spent in the current stage, which is how far through the
+    /// flat delay and hold stages the envelope is.
+    pub elapsed: f32,
+}
+
+/// The curve `AdsrEnvelope` follows for one set of stage times: each analog
+/// stage is an exponential approach that ends at the same thresholds the
+/// per-sample code uses, so the lengths here match what is heard.
+#[derive(Clone, Copy, Debug)]
+pub struct EnvelopeShape {
+    pub delay: f32,
+    pub attack: f32,
+    pub hold: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+    pub curve: EnvelopeCurve,
+}
+
+impl EnvelopeShape {
+    pub fn attack_length(&self) -> f32 {
+        fixed_stage_length(self.attack)
+    }
+
+    pub fn decay_length(&self) -> f32 {
+        let span = 1.0 - self.sustain;
+        if span <= 0.001 {
+            0.0
+        } else if self.curve != EnvelopeCurve::Analog {
+            fixed_stage_length(self.decay)
+        } else {
+            self.decay.max(0.0001) * (span / 0.001).ln()
+        }
+    }
+
+    pub fn release_length(&self) -> f32 {
+        if self.sustain <= 0.0001 {
+            0.0
+        } else if self.curve != EnvelopeCurve::Analog {
+            fixed_stage_length(self.release)
+        } else {
+            self.release.max(0.0001) * (self.sustain / 0.0001).ln()
+        }
+    }
+
+    // Start and end level of a stage along the drawn curve.
+    fn stage_span(&self, stage: EnvStage) -> (f32, f32) {
+        match stage {
+            EnvStage::Idle | EnvStage::Delay => (0.0, 0.0),
+            EnvStage::Attack => (0.0, 1.0),
+            EnvStage::Hold => (1.0, 1.0),
+            EnvStage::Decay => (1.0, self.sustain),
+            EnvStage::Sustain => (self.sustain, self.sustain),
+            EnvStage::Release => (self.sustain, 0.0),
+        }
+    }
+
+    pub fn stage_length(&self, stage: EnvStage) -> f32 {
+        match stage {
+            EnvStage::Delay => self.delay.max(0.0),
+            EnvStage::Attack => self.attack_length(),
+            EnvStage::Hold => self.hold.max(0.0),
+            EnvStage::Decay => self.decay_length(),
+            EnvStage::Release => self.release_length(),
+            EnvStage::Idle | EnvStage::Sustain => 0.0,
+        }
+    }
+
+    /// Level `elapsed` seconds into `stage`, starting from a note-on at zero
+    /// and a release from the sustain level.
+    pub fn level_at(&self, stage: EnvStage, elapsed: f32) -> f32 {
+        let elapsed = elapsed.max(0.0);
+        if self.curve != EnvelopeCurve::Analog || matches!(stage, EnvStage::Delay | EnvStage::Hold)
+        {
+            let (start, end) = self.stage_span(stage);
+            let length = self.stage_length(stage);
+            let progress = if length > 0.0 { elapsed / length } else { 1.0 };
+            return start + (end - start) * self.curve.shape(progress);
+        }
+        match stage {
+            EnvStage::Idle | EnvStage::Delay => 0.0,
+            EnvStage::Attack => 1.0 - (-elapsed / self.attack.max(0.0001)).exp(),
+            EnvStage::Hold => 1.0,
+            EnvStage::Decay => {
+                self.sustain + (1.0 - self.sustain) * (-elapsed / self.decay.max(0.0001)).exp()
+            }
+            EnvStage::Sustain => self.sustain,
+            EnvStage::Release => self.sustain * (-elapsed / self.release.max(0.0001)).exp(),
+        }
+    }
+
+    /// Inverse of `level_at`: how far into its stage an envelope reading
+    /// is, clamped to the stage's length.
+    pub fn time_in_stage(&self, state: EnvelopeState) -> f32 {
+        let level = state.level.clamp(0.0, 1.0);
+        if matches!(state.stage, EnvStage::Delay | EnvStage::Hold) {
+            return state.elapsed.clamp(0.0, self.stage_length(state.stage));
+        }
+        if self.curve != EnvelopeCurve::Analog {
+            let (start, end) = self.stage_span(state.stage);
+            if (end - start).abs() <= f32::EPSILON {
+                return 0.0;
+            }
+            let share = (level - start) / (end - start);
+            return self.curve.unshape(share) * self.stage_length(state.stage);
+        }
+        let time = match state.stage {
+            EnvStage::Idle | EnvStage::Delay | EnvStage::Hold | EnvStage::Sustain => 0.0,
+            EnvStage::Attack => -self.attack.max(0.0001) * (1.0 - level).max(0.001).ln(),
+            EnvStage::Decay => {
+                let span = (1.0 - self.sustain).max(0.001);
+                -self.decay.max(0.0001) * ((level - self.sustain).max(0.001) / span).ln()
+            }
+            EnvStage::Release => {
+                if level >= self.sustain {
+                    0.0
+                } else {
+                    self.release.max(0.0001) * (self.sustain / level.max(0.0001)).ln()
+                }
+            }
+        };
+        time.max(0.0)
+    }
+}
+
 struct AdsrEnvelope {
     value: f32,
     stage: EnvStage,
+    // Level the current stage began at and how far through it a fixed-length
+    // curve is, 0 to 1.
+    stage_start: f32,
+    progress: f32,
+    // Seconds since the current stage began.
+    elapsed: f32,
+    looping: bool,
 }
 
 impl AdsrEnvelope {
@@ -150,38 +664,83 @@ impl AdsrEnvelope {
         Self {
             value: 0.0,
             stage: EnvStage::Idle,
+            stage_start: 0.0,
+            progress: 0.0,
+            elapsed: 0.0,
+            looping: false,
         }
     }
 
+    fn enter(&mut self, stage: EnvStage) {
+        self.stage = stage;
+        self.elapsed = 0.0;
+        self.restart_stage();
+    }
+
+    // Picks the current stage up from where the level is now, so switching
+    // curves mid-note does not jump.
+    fn restart_stage(&mut self) {
+        self.stage_start = self.value;
+        self.progress = 0.0;
+    }
+
+    // A zero delay passes straight on to the attack in the next sample.
     fn trigger(&mut self) {
-        self.stage = EnvStage::Attack;
+        self.enter(EnvStage::Delay);
+    }
+
+    fn state(&self) -> EnvelopeState {
+        EnvelopeState {
+            stage: self.stage,
+            level: self.value,
+            elapsed: self.elapsed,
+        }
     }
 
     fn release(&mut self) {
         if !matches!(self.stage, EnvStage::Idle) {
-            self.stage = EnvStage::Release;
+            self.enter(EnvStage::Release);
         }
     }
 
-    fn advance(&mut self, dt: f32, params: &EnvelopeParams) -> f32 {
+    fn advance(&mut self, dt: f32, params: &EnvelopeParams, curve: EnvelopeCurve) -> f32 {
+        self.elapsed += dt;
+        // Delay holds the level where it was and hold keeps the peak; either
+        // hands over within the same sample once its time is up.
+        let wait = match self.stage {
+            EnvStage::Delay => Some((params.delay, EnvStage::Attack)),
+            EnvStage::Hold => Some((params.hold, EnvStage::Decay)),
+            _ => None,
+        };
+        if let Some((time, next)) = wait {
+            if self.elapsed < time {
+                return self.value.clamp(0.0, 1.0);
+            }
+            self.enter(next);
+        }
+        if curve != EnvelopeCurve::Analog {
+            return self.advance_fixed(dt, params, curve);
+        }
         match self.stage {
             EnvStage::Idle => {
                 self.value = 0.0;
             }
+            EnvStage::Delay | EnvStage::Hold => {}
             EnvStage::Attack => {
                 let step = dt / params.attack.max(0.0001);
                 self.value += (1.0 - self.value) * step;
                 if (1.0 - self.value).abs() < 0.001 {
                     self.value = 1.0;
-                    self.stage = EnvStage::Decay;
+                    self.enter(EnvStage::Hold);
                 }
             }
             EnvStage::Decay => {
+                let (floor, next) = self.decay_end(params);
                 let step = dt / params.decay.max(0.0001);
-                self.value += (params.sustain - self.value) * step;
-                if (self.value - params.sustain).abs() < 0.001 {
-                    self.value = params.sustain;
-                    self.stage = EnvStage::Sustain;
+                self.value += (floor - self.value) * step;
+                if (self.value - floor).abs() < 0.001 {
+                    self.value = floor;
+                    self.enter(next);
                 }
             }
             EnvStage::Sustain => {
@@ -192,36 +751,50 @@ impl AdsrEnvelope {
                 self.value += (0.0 - self.value) * step;
                 if self.value <= 0.0001 {
                     self.value = 0.0;
-                    self.stage = EnvStage::Idle;
+                    self.enter(EnvStage::Idle);
                 }
             }
         }
         self.value.clamp(0.0, 1.0)
     }
-}
 
-struct LadderFilter {
-    stage: [f32; 4],
-}
-
-impl LadderFilter {
-    fn new() -> Self {
-        Self { stage: [0.0; 4] }
+    // A looping envelope decays all the way down and starts over, skipping
+    // the delay.
+    fn decay_end(&self, params: &EnvelopeParams) -> (f32, EnvStage) {
+        if self.looping {
+            (0.0, EnvStage::Attack)
+        } else {
+            (params.sustain, EnvStage::Sustain)
+        }
     }
 
-    fn process(&mut self, input: f32, cutoff: f32, emphasis: f32, dt: f32) -> f32 {
-        let g = (2.0 * PI * cutoff * dt).clamp(0.0, 0.99);
-        let resonance = emphasis.clamp(0.0, 1.0) * 4.0;
-
-        let feedback = self.stage[3] * resonance;
-        let drive = (input - feedback).tanh();
-
-        self.stage[0] += g * (drive - self.stage[0]);
-        self.stage[1] += g * (self.stage[0].tanh() - self.stage[1]);
-        self.stage[2] += g * (self.stage[1].tanh() - self.stage[2]);
-        self.stage[3] += g * (self.stage[2].tanh() - self.stage[3]);
-
-        self.stage[3]
+    // Linear and logistic stages: a set journey from the stage's starting
+    // level to its target, finishing exactly on time.
+    fn advance_fixed(&mut self, dt: f32, params: &EnvelopeParams, curve: EnvelopeCurve) -> f32 {
+        let (target, time, next) = match self.stage {
+            EnvStage::Idle => {
+                self.value = 0.0;
+                return 0.0;
+            }
+            EnvStage::Delay | EnvStage::Hold => return self.value.clamp(0.0, 1.0),
+            EnvStage::Sustain => {
+                self.value = params.sustain;
+                return self.value;
+            }
+            EnvStage::Attack => (1.0, params.attack, EnvStage::Hold),
+            EnvStage::Decay => {
+                let (floor, next) = self.decay_end(params);
+                (floor, params.decay, next)
+            }
+            EnvStage::Release => (0.0, params.release, EnvStage::Idle),
+        };
+        self.progress += dt / fixed_stage_length(time);
+        self.value = self.stage_start + (target - self.stage_start) * curve.shape(self.progress);
+        if self.progress >= 1.0 {
+            self.value = target;
+            self.enter(next);
+        }
+        self.value.clamp(0.0, 1.0)
     }
 }
 
@@ -0,0 +1,204 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    clock::{DEFAULT_BPM, SyncDivision},
+    noise::NoiseGenerator,
+    params::{FILTER_MOD_MAX_DEPTH, KnobId, PITCH_MOD_MAX_OCT, knob_to_lfo_fade, knob_to_lfo_rate},
+    synthparams::SynthParams,
+};
+
+/// The modulation switches and performance controls that sit beside the
+/// CONTROLLERS knobs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModRouting {
+    /// OSC MODULATION: the bus bends the oscillators.
+    pub pitch: bool,
+    /// FILTER MODULATION: the bus moves the cutoff.
+    pub filter: bool,
+    /// MOD SOURCE NOISE: MOD MIX blends noise in.
+    pub noise: bool,
+    /// OSC 3 MODE LFO: OSC 3 stands in for the LFO.
+    pub osc3: bool,
+    pub key_sync: bool,
+    /// The division a clock-synced LFO repeats at.
+    pub division: Option<SyncDivision>,
+    pub wheel: f32,
+}
+
+impl Default for ModRouting {
+    fn default() -> Self {
+        Self {
+            pitch: false,
+            filter: true,
+            noise: true,
+            osc3: false,
+            key_sync: false,
+            division: None,
+            wheel: 1.0,
+        }
+    }
+}
+
+/// The modulation bus's settings, sent whole whenever one of them changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModulationParams {
+    /// Free-running LFO rate in Hz.
+    pub rate: f32,
+    pub division: Option<SyncDivision>,
+    /// Share of noise in the blend while `noise` is set.
+    pub mix: f32,
+    pub noise: bool,
+    pub osc3: bool,
+    pub key_sync: bool,
+    /// MOD AMOUNT scaled by the mod wheel.
+    pub amount: f32,
+    /// Seconds the bus takes to swell in after a new note.
+    pub fade: f32,
+    /// Octaves the oscillators bend at full swing; zero when not routed.
+    pub pitch_depth: f32,
+    /// Fraction of the cutoff it moves at full swing; zero when not routed.
+    pub cutoff_depth: f32,
+}
+
+impl ModulationParams {
+    pub fn new(params: &SynthParams, routing: ModRouting) -> Self {
+        Self {
+            rate: knob_to_lfo_rate(params.get(KnobId::ControllersModRate)),
+            division: routing.division.filter(|_| !routing.osc3),
+            mix: params.get(KnobId::ControllersModMix),
+            noise: routing.noise,
+            osc3: routing.osc3,
+            key_sync: routing.key_sync,
+            amount: params.get(KnobId::ControllersModAmount) * routing.wheel,
+            fade: knob_to_lfo_fade(params.get(KnobId::ModFade)),
+            pitch_depth: if routing.pitch {
+                params.get(KnobId::ModPitchDepth) * PITCH_MOD_MAX_OCT
+            } else {
+                0.0
+            },
+            cutoff_depth: if routing.filter {
+                params.get(KnobId::ModFilterDepth) * FILTER_MOD_MAX_DEPTH
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl Default for ModulationParams {
+    fn default() -> Self {
+        Self::new(&SynthParams::new(), ModRouting::default())
+    }
+}
+
+/// The modulation LFO and its noise blend, run per sample in the engine.
+/// With OSC 3 as the source the bus can't be known until OSC 3 has run, so
+/// `advance` returns the rest of the blend and `osc3_gain` says how much of
+/// OSC 3's sample to add to it.
+pub struct Modulation {
+    params: ModulationParams,
+    bpm: f32,
+    beats: f64,
+    phase: f32,
+    fade_elapsed: f32,
+    noise: NoiseGenerator,
+}
+
+impl Modulation {
+    pub fn new() -> Self {
+        Self {
+            params: ModulationParams::default(),
+            bpm: DEFAULT_BPM,
+            beats: 0.0,
+            phase: 0.0,
+            fade_elapsed: 0.0,
+            noise: NoiseGenerator::new(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            noise: NoiseGenerator::with_seed(seed),
+            ..Self::new()
+        }
+    }
+
+    pub fn set_params(&mut self, params: ModulationParams) {
+        self.params = params;
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = bpm.max(1.0);
+    }
+
+    /// Lines a clock-synced LFO up with the tempo clock after it moved.
+    pub fn restart_beat(&mut self, beat_in_bar: u32) {
+        self.beats = f64::from(beat_in_bar);
+    }
+
+    /// A new, non-legato note: the fade swells in again and a key-synced
+    /// LFO starts at the top of its cycle.
+    pub fn note_on(&mut self) {
+        self.fade_elapsed = 0.0;
+        if self.params.key_sync {
+            self.phase = 0.0;
+        }
+    }
+
+    pub fn pitch_depth(&self) -> f32 {
+        self.params.pitch_depth
+    }
+
+    pub fn cutoff_depth(&self) -> f32 {
+        self.params.cutoff_depth
+    }
+
+    /// How much of OSC 3's sample joins the bus, or `None` while the LFO is
+    /// the source.
+    pub fn osc3_gain(&self) -> Option<f32> {
+        let share = if self.params.noise {
+            1.0 - self.params.mix
+        } else {
+            1.0
+        };
+        self.params.osc3.then(|| share * self.gain())
+    }
+
+    /// Steps the LFO by `dt` and returns the bus apart from any OSC 3 share.
+    pub fn advance(&mut self, dt: f32, noise_morph: f32) -> f32 {
+        let params = &self.params;
+        self.beats += f64::from(dt * self.bpm / 60.0);
+        self.phase = match params.division {
+            Some(division) => (self.beats / division.beats()).fract() as f32,
+            None => (self.phase + dt * params.rate).fract(),
+        };
+        self.fade_elapsed += dt;
+        let lfo = if params.osc3 {
+            0.0
+        } else {
+            (self.phase * TAU).sin()
+        };
+        let source = if params.noise {
+            lfo * (1.0 - params.mix) + self.noise.morph(noise_morph) * params.mix
+        } else {
+            lfo
+        };
+        source * self.gain()
+    }
+
+    // Ramps from silent to full over MOD FADE after each new note.
+    fn gain(&self) -> f32 {
+        let fade = if self.params.fade <= 0.0 {
+            1.0
+        } else {
+            (self.fade_elapsed / self.params.fade).min(1.0)
+        };
+        self.params.amount * fade
+    }
+}
+
+impl Default for Modulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
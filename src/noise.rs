@@ -1,5 +1,19 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
+// The COLOR sweep runs through these, evenly spaced from 0 to 1.
+const MORPH_ORDER: [NoiseColor; 5] = [
+    NoiseColor::Violet,
+    NoiseColor::Blue,
+    NoiseColor::White,
+    NoiseColor::Pink,
+    NoiseColor::Brown,
+];
+// Grey is white with this much pink blended in, so it sits on the sweep
+// between the two.
+const GREY_PINK_SHARE: f32 = 0.65;
+// How close the knob must be to a color to be read as exactly that color.
+const SNAP_DISTANCE: f32 = 0.02;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NoiseColor {
     White,
@@ -40,6 +54,48 @@ impl NoiseColor {
             NoiseColor::Grey => "GREY",
         }
     }
+
+    /// Where the color sits on the COLOR knob, violet at 0 to brown at 1.
+    pub fn morph_position(self) -> f32 {
+        let step = 1.0 / (MORPH_ORDER.len() - 1) as f32;
+        match MORPH_ORDER.iter().position(|color| *color == self) {
+            Some(index) => index as f32 * step,
+            None => NoiseColor::White.morph_position() + GREY_PINK_SHARE * step,
+        }
+    }
+
+    pub fn nearest(position: f32) -> Self {
+        Self::VALUES
+            .into_iter()
+            .min_by(|a, b| {
+                let a = (a.morph_position() - position).abs();
+                let b = (b.morph_position() - position).abs();
+                a.total_cmp(&b)
+            })
+            .unwrap_or(NoiseColor::White)
+    }
+
+    /// A color name on a snap point, otherwise the two colors the knob is
+    /// between, as in `WHITE-PINK`.
+    pub fn describe_position(position: f32) -> String {
+        let nearest = Self::nearest(position);
+        if (nearest.morph_position() - position).abs() <= SNAP_DISTANCE {
+            return nearest.label().to_string();
+        }
+        let (lower, upper) = morph_segment(position);
+        format!(
+            "{}-{}",
+            MORPH_ORDER[lower].label(),
+            MORPH_ORDER[upper].label()
+        )
+    }
+}
+
+// The two neighbouring colors around `position` on the sweep.
+fn morph_segment(position: f32) -> (usize, usize) {
+    let scaled = position.clamp(0.0, 1.0) * (MORPH_ORDER.len() - 1) as f32;
+    let lower = (scaled.floor() as usize).min(MORPH_ORDER.len() - 2);
+    (lower, lower + 1)
 }
 
 #[derive(Clone)]
@@ -67,24 +123,28 @@ impl NoiseGenerator {
     }
 
     pub fn sample(&mut self, color: NoiseColor) -> f32 {
+        self.morph(color.morph_position())
+    }
+
+    /// Crossfades between the two neighbouring spectra on the violet, blue,
+    /// white, pink, brown sweep; `position` runs from 0 (violet) to 1
+    /// (brown).
+    pub fn morph(&mut self, position: f32) -> f32 {
         let white = self.white();
         let previous_last = self.white_last;
         let previous_prev = self.white_prev;
         self.white_prev = previous_last;
         self.white_last = white;
+        // Every filter state advances each sample, so sweeping the knob
+        // never exposes a stale one.
         let pink = self.pink_sample(white);
         let brown = self.brown_sample(white);
         let blue = (white - previous_last).clamp(-1.0, 1.0);
         let violet = (white - 2.0 * previous_last + previous_prev).clamp(-1.0, 1.0);
-        let grey = (white * 0.35 + pink * 0.65).clamp(-1.0, 1.0);
-        match color {
-            NoiseColor::White => white,
-            NoiseColor::Pink => pink,
-            NoiseColor::Brown => brown,
-            NoiseColor::Blue => blue,
-            NoiseColor::Violet => violet,
-            NoiseColor::Grey => grey,
-        }
+        let spectra = [violet, blue, white, pink, brown];
+        let (lower, upper) = morph_segment(position);
+        let blend = position.clamp(0.0, 1.0) * (MORPH_ORDER.len() - 1) as f32 - lower as f32;
+        spectra[lower] + (spectra[upper] - spectra[lower]) * blend
     }
 
     fn white(&mut self) -> f32 {
@@ -98,7 +158,7 @@ impl NoiseGenerator {
     fn pink_sample(&mut self, white: f32) -> f32 {
         self.pink[0] = 0.99886 * self.pink[0] + white * 0.0555179;
         self.pink[1] = 0.99332 * self.pink[1] + white * 0.0750759;
-        self.pink[2] = 0.96900 * self.pink[2] + white * 0.1538520;
+        self.pink[2] = 0.96900 * self.pink[2] + white * 0.153852;
         self.pink[3] = 0.86650 * self.pink[3] + white * 0.3104856;
         self.pink[4] = 0.55000 * self.pink[4] + white * 0.5329522;
         self.pink[5] = -0.7616 * self.pink[5] - white * 0.0168980;
@@ -120,6 +180,12 @@ impl NoiseGenerator {
     }
 }
 
+impl Default for NoiseGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 fn random_seed() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
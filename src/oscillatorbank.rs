@@ -1,47 +1,240 @@
-use std::sync::{Arc, Mutex};
+use crate::{
+    noise::{NoiseColor, NoiseGenerator},
+    vco::{OscEngine, VcoCommand, VcoState},
+    wavetable::WavetableSet,
+};
 
-use crate::vco::VcoState;
+/// OSC 3: the audio-rate FM source, and the modulation LFO in LFO mode.
+pub const MOD_SOURCE: usize = 2;
+const FM_MAX_INDEX: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhaseMode {
+    FreeRun,
+    Reset,
+    Random,
+}
+
+impl PhaseMode {
+    pub const VALUES: [PhaseMode; 3] = [PhaseMode::FreeRun, PhaseMode::Reset, PhaseMode::Random];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PhaseMode::FreeRun => "FREE",
+            PhaseMode::Reset => "RESET",
+            PhaseMode::Random => "RANDOM",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let index = Self::VALUES
+            .iter()
+            .position(|mode| *mode == self)
+            .unwrap_or(0);
+        Self::VALUES[(index + 1) % Self::VALUES.len()]
+    }
+}
 
 pub struct OscillatorVoice {
-    state: Arc<Mutex<VcoState>>,
+    state: VcoState,
     phase: f32,
 }
 
 impl OscillatorVoice {
-    fn new(state: Arc<Mutex<VcoState>>) -> Self {
-        Self { state, phase: 0.0 }
+    fn new() -> Self {
+        Self {
+            state: VcoState::new(),
+            phase: 0.0,
+        }
     }
 
-    fn sample(&mut self, sample_rate: f32) -> f32 {
-        let (frequency, waveform) = {
-            let guard = self.state.lock().expect("lock voice");
-            (guard.frequency, guard.waveform)
-        };
+    fn sample(
+        &mut self,
+        sample_rate: f32,
+        tables: &WavetableSet,
+        master_wrap: Option<f32>,
+        ratio: f32,
+    ) -> (f32, Option<f32>) {
+        let VcoState {
+            waveform,
+            engine,
+            sync,
+            frequency: base_frequency,
+            ..
+        } = self.state;
+        let frequency = base_frequency * ratio;
         let phase_delta = frequency / sample_rate;
-        self.phase = (self.phase + phase_delta).fract();
-        waveform.sample(self.phase)
+        let advanced = self.phase + phase_delta;
+        let wrap = (advanced >= 1.0 && phase_delta > 0.0).then(|| (advanced - 1.0) / phase_delta);
+        self.phase = match master_wrap {
+            Some(since_wrap) if sync => (since_wrap * phase_delta).rem_euclid(1.0),
+            _ => advanced.rem_euclid(1.0),
+        };
+        let sample = match engine {
+            OscEngine::Classic => waveform.sample(self.phase),
+            OscEngine::Wavetable => tables.sample(waveform, frequency, self.phase),
+        };
+        (sample, wrap)
     }
 }
 
 pub struct OscillatorBank {
     voices: Vec<OscillatorVoice>,
+    tables: WavetableSet,
+    spare_tables: Vec<WavetableSet>,
+    fm_depth: f32,
+    mod_pitch: f32,
+    osc3_mod: Option<f32>,
+    master_wrap: Option<f32>,
+    phase_mode: PhaseMode,
+    phase_noise: NoiseGenerator,
 }
 
 impl OscillatorBank {
-    pub fn new(states: Vec<Arc<Mutex<VcoState>>>) -> Self {
-        let voices = states.into_iter().map(OscillatorVoice::new).collect();
-        Self { voices }
+    pub fn new(voices: usize) -> Self {
+        let voices = (0..voices).map(|_| OscillatorVoice::new()).collect();
+        Self {
+            voices,
+            tables: WavetableSet::new(44_100.0),
+            spare_tables: Vec::new(),
+            fm_depth: 0.0,
+            mod_pitch: 0.0,
+            osc3_mod: None,
+            master_wrap: None,
+            phase_mode: PhaseMode::FreeRun,
+            phase_noise: NoiseGenerator::new(),
+        }
     }
 
     pub fn len(&self) -> usize {
         self.voices.len()
     }
 
-    pub fn fill_sample(&mut self, sample_rate: f32, out: &mut [f32]) {
+    pub fn is_empty(&self) -> bool {
+        self.voices.is_empty()
+    }
+
+    /// Builds tables for each rate ahead of time, so a later
+    /// `set_sample_rate` to one of them swaps them in without allocating.
+    pub fn prepare_sample_rates(&mut self, rates: &[f32]) {
+        self.spare_tables.retain(|tables| {
+            rates
+                .iter()
+                .any(|rate| same_rate(tables.sample_rate(), *rate))
+        });
+        for rate in rates {
+            let ready = same_rate(self.tables.sample_rate(), *rate)
+                || self
+                    .spare_tables
+                    .iter()
+                    .any(|tables| same_rate(tables.sample_rate(), *rate));
+            if !ready {
+                self.spare_tables.push(WavetableSet::new(*rate));
+            }
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        if same_rate(self.tables.sample_rate(), sample_rate) {
+            return;
+        }
+        match self
+            .spare_tables
+            .iter()
+            .position(|tables| same_rate(tables.sample_rate(), sample_rate))
+        {
+            Some(index) => std::mem::swap(&mut self.tables, &mut self.spare_tables[index]),
+            None => self.tables = WavetableSet::new(sample_rate),
+        }
+    }
+
+    pub fn seed_noise(&mut self, seed: u64) {
+        self.phase_noise = NoiseGenerator::with_seed(seed);
+    }
+
+    pub fn set_fm_depth(&mut self, depth: f32) {
+        self.fm_depth = depth.clamp(0.0, 1.0);
+    }
+
+    /// Octaves the modulation bus at full swing bends the oscillators by,
+    /// and how much of OSC 3 joins the bus while it is the LFO.
+    pub fn set_modulation(&mut self, pitch_octaves: f32, osc3_gain: Option<f32>) {
+        self.mod_pitch = pitch_octaves;
+        self.osc3_mod = osc3_gain;
+    }
+
+    pub fn set_phase_mode(&mut self, mode: PhaseMode) {
+        self.phase_mode = mode;
+    }
+
+    pub fn retrigger(&mut self) {
+        match self.phase_mode {
+            PhaseMode::FreeRun => return,
+            PhaseMode::Reset => {
+                for voice in &mut self.voices {
+                    voice.phase = 0.0;
+                }
+            }
+            PhaseMode::Random => {
+                for voice in &mut self.voices {
+                    voice.phase = self.phase_noise.sample(NoiseColor::White) * 0.5 + 0.5;
+                }
+            }
+        }
+        self.master_wrap = None;
+    }
+
+    pub fn apply(&mut self, index: usize, command: VcoCommand) {
+        if let Some(voice) = self.voices.get_mut(index) {
+            voice.state.apply(command);
+        }
+    }
+
+    /// Fills one frame of `out` per step of `modulation`, which carries the
+    /// modulation bus in and comes back with OSC 3's share added.
+    pub fn fill_block(&mut self, sample_rate: f32, out: &mut [f32], modulation: &mut [f32]) {
+        let stride = self.voices.len().max(1);
+        for (frame, modulation) in out.chunks_exact_mut(stride).zip(modulation) {
+            self.fill_sample(sample_rate, frame, modulation);
+        }
+    }
+
+    fn fill_sample(&mut self, sample_rate: f32, out: &mut [f32], modulation: &mut f32) {
+        // OSC 3 runs first so it can modulate OSC 1/2 within the same step; its
+        // own sync therefore follows OSC 1's wrap from the previous step.
+        let mut fm = 0.0;
+        if let Some(voice) = self.voices.get_mut(MOD_SOURCE) {
+            // OSC 3 can't bend itself while it feeds the bus.
+            let ratio = match self.osc3_mod {
+                Some(_) => 1.0,
+                None => (*modulation * self.mod_pitch).exp2(),
+            };
+            let (sample, _) = voice.sample(sample_rate, &self.tables, self.master_wrap, ratio);
+            fm = sample * self.fm_depth * FM_MAX_INDEX;
+            if let Some(gain) = self.osc3_mod {
+                *modulation += sample * gain;
+            }
+            if let Some(slot) = out.get_mut(MOD_SOURCE) {
+                *slot = sample;
+            }
+        }
         for (index, voice) in self.voices.iter_mut().enumerate() {
+            if index == MOD_SOURCE {
+                continue;
+            }
+            let ratio = (1.0 + fm) * (*modulation * self.mod_pitch).exp2();
+            let master_wrap = if index == 0 { None } else { self.master_wrap };
+            let (sample, wrap) = voice.sample(sample_rate, &self.tables, master_wrap, ratio);
+            if index == 0 {
+                self.master_wrap = wrap;
+            }
             if let Some(slot) = out.get_mut(index) {
-                *slot = voice.sample(sample_rate);
+                *slot = sample;
             }
         }
     }
 }
+
+fn same_rate(a: f32, b: f32) -> bool {
+    (a - b).abs() <= f32::EPSILON
+}
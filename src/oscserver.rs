@@ -0,0 +1,136 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::{net::UdpSocket, sync::mpsc, thread};
+
+use anyhow::Result;
+#[cfg(target_arch = "wasm32")]
+use anyhow::anyhow;
+
+use crate::params::KnobId;
+
+pub const ADDRESS_PREFIX: &str = "/miniroog/";
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_PACKET_BYTES: usize = 65_536;
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+
+/// A knob move received over OSC, e.g. `/miniroog/filter/cutoff 0.7`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OscControl {
+    pub knob: KnobId,
+    pub value: f32,
+}
+
+/// Addresses are the knob's preset key with `/` in place of `.`.
+pub fn knob_for_address(address: &str) -> Option<KnobId> {
+    let key = address.strip_prefix(ADDRESS_PREFIX)?.replace('/', ".");
+    KnobId::from_preset_key(&key)
+}
+
+/// Decodes a packet into knob changes, flattening bundles. Bundle time tags
+/// are ignored; everything applies on arrival.
+pub fn parse_packet(bytes: &[u8], out: &mut Vec<OscControl>) {
+    if let Some(mut rest) = bytes.strip_prefix(BUNDLE_TAG) {
+        // Skip the 64-bit time tag.
+        rest = rest.get(8..).unwrap_or(&[]);
+        while rest.len() >= 4 {
+            let size = i32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]).max(0) as usize;
+            let Some(element) = rest.get(4..4 + size) else {
+                return;
+            };
+            parse_packet(element, out);
+            rest = &rest[4 + size..];
+        }
+    } else if let Some(control) = parse_message(bytes) {
+        out.push(control);
+    }
+}
+
+fn parse_message(bytes: &[u8]) -> Option<OscControl> {
+    let (address, rest) = read_string(bytes)?;
+    let knob = knob_for_address(address)?;
+    let (tags, args) = read_string(rest)?;
+    let tag = tags.strip_prefix(',')?.bytes().next()?;
+    let value = match tag {
+        b'f' => f32::from_be_bytes(args.get(..4)?.try_into().ok()?),
+        b'd' => f64::from_be_bytes(args.get(..8)?.try_into().ok()?) as f32,
+        b'i' => i32::from_be_bytes(args.get(..4)?.try_into().ok()?) as f32,
+        b'T' => 1.0,
+        b'F' => 0.0,
+        _ => return None,
+    };
+    if !value.is_finite() {
+        return None;
+    }
+    Some(OscControl {
+        knob,
+        value: value.clamp(0.0, 1.0),
+    })
+}
+
+// OSC strings are NUL-terminated and padded to a multiple of four bytes.
+fn read_string(bytes: &[u8]) -> Option<(&str, &[u8])> {
+    let end = bytes.iter().position(|byte| *byte == 0)?;
+    let text = std::str::from_utf8(&bytes[..end]).ok()?;
+    let padded = (end + 4) & !3;
+    Some((text, bytes.get(padded..).unwrap_or(&[])))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct OscServer {
+    receiver: mpsc::Receiver<OscControl>,
+    address: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl OscServer {
+    pub fn bind(port: u16) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        let address = socket.local_addr()?.to_string();
+        let (tx, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("osc-server".into())
+            .spawn(move || {
+                let mut buffer = vec![0u8; MAX_PACKET_BYTES];
+                let mut controls = Vec::new();
+                while let Ok((len, _)) = socket.recv_from(&mut buffer) {
+                    controls.clear();
+                    parse_packet(&buffer[..len], &mut controls);
+                    for control in controls.drain(..) {
+                        if tx.send(control).is_err() {
+                            return;
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            receiver: rx,
+            address,
+        })
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn drain(&self) -> Vec<OscControl> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+// Browsers cannot open UDP sockets.
+#[cfg(target_arch = "wasm32")]
+pub struct OscServer;
+
+#[cfg(target_arch = "wasm32")]
+impl OscServer {
+    pub fn bind(_port: u16) -> Result<Self> {
+        Err(anyhow!("OSC is not supported in the browser build"))
+    }
+
+    pub fn address(&self) -> &str {
+        ""
+    }
+
+    pub fn drain(&self) -> Vec<OscControl> {
+        Vec::new()
+    }
+}
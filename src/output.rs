@@ -1,53 +1,316 @@
-use std::sync::{Arc, Mutex};
-
-use anyhow::{Result, anyhow};
-use cpal::{
-    SampleFormat, Stream,
-    traits::{DeviceTrait, HostTrait, StreamTrait},
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU32, Ordering},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    thread,
+    time::{Duration, Instant},
 };
 
+use rtrb::{Consumer, Producer, RingBuffer};
+
 use crate::{
-    mixer::Mixer,
-    modifiers::Modifiers,
+    bench::{BenchStage, StageTimings},
+    clock::SyncDivision,
+    command::{CommandQueue, EngineCommand, EngineMessage, PipelineJob},
+    cvgate::CvGateOutput,
+    effects::Effects,
+    filter::{FilterSlope, FilterType},
+    follower::EnvelopeFollower,
+    metronome::Metronome,
+    mixer::{ExternalInput, Mixer, StereoFrame},
+    modifiers::{EnvelopeCurve, EnvelopeParams, EnvelopeState, Modifiers, VelocityRoute},
+    modulation::{Modulation, ModulationParams},
     noise::{NoiseColor, NoiseGenerator},
-    oscillatorbank::OscillatorBank,
+    oscillatorbank::{OscillatorBank, PhaseMode},
+    outputstage::{OutputStage, soft_clip},
+    oversample::{Decimator, Oversampling},
+    probe::{ProbePoint, ProbeTap},
+    recorder::RecordTap,
+    resampler::Resampler,
+    rtaudit,
+    testsignal::{SignalGenerator, TestSignal},
 };
 
-pub type SharedPipeline = Arc<Mutex<SynthPipeline>>;
-pub type DebugHandle = Arc<Mutex<DebugData>>;
+/// Where the pipeline waits between audio streams. The playing stream
+/// checks it out on its first block and parks it again when dropped, so the
+/// callback never shares a lock with the UI; the UI reaches it through the
+/// command queue instead.
+#[derive(Clone)]
+pub struct SharedPipeline {
+    slot: Arc<Mutex<Option<Box<SynthPipeline>>>>,
+    lend_requested: Arc<AtomicBool>,
+    sample_rate: Arc<AtomicU32>,
+    cv_gate: Option<CvGateOutput>,
+}
+
+impl SharedPipeline {
+    pub fn new(pipeline: SynthPipeline) -> Self {
+        Self {
+            sample_rate: Arc::new(AtomicU32::new(pipeline.sample_rate().to_bits())),
+            cv_gate: pipeline.cv_gate(),
+            slot: Arc::new(Mutex::new(Some(Box::new(pipeline)))),
+            lend_requested: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The rate of the stream the pipeline plays in, or is about to.
+    pub fn sample_rate(&self) -> f32 {
+        f32::from_bits(self.sample_rate.load(Ordering::Relaxed))
+    }
+
+    /// CV/gate output as set up at launch.
+    pub fn cv_gate(&self) -> Option<CvGateOutput> {
+        self.cv_gate
+    }
+
+    pub(crate) fn announce_sample_rate(&self, rate: f32) {
+        self.sample_rate.store(rate.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Runs `job` on the pipeline itself, for work too big for the command
+    /// queue (offline bounces). The stream parks the pipeline and plays
+    /// silence until `job` returns; `None` if it did not let go in time.
+    pub fn borrow<R>(&self, job: impl FnOnce(&mut SynthPipeline) -> R) -> Option<R> {
+        self.lend_requested.store(true, Ordering::Release);
+        #[cfg(not(target_arch = "wasm32"))]
+        let started = Instant::now();
+        let result = loop {
+            if let Ok(mut slot) = self.slot.lock()
+                && let Some(pipeline) = slot.as_deref_mut()
+            {
+                break Some(job(pipeline));
+            }
+            // The browser stream renders on this thread and parks after
+            // every pump, so there is nothing to wait for.
+            #[cfg(target_arch = "wasm32")]
+            break None;
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                if started.elapsed() >= PIPELINE_LEND_TIMEOUT {
+                    break None;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+        self.lend_requested.store(false, Ordering::Release);
+        result
+    }
+
+    // Never waits: while the UI has the pipeline on loan, or the previous
+    // stream has yet to park it, the caller plays silence.
+    fn checkout(&self) -> Option<Box<SynthPipeline>> {
+        if self.lend_requested.load(Ordering::Acquire) {
+            return None;
+        }
+        self.slot.try_lock().ok()?.take()
+    }
+
+    fn park(&self, pipeline: Box<SynthPipeline>) {
+        rtaudit::may_block();
+        if let Ok(mut slot) = self.slot.lock() {
+            *slot = Some(pipeline);
+        }
+    }
+
+    // The callback's way to park: if the UI happens to be checking the slot,
+    // keep the pipeline and try again next block.
+    fn try_park(&self, pipeline: Box<SynthPipeline>) -> Option<Box<SynthPipeline>> {
+        match self.slot.try_lock() {
+            Ok(mut slot) => {
+                *slot = Some(pipeline);
+                None
+            }
+            Err(_) => Some(pipeline),
+        }
+    }
+}
+
+/// Re-derives everything that depends on the rate (oscillator tables, the
+/// output stage's filters) on the calling thread before a new stream
+/// starts, so the stream's first block only has to check it. If the old
+/// stream won't let go in time, the new one does it at checkout instead.
+pub(crate) fn retune_pipeline(pipeline: &SharedPipeline, rate: u32) {
+    let _ = pipeline.borrow(|synth| synth.set_sample_rate(rate as f32));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::device::AudioEngine;
+#[cfg(target_arch = "wasm32")]
+pub use crate::webaudio::AudioEngine;
+
+const METER_CLIP_LEVEL: f32 = 0.97;
+// How long an offline bounce waits for the stream to park the pipeline.
+#[cfg(not(target_arch = "wasm32"))]
+const PIPELINE_LEND_TIMEOUT: Duration = Duration::from_secs(1);
+const BLOCK_FRAMES: usize = 64;
+const MAX_OVERSAMPLING: usize = 4;
+// Per-callback smoothing for the DSP load readout.
+const LOAD_SMOOTHING: f32 = 0.05;
+// Block reports the audio thread can queue ahead of the UI.
+const DEBUG_REPORT_CAPACITY: usize = 1024;
+// Callback scratch is sized for buffers up to this many frames up front;
+// a device asking for more makes it grow once on the audio thread.
+const PREALLOCATED_FRAMES: usize = 8192;
+// Glitches held until the UI takes them; extras are dropped.
+const MAX_PENDING_GLITCHES: usize = 64;
+// Captured frames EXT INPUT lets queue up before skipping ahead, so an input
+// clock running faster than the output cannot build up latency.
+const CAPTURE_BACKLOG_FRAMES: usize = 4096;
 
 pub struct SynthPipeline {
     bank: OscillatorBank,
     mixer: Mixer,
     modifiers: Modifiers,
     sample_rate: f32,
-    voice_buffer: Vec<f32>,
+    voice_block: Vec<f32>,
+    noise_block: Vec<f32>,
+    follow_block: Vec<f32>,
+    mod_block: Vec<f32>,
+    external_block: Vec<StereoFrame>,
+    mix_block: Vec<StereoFrame>,
+    premix_probe: Vec<StereoFrame>,
+    filter_probe: Vec<StereoFrame>,
     noise: NoiseGenerator,
-    noise_color: NoiseColor,
+    noise_morph: f32,
+    gate: bool,
+    pitch_cv: f32,
+    cv_gate: Option<CvGateOutput>,
+    frame: u64,
+    oversampling: Oversampling,
+    decimators: [Decimator; 2],
+    effects: Effects,
+    output_stage: OutputStage,
+    feedback_patch: bool,
+    last_output: StereoFrame,
+    external_input: Option<ExternalInput>,
+    follower: EnvelopeFollower,
+    record_tap: Option<RecordTap>,
+    probe_tap: Option<(ProbePoint, ProbeTap)>,
+    scope_tap: Option<(ProbePoint, ProbeTap)>,
+    test_signal: SignalGenerator,
+    metronome: Metronome,
+    modulation: Modulation,
+    profile: Option<StageTimings>,
 }
 
 impl SynthPipeline {
     pub fn new(bank: OscillatorBank, mixer: Mixer, modifiers: Modifiers) -> Self {
-        let voice_buffer = vec![0.0; bank.len()];
-        Self {
+        Self::with_noise(
+            bank,
+            mixer,
+            modifiers,
+            NoiseGenerator::new(),
+            Modulation::new(),
+        )
+    }
+
+    /// Seeds every noise source so renders of the same patch, note sequence
+    /// and sample rate are bit-identical.
+    pub fn with_seed(
+        mut bank: OscillatorBank,
+        mixer: Mixer,
+        modifiers: Modifiers,
+        seed: u64,
+    ) -> Self {
+        bank.seed_noise(seed.rotate_left(32));
+        Self::with_noise(
+            bank,
+            mixer,
+            modifiers,
+            NoiseGenerator::with_seed(seed),
+            Modulation::with_seed(seed.rotate_left(16)),
+        )
+    }
+
+    fn with_noise(
+        bank: OscillatorBank,
+        mixer: Mixer,
+        modifiers: Modifiers,
+        noise: NoiseGenerator,
+        modulation: Modulation,
+    ) -> Self {
+        let steps = BLOCK_FRAMES * MAX_OVERSAMPLING;
+        let mut pipeline = Self {
+            voice_block: vec![0.0; steps * bank.len()],
+            noise_block: vec![0.0; steps],
+            follow_block: vec![0.0; steps],
+            mod_block: vec![0.0; steps],
+            external_block: vec![[0.0; 2]; steps],
+            mix_block: vec![[0.0; 2]; steps],
+            premix_probe: vec![[0.0; 2]; steps],
+            filter_probe: vec![[0.0; 2]; steps],
             bank,
             mixer,
             modifiers,
             sample_rate: 44_100.0,
-            voice_buffer,
-            noise: NoiseGenerator::new(),
-            noise_color: NoiseColor::White,
-        }
+            noise,
+            noise_morph: NoiseColor::White.morph_position(),
+            gate: false,
+            pitch_cv: 0.0,
+            cv_gate: None,
+            frame: 0,
+            oversampling: Oversampling::Off,
+            decimators: [Decimator::new(), Decimator::new()],
+            effects: Effects::new(),
+            output_stage: OutputStage::new(44_100.0),
+            feedback_patch: false,
+            last_output: [0.0; 2],
+            external_input: None,
+            follower: EnvelopeFollower::new(),
+            record_tap: None,
+            probe_tap: None,
+            scope_tap: None,
+            test_signal: SignalGenerator::new(),
+            metronome: Metronome::new(),
+            modulation,
+            profile: None,
+        };
+        pipeline.set_sample_rate(pipeline.sample_rate);
+        pipeline
     }
 
+    /// Also builds the oscillator tables for every oversampling mode, so
+    /// switching modes from the audio thread never allocates.
     pub fn set_sample_rate(&mut self, rate: f32) {
         self.sample_rate = rate.max(1.0);
+        let rates = Oversampling::VALUES.map(|mode| self.sample_rate * mode.factor() as f32);
+        self.bank.prepare_sample_rates(&rates);
+        self.bank.set_sample_rate(self.internal_rate());
+        self.effects.set_sample_rate(self.sample_rate);
+        self.output_stage.set_sample_rate(self.sample_rate);
+    }
+
+    pub fn set_oversampling(&mut self, mode: Oversampling) {
+        if mode != self.oversampling {
+            self.oversampling = mode;
+            self.bank.set_sample_rate(self.internal_rate());
+        }
+    }
+
+    fn internal_rate(&self) -> f32 {
+        self.sample_rate * self.oversampling.factor() as f32
     }
 
     pub fn set_gate(&mut self, gate: bool) {
+        if gate && !self.gate {
+            self.bank.retrigger();
+            self.modulation.note_on();
+        }
+        self.gate = gate;
         self.modifiers.set_gate(gate);
     }
 
+    pub fn retrigger(&mut self) {
+        self.bank.retrigger();
+        self.modifiers.force_trigger();
+    }
+
+    pub fn set_phase_mode(&mut self, mode: PhaseMode) {
+        self.bank.set_phase_mode(mode);
+    }
+
     pub fn set_mix_level(&mut self, index: usize, level: f32) {
         self.mixer.set_level(index, level);
     }
@@ -56,6 +319,14 @@ impl SynthPipeline {
         self.mixer.set_osc_enabled(index, enabled);
     }
 
+    pub fn set_pan(&mut self, index: usize, pan: f32) {
+        self.mixer.set_pan(index, pan);
+    }
+
+    pub fn set_noise_pan(&mut self, pan: f32) {
+        self.mixer.set_noise_pan(pan);
+    }
+
     pub fn set_noise_level(&mut self, level: f32) {
         self.mixer.set_noise_level(level);
     }
@@ -64,12 +335,167 @@ impl SynthPipeline {
         self.mixer.set_noise_enabled(enabled);
     }
 
+    pub fn set_fm_depth(&mut self, depth: f32) {
+        self.bank.set_fm_depth(depth);
+    }
+
+    /// The modulation LFO, its noise blend and where they are routed; the
+    /// bus runs here every sample rather than at the UI frame rate.
+    pub fn set_modulation(&mut self, params: ModulationParams) {
+        self.modulation.set_params(params);
+        self.modifiers.set_mod_cutoff(params.cutoff_depth);
+    }
+
+    pub fn set_external_level(&mut self, level: f32) {
+        self.mixer.set_external_level(level);
+    }
+
+    pub fn set_external_enabled(&mut self, enabled: bool) {
+        self.mixer.set_external_enabled(enabled);
+    }
+
+    pub fn set_feedback_patch(&mut self, patched: bool) {
+        self.feedback_patch = patched;
+    }
+
+    pub fn set_follower_release(&mut self, seconds: f32) {
+        self.follower.set_release(seconds);
+    }
+
+    pub fn set_follower_cutoff(&mut self, octaves: f32) {
+        self.modifiers.set_follower_cutoff(octaves);
+    }
+
+    pub fn set_follower_vca(&mut self, depth: f32) {
+        self.modifiers.set_follower_vca(depth);
+    }
+
+    pub fn set_record_tap(&mut self, tap: Option<RecordTap>) {
+        self.record_tap = tap;
+    }
+
+    /// Feeds the tuner.
+    pub fn set_probe_tap(&mut self, tap: Option<(ProbePoint, ProbeTap)>) {
+        self.probe_tap = tap;
+    }
+
+    /// Feeds the debug scope when it looks somewhere other than the output.
+    pub fn set_scope_tap(&mut self, tap: Option<(ProbePoint, ProbeTap)>) {
+        self.scope_tap = tap;
+    }
+
+    /// Replaces the oscillator mix with `signal` and holds the VCA open
+    /// while it runs; `TestSignal::Off` restores normal playing.
+    pub fn set_test_signal(&mut self, signal: TestSignal) {
+        self.test_signal.set_signal(signal);
+        self.modifiers.set_vca_hold(self.test_signal.is_active());
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.metronome.set_tempo(bpm);
+        self.modulation.set_tempo(bpm);
+        self.effects.delay.set_tempo(bpm);
+    }
+
+    pub fn set_click_level(&mut self, level: f32) {
+        self.metronome.set_level(level);
+    }
+
+    pub fn restart_click(&mut self, beat_in_bar: u32) {
+        self.metronome.restart(beat_in_bar);
+        self.modulation.restart_beat(beat_in_bar);
+    }
+
+    pub fn set_ring_level(&mut self, level: f32) {
+        self.mixer.set_ring_level(level);
+    }
+
+    pub fn set_ring_enabled(&mut self, enabled: bool) {
+        self.mixer.set_ring_enabled(enabled);
+    }
+
     pub fn set_noise_color(&mut self, color: NoiseColor) {
-        self.noise_color = color;
+        self.set_noise_morph(color.morph_position());
+    }
+
+    pub fn set_noise_morph(&mut self, position: f32) {
+        self.noise_morph = position.clamp(0.0, 1.0);
     }
 
     pub fn set_master_level(&mut self, value: f32) {
-        self.mixer.master = value.clamp(0.0, 1.0);
+        self.output_stage.set_master(value);
+    }
+
+    pub fn set_limiter(&mut self, enabled: bool) {
+        self.output_stage.set_limiter_enabled(enabled);
+    }
+
+    pub fn take_gain_reduction(&mut self) -> f32 {
+        self.output_stage.take_gain_reduction()
+    }
+
+    pub fn take_overload(&mut self) -> bool {
+        self.mixer.take_overload()
+    }
+
+    pub fn set_rumble_filter(&mut self, enabled: bool) {
+        self.output_stage.set_rumble_enabled(enabled);
+    }
+
+    pub fn set_chorus_enabled(&mut self, enabled: bool) {
+        self.effects.chorus.set_enabled(enabled);
+    }
+
+    pub fn set_chorus_rate(&mut self, hz: f32) {
+        self.effects.chorus.set_rate(hz);
+    }
+
+    pub fn set_chorus_depth(&mut self, depth: f32) {
+        self.effects.chorus.set_depth(depth);
+    }
+
+    pub fn set_chorus_mix(&mut self, mix: f32) {
+        self.effects.chorus.set_mix(mix);
+    }
+
+    pub fn set_delay_enabled(&mut self, enabled: bool) {
+        self.effects.delay.set_enabled(enabled);
+    }
+
+    pub fn set_delay_sync(&mut self, division: Option<SyncDivision>) {
+        self.effects.delay.set_division(division);
+    }
+
+    pub fn set_delay_time(&mut self, seconds: f32) {
+        self.effects.delay.set_time(seconds);
+    }
+
+    pub fn set_delay_feedback(&mut self, feedback: f32) {
+        self.effects.delay.set_feedback(feedback);
+    }
+
+    pub fn set_delay_damping(&mut self, hz: f32) {
+        self.effects.delay.set_damping(hz);
+    }
+
+    pub fn set_delay_mix(&mut self, mix: f32) {
+        self.effects.delay.set_mix(mix);
+    }
+
+    pub fn set_reverb_enabled(&mut self, enabled: bool) {
+        self.effects.reverb.set_enabled(enabled);
+    }
+
+    pub fn set_reverb_decay(&mut self, seconds: f32) {
+        self.effects.reverb.set_decay(seconds);
+    }
+
+    pub fn set_reverb_damping(&mut self, hz: f32) {
+        self.effects.reverb.set_damping(hz);
+    }
+
+    pub fn set_reverb_mix(&mut self, mix: f32) {
+        self.effects.reverb.set_mix(mix);
     }
 
     pub fn set_cutoff(&mut self, hz: f32) {
@@ -80,18 +506,44 @@ impl SynthPipeline {
         self.modifiers.set_emphasis(value);
     }
 
+    pub fn set_filter_type(&mut self, kind: FilterType) {
+        self.modifiers.set_filter_type(kind);
+    }
+
+    pub fn set_filter_slope(&mut self, slope: FilterSlope) {
+        self.modifiers.set_filter_slope(slope);
+    }
+
+    pub fn set_filter_envelope_curve(&mut self, curve: EnvelopeCurve) {
+        self.modifiers.set_filter_curve(curve);
+    }
+
+    pub fn set_filter_envelope_loop(&mut self, looping: bool) {
+        self.modifiers.set_filter_loop(looping);
+    }
+
+    pub fn set_loudness_envelope_curve(&mut self, curve: EnvelopeCurve) {
+        self.modifiers.set_loudness_curve(curve);
+    }
+
     pub fn set_filter_contour(&mut self, value: f32) {
         self.modifiers.set_contour_amount(value);
     }
 
-    pub fn set_filter_envelope(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
-        self.modifiers
-            .set_filter_envelope(attack, decay, sustain, release);
+    pub fn set_filter_envelope(&mut self, params: EnvelopeParams) {
+        self.modifiers.set_filter_envelope(params);
+    }
+
+    pub fn set_loudness_envelope(&mut self, params: EnvelopeParams) {
+        self.modifiers.set_loudness_envelope(params);
+    }
+
+    pub fn set_velocity(&mut self, velocity: f32) {
+        self.modifiers.set_velocity(velocity);
     }
 
-    pub fn set_loudness_envelope(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
-        self.modifiers
-            .set_loudness_envelope(attack, decay, sustain, release);
+    pub fn set_velocity_route(&mut self, route: VelocityRoute) {
+        self.modifiers.set_velocity_route(route);
     }
 
     pub fn trigger_envelopes(&mut self) {
@@ -102,40 +554,559 @@ impl SynthPipeline {
         self.sample_rate
     }
 
-    pub fn next_sample(&mut self) -> f32 {
+    /// Turns pitch CV and gate output on spare device channels on or off.
+    pub fn set_cv_gate(&mut self, cv_gate: Option<CvGateOutput>) {
+        self.cv_gate = cv_gate;
+    }
+
+    pub fn cv_gate(&self) -> Option<CvGateOutput> {
+        self.cv_gate
+    }
+
+    pub fn envelope_states(&self) -> [EnvelopeState; 2] {
+        self.modifiers.envelope_states()
+    }
+
+    pub fn apply(&mut self, command: EngineCommand) {
+        match command {
+            EngineCommand::Vco(index, command) => self.bank.apply(index, command),
+            EngineCommand::Gate(gate) => self.set_gate(gate),
+            EngineCommand::PitchCv(voltage) => self.pitch_cv = voltage,
+            EngineCommand::Retrigger => self.retrigger(),
+            EngineCommand::TriggerEnvelopes => self.trigger_envelopes(),
+            EngineCommand::PhaseMode(mode) => self.set_phase_mode(mode),
+            EngineCommand::Oversampling(mode) => self.set_oversampling(mode),
+            EngineCommand::MixLevel(index, level) => self.set_mix_level(index, level),
+            EngineCommand::OscEnabled(index, enabled) => self.set_osc_enabled(index, enabled),
+            EngineCommand::Pan(index, pan) => self.set_pan(index, pan),
+            EngineCommand::NoisePan(pan) => self.set_noise_pan(pan),
+            EngineCommand::NoiseLevel(level) => self.set_noise_level(level),
+            EngineCommand::NoiseEnabled(enabled) => self.set_noise_enabled(enabled),
+            EngineCommand::NoiseMorph(position) => self.set_noise_morph(position),
+            EngineCommand::FmDepth(depth) => self.set_fm_depth(depth),
+            EngineCommand::Modulation(params) => self.set_modulation(params),
+            EngineCommand::ExternalLevel(level) => self.set_external_level(level),
+            EngineCommand::ExternalEnabled(enabled) => self.set_external_enabled(enabled),
+            EngineCommand::FeedbackPatch(patched) => self.set_feedback_patch(patched),
+            EngineCommand::FollowerRelease(seconds) => self.set_follower_release(seconds),
+            EngineCommand::FollowerCutoff(octaves) => self.set_follower_cutoff(octaves),
+            EngineCommand::FollowerVca(depth) => self.set_follower_vca(depth),
+            EngineCommand::RingLevel(level) => self.set_ring_level(level),
+            EngineCommand::RingEnabled(enabled) => self.set_ring_enabled(enabled),
+            EngineCommand::MasterLevel(value) => self.set_master_level(value),
+            EngineCommand::Limiter(enabled) => self.set_limiter(enabled),
+            EngineCommand::RumbleFilter(enabled) => self.set_rumble_filter(enabled),
+            EngineCommand::ChorusEnabled(enabled) => self.set_chorus_enabled(enabled),
+            EngineCommand::ChorusRate(hz) => self.set_chorus_rate(hz),
+            EngineCommand::ChorusDepth(depth) => self.set_chorus_depth(depth),
+            EngineCommand::ChorusMix(mix) => self.set_chorus_mix(mix),
+            EngineCommand::DelayEnabled(enabled) => self.set_delay_enabled(enabled),
+            EngineCommand::DelaySync(division) => self.set_delay_sync(division),
+            EngineCommand::DelayTime(seconds) => self.set_delay_time(seconds),
+            EngineCommand::DelayFeedback(feedback) => self.set_delay_feedback(feedback),
+            EngineCommand::DelayDamping(hz) => self.set_delay_damping(hz),
+            EngineCommand::DelayMix(mix) => self.set_delay_mix(mix),
+            EngineCommand::ReverbEnabled(enabled) => self.set_reverb_enabled(enabled),
+            EngineCommand::ReverbDecay(seconds) => self.set_reverb_decay(seconds),
+            EngineCommand::ReverbDamping(hz) => self.set_reverb_damping(hz),
+            EngineCommand::ReverbMix(mix) => self.set_reverb_mix(mix),
+            EngineCommand::Cutoff(hz) => self.set_cutoff(hz),
+            EngineCommand::FilterEmphasis(value) => self.set_filter_emphasis(value),
+            EngineCommand::FilterContour(value) => self.set_filter_contour(value),
+            EngineCommand::FilterType(kind) => self.set_filter_type(kind),
+            EngineCommand::FilterSlope(slope) => self.set_filter_slope(slope),
+            EngineCommand::FilterEnvelope(params) => self.set_filter_envelope(params),
+            EngineCommand::LoudnessEnvelope(params) => self.set_loudness_envelope(params),
+            EngineCommand::FilterEnvelopeCurve(curve) => self.set_filter_envelope_curve(curve),
+            EngineCommand::FilterEnvelopeLoop(looping) => self.set_filter_envelope_loop(looping),
+            EngineCommand::LoudnessEnvelopeCurve(curve) => self.set_loudness_envelope_curve(curve),
+            EngineCommand::Velocity(velocity) => self.set_velocity(velocity),
+            EngineCommand::VelocityRoute(route) => self.set_velocity_route(route),
+            EngineCommand::TestSignal(signal) => self.set_test_signal(signal),
+            EngineCommand::Tempo(bpm) => self.set_tempo(bpm),
+            EngineCommand::ClickLevel(level) => self.set_click_level(level),
+            EngineCommand::ClickRestart(beat) => self.restart_click(beat),
+        }
+    }
+
+    /// Like `process`, but applies queued commands at the output frame they
+    /// were scheduled for.
+    pub fn process_scheduled(&mut self, output: &mut [f32], queue: &mut CommandQueue) {
+        self.process_scheduled_with_cv(output, &mut [], queue);
+    }
+
+    /// Like `process_scheduled`, also filling `cv` with the pitch and gate
+    /// levels for each frame when CV/gate output is on.
+    pub(crate) fn process_scheduled_with_cv(
+        &mut self,
+        output: &mut [f32],
+        cv: &mut [[f32; 2]],
+        queue: &mut CommandQueue,
+    ) {
+        let frames = output.len() / 2;
+        queue.publish_block(self.frame, frames, self.sample_rate);
+        let mut start = 0;
+        while start < frames {
+            while let Some(message) = queue.pop_due(self.frame) {
+                match message {
+                    EngineMessage::Command(command) => self.apply(command),
+                    EngineMessage::Job(job) => {
+                        let replaced = self.run_job(job);
+                        queue.retire(replaced);
+                    }
+                }
+            }
+            let end = queue
+                .next_due()
+                .map(|due| start + (due - self.frame) as usize)
+                .unwrap_or(frames)
+                .clamp(start + 1, frames);
+            self.process(&mut output[start * 2..end * 2]);
+            if let (Some(cv_gate), Some(cv)) = (self.cv_gate, cv.get_mut(start..end)) {
+                cv.fill(cv_gate.levels(self.pitch_cv, self.gate));
+            }
+            start = end;
+        }
+    }
+
+    /// Times each stage of `process` for the bench; costs a few clock reads
+    /// per block while on.
+    pub fn set_profiling(&mut self, on: bool) {
+        self.profile = on.then(StageTimings::default);
+    }
+
+    pub fn take_profile(&mut self) -> Option<StageTimings> {
+        self.profile.take()
+    }
+
+    /// Returns the job holding whatever it replaced, so the caller decides
+    /// which thread frees it.
+    pub fn run_job(&mut self, job: PipelineJob) -> PipelineJob {
+        match job {
+            PipelineJob::RecordTap(tap) => {
+                PipelineJob::RecordTap(std::mem::replace(&mut self.record_tap, tap))
+            }
+            PipelineJob::ProbeTap(tap) => {
+                PipelineJob::ProbeTap(std::mem::replace(&mut self.probe_tap, tap))
+            }
+            PipelineJob::ScopeTap(tap) => {
+                PipelineJob::ScopeTap(std::mem::replace(&mut self.scope_tap, tap))
+            }
+            PipelineJob::ExternalInput(input) => {
+                PipelineJob::ExternalInput(std::mem::replace(&mut self.external_input, input))
+            }
+        }
+    }
+
+    pub fn render(&mut self, seconds: f32) -> Vec<f32> {
+        let frames = (seconds.max(0.0) * self.sample_rate) as usize;
+        let mut samples = vec![0.0; frames * 2];
+        self.process(&mut samples);
+        samples
+    }
+
+    /// Fills interleaved stereo frames with the soft-clipped master output.
+    pub fn process(&mut self, output: &mut [f32]) {
+        // The feedback patch needs every output frame before the next one.
+        let frames = if self.feedback_patch { 1 } else { BLOCK_FRAMES };
+        for chunk in output.chunks_mut(frames * 2) {
+            self.process_block(chunk);
+        }
+    }
+
+    fn process_block(&mut self, output: &mut [f32]) {
+        let factor = self.oversampling.factor();
+        let rate = self.internal_rate();
+        let voices = self.bank.len();
+        let steps = output.len() / 2 * factor;
+        // The feedback patch returns the previous output frame to EXT INPUT,
+        // soft-clipped so the loop can scream without running away.
+        let feedback = if self.feedback_patch {
+            self.last_output.map(soft_clip)
+        } else {
+            [0.0; 2]
+        };
+        if let Some(input) = &mut self.external_input {
+            let excess = input.slots().saturating_sub(CAPTURE_BACKLOG_FRAMES);
+            if let Ok(skipped) = input.read_chunk(excess) {
+                skipped.commit_all();
+            }
+        }
+        // Captured audio arrives a frame at a time and holds across the
+        // oversampled steps; an empty ring reads as silence.
+        let external = &mut self.external_block[..steps];
+        for held in external.chunks_exact_mut(factor) {
+            let captured = self
+                .external_input
+                .as_mut()
+                .and_then(|input| input.pop().ok())
+                .unwrap_or([0.0; 2]);
+            held.fill([captured[0] + feedback[0], captured[1] + feedback[1]]);
+        }
+        let mut lap_start = self.profile.is_some().then(std::time::Instant::now);
+        for level in &mut self.mod_block[..steps] {
+            *level = self.modulation.advance(1.0 / rate, self.noise_morph);
+        }
         self.bank
-            .fill_sample(self.sample_rate, &mut self.voice_buffer);
-        let noise_sample = self.noise.sample(self.noise_color);
-        let mixed = self.mixer.mix(&self.voice_buffer, noise_sample);
-        self.modifiers
-            .process(mixed, 1.0 / self.sample_rate.max(1.0))
+            .set_modulation(self.modulation.pitch_depth(), self.modulation.osc3_gain());
+        let voice_block = &mut self.voice_block[..steps * voices];
+        self.bank
+            .fill_block(rate, voice_block, &mut self.mod_block[..steps]);
+        lap(&mut self.profile, BenchStage::Oscillators, &mut lap_start);
+        for sample in &mut self.noise_block[..steps] {
+            *sample = self.noise.morph(self.noise_morph);
+        }
+        let mixed = &mut self.mix_block[..steps];
+        self.mixer
+            .mix_block(voice_block, &self.noise_block[..steps], external, mixed);
+        // The follower hears EXT INPUT ahead of its level knob and switch, so
+        // the input can drive the modulation without being heard.
+        for (level, frame) in self.follow_block[..steps].iter_mut().zip(external.iter()) {
+            *level = self.follower.process(*frame, 1.0 / rate);
+        }
+        if self.test_signal.is_active() {
+            for frame in mixed.iter_mut() {
+                *frame = [self.test_signal.sample(1.0 / rate); 2];
+            }
+        }
+        self.premix_probe[..steps].copy_from_slice(mixed);
+        lap(&mut self.profile, BenchStage::Mixer, &mut lap_start);
+        let filtered = &mut self.filter_probe[..steps];
+        self.modifiers.process_block(
+            mixed,
+            &self.follow_block[..steps],
+            &self.mod_block[..steps],
+            filtered,
+            1.0 / rate,
+        );
+        lap(&mut self.profile, BenchStage::Modifiers, &mut lap_start);
+        for (index, (frame, oversampled)) in output
+            .chunks_exact_mut(2)
+            .zip(mixed.chunks_exact(factor))
+            .enumerate()
+        {
+            let mut channels = [[0.0; MAX_OVERSAMPLING]; 2];
+            for (step, sample) in oversampled.iter().enumerate() {
+                channels[0][step] = sample[0];
+                channels[1][step] = sample[1];
+            }
+            let decimated = [
+                self.decimators[0].process(self.oversampling, &channels[0]),
+                self.decimators[1].process(self.oversampling, &channels[1]),
+            ];
+            let effected = self.effects.process(decimated);
+            self.last_output = self.output_stage.process(effected);
+            let clipped = self.last_output.map(soft_clip);
+            if let Some(tap) = &mut self.record_tap
+                && tap.slots() >= clipped.len()
+            {
+                for sample in clipped {
+                    let _ = tap.push(sample);
+                }
+            }
+            // The click is a monitoring cue: left out of the record tap and
+            // probes, and kept running while muted so it stays on beat. It
+            // joins the signal ahead of the clipper so a loud patch plus a
+            // click still cannot leave the output past its ceiling.
+            let click = self.metronome.sample(1.0 / self.sample_rate);
+            frame.copy_from_slice(&self.last_output.map(|sample| soft_clip(sample + click)));
+            let step = index * factor;
+            let probed = ProbedFrame {
+                oscillators: &voice_block[step * voices..(step + 1) * voices],
+                mixer: self.premix_probe[step],
+                filter: filtered[step],
+                vca: oversampled[0],
+                output: clipped,
+            };
+            for (point, tap) in [&mut self.probe_tap, &mut self.scope_tap]
+                .into_iter()
+                .flatten()
+            {
+                let _ = tap.push(probed.sample(*point));
+            }
+        }
+        self.frame += (output.len() / 2) as u64;
+        lap(&mut self.profile, BenchStage::Output, &mut lap_start);
+    }
+}
+
+fn lap(
+    profile: &mut Option<StageTimings>,
+    stage: BenchStage,
+    since: &mut Option<std::time::Instant>,
+) {
+    if let (Some(profile), Some(since)) = (profile, since) {
+        profile.lap(stage, since);
+    }
+}
+
+/// One output frame's worth of signal at each probe point; oversampled
+/// points use the first step of the frame.
+struct ProbedFrame<'a> {
+    oscillators: &'a [f32],
+    mixer: StereoFrame,
+    filter: StereoFrame,
+    vca: StereoFrame,
+    output: StereoFrame,
+}
+
+impl ProbedFrame<'_> {
+    fn sample(&self, point: ProbePoint) -> f32 {
+        let mid = |frame: StereoFrame| (frame[0] + frame[1]) * 0.5;
+        match point {
+            ProbePoint::Oscillator(index) => self.oscillators.get(index).copied().unwrap_or(0.0),
+            ProbePoint::PostMixer => mid(self.mixer),
+            ProbePoint::PostFilter => mid(self.filter),
+            ProbePoint::PostVca => mid(self.vca),
+            ProbePoint::Output => mid(self.output),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MeterReading {
+    pub peak: f32,
+    pub rms: f32,
+    pub clipped: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+struct MeterAccumulator {
+    peak: f32,
+    sum_squares: f32,
+    count: usize,
+    clipped: bool,
+}
+
+impl MeterAccumulator {
+    fn push(&mut self, sample: f32) {
+        let magnitude = sample.abs();
+        self.peak = self.peak.max(magnitude);
+        self.sum_squares += sample * sample;
+        self.count += 1;
+        if magnitude >= METER_CLIP_LEVEL {
+            self.clipped = true;
+        }
+    }
+
+    fn merge(&mut self, other: &MeterAccumulator) {
+        self.peak = self.peak.max(other.peak);
+        self.sum_squares += other.sum_squares;
+        self.count += other.count;
+        self.clipped |= other.clipped;
+    }
+
+    fn take(&mut self) -> MeterReading {
+        let reading = MeterReading {
+            peak: self.peak,
+            rms: if self.count > 0 {
+                (self.sum_squares / self.count as f32).sqrt()
+            } else {
+                0.0
+            },
+            clipped: self.clipped,
+        };
+        *self = Self::default();
+        reading
+    }
+}
+
+/// An audible fault in the audio stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Glitch {
+    /// A callback took longer to render than its frames last.
+    Overrun,
+    /// A block played as silence because the pipeline was busy.
+    Dropout,
+    /// The audio backend reported an error.
+    StreamError,
+}
+
+impl Glitch {
+    pub fn label(self) -> &'static str {
+        match self {
+            Glitch::Overrun => "OVERRUN",
+            Glitch::Dropout => "DROPOUT",
+            Glitch::StreamError => "STREAM ERROR",
+        }
+    }
+}
+
+/// Audio-thread end of the debug capture. The callback pushes the output
+/// and one report per block into lock-free rings, so it never waits on the
+/// UI; whatever doesn't fit while the UI is behind is dropped.
+pub struct DebugTap {
+    samples: Producer<f32>,
+    reports: Producer<DebugReport>,
+    stream_errors: Arc<AtomicU32>,
+}
+
+impl DebugTap {
+    fn push_sample(&mut self, sample: f32) {
+        let _ = self.samples.push(sample);
+    }
+
+    fn report(&mut self, report: DebugReport) {
+        let _ = self.reports.push(report);
+    }
+
+    /// Counter for the backend's error callback, which runs on its own
+    /// thread and so can't share the report ring.
+    pub(crate) fn stream_errors(&self) -> Arc<AtomicU32> {
+        self.stream_errors.clone()
     }
 }
 
+#[derive(Clone, Copy)]
+enum DebugReport {
+    Block(BlockReport),
+    Glitch(Glitch),
+}
+
+#[derive(Clone, Copy)]
+struct BlockReport {
+    block_frames: usize,
+    latency: f32,
+    budget: f32,
+    callback_time: Option<f32>,
+    meters: [MeterAccumulator; 2],
+    envelopes: [EnvelopeState; 2],
+    gain_reduction_db: f32,
+    overload: bool,
+}
+
+/// UI end of the debug capture: drains the current stream's rings once per
+/// frame and accumulates what the meters and debug window read.
 pub struct DebugData {
+    samples: Option<Consumer<f32>>,
+    reports: Option<Consumer<DebugReport>>,
+    stream_errors: Arc<AtomicU32>,
+    stream_errors_seen: u32,
     buffer: Vec<f32>,
     cursor: usize,
     filled: bool,
     overload: bool,
+    gain_reduction_db: f32,
+    meters: [MeterAccumulator; 2],
+    envelopes: [EnvelopeState; 2],
+    block_frames: usize,
+    latency: f32,
+    callback_load: f32,
+    callback_peak: f32,
+    glitches: Vec<Glitch>,
 }
 
 impl DebugData {
     pub fn new(size: usize) -> Self {
         Self {
+            samples: None,
+            reports: None,
+            stream_errors: Arc::new(AtomicU32::new(0)),
+            stream_errors_seen: 0,
             buffer: vec![0.0; size],
             cursor: 0,
             filled: false,
             overload: false,
+            gain_reduction_db: 0.0,
+            meters: [MeterAccumulator::default(); 2],
+            envelopes: [EnvelopeState::default(); 2],
+            block_frames: 0,
+            latency: 0.0,
+            callback_load: 0.0,
+            callback_peak: 0.0,
+            glitches: Vec::new(),
         }
     }
 
-    pub fn push(&mut self, value: f32) {
+    /// Opens fresh rings for a new stream; the previous stream's tap keeps
+    /// pushing into its own rings until it is dropped, which nobody reads.
+    pub fn connect(&mut self) -> DebugTap {
+        self.drain();
+        let (samples, sample_consumer) = RingBuffer::new(self.buffer.len() * 4);
+        let (reports, report_consumer) = RingBuffer::new(DEBUG_REPORT_CAPACITY);
+        self.samples = Some(sample_consumer);
+        self.reports = Some(report_consumer);
+        self.stream_errors = Arc::new(AtomicU32::new(0));
+        self.stream_errors_seen = 0;
+        DebugTap {
+            samples,
+            reports,
+            stream_errors: self.stream_errors.clone(),
+        }
+    }
+
+    /// Pulls everything the audio thread has pushed since the last call.
+    pub fn drain(&mut self) {
+        if let Some(mut samples) = self.samples.take() {
+            while let Ok(sample) = samples.pop() {
+                self.push(sample);
+            }
+            self.samples = Some(samples);
+        }
+        if let Some(mut reports) = self.reports.take() {
+            while let Ok(report) = reports.pop() {
+                match report {
+                    DebugReport::Block(block) => self.merge(&block),
+                    DebugReport::Glitch(glitch) => self.record_glitch(glitch),
+                }
+            }
+            self.reports = Some(reports);
+        }
+        let errors = self.stream_errors.load(Ordering::Relaxed);
+        for _ in self.stream_errors_seen..errors {
+            self.record_glitch(Glitch::StreamError);
+        }
+        self.stream_errors_seen = errors;
+    }
+
+    fn record_glitch(&mut self, glitch: Glitch) {
+        if self.glitches.len() < MAX_PENDING_GLITCHES {
+            self.glitches.push(glitch);
+        }
+    }
+
+    fn merge(&mut self, block: &BlockReport) {
+        self.block_frames = block.block_frames;
+        self.latency = block.latency;
+        if let Some(seconds) = block.callback_time {
+            let load = seconds / block.budget.max(f32::EPSILON);
+            self.callback_load += (load - self.callback_load) * LOAD_SMOOTHING;
+            self.callback_peak = self.callback_peak.max(load);
+        }
+        for (meter, reported) in self.meters.iter_mut().zip(&block.meters) {
+            meter.merge(reported);
+        }
+        self.envelopes = block.envelopes;
+        self.gain_reduction_db = self.gain_reduction_db.max(block.gain_reduction_db);
+        self.overload |= block.overload;
+    }
+
+    pub fn latency(&self) -> (usize, f32) {
+        (self.block_frames, self.latency)
+    }
+
+    /// Smoothed load and the peak since the last call, both as a fraction
+    /// of the callback budget.
+    pub fn take_callback_load(&mut self) -> (f32, f32) {
+        (self.callback_load, std::mem::take(&mut self.callback_peak))
+    }
+
+    pub fn take_glitches(&mut self) -> Vec<Glitch> {
+        std::mem::take(&mut self.glitches)
+    }
+
+    pub fn envelopes(&self) -> [EnvelopeState; 2] {
+        self.envelopes
+    }
+
+    pub fn take_meters(&mut self) -> [MeterReading; 2] {
+        [self.meters[0].take(), self.meters[1].take()]
+    }
+
+    fn push(&mut self, value: f32) {
         if let Some(slot) = self.buffer.get_mut(self.cursor) {
             *slot = value;
         }
-        if value.abs() > 0.9 {
-            self.overload = true;
-        }
         self.cursor = (self.cursor + 1) % self.buffer.len();
         if self.cursor == 0 {
             self.filled = true;
@@ -152,6 +1123,10 @@ impl DebugData {
         data
     }
 
+    pub fn take_gain_reduction(&mut self) -> f32 {
+        std::mem::take(&mut self.gain_reduction_db)
+    }
+
     pub fn take_overload(&mut self) -> bool {
         let flag = self.overload;
         self.overload = false;
@@ -159,114 +1134,251 @@ impl DebugData {
     }
 }
 
-pub struct AudioEngine {
-    _stream: Stream,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferRequest {
+    Auto,
+    Frames64,
+    Frames128,
+    Frames256,
+    Frames512,
+    Frames1024,
 }
 
-impl AudioEngine {
-    pub fn start(pipeline: SharedPipeline, debug: DebugHandle) -> Result<Self> {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .ok_or_else(|| anyhow!("No default audio output"))?;
-        let supported = device.default_output_config()?;
-        let config = supported.config();
-        let sample_rate = config.sample_rate.0 as f32;
-        {
-            let mut guard = pipeline.lock().expect("pipeline lock");
-            guard.set_sample_rate(sample_rate);
-        }
-        let stream = match supported.sample_format() {
-            SampleFormat::F32 => build_stream_f32(&device, &config, pipeline, debug)?,
-            SampleFormat::I16 => build_stream_i16(&device, &config, pipeline, debug)?,
-            SampleFormat::U16 => build_stream_u16(&device, &config, pipeline, debug)?,
-            _ => build_stream_f32(&device, &config, pipeline, debug)?,
-        };
-        stream.play()?;
-        Ok(Self { _stream: stream })
+impl BufferRequest {
+    pub const VALUES: [BufferRequest; 6] = [
+        BufferRequest::Auto,
+        BufferRequest::Frames64,
+        BufferRequest::Frames128,
+        BufferRequest::Frames256,
+        BufferRequest::Frames512,
+        BufferRequest::Frames1024,
+    ];
+
+    pub fn frames(self) -> Option<u32> {
+        match self {
+            BufferRequest::Auto => None,
+            BufferRequest::Frames64 => Some(64),
+            BufferRequest::Frames128 => Some(128),
+            BufferRequest::Frames256 => Some(256),
+            BufferRequest::Frames512 => Some(512),
+            BufferRequest::Frames1024 => Some(1024),
+        }
     }
-}
 
-fn build_stream_f32(
-    device: &cpal::Device,
-    config: &cpal::StreamConfig,
-    pipeline: SharedPipeline,
-    debug: DebugHandle,
-) -> Result<Stream> {
-    let channels = config.channels as usize;
-    let config = config.clone();
-    let stream = device.build_output_stream(
-        &config,
-        move |output: &mut [f32], _| {
-            fill_output_buffer(output, channels, &pipeline, &debug, |sample| sample);
-        },
-        move |err| eprintln!("audio stream error: {err}"),
-        None,
-    )?;
-    Ok(stream)
+    /// Zero asks for the device default.
+    pub fn from_frames(frames: u32) -> Option<Self> {
+        Self::VALUES
+            .into_iter()
+            .find(|request| request.frames().unwrap_or(0) == frames)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BufferRequest::Auto => "AUTO",
+            BufferRequest::Frames64 => "64",
+            BufferRequest::Frames128 => "128",
+            BufferRequest::Frames256 => "256",
+            BufferRequest::Frames512 => "512",
+            BufferRequest::Frames1024 => "1024",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let index = Self::VALUES
+            .iter()
+            .position(|request| *request == self)
+            .unwrap_or(0);
+        Self::VALUES[(index + 1) % Self::VALUES.len()]
+    }
 }
 
-fn build_stream_i16(
-    device: &cpal::Device,
-    config: &cpal::StreamConfig,
-    pipeline: SharedPipeline,
-    debug: DebugHandle,
-) -> Result<Stream> {
-    let channels = config.channels as usize;
-    let config = config.clone();
-    let stream = device.build_output_stream(
-        &config,
-        move |output: &mut [i16], _| {
-            fill_output_buffer(output, channels, &pipeline, &debug, |sample| {
-                (sample * i16::MAX as f32) as i16
-            });
-        },
-        move |err| eprintln!("audio stream error: {err}"),
-        None,
-    )?;
-    Ok(stream)
+/// Which output device to open and at what rate; `None` takes the system
+/// default for either. With `engine_rate` set the pipeline runs at that rate
+/// whatever the device's, and the stream resamples in between.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceRequest {
+    pub name: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub engine_rate: Option<u32>,
 }
 
-fn build_stream_u16(
-    device: &cpal::Device,
-    config: &cpal::StreamConfig,
-    pipeline: SharedPipeline,
-    debug: DebugHandle,
-) -> Result<Stream> {
-    let channels = config.channels as usize;
-    let config = config.clone();
-    let stream = device.build_output_stream(
-        &config,
-        move |output: &mut [u16], _| {
-            fill_output_buffer(output, channels, &pipeline, &debug, |sample| {
-                let scaled = (sample * 0.5 + 0.5).clamp(0.0, 1.0);
-                (scaled * u16::MAX as f32) as u16
-            });
-        },
-        move |err| eprintln!("audio stream error: {err}"),
-        None,
-    )?;
-    Ok(stream)
+impl DeviceRequest {
+    /// The rate the pipeline runs at on a device running at `device_rate`.
+    pub fn engine_rate(&self, device_rate: u32) -> u32 {
+        self.engine_rate.unwrap_or(device_rate)
+    }
 }
 
-fn fill_output_buffer<T, F>(
-    output: &mut [T],
+pub(crate) struct StreamContext {
     channels: usize,
-    pipeline: &SharedPipeline,
-    debug: &DebugHandle,
-    mut convert: F,
-) where
-    F: FnMut(f32) -> T,
-    T: Copy,
-{
-    let mut pipe = pipeline.lock().expect("pipeline lock");
-    let mut debug_guard = debug.lock().expect("debug lock");
-    for frame in output.chunks_mut(channels) {
-        let sample = pipe.next_sample().clamp(-0.98, 0.98);
-        debug_guard.push(sample);
-        let value = convert(sample);
-        for channel in frame {
-            *channel = value;
+    pub(crate) pipeline: SharedPipeline,
+    active: Option<Box<SynthPipeline>>,
+    /// Engine rate to set on the pipeline at checkout; the null sink keeps
+    /// whatever it ran at before.
+    sample_rate: Option<f32>,
+    /// Set when the engine runs at a different rate from the device.
+    resampler: Option<Resampler>,
+    pub(crate) debug: DebugTap,
+    queue: CommandQueue,
+    scratch: Vec<f32>,
+    cv_scratch: Vec<[f32; 2]>,
+    resampled: Vec<f32>,
+    pub(crate) failed: Arc<AtomicBool>,
+}
+
+impl StreamContext {
+    pub(crate) fn new(
+        channels: usize,
+        pipeline: SharedPipeline,
+        debug: DebugTap,
+        queue: CommandQueue,
+        failed: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            channels,
+            pipeline,
+            active: None,
+            sample_rate: None,
+            resampler: None,
+            debug,
+            queue,
+            scratch: Vec::with_capacity(PREALLOCATED_FRAMES * 2),
+            cv_scratch: Vec::with_capacity(PREALLOCATED_FRAMES),
+            resampled: Vec::new(),
+            failed,
+        }
+    }
+
+    /// Runs the pipeline at `engine_rate` on a device at `device_rate`,
+    /// resampling when the two differ.
+    pub(crate) fn set_rates(&mut self, device_rate: u32, engine_rate: u32) {
+        self.sample_rate = Some(engine_rate as f32);
+        self.resampler =
+            (engine_rate != device_rate).then(|| Resampler::new(engine_rate, device_rate));
+        if self.resampler.is_some() {
+            // The engine renders more (or fewer) frames than the device
+            // asks for; size the scratch for that up front too.
+            let ratio = engine_rate.div_ceil(device_rate) as usize;
+            self.scratch.reserve(PREALLOCATED_FRAMES * 2 * ratio);
+            self.cv_scratch.reserve(PREALLOCATED_FRAMES * ratio);
+            self.resampled = Vec::with_capacity(PREALLOCATED_FRAMES * 2);
+        }
+    }
+
+    pub(crate) fn fill<T>(&mut self, output: &mut [T], device_delay: f32, convert: fn(f32) -> T)
+    where
+        T: Copy,
+    {
+        rtaudit::realtime(|| self.fill_block(output, device_delay, convert));
+    }
+
+    fn fill_block<T>(&mut self, output: &mut [T], device_delay: f32, convert: fn(f32) -> T)
+    where
+        T: Copy,
+    {
+        if self.pipeline.lend_requested.load(Ordering::Acquire)
+            && let Some(pipe) = self.active.take()
+        {
+            self.active = self.pipeline.try_park(pipe);
+        }
+        if self.active.is_none() {
+            self.active = self.pipeline.checkout();
+            if let (Some(pipe), Some(rate)) = (self.active.as_deref_mut(), self.sample_rate)
+                && pipe.sample_rate() != rate
+            {
+                // Rebuilds tables once per device change, on the first block.
+                rtaudit::permit(|| pipe.set_sample_rate(rate));
+            }
+        }
+        let Some(pipe) = self.active.as_deref_mut() else {
+            output.fill(convert(0.0));
+            self.debug.report(DebugReport::Glitch(Glitch::Dropout));
+            return;
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let started = std::time::Instant::now();
+        let frames = output.len() / self.channels.max(1);
+        let engine_frames = self
+            .resampler
+            .as_ref()
+            .map_or(frames, |resampler| resampler.input_frames(frames));
+        self.scratch.resize(engine_frames * 2, 0.0);
+        let cv_gate = pipe
+            .cv_gate()
+            .filter(|cv_gate| cv_gate.channels_needed() <= self.channels);
+        self.cv_scratch
+            .resize(if cv_gate.is_some() { engine_frames } else { 0 }, [0.0; 2]);
+        pipe.process_scheduled_with_cv(&mut self.scratch, &mut self.cv_scratch, &mut self.queue);
+        // Scope and meters see the engine's own rate, like the probes do.
+        let mut meters = [MeterAccumulator::default(); 2];
+        for stereo in self.scratch.chunks_exact(2) {
+            self.debug.push_sample((stereo[0] + stereo[1]) * 0.5);
+            meters[0].push(stereo[0]);
+            meters[1].push(stereo[1]);
+        }
+        let device_rate = match &mut self.resampler {
+            Some(resampler) => {
+                self.resampled.resize(frames * 2, 0.0);
+                resampler.process(&self.scratch, &mut self.resampled);
+                resampler.output_rate() as f32
+            }
+            None => pipe.sample_rate(),
+        };
+        let stereo_out = if self.resampler.is_some() {
+            &self.resampled
+        } else {
+            &self.scratch
+        };
+        for (frame_index, (frame, stereo)) in output
+            .chunks_mut(self.channels)
+            .zip(stereo_out.chunks_exact(2))
+            .enumerate()
+        {
+            if let [only] = frame {
+                *only = convert((stereo[0] + stereo[1]) * 0.5);
+                continue;
+            }
+            for (index, channel) in frame.iter_mut().enumerate() {
+                *channel = convert(stereo[index % 2]);
+            }
+            // CV holds between engine frames, so the nearest one will do.
+            let source = frame_index * engine_frames / frames.max(1);
+            if let (Some(cv_gate), Some(levels)) = (cv_gate, self.cv_scratch.get(source)) {
+                cv_gate.write(frame, *levels, convert);
+            }
+        }
+        let budget = frames as f32 / device_rate;
+        let resampler_delay = self.resampler.as_ref().map_or(0.0, Resampler::latency);
+        #[cfg(not(target_arch = "wasm32"))]
+        let callback_time = Some(started.elapsed().as_secs_f32());
+        #[cfg(target_arch = "wasm32")]
+        let callback_time = None;
+        if callback_time.is_some_and(|seconds| seconds > budget) {
+            self.debug.report(DebugReport::Glitch(Glitch::Overrun));
         }
+        let report = BlockReport {
+            block_frames: frames,
+            latency: budget + device_delay + resampler_delay,
+            budget,
+            callback_time,
+            meters,
+            envelopes: pipe.envelope_states(),
+            gain_reduction_db: pipe.take_gain_reduction(),
+            overload: pipe.take_overload(),
+        };
+        self.debug.report(DebugReport::Block(report));
+    }
+
+    /// Hands the pipeline back to its slot for the next stream.
+    pub(crate) fn park(&mut self) {
+        if let Some(pipe) = self.active.take() {
+            self.pipeline.park(pipe);
+        }
+    }
+}
+
+impl Drop for StreamContext {
+    fn drop(&mut self) {
+        self.park();
     }
 }
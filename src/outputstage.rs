@@ -0,0 +1,207 @@
+use std::f32::consts::{PI, SQRT_2};
+
+use crate::mixer::StereoFrame;
+
+const DC_BLOCK_HZ: f32 = 5.0;
+const RUMBLE_HZ: f32 = 20.0;
+const LIMITER_CEILING: f32 = 0.89;
+const LIMITER_LOOKAHEAD_SEC: f32 = 0.002;
+const LIMITER_RELEASE_SEC: f32 = 0.12;
+const SOFT_CLIP_CEILING: f32 = 0.98;
+
+pub fn soft_clip(sample: f32) -> f32 {
+    SOFT_CLIP_CEILING * (sample / SOFT_CLIP_CEILING).tanh()
+}
+
+struct DcBlocker {
+    coefficient: f32,
+    last_input: f32,
+    last_output: f32,
+}
+
+impl DcBlocker {
+    fn new(sample_rate: f32) -> Self {
+        Self {
+            coefficient: (-2.0 * PI * DC_BLOCK_HZ / sample_rate).exp(),
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = input - self.last_input + self.coefficient * self.last_output;
+        self.last_input = input;
+        self.last_output = output;
+        output
+    }
+}
+
+// Second-order Butterworth high-pass for subsonic rumble.
+struct RumbleFilter {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl RumbleFilter {
+    fn new(sample_rate: f32) -> Self {
+        let k = (PI * RUMBLE_HZ / sample_rate).tan();
+        let norm = 1.0 / (1.0 + SQRT_2 * k + k * k);
+        Self {
+            b: [norm, -2.0 * norm, norm],
+            a: [
+                2.0 * (k * k - 1.0) * norm,
+                (1.0 - SQRT_2 * k + k * k) * norm,
+            ],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+// Brick-wall peak limiter: the signal is delayed by the look-ahead window so
+// the gain can ramp down before a peak reaches the output. Both channels share
+// one gain so the stereo image doesn't shift while limiting.
+struct Limiter {
+    delay: Vec<StereoFrame>,
+    cursor: usize,
+    hold_gain: f32,
+    hold_remaining: usize,
+    gain: f32,
+    attack: f32,
+    release: f32,
+}
+
+impl Limiter {
+    fn new(sample_rate: f32) -> Self {
+        let lookahead = ((sample_rate * LIMITER_LOOKAHEAD_SEC) as usize).max(1);
+        Self {
+            delay: vec![[0.0; 2]; lookahead],
+            cursor: 0,
+            hold_gain: 1.0,
+            hold_remaining: 0,
+            gain: 1.0,
+            attack: (-4.0 / lookahead as f32).exp(),
+            release: (-1.0 / (sample_rate * LIMITER_RELEASE_SEC)).exp(),
+        }
+    }
+
+    fn process(&mut self, input: StereoFrame) -> StereoFrame {
+        let required = ceiling_gain(input);
+        if required <= self.hold_gain {
+            self.hold_gain = required;
+            self.hold_remaining = self.delay.len();
+        } else if self.hold_remaining > 0 {
+            self.hold_remaining -= 1;
+        } else {
+            self.hold_gain = required;
+        }
+        let coefficient = if self.hold_gain < self.gain {
+            self.attack
+        } else {
+            self.release
+        };
+        self.gain = self.hold_gain + (self.gain - self.hold_gain) * coefficient;
+
+        let delayed = std::mem::replace(&mut self.delay[self.cursor], input);
+        self.cursor = (self.cursor + 1) % self.delay.len();
+        self.gain = self.gain.min(ceiling_gain(delayed));
+        delayed.map(|sample| sample * self.gain)
+    }
+
+    fn gain_reduction_db(&self) -> f32 {
+        -20.0 * self.gain.max(1e-6).log10()
+    }
+}
+
+fn ceiling_gain(frame: StereoFrame) -> f32 {
+    let peak = frame[0].abs().max(frame[1].abs());
+    if peak > LIMITER_CEILING {
+        LIMITER_CEILING / peak
+    } else {
+        1.0
+    }
+}
+
+pub struct OutputStage {
+    dc_blockers: [DcBlocker; 2],
+    rumble: [RumbleFilter; 2],
+    rumble_enabled: bool,
+    limiter: Limiter,
+    limiter_enabled: bool,
+    peak_reduction_db: f32,
+    master: f32,
+    sample_rate: f32,
+}
+
+impl OutputStage {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            dc_blockers: [DcBlocker::new(sample_rate), DcBlocker::new(sample_rate)],
+            rumble: [
+                RumbleFilter::new(sample_rate),
+                RumbleFilter::new(sample_rate),
+            ],
+            rumble_enabled: false,
+            limiter: Limiter::new(sample_rate),
+            limiter_enabled: false,
+            peak_reduction_db: 0.0,
+            master: 0.7,
+            sample_rate,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        if (self.sample_rate - sample_rate).abs() > f32::EPSILON {
+            self.dc_blockers = [DcBlocker::new(sample_rate), DcBlocker::new(sample_rate)];
+            self.rumble = [
+                RumbleFilter::new(sample_rate),
+                RumbleFilter::new(sample_rate),
+            ];
+            self.limiter = Limiter::new(sample_rate);
+            self.sample_rate = sample_rate;
+        }
+    }
+
+    pub fn set_master(&mut self, value: f32) {
+        self.master = value.clamp(0.0, 1.0);
+    }
+
+    pub fn set_rumble_enabled(&mut self, enabled: bool) {
+        self.rumble_enabled = enabled;
+    }
+
+    pub fn set_limiter_enabled(&mut self, enabled: bool) {
+        self.limiter_enabled = enabled;
+    }
+
+    pub fn take_gain_reduction(&mut self) -> f32 {
+        std::mem::take(&mut self.peak_reduction_db)
+    }
+
+    pub fn process(&mut self, input: StereoFrame) -> StereoFrame {
+        let mut signal = [0.0; 2];
+        for channel in 0..2 {
+            let mut sample = self.dc_blockers[channel].process(input[channel]);
+            if self.rumble_enabled {
+                sample = self.rumble[channel].process(sample);
+            }
+            signal[channel] = sample * self.master;
+        }
+        if self.limiter_enabled {
+            signal = self.limiter.process(signal);
+            self.peak_reduction_db = self.peak_reduction_db.max(self.limiter.gain_reduction_db());
+        }
+        signal
+    }
+}
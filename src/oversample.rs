@@ -0,0 +1,119 @@
+use std::f32::consts::PI;
+
+const HALFBAND_TAPS: usize = 31;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Oversampling {
+    Off,
+    X2,
+    X4,
+}
+
+impl Oversampling {
+    pub const VALUES: [Oversampling; 3] = [Oversampling::Off, Oversampling::X2, Oversampling::X4];
+
+    pub fn next(self) -> Self {
+        let index = Self::VALUES
+            .iter()
+            .position(|mode| *mode == self)
+            .unwrap_or(0);
+        Self::VALUES[(index + 1) % Self::VALUES.len()]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Oversampling::Off => "OFF",
+            Oversampling::X2 => "2X",
+            Oversampling::X4 => "4X",
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        match self {
+            Oversampling::Off => 1,
+            Oversampling::X2 => 2,
+            Oversampling::X4 => 4,
+        }
+    }
+}
+
+// Blackman-windowed halfband FIR that halves the sample rate.
+struct HalfbandDecimator {
+    coefficients: [f32; HALFBAND_TAPS],
+    history: [f32; HALFBAND_TAPS],
+    cursor: usize,
+}
+
+impl HalfbandDecimator {
+    fn new() -> Self {
+        let center = (HALFBAND_TAPS - 1) as f32 * 0.5;
+        let mut coefficients = [0.0; HALFBAND_TAPS];
+        for (n, coefficient) in coefficients.iter_mut().enumerate() {
+            let offset = n as f32 - center;
+            let sinc = if offset == 0.0 {
+                0.5
+            } else {
+                (PI * offset * 0.5).sin() / (PI * offset)
+            };
+            let phase = 2.0 * PI * n as f32 / (HALFBAND_TAPS - 1) as f32;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            *coefficient = sinc * window;
+        }
+        let sum: f32 = coefficients.iter().sum();
+        for coefficient in &mut coefficients {
+            *coefficient /= sum;
+        }
+        Self {
+            coefficients,
+            history: [0.0; HALFBAND_TAPS],
+            cursor: 0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.history[self.cursor] = sample;
+        self.cursor = (self.cursor + 1) % HALFBAND_TAPS;
+    }
+
+    fn process(&mut self, first: f32, second: f32) -> f32 {
+        self.push(first);
+        self.push(second);
+        self.coefficients
+            .iter()
+            .enumerate()
+            .map(|(n, coefficient)| {
+                coefficient * self.history[(self.cursor + HALFBAND_TAPS - 1 - n) % HALFBAND_TAPS]
+            })
+            .sum()
+    }
+}
+
+pub struct Decimator {
+    stages: [HalfbandDecimator; 2],
+}
+
+impl Decimator {
+    pub fn new() -> Self {
+        Self {
+            stages: [HalfbandDecimator::new(), HalfbandDecimator::new()],
+        }
+    }
+
+    pub fn process(&mut self, mode: Oversampling, block: &[f32]) -> f32 {
+        match mode {
+            Oversampling::Off => block.first().copied().unwrap_or(0.0),
+            Oversampling::X2 => self.stages[0].process(block[0], block[1]),
+            Oversampling::X4 => {
+                let first = self.stages[1].process(block[0], block[1]);
+                let second = self.stages[1].process(block[2], block[3]);
+                self.stages[0].process(first, second)
+            }
+        }
+    }
+}
+
+impl Default for Decimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,656 @@
+use crate::{
+    effects::{
+        CHORUS_RATE_MAX, CHORUS_RATE_MIN, DELAY_DAMPING_CLOSED_HZ, DELAY_DAMPING_OPEN_HZ,
+        DELAY_TIME_MAX, DELAY_TIME_MIN, REVERB_DAMPING_CLOSED_HZ, REVERB_DAMPING_OPEN_HZ,
+        REVERB_DECAY_MAX, REVERB_DECAY_MIN,
+    },
+    follower::{FOLLOWER_RELEASE_MAX, FOLLOWER_RELEASE_MIN},
+    modifiers::knob_to_env_time,
+    noise::NoiseColor,
+    vco::Waveform,
+};
+
+pub const TUNE_RANGE_OCT: f32 = 1.0;
+pub const GLIDE_MIN_SEC: f32 = 0.0;
+pub const GLIDE_MAX_SEC: f32 = 0.6;
+pub const DETUNE_RANGE: f32 = 8.0;
+pub const SNAP_RANGE_SEMITONES: f32 = 24.0;
+pub const FILTER_MIN_HZ: f32 = 200.0;
+pub const FILTER_MAX_HZ: f32 = 5_000.0;
+pub const FILTER_ATTACK_MIN: f32 = 0.0015;
+pub const FILTER_ATTACK_MAX: f32 = 3.0;
+pub const FILTER_DECAY_MIN: f32 = 0.005;
+pub const FILTER_DECAY_MAX: f32 = 4.0;
+pub const LOUD_ATTACK_MIN: f32 = 0.001;
+pub const LOUD_ATTACK_MAX: f32 = 4.5;
+pub const LOUD_DECAY_MIN: f32 = 0.01;
+pub const LOUD_DECAY_MAX: f32 = 6.0;
+pub const ENV_STAGE_MIN: f32 = 0.001;
+pub const ENV_STAGE_MAX: f32 = 4.0;
+pub const ENV_RELEASE_MIN: f32 = 0.005;
+pub const ENV_RELEASE_MAX: f32 = 8.0;
+pub const LFO_RATE_MIN: f32 = 0.2;
+pub const LFO_RATE_MAX: f32 = 12.0;
+pub const LFO_FADE_MAX: f32 = 5.0;
+pub const PITCH_MOD_MAX_OCT: f32 = 1.0;
+pub const FILTER_MOD_MAX_DEPTH: f32 = 1.0;
+/// Pitch bend ranges in semitones either way.
+pub const BEND_RANGES: [i32; 5] = [1, 2, 5, 7, 12];
+pub const DEFAULT_BEND_RANGE: i32 = 2;
+pub const FOLLOWER_CUTOFF_MAX_OCT: f32 = 4.0;
+
+pub const OSC1_WAVES: [Waveform; 6] = [
+    Waveform::Triangle,
+    Waveform::TriangleSaw,
+    Waveform::Saw,
+    Waveform::PulseSquare,
+    Waveform::PulseWide,
+    Waveform::PulseNarrow,
+];
+
+pub const OSC2_WAVES: [Waveform; 6] = [
+    Waveform::Triangle,
+    Waveform::TriangleSaw,
+    Waveform::Saw,
+    Waveform::PulseSquare,
+    Waveform::PulseWide,
+    Waveform::PulseNarrow,
+];
+
+pub const OSC3_WAVES: [Waveform; 6] = [
+    Waveform::Triangle,
+    Waveform::ReverseSaw,
+    Waveform::Saw,
+    Waveform::PulseSquare,
+    Waveform::PulseWide,
+    Waveform::PulseNarrow,
+];
+
+#[derive(Clone, Copy)]
+pub struct RangeSetting {
+    pub label: &'static str,
+    pub octave_offset: f32,
+}
+
+pub const OSC_RANGE_SETTINGS: [RangeSetting; 6] = [
+    RangeSetting {
+        label: "LO",
+        octave_offset: -5.0,
+    },
+    RangeSetting {
+        label: "32'",
+        octave_offset: -2.0,
+    },
+    RangeSetting {
+        label: "16'",
+        octave_offset: -1.0,
+    },
+    RangeSetting {
+        label: "8'",
+        octave_offset: 0.0,
+    },
+    RangeSetting {
+        label: "4'",
+        octave_offset: 1.0,
+    },
+    RangeSetting {
+        label: "2'",
+        octave_offset: 2.0,
+    },
+];
+
+// LFO mode drops OSC 3 by the LO range's five octaves on top of its RANGE,
+// so RANGE and FREQ still set the rate.
+pub const OSC3_LFO_OFFSET: f32 = OSC_RANGE_SETTINGS[0].octave_offset;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KnobId {
+    ControllersTune,
+    ControllersGlide,
+    ControllersModMix,
+    ControllersModRate,
+    ControllersModAmount,
+    ModPitchDepth,
+    ModFilterDepth,
+    OscRange1,
+    OscRange2,
+    OscRange3,
+    OscFreq1,
+    OscFreq2,
+    OscFreq3,
+    OscWave1,
+    OscWave2,
+    OscWave3,
+    OscFmDepth,
+    MixerExternal,
+    MixerOsc1,
+    MixerOsc2,
+    MixerOsc3,
+    MixerNoise,
+    MixerRing,
+    MixerPan1,
+    MixerPan2,
+    MixerPan3,
+    MixerNoisePan,
+    FilterCutoff,
+    FilterEmphasis,
+    FilterContour,
+    FilterAttack,
+    FilterDecay,
+    FilterSustain,
+    LoudnessAttack,
+    LoudnessDecay,
+    LoudnessSustain,
+    OutputVolume,
+    OutputPhones,
+    Macro1,
+    Macro2,
+    Macro3,
+    Macro4,
+    MixerNoiseColor,
+    FilterDelay,
+    FilterHold,
+    FilterRelease,
+    LoudnessDelay,
+    LoudnessHold,
+    LoudnessRelease,
+    ModFade,
+    FollowerRelease,
+    FollowerCutoff,
+    FollowerVca,
+    ChorusRate,
+    ChorusDepth,
+    ChorusMix,
+    DelayTime,
+    DelayFeedback,
+    DelayDamping,
+    DelayMix,
+    ReverbSize,
+    ReverbDamping,
+    ReverbMix,
+}
+
+impl KnobId {
+    pub const ALL: [KnobId; 63] = [
+        KnobId::ControllersTune,
+        KnobId::ControllersGlide,
+        KnobId::ControllersModMix,
+        KnobId::ControllersModRate,
+        KnobId::ControllersModAmount,
+        KnobId::ModPitchDepth,
+        KnobId::ModFilterDepth,
+        KnobId::OscRange1,
+        KnobId::OscRange2,
+        KnobId::OscRange3,
+        KnobId::OscFreq1,
+        KnobId::OscFreq2,
+        KnobId::OscFreq3,
+        KnobId::OscWave1,
+        KnobId::OscWave2,
+        KnobId::OscWave3,
+        KnobId::OscFmDepth,
+        KnobId::MixerExternal,
+        KnobId::MixerOsc1,
+        KnobId::MixerOsc2,
+        KnobId::MixerOsc3,
+        KnobId::MixerNoise,
+        KnobId::MixerRing,
+        KnobId::MixerPan1,
+        KnobId::MixerPan2,
+        KnobId::MixerPan3,
+        KnobId::MixerNoisePan,
+        KnobId::FilterCutoff,
+        KnobId::FilterEmphasis,
+        KnobId::FilterContour,
+        KnobId::FilterAttack,
+        KnobId::FilterDecay,
+        KnobId::FilterSustain,
+        KnobId::LoudnessAttack,
+        KnobId::LoudnessDecay,
+        KnobId::LoudnessSustain,
+        KnobId::OutputVolume,
+        KnobId::OutputPhones,
+        KnobId::Macro1,
+        KnobId::Macro2,
+        KnobId::Macro3,
+        KnobId::Macro4,
+        KnobId::MixerNoiseColor,
+        KnobId::FilterDelay,
+        KnobId::FilterHold,
+        KnobId::FilterRelease,
+        KnobId::LoudnessDelay,
+        KnobId::LoudnessHold,
+        KnobId::LoudnessRelease,
+        KnobId::ModFade,
+        KnobId::FollowerRelease,
+        KnobId::FollowerCutoff,
+        KnobId::FollowerVca,
+        KnobId::ChorusRate,
+        KnobId::ChorusDepth,
+        KnobId::ChorusMix,
+        KnobId::DelayTime,
+        KnobId::DelayFeedback,
+        KnobId::DelayDamping,
+        KnobId::DelayMix,
+        KnobId::ReverbSize,
+        KnobId::ReverbDamping,
+        KnobId::ReverbMix,
+    ];
+
+    pub fn preset_key(self) -> &'static str {
+        match self {
+            KnobId::ControllersTune => "controllers.tune",
+            KnobId::ControllersGlide => "controllers.glide",
+            KnobId::ControllersModMix => "controllers.mod_mix",
+            KnobId::ControllersModRate => "controllers.mod_rate",
+            KnobId::ControllersModAmount => "controllers.mod_amount",
+            KnobId::ModPitchDepth => "controllers.pitch_mod_depth",
+            KnobId::ModFilterDepth => "controllers.filter_mod_depth",
+            KnobId::OscRange1 => "osc1.range",
+            KnobId::OscRange2 => "osc2.range",
+            KnobId::OscRange3 => "osc3.range",
+            KnobId::OscFreq1 => "osc1.freq",
+            KnobId::OscFreq2 => "osc2.freq",
+            KnobId::OscFreq3 => "osc3.freq",
+            KnobId::OscWave1 => "osc1.wave",
+            KnobId::OscWave2 => "osc2.wave",
+            KnobId::OscWave3 => "osc3.wave",
+            KnobId::OscFmDepth => "osc.fm_depth",
+            KnobId::MixerExternal => "mixer.external",
+            KnobId::MixerOsc1 => "mixer.osc1",
+            KnobId::MixerOsc2 => "mixer.osc2",
+            KnobId::MixerOsc3 => "mixer.osc3",
+            KnobId::MixerNoise => "mixer.noise",
+            KnobId::MixerRing => "mixer.ring",
+            KnobId::MixerPan1 => "mixer.osc1.pan",
+            KnobId::MixerPan2 => "mixer.osc2.pan",
+            KnobId::MixerPan3 => "mixer.osc3.pan",
+            KnobId::MixerNoisePan => "mixer.noise.pan",
+            KnobId::FilterCutoff => "filter.cutoff",
+            KnobId::FilterEmphasis => "filter.emphasis",
+            KnobId::FilterContour => "filter.contour",
+            KnobId::FilterAttack => "filter_env.attack",
+            KnobId::FilterDecay => "filter_env.decay",
+            KnobId::FilterSustain => "filter_env.sustain",
+            KnobId::LoudnessAttack => "loudness_env.attack",
+            KnobId::LoudnessDecay => "loudness_env.decay",
+            KnobId::LoudnessSustain => "loudness_env.sustain",
+            KnobId::OutputVolume => "output.main_volume",
+            KnobId::OutputPhones => "output.phones_volume",
+            KnobId::Macro1 => "macro1.amount",
+            KnobId::Macro2 => "macro2.amount",
+            KnobId::Macro3 => "macro3.amount",
+            KnobId::Macro4 => "macro4.amount",
+            KnobId::MixerNoiseColor => "mixer.noise.color",
+            KnobId::FilterDelay => "filter_env.delay",
+            KnobId::FilterHold => "filter_env.hold",
+            KnobId::FilterRelease => "filter_env.release",
+            KnobId::LoudnessDelay => "loudness_env.delay",
+            KnobId::LoudnessHold => "loudness_env.hold",
+            KnobId::LoudnessRelease => "loudness_env.release",
+            KnobId::ModFade => "controllers.mod_fade",
+            KnobId::FollowerRelease => "follower.release",
+            KnobId::FollowerCutoff => "follower.cutoff",
+            KnobId::FollowerVca => "follower.vca",
+            KnobId::ChorusRate => "chorus.rate",
+            KnobId::ChorusDepth => "chorus.depth",
+            KnobId::ChorusMix => "chorus.mix",
+            KnobId::DelayTime => "delay.time",
+            KnobId::DelayFeedback => "delay.feedback",
+            KnobId::DelayDamping => "delay.damping",
+            KnobId::DelayMix => "delay.mix",
+            KnobId::ReverbSize => "reverb.size",
+            KnobId::ReverbDamping => "reverb.damping",
+            KnobId::ReverbMix => "reverb.mix",
+        }
+    }
+
+    pub fn from_preset_key(key: &str) -> Option<KnobId> {
+        KnobId::ALL
+            .into_iter()
+            .find(|knob| knob.preset_key() == key)
+    }
+
+    pub fn default_value(self) -> f32 {
+        match self {
+            KnobId::ControllersTune => 0.5,
+            KnobId::ControllersGlide => 0.3,
+            KnobId::ControllersModMix => 0.5,
+            KnobId::ControllersModRate => 0.5,
+            KnobId::ControllersModAmount => 0.6,
+            KnobId::ModPitchDepth => 0.18,
+            KnobId::ModFilterDepth => 0.3,
+            KnobId::OscRange1 | KnobId::OscRange2 | KnobId::OscRange3 => range_value_from_index(3),
+            KnobId::OscFreq1 => 0.5,
+            KnobId::OscFreq2 => detune_to_value(0.03),
+            KnobId::OscFreq3 => detune_to_value(-0.02),
+            KnobId::OscWave1 => waveform_to_value(Waveform::Triangle, &OSC1_WAVES),
+            KnobId::OscWave2 => waveform_to_value(Waveform::Triangle, &OSC2_WAVES),
+            KnobId::OscWave3 => waveform_to_value(Waveform::Triangle, &OSC3_WAVES),
+            KnobId::OscFmDepth => 0.0,
+            KnobId::MixerExternal => 0.0,
+            KnobId::MixerOsc1 => 0.85,
+            KnobId::MixerOsc2 => 0.7,
+            KnobId::MixerOsc3 => 0.55,
+            KnobId::MixerNoise => 0.0,
+            KnobId::MixerRing => 0.5,
+            KnobId::MixerPan1 | KnobId::MixerPan2 | KnobId::MixerPan3 => 0.5,
+            KnobId::MixerNoisePan => 0.5,
+            KnobId::FilterCutoff => cutoff_to_value(2200.0),
+            KnobId::FilterEmphasis => 0.4,
+            KnobId::FilterContour => 0.5,
+            KnobId::FilterAttack | KnobId::LoudnessAttack => 0.2,
+            KnobId::FilterDecay | KnobId::LoudnessDecay => 0.5,
+            KnobId::FilterSustain | KnobId::LoudnessSustain => 0.5,
+            KnobId::OutputVolume | KnobId::OutputPhones => 0.7,
+            KnobId::Macro1 | KnobId::Macro2 | KnobId::Macro3 | KnobId::Macro4 => 0.0,
+            KnobId::MixerNoiseColor => NoiseColor::White.morph_position(),
+            KnobId::FilterDelay
+            | KnobId::FilterHold
+            | KnobId::FilterRelease
+            | KnobId::LoudnessDelay
+            | KnobId::LoudnessHold
+            | KnobId::LoudnessRelease => 0.0,
+            KnobId::ModFade => 0.0,
+            KnobId::FollowerRelease => 0.4,
+            KnobId::FollowerCutoff | KnobId::FollowerVca => 0.0,
+            KnobId::ChorusRate => 0.45,
+            KnobId::ChorusDepth | KnobId::ChorusMix => 0.5,
+            KnobId::DelayTime => 0.5,
+            KnobId::DelayFeedback | KnobId::DelayMix => 0.35,
+            KnobId::DelayDamping => 0.4,
+            KnobId::ReverbSize | KnobId::ReverbDamping => 0.5,
+            KnobId::ReverbMix => 0.25,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            KnobId::ControllersTune => "Tune",
+            KnobId::ControllersGlide => "Glide",
+            KnobId::ControllersModMix => "Mod Mix",
+            KnobId::ControllersModRate => "Mod Rate",
+            KnobId::ControllersModAmount => "Mod Amount",
+            KnobId::ModPitchDepth => "Pitch Mod Depth",
+            KnobId::ModFilterDepth => "Filter Mod Depth",
+            KnobId::OscRange1 => "Osc 1 Range",
+            KnobId::OscRange2 => "Osc 2 Range",
+            KnobId::OscRange3 => "Osc 3 Range",
+            KnobId::OscFreq1 => "Osc 1 Frequency",
+            KnobId::OscFreq2 => "Osc 2 Frequency",
+            KnobId::OscFreq3 => "Osc 3 Frequency",
+            KnobId::OscWave1 => "Osc 1 Waveform",
+            KnobId::OscWave2 => "Osc 2 Waveform",
+            KnobId::OscWave3 => "Osc 3 Waveform",
+            KnobId::OscFmDepth => "FM Depth",
+            KnobId::MixerExternal => "External Input",
+            KnobId::MixerOsc1 => "Osc 1 Level",
+            KnobId::MixerOsc2 => "Osc 2 Level",
+            KnobId::MixerOsc3 => "Osc 3 Level",
+            KnobId::MixerNoise => "Noise Level",
+            KnobId::MixerRing => "Ring Mod Level",
+            KnobId::MixerPan1 => "Osc 1 Pan",
+            KnobId::MixerPan2 => "Osc 2 Pan",
+            KnobId::MixerPan3 => "Osc 3 Pan",
+            KnobId::MixerNoisePan => "Noise Pan",
+            KnobId::FilterCutoff => "Cutoff",
+            KnobId::FilterEmphasis => "Emphasis",
+            KnobId::FilterContour => "Contour Amount",
+            KnobId::FilterAttack => "Filter Attack",
+            KnobId::FilterDecay => "Filter Decay",
+            KnobId::FilterSustain => "Filter Sustain",
+            KnobId::LoudnessAttack => "Loudness Attack",
+            KnobId::LoudnessDecay => "Loudness Decay",
+            KnobId::LoudnessSustain => "Loudness Sustain",
+            KnobId::OutputVolume => "Volume",
+            KnobId::OutputPhones => "Phones Volume",
+            KnobId::Macro1 => "Macro 1",
+            KnobId::Macro2 => "Macro 2",
+            KnobId::Macro3 => "Macro 3",
+            KnobId::Macro4 => "Macro 4",
+            KnobId::MixerNoiseColor => "Noise Color",
+            KnobId::FilterDelay => "Filter Delay",
+            KnobId::FilterHold => "Filter Hold",
+            KnobId::FilterRelease => "Filter Release",
+            KnobId::LoudnessDelay => "Loudness Delay",
+            KnobId::LoudnessHold => "Loudness Hold",
+            KnobId::LoudnessRelease => "Loudness Release",
+            KnobId::ModFade => "Mod Fade",
+            KnobId::FollowerRelease => "Follower Release",
+            KnobId::FollowerCutoff => "Follower Cutoff",
+            KnobId::FollowerVca => "Follower VCA",
+            KnobId::ChorusRate => "Chorus Rate",
+            KnobId::ChorusDepth => "Chorus Depth",
+            KnobId::ChorusMix => "Chorus Mix",
+            KnobId::DelayTime => "Delay Time",
+            KnobId::DelayFeedback => "Delay Feedback",
+            KnobId::DelayDamping => "Delay Damping",
+            KnobId::DelayMix => "Delay Mix",
+            KnobId::ReverbSize => "Reverb Size",
+            KnobId::ReverbDamping => "Reverb Damping",
+            KnobId::ReverbMix => "Reverb Mix",
+        }
+    }
+
+    /// The setting in the units the knob stands for, as the panel tooltips
+    /// and plugin hosts show it.
+    pub fn format_value(self, value: f32) -> String {
+        match self {
+            KnobId::ControllersTune => format!("{:+.0} ct", knob_to_tune(value) * 1200.0),
+            KnobId::ControllersGlide => format_seconds(knob_to_glide(value)),
+            KnobId::ControllersModMix => format!("{:.0}% noise", value * 100.0),
+            KnobId::ControllersModRate => format!("{:.2} Hz", knob_to_lfo_rate(value)),
+            KnobId::ModPitchDepth => format!("{:.1} st", value * PITCH_MOD_MAX_OCT * 12.0),
+            KnobId::ModFade if value <= 0.0 => "Off".to_string(),
+            KnobId::ModFade => format_seconds(knob_to_lfo_fade(value)),
+            KnobId::FollowerRelease => format_seconds(knob_to_follower_release(value)),
+            KnobId::FollowerCutoff => format!("+{:.1} oct", value * FOLLOWER_CUTOFF_MAX_OCT),
+            KnobId::ChorusRate => format!("{:.2} Hz", knob_to_chorus_rate(value)),
+            KnobId::DelayTime => format_seconds(knob_to_delay_time(value)),
+            KnobId::DelayDamping => format!("{:.1} kHz", knob_to_delay_damping(value) / 1_000.0),
+            KnobId::ReverbSize => format_seconds(knob_to_reverb_decay(value)),
+            KnobId::ReverbDamping => {
+                format!("{:.1} kHz", knob_to_reverb_damping(value) / 1_000.0)
+            }
+            KnobId::OscRange1 | KnobId::OscRange2 | KnobId::OscRange3 => {
+                range_setting_from_value(value).label.to_string()
+            }
+            KnobId::OscFreq1 | KnobId::OscFreq2 | KnobId::OscFreq3 => {
+                format!("{:+.2} st", knob_to_detune(value))
+            }
+            KnobId::OscWave1 => value_to_waveform(0, value).label().to_string(),
+            KnobId::OscWave2 => value_to_waveform(1, value).label().to_string(),
+            KnobId::OscWave3 => value_to_waveform(2, value).label().to_string(),
+            KnobId::MixerPan1 | KnobId::MixerPan2 | KnobId::MixerPan3 | KnobId::MixerNoisePan => {
+                let pan = knob_to_pan(value);
+                if pan.abs() < 0.005 {
+                    "C".to_string()
+                } else if pan < 0.0 {
+                    format!("L{:.0}", -pan * 100.0)
+                } else {
+                    format!("R{:.0}", pan * 100.0)
+                }
+            }
+            KnobId::MixerNoiseColor => NoiseColor::describe_position(value),
+            KnobId::FilterCutoff => {
+                let hz = knob_to_cutoff(value);
+                if hz < 1_000.0 {
+                    format!("{hz:.0} Hz")
+                } else {
+                    format!("{:.1} kHz", hz / 1_000.0)
+                }
+            }
+            KnobId::FilterAttack
+            | KnobId::FilterDecay
+            | KnobId::LoudnessAttack
+            | KnobId::LoudnessDecay
+            | KnobId::FilterDelay
+            | KnobId::FilterHold
+            | KnobId::LoudnessDelay
+            | KnobId::LoudnessHold => format_seconds(knob_to_env_seconds(self, value)),
+            KnobId::FilterRelease | KnobId::LoudnessRelease if value <= 0.0 => {
+                "Decay switch".to_string()
+            }
+            KnobId::FilterRelease | KnobId::LoudnessRelease => {
+                format_seconds(knob_to_env_seconds(self, value))
+            }
+            _ => format!("{:.0}%", value * 100.0),
+        }
+    }
+
+    // Envelope stages beyond the panel's ADS knobs, which are off at zero.
+    fn is_optional_stage(self) -> bool {
+        matches!(
+            self,
+            KnobId::FilterDelay
+                | KnobId::FilterHold
+                | KnobId::FilterRelease
+                | KnobId::LoudnessDelay
+                | KnobId::LoudnessHold
+                | KnobId::LoudnessRelease
+        )
+    }
+
+    /// Readouts at the two ends of the knob's travel.
+    pub fn range_text(self) -> (String, String) {
+        (self.format_value(0.0), self.format_value(1.0))
+    }
+}
+
+fn format_seconds(seconds: f32) -> String {
+    if seconds < 0.01 {
+        format!("{:.1} ms", seconds * 1_000.0)
+    } else if seconds < 1.0 {
+        format!("{:.0} ms", seconds * 1_000.0)
+    } else {
+        format!("{seconds:.2} s")
+    }
+}
+
+pub fn knob_to_tune(value: f32) -> f32 {
+    (value - 0.5) * TUNE_RANGE_OCT
+}
+
+pub fn knob_to_glide(value: f32) -> f32 {
+    GLIDE_MIN_SEC + value * (GLIDE_MAX_SEC - GLIDE_MIN_SEC)
+}
+
+pub fn knob_to_lfo_rate(value: f32) -> f32 {
+    LFO_RATE_MIN + value * (LFO_RATE_MAX - LFO_RATE_MIN)
+}
+
+pub fn knob_to_follower_release(value: f32) -> f32 {
+    knob_to_env_time(value, FOLLOWER_RELEASE_MIN, FOLLOWER_RELEASE_MAX)
+}
+
+/// Chorus LFO rate in Hz, spread evenly per octave across the knob.
+pub fn knob_to_chorus_rate(value: f32) -> f32 {
+    CHORUS_RATE_MIN * (CHORUS_RATE_MAX / CHORUS_RATE_MIN).powf(value.clamp(0.0, 1.0))
+}
+
+/// Free-running delay time in seconds, spread evenly per octave.
+pub fn knob_to_delay_time(value: f32) -> f32 {
+    DELAY_TIME_MIN * (DELAY_TIME_MAX / DELAY_TIME_MIN).powf(value.clamp(0.0, 1.0))
+}
+
+/// Corner of the delay loop's low-pass in Hz, falling as DAMPING goes up.
+pub fn knob_to_delay_damping(value: f32) -> f32 {
+    DELAY_DAMPING_OPEN_HZ
+        * (DELAY_DAMPING_CLOSED_HZ / DELAY_DAMPING_OPEN_HZ).powf(value.clamp(0.0, 1.0))
+}
+
+/// Reverb decay time in seconds, spread evenly per octave.
+pub fn knob_to_reverb_decay(value: f32) -> f32 {
+    REVERB_DECAY_MIN * (REVERB_DECAY_MAX / REVERB_DECAY_MIN).powf(value.clamp(0.0, 1.0))
+}
+
+/// Corner of the reverb lines' low-pass in Hz, falling as DAMPING goes up.
+pub fn knob_to_reverb_damping(value: f32) -> f32 {
+    REVERB_DAMPING_OPEN_HZ
+        * (REVERB_DAMPING_CLOSED_HZ / REVERB_DAMPING_OPEN_HZ).powf(value.clamp(0.0, 1.0))
+}
+
+/// Seconds the modulation takes to swell in after a new note.
+pub fn knob_to_lfo_fade(value: f32) -> f32 {
+    value.clamp(0.0, 1.0) * LFO_FADE_MAX
+}
+
+/// Envelope stage time in seconds; zero for any other knob. Delay, hold
+/// and release read zero at the bottom of their travel, where the first two
+/// are skipped and release follows the panel's Decay switch.
+pub fn knob_to_env_seconds(knob: KnobId, value: f32) -> f32 {
+    match knob {
+        _ if value <= 0.0 && knob.is_optional_stage() => 0.0,
+        KnobId::FilterDelay | KnobId::FilterHold | KnobId::LoudnessDelay | KnobId::LoudnessHold => {
+            knob_to_env_time(value, ENV_STAGE_MIN, ENV_STAGE_MAX)
+        }
+        KnobId::FilterRelease | KnobId::LoudnessRelease => {
+            knob_to_env_time(value, ENV_RELEASE_MIN, ENV_RELEASE_MAX)
+        }
+        KnobId::FilterAttack => knob_to_env_time(value, FILTER_ATTACK_MIN, FILTER_ATTACK_MAX),
+        KnobId::FilterDecay => knob_to_env_time(value, FILTER_DECAY_MIN, FILTER_DECAY_MAX),
+        KnobId::LoudnessAttack => knob_to_env_time(value, LOUD_ATTACK_MIN, LOUD_ATTACK_MAX),
+        KnobId::LoudnessDecay => knob_to_env_time(value, LOUD_DECAY_MIN, LOUD_DECAY_MAX),
+        _ => 0.0,
+    }
+}
+
+pub fn knob_to_pan(value: f32) -> f32 {
+    value * 2.0 - 1.0
+}
+
+pub fn knob_to_detune(value: f32) -> f32 {
+    (value * 2.0 - 1.0) * DETUNE_RANGE
+}
+
+/// Detune in octaves for a FREQ knob snapped to whole semitones, which
+/// spans `SNAP_RANGE_SEMITONES` either way so intervals are easy to find.
+pub fn knob_to_snapped_detune(value: f32) -> f32 {
+    ((value * 2.0 - 1.0) * SNAP_RANGE_SEMITONES).round() / 12.0
+}
+
+pub fn detune_to_value(detune: f32) -> f32 {
+    ((detune / DETUNE_RANGE) + 1.0) * 0.5
+}
+
+pub fn knob_to_cutoff(value: f32) -> f32 {
+    FILTER_MIN_HZ + value * (FILTER_MAX_HZ - FILTER_MIN_HZ)
+}
+
+pub fn cutoff_to_value(hz: f32) -> f32 {
+    (hz - FILTER_MIN_HZ) / (FILTER_MAX_HZ - FILTER_MIN_HZ)
+}
+
+pub fn value_to_waveform(osc_index: usize, value: f32) -> Waveform {
+    let waves = match osc_index {
+        0 => &OSC1_WAVES,
+        1 => &OSC2_WAVES,
+        _ => &OSC3_WAVES,
+    };
+    let mut index = (value.clamp(0.0, 0.999) * waves.len() as f32) as usize;
+    if index >= waves.len() {
+        index = waves.len() - 1;
+    }
+    waves[index]
+}
+
+pub fn waveform_to_value(waveform: Waveform, waves: &[Waveform]) -> f32 {
+    if let Some(index) = waves.iter().position(|w| *w == waveform) {
+        (index as f32 + 0.5) / waves.len() as f32
+    } else {
+        0.5
+    }
+}
+
+pub fn range_setting_from_value(value: f32) -> RangeSetting {
+    let mut index = (value.clamp(0.0, 0.999) * OSC_RANGE_SETTINGS.len() as f32) as usize;
+    if index >= OSC_RANGE_SETTINGS.len() {
+        index = OSC_RANGE_SETTINGS.len() - 1;
+    }
+    OSC_RANGE_SETTINGS[index]
+}
+
+pub fn range_value_from_index(index: usize) -> f32 {
+    let max_index = OSC_RANGE_SETTINGS.len().saturating_sub(1).max(1);
+    (index.min(max_index)) as f32 / max_index as f32
+}
@@ -0,0 +1,243 @@
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::{
+    filter::{FilterSlope, FilterType},
+    noise::NoiseColor,
+    params::{
+        DETUNE_RANGE, KnobId, OSC_RANGE_SETTINGS, OSC1_WAVES, OSC2_WAVES, OSC3_WAVES,
+        detune_to_value, range_value_from_index, waveform_to_value,
+    },
+    preset::Preset,
+    vco::Waveform,
+};
+
+pub const PATCH_SHEET_EXTENSION: &str = "chart";
+
+const DIAL_MAX: f32 = 10.0;
+
+/// What a line on the chart sets.
+#[derive(Clone, Copy)]
+enum Setting {
+    /// A 0-10 dial onto a knob's preset key.
+    Dial(&'static str),
+    Switch(&'static str),
+    /// Footage (LO, 32', 16' ... 2') of an oscillator, counted from 1.
+    Range(usize),
+    /// Frequency dial in semitones, -7 to +7 on the original.
+    Frequency(usize),
+    Waveform(usize),
+    NoiseColor,
+    Glide,
+}
+
+const SETTINGS: [(&str, Setting); 37] = [
+    ("tune", Setting::Dial("controllers.tune")),
+    ("glide", Setting::Dial("controllers.glide")),
+    ("glide on", Setting::Glide),
+    ("mod mix", Setting::Dial("controllers.mod_mix")),
+    ("osc modulation", Setting::Switch("switch.osc_modulation")),
+    (
+        "filter modulation",
+        Setting::Switch("switch.mod_target_filter"),
+    ),
+    ("osc 3 control", Setting::Switch("switch.osc3_control")),
+    ("osc 1 range", Setting::Range(1)),
+    ("osc 2 range", Setting::Range(2)),
+    ("osc 3 range", Setting::Range(3)),
+    ("osc 2 frequency", Setting::Frequency(2)),
+    ("osc 3 frequency", Setting::Frequency(3)),
+    ("osc 1 waveform", Setting::Waveform(1)),
+    ("osc 2 waveform", Setting::Waveform(2)),
+    ("osc 3 waveform", Setting::Waveform(3)),
+    ("osc 1 volume", Setting::Dial("mixer.osc1")),
+    ("osc 2 volume", Setting::Dial("mixer.osc2")),
+    ("osc 3 volume", Setting::Dial("mixer.osc3")),
+    ("osc 1 on", Setting::Switch("mixer.osc1.on")),
+    ("osc 2 on", Setting::Switch("mixer.osc2.on")),
+    ("osc 3 on", Setting::Switch("mixer.osc3.on")),
+    ("external volume", Setting::Dial("mixer.external")),
+    ("external on", Setting::Switch("mixer.external.on")),
+    ("noise volume", Setting::Dial("mixer.noise")),
+    ("noise on", Setting::Switch("mixer.noise.on")),
+    ("noise color", Setting::NoiseColor),
+    ("cutoff", Setting::Dial("filter.cutoff")),
+    ("emphasis", Setting::Dial("filter.emphasis")),
+    ("contour amount", Setting::Dial("filter.contour")),
+    ("filter attack", Setting::Dial("filter_env.attack")),
+    ("filter decay", Setting::Dial("filter_env.decay")),
+    ("filter sustain", Setting::Dial("filter_env.sustain")),
+    ("loudness attack", Setting::Dial("loudness_env.attack")),
+    ("loudness decay", Setting::Dial("loudness_env.decay")),
+    ("loudness sustain", Setting::Dial("loudness_env.sustain")),
+    ("decay", Setting::Switch("switch.decay")),
+    ("volume", Setting::Dial("output.main_volume")),
+];
+
+/// Reads a patch chart as written on the original Minimoog sheets: one
+/// `control = setting` line per panel control, with dials as 0-10
+/// positions, switches as on/off, ranges as footages and waveforms by name.
+/// Anything the chart leaves out, and every feature the original lacked,
+/// starts from a plain classic setup so the patch sounds the same whatever
+/// was loaded before.
+pub fn parse(text: &str) -> Result<Preset> {
+    let mut preset = classic_base();
+    for (line_no, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let context = || format!("line {}", line_no + 1);
+        let (control, setting) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `control = setting`"))
+            .with_context(context)?;
+        let control = control.split_whitespace().collect::<Vec<_>>().join(" ");
+        let control = control.to_ascii_lowercase();
+        // A name may contain `#` itself, so it takes the rest of its line;
+        // no other setting can, so there one starts a trailing comment.
+        if control == "name" {
+            preset.name = setting.trim().to_string();
+            continue;
+        }
+        let setting = setting.split('#').next().unwrap_or("").trim();
+        let (_, kind) = SETTINGS
+            .iter()
+            .find(|(name, _)| *name == control)
+            .ok_or_else(|| anyhow!("unknown control `{control}`"))
+            .with_context(context)?;
+        apply(&mut preset, *kind, setting)
+            .with_context(|| format!("{control} = {setting}"))
+            .with_context(context)?;
+    }
+    Ok(preset)
+}
+
+fn apply(preset: &mut Preset, kind: Setting, setting: &str) -> Result<()> {
+    match kind {
+        Setting::Dial(key) => preset.set(key, dial(setting)?),
+        Setting::Switch(key) => preset.set_flag(key, switch(setting)?),
+        Setting::Glide => preset.set("switch.glide", if switch(setting)? { 1.0 } else { 0.0 }),
+        Setting::Range(osc) => {
+            let footage = setting.trim_end_matches('\'');
+            let index = OSC_RANGE_SETTINGS
+                .iter()
+                .position(|range| {
+                    range
+                        .label
+                        .trim_end_matches('\'')
+                        .eq_ignore_ascii_case(footage)
+                })
+                .ok_or_else(|| anyhow!("use LO, 32, 16, 8, 4 or 2"))?;
+            preset.set(&format!("osc{osc}.range"), range_value_from_index(index));
+        }
+        Setting::Frequency(osc) => {
+            let semitones: f32 = setting
+                .parse()
+                .map_err(|_| anyhow!("expected semitones from -7 to +7"))?;
+            let semitones = semitones.clamp(-DETUNE_RANGE, DETUNE_RANGE);
+            preset.set(&format!("osc{osc}.freq"), detune_to_value(semitones));
+        }
+        Setting::Waveform(osc) => {
+            let waves: &[Waveform] = match osc {
+                1 => &OSC1_WAVES,
+                2 => &OSC2_WAVES,
+                _ => &OSC3_WAVES,
+            };
+            let wave = waveform(setting)?;
+            if !waves.contains(&wave) {
+                bail!("oscillator {osc} has no {} wave", wave.label());
+            }
+            preset.set(&format!("osc{osc}.wave"), waveform_to_value(wave, waves));
+        }
+        Setting::NoiseColor => {
+            let index = NoiseColor::VALUES
+                .iter()
+                .position(|color| color.label().eq_ignore_ascii_case(setting))
+                .ok_or_else(|| {
+                    let labels: Vec<_> = NoiseColor::VALUES
+                        .iter()
+                        .map(|color| color.label().to_ascii_lowercase())
+                        .collect();
+                    let (last, rest) = labels.split_last().expect("noise colors");
+                    anyhow!("use {} or {last}", rest.join(", "))
+                })?;
+            preset.set("mixer.noise_color", index as f32);
+            preset.set(
+                KnobId::MixerNoiseColor.preset_key(),
+                NoiseColor::VALUES[index].morph_position(),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn dial(setting: &str) -> Result<f32> {
+    let position: f32 = setting
+        .parse()
+        .map_err(|_| anyhow!("expected a dial position from 0 to 10"))?;
+    if !(0.0..=DIAL_MAX).contains(&position) {
+        bail!("dial positions run from 0 to 10");
+    }
+    Ok(position / DIAL_MAX)
+}
+
+fn switch(setting: &str) -> Result<bool> {
+    match setting.to_ascii_lowercase().as_str() {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => bail!("expected on or off"),
+    }
+}
+
+fn waveform(setting: &str) -> Result<Waveform> {
+    let name = setting.to_ascii_lowercase().replace(['-', '_', '/'], " ");
+    Ok(match name.as_str() {
+        "triangle" => Waveform::Triangle,
+        "tri saw" | "sharktooth" => Waveform::TriangleSaw,
+        "saw" | "sawtooth" => Waveform::Saw,
+        "reverse saw" => Waveform::ReverseSaw,
+        "square" => Waveform::PulseSquare,
+        "wide pulse" | "wide rectangle" => Waveform::PulseWide,
+        "narrow pulse" | "narrow rectangle" => Waveform::PulseNarrow,
+        _ => {
+            bail!("use triangle, sharktooth, saw, reverse saw, square, wide pulse or narrow pulse")
+        }
+    })
+}
+
+// Every knob at its default, then the Model D signal path: OSC 3 and noise
+// blended by MOD MIX as the modulation source, a 24 dB low-pass and none of
+// the extras switched in.
+fn classic_base() -> Preset {
+    let mut preset = Preset::new("Patch chart");
+    for knob in KnobId::ALL {
+        preset.set(knob.preset_key(), knob.default_value());
+    }
+    let slope = FilterSlope::VALUES
+        .iter()
+        .position(|slope| *slope == FilterSlope::Db24)
+        .unwrap_or(0);
+    let kind = FilterType::VALUES
+        .iter()
+        .position(|kind| *kind == FilterType::LowPass)
+        .unwrap_or(0);
+    preset.set("filter.slope", slope as f32);
+    preset.set("filter.type", kind as f32);
+    preset.set_flag("switch.osc3_lfo", true);
+    preset.set_flag("switch.lfo_sync", false);
+    preset.set_flag("switch.lfo_key_sync", false);
+    preset.set_flag("osc.freq_snap", false);
+    preset.set_flag("switch.mod_source_noise", true);
+    preset.set("switch.glide", 0.0);
+    preset.set_flag("mixer.ring.on", false);
+    preset.set_flag("mixer.feedback_patch", false);
+    preset.set_flag("mixer.external.on", false);
+    preset.set_flag("output.chorus", false);
+    preset.set_flag("output.delay", false);
+    preset.set_flag("output.reverb", false);
+    for osc in 1..=3 {
+        preset.set_flag(&format!("osc{osc}.sync"), false);
+        preset.set_flag(&format!("osc{osc}.wavetable"), false);
+    }
+    preset
+}
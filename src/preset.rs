@@ -0,0 +1,166 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::patchsheet::{self, PATCH_SHEET_EXTENSION};
+
+pub const PRESET_DIR: &str = "presets";
+pub const PRESET_EXTENSION: &str = "mrp";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    values: BTreeMap<String, f32>,
+}
+
+impl Preset {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            values: BTreeMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: f32) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    pub fn set_flag(&mut self, key: &str, flag: bool) {
+        self.set(key, if flag { 1.0 } else { 0.0 });
+    }
+
+    pub fn get(&self, key: &str) -> Option<f32> {
+        self.values.get(key).copied()
+    }
+
+    pub fn get_flag(&self, key: &str) -> Option<bool> {
+        self.get(key).map(|value| value >= 0.5)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.values
+            .iter()
+            .map(|(key, value)| (key.as_str(), *value))
+    }
+
+    /// One `key = value` line each; control characters in the name are
+    /// dropped so it stays on its own line.
+    pub fn serialize(&self) -> String {
+        let name: String = self.name.chars().filter(|c| !c.is_control()).collect();
+        let mut text = format!("name = {name}\n");
+        for (key, value) in &self.values {
+            text.push_str(&format!("{key} = {value}\n"));
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut preset = Preset::default();
+        for (line_no, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("line {}: expected `key = value`", line_no + 1))?;
+            let key = key.trim();
+            let value = value.trim();
+            if key == "name" {
+                preset.name = value.to_string();
+                continue;
+            }
+            let number = value
+                .parse::<f32>()
+                .with_context(|| format!("line {}: invalid value for {key}", line_no + 1))?;
+            if !number.is_finite() {
+                return Err(anyhow!(
+                    "line {}: {key} is not a finite number",
+                    line_no + 1
+                ));
+            }
+            preset.set(key, number);
+        }
+        Ok(preset)
+    }
+
+    /// Reads a `.mrp` preset, or imports a Minimoog patch chart.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading preset {}", path.display()))?;
+        let mut preset = if has_extension(path, PATCH_SHEET_EXTENSION) {
+            patchsheet::parse(&text)
+                .with_context(|| format!("importing patch chart {}", path.display()))?
+        } else {
+            Self::parse(&text)?
+        };
+        if preset.name.is_empty() {
+            preset.name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+        Ok(preset)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        let file_name = sanitize_file_name(&self.name);
+        if file_name.is_empty() {
+            return Err(anyhow!("preset name is empty"));
+        }
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        let path = dir.join(format!("{file_name}.{PRESET_EXTENSION}"));
+        fs::write(&path, self.serialize())
+            .with_context(|| format!("writing preset {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+pub fn list_presets(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            has_extension(path, PRESET_EXTENSION) || has_extension(path, PATCH_SHEET_EXTENSION)
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Where the preset called `name` lives: its `.mrp` file, or a patch chart
+/// of that name when there is no saved preset.
+pub fn preset_path(dir: &Path, name: &str) -> PathBuf {
+    let file_name = sanitize_file_name(name);
+    let preset = dir.join(format!("{file_name}.{PRESET_EXTENSION}"));
+    let chart = dir.join(format!("{file_name}.{PATCH_SHEET_EXTENSION}"));
+    if !preset.exists() && chart.exists() {
+        chart
+    } else {
+        preset
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+pub fn sanitize_file_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
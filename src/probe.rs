@@ -0,0 +1,99 @@
+use rtrb::{Consumer, Producer, RingBuffer};
+
+// Room for several UI frames of samples at high output rates.
+const TAP_CAPACITY: usize = 16_384;
+
+pub type ProbeTap = Producer<f32>;
+
+/// Where in the signal chain a probe listens. Stereo points are probed as
+/// their mid (L+R)/2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProbePoint {
+    /// One oscillator before the mixer.
+    Oscillator(usize),
+    PostMixer,
+    /// Filter output ahead of the VCA.
+    PostFilter,
+    PostVca,
+    /// The soft-clipped master output.
+    Output,
+}
+
+impl ProbePoint {
+    /// Steps down the signal chain, wrapping from the output back to the
+    /// first oscillator.
+    pub fn next(self, oscillators: usize) -> Self {
+        match self {
+            ProbePoint::Oscillator(index) if index + 1 < oscillators => {
+                ProbePoint::Oscillator(index + 1)
+            }
+            ProbePoint::Oscillator(_) => ProbePoint::PostMixer,
+            ProbePoint::PostMixer => ProbePoint::PostFilter,
+            ProbePoint::PostFilter => ProbePoint::PostVca,
+            ProbePoint::PostVca => ProbePoint::Output,
+            ProbePoint::Output => ProbePoint::Oscillator(0),
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            ProbePoint::Oscillator(index) => format!("OSC{}", index + 1),
+            ProbePoint::PostMixer => "MIX".to_string(),
+            ProbePoint::PostFilter => "VCF".to_string(),
+            ProbePoint::PostVca => "VCA".to_string(),
+            ProbePoint::Output => "OUT".to_string(),
+        }
+    }
+}
+
+/// UI side of a probe tap: keeps the newest `size` samples the pipeline
+/// pushed, one per output frame.
+pub struct ProbeBuffer {
+    consumer: Consumer<f32>,
+    history: Vec<f32>,
+    cursor: usize,
+    filled: bool,
+}
+
+impl ProbeBuffer {
+    pub fn new(size: usize) -> (Self, ProbeTap) {
+        let (producer, consumer) = RingBuffer::new(TAP_CAPACITY);
+        let buffer = Self {
+            consumer,
+            history: vec![0.0; size.max(1)],
+            cursor: 0,
+            filled: false,
+        };
+        (buffer, producer)
+    }
+
+    /// Drains the tap; call every frame so it never fills up.
+    pub fn drain(&mut self) {
+        while let Ok(sample) = self.consumer.pop() {
+            self.history[self.cursor] = sample;
+            self.cursor = (self.cursor + 1) % self.history.len();
+            self.filled |= self.cursor == 0;
+        }
+    }
+
+    /// Everything captured so far, oldest first.
+    pub fn snapshot(&self) -> Vec<f32> {
+        if !self.filled {
+            return self.history[..self.cursor].to_vec();
+        }
+        let mut data = Vec::with_capacity(self.history.len());
+        data.extend_from_slice(&self.history[self.cursor..]);
+        data.extend_from_slice(&self.history[..self.cursor]);
+        data
+    }
+
+    /// Fills `out` with the newest samples, oldest first; slots not yet
+    /// captured read as silence.
+    pub fn copy_latest(&self, out: &mut [f32]) {
+        let len = self.history.len();
+        let start = self.cursor + len - out.len().min(len);
+        for (index, slot) in out.iter_mut().enumerate() {
+            *slot = self.history[(start + index) % len];
+        }
+    }
+}
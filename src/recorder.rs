@@ -0,0 +1,111 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use rtrb::{Consumer, Producer, RingBuffer};
+
+pub const RECORDING_DIR: &str = "recordings";
+const QUEUE_SECONDS: f32 = 2.0;
+
+pub type RecordTap = Producer<f32>;
+
+pub struct Recording {
+    path: PathBuf,
+    started: Instant,
+    stop: Arc<AtomicBool>,
+    writer: Option<JoinHandle<Result<()>>>,
+}
+
+impl Recording {
+    pub fn start(dir: &Path, sample_rate: f32) -> Result<(Self, RecordTap)> {
+        let path = timestamped_path(dir, "miniroog")?;
+        let writer = WavWriter::create(&path, wav_spec(sample_rate))
+            .with_context(|| format!("creating {}", path.display()))?;
+        let capacity = (sample_rate * QUEUE_SECONDS) as usize * 2;
+        let (producer, consumer) = RingBuffer::new(capacity);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let handle = thread::spawn(move || drain_to_file(writer, consumer, thread_stop));
+        Ok((
+            Self {
+                path,
+                started: Instant::now(),
+                stop,
+                writer: Some(handle),
+            },
+            producer,
+        ))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn finish(mut self) -> Result<PathBuf> {
+        self.stop.store(true, Ordering::Release);
+        if let Some(handle) = self.writer.take() {
+            handle
+                .join()
+                .map_err(|_| anyhow::anyhow!("recorder thread panicked"))??;
+        }
+        Ok(self.path.clone())
+    }
+}
+
+pub fn write_bounce(dir: &Path, sample_rate: f32, samples: &[f32]) -> Result<PathBuf> {
+    let path = timestamped_path(dir, "bounce")?;
+    let mut writer = WavWriter::create(&path, wav_spec(sample_rate))
+        .with_context(|| format!("creating {}", path.display()))?;
+    for sample in samples {
+        writer.write_sample(*sample)?;
+    }
+    writer.finalize()?;
+    Ok(path)
+}
+
+fn timestamped_path(dir: &Path, prefix: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    Ok(dir.join(format!("{prefix}-{stamp}.wav")))
+}
+
+fn wav_spec(sample_rate: f32) -> WavSpec {
+    WavSpec {
+        channels: 2,
+        sample_rate: sample_rate as u32,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    }
+}
+
+fn drain_to_file(
+    mut writer: WavWriter<std::io::BufWriter<fs::File>>,
+    mut consumer: Consumer<f32>,
+    stop: Arc<AtomicBool>,
+) -> Result<()> {
+    loop {
+        let finished = stop.load(Ordering::Acquire);
+        while let Ok(sample) = consumer.pop() {
+            writer.write_sample(sample)?;
+        }
+        if finished {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    writer.finalize()?;
+    Ok(())
+}
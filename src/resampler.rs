@@ -0,0 +1,135 @@
+use std::f32::consts::PI;
+
+// Kernel length in input frames, and the number of fractional positions
+// tabulated between two of them; positions in between are interpolated.
+const TAPS: usize = 64;
+const PHASES: usize = 256;
+// Passband edge as a fraction of the lower Nyquist, leaving the rest of the
+// band for the kernel's transition.
+const CUTOFF: f32 = 0.92;
+
+/// Stereo polyphase windowed-sinc resampler between two fixed rates, for
+/// running the engine at one rate and the device at another. Input and
+/// output are interleaved stereo; `input_frames` says how much input the
+/// next call needs, so a block can be rendered to fit exactly.
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    // The rate ratio reduced to lowest terms, so stepping stays exact.
+    step: u64,
+    period: u64,
+    // (PHASES + 1) rows of TAPS coefficients.
+    table: Vec<f32>,
+    // The last TAPS input frames, stored twice so a window never wraps.
+    history: [[f32; 2]; TAPS * 2],
+    cursor: usize,
+    // Position of the next output frame past the newest input, in units of
+    // 1 / period; each whole period is an input frame still to take in.
+    phase: u64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let input_rate = input_rate.max(1);
+        let output_rate = output_rate.max(1);
+        let divisor = gcd(input_rate, output_rate);
+        let cutoff = CUTOFF * (output_rate as f32 / input_rate as f32).min(1.0);
+        let mut table = vec![0.0; (PHASES + 1) * TAPS];
+        for (phase, row) in table.chunks_exact_mut(TAPS).enumerate() {
+            let fraction = phase as f32 / PHASES as f32;
+            for (tap, coefficient) in row.iter_mut().enumerate() {
+                let offset = tap as f32 - (TAPS / 2) as f32 + 1.0 - fraction;
+                *coefficient = kernel(offset, cutoff);
+            }
+            // Unity gain at DC for every position, or the output would
+            // carry a ripple at the beat between the two rates.
+            let sum: f32 = row.iter().sum();
+            for coefficient in row.iter_mut() {
+                *coefficient /= sum;
+            }
+        }
+        Self {
+            input_rate,
+            output_rate,
+            step: (input_rate / divisor) as u64,
+            period: (output_rate / divisor) as u64,
+            table,
+            history: [[0.0; 2]; TAPS * 2],
+            cursor: 0,
+            phase: 0,
+        }
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    /// Delay the kernel adds, in seconds.
+    pub fn latency(&self) -> f32 {
+        (TAPS / 2) as f32 / self.input_rate as f32
+    }
+
+    /// Input frames the next `process` call takes to make `output_frames`.
+    pub fn input_frames(&self, output_frames: usize) -> usize {
+        if output_frames == 0 {
+            return 0;
+        }
+        ((self.phase + (output_frames as u64 - 1) * self.step) / self.period) as usize
+    }
+
+    /// Fills `output` from exactly `input_frames(output.len() / 2)` frames
+    /// of `input`; missing input reads as silence.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        let mut frames = input.chunks_exact(2);
+        for out in output.chunks_exact_mut(2) {
+            while self.phase >= self.period {
+                let frame = frames.next().map_or([0.0; 2], |frame| [frame[0], frame[1]]);
+                self.push(frame);
+                self.phase -= self.period;
+            }
+            let position = self.phase as f32 / self.period as f32 * PHASES as f32;
+            let row = (position as usize).min(PHASES - 1);
+            let blend = position - row as f32;
+            let (near, far) = self.table[row * TAPS..(row + 2) * TAPS].split_at(TAPS);
+            let window = &self.history[self.cursor..self.cursor + TAPS];
+            let mut sum = [0.0; 2];
+            for ((frame, a), b) in window.iter().zip(near).zip(far) {
+                let coefficient = a + (b - a) * blend;
+                sum[0] += frame[0] * coefficient;
+                sum[1] += frame[1] * coefficient;
+            }
+            out.copy_from_slice(&sum);
+            self.phase += self.step;
+        }
+    }
+
+    fn push(&mut self, frame: [f32; 2]) {
+        self.history[self.cursor] = frame;
+        self.history[self.cursor + TAPS] = frame;
+        self.cursor = (self.cursor + 1) % TAPS;
+    }
+}
+
+// Blackman-windowed sinc with its first zero at 1 / `cutoff` frames.
+fn kernel(offset: f32, cutoff: f32) -> f32 {
+    let half = (TAPS / 2) as f32;
+    if offset.abs() >= half {
+        return 0.0;
+    }
+    let x = PI * cutoff * offset;
+    let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+    let u = PI * offset / half;
+    let window = 0.42 + 0.5 * u.cos() + 0.08 * (2.0 * u).cos();
+    cutoff * sinc * window
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
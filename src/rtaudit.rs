@@ -0,0 +1,81 @@
+#[cfg(feature = "rt-audit")]
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+#[cfg(feature = "rt-audit")]
+#[global_allocator]
+static AUDIT_ALLOCATOR: assert_no_alloc::AllocDisabler = assert_no_alloc::AllocDisabler;
+
+#[cfg(feature = "rt-audit")]
+static ALLOCATIONS: AtomicU32 = AtomicU32::new(0);
+#[cfg(feature = "rt-audit")]
+static BLOCKING_CALLS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(feature = "rt-audit")]
+thread_local! {
+    static IN_CALLBACK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// What the audit has caught since the process started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Violations {
+    pub allocations: u32,
+    pub blocking_calls: u32,
+}
+
+impl Violations {
+    pub fn is_clean(&self) -> bool {
+        self.allocations == 0 && self.blocking_calls == 0
+    }
+}
+
+/// Runs one audio callback under the audit: with the `rt-audit` feature,
+/// every allocation, free or blocking call made inside is counted, and
+/// `tests/realtime.rs` fails if any turn up. Without it this is a plain call.
+#[cfg(feature = "rt-audit")]
+pub fn realtime<R>(callback: impl FnOnce() -> R) -> R {
+    let before = assert_no_alloc::violation_count();
+    IN_CALLBACK.with(|flag| flag.set(true));
+    let result = assert_no_alloc::assert_no_alloc(callback);
+    IN_CALLBACK.with(|flag| flag.set(false));
+    let caught = assert_no_alloc::violation_count().wrapping_sub(before);
+    ALLOCATIONS.fetch_add(caught, Ordering::Relaxed);
+    result
+}
+
+#[cfg(not(feature = "rt-audit"))]
+pub fn realtime<R>(callback: impl FnOnce() -> R) -> R {
+    callback()
+}
+
+/// Lets a known one-off through, such as rebuilding tables for a new
+/// device rate on a stream's first block.
+#[cfg(feature = "rt-audit")]
+pub fn permit<R>(work: impl FnOnce() -> R) -> R {
+    assert_no_alloc::permit_alloc(work)
+}
+
+#[cfg(not(feature = "rt-audit"))]
+pub fn permit<R>(work: impl FnOnce() -> R) -> R {
+    work()
+}
+
+/// Marks a call that can wait on another thread, such as taking a mutex.
+pub fn may_block() {
+    #[cfg(feature = "rt-audit")]
+    if IN_CALLBACK.with(Cell::get) {
+        BLOCKING_CALLS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn violations() -> Violations {
+    #[cfg(feature = "rt-audit")]
+    return Violations {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        blocking_calls: BLOCKING_CALLS.load(Ordering::Relaxed),
+    };
+    #[cfg(not(feature = "rt-audit"))]
+    Violations::default()
+}
@@ -0,0 +1,178 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+pub const EXPORT_DIR: &str = "exports";
+
+const IMAGE_WIDTH: usize = 1024;
+const IMAGE_HEIGHT: usize = 512;
+const IMAGE_MIN_DB: f32 = -80.0;
+const IMAGE_MAX_DB: f32 = 0.0;
+const BACKGROUND: [u8; 4] = [5, 5, 5, 255];
+const GRID: [u8; 4] = [60, 30, 8, 255];
+const TRACE: [u8; 4] = [250, 168, 31, 255];
+
+/// Writes the scope buffer and spectrum bins as two CSV files sharing one
+/// timestamp, plus a PNG of both plots when `with_image` is set. Spectrum
+/// bins are spaced as `SpectrumAnalyzer` produces them, Nyquist / bins.
+pub fn export_scope(
+    dir: &Path,
+    sample_rate: f32,
+    waveform: &[f32],
+    spectrum: &[f32],
+    with_image: bool,
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let bin_hz = sample_rate * 0.5 / spectrum.len().max(1) as f32;
+
+    let waveform_path = dir.join(format!("scope-{stamp}.csv"));
+    write_csv(&waveform_path, "sample,time_s,value", |out| {
+        for (index, value) in waveform.iter().enumerate() {
+            writeln!(out, "{index},{},{value}", index as f32 / sample_rate)?;
+        }
+        Ok(())
+    })?;
+
+    let spectrum_path = dir.join(format!("spectrum-{stamp}.csv"));
+    write_csv(&spectrum_path, "bin,frequency_hz,magnitude,db", |out| {
+        for (index, magnitude) in spectrum.iter().enumerate() {
+            let db = 20.0 * magnitude.max(1e-9).log10();
+            writeln!(out, "{index},{},{magnitude},{db}", index as f32 * bin_hz)?;
+        }
+        Ok(())
+    })?;
+
+    let mut paths = vec![waveform_path, spectrum_path];
+    if with_image {
+        let image_path = dir.join(format!("scope-{stamp}.png"));
+        write_png(&image_path, &render_plots(waveform, spectrum))?;
+        paths.push(image_path);
+    }
+    Ok(paths)
+}
+
+fn write_csv(
+    path: &Path,
+    header: &str,
+    rows: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    writeln!(out, "{header}")
+        .and_then(|()| rows(&mut out))
+        .and_then(|()| out.flush())
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+fn write_png(path: &Path, pixels: &[u8]) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        IMAGE_WIDTH as u32,
+        IMAGE_HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+/// Waveform on top, spectrum below on a linear frequency axis and a fixed
+/// dB scale, so images from different runs compare directly.
+fn render_plots(waveform: &[f32], spectrum: &[f32]) -> Vec<u8> {
+    let mut canvas = Canvas::new();
+    let half = IMAGE_HEIGHT / 2;
+    let scope_mid = (half / 2) as f32;
+    canvas.hline(scope_mid as usize, GRID);
+    canvas.hline(half, GRID);
+    let db_step = (IMAGE_MAX_DB - IMAGE_MIN_DB) / 4.0;
+    for step in 1..4 {
+        let ratio = step as f32 * db_step / (IMAGE_MAX_DB - IMAGE_MIN_DB);
+        canvas.hline(half + (ratio * half as f32) as usize, GRID);
+    }
+
+    let waveform_points = waveform.iter().enumerate().map(|(index, value)| {
+        let x = index as f32 / waveform.len().max(2).saturating_sub(1) as f32;
+        (x, scope_mid - value.clamp(-1.0, 1.0) * scope_mid * 0.9)
+    });
+    canvas.polyline(waveform_points, TRACE);
+
+    let spectrum_points = spectrum.iter().enumerate().map(|(index, magnitude)| {
+        let db = 20.0 * magnitude.max(1e-9).log10();
+        let level = ((db - IMAGE_MIN_DB) / (IMAGE_MAX_DB - IMAGE_MIN_DB)).clamp(0.0, 1.0);
+        let x = index as f32 / spectrum.len().max(2).saturating_sub(1) as f32;
+        (x, (IMAGE_HEIGHT - 1) as f32 - level * (half - 1) as f32)
+    });
+    canvas.polyline(spectrum_points, TRACE);
+    canvas.pixels
+}
+
+struct Canvas {
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new() -> Self {
+        Self {
+            pixels: BACKGROUND.repeat(IMAGE_WIDTH * IMAGE_HEIGHT),
+        }
+    }
+
+    fn put(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        if x < 0 || y < 0 || x as usize >= IMAGE_WIDTH || y as usize >= IMAGE_HEIGHT {
+            return;
+        }
+        let offset = (y as usize * IMAGE_WIDTH + x as usize) * 4;
+        self.pixels[offset..offset + 4].copy_from_slice(&color);
+    }
+
+    fn hline(&mut self, y: usize, color: [u8; 4]) {
+        for x in 0..IMAGE_WIDTH {
+            self.put(x as i32, y as i32, color);
+        }
+    }
+
+    /// Points are (0..1 across the width, pixel row).
+    fn polyline(&mut self, points: impl Iterator<Item = (f32, f32)>, color: [u8; 4]) {
+        let scale = (IMAGE_WIDTH - 1) as f32;
+        let mut previous: Option<(i32, i32)> = None;
+        for (x, y) in points {
+            let point = ((x * scale).round() as i32, y.round() as i32);
+            match previous {
+                Some(start) => self.line(start, point, color),
+                None => self.put(point.0, point.1, color),
+            }
+            previous = Some(point);
+        }
+    }
+
+    fn line(&mut self, (x0, y0): (i32, i32), (x1, y1): (i32, i32), color: [u8; 4]) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y, mut error) = (x0, y0, dx + dy);
+        loop {
+            self.put(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use macroquad::prelude::{KeyCode, Vec2, vec2};
+use miniroog_model_r::{
+    clock::{ClockSource, DEFAULT_BPM},
+    preset::Preset,
+    tuning::{DEFAULT_REFERENCE_HZ, Temperament},
+};
+
+use crate::controllers::{KeyLayout, key_from_id, key_id};
+
+pub const SESSION_FILE: &str = "miniroog-session.mrp";
+
+const DEBUG_OPEN_KEY: &str = "ui.debug.open";
+const DEBUG_X_KEY: &str = "ui.debug.x";
+const DEBUG_Y_KEY: &str = "ui.debug.y";
+const OCTAVE_KEY: &str = "ui.octave_shift";
+const TEMPO_KEY: &str = "ui.tempo";
+const MIDI_CLOCK_KEY: &str = "ui.midi_clock";
+const METRONOME_KEY: &str = "ui.metronome";
+const TEMPERAMENT_KEY: &str = "ui.temperament";
+const REFERENCE_PITCH_KEY: &str = "ui.reference_pitch";
+const TUNE_CALIBRATION_KEY: &str = "ui.tune_calibration_cents";
+const KEY_LAYOUT_KEY: &str = "ui.keys.layout";
+const WHITE_KEYS_PREFIX: &str = "ui.keys.white.";
+const BLACK_KEYS_PREFIX: &str = "ui.keys.black.";
+
+/// What the window looked like at exit. The patch is a full preset, so the
+/// noise color and every switch come back with it.
+pub struct Session {
+    pub patch: Preset,
+    pub debug_open: bool,
+    pub debug_position: Vec2,
+    pub octave_shift: i32,
+    pub tempo: f32,
+    pub clock_source: ClockSource,
+    pub metronome: bool,
+    pub temperament: Temperament,
+    pub reference_pitch: f32,
+    /// Trim under every patch's TUNE, in cents.
+    pub tune_calibration: f32,
+    pub key_layout: KeyLayout,
+    pub white_keys: Vec<KeyCode>,
+    pub black_keys: Vec<KeyCode>,
+}
+
+impl Session {
+    /// Stored in the preset format, with the UI fields under `ui.` keys that
+    /// `apply_preset` skips over.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut preset = self.patch.clone();
+        preset.set_flag(DEBUG_OPEN_KEY, self.debug_open);
+        preset.set(DEBUG_X_KEY, self.debug_position.x);
+        preset.set(DEBUG_Y_KEY, self.debug_position.y);
+        preset.set(OCTAVE_KEY, self.octave_shift as f32);
+        preset.set(TEMPO_KEY, self.tempo);
+        preset.set_flag(MIDI_CLOCK_KEY, self.clock_source == ClockSource::Midi);
+        preset.set_flag(METRONOME_KEY, self.metronome);
+        preset.set(TEMPERAMENT_KEY, self.temperament.index() as f32);
+        preset.set(REFERENCE_PITCH_KEY, self.reference_pitch);
+        preset.set(TUNE_CALIBRATION_KEY, self.tune_calibration);
+        preset.set(KEY_LAYOUT_KEY, self.key_layout.index() as f32);
+        set_keys(&mut preset, WHITE_KEYS_PREFIX, &self.white_keys);
+        set_keys(&mut preset, BLACK_KEYS_PREFIX, &self.black_keys);
+        fs::write(path, preset.serialize())
+            .with_context(|| format!("writing session {}", path.display()))
+    }
+
+    /// `None` on first run; the caller falls back to its usual defaults.
+    pub fn load(path: &Path) -> Option<Result<Self>> {
+        if !path.exists() {
+            return None;
+        }
+        Some(Preset::load(path).map(|patch| {
+            Self {
+                debug_open: patch.get_flag(DEBUG_OPEN_KEY).unwrap_or(true),
+                debug_position: vec2(
+                    patch.get(DEBUG_X_KEY).unwrap_or(0.0),
+                    patch.get(DEBUG_Y_KEY).unwrap_or(0.0),
+                ),
+                octave_shift: patch.get(OCTAVE_KEY).unwrap_or(0.0).round() as i32,
+                tempo: patch.get(TEMPO_KEY).unwrap_or(DEFAULT_BPM),
+                clock_source: if patch.get_flag(MIDI_CLOCK_KEY).unwrap_or(false) {
+                    ClockSource::Midi
+                } else {
+                    ClockSource::Internal
+                },
+                metronome: patch.get_flag(METRONOME_KEY).unwrap_or(false),
+                temperament: patch
+                    .get(TEMPERAMENT_KEY)
+                    .and_then(|index| Temperament::VALUES.get(index.round() as usize).copied())
+                    .unwrap_or_default(),
+                reference_pitch: patch
+                    .get(REFERENCE_PITCH_KEY)
+                    .unwrap_or(DEFAULT_REFERENCE_HZ),
+                tune_calibration: patch.get(TUNE_CALIBRATION_KEY).unwrap_or(0.0),
+                key_layout: patch
+                    .get(KEY_LAYOUT_KEY)
+                    .and_then(|index| KeyLayout::VALUES.get(index.round() as usize).copied())
+                    .unwrap_or_default(),
+                white_keys: get_keys(&patch, WHITE_KEYS_PREFIX),
+                black_keys: get_keys(&patch, BLACK_KEYS_PREFIX),
+                patch,
+            }
+        }))
+    }
+}
+
+// Key bindings are numbered slots holding `key_id`s, since the file only
+// stores numbers.
+fn set_keys(preset: &mut Preset, prefix: &str, keys: &[KeyCode]) {
+    for (slot, id) in keys.iter().filter_map(|key| key_id(*key)).enumerate() {
+        preset.set(&format!("{prefix}{slot}"), id as f32);
+    }
+}
+
+fn get_keys(preset: &Preset, prefix: &str) -> Vec<KeyCode> {
+    (0..)
+        .map_while(|slot| preset.get(&format!("{prefix}{slot}")))
+        .filter_map(|id| key_from_id(id.round() as usize))
+        .collect()
+}
@@ -0,0 +1,472 @@
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::midi::{CC_SUSTAIN, MidiMessage};
+
+pub const MIDI_FILE_EXTENSIONS: [&str; 2] = ["mid", "midi"];
+
+const DEFAULT_TEMPO_US: u64 = 500_000;
+const SMPTE_FPS: [u8; 4] = [24, 25, 29, 30];
+// 480 PPQ at the default 120 BPM gives millisecond-ish resolution.
+const WRITE_PPQ: u16 = 480;
+// Keyboard, mouse and touch notes carry no velocity of their own.
+const RECORD_VELOCITY: u8 = 100;
+// General MIDI drums; a mono lead has no use for them.
+const PERCUSSION_CHANNEL: u8 = 9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimedMessage {
+    pub seconds: f64,
+    pub message: MidiMessage,
+}
+
+/// Note and controller events from a Standard MIDI File (format 0 or 1),
+/// with every track merged and tempo changes applied.
+#[derive(Clone, Debug)]
+pub struct MidiFile {
+    name: String,
+    events: Vec<TimedMessage>,
+    length: f64,
+}
+
+enum TrackEvent {
+    Tempo(u64),
+    Message(MidiMessage),
+    End,
+}
+
+impl MidiFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self::parse(&bytes, name).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn parse(bytes: &[u8], name: String) -> Result<Self> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(4)? != b"MThd" {
+            bail!("not a standard MIDI file");
+        }
+        let header = reader.u32()? as usize;
+        let mut header_reader = Reader {
+            bytes: reader.take(header)?,
+            position: 0,
+        };
+        let format = header_reader.u16()?;
+        let _tracks = header_reader.u16()?;
+        let division = header_reader.u16()?;
+        if format > 1 {
+            bail!("format {format} MIDI files are not supported");
+        }
+        if let Some(fps) = smpte_fps(division)
+            && !SMPTE_FPS.contains(&fps)
+        {
+            bail!("{fps} is not an SMPTE frame rate (24, 25, 29 or 30)");
+        }
+
+        let mut events = Vec::new();
+        while reader.remaining() >= 8 {
+            let id = reader.take(4)?;
+            let length = reader.u32()? as usize;
+            let chunk = reader.take(length)?;
+            // Unknown chunks are skipped, as the spec asks.
+            if id == b"MTrk" {
+                parse_track(chunk, &mut events)?;
+            }
+        }
+        // Stable, so same-tick events keep their track order and a tempo
+        // change in the first track lands before notes at that tick.
+        events.sort_by_key(|(tick, _)| *tick);
+
+        let mut seconds = 0.0;
+        let mut last_tick = 0;
+        let mut tempo = DEFAULT_TEMPO_US;
+        let mut timed = Vec::new();
+        for (tick, event) in events {
+            seconds += tick_seconds(division, tempo) * (tick - last_tick) as f64;
+            last_tick = tick;
+            match event {
+                TrackEvent::Tempo(us) => tempo = us,
+                TrackEvent::Message(message) => timed.push(TimedMessage { seconds, message }),
+                TrackEvent::End => {}
+            }
+        }
+        Ok(Self {
+            name,
+            events: timed,
+            length: seconds,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn events(&self) -> &[TimedMessage] {
+        &self.events
+    }
+
+    /// Seconds up to the last event, end-of-track markers included.
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+}
+
+// SMPTE divisions carry frames per second in the high byte, negated.
+fn smpte_fps(division: u16) -> Option<u8> {
+    (division & 0x8000 != 0).then(|| ((division >> 8) as u8 as i8).unsigned_abs())
+}
+
+fn tick_seconds(division: u16, tempo_us: u64) -> f64 {
+    match smpte_fps(division) {
+        // SMPTE: ticks per frame in the low byte; tempo doesn't apply, and
+        // 29 stands for 29.97 drop-frame.
+        Some(fps) => {
+            let fps = if fps == 29 {
+                30_000.0 / 1_001.0
+            } else {
+                fps as f64
+            };
+            let ticks_per_frame = (division & 0xFF) as f64;
+            1.0 / (fps * ticks_per_frame).max(1.0)
+        }
+        None => tempo_us as f64 / 1_000_000.0 / division.max(1) as f64,
+    }
+}
+
+fn parse_track(bytes: &[u8], events: &mut Vec<(u64, TrackEvent)>) -> Result<()> {
+    let mut reader = Reader { bytes, position: 0 };
+    let mut tick = 0u64;
+    let mut running_status = None;
+    while reader.remaining() > 0 {
+        tick += reader.variable()? as u64;
+        let mut status = reader.u8()?;
+        let first_data = if status < 0x80 {
+            let data = status;
+            status = running_status.ok_or_else(|| anyhow!("data byte without a status"))?;
+            Some(data)
+        } else {
+            None
+        };
+        match status {
+            0xFF => {
+                // Meta and sysex events cancel running status.
+                running_status = None;
+                let kind = reader.u8()?;
+                let length = reader.variable()? as usize;
+                let data = reader.take(length)?;
+                match (kind, data) {
+                    (0x51, [a, b, c]) => {
+                        let tempo = u64::from_be_bytes([0, 0, 0, 0, 0, *a, *b, *c]);
+                        events.push((tick, TrackEvent::Tempo(tempo)));
+                    }
+                    (0x2F, _) => {
+                        events.push((tick, TrackEvent::End));
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                running_status = None;
+                let length = reader.variable()? as usize;
+                reader.take(length)?;
+            }
+            _ => {
+                running_status = Some(status);
+                let data1 = match first_data {
+                    Some(data) => data,
+                    None => reader.u8()?,
+                };
+                // Program change and channel pressure carry one data byte.
+                let data2 = match status & 0xF0 {
+                    0xC0 | 0xD0 => 0,
+                    _ => reader.u8()?,
+                };
+                if status & 0x0F == PERCUSSION_CHANNEL {
+                    continue;
+                }
+                if let Some(message) = MidiMessage::parse(&[status, data1, data2]) {
+                    events.push((tick, TrackEvent::Message(message)));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("file ends early"))?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// MIDI variable-length quantity: seven bits per byte, high bit set on
+    /// all but the last, at most four bytes.
+    fn variable(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("variable-length value too long")
+    }
+}
+
+/// Transport over a loaded file. Messages come out of `advance` in file
+/// order for the caller to feed through the same path as live MIDI input;
+/// pausing or stopping releases whatever the file left sounding.
+pub struct MidiPlayer {
+    file: MidiFile,
+    position: f64,
+    cursor: usize,
+    playing: bool,
+    sounding: Vec<u8>,
+    sustain: bool,
+}
+
+impl MidiPlayer {
+    pub fn new(file: MidiFile) -> Self {
+        Self {
+            file,
+            position: 0.0,
+            cursor: 0,
+            playing: false,
+            sounding: Vec::new(),
+            sustain: false,
+        }
+    }
+
+    pub fn file(&self) -> &MidiFile {
+        &self.file
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) -> Vec<MidiMessage> {
+        self.playing = false;
+        self.release()
+    }
+
+    /// Pauses and rewinds to the start.
+    pub fn stop(&mut self) -> Vec<MidiMessage> {
+        self.position = 0.0;
+        self.cursor = 0;
+        self.pause()
+    }
+
+    /// Moves the playhead on by `dt` seconds and returns the events it
+    /// passed. Reaching the end stops and rewinds.
+    pub fn advance(&mut self, dt: f64) -> Vec<MidiMessage> {
+        if !self.playing {
+            return Vec::new();
+        }
+        self.position += dt;
+        let mut due = Vec::new();
+        while let Some(&event) = self.file.events.get(self.cursor) {
+            if event.seconds > self.position {
+                break;
+            }
+            self.track(event.message);
+            due.push(event.message);
+            self.cursor += 1;
+        }
+        if self.cursor >= self.file.events.len() && self.position >= self.file.length {
+            due.extend(self.stop());
+        }
+        due
+    }
+
+    fn track(&mut self, message: MidiMessage) {
+        match message {
+            MidiMessage::NoteOn { note, .. } if !self.sounding.contains(&note) => {
+                self.sounding.push(note)
+            }
+            MidiMessage::NoteOff { note } => self.sounding.retain(|held| *held != note),
+            MidiMessage::ControlChange {
+                controller: CC_SUSTAIN,
+                value,
+            } => self.sustain = value >= 64,
+            _ => {}
+        }
+    }
+
+    fn release(&mut self) -> Vec<MidiMessage> {
+        let mut messages: Vec<MidiMessage> = self
+            .sounding
+            .drain(..)
+            .map(|note| MidiMessage::NoteOff { note })
+            .collect();
+        if std::mem::take(&mut self.sustain) {
+            messages.push(MidiMessage::ControlChange {
+                controller: CC_SUSTAIN,
+                value: 0,
+            });
+        }
+        messages
+    }
+}
+
+/// Notes as the keyboard controller resolved them (octave shift, note
+/// priority and sustain already applied), timestamped against the caller's
+/// clock for writing out as a single-track file.
+pub struct Performance {
+    started: f64,
+    events: Vec<TimedMessage>,
+    sounding: Option<u8>,
+}
+
+impl Performance {
+    pub fn new(now: f64) -> Self {
+        Self {
+            started: now,
+            events: Vec::new(),
+            sounding: None,
+        }
+    }
+
+    /// Records the note now sounding, or `None` once the gate closes.
+    pub fn set_note(&mut self, now: f64, note: Option<u8>) {
+        if note == self.sounding {
+            return;
+        }
+        let seconds = (now - self.started).max(0.0);
+        // Note on before note off, so a legato change stays legato in a DAW.
+        if let Some(note) = note {
+            self.push(
+                seconds,
+                MidiMessage::NoteOn {
+                    note,
+                    velocity: RECORD_VELOCITY,
+                },
+            );
+        }
+        if let Some(previous) = self.sounding {
+            self.push(seconds, MidiMessage::NoteOff { note: previous });
+        }
+        self.sounding = note;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Closes any held note at `now` and writes the take as a format 0 file.
+    pub fn save(mut self, now: f64, path: &Path) -> Result<()> {
+        self.set_note(now, None);
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let bytes = encode(&name, &self.events);
+        let file =
+            fs::File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        out.write_all(&bytes)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    fn push(&mut self, seconds: f64, message: MidiMessage) {
+        self.events.push(TimedMessage { seconds, message });
+    }
+}
+
+fn encode(name: &str, events: &[TimedMessage]) -> Vec<u8> {
+    let ticks_per_second = WRITE_PPQ as f64 * 1_000_000.0 / DEFAULT_TEMPO_US as f64;
+    let mut track = Vec::new();
+    write_variable(&mut track, 0);
+    track.extend([0xFF, 0x03]);
+    write_variable(&mut track, name.len() as u32);
+    track.extend(name.as_bytes());
+    write_variable(&mut track, 0);
+    track.extend([0xFF, 0x51, 0x03]);
+    track.extend(&(DEFAULT_TEMPO_US as u32).to_be_bytes()[1..]);
+    let mut last_tick = 0u64;
+    for event in events {
+        let tick = (event.seconds * ticks_per_second).round() as u64;
+        write_variable(&mut track, tick.saturating_sub(last_tick) as u32);
+        last_tick = last_tick.max(tick);
+        track.extend(event.message.bytes());
+    }
+    write_variable(&mut track, 0);
+    track.extend([0xFF, 0x2F, 0x00]);
+
+    let mut bytes = b"MThd".to_vec();
+    bytes.extend(6u32.to_be_bytes());
+    bytes.extend(0u16.to_be_bytes());
+    bytes.extend(1u16.to_be_bytes());
+    bytes.extend(WRITE_PPQ.to_be_bytes());
+    bytes.extend(b"MTrk");
+    bytes.extend((track.len() as u32).to_be_bytes());
+    bytes.extend(track);
+    bytes
+}
+
+fn write_variable(out: &mut Vec<u8>, value: u32) {
+    let mut groups = [0u8; 4];
+    let mut count = 0;
+    let mut rest = value.min(0x0FFF_FFFF);
+    loop {
+        groups[count] = (rest & 0x7F) as u8;
+        count += 1;
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    for index in (0..count).rev() {
+        let more = if index > 0 { 0x80 } else { 0 };
+        out.push(groups[index] | more);
+    }
+}
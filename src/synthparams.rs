@@ -0,0 +1,250 @@
+use crate::{
+    command::EngineCommand,
+    midi::knob_cc,
+    modifiers::EnvelopeParams,
+    params::{
+        FOLLOWER_CUTOFF_MAX_OCT, KnobId, knob_to_chorus_rate, knob_to_cutoff,
+        knob_to_delay_damping, knob_to_delay_time, knob_to_detune, knob_to_env_seconds,
+        knob_to_follower_release, knob_to_pan, knob_to_reverb_damping, knob_to_reverb_decay,
+        value_to_waveform,
+    },
+    vco::VcoCommand,
+};
+
+pub const PARAM_COUNT: usize = KnobId::ALL.len();
+
+const FILTER_STAGES: [KnobId; 6] = [
+    KnobId::FilterDelay,
+    KnobId::FilterAttack,
+    KnobId::FilterHold,
+    KnobId::FilterDecay,
+    KnobId::FilterSustain,
+    KnobId::FilterRelease,
+];
+const LOUDNESS_STAGES: [KnobId; 6] = [
+    KnobId::LoudnessDelay,
+    KnobId::LoudnessAttack,
+    KnobId::LoudnessHold,
+    KnobId::LoudnessDecay,
+    KnobId::LoudnessSustain,
+    KnobId::LoudnessRelease,
+];
+
+/// What a knob's travel stands for, for hosts that show a unit beside the
+/// value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamUnit {
+    Percent,
+    Cents,
+    Semitones,
+    Octaves,
+    Seconds,
+    Hertz,
+    Pan,
+    Choice,
+}
+
+impl ParamUnit {
+    pub fn label(self) -> &'static str {
+        match self {
+            ParamUnit::Percent => "%",
+            ParamUnit::Cents => "ct",
+            ParamUnit::Semitones => "st",
+            ParamUnit::Octaves => "oct",
+            ParamUnit::Seconds => "s",
+            ParamUnit::Hertz => "Hz",
+            ParamUnit::Pan | ParamUnit::Choice => "",
+        }
+    }
+
+    fn of(knob: KnobId) -> Self {
+        match knob {
+            KnobId::ControllersTune => ParamUnit::Cents,
+            KnobId::ModPitchDepth | KnobId::OscFreq1 | KnobId::OscFreq2 | KnobId::OscFreq3 => {
+                ParamUnit::Semitones
+            }
+            KnobId::FollowerCutoff => ParamUnit::Octaves,
+            KnobId::ControllersGlide
+            | KnobId::ModFade
+            | KnobId::FollowerRelease
+            | KnobId::FilterDelay
+            | KnobId::FilterAttack
+            | KnobId::FilterHold
+            | KnobId::FilterDecay
+            | KnobId::FilterRelease
+            | KnobId::LoudnessDelay
+            | KnobId::LoudnessAttack
+            | KnobId::LoudnessHold
+            | KnobId::LoudnessDecay
+            | KnobId::LoudnessRelease
+            | KnobId::DelayTime
+            | KnobId::ReverbSize => ParamUnit::Seconds,
+            KnobId::ControllersModRate
+            | KnobId::FilterCutoff
+            | KnobId::ChorusRate
+            | KnobId::DelayDamping
+            | KnobId::ReverbDamping => ParamUnit::Hertz,
+            KnobId::MixerPan1 | KnobId::MixerPan2 | KnobId::MixerPan3 | KnobId::MixerNoisePan => {
+                ParamUnit::Pan
+            }
+            KnobId::OscRange1
+            | KnobId::OscRange2
+            | KnobId::OscRange3
+            | KnobId::OscWave1
+            | KnobId::OscWave2
+            | KnobId::OscWave3
+            | KnobId::MixerNoiseColor => ParamUnit::Choice,
+            _ => ParamUnit::Percent,
+        }
+    }
+}
+
+/// One knob as the panel, presets, MIDI output and plugin hosts all see it.
+/// Every knob travels 0 to 1; `range` gives the readouts at the two ends.
+#[derive(Clone, Copy, Debug)]
+pub struct ParamSpec {
+    pub knob: KnobId,
+    /// Position in `KnobId::ALL`, which doubles as the plugin parameter ID.
+    pub id: u32,
+    pub key: &'static str,
+    pub name: &'static str,
+    pub unit: ParamUnit,
+    pub default: f32,
+    pub cc: Option<u8>,
+}
+
+impl ParamSpec {
+    pub fn of(knob: KnobId) -> Self {
+        Self {
+            knob,
+            id: knob as u32,
+            key: knob.preset_key(),
+            name: knob.label(),
+            unit: ParamUnit::of(knob),
+            default: knob.default_value(),
+            cc: knob_cc(knob),
+        }
+    }
+
+    pub fn format(&self, value: f32) -> String {
+        self.knob.format_value(value)
+    }
+
+    pub fn range(&self) -> (String, String) {
+        self.knob.range_text()
+    }
+}
+
+pub fn param_specs() -> impl Iterator<Item = ParamSpec> {
+    KnobId::ALL.into_iter().map(ParamSpec::of)
+}
+
+/// A full set of knob positions and the engine commands they stand for.
+#[derive(Clone, Copy, Debug)]
+pub struct SynthParams {
+    values: [f32; PARAM_COUNT],
+}
+
+impl SynthParams {
+    pub fn new() -> Self {
+        Self::from_values(KnobId::ALL.map(KnobId::default_value))
+    }
+
+    pub fn from_values(values: [f32; PARAM_COUNT]) -> Self {
+        Self { values }
+    }
+
+    pub fn values(&self) -> &[f32; PARAM_COUNT] {
+        &self.values
+    }
+
+    pub fn get(&self, knob: KnobId) -> f32 {
+        self.values[knob as usize]
+    }
+
+    pub fn set(&mut self, knob: KnobId, value: f32) {
+        self.values[knob as usize] = value.clamp(0.0, 1.0);
+    }
+
+    /// The command that carries this knob to the engine, or `None` for knobs
+    /// the caller handles itself (pitch, glide, modulation, macros).
+    pub fn engine_command(&self, knob: KnobId) -> Option<EngineCommand> {
+        let value = self.get(knob);
+        let command = match knob {
+            KnobId::OscFreq1 => EngineCommand::Vco(0, VcoCommand::SetDetune(knob_to_detune(value))),
+            KnobId::OscFreq2 => EngineCommand::Vco(1, VcoCommand::SetDetune(knob_to_detune(value))),
+            KnobId::OscFreq3 => EngineCommand::Vco(2, VcoCommand::SetDetune(knob_to_detune(value))),
+            KnobId::OscWave1 => {
+                EngineCommand::Vco(0, VcoCommand::SetWaveform(value_to_waveform(0, value)))
+            }
+            KnobId::OscWave2 => {
+                EngineCommand::Vco(1, VcoCommand::SetWaveform(value_to_waveform(1, value)))
+            }
+            KnobId::OscWave3 => {
+                EngineCommand::Vco(2, VcoCommand::SetWaveform(value_to_waveform(2, value)))
+            }
+            KnobId::OscFmDepth => EngineCommand::FmDepth(value),
+            KnobId::MixerExternal => EngineCommand::ExternalLevel(value),
+            KnobId::MixerOsc1 => EngineCommand::MixLevel(0, value),
+            KnobId::MixerOsc2 => EngineCommand::MixLevel(1, value),
+            KnobId::MixerOsc3 => EngineCommand::MixLevel(2, value),
+            KnobId::MixerNoise => EngineCommand::NoiseLevel(value),
+            KnobId::MixerRing => EngineCommand::RingLevel(value),
+            KnobId::MixerNoiseColor => EngineCommand::NoiseMorph(value),
+            KnobId::MixerPan1 => EngineCommand::Pan(0, knob_to_pan(value)),
+            KnobId::MixerPan2 => EngineCommand::Pan(1, knob_to_pan(value)),
+            KnobId::MixerPan3 => EngineCommand::Pan(2, knob_to_pan(value)),
+            KnobId::MixerNoisePan => EngineCommand::NoisePan(knob_to_pan(value)),
+            KnobId::FollowerRelease => {
+                EngineCommand::FollowerRelease(knob_to_follower_release(value))
+            }
+            KnobId::FollowerCutoff => {
+                EngineCommand::FollowerCutoff(value * FOLLOWER_CUTOFF_MAX_OCT)
+            }
+            KnobId::FollowerVca => EngineCommand::FollowerVca(value),
+            KnobId::FilterCutoff => EngineCommand::Cutoff(knob_to_cutoff(value)),
+            KnobId::FilterEmphasis => EngineCommand::FilterEmphasis(value),
+            KnobId::FilterContour => EngineCommand::FilterContour(value),
+            knob if FILTER_STAGES.contains(&knob) => {
+                EngineCommand::FilterEnvelope(self.envelope(FILTER_STAGES))
+            }
+            knob if LOUDNESS_STAGES.contains(&knob) => {
+                EngineCommand::LoudnessEnvelope(self.envelope(LOUDNESS_STAGES))
+            }
+            KnobId::OutputVolume => EngineCommand::MasterLevel(value),
+            KnobId::ChorusRate => EngineCommand::ChorusRate(knob_to_chorus_rate(value)),
+            KnobId::ChorusDepth => EngineCommand::ChorusDepth(value),
+            KnobId::ChorusMix => EngineCommand::ChorusMix(value),
+            KnobId::DelayTime => EngineCommand::DelayTime(knob_to_delay_time(value)),
+            KnobId::DelayFeedback => EngineCommand::DelayFeedback(value),
+            KnobId::DelayDamping => EngineCommand::DelayDamping(knob_to_delay_damping(value)),
+            KnobId::DelayMix => EngineCommand::DelayMix(value),
+            KnobId::ReverbSize => EngineCommand::ReverbDecay(knob_to_reverb_decay(value)),
+            KnobId::ReverbDamping => EngineCommand::ReverbDamping(knob_to_reverb_damping(value)),
+            KnobId::ReverbMix => EngineCommand::ReverbMix(value),
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    // Knobs in DAHDSR order; with no release set, it follows the decay as
+    // the panel's Decay switch does.
+    fn envelope(&self, knobs: [KnobId; 6]) -> EnvelopeParams {
+        let [delay, attack, hold, decay, _, release] =
+            knobs.map(|knob| knob_to_env_seconds(knob, self.get(knob)));
+        EnvelopeParams {
+            delay,
+            attack,
+            hold,
+            decay,
+            sustain: self.get(knobs[4]),
+            release: if release > 0.0 { release } else { decay },
+        }
+    }
+}
+
+impl Default for SynthParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,112 @@
+use std::f32::consts::TAU;
+
+use crate::noise::{NoiseColor, NoiseGenerator};
+
+const SWEEP_START_HZ: f32 = 20.0;
+const SWEEP_END_HZ: f32 = 20_000.0;
+const SWEEP_SECONDS: f32 = 4.0;
+const BURST_SECONDS: f32 = 0.25;
+const REPEAT_SECONDS: f32 = 1.0;
+const LEVEL: f32 = 0.5;
+
+/// Signal injected in place of the oscillator mix for measuring the filter
+/// and output chain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TestSignal {
+    #[default]
+    Off,
+    Sweep,
+    Noise,
+    Impulse,
+}
+
+impl TestSignal {
+    pub fn next(self) -> Self {
+        match self {
+            TestSignal::Off => TestSignal::Sweep,
+            TestSignal::Sweep => TestSignal::Noise,
+            TestSignal::Noise => TestSignal::Impulse,
+            TestSignal::Impulse => TestSignal::Off,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TestSignal::Off => "OFF",
+            TestSignal::Sweep => "SWEEP",
+            TestSignal::Noise => "NOISE",
+            TestSignal::Impulse => "IMPULSE",
+        }
+    }
+
+    fn period(self) -> f32 {
+        match self {
+            TestSignal::Sweep => SWEEP_SECONDS,
+            _ => REPEAT_SECONDS,
+        }
+    }
+}
+
+/// Repeating sources: an exponential 20 Hz–20 kHz sine sweep, a white noise
+/// burst, or a full-scale single-sample impulse, each restarting every
+/// period so the scope keeps catching them.
+pub struct SignalGenerator {
+    signal: TestSignal,
+    elapsed: f32,
+    phase: f32,
+    noise: NoiseGenerator,
+}
+
+impl SignalGenerator {
+    pub fn new() -> Self {
+        Self {
+            signal: TestSignal::Off,
+            elapsed: 0.0,
+            phase: 0.0,
+            noise: NoiseGenerator::with_seed(0x7E57),
+        }
+    }
+
+    pub fn set_signal(&mut self, signal: TestSignal) {
+        if signal != self.signal {
+            self.signal = signal;
+            self.elapsed = 0.0;
+            self.phase = 0.0;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.signal != TestSignal::Off
+    }
+
+    pub fn sample(&mut self, dt: f32) -> f32 {
+        let value = match self.signal {
+            TestSignal::Off => 0.0,
+            TestSignal::Sweep => {
+                let progress = self.elapsed / SWEEP_SECONDS;
+                let freq = SWEEP_START_HZ * (SWEEP_END_HZ / SWEEP_START_HZ).powf(progress);
+                self.phase = (self.phase + freq * dt).fract();
+                (self.phase * TAU).sin() * LEVEL
+            }
+            TestSignal::Noise if self.elapsed < BURST_SECONDS => {
+                self.noise.sample(NoiseColor::White) * LEVEL
+            }
+            TestSignal::Noise => 0.0,
+            TestSignal::Impulse if self.elapsed < dt => 1.0,
+            TestSignal::Impulse => 0.0,
+        };
+        self.elapsed += dt;
+        let period = self.signal.period();
+        if self.elapsed >= period {
+            self.elapsed -= period;
+            self.phase = 0.0;
+        }
+        value
+    }
+}
+
+impl Default for SignalGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use crate::{
+    probe::{ProbeBuffer, ProbeTap},
+    tuning::Tuning,
+};
+
+const WINDOW: usize = 4096;
+const MIN_FREQUENCY: f32 = 20.0;
+const MAX_FREQUENCY: f32 = 5_000.0;
+// Quieter than this and the detector is only chasing noise.
+const MIN_RMS: f32 = 0.001;
+// McLeod's "first peak within this fraction of the highest" rule, which
+// keeps the detector from locking onto a subharmonic.
+const PEAK_THRESHOLD: f32 = 0.9;
+const MIN_CLARITY: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TunerReading {
+    pub frequency: f32,
+    pub note: i32,
+    pub cents: f32,
+}
+
+/// Pitch detector fed from a probe tap in the pipeline, using the McLeod
+/// normalized square difference over the newest `WINDOW` samples.
+pub struct Tuner {
+    history: ProbeBuffer,
+    fft: Arc<dyn Fft<f32>>,
+    inverse: Arc<dyn Fft<f32>>,
+    spectrum: Vec<Complex<f32>>,
+    window: Vec<f32>,
+}
+
+impl Tuner {
+    pub fn new() -> (Self, ProbeTap) {
+        let (history, tap) = ProbeBuffer::new(WINDOW);
+        let mut planner = FftPlanner::new();
+        let tuner = Self {
+            history,
+            fft: planner.plan_fft_forward(WINDOW * 2),
+            inverse: planner.plan_fft_inverse(WINDOW * 2),
+            spectrum: vec![Complex::new(0.0, 0.0); WINDOW * 2],
+            window: vec![0.0; WINDOW],
+        };
+        (tuner, tap)
+    }
+
+    /// Swaps in a fresh tap, e.g. after the probe point changes, so stale
+    /// samples from the old point don't skew the next readings.
+    pub fn reset(&mut self) -> ProbeTap {
+        let (history, tap) = ProbeBuffer::new(WINDOW);
+        self.history = history;
+        tap
+    }
+
+    /// Drains the tap; call every frame so it never fills up.
+    pub fn drain(&mut self) {
+        self.history.drain();
+    }
+
+    /// Reads the pitch against the keys of `tuning`, so cents are measured
+    /// from the nearest key as the current temperament tunes it.
+    pub fn detect(&mut self, sample_rate: f32, tuning: &Tuning) -> Option<TunerReading> {
+        self.history.copy_latest(&mut self.window);
+        let frequency = self.pitch(sample_rate)?;
+        let (note, cents) = tuning.nearest(frequency)?;
+        Some(TunerReading {
+            frequency,
+            note,
+            cents,
+        })
+    }
+
+    fn pitch(&mut self, sample_rate: f32) -> Option<f32> {
+        let energy: f32 = self.window.iter().map(|sample| sample * sample).sum();
+        if (energy / WINDOW as f32).sqrt() < MIN_RMS {
+            return None;
+        }
+        // Autocorrelation through a zero-padded FFT.
+        for (slot, sample) in self
+            .spectrum
+            .iter_mut()
+            .zip(self.window.iter().copied().chain(std::iter::repeat(0.0)))
+        {
+            *slot = Complex::new(sample, 0.0);
+        }
+        self.fft.process(&mut self.spectrum);
+        for bin in &mut self.spectrum {
+            *bin = Complex::new(bin.norm_sqr(), 0.0);
+        }
+        self.inverse.process(&mut self.spectrum);
+        let scale = 1.0 / self.spectrum.len() as f32;
+
+        let min_lag = (sample_rate / MAX_FREQUENCY).floor().max(1.0) as usize;
+        let max_lag = ((sample_rate / MIN_FREQUENCY).ceil() as usize).min(WINDOW - 2);
+        let mut nsdf = vec![0.0; max_lag + 2];
+        let mut squares = 2.0 * energy;
+        for (lag, value) in nsdf.iter_mut().enumerate() {
+            if lag > 0 {
+                let leaving = self.window[lag - 1];
+                let entering = self.window[WINDOW - lag];
+                squares -= leaving * leaving + entering * entering;
+            }
+            if squares > f32::EPSILON {
+                *value = 2.0 * self.spectrum[lag].re * scale / squares;
+            }
+        }
+
+        // Peaks between positive-going and negative-going zero crossings.
+        let mut peaks = Vec::new();
+        let mut lag = min_lag;
+        while lag <= max_lag && nsdf[lag] > 0.0 {
+            lag += 1;
+        }
+        while lag <= max_lag {
+            while lag <= max_lag && nsdf[lag] <= 0.0 {
+                lag += 1;
+            }
+            let mut best = lag;
+            while lag <= max_lag && nsdf[lag] > 0.0 {
+                if nsdf[lag] > nsdf[best] {
+                    best = lag;
+                }
+                lag += 1;
+            }
+            if best <= max_lag {
+                peaks.push(best);
+            }
+        }
+        let highest = peaks.iter().map(|&lag| nsdf[lag]).fold(0.0, f32::max);
+        if highest < MIN_CLARITY {
+            return None;
+        }
+        let lag = *peaks
+            .iter()
+            .find(|&&lag| nsdf[lag] >= highest * PEAK_THRESHOLD)?;
+
+        let (before, at, after) = (nsdf[lag - 1], nsdf[lag], nsdf[lag + 1]);
+        let curvature = before - 2.0 * at + after;
+        let offset = if curvature.abs() > f32::EPSILON {
+            0.5 * (before - after) / curvature
+        } else {
+            0.0
+        };
+        Some(sample_rate / (lag as f32 + offset.clamp(-0.5, 0.5)))
+    }
+}
@@ -0,0 +1,380 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::vco::{midi_to_voltage, voltage_to_frequency};
+
+pub const SCALA_EXTENSION: &str = "scl";
+pub const KEYMAP_EXTENSION: &str = "kbm";
+pub const DEFAULT_REFERENCE_HZ: f32 = 440.0;
+pub const MIN_REFERENCE_HZ: f32 = 432.0;
+pub const MAX_REFERENCE_HZ: f32 = 446.0;
+
+const CENTS_PER_OCTAVE: f64 = 1200.0;
+const A4_NOTE: i32 = 69;
+const MIDI_NOTES: std::ops::RangeInclusive<i32> = 0..=127;
+// A keyboard map never needs more entries than there are MIDI notes.
+const KBM_MAX_SIZE: i64 = 128;
+
+/// Equal divisions of the octave offered without a Scala file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Temperament {
+    #[default]
+    Twelve,
+    Nineteen,
+    TwentyFour,
+}
+
+impl Temperament {
+    pub const VALUES: [Temperament; 3] = [
+        Temperament::Twelve,
+        Temperament::Nineteen,
+        Temperament::TwentyFour,
+    ];
+
+    pub fn index(self) -> usize {
+        Self::VALUES
+            .iter()
+            .position(|temperament| *temperament == self)
+            .unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::VALUES[(self.index() + 1) % Self::VALUES.len()]
+    }
+
+    pub fn steps(self) -> usize {
+        match self {
+            Temperament::Twelve => 12,
+            Temperament::Nineteen => 19,
+            Temperament::TwentyFour => 24,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Temperament::Twelve => "12-TET",
+            Temperament::Nineteen => "19-TET",
+            Temperament::TwentyFour => "24-TET",
+        }
+    }
+}
+
+/// A Scala scale: pitches of each degree above the 1/1 in cents, the last
+/// one being the period (usually the 2/1 octave).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScalaScale {
+    pub description: String,
+    degrees: Vec<f64>,
+}
+
+impl ScalaScale {
+    /// `steps` equal divisions of the octave.
+    pub fn equal(steps: usize) -> Self {
+        let steps = steps.max(1);
+        Self {
+            description: format!("{steps}-tone equal temperament"),
+            degrees: (1..=steps)
+                .map(|step| CENTS_PER_OCTAVE * step as f64 / steps as f64)
+                .collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Reads the `.scl` format: `!` comment lines, a description line, the
+    /// note count, then one pitch per line as cents (with a `.`) or a ratio.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+        let description = lines
+            .next()
+            .ok_or_else(|| anyhow!("missing description line"))?
+            .trim()
+            .to_string();
+        let count: usize = first_field(lines.next())
+            .ok_or_else(|| anyhow!("missing note count"))?
+            .parse()
+            .map_err(|_| anyhow!("note count is not a number"))?;
+        if count == 0 {
+            bail!("scale has no notes");
+        }
+        let degrees = lines
+            .filter_map(|line| first_field(Some(line)))
+            .take(count)
+            .map(parse_pitch)
+            .collect::<Result<Vec<_>>>()?;
+        if degrees.len() < count {
+            bail!("expected {count} pitches, found {}", degrees.len());
+        }
+        if degrees.last().is_some_and(|period| *period <= 0.0) {
+            bail!("the last pitch (the period) must be above 1/1");
+        }
+        Ok(Self {
+            description,
+            degrees,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.degrees.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.degrees.is_empty()
+    }
+
+    /// Cents above the 1/1 for any degree, repeating at the period in both
+    /// directions.
+    fn cents(&self, degree: i64) -> f64 {
+        let steps = self.degrees.len() as i64;
+        let period = self.degrees[self.degrees.len() - 1];
+        let index = degree.rem_euclid(steps);
+        let base = if index == 0 {
+            0.0
+        } else {
+            self.degrees[index as usize - 1]
+        };
+        base + degree.div_euclid(steps) as f64 * period
+    }
+}
+
+fn first_field(line: Option<&str>) -> Option<&str> {
+    line?.split_whitespace().next()
+}
+
+fn parse_pitch(field: &str) -> Result<f64> {
+    if field.contains('.') {
+        return field
+            .parse()
+            .map_err(|_| anyhow!("cannot read cents value {field}"));
+    }
+    let (numerator, denominator) = field.split_once('/').unwrap_or((field, "1"));
+    let ratio = match (numerator.parse::<f64>(), denominator.parse::<f64>()) {
+        (Ok(numerator), Ok(denominator)) if numerator > 0.0 && denominator > 0.0 => {
+            numerator / denominator
+        }
+        _ => bail!("cannot read ratio {field}"),
+    };
+    Ok(CENTS_PER_OCTAVE * ratio.log2())
+}
+
+/// A Scala keyboard mapping (`.kbm`): which MIDI keys sound which scale
+/// degrees, and which key is tuned to which frequency.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMap {
+    first_note: i32,
+    last_note: i32,
+    /// Key that plays the scale's 1/1.
+    middle_note: i32,
+    reference_note: i32,
+    reference_hz: f64,
+    /// Degree the mapping repeats at; zero means the scale's period.
+    octave_degree: usize,
+    /// Degree per key within one repeat of the pattern; `None` is unmapped.
+    /// Empty maps every key to consecutive degrees.
+    pattern: Vec<Option<usize>>,
+}
+
+impl Default for KeyboardMap {
+    /// Consecutive degrees from middle C, with A4 at 440 Hz.
+    fn default() -> Self {
+        Self {
+            first_note: 0,
+            last_note: 127,
+            middle_note: 60,
+            reference_note: A4_NOTE,
+            reference_hz: voltage_to_frequency(midi_to_voltage(A4_NOTE)) as f64,
+            octave_degree: 0,
+            pattern: Vec::new(),
+        }
+    }
+}
+
+impl KeyboardMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Reads the `.kbm` format: map size, first and last key, middle key,
+    /// reference key and frequency, octave degree, then one degree (or `x`)
+    /// per map entry.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut fields = text
+            .lines()
+            .filter(|line| !line.starts_with('!'))
+            .filter_map(|line| first_field(Some(line)));
+        let mut header = |name: &str| {
+            fields
+                .next()
+                .ok_or_else(|| anyhow!("missing {name}"))
+                .map(str::to_string)
+        };
+        let int = |name: &str, text: String| {
+            text.parse::<i64>()
+                .map_err(|_| anyhow!("{name} is not a whole number"))
+        };
+        let note = |name: &str, text: String| {
+            int(name, text).and_then(|value| {
+                i32::try_from(value)
+                    .ok()
+                    .filter(|note| MIDI_NOTES.contains(note))
+                    .ok_or_else(|| anyhow!("{name} {value} is not a MIDI note (0 to 127)"))
+            })
+        };
+        let size = int("map size", header("map size")?)?;
+        if size > KBM_MAX_SIZE {
+            bail!("map size {size} is over {KBM_MAX_SIZE}");
+        }
+        let first_note = note("first note", header("first note")?)?;
+        let last_note = note("last note", header("last note")?)?;
+        let middle_note = note("middle note", header("middle note")?)?;
+        let reference_note = note("reference note", header("reference note")?)?;
+        let reference_hz: f64 = header("reference frequency")?
+            .parse()
+            .map_err(|_| anyhow!("reference frequency is not a number"))?;
+        let octave_degree = int("octave degree", header("octave degree")?)?;
+        if size < 0 || octave_degree < 0 || reference_hz <= 0.0 {
+            bail!("map size, octave degree and reference frequency must be positive");
+        }
+        let pattern = (0..size)
+            .map(|_| {
+                let entry = fields.next().unwrap_or("x");
+                if entry.eq_ignore_ascii_case("x") {
+                    return Ok(None);
+                }
+                entry
+                    .parse::<usize>()
+                    .map(Some)
+                    .map_err(|_| anyhow!("map entry {entry} is not a degree or x"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_hz,
+            octave_degree: octave_degree as usize,
+            pattern,
+        })
+    }
+
+    fn degree(&self, note: i32, scale_len: usize) -> Option<i64> {
+        if note < self.first_note || note > self.last_note {
+            return None;
+        }
+        let offset = (note - self.middle_note) as i64;
+        if self.pattern.is_empty() {
+            return Some(offset);
+        }
+        let size = self.pattern.len() as i64;
+        let repeat_degree = match self.octave_degree {
+            0 => scale_len,
+            degree => degree,
+        } as i64;
+        let degree = self.pattern[offset.rem_euclid(size) as usize]?;
+        Some(degree as i64 + offset.div_euclid(size) * repeat_degree)
+    }
+}
+
+/// Maps MIDI notes to control voltages through a Scala scale and keyboard
+/// map. The default is plain 12-tone equal temperament at A4 = 440 Hz.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    name: String,
+    scale: ScalaScale,
+    map: KeyboardMap,
+    reference_cents: f64,
+}
+
+impl Tuning {
+    pub fn new(name: String, scale: ScalaScale, map: KeyboardMap) -> Result<Self> {
+        let reference_cents = map
+            .degree(map.reference_note, scale.len())
+            .map(|degree| scale.cents(degree))
+            .ok_or_else(|| anyhow!("the reference note {} is unmapped", map.reference_note))?;
+        Ok(Self {
+            name,
+            scale,
+            map,
+            reference_cents,
+        })
+    }
+
+    pub fn equal(steps: usize) -> Self {
+        let scale = ScalaScale::equal(steps);
+        Self::new(format!("{steps}-TET"), scale, KeyboardMap::default())
+            .expect("the default map tunes A4")
+    }
+
+    /// Loads a `.scl` file, with a `.kbm` mapping when given.
+    pub fn load(scale: &Path, map: Option<&Path>) -> Result<Self> {
+        let name = scale
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let map = match map {
+            Some(path) => KeyboardMap::load(path)?,
+            None => KeyboardMap::default(),
+        };
+        Self::new(name, ScalaScale::load(scale)?, map)
+    }
+
+    /// The same scale under a different keyboard map.
+    pub fn with_map(&self, map: KeyboardMap) -> Result<Self> {
+        Self::new(self.name.clone(), self.scale.clone(), map)
+    }
+
+    /// The same tuning with the reference key moved to `hz`.
+    pub fn with_reference(&self, hz: f32) -> Self {
+        let mut tuning = self.clone();
+        tuning.map.reference_hz = hz.max(1.0) as f64;
+        tuning
+    }
+
+    /// Whether a `.kbm` file set the mapping, reference pitch included.
+    pub fn has_keyboard_map(&self) -> bool {
+        self.map != KeyboardMap::default()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn scale(&self) -> &ScalaScale {
+        &self.scale
+    }
+
+    /// The 1 V/oct control voltage for `note`, or `None` when the keyboard
+    /// map leaves the key silent.
+    pub fn voltage(&self, note: i32) -> Option<f32> {
+        let degree = self.map.degree(note, self.scale.len())?;
+        let cents = self.scale.cents(degree) - self.reference_cents;
+        let reference = (self.map.reference_hz / voltage_to_frequency(0.0) as f64).log2();
+        Some((reference + cents / CENTS_PER_OCTAVE) as f32)
+    }
+
+    /// The key whose pitch is closest to `frequency`, and how far off it
+    /// is in cents.
+    pub fn nearest(&self, frequency: f32) -> Option<(i32, f32)> {
+        let voltage = (frequency / voltage_to_frequency(0.0)).log2();
+        MIDI_NOTES
+            .filter_map(|note| {
+                self.voltage(note)
+                    .map(|key| (note, (voltage - key) * 1_200.0))
+            })
+            .min_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::equal(12)
+    }
+}
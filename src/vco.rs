@@ -1,7 +1,3 @@
-use std::sync::{Arc, Mutex, mpsc};
-
-use tokio::runtime::Runtime;
-
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Waveform {
     Triangle,
@@ -43,6 +39,21 @@ impl Waveform {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OscEngine {
+    Classic,
+    Wavetable,
+}
+
+impl OscEngine {
+    pub fn label(&self) -> &'static str {
+        match self {
+            OscEngine::Classic => "CLASSIC",
+            OscEngine::Wavetable => "WAVETABLE",
+        }
+    }
+}
+
 fn pulse_wave(phase: f32, duty: f32) -> f32 {
     if phase < duty { 1.0 } else { -1.0 }
 }
@@ -50,6 +61,8 @@ fn pulse_wave(phase: f32, duty: f32) -> f32 {
 #[derive(Debug)]
 pub struct VcoState {
     pub waveform: Waveform,
+    pub engine: OscEngine,
+    pub sync: bool,
     pub voltage: f32,
     pub detune: f32,
     pub frequency: f32,
@@ -59,6 +72,8 @@ impl VcoState {
     pub fn new() -> Self {
         Self {
             waveform: Waveform::Saw,
+            engine: OscEngine::Classic,
+            sync: false,
             voltage: 0.0,
             detune: 0.0,
             frequency: voltage_to_frequency(0.0),
@@ -69,6 +84,14 @@ impl VcoState {
         self.waveform = waveform;
     }
 
+    pub fn set_engine(&mut self, engine: OscEngine) {
+        self.engine = engine;
+    }
+
+    pub fn set_sync(&mut self, sync: bool) {
+        self.sync = sync;
+    }
+
     pub fn set_voltage(&mut self, voltage: f32) {
         self.voltage = voltage;
         self.frequency = voltage_to_frequency(self.voltage + self.detune);
@@ -78,39 +101,63 @@ impl VcoState {
         self.detune = detune;
         self.frequency = voltage_to_frequency(self.voltage + self.detune);
     }
+
+    pub fn apply(&mut self, command: VcoCommand) {
+        match command {
+            VcoCommand::SetVoltage(voltage) => self.set_voltage(voltage),
+            VcoCommand::SetDetune(detune) => self.set_detune(detune),
+            VcoCommand::SetWaveform(waveform) => self.set_waveform(waveform),
+            VcoCommand::SetEngine(engine) => self.set_engine(engine),
+            VcoCommand::SetSync(sync) => self.set_sync(sync),
+        }
+    }
 }
 
-#[derive(Debug)]
+impl Default for VcoState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum VcoCommand {
     SetVoltage(f32),
     SetDetune(f32),
     SetWaveform(Waveform),
+    SetEngine(OscEngine),
+    SetSync(bool),
 }
 
-pub type VcoHandle = (Arc<Mutex<VcoState>>, mpsc::Sender<VcoCommand>);
+const REFERENCE_FREQ: f32 = 55.0;
+
+pub fn voltage_to_frequency(voltage: f32) -> f32 {
+    let octave = voltage;
+    REFERENCE_FREQ * 2.0f32.powf(octave)
+}
 
-pub fn spawn_vco(runtime: &Runtime) -> VcoHandle {
-    let (tx, rx) = mpsc::channel();
-    let state = Arc::new(Mutex::new(VcoState::new()));
-    let thread_state = state.clone();
+pub fn midi_to_voltage(midi_note: i32) -> f32 {
+    (midi_note as f32 - 33.0) / 12.0
+}
 
-    runtime.spawn_blocking(move || {
-        while let Ok(cmd) = rx.recv() {
-            let mut guard = thread_state.lock().expect("lock VCO state");
-            match cmd {
-                VcoCommand::SetVoltage(voltage) => guard.set_voltage(voltage),
-                VcoCommand::SetDetune(detune) => guard.set_detune(detune),
-                VcoCommand::SetWaveform(waveform) => guard.set_waveform(waveform),
-            }
-        }
-    });
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
 
-    (state, tx)
+/// Nearest MIDI note to `freq`, and how far `freq` sits from it in cents.
+pub fn frequency_to_midi(freq: f32) -> (i32, f32) {
+    let exact = 33.0 + 12.0 * (freq / REFERENCE_FREQ).log2();
+    let nearest = exact.round();
+    (nearest as i32, (exact - nearest) * 100.0)
 }
 
-const REFERENCE_FREQ: f32 = 55.0;
+/// Note name without the octave, e.g. "C#" for any C sharp.
+pub fn pitch_class_name(midi_note: i32) -> &'static str {
+    NOTE_NAMES[midi_note.rem_euclid(12) as usize]
+}
 
-pub fn voltage_to_frequency(voltage: f32) -> f32 {
-    let octave = voltage;
-    REFERENCE_FREQ * 2.0f32.powf(octave)
+/// Scientific pitch name, so MIDI 69 is "A4".
+pub fn note_name(midi_note: i32) -> String {
+    let octave = midi_note.div_euclid(12) - 1;
+    format!("{}{octave}", NOTE_NAMES[midi_note.rem_euclid(12) as usize])
 }
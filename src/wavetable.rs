@@ -0,0 +1,83 @@
+use rustfft::{FftPlanner, num_complex::Complex};
+
+use crate::vco::Waveform;
+
+const TABLE_SIZE: usize = 2048;
+const MIP_LEVELS: usize = 11;
+const LOWEST_FREQ: f32 = 20.0;
+
+const WAVEFORMS: [Waveform; 7] = [
+    Waveform::Triangle,
+    Waveform::TriangleSaw,
+    Waveform::Saw,
+    Waveform::ReverseSaw,
+    Waveform::PulseSquare,
+    Waveform::PulseWide,
+    Waveform::PulseNarrow,
+];
+
+struct MipTable {
+    levels: Vec<Vec<f32>>,
+}
+
+pub struct WavetableSet {
+    tables: Vec<MipTable>,
+    sample_rate: f32,
+}
+
+impl WavetableSet {
+    pub fn new(sample_rate: f32) -> Self {
+        let nyquist = sample_rate.max(1.0) * 0.5;
+        let mut planner = FftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(TABLE_SIZE);
+        let inverse = planner.plan_fft_inverse(TABLE_SIZE);
+        let tables = WAVEFORMS
+            .iter()
+            .map(|waveform| {
+                let mut spectrum: Vec<Complex<f32>> = (0..TABLE_SIZE)
+                    .map(|i| Complex::new(waveform.sample(i as f32 / TABLE_SIZE as f32), 0.0))
+                    .collect();
+                forward.process(&mut spectrum);
+                let levels = (0..MIP_LEVELS)
+                    .map(|level| {
+                        let top_freq = LOWEST_FREQ * 2.0f32.powi(level as i32 + 1);
+                        let harmonics =
+                            ((nyquist / top_freq) as usize).clamp(1, TABLE_SIZE / 2 - 1);
+                        let mut buffer = spectrum.clone();
+                        for (bin, value) in buffer.iter_mut().enumerate() {
+                            let harmonic = bin.min(TABLE_SIZE - bin);
+                            if harmonic == 0 || harmonic > harmonics {
+                                *value = Complex::new(0.0, 0.0);
+                            }
+                        }
+                        inverse.process(&mut buffer);
+                        buffer.iter().map(|c| c.re / TABLE_SIZE as f32).collect()
+                    })
+                    .collect();
+                MipTable { levels }
+            })
+            .collect();
+        Self {
+            tables,
+            sample_rate,
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn sample(&self, waveform: Waveform, frequency: f32, phase: f32) -> f32 {
+        let Some(index) = WAVEFORMS.iter().position(|wave| *wave == waveform) else {
+            return waveform.sample(phase);
+        };
+        let ratio = frequency.abs().max(LOWEST_FREQ) / LOWEST_FREQ;
+        let level = (ratio.log2() as usize).min(MIP_LEVELS - 1);
+        let table = &self.tables[index].levels[level];
+        let position = phase.rem_euclid(1.0) * TABLE_SIZE as f32;
+        let base = position as usize % TABLE_SIZE;
+        let next = (base + 1) % TABLE_SIZE;
+        let frac = position.fract();
+        table[base] + (table[next] - table[base]) * frac
+    }
+}
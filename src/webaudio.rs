@@ -0,0 +1,130 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    command::{CommandSender, command_channel},
+    output::{
+        BufferRequest, DebugData, DeviceRequest, SharedPipeline, StreamContext, retune_pipeline,
+    },
+};
+
+// The queue is refilled once per display frame, so it has to cover at least
+// one frame at 30 fps plus some slack.
+const DEFAULT_QUEUE_FRAMES: u32 = 2048;
+const MIN_QUEUE_FRAMES: u32 = 1024;
+const WORKLET_QUANTUM: usize = 128;
+
+// Implemented by web/miniroog-audio.js, which owns the AudioContext and the
+// AudioWorklet that plays the blocks pushed from here.
+unsafe extern "C" {
+    fn miniroog_audio_open() -> f32;
+    fn miniroog_audio_queued() -> u32;
+    fn miniroog_audio_push(samples: *const f32, len: u32);
+    fn miniroog_audio_close();
+}
+
+pub struct AudioEngine {
+    context: StreamContext,
+    open: bool,
+    sample_rate: f32,
+    queue_frames: usize,
+    buffer: Vec<f32>,
+}
+
+impl AudioEngine {
+    pub fn start(
+        pipeline: SharedPipeline,
+        debug: &mut DebugData,
+        buffer: BufferRequest,
+        request: &DeviceRequest,
+    ) -> Result<(Self, CommandSender)> {
+        let sample_rate = unsafe { miniroog_audio_open() };
+        if sample_rate <= 0.0 {
+            return Err(anyhow!("Web Audio is not available"));
+        }
+        let engine_rate = request.engine_rate(sample_rate as u32);
+        pipeline.announce_sample_rate(engine_rate as f32);
+        retune_pipeline(&pipeline, engine_rate);
+        let queue_frames = buffer
+            .frames()
+            .map_or(DEFAULT_QUEUE_FRAMES, |frames| frames.max(MIN_QUEUE_FRAMES));
+        let (mut engine, commands) = Self::with_context(pipeline, debug);
+        engine.context.set_rates(sample_rate as u32, engine_rate);
+        engine.open = true;
+        engine.sample_rate = sample_rate;
+        engine.queue_frames = queue_frames as usize;
+        Ok((engine, commands))
+    }
+
+    /// Without threads there is nothing to drive a silent pipeline, so the
+    /// browser null sink simply stops rendering.
+    pub fn null(pipeline: SharedPipeline, debug: &mut DebugData) -> (Self, CommandSender) {
+        Self::with_context(pipeline, debug)
+    }
+
+    fn with_context(pipeline: SharedPipeline, debug: &mut DebugData) -> (Self, CommandSender) {
+        let (commands, queue) = command_channel();
+        let context = StreamContext::new(
+            2,
+            pipeline,
+            debug.connect(),
+            queue,
+            Arc::new(AtomicBool::new(false)),
+        );
+        let engine = Self {
+            context,
+            open: false,
+            sample_rate: 44_100.0,
+            queue_frames: 0,
+            buffer: Vec::new(),
+        };
+        (engine, commands)
+    }
+
+    /// Tops the worklet's queue back up; call once per UI frame.
+    pub fn pump(&mut self) {
+        if !self.open {
+            return;
+        }
+        let queued = unsafe { miniroog_audio_queued() } as usize;
+        let frames = self.queue_frames.saturating_sub(queued) / WORKLET_QUANTUM * WORKLET_QUANTUM;
+        if frames == 0 {
+            return;
+        }
+        self.buffer.resize(frames * 2, 0.0);
+        let delay = queued as f32 / self.sample_rate;
+        self.context.fill(&mut self.buffer, delay, |sample| sample);
+        // Rendering happens on the UI thread here, so the pipeline can go
+        // back to its slot between pumps and stay in reach of a bounce.
+        self.context.park();
+        unsafe { miniroog_audio_push(self.buffer.as_ptr(), self.buffer.len() as u32) };
+    }
+
+    pub fn is_null(&self) -> bool {
+        !self.open
+    }
+
+    pub fn device_name(&self) -> &str {
+        if self.open { "Web Audio" } else { "null sink" }
+    }
+
+    pub fn has_failed(&self) -> bool {
+        self.context.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn default_device_changed(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for AudioEngine {
+    fn drop(&mut self) {
+        if self.open {
+            unsafe { miniroog_audio_close() };
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Runs with `cargo test --features rt-audit`: drives the null sink through a
+// busy session and fails if its callback allocated, freed or blocked.
+#![cfg(feature = "rt-audit")]
+
+use std::{thread, time::Duration};
+
+use miniroog_model_r::{
+    command::{CommandSender, EngineCommand, PipelineJob},
+    filter::{FilterSlope, FilterType},
+    mixer::Mixer,
+    modifiers::{EnvelopeCurve, EnvelopeParams, Modifiers, VelocityRoute},
+    oscillatorbank::{OscillatorBank, PhaseMode},
+    output::{AudioEngine, DebugData, SharedPipeline, SynthPipeline},
+    oversample::Oversampling,
+    probe::{ProbeBuffer, ProbePoint},
+    rtaudit,
+    testsignal::TestSignal,
+    vco::{OscEngine, VcoCommand, Waveform},
+};
+
+const SETTLE: Duration = Duration::from_millis(60);
+
+fn play(commands: &mut CommandSender, round: usize) {
+    let level = (round % 4) as f32 / 4.0;
+    let even = round.is_multiple_of(2);
+    for index in 0..3 {
+        for command in [
+            VcoCommand::SetVoltage(level),
+            VcoCommand::SetDetune(level * 0.1),
+            VcoCommand::SetWaveform(if even {
+                Waveform::Saw
+            } else {
+                Waveform::PulseNarrow
+            }),
+            VcoCommand::SetEngine(if even {
+                OscEngine::Wavetable
+            } else {
+                OscEngine::Classic
+            }),
+            VcoCommand::SetSync(!even),
+        ] {
+            commands.send(EngineCommand::Vco(index, command));
+        }
+        commands.send(EngineCommand::MixLevel(index, level));
+        commands.send(EngineCommand::Pan(index, level - 0.5));
+    }
+    let envelope = EnvelopeParams {
+        delay: 0.0,
+        attack: 0.01,
+        hold: 0.0,
+        decay: 0.1,
+        sustain: level,
+        release: 0.05,
+    };
+    for command in [
+        EngineCommand::Gate(even),
+        EngineCommand::Velocity(level),
+        EngineCommand::PhaseMode(PhaseMode::Random),
+        EngineCommand::Oversampling(Oversampling::VALUES[round % Oversampling::VALUES.len()]),
+        EngineCommand::NoiseLevel(level),
+        EngineCommand::NoiseMorph(level),
+        EngineCommand::FmDepth(level),
+        EngineCommand::ExternalLevel(level),
+        EngineCommand::FeedbackPatch(round == 2),
+        EngineCommand::FollowerVca(level),
+        EngineCommand::FollowerCutoff(level * 4.0),
+        EngineCommand::RingLevel(level),
+        EngineCommand::Cutoff(200.0 + level * 4_000.0),
+        EngineCommand::FilterEmphasis(level),
+        EngineCommand::FilterType(if even {
+            FilterType::LowPass
+        } else {
+            FilterType::BandPass
+        }),
+        EngineCommand::FilterSlope(FilterSlope::Db12),
+        EngineCommand::FilterEnvelope(envelope),
+        EngineCommand::LoudnessEnvelope(envelope),
+        EngineCommand::FilterEnvelopeCurve(EnvelopeCurve::Logistic),
+        EngineCommand::FilterEnvelopeLoop(round == 1),
+        EngineCommand::VelocityRoute(VelocityRoute::Both),
+        EngineCommand::Limiter(true),
+        EngineCommand::RumbleFilter(true),
+        EngineCommand::TestSignal(if round == 3 {
+            TestSignal::Sweep
+        } else {
+            TestSignal::Off
+        }),
+        EngineCommand::Tempo(90.0 + level * 60.0),
+        EngineCommand::ClickLevel(level),
+        EngineCommand::ClickRestart(0),
+    ] {
+        commands.send(command);
+    }
+}
+
+#[test]
+fn audio_callback_neither_allocates_nor_blocks() {
+    let pipeline = SharedPipeline::new(SynthPipeline::new(
+        OscillatorBank::new(3),
+        Mixer::new(),
+        Modifiers::new(),
+    ));
+    let mut debug = DebugData::new(1024);
+    let (engine, mut commands) = AudioEngine::null(pipeline.clone(), &mut debug);
+    // Each round's readers are dropped before the next round replaces their
+    // taps, so the callback holds the last reference to the old rings.
+    for round in 0..6 {
+        play(&mut commands, round);
+        let (probe, tap) = ProbeBuffer::new(256);
+        let (scope, scope_tap) = ProbeBuffer::new(256);
+        let (record, record_tap) = ProbeBuffer::new(256);
+        commands.send_job(PipelineJob::ProbeTap(Some((ProbePoint::Output, tap))));
+        commands.send_job(PipelineJob::ScopeTap(Some((ProbePoint::Output, scope_tap))));
+        commands.send_job(PipelineJob::RecordTap(Some(record_tap)));
+        thread::sleep(SETTLE);
+        debug.drain();
+        drop((probe, scope, record));
+    }
+    let bounced = pipeline.borrow(|synth| synth.render(0.05).len());
+    assert!(bounced.is_some(), "the stream never lent out the pipeline");
+    thread::sleep(SETTLE);
+    drop(engine);
+
+    let violations = rtaudit::violations();
+    assert!(
+        violations.is_clean(),
+        "audio callback broke realtime rules: {violations:?}"
+    );
+}
@@ -0,0 +1,94 @@
+// miniquad plugin backing src/webaudio.rs. Rendered stereo blocks are copied
+// out of wasm memory and posted to an AudioWorklet that plays them in order.
+"use strict";
+
+const miniroog_audio = {
+    context: null,
+    node: null,
+    pending: [],
+    scheduled_until: 0,
+};
+
+function miniroog_audio_resume() {
+    if (miniroog_audio.context && miniroog_audio.context.state !== "running") {
+        miniroog_audio.context.resume();
+    }
+}
+
+// Browsers keep new audio contexts suspended until the first user gesture.
+for (const event of ["pointerdown", "touchstart", "keydown"]) {
+    window.addEventListener(event, miniroog_audio_resume);
+}
+
+function miniroog_audio_send(chunk) {
+    if (miniroog_audio.node) {
+        miniroog_audio.node.port.postMessage(chunk, [chunk.buffer]);
+    } else {
+        miniroog_audio.pending.push(chunk);
+    }
+}
+
+function miniroog_audio_open() {
+    const AudioContextClass = window.AudioContext || window.webkitAudioContext;
+    if (!AudioContextClass) {
+        return 0;
+    }
+    if (!miniroog_audio.context) {
+        const context = new AudioContextClass({ latencyHint: "interactive" });
+        miniroog_audio.context = context;
+        context.audioWorklet.addModule("miniroog-worklet.js").then(() => {
+            const node = new AudioWorkletNode(context, "miniroog-output", {
+                numberOfInputs: 0,
+                outputChannelCount: [2],
+            });
+            node.connect(context.destination);
+            miniroog_audio.node = node;
+            for (const chunk of miniroog_audio.pending.splice(0)) {
+                miniroog_audio_send(chunk);
+            }
+        }, (err) => console.error("MiniRoog audio worklet failed to load", err));
+    }
+    miniroog_audio.scheduled_until = miniroog_audio.context.currentTime;
+    return miniroog_audio.context.sampleRate;
+}
+
+function miniroog_audio_queued() {
+    const context = miniroog_audio.context;
+    if (!context) {
+        return 0;
+    }
+    const ahead = miniroog_audio.scheduled_until - context.currentTime;
+    return Math.max(0, Math.round(ahead * context.sampleRate));
+}
+
+function miniroog_audio_push(ptr, len) {
+    const context = miniroog_audio.context;
+    if (!context) {
+        return;
+    }
+    const chunk = new Float32Array(wasm_memory.buffer, ptr, len).slice();
+    const start = Math.max(miniroog_audio.scheduled_until, context.currentTime);
+    miniroog_audio.scheduled_until = start + len / 2 / context.sampleRate;
+    miniroog_audio_send(chunk);
+}
+
+function miniroog_audio_close() {
+    miniroog_audio.pending = [];
+    if (miniroog_audio.node) {
+        miniroog_audio.node.port.postMessage("reset");
+    }
+    if (miniroog_audio.context) {
+        miniroog_audio.scheduled_until = miniroog_audio.context.currentTime;
+    }
+}
+
+miniquad_add_plugin({
+    name: "miniroog_audio",
+    version: 1,
+    register_plugin: function (importObject) {
+        importObject.env.miniroog_audio_open = miniroog_audio_open;
+        importObject.env.miniroog_audio_queued = miniroog_audio_queued;
+        importObject.env.miniroog_audio_push = miniroog_audio_push;
+        importObject.env.miniroog_audio_close = miniroog_audio_close;
+    },
+});
@@ -0,0 +1,40 @@
+// Plays interleaved stereo chunks posted by miniroog-audio.js, outputting
+// silence whenever the UI thread falls behind.
+class MiniroogOutput extends AudioWorkletProcessor {
+    constructor() {
+        super();
+        this.chunks = [];
+        this.offset = 0;
+        this.port.onmessage = (event) => {
+            if (event.data === "reset") {
+                this.chunks = [];
+                this.offset = 0;
+            } else {
+                this.chunks.push(event.data);
+            }
+        };
+    }
+
+    process(inputs, outputs) {
+        const left = outputs[0][0];
+        const right = outputs[0][1] || left;
+        for (let i = 0; i < left.length; i++) {
+            const chunk = this.chunks[0];
+            if (!chunk) {
+                left[i] = 0;
+                right[i] = 0;
+                continue;
+            }
+            left[i] = chunk[this.offset];
+            right[i] = chunk[this.offset + 1];
+            this.offset += 2;
+            if (this.offset >= chunk.length) {
+                this.chunks.shift();
+                this.offset = 0;
+            }
+        }
+        return true;
+    }
+}
+
+registerProcessor("miniroog-output", MiniroogOutput);